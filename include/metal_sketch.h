@@ -0,0 +1,240 @@
+/* The C API of metal-sketch, built with the `capi` feature.
+ *
+ * Every object is opaque and destroyed with its ms_*_destroy function. Fallible functions
+ * return an MsStatus and, on failure, leave a description for ms_device_last_error.
+ *
+ * Declarations are only ever added at the end of this file, never changed. */
+
+#ifndef METAL_SKETCH_H
+#define METAL_SKETCH_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct MsDevice MsDevice;
+typedef struct MsBuffer MsBuffer;
+typedef struct MsTexture MsTexture;
+typedef struct MsShaderProgram MsShaderProgram;
+
+typedef enum MsStatus {
+    MS_OK = 0,
+    MS_INVALID_ARGUMENT = 1,
+    MS_WRONG_BACKEND = 2,
+    MS_BACKEND = 3,
+} MsStatus;
+
+enum {
+    MS_BACKEND_METAL = 0,
+    MS_BACKEND_VULKAN = 1,
+    MS_BACKEND_D3D12 = 2,
+    MS_BACKEND_OPENGL = 3,
+};
+
+enum {
+    MS_TEXTURE_TYPE_2D = 0,
+    MS_TEXTURE_TYPE_2D_ARRAY = 1,
+    MS_TEXTURE_TYPE_3D = 2,
+    MS_TEXTURE_TYPE_CUBE = 3,
+};
+
+enum {
+    MS_PIXEL_FORMAT_R8_UNORM = 0,
+    MS_PIXEL_FORMAT_RG8_UNORM = 1,
+    MS_PIXEL_FORMAT_RGBA8_UNORM = 2,
+    MS_PIXEL_FORMAT_RGBA8_UNORM_SRGB = 3,
+    MS_PIXEL_FORMAT_BGRA8_UNORM = 4,
+    MS_PIXEL_FORMAT_BGRA8_UNORM_SRGB = 5,
+    MS_PIXEL_FORMAT_R16_FLOAT = 6,
+    MS_PIXEL_FORMAT_RG16_FLOAT = 7,
+    MS_PIXEL_FORMAT_RGBA16_FLOAT = 8,
+    MS_PIXEL_FORMAT_R32_FLOAT = 9,
+    MS_PIXEL_FORMAT_RG32_FLOAT = 10,
+    MS_PIXEL_FORMAT_RGBA32_FLOAT = 11,
+    MS_PIXEL_FORMAT_R32_UINT = 12,
+    MS_PIXEL_FORMAT_DEPTH32_FLOAT = 13,
+    MS_PIXEL_FORMAT_DEPTH24_UNORM_STENCIL8 = 14,
+    MS_PIXEL_FORMAT_DEPTH32_FLOAT_STENCIL8 = 15,
+    MS_PIXEL_FORMAT_RG32_UINT = 16,
+    MS_PIXEL_FORMAT_R32_SINT = 17,
+    MS_PIXEL_FORMAT_RG11B10_FLOAT = 18,
+    MS_PIXEL_FORMAT_RGB9E5_FLOAT = 19,
+};
+
+#define MS_TEXTURE_USAGE_SHADER_READ 1u
+#define MS_TEXTURE_USAGE_SHADER_WRITE 2u
+#define MS_TEXTURE_USAGE_RENDER_TARGET 4u
+
+typedef struct MsTextureDescriptor {
+    uint32_t texture_type;
+    uint32_t pixel_format;
+    uint32_t width;
+    uint32_t height;
+    uint32_t depth;
+    uint32_t mipmap_level_count;
+    uint32_t array_length;
+    uint32_t sample_count; /* 1 for textures which are not multisampled */
+    uint32_t usage;
+} MsTextureDescriptor;
+
+typedef struct MsTextureViewDescriptor {
+    uint32_t pixel_format;
+    uint32_t base_mipmap_level;
+    uint32_t mipmap_level_count;
+    uint32_t base_array_slice;
+    uint32_t array_length;
+} MsTextureViewDescriptor;
+
+typedef struct MsRegion {
+    uint32_t x, y, z;
+    uint32_t width, height, depth;
+} MsRegion;
+
+void ms_device_destroy(MsDevice *device);
+uint32_t ms_device_backend(const MsDevice *device);
+const char *ms_device_last_error(const MsDevice *device);
+
+MsStatus ms_shader_program_create_from_archive(MsDevice *device, const uint8_t *data, size_t length,
+                                               MsShaderProgram **out);
+void ms_shader_program_destroy(MsShaderProgram *program);
+
+MsStatus ms_buffer_create(MsDevice *device, size_t length, MsBuffer **out);
+MsStatus ms_buffer_write(MsDevice *device, const MsBuffer *buffer, size_t offset, const uint8_t *data,
+                         size_t length);
+void ms_buffer_destroy(MsBuffer *buffer);
+
+MsStatus ms_texture_create(MsDevice *device, const MsTextureDescriptor *desc, MsTexture **out);
+MsStatus ms_texture_create_view(MsDevice *device, const MsTexture *texture, const MsTextureViewDescriptor *desc,
+                                MsTexture **out);
+MsStatus ms_texture_write(MsDevice *device, const MsTexture *texture, uint32_t mipmap_level, uint32_t slice,
+                          const MsRegion *region, const uint8_t *data, size_t length, size_t bytes_per_row);
+void ms_texture_destroy(MsTexture *texture);
+
+MsStatus ms_buffer_read(MsDevice *device, const MsBuffer *buffer, size_t offset, uint8_t *data, size_t length);
+
+typedef struct MsComputePipeline MsComputePipeline;
+typedef struct MsRenderPipeline MsRenderPipeline;
+typedef struct MsCommandQueue MsCommandQueue;
+typedef struct MsCommandBuffer MsCommandBuffer;
+typedef struct MsFence MsFence;
+
+#define MS_PIXEL_FORMAT_NONE 0xffffffffu
+
+enum {
+    MS_LOAD_ACTION_DONT_CARE = 0,
+    MS_LOAD_ACTION_LOAD = 1,
+    MS_LOAD_ACTION_CLEAR = 2,
+};
+
+enum {
+    MS_STORE_ACTION_DONT_CARE = 0,
+    MS_STORE_ACTION_STORE = 1,
+};
+
+enum {
+    MS_PRIMITIVE_TYPE_POINT = 0,
+    MS_PRIMITIVE_TYPE_LINE = 1,
+    MS_PRIMITIVE_TYPE_LINE_STRIP = 2,
+    MS_PRIMITIVE_TYPE_TRIANGLE = 3,
+    MS_PRIMITIVE_TYPE_TRIANGLE_STRIP = 4,
+};
+
+enum {
+    MS_INDEX_FORMAT_UINT16 = 0,
+    MS_INDEX_FORMAT_UINT32 = 1,
+};
+
+enum {
+    MS_COMMAND_BUFFER_COMPLETED = 0,
+    MS_COMMAND_BUFFER_TIMEOUT = 1,
+    MS_COMMAND_BUFFER_PAGE_FAULT = 2,
+    MS_COMMAND_BUFFER_OUT_OF_MEMORY = 3,
+    MS_COMMAND_BUFFER_DEVICE_LOST = 4,
+};
+
+typedef struct MsRenderPipelineDescriptor {
+    const char *vertex_function;
+    const char *fragment_function; /* null for a pipeline which only rasterizes */
+    const uint32_t *color_formats;
+    size_t color_format_count;
+    uint32_t depth_format; /* MS_PIXEL_FORMAT_NONE for no depth attachment */
+    uint32_t sample_count;
+} MsRenderPipelineDescriptor;
+
+typedef struct MsColorAttachment {
+    const MsTexture *texture;
+    uint32_t mipmap_level, slice;
+    uint32_t load_action, store_action;
+    double clear_color[4];
+} MsColorAttachment;
+
+typedef struct MsDepthAttachment {
+    const MsTexture *texture;
+    uint32_t mipmap_level, slice;
+    uint32_t load_action, store_action;
+    double clear_depth;
+} MsDepthAttachment;
+
+MsStatus ms_compute_pipeline_create(MsDevice *device, const MsShaderProgram *program, const char *function,
+                                    MsComputePipeline **out);
+void ms_compute_pipeline_destroy(MsComputePipeline *pipeline);
+
+MsStatus ms_render_pipeline_create(MsDevice *device, const MsShaderProgram *program,
+                                   const MsRenderPipelineDescriptor *desc, MsRenderPipeline **out);
+void ms_render_pipeline_destroy(MsRenderPipeline *pipeline);
+
+MsStatus ms_command_queue_create(MsDevice *device, MsCommandQueue **out);
+void ms_command_queue_destroy(MsCommandQueue *queue);
+
+/* Recording never fails on the spot: the first misuse is reported by ms_command_buffer_commit.
+ * Every object a command uses must outlive the commit. */
+MsCommandBuffer *ms_command_buffer_create(void);
+void ms_command_buffer_destroy(MsCommandBuffer *commands);
+void ms_command_buffer_begin_compute(MsCommandBuffer *commands);
+void ms_command_buffer_begin_blit(MsCommandBuffer *commands);
+void ms_command_buffer_begin_render(MsCommandBuffer *commands, const MsColorAttachment *colors, size_t color_count,
+                                    const MsDepthAttachment *depth);
+void ms_command_buffer_end_pass(MsCommandBuffer *commands);
+
+void ms_compute_set_pipeline(MsCommandBuffer *commands, const MsComputePipeline *pipeline);
+void ms_compute_set_buffer(MsCommandBuffer *commands, const MsBuffer *buffer, size_t offset, size_t size,
+                           uint32_t index);
+void ms_compute_set_bytes(MsCommandBuffer *commands, const uint8_t *data, size_t length, uint32_t index);
+void ms_compute_set_texture(MsCommandBuffer *commands, const MsTexture *texture, uint32_t index);
+void ms_compute_dispatch(MsCommandBuffer *commands, const MsRegion *threadgroups,
+                         const MsRegion *threads_per_threadgroup);
+
+void ms_blit_fill_buffer(MsCommandBuffer *commands, const MsBuffer *buffer, size_t offset, size_t size,
+                         uint8_t value);
+void ms_blit_copy_buffer(MsCommandBuffer *commands, const MsBuffer *src, size_t src_offset, size_t size,
+                         const MsBuffer *dst, size_t dst_offset);
+
+void ms_render_set_pipeline(MsCommandBuffer *commands, const MsRenderPipeline *pipeline);
+void ms_render_set_vertex_buffer(MsCommandBuffer *commands, const MsBuffer *buffer, size_t offset, size_t size,
+                                 uint32_t index);
+void ms_render_set_fragment_buffer(MsCommandBuffer *commands, const MsBuffer *buffer, size_t offset, size_t size,
+                                   uint32_t index);
+void ms_render_set_fragment_texture(MsCommandBuffer *commands, const MsTexture *texture, uint32_t index);
+void ms_render_set_index_buffer(MsCommandBuffer *commands, const MsBuffer *buffer, size_t offset, size_t size,
+                                uint32_t format);
+void ms_render_draw(MsCommandBuffer *commands, uint32_t primitive, uint32_t first_vertex, uint32_t vertex_count,
+                    uint32_t instance_count, uint32_t base_instance);
+void ms_render_draw_indexed(MsCommandBuffer *commands, uint32_t primitive, uint32_t first_index, uint32_t index_count,
+                            uint32_t instance_count, int32_t base_vertex, uint32_t base_instance);
+
+/* Submits and destroys commands, whether or not it succeeds, even if queue or out is null. */
+MsStatus ms_command_buffer_commit(MsDevice *device, MsCommandQueue *queue, MsCommandBuffer *commands,
+                                  MsFence **out);
+
+uint32_t ms_fence_wait(const MsFence *fence);
+uint32_t ms_fence_wait_timeout(const MsFence *fence, uint32_t timeout_ms, uint32_t *status);
+void ms_fence_destroy(MsFence *fence);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
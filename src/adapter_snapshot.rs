@@ -0,0 +1,84 @@
+//! Snapshots of everything an adapter reports, for bug reports.
+//!
+//! Most rendering bugs only happen on some GPUs or drivers, and the first question about any of
+//! them is what the adapter was and what it supports. `Adapter::diagnostic_snapshot` answers it
+//! in one go, as JSON an application can write to its log or attach to a crash report:
+//!
+//! ```ignore
+//! std::io::File::create(&Path::new("adapter.json")).write_str(adapter.diagnostic_snapshot().as_slice());
+//! ```
+//!
+//! The JSON is an object with:
+//!
+//! * `crate_version`, `os` and `arch`: the version of this crate, and the platform it was built
+//!   for;
+//! * `name` and `driver_version`, as `Adapter::name` and `Adapter::driver_version` report them;
+//! * `features`: an object with every field of `DeviceFeatures`, `true` or `false`;
+//! * `limits`: an object with every field of `DeviceLimits`;
+//! * `formats`: an object with every `PixelFormat`, by name, mapped to an object with every field
+//!   of its `FormatCapabilities`.
+//!
+//! Fields are only ever added, so snapshots of older versions of the crate remain readable.
+
+use {DeviceFeatures, DeviceLimits, FormatCapabilities, PixelFormat};
+
+/// Every pixel format, in declaration order.
+pub const PIXEL_FORMATS: [PixelFormat, ..20] = [
+    PixelFormat::R8Unorm, PixelFormat::RG8Unorm, PixelFormat::RGBA8Unorm, PixelFormat::RGBA8UnormSrgb,
+    PixelFormat::BGRA8Unorm, PixelFormat::BGRA8UnormSrgb, PixelFormat::R16Float, PixelFormat::RG16Float,
+    PixelFormat::RGBA16Float, PixelFormat::R32Float, PixelFormat::RG32Float, PixelFormat::RGBA32Float,
+    PixelFormat::R32Uint, PixelFormat::RG32Uint, PixelFormat::R32Sint, PixelFormat::RG11B10Float, PixelFormat::RGB9E5Float,
+    PixelFormat::Depth32Float, PixelFormat::Depth24UnormStencil8, PixelFormat::Depth32FloatStencil8,
+];
+
+/// What an adapter reports. See `Adapter::diagnostic_snapshot`, which captures one.
+#[deriving(Clone)]
+pub struct AdapterSnapshot {
+    pub name: String,
+    pub driver_version: String,
+    pub features: DeviceFeatures,
+    pub limits: DeviceLimits,
+    /// The capabilities of every format of `PIXEL_FORMATS`.
+    pub formats: Vec<(PixelFormat, FormatCapabilities)>,
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(format!("\\u{:04x}", c as u32).as_slice()),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn json_object<T: ::std::fmt::Show>(values: &[(&'static str, T)]) -> String {
+    let fields: Vec<String> = values.iter().map(|&(name, ref value)| format!("\"{}\":{}", name, value)).collect();
+    format!("{{{}}}", fields.connect(","))
+}
+
+impl AdapterSnapshot {
+    /// The snapshot as JSON, as the module documentation lays out.
+    pub fn to_json(&self) -> String {
+        let formats: Vec<String> = self.formats.iter().map(|&(ref format, ref capabilities)| {
+            let capabilities = [
+                ("filterable", capabilities.filterable),
+                ("render_target", capabilities.render_target),
+                ("blendable", capabilities.blendable),
+                ("storage", capabilities.storage),
+                ("multisample", capabilities.multisample),
+            ];
+            format!("\"{}\":{}", format, json_object(capabilities.as_slice()))
+        }).collect();
+        format!("{{\"crate_version\":\"{}\",\"os\":\"{}\",\"arch\":\"{}\",\"name\":\"{}\",\"driver_version\":\"{}\",\
+                 \"features\":{},\"limits\":{},\"formats\":{{{}}}}}",
+                env!("CARGO_PKG_VERSION"), ::std::env::consts::OS, ::std::env::consts::ARCH,
+                escape(self.name.as_slice()), escape(self.driver_version.as_slice()),
+                json_object(self.features.values().as_slice()), json_object(self.limits.values().as_slice()),
+                formats.connect(","))
+    }
+}
@@ -0,0 +1,729 @@
+//! Choosing a backend at runtime.
+//!
+//! Applications which ship several backends register each of them as a `BackendEntry`, and let
+//! `auto` open a device on the first one, in order of preference, that works:
+//!
+//! ```ignore
+//! let backends = vec![
+//!     BackendEntry::new(BackendKind::Metal, || metal::adapters()),
+//!     BackendEntry::new(BackendKind::Vulkan, || vulkan::adapters()),
+//!     BackendEntry::new(BackendKind::OpenGl, || gl::adapters()),
+//! ];
+//! let device = try!(auto(&desc, &DEFAULT_BACKEND_ORDER, backends));
+//! println!("running on {}", device.adapter_name());
+//! ```
+//!
+//! The result is an `AnyDevice`, a single type whatever the backend, so applications do not have
+//! to be generic over `Device` everywhere. Code which needs all of a backend gets its device back
+//! with `downcast_ref` or `downcast_mut`.
+//!
+//! For everything else, there is `DeviceObject`: an object safe version of `Device`, implemented
+//! for every device, whose resources are type erased handles (`AnyBuffer`, `AnyTexture`, ...)
+//! and whose errors are strings. This is what plugin systems and scripting bindings hold on to.
+//! Handles are checked when they are used, so passing a handle to a device of another backend
+//! fails with `ObjectError::WrongBackend` instead of being undefined. The check is only of the
+//! backend: a handle of another device of the same backend is not caught, and is as undefined
+//! as it would be with the backend's own types.
+//!
+//! Commands are recorded as a list of `AnyPass`es, each of which becomes one encoder of a
+//! command buffer of an `AnyCommandQueue`, and submitted at once:
+//!
+//! ```ignore
+//! let mut queue = try!(device.object().create_command_queue(None));
+//! let fence = try!(device.object().submit(&mut queue, vec![AnyPass::Compute(vec![
+//!     AnyComputeCommand::SetPipeline(&pipeline),
+//!     AnyComputeCommand::SetBuffer { buffer: &data, offset: 0, size: 4096, index: 0 },
+//!     AnyComputeCommand::Dispatch {
+//!         threadgroups: Size { width: 16, height: 1, depth: 1 },
+//!         threads_per_threadgroup: Size { width: 64, height: 1, depth: 1 },
+//!     },
+//! ])]));
+//! fence.wait();
+//! ```
+
+use std::any::{Any, AnyRefExt, AnyMutRefExt};
+use std::fmt;
+
+use {Adapter, Device, DeviceDescriptor, DeviceFeatures, DeviceLimits, FormatCapabilities, OpenDeviceError, PixelFormat};
+use {BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindingResource, BufferHints};
+use {BufferSlice};
+use {DepthStencilStateDescriptor, Origin, RenderPipelineDescriptor, SamplerDescriptor, ShaderArchive};
+use {ComputePipelineDescriptor, Size, TextureDescriptor, TextureViewDescriptor};
+use {BlitCommandEncoder, ClearColor, CommandBuffer, CommandQueue, ComputeCommandEncoder, IndexFormat, LoadAction};
+use {PrimitiveType, RenderCommandEncoder, RenderPassColorAttachment, RenderPassDepthAttachment, RenderPassDescriptor};
+use {StoreAction};
+use util::fence::Fence;
+#[cfg(feature = "raw_handles")]
+use {RawHandle, Resource};
+
+/// The graphics APIs a backend may be built on.
+#[deriving(PartialEq, Clone, Show)]
+pub enum BackendKind {
+    Metal,
+    Vulkan,
+    D3D12,
+    OpenGl,
+}
+
+/// The order `auto` is usually given: the native API of each platform first, then Vulkan, then
+/// OpenGL as the last resort.
+pub const DEFAULT_BACKEND_ORDER: [BackendKind, ..4] = [BackendKind::Metal, BackendKind::D3D12,
+                                                       BackendKind::Vulkan, BackendKind::OpenGl];
+
+macro_rules! handles {
+    ($($(#[$attr:meta])* $name:ident),*) => {
+        $($(#[$attr])*
+        pub struct $name {
+            inner: Box<Any>,
+        })*
+    }
+}
+
+handles! {
+    /// A shader program created through a `DeviceObject`.
+    AnyShaderProgram,
+    /// A buffer created through a `DeviceObject`.
+    AnyBuffer,
+    /// A texture (or texture view) created through a `DeviceObject`.
+    AnyTexture,
+    /// A sampler created through a `DeviceObject`.
+    AnySampler,
+    /// A depth/stencil state created through a `DeviceObject`.
+    AnyDepthStencilState,
+    /// A render pipeline created through a `DeviceObject`.
+    AnyRenderPipeline,
+    /// A compute pipeline created through a `DeviceObject`.
+    AnyComputePipeline,
+    /// A bind group layout created through a `DeviceObject`.
+    AnyBindGroupLayout,
+    /// A bind group created through a `DeviceObject`.
+    AnyBindGroup,
+    /// A command queue created through a `DeviceObject`.
+    AnyCommandQueue
+}
+
+/// Why a `DeviceObject` operation failed.
+#[deriving(Show)]
+pub enum ObjectError {
+    /// A handle given to the device was created by a device of another backend. Handles of
+    /// other devices of the same backend are not detected.
+    WrongBackend,
+    /// The backend failed, with its error formatted as a string.
+    Backend(String),
+    /// A buffer range reaches past the end of its buffer.
+    OutOfBounds,
+    /// A render pass is invalid, as described (see `RenderPassDescriptor::validate`).
+    InvalidPass(String),
+}
+
+fn wrap<T: 'static>(value: T) -> Box<Any> {
+    box value as Box<Any>
+}
+
+fn get<T: 'static>(inner: &Box<Any>) -> Result<&T, ObjectError> {
+    match inner.downcast_ref::<T>() {
+        Some(value) => Ok(value),
+        None => Err(ObjectError::WrongBackend),
+    }
+}
+
+fn get_mut<T: 'static>(inner: &mut Box<Any>) -> Result<&mut T, ObjectError> {
+    match inner.downcast_mut::<T>() {
+        Some(value) => Ok(value),
+        None => Err(ObjectError::WrongBackend),
+    }
+}
+
+fn backend<T, E: fmt::Show>(result: Result<T, E>) -> Result<T, ObjectError> {
+    result.map_err(|e| ObjectError::Backend(format!("{}", e)))
+}
+
+/// A resource bound into a bind group created through a `DeviceObject`.
+pub enum AnyBindingResource<'a> {
+    Buffer { buffer: &'a AnyBuffer, offset: uint, size: uint },
+    Texture(&'a AnyTexture),
+    Sampler(&'a AnySampler),
+}
+
+pub struct AnyBindGroupEntry<'a> {
+    pub binding: uint,
+    pub array_element: uint,
+    pub resource: AnyBindingResource<'a>,
+}
+
+/// A command of an `AnyPass::Compute`, as the `ComputeCommandEncoder` method of the same name.
+pub enum AnyComputeCommand<'a> {
+    SetPipeline(&'a AnyComputePipeline),
+    SetBuffer { buffer: &'a AnyBuffer, offset: uint, size: uint, index: uint },
+    SetBytes { bytes: Vec<u8>, index: uint },
+    SetTexture { texture: &'a AnyTexture, index: uint },
+    SetSampler { sampler: &'a AnySampler, index: uint },
+    SetBindGroup { group: &'a AnyBindGroup, index: uint },
+    Dispatch { threadgroups: Size, threads_per_threadgroup: Size },
+}
+
+/// A command of an `AnyPass::Blit`, as the `BlitCommandEncoder` method of the same name.
+pub enum AnyBlitCommand<'a> {
+    FillBuffer { buffer: &'a AnyBuffer, offset: uint, size: uint, value: u8 },
+    CopyBuffer { src: &'a AnyBuffer, src_offset: uint, size: uint, dst: &'a AnyBuffer, dst_offset: uint },
+    CopyBufferToTexture { src: &'a AnyBuffer, src_offset: uint, src_size: uint, bytes_per_row: uint,
+                          dst: &'a AnyTexture, dst_level: uint, dst_slice: uint, dst_origin: Origin, size: Size },
+    CopyTextureToBuffer { src: &'a AnyTexture, src_level: uint, src_slice: uint, src_origin: Origin, size: Size,
+                          dst: &'a AnyBuffer, dst_offset: uint, dst_size: uint, bytes_per_row: uint },
+}
+
+/// A command of an `AnyPass::Render`, as the `RenderCommandEncoder` method of the same name.
+pub enum AnyRenderCommand<'a> {
+    SetPipeline(&'a AnyRenderPipeline),
+    SetDepthStencilState(&'a AnyDepthStencilState),
+    SetVertexBuffer { buffer: &'a AnyBuffer, offset: uint, size: uint, index: uint },
+    SetVertexBytes { bytes: Vec<u8>, index: uint },
+    SetFragmentBuffer { buffer: &'a AnyBuffer, offset: uint, size: uint, index: uint },
+    SetFragmentBytes { bytes: Vec<u8>, index: uint },
+    SetFragmentTexture { texture: &'a AnyTexture, index: uint },
+    SetFragmentSampler { sampler: &'a AnySampler, index: uint },
+    SetBindGroup { group: &'a AnyBindGroup, index: uint },
+    SetIndexBuffer { buffer: &'a AnyBuffer, offset: uint, size: uint, format: IndexFormat },
+    Draw { primitive: PrimitiveType, first_vertex: uint, vertex_count: uint, instance_count: uint,
+           base_instance: uint },
+    DrawIndexed { primitive: PrimitiveType, first_index: uint, index_count: uint, instance_count: uint,
+                  base_vertex: int, base_instance: uint },
+}
+
+/// A color attachment of an `AnyRenderPass`, as a `RenderPassColorAttachment`.
+pub struct AnyColorAttachment<'a> {
+    pub texture: &'a AnyTexture,
+    pub mipmap_level: uint,
+    pub slice: uint,
+    pub load_action: LoadAction,
+    pub store_action: StoreAction,
+    pub clear_color: ClearColor,
+}
+
+/// The depth attachment of an `AnyRenderPass`, as a `RenderPassDepthAttachment`.
+pub struct AnyDepthAttachment<'a> {
+    pub texture: &'a AnyTexture,
+    pub mipmap_level: uint,
+    pub slice: uint,
+    pub load_action: LoadAction,
+    pub store_action: StoreAction,
+    pub clear_depth: f64,
+}
+
+/// A render pass of an `AnyPass::Render`: its attachments, and the commands recorded in it.
+pub struct AnyRenderPass<'a> {
+    pub color_attachments: Vec<AnyColorAttachment<'a>>,
+    pub depth_attachment: Option<AnyDepthAttachment<'a>>,
+    pub commands: Vec<AnyRenderCommand<'a>>,
+}
+
+/// One encoder of a command buffer submitted with `DeviceObject::submit`.
+pub enum AnyPass<'a> {
+    Compute(Vec<AnyComputeCommand<'a>>),
+    Blit(Vec<AnyBlitCommand<'a>>),
+    Render(AnyRenderPass<'a>),
+}
+
+/// An object safe version of `Device`.
+///
+/// Each method behaves as the `Device` method of the same name. Commands are recorded as
+/// `AnyPass`es and submitted with `submit`; code which needs more of the encoders downcasts to
+/// the backend's device.
+pub trait DeviceObject : Send {
+    fn features(&self) -> DeviceFeatures;
+    fn limits(&self) -> DeviceLimits;
+    fn format_capabilities(&self, format: PixelFormat) -> FormatCapabilities;
+    fn as_any(&self) -> &Any;
+    fn as_any_mut(&mut self) -> &mut Any;
+
+    fn create_shader_program_from_archive(&mut self, archive: &ShaderArchive) -> Result<AnyShaderProgram, ObjectError>;
+    fn create_buffer(&mut self, length: uint, hints: Option<BufferHints>) -> Result<AnyBuffer, ObjectError>;
+    fn write_buffer(&mut self, buffer: &AnyBuffer, offset: uint, data: &[u8]) -> Result<(), ObjectError>;
+    fn read_buffer(&mut self, buffer: &AnyBuffer, offset: uint, data: &mut [u8]) -> Result<(), ObjectError>;
+    fn create_texture(&mut self, desc: TextureDescriptor) -> Result<AnyTexture, ObjectError>;
+    fn create_texture_view(&mut self, texture: &AnyTexture, desc: TextureViewDescriptor) -> Result<AnyTexture, ObjectError>;
+    fn write_texture(&mut self, texture: &AnyTexture, mipmap_level: uint, slice: uint, origin: Origin, size: Size,
+                     data: &[u8], bytes_per_row: uint) -> Result<(), ObjectError>;
+    fn create_sampler(&mut self, desc: SamplerDescriptor) -> Result<AnySampler, ObjectError>;
+    fn create_depth_stencil_state(&mut self, desc: DepthStencilStateDescriptor) -> AnyDepthStencilState;
+    fn create_render_pipeline(&mut self, program: &AnyShaderProgram, desc: RenderPipelineDescriptor) -> Result<AnyRenderPipeline, ObjectError>;
+    fn create_compute_pipeline(&mut self, program: &AnyShaderProgram, desc: ComputePipelineDescriptor) -> Result<AnyComputePipeline, ObjectError>;
+    fn create_bind_group_layout(&mut self, desc: BindGroupLayoutDescriptor) -> Result<AnyBindGroupLayout, ObjectError>;
+    fn create_bind_group(&mut self, layout: &AnyBindGroupLayout, entries: Vec<AnyBindGroupEntry>,
+                         unsized_length: uint) -> Result<AnyBindGroup, ObjectError>;
+    fn create_command_queue(&mut self, count: Option<uint>) -> Result<AnyCommandQueue, ObjectError>;
+
+    /// Encode `passes` into a command buffer of `queue`, one encoder each, in order, and commit
+    /// it. The fence is signaled once the command buffer has completed.
+    ///
+    /// Every handle is checked before anything is committed: if one is of another backend, a
+    /// buffer range is out of bounds or a render pass is invalid, nothing is submitted.
+    fn submit(&mut self, queue: &mut AnyCommandQueue, passes: Vec<AnyPass>) -> Result<Fence, ObjectError>;
+
+    #[cfg(feature = "raw_handles")]
+    unsafe fn as_raw(&self) -> RawHandle;
+    #[cfg(feature = "raw_handles")]
+    unsafe fn raw_buffer(&self, buffer: &AnyBuffer) -> Result<RawHandle, ObjectError>;
+    #[cfg(feature = "raw_handles")]
+    unsafe fn raw_texture(&self, texture: &AnyTexture) -> Result<RawHandle, ObjectError>;
+}
+
+fn bind_group_entries<'a, D: Device + 'static>(entries: Vec<AnyBindGroupEntry<'a>>) -> Result<Vec<BindGroupEntry<'a, D>>, ObjectError> {
+    let mut converted = Vec::with_capacity(entries.len());
+    for entry in entries.into_iter() {
+        let resource = match entry.resource {
+            AnyBindingResource::Buffer { buffer, offset, size } => {
+                let buffer = try!(get::<D::Buffer>(&buffer.inner));
+                match BufferSlice::try_new(buffer, offset, size) {
+                    Some(slice) => BindingResource::Buffer(slice),
+                    None => return Err(ObjectError::OutOfBounds),
+                }
+            }
+            AnyBindingResource::Texture(texture) => BindingResource::Texture(try!(get::<D::Texture>(&texture.inner))),
+            AnyBindingResource::Sampler(sampler) => BindingResource::Sampler(try!(get::<D::Sampler>(&sampler.inner))),
+        };
+        converted.push(BindGroupEntry { binding: entry.binding, array_element: entry.array_element, resource: resource });
+    }
+    Ok(converted)
+}
+
+fn slice<'a, D: Device + 'static>(buffer: &'a AnyBuffer, offset: uint, size: uint)
+                                  -> Result<BufferSlice<'a, D::Buffer>, ObjectError> {
+    let buffer = try!(get::<D::Buffer>(&buffer.inner));
+    match BufferSlice::try_new(buffer, offset, size) {
+        Some(slice) => Ok(slice),
+        None => Err(ObjectError::OutOfBounds),
+    }
+}
+
+// The encoders stop at the first command with a bad handle. Their callers end the encoding
+// either way, and drop the command buffer uncommitted on an error.
+
+fn encode_compute<D: Device + 'static>(encoder: &mut D::ComputeCommandEncoder, commands: Vec<AnyComputeCommand>)
+                                       -> Result<(), ObjectError> {
+    for command in commands.into_iter() {
+        match command {
+            AnyComputeCommand::SetPipeline(pipeline) => {
+                encoder.set_compute_pipeline(try!(get::<D::ComputePipeline>(&pipeline.inner)))
+            }
+            AnyComputeCommand::SetBuffer { buffer, offset, size, index } => {
+                encoder.set_buffer(try!(slice::<D>(buffer, offset, size)), index)
+            }
+            AnyComputeCommand::SetBytes { bytes, index } => encoder.set_bytes(bytes.as_slice(), index),
+            AnyComputeCommand::SetTexture { texture, index } => {
+                encoder.set_texture(try!(get::<D::Texture>(&texture.inner)), index)
+            }
+            AnyComputeCommand::SetSampler { sampler, index } => {
+                encoder.set_sampler(try!(get::<D::Sampler>(&sampler.inner)), index)
+            }
+            AnyComputeCommand::SetBindGroup { group, index } => {
+                encoder.set_bind_group(try!(get::<D::BindGroup>(&group.inner)), index)
+            }
+            AnyComputeCommand::Dispatch { threadgroups, threads_per_threadgroup } => {
+                encoder.dispatch_threadgroups(threadgroups, threads_per_threadgroup)
+            }
+        }
+    }
+    Ok(())
+}
+
+fn encode_blit<D: Device + 'static>(encoder: &mut D::BlitCommandEncoder, commands: Vec<AnyBlitCommand>)
+                                    -> Result<(), ObjectError> {
+    for command in commands.into_iter() {
+        match command {
+            AnyBlitCommand::FillBuffer { buffer, offset, size, value } => {
+                encoder.fill_buffer(try!(slice::<D>(buffer, offset, size)), value)
+            }
+            AnyBlitCommand::CopyBuffer { src, src_offset, size, dst, dst_offset } => {
+                let src = try!(slice::<D>(src, src_offset, size));
+                encoder.copy_buffer(src, try!(get::<D::Buffer>(&dst.inner)), dst_offset)
+            }
+            AnyBlitCommand::CopyBufferToTexture { src, src_offset, src_size, bytes_per_row, dst, dst_level, dst_slice,
+                                                  dst_origin, size } => {
+                let src = try!(slice::<D>(src, src_offset, src_size));
+                let dst = try!(get::<D::Texture>(&dst.inner));
+                encoder.copy_buffer_to_texture(src, bytes_per_row, dst, dst_level, dst_slice, dst_origin, size)
+            }
+            AnyBlitCommand::CopyTextureToBuffer { src, src_level, src_slice, src_origin, size, dst, dst_offset, dst_size,
+                                                  bytes_per_row } => {
+                let src = try!(get::<D::Texture>(&src.inner));
+                let dst = try!(slice::<D>(dst, dst_offset, dst_size));
+                encoder.copy_texture_to_buffer(src, src_level, src_slice, src_origin, size, dst, bytes_per_row)
+            }
+        }
+    }
+    Ok(())
+}
+
+fn encode_render<D: Device + 'static>(encoder: &mut D::RenderCommandEncoder, commands: Vec<AnyRenderCommand>)
+                                      -> Result<(), ObjectError> {
+    for command in commands.into_iter() {
+        match command {
+            AnyRenderCommand::SetPipeline(pipeline) => {
+                encoder.set_render_pipeline(try!(get::<D::RenderPipeline>(&pipeline.inner)))
+            }
+            AnyRenderCommand::SetDepthStencilState(state) => {
+                encoder.set_depth_stencil_state(try!(get::<D::DepthStencilState>(&state.inner)))
+            }
+            AnyRenderCommand::SetVertexBuffer { buffer, offset, size, index } => {
+                encoder.set_vertex_buffer(try!(slice::<D>(buffer, offset, size)), index)
+            }
+            AnyRenderCommand::SetVertexBytes { bytes, index } => encoder.set_vertex_bytes(bytes.as_slice(), index),
+            AnyRenderCommand::SetFragmentBuffer { buffer, offset, size, index } => {
+                encoder.set_fragment_buffer(try!(slice::<D>(buffer, offset, size)), index)
+            }
+            AnyRenderCommand::SetFragmentBytes { bytes, index } => encoder.set_fragment_bytes(bytes.as_slice(), index),
+            AnyRenderCommand::SetFragmentTexture { texture, index } => {
+                encoder.set_fragment_texture(try!(get::<D::Texture>(&texture.inner)), index)
+            }
+            AnyRenderCommand::SetFragmentSampler { sampler, index } => {
+                encoder.set_fragment_sampler(try!(get::<D::Sampler>(&sampler.inner)), index)
+            }
+            AnyRenderCommand::SetBindGroup { group, index } => {
+                encoder.set_bind_group(try!(get::<D::BindGroup>(&group.inner)), index)
+            }
+            AnyRenderCommand::SetIndexBuffer { buffer, offset, size, format } => {
+                encoder.set_index_buffer(try!(slice::<D>(buffer, offset, size)), format)
+            }
+            AnyRenderCommand::Draw { primitive, first_vertex, vertex_count, instance_count, base_instance } => {
+                encoder.draw(primitive, first_vertex, vertex_count, instance_count, base_instance)
+            }
+            AnyRenderCommand::DrawIndexed { primitive, first_index, index_count, instance_count, base_vertex,
+                                            base_instance } => {
+                encoder.draw_indexed(primitive, first_index, index_count, instance_count, base_vertex, base_instance)
+            }
+        }
+    }
+    Ok(())
+}
+
+fn render_pass_descriptor<'a, D: Device + 'static>(color_attachments: Vec<AnyColorAttachment<'a>>,
+                                                   depth_attachment: Option<AnyDepthAttachment<'a>>)
+                                                   -> Result<RenderPassDescriptor<'a, D>, ObjectError> {
+    let mut colors = Vec::with_capacity(color_attachments.len());
+    for attachment in color_attachments.into_iter() {
+        colors.push(RenderPassColorAttachment {
+            texture: try!(get::<D::Texture>(&attachment.texture.inner)),
+            mipmap_level: attachment.mipmap_level,
+            slice: attachment.slice,
+            load_action: attachment.load_action,
+            store_action: attachment.store_action,
+            clear_color: attachment.clear_color,
+        });
+    }
+    let depth = match depth_attachment {
+        Some(attachment) => Some(RenderPassDepthAttachment {
+            texture: try!(get::<D::Texture>(&attachment.texture.inner)),
+            mipmap_level: attachment.mipmap_level,
+            slice: attachment.slice,
+            load_action: attachment.load_action,
+            store_action: attachment.store_action,
+            clear_depth: attachment.clear_depth,
+        }),
+        None => None,
+    };
+    let desc = RenderPassDescriptor {
+        color_attachments: colors,
+        depth_attachment: depth,
+        stencil_attachment: None,
+        sample_positions: None,
+        visibility_result_buffer: None,
+    };
+    match desc.validate() {
+        Ok(()) => Ok(desc),
+        Err(e) => Err(ObjectError::InvalidPass(format!("{}", e))),
+    }
+}
+
+impl<D: Device + Send + 'static> DeviceObject for D {
+    fn features(&self) -> DeviceFeatures {
+        Device::features(self)
+    }
+
+    fn limits(&self) -> DeviceLimits {
+        Device::limits(self)
+    }
+
+    fn format_capabilities(&self, format: PixelFormat) -> FormatCapabilities {
+        Device::format_capabilities(self, format)
+    }
+
+    fn as_any(&self) -> &Any {
+        self as &Any
+    }
+
+    fn as_any_mut(&mut self) -> &mut Any {
+        self as &mut Any
+    }
+
+    fn create_shader_program_from_archive(&mut self, archive: &ShaderArchive) -> Result<AnyShaderProgram, ObjectError> {
+        enter_span!("create_shader_program_from_archive");
+        let program = try!(backend(Device::create_shader_program_from_archive(self, archive)));
+        Ok(AnyShaderProgram { inner: wrap(program) })
+    }
+
+    fn create_buffer(&mut self, length: uint, hints: Option<BufferHints>) -> Result<AnyBuffer, ObjectError> {
+        enter_span!("create_buffer", length = length);
+        let buffer = try!(backend(Device::create_buffer(self, length, hints)));
+        Ok(AnyBuffer { inner: wrap(buffer) })
+    }
+
+    fn write_buffer(&mut self, buffer: &AnyBuffer, offset: uint, data: &[u8]) -> Result<(), ObjectError> {
+        enter_span!("write_buffer", length = data.len());
+        let buffer = try!(get::<D::Buffer>(&buffer.inner));
+        Device::write_buffer(self, buffer, offset, data);
+        Ok(())
+    }
+
+    fn read_buffer(&mut self, buffer: &AnyBuffer, offset: uint, data: &mut [u8]) -> Result<(), ObjectError> {
+        enter_span!("read_buffer", length = data.len());
+        let buffer = try!(get::<D::Buffer>(&buffer.inner));
+        Device::read_buffer(self, buffer, offset, data);
+        Ok(())
+    }
+
+    fn create_texture(&mut self, desc: TextureDescriptor) -> Result<AnyTexture, ObjectError> {
+        enter_span!("create_texture");
+        let texture = try!(backend(Device::create_texture(self, desc)));
+        Ok(AnyTexture { inner: wrap(texture) })
+    }
+
+    fn create_texture_view(&mut self, texture: &AnyTexture, desc: TextureViewDescriptor) -> Result<AnyTexture, ObjectError> {
+        enter_span!("create_texture_view");
+        let texture = try!(get::<D::Texture>(&texture.inner));
+        let view = try!(backend(Device::create_texture_view(self, texture, desc)));
+        Ok(AnyTexture { inner: wrap(view) })
+    }
+
+    fn write_texture(&mut self, texture: &AnyTexture, mipmap_level: uint, slice: uint, origin: Origin, size: Size,
+                     data: &[u8], bytes_per_row: uint) -> Result<(), ObjectError> {
+        let texture = try!(get::<D::Texture>(&texture.inner));
+        Device::write_texture(self, texture, mipmap_level, slice, origin, size, data, bytes_per_row);
+        Ok(())
+    }
+
+    fn create_sampler(&mut self, desc: SamplerDescriptor) -> Result<AnySampler, ObjectError> {
+        enter_span!("create_sampler");
+        let sampler = try!(backend(Device::create_sampler(self, desc)));
+        Ok(AnySampler { inner: wrap(sampler) })
+    }
+
+    fn create_depth_stencil_state(&mut self, desc: DepthStencilStateDescriptor) -> AnyDepthStencilState {
+        enter_span!("create_depth_stencil_state");
+        AnyDepthStencilState { inner: wrap(Device::create_depth_stencil_state(self, desc)) }
+    }
+
+    fn create_render_pipeline(&mut self, program: &AnyShaderProgram, desc: RenderPipelineDescriptor) -> Result<AnyRenderPipeline, ObjectError> {
+        enter_span!("create_render_pipeline");
+        let program = try!(get::<D::ShaderProgram>(&program.inner));
+        let pipeline = try!(backend(Device::create_render_pipeline(self, program, desc)));
+        Ok(AnyRenderPipeline { inner: wrap(pipeline) })
+    }
+
+    fn create_compute_pipeline(&mut self, program: &AnyShaderProgram, desc: ComputePipelineDescriptor) -> Result<AnyComputePipeline, ObjectError> {
+        enter_span!("create_compute_pipeline");
+        let program = try!(get::<D::ShaderProgram>(&program.inner));
+        let pipeline = try!(backend(Device::create_compute_pipeline(self, program, desc)));
+        Ok(AnyComputePipeline { inner: wrap(pipeline) })
+    }
+
+    fn create_bind_group_layout(&mut self, desc: BindGroupLayoutDescriptor) -> Result<AnyBindGroupLayout, ObjectError> {
+        enter_span!("create_bind_group_layout");
+        let layout = try!(backend(Device::create_bind_group_layout(self, desc)));
+        Ok(AnyBindGroupLayout { inner: wrap(layout) })
+    }
+
+    fn create_bind_group(&mut self, layout: &AnyBindGroupLayout, entries: Vec<AnyBindGroupEntry>,
+                         unsized_length: uint) -> Result<AnyBindGroup, ObjectError> {
+        enter_span!("create_bind_group");
+        let layout = try!(get::<D::BindGroupLayout>(&layout.inner));
+        let entries = try!(bind_group_entries::<D>(entries));
+        let group = try!(backend(Device::create_bind_group(self, BindGroupDescriptor {
+            layout: layout,
+            entries: entries,
+            unsized_length: unsized_length,
+        })));
+        Ok(AnyBindGroup { inner: wrap(group) })
+    }
+
+    fn create_command_queue(&mut self, count: Option<uint>) -> Result<AnyCommandQueue, ObjectError> {
+        enter_span!("create_command_queue");
+        let queue = try!(backend(Device::create_command_queue(self, count)));
+        Ok(AnyCommandQueue { inner: wrap(queue) })
+    }
+
+    fn submit(&mut self, queue: &mut AnyCommandQueue, passes: Vec<AnyPass>) -> Result<Fence, ObjectError> {
+        enter_span!("submit", passes = passes.len());
+        let queue = try!(get_mut::<D::CommandQueue>(&mut queue.inner));
+        let mut commands = queue.acquire_command_buffer();
+        for pass in passes.into_iter() {
+            match pass {
+                AnyPass::Compute(list) => {
+                    let mut encoder = commands.compute_command_encoder();
+                    let result = encode_compute::<D>(&mut encoder, list);
+                    encoder.end_encoding();
+                    try!(result);
+                }
+                AnyPass::Blit(list) => {
+                    let mut encoder = commands.blit_command_encoder();
+                    let result = encode_blit::<D>(&mut encoder, list);
+                    encoder.end_encoding();
+                    try!(result);
+                }
+                AnyPass::Render(AnyRenderPass { color_attachments, depth_attachment, commands: list }) => {
+                    let desc = try!(render_pass_descriptor::<D>(color_attachments, depth_attachment));
+                    let mut encoder = commands.render_command_encoder(desc);
+                    let result = encode_render::<D>(&mut encoder, list);
+                    encoder.end_encoding();
+                    try!(result);
+                }
+            }
+        }
+        let fence = Fence::new();
+        commands.add_completed_handler(fence.completed_handler());
+        commands.commit();
+        Ok(fence)
+    }
+
+    #[cfg(feature = "raw_handles")]
+    unsafe fn as_raw(&self) -> RawHandle {
+        Device::as_raw(self)
+    }
+
+    #[cfg(feature = "raw_handles")]
+    unsafe fn raw_buffer(&self, buffer: &AnyBuffer) -> Result<RawHandle, ObjectError> {
+        Ok(try!(get::<D::Buffer>(&buffer.inner)).as_raw())
+    }
+
+    #[cfg(feature = "raw_handles")]
+    unsafe fn raw_texture(&self, texture: &AnyTexture) -> Result<RawHandle, ObjectError> {
+        Ok(try!(get::<D::Texture>(&texture.inner)).as_raw())
+    }
+}
+
+/// A device of any backend.
+pub struct AnyDevice {
+    backend: BackendKind,
+    adapter_name: String,
+    device: Box<DeviceObject + Send>,
+}
+
+impl AnyDevice {
+    /// Wrap a device of a backend.
+    pub fn new<D: Device + Send + 'static>(backend: BackendKind, adapter_name: String, device: D) -> AnyDevice {
+        AnyDevice {
+            backend: backend,
+            adapter_name: adapter_name,
+            device: box device as Box<DeviceObject + Send>,
+        }
+    }
+
+    /// The backend the device was opened with.
+    pub fn backend(&self) -> BackendKind {
+        self.backend.clone()
+    }
+
+    /// The name of the adapter the device was opened on.
+    pub fn adapter_name(&self) -> &str {
+        self.adapter_name.as_slice()
+    }
+
+    pub fn features(&self) -> DeviceFeatures {
+        self.device.features()
+    }
+
+    pub fn limits(&self) -> DeviceLimits {
+        self.device.limits()
+    }
+
+    pub fn format_capabilities(&self, format: PixelFormat) -> FormatCapabilities {
+        self.device.format_capabilities(format)
+    }
+
+    /// The device, as a `DeviceObject`.
+    pub fn object(&mut self) -> &mut DeviceObject {
+        &mut *self.device
+    }
+
+    /// The backend's device, if it is a `D`.
+    pub fn downcast_ref<D: Device + 'static>(&self) -> Option<&D> {
+        self.device.as_any().downcast_ref::<D>()
+    }
+
+    /// The backend's device, if it is a `D`.
+    pub fn downcast_mut<D: Device + 'static>(&mut self) -> Option<&mut D> {
+        self.device.as_any_mut().downcast_mut::<D>()
+    }
+}
+
+/// A backend `auto` can open devices with.
+pub struct BackendEntry {
+    kind: BackendKind,
+    open: Box<FnMut(&DeviceDescriptor) -> Result<AnyDevice, String> + 'static>,
+}
+
+impl BackendEntry {
+    /// Register a backend, whose adapters are listed by `adapters`.
+    ///
+    /// `adapters` is only called if `auto` gets to this backend, so backends which are not
+    /// needed are never initialized. Adapters are tried in the order they are listed in.
+    pub fn new<A, F>(kind: BackendKind, adapters: F) -> BackendEntry
+            where A: Adapter + 'static, A::Device: Send + 'static, F: FnMut() -> Vec<A> + 'static {
+        let mut adapters = adapters;
+        let backend = kind.clone();
+        BackendEntry {
+            kind: kind,
+            open: box move |desc: &DeviceDescriptor| {
+                let mut failures = Vec::new();
+                for adapter in adapters().into_iter() {
+                    match adapter.open(desc.clone()) {
+                        Ok(device) => return Ok(AnyDevice::new(backend.clone(), adapter.name(), device)),
+                        Err(e) => failures.push(format!("{}: {}", adapter.name(), describe(&e))),
+                    }
+                }
+                if failures.is_empty() {
+                    Err("no adapters".to_string())
+                } else {
+                    Err(failures.connect("; "))
+                }
+            },
+        }
+    }
+
+    pub fn kind(&self) -> BackendKind {
+        self.kind.clone()
+    }
+}
+
+fn describe<E: fmt::Show>(error: &OpenDeviceError<E>) -> String {
+    match *error {
+        OpenDeviceError::UnsupportedFeatures(ref missing) => format!("unsupported features {}", missing),
+        OpenDeviceError::UnsupportedLimits(ref unmet) => format!("unsupported limits {}", unmet),
+        OpenDeviceError::Backend(ref e) => format!("{}", e),
+    }
+}
+
+/// Why `auto` could not open a device.
+#[deriving(Show)]
+pub struct AutoError {
+    /// Every backend that was tried, in order, with why it failed.
+    pub attempts: Vec<(BackendKind, String)>,
+}
+
+/// Open a device with the first backend in `order` that can provide one meeting `desc`.
+///
+/// Backends in `order` which are not in `backends` are skipped, as are backends in `backends`
+/// which are not in `order`.
+pub fn auto(desc: &DeviceDescriptor, order: &[BackendKind], backends: Vec<BackendEntry>) -> Result<AnyDevice, AutoError> {
+    let mut backends = backends;
+    let mut attempts = Vec::new();
+    for kind in order.iter() {
+        for backend in backends.iter_mut().filter(|b| b.kind == *kind) {
+            match (backend.open)(desc) {
+                Ok(device) => return Ok(device),
+                Err(e) => attempts.push((kind.clone(), e)),
+            }
+        }
+    }
+    Err(AutoError { attempts: attempts })
+}
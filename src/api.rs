@@ -0,0 +1,3539 @@
+//! The abstraction itself: the device, resource and encoder traits, and the descriptor types.
+//!
+//! This module only needs `core` and `alloc`, so a backend for a platform without `std` can be
+//! written against it by building the crate without its default `std` feature. Everything else in
+//! the crate is built on top of it, and needs `std`.
+
+#[cfg(not(feature = "std"))]
+use core::prelude::*;
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+use alloc::arc::Arc;
+#[cfg(not(feature = "std"))]
+use collections::{String, Vec};
+use core::fmt::Show;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+use shader_archive::{BindingRemap, ShaderArchive};
+#[cfg(feature = "std")]
+use adapter_snapshot::{AdapterSnapshot, PIXEL_FORMATS};
+#[cfg(feature = "openxr")]
+use openxr::GraphicsBinding;
+#[cfg(feature = "shared_swapchain")]
+use shared_swapchain::ExternalHandle;
+#[cfg(feature = "xr")]
+use xr::{RasterizationRateMap, RasterizationRateMapDescriptor, XrRenderPass};
+
+pub trait Resource {
+    /// The backend's native object of this resource: the `MTLBuffer` or `MTLTexture`, `VkBuffer`
+    /// or `VkImage`, `ID3D12Resource`, or OpenGL buffer or texture name.
+    ///
+    /// Unsafe because nothing the backend does to the object stays consistent with what is done
+    /// to it natively: the handle must not be destroyed, and native uses of it must be
+    /// synchronized with this crate's by the caller.
+    #[cfg(feature = "raw_handles")]
+    unsafe fn as_raw(&self) -> RawHandle;
+}
+pub trait Buffer : Resource {
+    /// The address of the start of this buffer in the GPU's address space.
+    ///
+    /// This is `None` unless the device supports the `buffer_device_address` feature and the
+    /// buffer was created with the `device_address` hint. The address stays the same for the
+    /// lifetime of the buffer, and may be offset to point into the buffer like any other pointer.
+    ///
+    /// Addresses are handed to shaders as plain `u64` data (see `GpuAddress`), in `set_bytes`
+    /// data or inside of other buffers. Shaders may then dereference them as pointers, with the
+    /// usual alignment requirements of the pointed-to type. Because the backend cannot see which
+    /// buffers are reachable through such pointers, every buffer accessed that way must be
+    /// declared to the encoder with `use_resource`, and must not be destroyed while pending work
+    /// can still dereference it.
+    fn gpu_address(&self) -> Option<GpuAddress>;
+
+    /// The length of this buffer in bytes, as it was created with.
+    fn length(&self) -> uint;
+}
+pub trait Texture : Resource {
+    /// The format texels are read and written as: the texture's format, or a view's own.
+    fn pixel_format(&self) -> PixelFormat;
+
+    /// The descriptor this texture was created with. A view's is that of a texture of the
+    /// view's format, mip levels and array slices, whose level `0` is the view's base level, so
+    /// regions of views are checked against it as those of textures are (see `texture::math`).
+    fn descriptor(&self) -> &TextureDescriptor;
+}
+pub trait Sampler;
+pub trait Heap {
+    /// The size of this heap in bytes, as it was created with.
+    fn size(&self) -> uint;
+}
+pub trait DepthStencilState {
+    /// The configuration this state was created with.
+    fn descriptor(&self) -> &DepthStencilStateDescriptor;
+}
+pub trait ShaderProgram {
+    /// The optional device features that this shader program makes use of.
+    ///
+    /// A shader program can only be created on devices which support all of these (see
+    /// `Device::features`), so this is mostly useful for picking between a set of shader
+    /// variants written for different device tiers.
+    fn required_features(&self) -> DeviceFeatures;
+
+    /// How the translation this program was compiled from moved the bindings of its source, for
+    /// remapping bind groups written against the source (see `shader_archive`). `None` if nothing
+    /// was moved, or nothing reported it: backends return the remap of the archive entry a
+    /// program was created from.
+    fn binding_remap(&self) -> Option<&BindingRemap> {
+        None
+    }
+}
+/// A queue of command buffers, executed by the device in order.
+///
+/// A queue created with a `count` has that many command buffer "slots". A slot is taken when a
+/// command buffer is acquired, and given back when that command buffer completes on the GPU (or is
+/// dropped without being committed). When every slot is taken, the queue is full:
+/// `acquire_command_buffer` blocks, `try_acquire_command_buffer` fails, and
+/// `notify_when_available` defers its callback until a slot frees up. This is what frame pacing is
+/// built on: with a queue of `count` 2, the CPU can never get more than two frames ahead of the
+/// GPU.
+///
+/// Queues created without a `count` are never full.
+pub trait CommandQueue<D: Device> {
+    /// Acquire a new command buffer, blocking while the queue is full.
+    fn acquire_command_buffer(&mut self) -> D::CommandBuffer;
+
+    /// Acquire a new command buffer if the queue is not full.
+    ///
+    /// Returns `None` instead of blocking when every slot is taken.
+    fn try_acquire_command_buffer(&mut self) -> Option<D::CommandBuffer>;
+
+    /// Call `f` once a command buffer can be acquired without blocking.
+    ///
+    /// If the queue is not full, `f` is called right away. Otherwise it is called, possibly on
+    /// another thread, as soon as a slot is given back. Another thread may take that slot first,
+    /// so `f` should use `try_acquire_command_buffer` and wait again if it fails. This is the
+    /// building block for waiting on the queue from an event loop or async runtime.
+    fn notify_when_available(&self, f: Box<FnOnce() + Send>);
+
+    /// The maximum number of uncompleted command buffers, or `None` if unbounded.
+    fn capacity(&self) -> Option<uint>;
+
+    /// The priority the GPU schedules this queue's work with: the one it was created with, or
+    /// `Normal` on devices without the `queue_priority` feature.
+    fn priority(&self) -> QueuePriority;
+
+    /// Set when the allocations behind this queue's command buffers (command pools, allocators,
+    /// the memory their commands are encoded into) are recycled. Queues start out with
+    /// `CommandBufferPooling::OnCompletion`.
+    fn set_pooling(&mut self, pooling: CommandBufferPooling);
+
+    /// With `CommandBufferPooling::PerFrame`, recycle the allocations of every command buffer
+    /// acquired from this queue which has completed. Call it once a frame, typically after
+    /// waiting for the frame before last. Otherwise this does nothing.
+    fn reset_pool(&mut self);
+
+    /// How this queue's pool has been used since it was created, or since the statistics were
+    /// last reset.
+    ///
+    /// See the `CommandBufferPoolStatistics` struct for what is counted.
+    fn pool_statistics(&self) -> CommandBufferPoolStatistics;
+
+    /// Start counting `pool_statistics` from zero, keeping the pool as it is.
+    fn reset_pool_statistics(&mut self);
+
+    /// Block until every command buffer committed to this queue has completed, and their
+    /// completed handlers have run.
+    fn wait_idle(&self);
+
+    /// The backend's native queue: the `MTLCommandQueue`, `VkQueue` or `ID3D12CommandQueue`.
+    /// Unsafe, as for `Resource::as_raw`.
+    #[cfg(feature = "raw_handles")]
+    unsafe fn as_raw(&self) -> RawHandle;
+}
+/// A buffer of GPU commands, executed on the queue it was acquired from.
+///
+/// Command buffers execute in the order they take their place in the queue. That place is taken
+/// either explicitly by `enqueue`, or implicitly by `commit` for command buffers which were never
+/// enqueued. Encoding into a command buffer does not touch its queue, so command buffers can be
+/// encoded on any thread.
+///
+/// Commands are recorded with encoders, created by `render_command_encoder`,
+/// `compute_command_encoder` and `blit_command_encoder`. Only one encoder may be open on a command
+/// buffer at a time: each must be finished with `end_encoding` before the next is created, and
+/// the commands of consecutive encoders execute in the order the encoders were created.
+pub trait CommandBuffer<D: Device> : Send {
+    /// Begin a render pass, and create the encoder which records its draws.
+    ///
+    /// The attachments are loaded as their `load_action`s say when the pass begins, and stored as
+    /// their `store_action`s say when the encoder's `end_encoding` is called. Panics if `desc`
+    /// is not valid (see `RenderPassDescriptor::validate`).
+    fn render_command_encoder(&mut self, desc: RenderPassDescriptor<D>) -> D::RenderCommandEncoder;
+
+    /// Begin a render pass rendering the views of `xr` at once, at the rasterization rates of
+    /// its map, if it has one. Draws must use pipelines created for as many views (see
+    /// `Device::create_multiview_render_pipeline`).
+    ///
+    /// Panics as `render_command_encoder` does, and if `xr` is not valid (see
+    /// `XrRenderPass::validate`).
+    #[cfg(feature = "xr")]
+    fn xr_render_command_encoder(&mut self, desc: RenderPassDescriptor<D>, xr: XrRenderPass<D>) -> D::RenderCommandEncoder;
+
+    /// Create an encoder which records compute dispatches.
+    fn compute_command_encoder(&mut self) -> D::ComputeCommandEncoder;
+
+    /// Create an encoder which records copies and fills.
+    fn blit_command_encoder(&mut self) -> D::BlitCommandEncoder;
+
+    /// Write the GPU clock, in the ticks `Device::calibrate_timestamps` measures, as a `u64` at
+    /// byte `offset` of `buffer`, once everything encoded before it has finished executing.
+    ///
+    /// `offset` must be a multiple of `8`, and no encoder may be open. This requires the
+    /// `timestamp_writes` device feature. Two timestamps around some encoders measure how long
+    /// the GPU spent on them (see `util::gpu_profiler`).
+    fn write_timestamp(&mut self, buffer: &D::Buffer, offset: uint);
+
+    /// Open a named group of the commands encoded after it, which GPU debuggers and frame
+    /// captures show as one node. Groups nest, and no encoder may be open.
+    fn push_debug_group(&mut self, label: &str);
+
+    /// Close the group opened by the last unmatched `push_debug_group` of this command buffer.
+    fn pop_debug_group(&mut self);
+
+    /// Set `event` to `value` once everything encoded before it has finished executing.
+    ///
+    /// No encoder may be open. The values an event is signaled with must increase, whichever
+    /// queues they are signaled from.
+    fn encode_signal_event(&mut self, event: &D::Event, value: u64);
+
+    /// Hold back everything encoded after this until `event` reaches at least `value`.
+    ///
+    /// No encoder may be open. Waiting on a value which is only signaled by a command buffer
+    /// of the same queue which executes later never finishes.
+    fn encode_wait_for_event(&mut self, event: &D::Event, value: u64);
+
+    /// Register a function to be called, possibly on another thread, once this command buffer
+    /// has finished executing.
+    ///
+    /// Handlers are called in the order they were added, with the command buffer's final status.
+    /// A command buffer which is dropped without being committed never completes, and its handlers
+    /// are dropped without being called.
+    fn add_completed_handler(&mut self, handler: Box<FnOnce(CommandBufferStatus) + Send>);
+
+    /// Reserve this command buffer's place in its queue, without submitting it.
+    ///
+    /// The command buffer will execute after every command buffer enqueued before it and before
+    /// every command buffer enqueued after it, regardless of the order they are committed in. The
+    /// queue does not execute past an enqueued command buffer until it is committed.
+    ///
+    /// This is what makes multithreaded encoding deterministic: enqueue the command buffers for a
+    /// frame in the order they must run, hand them out to worker threads, and commit each one when
+    /// its thread finishes encoding. Enqueuing more than once does nothing.
+    fn enqueue(&mut self);
+
+    /// Submit this command buffer for execution.
+    ///
+    /// If it was not enqueued, it is enqueued first. No encoder may be open on it.
+    fn commit(self);
+
+    /// Submit this command buffer for execution, and block until it has finished.
+    ///
+    /// This is `commit` followed by waiting for the completed handlers, mostly for tests and
+    /// offline tools; anything interactive should use `add_completed_handler` instead.
+    fn commit_and_wait(self) -> CommandBufferStatus;
+
+    /// Mark this command buffer as intentionally long running.
+    ///
+    /// On devices with the `long_running_command_buffers` feature, the GPU watchdog (see
+    /// `Device::watchdog`) is not applied to this command buffer, so a long simulation will not
+    /// get the device reset under it. Without the feature this does nothing, and long workloads
+    /// must be split up instead (see `util::dispatch_splitting`).
+    fn set_long_running(&mut self);
+
+    /// Make the contents of `resource` undefined from here on, as they are for a resource just
+    /// created, because nothing encoded later reads them before writing them.
+    ///
+    /// This is for transient resources reused from one use to the next: backends can then skip
+    /// preserving the old contents (a layout transition from undefined on Vulkan,
+    /// `DiscardResource` on d3d12, not loading the old tiles on tile based GPUs). Attachments
+    /// overwritten by a render pass do the same with `LoadAction::DontCare`. No encoder may be
+    /// open, and no pending work may still use the old contents.
+    fn discard_contents(&mut self, resource: ResourceRef<D>);
+
+    /// Hand the memory `after` shares with `before` (resources placed at overlapping ranges of
+    /// a heap, see `Device::create_placed_buffer`) over to `after`, which is used from here on.
+    /// `before` is `None` to hand over from whichever of the aliased resources was used last.
+    ///
+    /// Aliased resources share memory, not contents: the contents of `after` are undefined
+    /// afterwards, as for `discard_contents`, and no encoder may be open. Using aliased
+    /// resources in one pass, or one after another without this barrier in between, corrupts
+    /// both, in whatever way the GPU's caches and compression make of it;
+    /// `Recording::aliasing_hazards` finds where that happens.
+    fn aliasing_barrier(&mut self, before: Option<ResourceRef<D>>, after: ResourceRef<D>);
+}
+
+/// How the GPU schedules the work of a command queue relative to other queues. See
+/// `Device::create_command_queue_with_priority`.
+#[deriving(Clone, PartialEq, Show)]
+pub enum QueuePriority {
+    /// Background work, which should not hold up anything else.
+    Low,
+    Normal,
+    /// Work which must finish on time, such as compositing for a VR headset. Some platforms only
+    /// grant this to privileged processes, and create a `Normal` queue otherwise.
+    High,
+}
+
+/// A heap of memory for placed resources. See `Device::create_heap`.
+#[deriving(Clone, Show)]
+pub struct HeapDescriptor {
+    pub label: Option<String>,
+    /// The size of the heap in bytes.
+    pub size: uint,
+}
+
+/// What placing a resource in a heap takes. See `Device::texture_placement_requirements`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct PlacementRequirements {
+    /// The number of bytes of the heap the resource covers.
+    pub size: uint,
+    /// What the offset of the resource in the heap must be a multiple of.
+    pub alignment: uint,
+}
+
+/// A point in the life of a frame, from input to display, for `Device::set_latency_marker`.
+#[deriving(Clone, PartialEq, Show)]
+pub enum LatencyMarker {
+    /// The input the frame reacts to was sampled.
+    InputSample,
+    SimulationStart,
+    SimulationEnd,
+    /// The first command buffer of the frame is about to be committed.
+    RenderSubmitStart,
+    /// The last command buffer of the frame was committed.
+    RenderSubmitEnd,
+    /// The frame is about to be presented.
+    PresentStart,
+    /// The frame was handed to the presentation layer.
+    PresentEnd,
+}
+
+/// When a command queue recycles the allocations behind its command buffers. See
+/// `CommandQueue::set_pooling`.
+///
+/// Allocating them anew for every command buffer is a measurable cost per frame on backends
+/// whose command buffers sit on command pools or allocators (Vulkan, d3d12), and recycling them
+/// keeps memory from growing and shrinking every frame.
+#[deriving(Clone, PartialEq, Show)]
+pub enum CommandBufferPooling {
+    /// Allocate for every command buffer, and free the allocations when it completes.
+    None,
+    /// Recycle the allocations of a command buffer as soon as it completes, for the next one
+    /// acquired. This is the default.
+    OnCompletion,
+    /// Recycle the allocations of completed command buffers all at once, at
+    /// `CommandQueue::reset_pool`. Resetting whole pools at once is cheaper than resetting
+    /// command buffers one by one, and frames encoding about the same commands every frame keep
+    /// reusing allocations of about the right size.
+    PerFrame,
+}
+
+/// How a command queue's pool of command buffer allocations has been used. Returned by
+/// `CommandQueue::pool_statistics`.
+#[deriving(Clone, PartialEq, Show, Default)]
+pub struct CommandBufferPoolStatistics {
+    /// The command buffers acquired.
+    pub acquired: uint,
+    /// Of those, the ones which were given recycled allocations rather than new ones.
+    pub reused: uint,
+    /// The most command buffers which were acquired and not recycled at once.
+    pub peak_in_use: uint,
+    /// The allocations recycled and waiting to be reused, now.
+    pub idle: uint,
+    /// The bytes held by the pool, in use or idle, now, as far as the backend knows.
+    pub allocated_bytes: uint,
+}
+
+/// How a command buffer finished executing.
+#[deriving(Clone, PartialEq, Show)]
+pub enum CommandBufferStatus {
+    /// Every command executed.
+    Completed,
+    /// Execution stopped early. Anything the command buffer wrote is undefined.
+    Failed(CommandBufferError),
+}
+
+/// Why a command buffer failed.
+#[deriving(Clone, PartialEq, Show)]
+pub enum CommandBufferError {
+    /// The command buffer ran for longer than the watchdog allows (see `Device::watchdog`).
+    Timeout,
+    /// A shader accessed memory outside of any resource, and robustness did not catch it.
+    PageFault,
+    /// The device ran out of memory while executing.
+    OutOfMemory,
+    /// The device was lost, possibly because of another process. Every object created from it
+    /// must be recreated from a new device.
+    DeviceLost,
+}
+
+/// Optional capabilities that a device may or may not have.
+///
+/// Shaders which use any of these must only be used on devices that report them. Creating a
+/// shader program or pipeline which requires a missing feature fails with the corresponding
+/// creation error rather than producing undefined behavior at draw time.
+#[deriving(Clone, Default)]
+pub struct DeviceFeatures {
+    /// 64-bit integer atomic operations on storage buffers.
+    pub atomic_int64: bool,
+    /// Atomic operations on storage textures.
+    pub image_atomics: bool,
+    /// 16-bit floating point arithmetic in shaders (not just 16-bit storage).
+    pub float16_arithmetic: bool,
+    /// Packed 8-bit integer dot product instructions.
+    pub int8_dot_product: bool,
+    /// Unsized arrays of textures in bind groups, indexed non-uniformly by shaders ("bindless"
+    /// textures). The maximum array length is `DeviceLimits::max_bindless_textures`.
+    pub bindless_textures: bool,
+    /// Unsized arrays of samplers in bind groups ("sampler heaps"), indexed non-uniformly by
+    /// shaders, independently of any texture. The maximum array length is
+    /// `DeviceLimits::max_bindless_samplers`.
+    pub bindless_samplers: bool,
+    /// Cooperative matrix (subgroup matrix multiply-accumulate) operations in compute shaders.
+    ///
+    /// The matrix shapes and component types which can be used are reported by
+    /// `Device::cooperative_matrix_properties`.
+    pub cooperative_matrix: bool,
+    /// Indirect multi-draws which read the number of draws from a buffer
+    /// (`multi_draw_indexed_indirect_count`) natively.
+    pub multi_draw_indirect_count: bool,
+    /// The base vertex and base instance of a draw can be read by vertex functions as built-in
+    /// values (`[[base_vertex]]`/`[[base_instance]]` in metal-speak).
+    ///
+    /// The vertex and instance IDs always include the bases, whether or not this is supported.
+    pub shader_draw_parameters: bool,
+    /// Per-instance vertex buffers with a `step_rate` other than `1`.
+    pub instance_step_rate: bool,
+    /// Capturing vertex function outputs into buffers ("transform feedback"), and drawing the
+    /// captured vertices with `draw_from_stream_output`.
+    pub stream_output: bool,
+    /// Buffers can report their GPU address (`Buffer::gpu_address`) and shaders can dereference
+    /// such addresses as pointers.
+    pub buffer_device_address: bool,
+    /// Shaders can print debug output (`printf`-style), delivered through
+    /// `Device::set_shader_debug_callback`.
+    pub shader_debug_printf: bool,
+    /// The `Robustness::Zeroed` mode.
+    pub robust_access_zeroed: bool,
+    /// Command buffers marked with `CommandBuffer::set_long_running` are exempt from the GPU
+    /// watchdog.
+    pub long_running_command_buffers: bool,
+    /// The index of the primitive being rasterized can be read by fragment functions as a
+    /// built-in value (`[[primitive_id]]` in metal-speak). Visibility buffer renderers write it
+    /// (with the instance) into an integer attachment.
+    pub primitive_id: bool,
+    /// Fragment functions can write to storage buffers and textures, and perform atomic
+    /// operations on them.
+    ///
+    /// Such writes are not ordered between overlapping fragments, so a fragment function which
+    /// writes per pixel should run in a pass with a depth `Equal` test against a depth prepass,
+    /// which leaves exactly one fragment per pixel.
+    pub fragment_storage_writes: bool,
+    /// Raster order groups ("rasterizer ordered views"): fragment function accesses to storage
+    /// resources marked as belonging to a group are ordered between overlapping fragments, in
+    /// primitive order.
+    ///
+    /// This is what exact order-independent transparency needs: with it, fragments can sort
+    /// themselves into a small per-pixel array in place. Without it, the exact alternative is
+    /// per-pixel linked lists (appending with atomics on a storage buffer, then sorting in a
+    /// resolve pass), and the portable approximation is `util::oit`.
+    pub raster_order_groups: bool,
+    /// Render passes can place the samples of multisampled attachments anywhere within the
+    /// pixel (`RenderPassDescriptor::sample_positions`), rather than only at the standard
+    /// positions.
+    pub programmable_sample_positions: bool,
+    /// `CommandBuffer::write_timestamp`.
+    pub timestamp_writes: bool,
+    /// The vertex formats smaller than 32 bits per component, other than `UChar4Normalized`:
+    /// half floats, 8 and 16-bit normalized integers, and 10-10-10-2 packed normalized integers.
+    /// See `VertexFormat::required_features` and `util::vertex_packing`.
+    pub packed_vertex_formats: bool,
+    /// Fragment functions can write the stencil value of each fragment (`[[stencil]]` in
+    /// metal-speak), which stencil tests and `StencilOperation::Replace` then use instead of the
+    /// encoder's reference value. Decals and masks can so tag pixels with per-object values in a
+    /// single draw.
+    ///
+    /// Shader programs with a fragment function which exports stencil require this feature.
+    pub shader_stencil_export: bool,
+    /// Fragment functions can ask which mip level a sample would use, before clamping to the
+    /// levels of the texture (`calculate_unclamped_lod` in metal-speak). Without it, the level
+    /// has to be computed from the derivatives of the texture coordinates, which is what
+    /// `util::texture_feedback` does by default.
+    pub texture_lod_query: bool,
+    /// Triangles can be rasterized as their edges (`TriangleFillMode::Lines`), for wireframes
+    /// (see `util::wireframe`). Desktop GPUs all can; many mobile ones cannot.
+    pub fill_mode_lines: bool,
+    /// Command queues can be created with a `QueuePriority` other than `Normal`
+    /// (`Device::create_command_queue_with_priority`), which the GPU schedules ahead of or behind
+    /// the work of other queues. Without it, background work can only be kept from spiking frame
+    /// times by submitting little of it at a time (see `util::background_work`).
+    pub queue_priority: bool,
+    /// The backend hands latency markers and sleeps to the platform's latency mechanism (NVIDIA
+    /// Reflex, AMD Anti-Lag and the like), which uses them to keep the CPU from running ahead
+    /// of the GPU and measures the frames from input to display.
+    pub latency_markers: bool,
+    /// Buffers and textures can be placed at offsets of heaps the application manages
+    /// (`Device::create_heap`), where resources used at different times can alias the same
+    /// memory.
+    pub placed_resources: bool,
+    /// Render passes can render several views at once, each into its own array slice of the
+    /// attachments, with every draw broadcast to all of them ("multiview", vertex amplification
+    /// in metal-speak). Stereo XR renderers draw both eyes this way. Up to
+    /// `DeviceLimits::max_multiview_view_count` views; see the `xr` module.
+    pub multiview: bool,
+    /// Render passes can be rasterized at a lower rate in some regions of the attachments than
+    /// in others (rasterization rate maps on metal, fragment density maps on vulkan), which is
+    /// how XR renderers foveate. See the `xr` module.
+    pub rasterization_rate_maps: bool,
+    /// Textures and events can be shared with other processes through native handles, for
+    /// compositors rendering what their clients render. See the `shared_swapchain` module.
+    pub external_memory: bool,
+}
+
+impl DeviceFeatures {
+    /// Returns whether every feature in `required` is also present in `self`.
+    pub fn contains(&self, required: &DeviceFeatures) -> bool {
+        self.missing(required).is_empty()
+    }
+
+    /// Returns the names of the features in `required` which are not present in `self`.
+    pub fn missing(&self, required: &DeviceFeatures) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        macro_rules! check {
+            ($($feature:ident),*) => {
+                $(if required.$feature && !self.$feature {
+                    missing.push(stringify!($feature));
+                })*
+            }
+        }
+        check!(atomic_int64,
+               image_atomics,
+               float16_arithmetic,
+               int8_dot_product,
+               bindless_textures,
+               bindless_samplers,
+               cooperative_matrix,
+               multi_draw_indirect_count,
+               shader_draw_parameters,
+               instance_step_rate,
+               stream_output,
+               buffer_device_address,
+               shader_debug_printf,
+               robust_access_zeroed,
+               long_running_command_buffers,
+               primitive_id,
+               fragment_storage_writes,
+               raster_order_groups,
+               programmable_sample_positions,
+               timestamp_writes,
+               packed_vertex_formats,
+               shader_stencil_export,
+               texture_lod_query,
+               fill_mode_lines,
+               queue_priority,
+               latency_markers,
+               placed_resources,
+               multiview,
+               rasterization_rate_maps,
+               external_memory);
+        missing
+    }
+
+    /// Every feature, by name, and whether it is present in `self`, in declaration order.
+    pub fn values(&self) -> Vec<(&'static str, bool)> {
+        let mut values = Vec::new();
+        macro_rules! value {
+            ($($feature:ident),*) => {
+                $(values.push((stringify!($feature), self.$feature));)*
+            }
+        }
+        value!(atomic_int64,
+               image_atomics,
+               float16_arithmetic,
+               int8_dot_product,
+               bindless_textures,
+               bindless_samplers,
+               cooperative_matrix,
+               multi_draw_indirect_count,
+               shader_draw_parameters,
+               instance_step_rate,
+               stream_output,
+               buffer_device_address,
+               shader_debug_printf,
+               robust_access_zeroed,
+               long_running_command_buffers,
+               primitive_id,
+               fragment_storage_writes,
+               raster_order_groups,
+               programmable_sample_positions,
+               timestamp_writes,
+               packed_vertex_formats,
+               shader_stencil_export,
+               texture_lod_query,
+               fill_mode_lines,
+               queue_priority,
+               latency_markers,
+               placed_resources,
+               multiview,
+               rasterization_rate_maps,
+               external_memory);
+        values
+    }
+}
+
+/// How flexibly shaders can index arrays of bindings, and how those arrays may be filled.
+///
+/// Each tier includes everything allowed by the tiers before it, so tiers can be compared
+/// directly (`tier >= DescriptorIndexingTier::PartiallyBound`). Devices with either bindless
+/// feature are always at least `NonUniform`.
+#[deriving(Clone, PartialEq, PartialOrd)]
+pub enum DescriptorIndexingTier {
+    /// Arrays of bindings may only be indexed with dynamically uniform indices (every invocation
+    /// of a draw or dispatch uses the same index), and every element must be filled.
+    Uniform,
+    /// Arrays of textures, samplers and storage buffers may be indexed with non-uniform indices.
+    NonUniform,
+    /// Bindings marked `partially_bound` may leave elements unfilled, as long as shaders never
+    /// access them.
+    PartiallyBound,
+    /// Bindings marked `update_after_bind` may be changed with `Device::update_bind_group` while
+    /// the bind group is bound or used by submitted work, as long as that work does not access the
+    /// updated elements.
+    UpdateAfterBind,
+}
+
+/// How serious a message from a backend is.
+#[deriving(PartialEq, PartialOrd)]
+pub enum Severity {
+    /// Informational, such as which adapter was picked.
+    Info,
+    /// A hint that something is valid but slow (a redundant state change, a resource in a poor
+    /// memory location).
+    Performance,
+    /// Something is likely wrong, but still well defined.
+    Warning,
+    /// Invalid usage or a backend error. Results are likely wrong.
+    Error,
+}
+
+/// A single message printed by a shader.
+pub struct ShaderDebugMessage {
+    /// The name of the shader function which printed the message.
+    pub function: String,
+    /// The formatted message.
+    pub message: String,
+}
+
+/// A kind of suspicious value written by a shader.
+pub enum NumericIssue {
+    /// A NaN was written.
+    NaN,
+    /// An infinity was written.
+    Infinity,
+    /// A finite value outside of the range the destination can represent was written, such as a
+    /// value outside of `[0, 1]` to a unorm color attachment, or a depth outside of `[0, 1]`.
+    OutOfRange,
+}
+
+/// A report of suspicious values written by a pipeline.
+pub struct NumericValidationReport {
+    /// The label of the pipeline which wrote the values, if it had one.
+    pub pipeline_label: Option<String>,
+    pub issue: NumericIssue,
+    /// The color attachment index the values were written to, or `None` for the depth
+    /// attachment and for compute pipelines.
+    pub attachment: Option<uint>,
+    /// How many writes had this issue, within a single command buffer.
+    pub count: uint,
+}
+
+/// What happens when a shader accesses a buffer or texture out of bounds.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum Robustness {
+    /// Nothing is guaranteed. Out of bounds accesses are undefined behavior, and may read other
+    /// resources' data, corrupt memory, or hang the device. This is the fastest.
+    Disabled,
+    /// Out of bounds reads return some value from within the same resource (or zero), and out of
+    /// bounds writes are either discarded or land somewhere within the same resource. Nothing
+    /// outside of the resource is ever touched. Always available: backends without hardware
+    /// support instrument the shader.
+    Clamped,
+    /// Out of bounds reads return zero, and out of bounds writes are discarded. Requires the
+    /// `robust_access_zeroed` feature.
+    Zeroed,
+}
+
+/// The kind of resource that was accessed out of bounds.
+pub enum ResourceKind {
+    Buffer,
+    Texture,
+}
+
+/// A report of out of bounds accesses made by a pipeline.
+pub struct BoundsViolationReport {
+    /// The label of the pipeline which made the accesses, if it had one.
+    pub pipeline_label: Option<String>,
+    pub resource: ResourceKind,
+    /// The bind group index and binding number of the accessed resource, if it was bound through
+    /// a bind group.
+    pub binding: Option<(uint, uint)>,
+    /// How many out of bounds accesses were made, within a single command buffer.
+    pub count: uint,
+}
+
+/// Whether the operating system or driver kills GPU work that runs for too long.
+pub enum Watchdog {
+    /// Work may run for as long as it needs to. This is usually the case for devices which are not
+    /// driving a display.
+    Disabled,
+    /// Work running for longer than the timeout may be killed, losing the device (a "TDR" on
+    /// windows). The timeout is given in milliseconds when the backend can find out what it is.
+    Enforced { timeout_ms: Option<uint> },
+}
+
+/// How hot the system is running, from the operating system's point of view.
+///
+/// These mirror the thermal states of iOS and macOS; other platforms map what they have onto them.
+#[deriving(PartialEq, PartialOrd)]
+pub enum ThermalState {
+    /// No throttling.
+    Nominal,
+    /// Slightly elevated. Good time to stop doing optional work.
+    Fair,
+    /// The system is throttling. Quality should be reduced.
+    Serious,
+    /// The system is throttling heavily, and may shut down soon if nothing changes.
+    Critical,
+}
+
+/// The power and thermal situation of the system the device is in.
+pub struct PowerState {
+    pub thermal: ThermalState,
+    /// Whether the user or system has asked applications to save power ("low power mode").
+    pub low_power_mode: bool,
+    /// Whether the system is running on battery, if the backend can tell.
+    pub on_battery: Option<bool>,
+}
+
+/// Counts of the work encoded on a device, for HUD overlays (see `Device::take_statistics`).
+#[deriving(Clone, Default, Show)]
+pub struct FrameStatistics {
+    /// Draw calls, with every indirect draw (and every draw of a multi-draw) counted once.
+    pub draws: uint,
+    /// Compute dispatches.
+    pub dispatches: uint,
+    /// Triangles drawn by direct draws, every instance counted. The triangles of indirect draws
+    /// are only known to the GPU, and are not counted.
+    pub triangles: u64,
+    /// `set_render_pipeline` and `set_compute_pipeline` calls.
+    pub pipeline_changes: uint,
+    /// `set_bind_group` calls.
+    pub bind_group_sets: uint,
+    /// Every other state setting call: depth stencil states, viewports, scissors, buffers,
+    /// textures, samplers and bytes.
+    pub state_changes: uint,
+    /// Bytes written from the CPU with `write_buffer`, `write_texture` and `set_*_bytes`.
+    pub bytes_uploaded: u64,
+}
+
+impl PrimitiveType {
+    /// The number of triangles `vertex_count` vertices of this type make up.
+    pub fn triangle_count(&self, vertex_count: uint) -> uint {
+        match *self {
+            PrimitiveType::Triangle => vertex_count / 3,
+            PrimitiveType::TriangleStrip => if vertex_count < 3 { 0 } else { vertex_count - 2 },
+            PrimitiveType::Point | PrimitiveType::Line | PrimitiveType::LineStrip => 0,
+        }
+    }
+}
+
+/// A CPU and a GPU timestamp taken at (as close as the backend can get to) the same instant.
+pub struct TimestampCalibration {
+    /// The CPU timestamp, in nanoseconds of the monotonic clock `time::precise_time_ns` reads.
+    pub cpu_ns: u64,
+    /// The GPU timestamp, in GPU ticks.
+    pub gpu_ticks: u64,
+    /// The length of a GPU tick, in nanoseconds.
+    pub gpu_tick_ns: f64,
+    /// How far apart the two timestamps may actually have been taken, in nanoseconds.
+    pub max_deviation_ns: u64,
+}
+
+impl TimestampCalibration {
+    /// Convert a GPU timestamp of the same device to the CPU clock.
+    ///
+    /// GPU and CPU clocks drift apart over time, so the result gets less accurate the further
+    /// `gpu_ticks` is from the calibration; calibrating again every second or so keeps it within
+    /// a few microseconds.
+    pub fn gpu_to_cpu_ns(&self, gpu_ticks: u64) -> u64 {
+        let delta = (gpu_ticks as f64 - self.gpu_ticks as f64) * self.gpu_tick_ns;
+        (self.cpu_ns as f64 + delta) as u64
+    }
+}
+
+/// Numeric limits of a device.
+#[deriving(Clone)]
+pub struct DeviceLimits {
+    /// The maximum length of an unsized texture array binding. `0` without the
+    /// `bindless_textures` feature.
+    pub max_bindless_textures: uint,
+    /// The maximum length of an unsized sampler array binding. `0` without the
+    /// `bindless_samplers` feature.
+    pub max_bindless_samplers: uint,
+    /// The alignment, in bytes, of the `offset` of buffers bound as uniform buffers (through a
+    /// `UniformBuffer` binding). A power of two; `256` is what every backend can do.
+    pub min_uniform_buffer_offset_alignment: uint,
+    /// The alignment, in bytes, of the `offset` of buffers bound as storage buffers (through a
+    /// `StorageBuffer` binding). A power of two.
+    pub min_storage_buffer_offset_alignment: uint,
+    /// The maximum total length, in bytes, of the threadgroup memory of a compute pipeline (see
+    /// `ComputePipelineDescriptor::threadgroup_memory_lengths`).
+    pub max_threadgroup_memory_length: uint,
+    /// The maximum number of threads in one threadgroup of a dispatch.
+    pub max_threads_per_threadgroup: uint,
+    /// The maximum `sample_count` of a texture or pipeline. Every backend can do `4`.
+    pub max_sample_count: uint,
+    /// The maximum number of color attachments of a render pass or pipeline. At most
+    /// `MAX_COLOR_ATTACHMENTS`; every backend can do `4`.
+    pub max_color_attachments: uint,
+    /// The maximum number of views of a multiview render pass. `1` without the `multiview`
+    /// feature.
+    pub max_multiview_view_count: uint,
+}
+
+impl DeviceLimits {
+    /// Limits every device meets, for requiring nothing beyond the baseline.
+    pub fn baseline() -> DeviceLimits {
+        DeviceLimits {
+            max_bindless_textures: 0,
+            max_bindless_samplers: 0,
+            min_uniform_buffer_offset_alignment: 256,
+            min_storage_buffer_offset_alignment: 256,
+            max_threadgroup_memory_length: 16384,
+            max_threads_per_threadgroup: 256,
+            max_sample_count: 4,
+            max_color_attachments: 4,
+            max_multiview_view_count: 1,
+        }
+    }
+
+    /// Returns the names of the limits in `self` which are worse than those in `required`: lower
+    /// for maximums, higher for alignments.
+    pub fn unmet(&self, required: &DeviceLimits) -> Vec<&'static str> {
+        let mut unmet = Vec::new();
+        macro_rules! check {
+            ($($limit:ident),*) => {
+                $(if self.$limit < required.$limit {
+                    unmet.push(stringify!($limit));
+                })*
+            }
+        }
+        macro_rules! check_alignment {
+            ($($limit:ident),*) => {
+                $(if self.$limit > required.$limit {
+                    unmet.push(stringify!($limit));
+                })*
+            }
+        }
+        check!(max_bindless_textures,
+               max_bindless_samplers,
+               max_threadgroup_memory_length,
+               max_threads_per_threadgroup,
+               max_sample_count,
+               max_color_attachments,
+               max_multiview_view_count);
+        check_alignment!(min_uniform_buffer_offset_alignment,
+                         min_storage_buffer_offset_alignment);
+        unmet
+    }
+
+    /// Every limit, by name, and its value in `self`, in declaration order.
+    pub fn values(&self) -> Vec<(&'static str, uint)> {
+        let mut values = Vec::new();
+        macro_rules! value {
+            ($($limit:ident),*) => {
+                $(values.push((stringify!($limit), self.$limit));)*
+            }
+        }
+        value!(max_bindless_textures,
+               max_bindless_samplers,
+               min_uniform_buffer_offset_alignment,
+               min_storage_buffer_offset_alignment,
+               max_threadgroup_memory_length,
+               max_threads_per_threadgroup,
+               max_sample_count,
+               max_color_attachments,
+               max_multiview_view_count);
+        values
+    }
+}
+
+/// Hints describing how a buffer will be used.
+///
+/// A backend is free to ignore any of these, but they may influence where the buffer is placed
+/// and which operations it can be used for.
+pub struct BufferHints {
+    /// The buffer will be used as the source of a predicate for `begin_predication`.
+    ///
+    /// Predicate buffers may also be written by compute shaders (see `PREDICATE_SIZE` for the
+    /// layout each predicate must have), which is how GPU-driven culling feeds predication.
+    pub predicate: bool,
+
+    /// The buffer has an associated 32-bit atomic counter.
+    ///
+    /// Shaders which bind the buffer as a storage buffer can atomically increment ("append") or
+    /// decrement ("consume") the counter to allocate or release elements. The counter lives
+    /// outside of the buffer's `length` bytes; it can only be read by copying it into another
+    /// buffer with `copy_counter_value`, and set with `reset_counter`. A newly created buffer's
+    /// counter is `0`.
+    pub counter: bool,
+
+    /// The buffer will be a stream output target.
+    ///
+    /// Like `counter`, this gives the buffer some hidden state: the number of bytes that were
+    /// captured into it by the last draw which used it as a target. This requires the
+    /// `stream_output` device feature.
+    pub stream_output: bool,
+
+    /// The buffer's GPU address will be queried with `Buffer::gpu_address`. This requires the
+    /// `buffer_device_address` device feature.
+    pub device_address: bool,
+}
+
+/// An address in the GPU's address space, as returned by `Buffer::gpu_address`.
+///
+/// This has the same layout as a `uint64_t`/`device T*` on the shader side, so it can be written
+/// directly into `set_bytes` data, argument buffers, or shader binding tables. Addresses which
+/// are stored in buffers must be 8-byte aligned.
+#[repr(C)]
+pub struct GpuAddress(pub u64);
+
+/// A native object of a backend's API, for middleware (physics debug renderers, video SDKs, ...)
+/// which talks to the API directly. Returned by the `as_raw` methods.
+///
+/// Pointers are the API's own (retained by the backend, not by the handle), and handles of the
+/// other APIs are their integer values.
+#[cfg(feature = "raw_handles")]
+#[deriving(Clone, PartialEq, Show)]
+pub enum RawHandle {
+    /// An Objective-C object: `id<MTLDevice>`, `id<MTLBuffer>`, ...
+    Metal(*mut u8),
+    /// A dispatchable handle (`VkDevice`, `VkQueue`) as its pointer value, or a non-dispatchable
+    /// one (`VkBuffer`, `VkImage`) as its 64 bit value.
+    Vulkan(u64),
+    /// A COM interface pointer: `ID3D12Device *`, `ID3D12Resource *`, ...
+    D3D12(*mut u8),
+    /// An OpenGL object name. The context is the one current when the device was opened.
+    OpenGl(u32),
+}
+
+/// A range of bytes of a buffer, which is known to lie within it.
+///
+/// This is how buffers are bound (into bind groups, as vertex, index and shader buffers) and
+/// copied: like a `&[u8]`, it cannot be made to reach past the end of its buffer, so a bad size
+/// panics where it was computed rather than turning into an out of bounds GPU access. Commands
+/// which read a fixed size structure (indirect arguments, predicates, counter values, timestamps)
+/// still take a buffer and an offset.
+pub struct BufferSlice<'a, B: 'a> {
+    buffer: &'a B,
+    offset: uint,
+    size: uint,
+}
+
+impl<'a, B: Buffer> BufferSlice<'a, B> {
+    /// The whole of `buffer`.
+    pub fn whole(buffer: &'a B) -> BufferSlice<'a, B> {
+        BufferSlice { buffer: buffer, offset: 0, size: buffer.length() }
+    }
+
+    /// The `size` bytes of `buffer` from byte `offset` on.
+    ///
+    /// Panics if that reaches past the end of `buffer`.
+    pub fn new(buffer: &'a B, offset: uint, size: uint) -> BufferSlice<'a, B> {
+        match BufferSlice::try_new(buffer, offset, size) {
+            Some(slice) => slice,
+            None => panic!("buffer slice {}..{} out of bounds of a {} byte buffer", offset, offset + size, buffer.length()),
+        }
+    }
+
+    /// The rest of `buffer` from byte `offset` on. Panics if `offset` is past its end.
+    pub fn from_offset(buffer: &'a B, offset: uint) -> BufferSlice<'a, B> {
+        let length = buffer.length();
+        if offset > length {
+            panic!("buffer slice offset {} out of bounds of a {} byte buffer", offset, length);
+        }
+        BufferSlice { buffer: buffer, offset: offset, size: length - offset }
+    }
+
+    /// As `new`, but returns `None` instead of panicking.
+    pub fn try_new(buffer: &'a B, offset: uint, size: uint) -> Option<BufferSlice<'a, B>> {
+        match offset.checked_add(size) {
+            Some(end) if end <= buffer.length() => Some(BufferSlice { buffer: buffer, offset: offset, size: size }),
+            _ => None,
+        }
+    }
+
+    /// The `size` bytes of this slice from byte `offset` of it on. Panics if that reaches past
+    /// the end of this slice.
+    pub fn slice(&self, offset: uint, size: uint) -> BufferSlice<'a, B> {
+        if offset.checked_add(size).map_or(true, |end| end > self.size) {
+            panic!("buffer slice {}..{} out of bounds of a {} byte slice", offset, offset + size, self.size);
+        }
+        BufferSlice { buffer: self.buffer, offset: self.offset + offset, size: size }
+    }
+
+    pub fn buffer(&self) -> &'a B {
+        self.buffer
+    }
+
+    /// The offset of the first byte of the slice in its buffer.
+    pub fn offset(&self) -> uint {
+        self.offset
+    }
+
+    /// The length of the slice in bytes.
+    pub fn size(&self) -> uint {
+        self.size
+    }
+}
+
+impl<'a, B> Clone for BufferSlice<'a, B> {
+    fn clone(&self) -> BufferSlice<'a, B> {
+        BufferSlice { buffer: self.buffer, offset: self.offset, size: self.size }
+    }
+}
+
+impl GpuAddress {
+    /// The address `bytes` bytes past this one.
+    pub fn offset(&self, bytes: u64) -> GpuAddress {
+        let GpuAddress(address) = *self;
+        GpuAddress(address + bytes)
+    }
+}
+
+/// The format of the texels of a texture.
+///
+/// The `Uint` and `Sint` formats are read and written by shaders as unsigned and signed
+/// integers, without any conversion. They can be render targets (this is how ID buffers are
+/// rendered), but cannot be filtered or blended.
+#[deriving(Clone, PartialEq, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum PixelFormat {
+    R8Unorm,
+    RG8Unorm,
+    RGBA8Unorm,
+    RGBA8UnormSrgb,
+    BGRA8Unorm,
+    BGRA8UnormSrgb,
+    R16Float,
+    RG16Float,
+    RGBA16Float,
+    R32Float,
+    RG32Float,
+    RGBA32Float,
+    R32Uint,
+    RG32Uint,
+    R32Sint,
+    /// Unsigned floats, 11 bits for red and green and 10 for blue, without alpha: half the size
+    /// of `RGBA16Float`, for HDR light buffers.
+    RG11B10Float,
+    /// Unsigned floats of 9 bits of mantissa each, sharing a 5-bit exponent, without alpha. More
+    /// precise than `RG11B10Float` for colors of similar magnitude, but rarely renderable; see
+    /// `Device::format_capabilities`.
+    RGB9E5Float,
+    Depth32Float,
+    Depth24UnormStencil8,
+    Depth32FloatStencil8,
+}
+
+impl PixelFormat {
+    /// The size of a single texel, in bytes.
+    pub fn bytes_per_pixel(&self) -> uint {
+        match *self {
+            PixelFormat::R8Unorm => 1,
+            PixelFormat::RG8Unorm | PixelFormat::R16Float => 2,
+            PixelFormat::RGBA8Unorm | PixelFormat::RGBA8UnormSrgb | PixelFormat::BGRA8Unorm |
+            PixelFormat::BGRA8UnormSrgb | PixelFormat::RG16Float | PixelFormat::R32Float |
+            PixelFormat::R32Uint | PixelFormat::R32Sint | PixelFormat::RG11B10Float |
+            PixelFormat::RGB9E5Float | PixelFormat::Depth32Float | PixelFormat::Depth24UnormStencil8 => 4,
+            PixelFormat::RGBA16Float | PixelFormat::RG32Float | PixelFormat::RG32Uint |
+            PixelFormat::Depth32FloatStencil8 => 8,
+            PixelFormat::RGBA32Float => 16,
+        }
+    }
+
+    /// Whether texels are (signed or unsigned) integers rather than normalized or floating point
+    /// numbers.
+    pub fn is_integer(&self) -> bool {
+        self.is_unsigned_integer() || self.is_signed_integer()
+    }
+
+    /// Whether texels are unsigned integers.
+    pub fn is_unsigned_integer(&self) -> bool {
+        match *self {
+            PixelFormat::R32Uint | PixelFormat::RG32Uint => true,
+            _ => false,
+        }
+    }
+
+    /// Whether texels are signed integers.
+    pub fn is_signed_integer(&self) -> bool {
+        match *self {
+            PixelFormat::R32Sint => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the format has a depth component, and so can only be a depth attachment.
+    pub fn has_depth(&self) -> bool {
+        match *self {
+            PixelFormat::Depth32Float | PixelFormat::Depth24UnormStencil8 | PixelFormat::Depth32FloatStencil8 => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the format has a stencil component, and so can be a stencil attachment.
+    pub fn has_stencil(&self) -> bool {
+        match *self {
+            PixelFormat::Depth24UnormStencil8 | PixelFormat::Depth32FloatStencil8 => true,
+            _ => false,
+        }
+    }
+}
+
+/// What textures of a pixel format can be used for on a device (see
+/// `Device::format_capabilities`).
+///
+/// Every format can be created with the `shader_read` usage, but the packed HDR formats in
+/// particular vary in the rest: `RG11B10Float` is renderable on most devices, `RGB9E5Float` on
+/// few (Apple GPUs, mostly), and neither is usually a storage texture format.
+#[deriving(Clone, Default, Show)]
+pub struct FormatCapabilities {
+    /// Shaders can sample textures of the format with linear filtering, not just nearest.
+    pub filterable: bool,
+    /// Textures of the format can have the `render_target` usage.
+    pub render_target: bool,
+    /// Render targets of the format can be blended into.
+    pub blendable: bool,
+    /// Textures of the format can have the `shader_write` usage.
+    pub storage: bool,
+    /// Render targets of the format can be multisampled.
+    pub multisample: bool,
+}
+
+/// The dimensionality of a texture.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum TextureType {
+    Type2D,
+    Type2DArray,
+    Type3D,
+    TypeCube,
+}
+
+/// How a texture will be accessed. A texture must only be used in the ways it was created for.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct TextureUsage {
+    /// Sampled or read by shaders.
+    pub shader_read: bool,
+    /// Written by shaders (as a "storage" texture).
+    pub shader_write: bool,
+    /// Used as an attachment of a render pass.
+    pub render_target: bool,
+}
+
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct TextureDescriptor {
+    pub texture_type: TextureType,
+    pub pixel_format: PixelFormat,
+    pub width: uint,
+    pub height: uint,
+    /// The depth of a `Type3D` texture. Must be `1` for any other type.
+    pub depth: uint,
+    pub mipmap_level_count: uint,
+    /// The number of slices of a `Type2DArray` texture. Must be `1` for any other type.
+    pub array_length: uint,
+    /// The number of samples per texel. Anything but `1` makes a multisampled texture, which must
+    /// be a `Type2D` render target with a single mip level, and can only be read texel by texel.
+    /// Every power of two up to `DeviceLimits::max_sample_count` is supported.
+    pub sample_count: uint,
+    pub usage: TextureUsage,
+}
+
+/// The subset of a texture that a texture view covers.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct TextureViewDescriptor {
+    /// The format the view reinterprets the texture as. This must have the same texel size as
+    /// the texture's format.
+    pub pixel_format: PixelFormat,
+    pub base_mipmap_level: uint,
+    pub mipmap_level_count: uint,
+    pub base_array_slice: uint,
+    pub array_length: uint,
+}
+
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct SamplerDescriptor;
+/// A comparison between a new value and the value already stored in an attachment.
+///
+/// The test passes if `new <op> stored` holds.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum CompareFunction {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+/// What happens to the stored stencil value.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum StencilOperation {
+    Keep,
+    Zero,
+    /// Replace the stored value with the stencil reference value, or with the fragment's own
+    /// value if the fragment function exports one (see the `shader_stencil_export` feature).
+    Replace,
+    IncrementClamp,
+    DecrementClamp,
+    Invert,
+    IncrementWrap,
+    DecrementWrap,
+}
+
+/// The stencil test and update for one facing of primitives.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct StencilDescriptor {
+    /// The comparison between the (masked) reference value and the (masked) stored value.
+    pub stencil_compare_function: CompareFunction,
+    /// The operation when the stencil test fails.
+    pub stencil_failure_operation: StencilOperation,
+    /// The operation when the stencil test passes but the depth test fails.
+    pub depth_failure_operation: StencilOperation,
+    /// The operation when both tests pass.
+    pub depth_stencil_pass_operation: StencilOperation,
+    /// The bits of the reference and stored values which take part in the comparison.
+    pub read_mask: u32,
+    /// The bits of the stored value which the operations may change.
+    pub write_mask: u32,
+}
+
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct DepthStencilStateDescriptor {
+    /// The depth test. `Always` disables it.
+    pub depth_compare_function: CompareFunction,
+    /// Whether fragments which pass the depth test write their depth.
+    pub depth_write_enabled: bool,
+    /// The stencil test for front facing primitives, or `None` to disable it.
+    pub front_face_stencil: Option<StencilDescriptor>,
+    /// The stencil test for back facing primitives, or `None` to disable it.
+    pub back_face_stencil: Option<StencilDescriptor>,
+}
+
+/// The descriptor itself can be used as the depth/stencil state.
+///
+/// This is what a backend without a separate state object does:
+///
+/// ```ignore
+/// type DepthStencilState = DepthStencilStateDescriptor;
+///
+/// fn create_depth_stencil_state(&mut self, desc: DepthStencilStateDescriptor) -> DepthStencilStateDescriptor {
+///     desc
+/// }
+/// ```
+impl DepthStencilState for DepthStencilStateDescriptor {
+    fn descriptor(&self) -> &DepthStencilStateDescriptor {
+        self
+    }
+}
+
+/// A set of shader stages.
+#[deriving(Clone)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct ShaderStages {
+    pub vertex: bool,
+    pub fragment: bool,
+    pub compute: bool,
+}
+
+/// The kind of resource a binding holds.
+#[deriving(Clone)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum BindingType {
+    UniformBuffer,
+    StorageBuffer { read_only: bool },
+    SampledTexture,
+    StorageTexture,
+    Sampler,
+}
+
+/// How many resources a binding holds.
+#[deriving(Clone)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum BindingCount {
+    /// A single resource.
+    Single,
+    /// A fixed size array of resources.
+    Array(uint),
+    /// An array whose length is only decided when a bind group is created, up to the device's
+    /// bindless limit for the binding type. Only `SampledTexture` (with the `bindless_textures`
+    /// feature) and `Sampler` (with the `bindless_samplers` feature) bindings can be unsized, and
+    /// only the highest numbered binding of a layout.
+    ///
+    /// Shaders may index unsized arrays with non-uniform (per-invocation) indices, which is what
+    /// lets a bindless material system keep every texture in one array and every sampler in
+    /// another, and pair them up freely in the shader.
+    Unsized,
+}
+
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct BindGroupLayoutEntry {
+    /// The binding number, as declared in shaders.
+    pub binding: uint,
+    /// The stages which may access the binding.
+    pub visibility: ShaderStages,
+    pub ty: BindingType,
+    pub count: BindingCount,
+    /// Whether elements of the binding may be left unfilled. Requires the `PartiallyBound`
+    /// descriptor indexing tier.
+    pub partially_bound: bool,
+    /// Whether the binding may be updated after the bind group has been used. Requires the
+    /// `UpdateAfterBind` descriptor indexing tier.
+    pub update_after_bind: bool,
+}
+
+/// The layout of a bind group: the set of bindings it holds, and their types.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct BindGroupLayoutDescriptor {
+    pub entries: Vec<BindGroupLayoutEntry>,
+}
+
+impl BindGroupLayoutDescriptor {
+    /// This layout of bind group `group`, written against the source of a translated shader,
+    /// remapped to the shader's bindings, if `remap` is given (see `BindingRemap::remap_layout`).
+    pub fn remapped(self, group: uint, remap: Option<&BindingRemap>) -> BindGroupLayoutDescriptor {
+        match remap {
+            Some(remap) => remap.remap_layout(group, self),
+            None => self,
+        }
+    }
+}
+
+/// A resource bound into a bind group.
+pub enum BindingResource<'a, D: Device> {
+    Buffer(BufferSlice<'a, D::Buffer>),
+    Texture(&'a D::Texture),
+    Sampler(&'a D::Sampler),
+}
+
+pub struct BindGroupEntry<'a, D: Device> {
+    pub binding: uint,
+    /// The element of an array binding this entry fills. `0` for single bindings.
+    pub array_element: uint,
+    pub resource: BindingResource<'a, D>,
+}
+
+/// The resources of a bind group.
+pub struct BindGroupDescriptor<'a, D: Device> {
+    pub layout: &'a D::BindGroupLayout,
+    pub entries: Vec<BindGroupEntry<'a, D>>,
+    /// The length of the layout's `Unsized` binding, if it has one. Ignored otherwise.
+    pub unsized_length: uint,
+}
+
+impl<'a, D: Device> BindGroupDescriptor<'a, D> {
+    /// These resources of a bind group of group `group`, written against the source of a
+    /// translated shader, remapped to the shader's bindings, if `remap` is given (see
+    /// `BindingRemap::remap_bind_group`). The layout must have been remapped the same way.
+    pub fn remapped(self, group: uint, remap: Option<&BindingRemap>) -> BindGroupDescriptor<'a, D> {
+        match remap {
+            Some(remap) => remap.remap_bind_group(group, self),
+            None => self,
+        }
+    }
+}
+
+/// The type of a single vertex attribute, as stored in a vertex buffer.
+///
+/// Normalized formats are read by vertex functions as floats: unsigned ones map `0` to `0.0` and
+/// the largest value to `1.0`, and signed ones map the smallest and largest values to `-1.0` and
+/// `1.0`. The formats past `UInt4` need the `packed_vertex_formats` device feature.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum VertexFormat {
+    Float,
+    Float2,
+    Float3,
+    Float4,
+    UChar4Normalized,
+    UInt,
+    UInt2,
+    UInt4,
+    /// 16-bit floats.
+    Half2,
+    Half4,
+    UChar2Normalized,
+    Char2Normalized,
+    Char4Normalized,
+    UShort2Normalized,
+    UShort4Normalized,
+    Short2Normalized,
+    Short4Normalized,
+    /// Three 10-bit components and a 2-bit one (usually alpha, or the sign of a tangent's
+    /// bitangent), from the lowest bits up, in a 32-bit word.
+    UInt1010102Normalized,
+    Int1010102Normalized,
+}
+
+impl VertexFormat {
+    /// The size of an attribute of the format, in bytes.
+    pub fn size(&self) -> uint {
+        match *self {
+            VertexFormat::UChar2Normalized | VertexFormat::Char2Normalized => 2,
+            VertexFormat::Float | VertexFormat::UInt | VertexFormat::UChar4Normalized | VertexFormat::Char4Normalized |
+            VertexFormat::Half2 | VertexFormat::UShort2Normalized | VertexFormat::Short2Normalized |
+            VertexFormat::UInt1010102Normalized | VertexFormat::Int1010102Normalized => 4,
+            VertexFormat::Float2 | VertexFormat::UInt2 | VertexFormat::Half4 | VertexFormat::UShort4Normalized |
+            VertexFormat::Short4Normalized => 8,
+            VertexFormat::Float3 => 12,
+            VertexFormat::Float4 | VertexFormat::UInt4 => 16,
+        }
+    }
+
+    /// The device features pipelines with attributes of the format need. Creating a render
+    /// pipeline whose vertex descriptor uses a format the device lacks the features of fails.
+    pub fn required_features(&self) -> DeviceFeatures {
+        match *self {
+            VertexFormat::Float | VertexFormat::Float2 | VertexFormat::Float3 | VertexFormat::Float4 |
+            VertexFormat::UChar4Normalized | VertexFormat::UInt | VertexFormat::UInt2 | VertexFormat::UInt4 => {
+                Default::default()
+            }
+            _ => DeviceFeatures { packed_vertex_formats: true, .. Default::default() },
+        }
+    }
+}
+
+/// Where a vertex attribute is fetched from.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct VertexAttribute {
+    pub format: VertexFormat,
+    /// The offset, in bytes, of the attribute from the start of each element of the buffer.
+    pub offset: uint,
+    /// The vertex buffer index (see `RenderCommandEncoder::set_vertex_buffer`) the attribute is
+    /// fetched from.
+    pub buffer_index: uint,
+}
+
+/// How often the vertex fetch stage advances to the next element of a vertex buffer.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum VertexStepFunction {
+    /// Advance once per vertex.
+    PerVertex,
+    /// Advance once every `step_rate` instances.
+    PerInstance,
+    /// Never advance; every vertex of every instance reads the first element.
+    Constant,
+}
+
+/// The layout of a single vertex buffer.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct VertexBufferLayout {
+    /// The distance, in bytes, between consecutive elements of the buffer.
+    pub stride: uint,
+    pub step_function: VertexStepFunction,
+    /// The number of instances which share each element, for `PerInstance` buffers.
+    ///
+    /// For example, a step rate of `4` feeds the same per-instance data to instances 0 to 3, the
+    /// next element to instances 4 to 7, and so on. This must be at least `1`, and anything other
+    /// than `1` requires the `instance_step_rate` device feature. It is ignored for the other step
+    /// functions.
+    pub step_rate: uint,
+}
+
+/// How the fixed-function vertex fetch stage reads vertex attributes.
+///
+/// `attributes` is indexed by attribute index, `layouts` by vertex buffer index.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct VertexDescriptor {
+    pub attributes: Vec<VertexAttribute>,
+    pub layouts: Vec<VertexBufferLayout>,
+}
+
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct RenderPipelineDescriptor {
+    /// A name for the pipeline, used in debugging tools and in reports about it.
+    pub label: Option<String>,
+
+    /// How out of bounds accesses by this pipeline's shaders are handled.
+    ///
+    /// If `None`, the device's current setting (see `Device::set_robustness`) is used. Setting
+    /// this lets hot, trusted pipelines opt out of the cost of bounds checking while pipelines
+    /// running untrusted content keep it, or the other way around.
+    pub robustness: Option<Robustness>,
+
+    /// The name of the vertex function in the pipeline's shader program.
+    pub vertex_function: String,
+    /// The name of the fragment function in the pipeline's shader program, or `None` for a
+    /// pipeline which only rasterizes (depth-only passes, stream output).
+    ///
+    /// A pipeline whose fragment function exports stencil values must only be used in passes
+    /// with a stencil attachment; encoding a draw with it anywhere else is a validation error.
+    pub fragment_function: Option<String>,
+
+    /// How vertex attributes are fetched before the vertex function runs.
+    ///
+    /// If `None`, there is no fixed-function vertex fetch at all, and the vertex function reads
+    /// its inputs itself from buffers bound with `set_vertex_buffer` (see the `vertex_pulling`
+    /// module for the conventions this relies on).
+    pub vertex_descriptor: Option<VertexDescriptor>,
+
+    /// How the output of the fragment function is blended into each color attachment.
+    ///
+    /// The `n`th entry applies to the `n`th color attachment. `None` entries, and attachments
+    /// past the end, are written without blending. Attachments with integer pixel formats must
+    /// not be blended.
+    pub blending: Vec<Option<BlendDescriptor>>,
+
+    /// The number of samples per pixel the pipeline rasterizes with, which must be the
+    /// `sample_count` of the attachments of the passes it is used in.
+    pub sample_count: uint,
+
+    /// The formats of the attachments of the passes the pipeline is used in, or `None` for a
+    /// pipeline which can be used with any attachments (the backend compiles it for the formats
+    /// of each pass it is first used in, which costs a hitch every time).
+    ///
+    /// Creation fails if there are more color attachments than the device's
+    /// `max_color_attachments`, or if an attachment with an integer format is blended. Setting a
+    /// pipeline in a pass whose attachments (see `RenderPassDescriptor::attachment_formats`)
+    /// differ is a validation error.
+    pub attachment_formats: Option<AttachmentFormats>,
+}
+
+/// The formats of the attachments of a render pass, which the pipelines used in it are created
+/// for.
+///
+/// A G-buffer pass, for example, writes albedo, normals and material parameters at once:
+///
+/// ```ignore
+/// AttachmentFormats {
+///     color: vec![PixelFormat::RGBA8UnormSrgb, PixelFormat::RGBA16Float, PixelFormat::RGBA8Unorm],
+///     depth: Some(PixelFormat::Depth32Float),
+///     stencil: None,
+/// }
+/// ```
+#[deriving(Clone, PartialEq, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct AttachmentFormats {
+    /// The format of each color attachment: the `n`th is the `[[color(n)]]` output of the
+    /// fragment function.
+    pub color: Vec<PixelFormat>,
+    pub depth: Option<PixelFormat>,
+    pub stencil: Option<PixelFormat>,
+}
+
+/// Why a render pipeline's `attachment_formats` are invalid.
+#[deriving(Show)]
+pub enum AttachmentFormatError {
+    /// More color attachments than the device's `max_color_attachments`.
+    TooManyColorAttachments,
+    /// The color attachment at this index has a depth format.
+    NotColorFormat(uint),
+    /// The color attachment at this index has an integer format, but is blended.
+    BlendedIntegerFormat(uint),
+    /// The depth format has no depth.
+    NotDepthFormat,
+    /// The stencil format has no stencil.
+    NotStencilFormat,
+}
+
+impl RenderPipelineDescriptor {
+    /// Check the `attachment_formats` (if any) against each other, the blending and `limits`.
+    ///
+    /// Backends call this from `create_render_pipeline`, which fails on an error.
+    pub fn validate_attachments(&self, limits: &DeviceLimits) -> Result<(), AttachmentFormatError> {
+        let formats = match self.attachment_formats {
+            Some(ref formats) => formats,
+            None => return Ok(()),
+        };
+        if formats.color.len() > limits.max_color_attachments {
+            return Err(AttachmentFormatError::TooManyColorAttachments);
+        }
+        for (i, format) in formats.color.iter().enumerate() {
+            if format.has_depth() {
+                return Err(AttachmentFormatError::NotColorFormat(i));
+            }
+            let blended = i < self.blending.len() && self.blending[i].is_some();
+            if blended && format.is_integer() {
+                return Err(AttachmentFormatError::BlendedIntegerFormat(i));
+            }
+        }
+        if formats.depth.as_ref().map_or(false, |format| !format.has_depth()) {
+            return Err(AttachmentFormatError::NotDepthFormat);
+        }
+        if formats.stencil.as_ref().map_or(false, |format| !format.has_stencil()) {
+            return Err(AttachmentFormatError::NotStencilFormat);
+        }
+        Ok(())
+    }
+}
+
+/// What the backend reports about the creation of a pipeline.
+#[deriving(Clone, Show)]
+pub struct PipelineCreationFeedback {
+    /// How long creation took, in milliseconds, from the call to the result.
+    pub duration_ms: f64,
+    /// Whether the compiled pipeline was found in a cache (the driver's, or a pipeline cache
+    /// of the backend), or `None` if the backend cannot tell.
+    pub cache_hit: Option<bool>,
+    /// Whatever statistics the backend's compiler gives, by name: register and instruction
+    /// counts, spills, occupancy... Empty for backends which give none.
+    pub compiler_statistics: Vec<(String, u64)>,
+}
+
+/// What a blend term is multiplied with.
+#[deriving(Clone)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SourceColor,
+    OneMinusSourceColor,
+    SourceAlpha,
+    OneMinusSourceAlpha,
+    DestinationColor,
+    OneMinusDestinationColor,
+    DestinationAlpha,
+    OneMinusDestinationAlpha,
+}
+
+/// How the source and destination terms are combined.
+#[deriving(Clone)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum BlendOperation {
+    /// `source + destination`.
+    Add,
+    /// `source - destination`.
+    Subtract,
+    /// `destination - source`.
+    ReverseSubtract,
+    /// The minimum of the source and destination, ignoring the factors.
+    Min,
+    /// The maximum of the source and destination, ignoring the factors.
+    Max,
+}
+
+/// How a fragment's color (the source) is blended with an attachment's (the destination).
+///
+/// The color and alpha channels are blended separately, each as
+/// `operation(source * source_factor, destination * destination_factor)`.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct BlendDescriptor {
+    pub source_rgb_factor: BlendFactor,
+    pub destination_rgb_factor: BlendFactor,
+    pub rgb_operation: BlendOperation,
+    pub source_alpha_factor: BlendFactor,
+    pub destination_alpha_factor: BlendFactor,
+    pub alpha_operation: BlendOperation,
+}
+
+impl BlendDescriptor {
+    /// Blending with the same factors and operation for color and alpha.
+    pub fn uniform(source: BlendFactor, destination: BlendFactor, operation: BlendOperation) -> BlendDescriptor {
+        BlendDescriptor {
+            source_rgb_factor: source.clone(),
+            destination_rgb_factor: destination.clone(),
+            rgb_operation: operation.clone(),
+            source_alpha_factor: source,
+            destination_alpha_factor: destination,
+            alpha_operation: operation,
+        }
+    }
+
+    /// The usual "over" blending of straight (not premultiplied) alpha.
+    pub fn alpha() -> BlendDescriptor {
+        BlendDescriptor {
+            source_rgb_factor: BlendFactor::SourceAlpha,
+            destination_rgb_factor: BlendFactor::OneMinusSourceAlpha,
+            rgb_operation: BlendOperation::Add,
+            source_alpha_factor: BlendFactor::One,
+            destination_alpha_factor: BlendFactor::OneMinusSourceAlpha,
+            alpha_operation: BlendOperation::Add,
+        }
+    }
+
+    /// "Over" blending of premultiplied alpha.
+    pub fn premultiplied_alpha() -> BlendDescriptor {
+        BlendDescriptor::uniform(BlendFactor::One, BlendFactor::OneMinusSourceAlpha, BlendOperation::Add)
+    }
+
+    /// Adding the source to the destination.
+    pub fn additive() -> BlendDescriptor {
+        BlendDescriptor::uniform(BlendFactor::One, BlendFactor::One, BlendOperation::Add)
+    }
+}
+
+/// The configuration of a compute pipeline.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct ComputePipelineDescriptor {
+    /// A name for the pipeline, used in debugging tools and in reports about it.
+    pub label: Option<String>,
+
+    /// How out of bounds accesses by the compute function are handled.
+    ///
+    /// As for `RenderPipelineDescriptor::robustness`.
+    pub robustness: Option<Robustness>,
+
+    /// The subgroup size the compute function must be run with.
+    ///
+    /// If `None`, the backend picks any size between `SubgroupProperties::min_size` and
+    /// `max_size`, possibly varying between dispatches. Kernels which hardcode a subgroup size
+    /// (many prefix sum and culling kernels do) must set this. It must be a power of two within
+    /// the device's supported range, and the device must report `size_control`.
+    pub required_subgroup_size: Option<uint>,
+
+    /// The cooperative matrix configurations the compute function uses.
+    ///
+    /// Every entry must be one of the configurations reported by
+    /// `Device::cooperative_matrix_properties`, otherwise pipeline creation fails. Leave this
+    /// empty if the compute function does not use cooperative matrices.
+    pub cooperative_matrices: Vec<CooperativeMatrixProperties>,
+
+    /// The name of the compute function in the pipeline's shader program.
+    pub compute_function: String,
+
+    /// The length, in bytes, of each threadgroup memory argument of the compute function.
+    ///
+    /// The `n`th entry is the `[[threadgroup(n)]]` argument. Each length must be a multiple of 16,
+    /// and together they must not exceed `DeviceLimits::max_threadgroup_memory_length`. Arguments
+    /// whose size depends on the dispatch can be resized per dispatch with
+    /// `ComputeCommandEncoder::set_threadgroup_memory_length`.
+    pub threadgroup_memory_lengths: Vec<uint>,
+}
+
+/// The subgroup operations a device supports in shaders.
+///
+/// A subgroup (a "wave" or "warp", or a "SIMD-group" in metal-speak) is the set of invocations that
+/// the hardware executes in lockstep. These operations let invocations within a subgroup
+/// communicate without going through threadgroup memory.
+pub struct SubgroupOperations {
+    /// Electing a single invocation and querying the invocation's index within the subgroup.
+    pub basic: bool,
+    /// `all`/`any` style votes across the subgroup.
+    pub vote: bool,
+    /// Reductions and inclusive/exclusive scans (sum, min, max, and/or/xor).
+    pub arithmetic: bool,
+    /// Broadcasts and ballots.
+    pub ballot: bool,
+    /// Reading a value from an arbitrary invocation in the subgroup.
+    pub shuffle: bool,
+    /// Reading a value from an invocation a fixed distance up or down the subgroup.
+    pub shuffle_relative: bool,
+    /// Operations within a 2x2 quad of invocations.
+    pub quad: bool,
+}
+
+/// The type of the components of a cooperative matrix.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum CooperativeMatrixComponentType {
+    Float16,
+    BFloat16,
+    Float32,
+    SInt8,
+    UInt8,
+    SInt32,
+    UInt32,
+}
+
+/// A single supported cooperative matrix multiply-accumulate configuration.
+///
+/// This describes the operation `result = a * b + c`, where `a` is an `m` by `k` matrix, `b` is
+/// `k` by `n`, and `c` and `result` are `m` by `n`. The matrices are distributed across the
+/// invocations of a subgroup.
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct CooperativeMatrixProperties {
+    pub m: uint,
+    pub n: uint,
+    pub k: uint,
+    pub a_type: CooperativeMatrixComponentType,
+    pub b_type: CooperativeMatrixComponentType,
+    pub c_type: CooperativeMatrixComponentType,
+    pub result_type: CooperativeMatrixComponentType,
+}
+
+/// The subgroup capabilities of a device.
+pub struct SubgroupProperties {
+    /// The smallest subgroup size the device may run compute functions with.
+    pub min_size: uint,
+    /// The largest subgroup size the device may run compute functions with.
+    ///
+    /// This is equal to `min_size` on devices with a fixed subgroup size.
+    pub max_size: uint,
+    /// The operations which can be used within a subgroup.
+    pub operations: SubgroupOperations,
+    /// Whether a specific subgroup size can be requested with
+    /// `ComputePipelineDescriptor::required_subgroup_size`.
+    pub size_control: bool,
+}
+
+/// A GPU opened through an `Adapter`, which creates every other object.
+///
+/// # Teardown
+///
+/// Objects must not outlive the work that uses them, and nothing may outlive the device. A
+/// device shuts down safely, whatever the GPU is doing (or if it hung), in this order:
+///
+/// 1. wait for the GPU: `CommandQueue::wait_idle` on every queue, or `Device::wait_idle`. After
+///    `DeviceLost`, this returns once the backend has given up on the lost work;
+/// 2. drop what was kept alive for pending work: flush every deletion queue
+///    (`util::teardown::DeletionQueue::flush`);
+/// 3. drop presentation objects (swapchains, surfaces, and textures wrapping their images),
+///    before the queues which present to them;
+/// 4. drop the remaining resources, pipelines and queues;
+/// 5. drop the device.
+///
+/// `util::teardown::shutdown` does all of it. Backends `debug_assert!` the parts they can see
+/// when the device is dropped: no command buffer in flight (see `in_flight_command_buffers`),
+/// and no queue left.
+pub trait Device {
+    // error types. use proposed ToError/Error trait instead of Show.
+    type ShaderProgramCreationError : Show;
+    type CommandQueueCreationError : Show;
+    type BufferCreationError : Show;
+    type TextureCreationError : Show;
+    type SamplerCreationError : Show;
+    type RenderPipelineCreationError : Show;
+    type ComputePipelineCreationError : Show;
+    type BindGroupLayoutCreationError : Show;
+    type BindGroupCreationError : Show;
+    type HeapCreationError : Show;
+
+    // input types
+    type ShaderProgramInput;
+
+    // "handle" types
+    type ShaderProgram : ShaderProgram;
+    type CommandQueue : CommandQueue<Self>;
+    type CommandBuffer : CommandBuffer<Self>;
+    type RenderCommandEncoder : RenderCommandEncoder<Self>;
+    type ComputeCommandEncoder : ComputeCommandEncoder<Self>;
+    type BlitCommandEncoder : BlitCommandEncoder<Self>;
+    type Buffer : Buffer;
+    type Texture : Texture;
+    type Sampler : Sampler;
+    type DepthStencilState : DepthStencilState;
+    type RenderPipeline;
+    type ComputePipeline;
+    type BindGroupLayout;
+    type BindGroup;
+    type Event;
+    type Heap : Heap;
+    #[cfg(feature = "xr")]
+    type RasterizationRateMapCreationError : Show;
+    #[cfg(feature = "xr")]
+    type RasterizationRateMap : RasterizationRateMap;
+    #[cfg(feature = "shared_swapchain")]
+    type ExternalHandleError : Show;
+
+    /// The optional features enabled on this device.
+    ///
+    /// These are exactly the `required_features` the device was opened with (see
+    /// `Adapter::open`), even if the adapter supports more, so that code which works on one
+    /// machine does not silently depend on features another machine lacks.
+    ///
+    /// See the `DeviceFeatures` struct for the exact set of features which may be queried.
+    fn features(&self) -> DeviceFeatures;
+
+    /// The subgroup ("wave") capabilities of this device.
+    ///
+    /// See the `SubgroupProperties` struct for what is reported.
+    fn subgroup_properties(&self) -> SubgroupProperties;
+
+    /// The cooperative matrix configurations supported by this device.
+    ///
+    /// This is empty unless the device reports the `cooperative_matrix` feature.
+    ///
+    /// See the `CooperativeMatrixProperties` struct for what a configuration describes.
+    fn cooperative_matrix_properties(&self) -> Vec<CooperativeMatrixProperties>;
+
+    /// The numeric limits of this device.
+    ///
+    /// As with `features`, these are the `required_limits` the device was opened with.
+    ///
+    /// See the `DeviceLimits` struct for the exact set of limits which may be queried.
+    fn limits(&self) -> DeviceLimits;
+
+    /// How the device was opened to run deterministically, or `None` if it was not (see
+    /// `DeviceDescriptor::deterministic_replay`). Middleware which makes ordering decisions of
+    /// its own should make them from the seed when this is set (with a `fuzz::Rng`, say), as
+    /// the backend does.
+    fn deterministic_replay(&self) -> Option<DeterministicReplay>;
+
+    /// The descriptor indexing tier of this device.
+    ///
+    /// See the `DescriptorIndexingTier` enum for what each tier allows.
+    fn descriptor_indexing_tier(&self) -> DescriptorIndexingTier;
+
+    /// What textures of `format` can be used for on this device. Creating a texture with a usage
+    /// the format does not support fails.
+    ///
+    /// See the `FormatCapabilities` struct for what is reported.
+    fn format_capabilities(&self, format: PixelFormat) -> FormatCapabilities;
+
+    /// The backend's native device: the `MTLDevice`, `VkDevice` or `ID3D12Device`, or
+    /// `OpenGl(0)` for OpenGL, whose device is the current context. Unsafe, as for
+    /// `Resource::as_raw`.
+    #[cfg(feature = "raw_handles")]
+    unsafe fn as_raw(&self) -> RawHandle;
+
+    /// The handles of the OpenXR graphics binding of this device and `queue`, to create an
+    /// OpenXR session rendering with them, or `None` if OpenXR has no binding for the backend's
+    /// API (OpenGL devices, which are bound through the platform's context instead). Unsafe, as
+    /// for `as_raw`. See the `openxr` module.
+    #[cfg(feature = "openxr")]
+    unsafe fn openxr_graphics_binding(&self, queue: &CommandQueue) -> Option<GraphicsBinding>;
+
+    /// Set the callback which receives messages from the backend.
+    ///
+    /// Validation errors, warnings, and performance hints from the backend (and from its
+    /// validation layer or driver, where the backend can intercept them) are passed to `callback`
+    /// instead of going wherever the platform sends them by default, which is usually stderr or
+    /// the system log. Passing `None` restores the default. `callback` may be called from any
+    /// thread, including from inside of calls into the device.
+    fn set_error_callback(&mut self, callback: Option<Box<Fn(Severity, &str) + Send + Sync>>);
+
+    /// Whether GPU work on this device is subject to a watchdog timeout.
+    ///
+    /// See the `Watchdog` enum for what may be reported.
+    fn watchdog(&self) -> Watchdog;
+
+    /// The current power and thermal state.
+    ///
+    /// Backends which cannot find out report `ThermalState::Nominal` and no low power mode.
+    ///
+    /// See the `PowerState` struct for what is reported.
+    fn power_state(&self) -> PowerState;
+
+    /// Set a callback to be notified when the power state changes.
+    ///
+    /// `callback` is called, possibly on another thread, with the new state whenever any part
+    /// of the power state changes. This lets applications lower their quality as soon as the
+    /// system starts throttling, instead of finding out from missed frames.
+    fn set_power_state_callback(&mut self, callback: Option<Box<FnMut(&PowerState) + Send>>);
+
+    /// Sample the CPU and GPU clocks together.
+    ///
+    /// This is what places GPU timestamps on the CPU timeline of a profiler. It may take a few
+    /// microseconds, so it is meant to be called now and then, not per timestamp. Backends which
+    /// cannot sample the GPU clock from the CPU return `None`.
+    ///
+    /// See the `TimestampCalibration` struct for what is returned.
+    fn calibrate_timestamps(&self) -> Option<TimestampCalibration>;
+
+    /// Mark that frame `frame` reached `marker`, now.
+    ///
+    /// Frames are numbered by the application, the same numbers for every marker of a frame,
+    /// and always increasing. With the `latency_markers` device feature, markers go to the
+    /// platform's latency mechanism; otherwise this does nothing. `util::latency` measures
+    /// from the same markers on the CPU either way.
+    fn set_latency_marker(&mut self, frame: u64, marker: LatencyMarker);
+
+    /// Block until the best time to start the next frame: late enough that the GPU will be
+    /// ready for it as soon as it is submitted, so that its input is sampled as late as it can
+    /// be. Call this just before sampling input.
+    ///
+    /// Without the `latency_markers` device feature, this returns immediately.
+    fn latency_sleep(&mut self);
+
+    /// Enable or disable shader debug output.
+    ///
+    /// While a callback is set, shader programs and pipelines created afterwards are built with
+    /// debug printing enabled (through the backend's validation layer, or by instrumenting the
+    /// shader), and everything they print is delivered to `callback`. Passing `None` disables
+    /// the output again for programs created afterwards. This is a debugging aid and can be very
+    /// slow; it requires the `shader_debug_printf` feature, and does nothing without it.
+    ///
+    /// Messages are delivered some time after the command buffer that printed them has
+    /// completed, possibly on another thread, in the order they were printed within a single
+    /// draw or dispatch.
+    fn set_shader_debug_callback(&mut self, callback: Option<Box<FnMut(&ShaderDebugMessage) + Send>>);
+
+    /// Enable or disable numeric validation.
+    ///
+    /// While a callback is set, pipelines created afterwards check every value they write to
+    /// attachments (and, for compute pipelines, to storage textures) for NaNs, infinities, and
+    /// values the destination cannot represent, and report them to `callback`. This is the
+    /// classic "everything is black" bug: a single NaN spreading through blurs and
+    /// reductions. Backends use their driver tooling where it exists, and otherwise instrument
+    /// the shader's outputs, so this is always available but slow.
+    ///
+    /// Reports are delivered after the command buffer which caused them completes, possibly on
+    /// another thread, at most one per pipeline, issue and attachment per command buffer.
+    fn set_numeric_validation_callback(&mut self, callback: Option<Box<FnMut(&NumericValidationReport) + Send>>);
+
+    /// Set how out of bounds resource accesses are handled.
+    ///
+    /// This applies to pipelines created afterwards which do not override it in their
+    /// descriptor (see `RenderPipelineDescriptor::robustness`). Devices start out with
+    /// `Robustness::Disabled`. Anything other than that keeps content bugs (a bad index in a
+    /// mesh, a stale texture size) from turning into memory corruption or device hangs, which is
+    /// worth the cost for anything running content that isn't fully trusted. Setting
+    /// `Robustness::Zeroed` without the `robust_access_zeroed` feature behaves like `Clamped`.
+    ///
+    /// If `report` is `Some`, pipelines are additionally instrumented to count out of bounds
+    /// accesses, which are delivered to it after the offending command buffer completes,
+    /// possibly on another thread. This is a debugging aid and can be slow.
+    fn set_robustness(&mut self, robustness: Robustness, report: Option<Box<FnMut(&BoundsViolationReport) + Send>>);
+
+    /// Enable or disable counting work into `FrameStatistics`.
+    ///
+    /// Devices start out with statistics disabled, which costs nothing. While enabled, every
+    /// encoder of the device counts what it records.
+    fn set_statistics_enabled(&mut self, enabled: bool);
+
+    /// Return the statistics counted since the last call, and start counting from zero again.
+    ///
+    /// Calling this once per frame gives per-frame numbers. Work is counted when it is encoded,
+    /// not when it executes.
+    fn take_statistics(&mut self) -> FrameStatistics;
+
+    /// Create a new shader program.
+    ///
+    /// The new shader program is a "ready to use" shader program that can be used for rendering.
+    ///
+    /// If the shader program requires features the device does not support (see
+    /// `ShaderProgram::required_features`), this fails instead of creating a program which cannot
+    /// run correctly.
+    ///
+    /// See the `ShaderProgram` trait for more details on how a shader program is used.
+    fn create_shader_program(&mut self, source: ShaderProgramInput) -> Result<ShaderProgram, ShaderProgramCreationError>;
+
+    /// Create a new shader program from a shader archive.
+    ///
+    /// The device uses the archive's entry for its own backend, so no translation or compilation
+    /// from source happens at runtime (beyond whatever the driver does). Creation fails if the
+    /// archive has no entry this device can use. The program's `binding_remap` is that entry's.
+    ///
+    /// See the `shader_archive` module for how archives are produced.
+    fn create_shader_program_from_archive(&mut self, archive: &ShaderArchive) -> Result<ShaderProgram, ShaderProgramCreationError>;
+
+    /// Create a new command queue.
+    ///
+    /// The command queue will have a maximum of `count` uncompleted command buffers if it is
+    /// `Some`, otherwise it will be unbounded and grow as necessary. `count` must not be
+    /// `Some(0)`.
+    ///
+    /// See the `CommandQueue` trait for more details on how a `CommandQueue` is used.
+    fn create_command_queue(&mut self, count: Option<uint>) -> Result<CommandQueue, CommandQueueCreationError>;
+
+    /// As `create_command_queue`, but the GPU schedules the queue's work with `priority`
+    /// relative to the work of other queues, including those of other applications.
+    ///
+    /// `Low` is for work nobody waits on from frame to frame, such as asset baking or global
+    /// illumination updates running in game: the GPU runs it when the normal queues leave it
+    /// idle, and, where it can preempt, interrupts it for them. How finely depends on the GPU;
+    /// most can only switch between command buffers, so low priority work should still come in
+    /// short command buffers. `create_command_queue` creates `Normal` queues. Without the
+    /// `queue_priority` device feature, `priority` is ignored and the queue is `Normal`.
+    fn create_command_queue_with_priority(&mut self, count: Option<uint>, priority: QueuePriority) -> Result<CommandQueue, CommandQueueCreationError>;
+
+    /// Block until every command buffer committed to any queue of this device has completed,
+    /// and their completed handlers have run. The first step of teardown (see the trait
+    /// documentation).
+    fn wait_idle(&mut self);
+
+    /// The number of command buffers committed to queues of this device which have not
+    /// completed yet. Must be zero when the device is dropped.
+    fn in_flight_command_buffers(&self) -> uint;
+
+    /// Wrap `queue`, a native queue of this device (see `Adapter::open_raw`), as a command queue
+    /// with at most `count` uncompleted command buffers, as for `create_command_queue`.
+    ///
+    /// The host application may keep submitting to the queue natively; this crate's command
+    /// buffers are ordered with its submissions as they are with each other. Unsafe, as for
+    /// `Resource::as_raw`, and the queue must outlive the command queue.
+    #[cfg(feature = "raw_handles")]
+    unsafe fn command_queue_from_raw(&mut self, queue: RawHandle, count: Option<uint>) -> Result<CommandQueue, CommandQueueCreationError>;
+
+    /// Wrap `buffer`, a native buffer of `length` bytes created on this device by someone else.
+    ///
+    /// The buffer is retained, not copied. Unsafe, as for `Resource::as_raw`.
+    #[cfg(feature = "raw_handles")]
+    unsafe fn buffer_from_raw(&mut self, buffer: RawHandle, length: uint) -> Result<Buffer, BufferCreationError>;
+
+    /// Wrap `texture`, a native texture created on this device by someone else, which `desc`
+    /// describes.
+    ///
+    /// `desc` must match how the texture was created natively; its usage only needs to cover how
+    /// this crate uses it. Unsafe, as for `Resource::as_raw`.
+    #[cfg(feature = "raw_handles")]
+    unsafe fn texture_from_raw(&mut self, texture: RawHandle, desc: TextureDescriptor) -> Result<Texture, TextureCreationError>;
+
+    /// Create a new buffer.
+    ///
+    /// The buffer will have the capacity to store `length` bytes. If `Some`, the `hints` will
+    /// influence the behavior of the created buffer. Otherwise, the default hints shall be used.
+    /// These hints cannot be changed after creation.
+    ///
+    /// See the `BufferHints` struct for the exact set of hints which may be used.
+    ///
+    /// The contents of the new buffer are undefined until they are written, by `write_buffer`,
+    /// a copy, `fill_buffer` or a shader. Backends do not clear new buffers, and although some
+    /// drivers zero new memory, code which relies on it breaks on the others
+    /// (`Recording::undefined_reads` finds such reads).
+    fn create_buffer(&mut self, length: uint, hints: Option<BufferHints>) -> Result<Buffer, BufferCreationError>;
+
+    /// Write data into a buffer from the CPU.
+    ///
+    /// `data` is copied into `buffer` starting at byte `offset`. The write happens before any
+    /// command buffer committed after this call executes, and after every command buffer
+    /// committed before it has completed, so it must not race with work that is still using the
+    /// same range: rotate between several buffers (one per frame in flight) for data which
+    /// changes every frame.
+    fn write_buffer(&mut self, buffer: &Buffer, offset: uint, data: &[u8]);
+
+    /// Read data from a buffer back to the CPU.
+    ///
+    /// `data.len()` bytes of `buffer`, starting at byte `offset`, are copied into `data`. This
+    /// blocks until every command buffer committed before this call has completed, so the data
+    /// includes everything they wrote.
+    fn read_buffer(&mut self, buffer: &Buffer, offset: uint, data: &mut [u8]);
+
+    /// Write texels into a texture from the CPU.
+    ///
+    /// The `size` region at `origin` of mip level `mipmap_level` of array slice `slice` is
+    /// replaced with `data`, which holds rows of `bytes_per_row` bytes each (at least the width of
+    /// the region times the texel size of the texture's format), top row first. For 3D textures,
+    /// the depth slices follow each other without padding. This is ordered with respect to
+    /// command buffers like `write_buffer`.
+    fn write_texture(&mut self, texture: &Texture, mipmap_level: uint, slice: uint, origin: Origin, size: Size,
+                     data: &[u8], bytes_per_row: uint);
+
+    /// Read texels of a texture back to the CPU.
+    ///
+    /// The inverse of `write_texture`, with `data` laid out the same way. As for `read_buffer`,
+    /// this blocks until every command buffer committed before this call has completed. The
+    /// texture must have been created with the `shader_read` or `render_target` usage.
+    fn read_texture(&mut self, texture: &Texture, mipmap_level: uint, slice: uint, origin: Origin, size: Size,
+                    data: &mut [u8], bytes_per_row: uint);
+
+    // todo: no-copy buffer creation, `newBufferWithBytesNoCopy:length:options:deallocator` in
+    // metal-speak. in particular, it takes a page-aligned buffer and shares it with the gpu
+    // directly, calling the deallocator function when it is done with it.
+
+    /// Create a new texture.
+    ///
+    /// The `desc` describes the layout that this texture has.
+    ///
+    /// See the `TextureDescriptor` struct for the exact set of properties that a texture
+    /// encompasses.
+    ///
+    /// As for `create_buffer`, the contents of the new texture are undefined until they are
+    /// written: by `write_texture`, a copy, a shader, or a render pass which clears or draws over
+    /// them.
+    fn create_texture(&mut self, desc: TextureDescriptor) -> Result<Texture, TextureCreationError>;
+
+    /// Create a new view of an existing texture.
+    ///
+    /// The view shares storage with `texture`, but only covers the mip levels and array slices
+    /// selected by `desc`, which is how a single mip level is bound for writing, for example. The
+    /// view keeps `texture`'s storage alive.
+    ///
+    /// See the `TextureViewDescriptor` struct for what a view can select.
+    fn create_texture_view(&mut self, texture: &Texture, desc: TextureViewDescriptor) -> Result<Texture, TextureCreationError>;
+
+    /// Create a heap, memory which buffers and textures can be placed in at offsets the
+    /// application chooses. This requires the `placed_resources` device feature.
+    ///
+    /// Resources whose uses never overlap in time can be placed at overlapping offsets, to share
+    /// the memory: the transient targets of different passes, say. The application then takes
+    /// over what the backend otherwise does for it, and must hand the memory from one resource
+    /// to the next with `CommandBuffer::aliasing_barrier`.
+    fn create_heap(&mut self, desc: HeapDescriptor) -> Result<Heap, HeapCreationError>;
+
+    /// Create a rasterization rate map, for foveating the render passes of an XR renderer. This
+    /// requires the `rasterization_rate_maps` device feature.
+    ///
+    /// Fails if `desc` is invalid (see `RasterizationRateMapDescriptor::validate`).
+    #[cfg(feature = "xr")]
+    fn create_rasterization_rate_map(&mut self, desc: RasterizationRateMapDescriptor)
+                                     -> Result<RasterizationRateMap, RasterizationRateMapCreationError>;
+
+    /// How many bytes a buffer of `length` bytes created with `hints` takes in a heap, and how
+    /// its offset must be aligned.
+    fn buffer_placement_requirements(&self, length: uint, hints: Option<BufferHints>) -> PlacementRequirements;
+
+    /// How many bytes a texture described by `desc` takes in a heap, and how its offset must be
+    /// aligned.
+    fn texture_placement_requirements(&self, desc: &TextureDescriptor) -> PlacementRequirements;
+
+    /// As `create_buffer`, but placed `offset` bytes into `heap`, which must hold all of it
+    /// (see `buffer_placement_requirements`). The buffer keeps the heap alive. Its contents are
+    /// undefined, as for `create_buffer`, however the memory was used before.
+    fn create_placed_buffer(&mut self, heap: &Heap, offset: uint, length: uint, hints: Option<BufferHints>) -> Result<Buffer, BufferCreationError>;
+
+    /// As `create_texture`, but placed `offset` bytes into `heap`, which must hold all of it
+    /// (see `texture_placement_requirements`). The texture keeps the heap alive.
+    fn create_placed_texture(&mut self, heap: &Heap, offset: uint, desc: TextureDescriptor) -> Result<Texture, TextureCreationError>;
+
+    /// Set how important it is for `resource` to stay resident when GPU memory runs short.
+    ///
+    /// Resources start out at `ResidencyPriority::Normal`. This is only a hint: backends without
+    /// residency management ignore it, and a resource used by pending work is kept resident
+    /// whatever its priority. Views share the priority of their texture.
+    fn set_residency_priority(&mut self, resource: ResourceRef<Self>, priority: ResidencyPriority);
+
+    /// Create a new sampler.
+    ///
+    /// A sampler is a description of how a shader should sample a texture. Once created, it cannot
+    /// be changed. The `desc` describes the properties the sampler will have.
+    ///
+    /// See the `SamplerDescriptor` struct for the exact set of properties that a sampler
+    /// encompasses.
+    fn create_sampler(&mut self, desc: SamplerDescriptor) -> Result<Sampler, SamplerCreationError>;
+
+    /// Create a new depth/stencil state.
+    ///
+    /// This depth/stencil test state is used to configure the depth and stencil stages of the
+    /// rendering pipeline. The `desc` is the configuration that this state will use.
+    ///
+    /// See the `DepthStencilStateDescriptor` struct for the configuration options available.
+
+    // note: this can't fail. There really isn't any work a device would have to do for this, and I
+    // suspect most implementations will use the DepthStencilStateDescriptor as the
+    // DepthStencilState directly! (it implements `DepthStencilState` for exactly that)
+    fn create_depth_stencil_state(&mut self, desc: DepthStencilStateDescriptor) -> DepthStencilState;
+
+    /// Create a new event, with a value of `0`.
+    ///
+    /// Events order command buffers of different queues, which otherwise run with no ordering
+    /// between them at all: see `CommandBuffer::encode_signal_event`.
+    fn create_event(&mut self) -> Event;
+
+    /// The value `event` was last signaled with, or `0` if it never was.
+    fn event_value(&self, event: &Event) -> u64;
+
+    /// Call `f` once `event` reaches at least `value`.
+    ///
+    /// If it already has, `f` is called right away. Otherwise it is called, possibly on another
+    /// thread, as soon as a command buffer signals it with `value` or more. As for
+    /// `CommandQueue::notify_when_available`, this is the building block for waiting on the GPU
+    /// timeline from an event loop or async runtime.
+    fn notify_event(&self, event: &Event, value: u64, f: Box<FnOnce() + Send>);
+
+    /// Create a texture whose memory can be shared with other processes, with `export_texture`.
+    /// This requires the `external_memory` device feature, and a single mip level and sample.
+    #[cfg(feature = "shared_swapchain")]
+    fn create_shareable_texture(&mut self, desc: TextureDescriptor) -> Result<Texture, TextureCreationError>;
+
+    /// A new handle to the memory of `texture`, which must have been created by
+    /// `create_shareable_texture`, for another process to import with `import_texture`.
+    #[cfg(feature = "shared_swapchain")]
+    fn export_texture(&self, texture: &Texture) -> Result<ExternalHandle, ExternalHandleError>;
+
+    /// Wrap the memory another process exported with `export_texture` as a texture. This
+    /// requires the `external_memory` device feature.
+    ///
+    /// Unsafe: `desc` must describe the exported texture, and the other process must order its
+    /// accesses to the memory with this one's, with shared events.
+    #[cfg(feature = "shared_swapchain")]
+    unsafe fn import_texture(&mut self, handle: ExternalHandle, desc: TextureDescriptor) -> Result<Texture, TextureCreationError>;
+
+    /// Create an event, with a value of `0`, which can be shared with other processes, with
+    /// `export_event`. This requires the `external_memory` device feature.
+    #[cfg(feature = "shared_swapchain")]
+    fn create_shareable_event(&mut self) -> Result<Event, ExternalHandleError>;
+
+    /// A new handle to `event`, which must have been created by `create_shareable_event`, for
+    /// another process to import with `import_event`.
+    #[cfg(feature = "shared_swapchain")]
+    fn export_event(&self, event: &Event) -> Result<ExternalHandle, ExternalHandleError>;
+
+    /// Wrap an event another process exported with `export_event`. Signaling it from either
+    /// process signals it in both. This requires the `external_memory` device feature.
+    ///
+    /// Unsafe, as the handle cannot be checked to be of an event.
+    #[cfg(feature = "shared_swapchain")]
+    unsafe fn import_event(&mut self, handle: ExternalHandle) -> Result<Event, ExternalHandleError>;
+
+    /// Create a render pipeline.
+    ///
+    /// A render pipeline encodes the state required to issue a draw call. It can be relatively
+    /// expensive to construct a render pipeline, so it should be done as infrequently as possible.
+    /// The `desc` contains the state the render pipeline encodes, and names the functions of
+    /// `program` that it uses.
+    ///
+    /// Pipeline creation is validated against `features`: any shader used by the pipeline which
+    /// requires an unsupported feature, or any vertex attribute whose format does (see
+    /// `VertexFormat::required_features`), causes creation to fail.
+    ///
+    /// See the `RenderPipelineDescriptor` struct for the state that must be set.
+    fn create_render_pipeline(&mut self, program: &ShaderProgram, desc: RenderPipelineDescriptor) -> Result<RenderPipeline, RenderPipelineCreationError>;
+
+    /// Create a render pipeline for the passes of `CommandBuffer::xr_render_command_encoder`
+    /// which render `view_count` views. Its vertex function reads the view it is run for as a
+    /// built-in value (`[[amplification_id]]` in metal-speak).
+    ///
+    /// `view_count` must be at least `1` and at most `max_multiview_view_count`, and more than
+    /// `1` requires the `multiview` device feature.
+    #[cfg(feature = "xr")]
+    fn create_multiview_render_pipeline(&mut self, program: &ShaderProgram, desc: RenderPipelineDescriptor, view_count: uint)
+                                        -> Result<RenderPipeline, RenderPipelineCreationError>;
+
+    /// `create_render_pipeline`, also returning what the backend reports about the creation,
+    /// whether it worked or not. For keeping track of how much shader compilation costs, and how
+    /// well caches work, in telemetry.
+    fn create_render_pipeline_with_feedback(&mut self, program: &ShaderProgram, desc: RenderPipelineDescriptor)
+                                            -> (Result<RenderPipeline, RenderPipelineCreationError>, PipelineCreationFeedback);
+
+    /// Create a render pipeline in the background.
+    ///
+    /// This returns immediately, and `callback` is called, possibly on another thread, with what
+    /// `create_render_pipeline_with_feedback` would have returned once the backend is done
+    /// compiling; the duration is the time spent compiling, not waiting to be. `program` only
+    /// has to live until this returns. Backends which cannot compile in the background call
+    /// `callback` before returning.
+    ///
+    /// This is how compiling shader variants is kept from hitching the first frame that needs
+    /// them (see `pipeline_loader::PipelineRegistry`).
+    fn create_render_pipeline_async(&mut self, program: &ShaderProgram, desc: RenderPipelineDescriptor,
+                                    callback: Box<FnOnce(Result<RenderPipeline, RenderPipelineCreationError>,
+                                                         PipelineCreationFeedback) + Send>);
+
+    /// Create a compute pipeline.
+    ///
+    /// As for render pipelines, creation can be expensive and is validated against `features`,
+    /// and also against the limits on threadgroup memory.
+    ///
+    /// See the `ComputePipelineDescriptor` struct for the state that must be set.
+    fn create_compute_pipeline(&mut self, program: &ShaderProgram, desc: ComputePipelineDescriptor) -> Result<ComputePipeline, ComputePipelineCreationError>;
+
+    /// Create a new bind group layout.
+    ///
+    /// Creation fails if `desc` uses an unsized binding which the device does not support, or
+    /// which is not the highest numbered binding, or if any entry is `partially_bound` or
+    /// `update_after_bind` without the device being of the required descriptor indexing tier.
+    ///
+    /// See the `BindGroupLayoutDescriptor` struct for how a layout is described.
+    fn create_bind_group_layout(&mut self, desc: BindGroupLayoutDescriptor) -> Result<BindGroupLayout, BindGroupLayoutCreationError>;
+
+    /// Create a new bind group.
+    ///
+    /// A bind group is a set of resources that are bound together with a single call
+    /// (`set_bind_group`), laid out as described by its layout. Every element of every binding of
+    /// the layout must be filled, except for elements of an unsized array past `unsized_length`
+    /// and elements of `partially_bound` bindings. Creation fails otherwise.
+    ///
+    /// The offset of every buffer slice must be a multiple of the device's
+    /// `min_uniform_buffer_offset_alignment` for `UniformBuffer` bindings, and of its
+    /// `min_storage_buffer_offset_alignment` for `StorageBuffer` bindings. Creation fails
+    /// otherwise, on every backend, even those which could bind the buffer anyway.
+    ///
+    /// See the `BindGroupDescriptor` struct for how the resources are given.
+    fn create_bind_group(&mut self, desc: BindGroupDescriptor<Self>) -> Result<BindGroup, BindGroupCreationError>;
+
+    /// Change some of the resources of an existing bind group.
+    ///
+    /// Entries of `update_after_bind` bindings may be updated at any time, as long as no
+    /// submitted or encoded work that is still pending accesses the updated elements. Entries of
+    /// any other binding may only be updated if the bind group is not used by any pending work at
+    /// all. Updating fails if `entries` refers to bindings or elements outside of the layout, or
+    /// if a buffer offset is misaligned as for `create_bind_group`.
+    fn update_bind_group(&mut self, group: &BindGroup, entries: Vec<BindGroupEntry<Self>>) -> Result<(), BindGroupCreationError>;
+
+    // todo: pipeline reflection
+}
+
+/// What is required of a device when opening it.
+#[deriving(Clone)]
+pub struct DeviceDescriptor {
+    /// The optional features the device must support. Only these are enabled.
+    pub required_features: DeviceFeatures,
+    /// The limits the device must at least have. The device reports (and validates against)
+    /// exactly these.
+    pub required_limits: DeviceLimits,
+    /// Where the device's host memory comes from, or `None` for the global allocator.
+    pub host_allocator: Option<Arc<HostAllocator + Send + Sync>>,
+    /// Run the device deterministically, for reproducing intermittent rendering bugs, or `None`
+    /// to run it as fast as it can. See the `DeterministicReplay` struct.
+    pub deterministic_replay: Option<DeterministicReplay>,
+}
+
+/// A device which runs the same way every time it is given the same work, for debugging
+/// synchronization bugs which only show up now and then: a missing barrier or event wait, work
+/// of one queue racing another's, a resource reused before the GPU was done with it.
+///
+/// Such bugs depend on timing the application does not control, and a device opened with
+/// `DeviceDescriptor::deterministic_replay` takes away what it can of it:
+///
+/// * queue execution is serialized: the command buffers of every queue of the device run one
+///   at a time, in the order they were committed (or enqueued), each after the last has
+///   completed, so work of different queues never overlaps;
+/// * there is no async compute: every queue is created on the same hardware queue, whatever its
+///   priority, and `queue_priority` has no effect;
+/// * there is no background compilation: `create_render_pipeline_async` compiles before it
+///   returns, and calls its callback on the calling thread;
+/// * completion is reported in order: completed handlers, `notify_when_available` and
+///   `notify_event` callbacks are called on a single thread of the backend, in the order the
+///   work completed, which is the order it was committed;
+/// * the backend's internal ordering decisions (which pooled command buffer or descriptor slot
+///   is handed out next, where suballocations are placed, the order deferred destruction runs
+///   in) are made from `seed`, instead of from addresses, hash orders or timing.
+///
+/// A bug which reproduces in this mode reproduces every run with the same seed and the same
+/// calls; one which goes away shows that it depends on overlapping work, which narrows down
+/// where the missing synchronization is. Running with different seeds shakes out bugs which
+/// depend on the backend's choices, such as reading a recycled resource's old contents.
+///
+/// Everything runs much slower like this, and the GPU still has timing of its own (within a
+/// command buffer, and the order threads of a dispatch run and atomics land in), so this is for
+/// debugging, not for shipping.
+#[deriving(Clone, PartialEq, Show)]
+pub struct DeterministicReplay {
+    pub seed: u64,
+}
+
+/// How long a host allocation is expected to live, for allocators which place allocations of
+/// different lifetimes in different pools.
+#[deriving(Clone, PartialEq, Show)]
+pub enum AllocationScope {
+    /// Only for the duration of the call which made it.
+    Command,
+    /// As long as the object (a resource, pipeline, command buffer, ...) it was made for.
+    Object,
+    /// Cached across objects, such as compiled shaders and pipeline state.
+    Cache,
+    /// As long as the device.
+    Device,
+}
+
+/// An allocator for the host memory of a device, for engines and consoles which must account
+/// for, cap or place every allocation they make.
+///
+/// Given to `Adapter::open` as `DeviceDescriptor::host_allocator`. The backend makes the host
+/// allocations it does for the device and its objects (command recording, state tracking,
+/// staging copies of data given to the device, and the objects themselves) with it, and installs
+/// it as the native API's allocation callbacks where there are any (`VkAllocationCallbacks` on
+/// Vulkan). On APIs which have none, such as Metal and d3d12, the driver allocates as it always
+/// does.
+///
+/// The methods take `&self` and may be called from any thread, as the methods of the device and
+/// of its objects can be. They follow the native callbacks: frees and reallocations are not told
+/// the size or alignment of the allocation, so allocators which need them must record them.
+///
+/// Allocations made by the crate's utilities (`Vec`s of draws, readback results and so on) are
+/// not device allocations, and use the global allocator.
+pub trait HostAllocator {
+    /// Allocate `size` bytes (never zero) aligned to `align` (a power of two), or return null if
+    /// the allocation is refused.
+    unsafe fn allocate(&self, size: uint, align: uint, scope: AllocationScope) -> *mut u8;
+
+    /// Resize the allocation at `ptr` to `size` bytes aligned to `align`, moving its contents if
+    /// needed, or return null leaving it as it was. `ptr` may be null, to allocate.
+    unsafe fn reallocate(&self, ptr: *mut u8, size: uint, align: uint, scope: AllocationScope) -> *mut u8;
+
+    /// Free the allocation at `ptr`, which may be null.
+    unsafe fn deallocate(&self, ptr: *mut u8);
+
+    /// Told that the driver allocated `size` bytes by itself, which it does not use the
+    /// allocator for (for Vulkan, executable memory). Nothing by default.
+    #[allow(unused_variables)]
+    fn internal_allocated(&self, size: uint, scope: AllocationScope) {}
+
+    /// Told that the driver freed `size` bytes it reported with `internal_allocated`.
+    #[allow(unused_variables)]
+    fn internal_freed(&self, size: uint, scope: AllocationScope) {}
+}
+
+/// Why a device could not be opened.
+pub enum OpenDeviceError<E> {
+    /// The adapter lacks these required features.
+    UnsupportedFeatures(Vec<&'static str>),
+    /// The adapter does not meet these required limits.
+    UnsupportedLimits(Vec<&'static str>),
+    /// The backend failed to open the device.
+    Backend(E),
+}
+
+/// A physical device (a GPU, or a software renderer) which devices can be opened on.
+pub trait Adapter {
+    type Device : Device;
+    type DeviceCreationError : Show;
+
+    /// A human readable name for the adapter.
+    fn name(&self) -> String;
+
+    /// Every optional feature the adapter supports.
+    fn features(&self) -> DeviceFeatures;
+
+    /// The best limits the adapter supports.
+    fn limits(&self) -> DeviceLimits;
+
+    /// The version of the driver, as the platform reports it: the driver's own version where it
+    /// has one (Vulkan, d3d12), or the OS build where the driver ships with the OS (Metal).
+    fn driver_version(&self) -> String;
+
+    /// What textures of `format` can be used for on devices opened on the adapter, as their
+    /// `Device::format_capabilities` reports.
+    fn format_capabilities(&self, format: PixelFormat) -> FormatCapabilities;
+
+    /// Everything the adapter reports (its name, driver version, features, limits and the
+    /// capabilities of every pixel format) and the platform and crate version, as JSON, for
+    /// attaching to bug reports against the crate or an application.
+    ///
+    /// See the `adapter_snapshot` module for the layout.
+    #[cfg(feature = "std")]
+    fn diagnostic_snapshot(&self) -> String {
+        let snapshot = AdapterSnapshot {
+            name: self.name(),
+            driver_version: self.driver_version(),
+            features: self.features(),
+            limits: self.limits(),
+            formats: PIXEL_FORMATS.iter().map(|&format| (format, self.format_capabilities(format))).collect(),
+        };
+        snapshot.to_json()
+    }
+
+    /// Open a device.
+    ///
+    /// Opening fails with `UnsupportedFeatures` or `UnsupportedLimits` (naming what is missing)
+    /// if the adapter cannot provide what `desc` requires, so applications find out up front
+    /// instead of when some pipeline fails to be created later.
+    ///
+    /// See the `DeviceDescriptor` struct for what can be required.
+    fn open(&self, desc: DeviceDescriptor) -> Result<Device, OpenDeviceError<DeviceCreationError>>;
+
+    /// Wrap `device`, a native device of this adapter which was created by someone else (a host
+    /// engine, a plugin environment), instead of opening a new one.
+    ///
+    /// This is how the crate is adopted inside an existing application: resources and queues
+    /// are shared both ways, with `Device::command_queue_from_raw`, `buffer_from_raw` and
+    /// `texture_from_raw` one way and the `as_raw` methods the other. Fails as `open` does if the
+    /// native device was not created with what `desc` requires (for Vulkan and d3d12, the
+    /// features and extensions the backend needs for them enabled).
+    ///
+    /// Unsafe, as for `Resource::as_raw`: the native device must belong to this adapter and
+    /// outlive the device.
+    #[cfg(feature = "raw_handles")]
+    unsafe fn open_raw(&self, device: RawHandle, desc: DeviceDescriptor) -> Result<Device, OpenDeviceError<DeviceCreationError>>;
+}
+
+/// The size, in bytes, of a single predicate inside of a predicate buffer.
+///
+/// A predicate is a 32-bit unsigned integer, stored at a 4-byte aligned offset. A compute shader
+/// producing visibility results should write `0` for "not visible" and any other value for
+/// "visible" (conventionally `1`). A single predicate buffer may hold many predicates, which are
+/// selected by offset when calling `begin_predication`.
+pub const PREDICATE_SIZE: uint = 4;
+
+/// Returns the byte offset of the `index`th predicate in a tightly packed predicate buffer.
+pub fn predicate_offset(index: uint) -> uint {
+    index * PREDICATE_SIZE
+}
+
+/// How the value of a predicate affects the draw calls it guards.
+#[deriving(Show)]
+pub enum PredicationOp {
+    /// Execute the draw calls only if the predicate is non-zero ("visible").
+    DrawIfNonZero,
+    /// Execute the draw calls only if the predicate is zero ("not visible").
+    DrawIfZero,
+}
+
+/// How vertices are assembled into primitives.
+#[deriving(Show)]
+pub enum PrimitiveType {
+    Point,
+    Line,
+    LineStrip,
+    Triangle,
+    TriangleStrip,
+}
+
+/// How triangles are rasterized (see `RenderCommandEncoder::set_triangle_fill_mode`).
+#[deriving(Clone, PartialEq, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum TriangleFillMode {
+    /// The whole interior.
+    Fill,
+    /// Only the edges, as one pixel wide lines. Requires the `fill_mode_lines` feature.
+    Lines,
+}
+
+/// A rectangle of an attachment, in pixels from the top left corner.
+#[deriving(Clone, PartialEq, Show)]
+pub struct ScissorRect {
+    pub x: uint,
+    pub y: uint,
+    pub width: uint,
+    pub height: uint,
+}
+
+/// The type of the indices in an index buffer.
+#[deriving(Clone, Show)]
+pub enum IndexFormat {
+    UInt16,
+    UInt32,
+}
+
+impl IndexFormat {
+    /// The size, in bytes, of a single index.
+    pub fn size(&self) -> uint {
+        match *self {
+            IndexFormat::UInt16 => 2,
+            IndexFormat::UInt32 => 4,
+        }
+    }
+}
+
+/// The arguments of a single indirect draw, as laid out in a buffer.
+#[repr(C)]
+pub struct DrawIndirectArgs {
+    pub vertex_count: u32,
+    pub instance_count: u32,
+    pub first_vertex: u32,
+    pub base_instance: u32,
+}
+
+/// The arguments of a single indexed indirect draw, as laid out in a buffer.
+///
+/// GPU culling shaders write arrays of these. The stride between consecutive draws is always
+/// `size_of::<DrawIndexedIndirectArgs>()`.
+#[repr(C)]
+pub struct DrawIndexedIndirectArgs {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub base_instance: u32,
+}
+
+/// The arguments of a single indirect dispatch, as laid out in a buffer: the number of
+/// threadgroups along each dimension.
+#[repr(C)]
+pub struct DispatchIndirectArgs {
+    pub threadgroups_x: u32,
+    pub threadgroups_y: u32,
+    pub threadgroups_z: u32,
+}
+
+/// The alignment of the offset of indirect arguments in their buffer, on every backend.
+pub const INDIRECT_ARGS_ALIGNMENT: uint = 4;
+
+/// The maximum number of color attachments of a render pass.
+pub const MAX_COLOR_ATTACHMENTS: uint = 8;
+
+/// What happens to the contents of an attachment when a render pass begins.
+#[deriving(Clone, PartialEq, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum LoadAction {
+    /// The contents are undefined. This is the cheapest option, for attachments every pixel of
+    /// which will be overwritten.
+    DontCare,
+    /// The contents are kept.
+    Load,
+    /// Every pixel is set to the attachment's clear value.
+    Clear,
+}
+
+/// What happens to the contents of an attachment when a render pass ends.
+#[deriving(Clone, PartialEq, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum StoreAction {
+    /// The contents become undefined. This is the cheapest option, for attachments (usually
+    /// depth) which are not needed after the pass.
+    DontCare,
+    /// The contents are written to the texture.
+    Store,
+    // todo: resolving multisampled attachments into single sampled textures.
+}
+
+/// The value a color attachment is cleared to.
+///
+/// Each kind of value clears one class of formats: `Float` the normalized and floating point
+/// formats (converted to the format as shaders' outputs are), `Uint` the `Uint` formats, and
+/// `Sint` the `Sint` formats, both exactly. Components the format lacks are ignored. Clearing an
+/// attachment with the wrong kind of value is an error (see `RenderPassDescriptor::validate`),
+/// as backends would otherwise reinterpret the value's bits.
+#[deriving(Clone, PartialEq, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum ClearColor {
+    Float { red: f64, green: f64, blue: f64, alpha: f64 },
+    Uint([u32, ..4]),
+    Sint([i32, ..4]),
+}
+
+impl ClearColor {
+    /// Transparent black, or zero, for each kind of value.
+    pub fn zero_for(format: &PixelFormat) -> ClearColor {
+        if format.is_unsigned_integer() {
+            ClearColor::Uint([0, ..4])
+        } else if format.is_signed_integer() {
+            ClearColor::Sint([0, ..4])
+        } else {
+            ClearColor::Float { red: 0.0, green: 0.0, blue: 0.0, alpha: 0.0 }
+        }
+    }
+
+    /// Whether this value can clear an attachment of `format`.
+    pub fn matches(&self, format: &PixelFormat) -> bool {
+        match *self {
+            ClearColor::Float { .. } => !format.is_integer() && !format.has_depth(),
+            ClearColor::Uint(_) => format.is_unsigned_integer(),
+            ClearColor::Sint(_) => format.is_signed_integer(),
+        }
+    }
+}
+
+/// An attachment for `RenderCommandEncoder::clear_rects` to clear, and the value to clear it to.
+#[deriving(Clone, PartialEq, Show)]
+pub enum ClearAttachment {
+    /// The color attachment at an index, with a value which `matches` its format.
+    Color(uint, ClearColor),
+    Depth(f64),
+    Stencil(u32),
+}
+
+/// A color attachment of a render pass.
+pub struct RenderPassColorAttachment<'a, D: Device> {
+    /// The texture rendered into, which must have been created with the `render_target` usage.
+    pub texture: &'a D::Texture,
+    pub mipmap_level: uint,
+    /// The array slice, or the depth plane of a `Type3D` texture.
+    pub slice: uint,
+    pub load_action: LoadAction,
+    pub store_action: StoreAction,
+    /// Used if `load_action` is `Clear`.
+    pub clear_color: ClearColor,
+}
+
+/// The depth attachment of a render pass.
+pub struct RenderPassDepthAttachment<'a, D: Device> {
+    /// The texture rendered into, which must have a depth pixel format and the `render_target`
+    /// usage.
+    pub texture: &'a D::Texture,
+    pub mipmap_level: uint,
+    pub slice: uint,
+    pub load_action: LoadAction,
+    pub store_action: StoreAction,
+    /// Used if `load_action` is `Clear`.
+    pub clear_depth: f64,
+}
+
+/// The stencil attachment of a render pass.
+pub struct RenderPassStencilAttachment<'a, D: Device> {
+    /// The texture rendered into, which must have a pixel format with stencil and the
+    /// `render_target` usage. For combined formats this is usually the depth attachment's
+    /// texture.
+    pub texture: &'a D::Texture,
+    pub mipmap_level: uint,
+    pub slice: uint,
+    pub load_action: LoadAction,
+    pub store_action: StoreAction,
+    /// Used if `load_action` is `Clear`.
+    pub clear_stencil: u32,
+}
+
+/// The attachments a render pass draws into.
+///
+/// Every attachment must have the same size (at its mip level). Up to the device's
+/// `max_color_attachments` color attachments may be given; the `n`th is the `[[color(n)]]` output
+/// of fragment functions.
+pub struct RenderPassDescriptor<'a, D: Device> {
+    pub color_attachments: Vec<RenderPassColorAttachment<'a, D>>,
+    pub depth_attachment: Option<RenderPassDepthAttachment<'a, D>>,
+    pub stencil_attachment: Option<RenderPassStencilAttachment<'a, D>>,
+    /// Where the samples of each pixel of the (multisampled) attachments are, or `None` for
+    /// `standard_sample_positions`.
+    ///
+    /// There must be one position per sample, and the device must support
+    /// `programmable_sample_positions`. Positions are fixed for the whole pass, and changing them
+    /// between passes that share a depth attachment makes its contents undefined for depth tests
+    /// against the new positions.
+    pub sample_positions: Option<Vec<SamplePosition>>,
+    /// The buffer `RenderCommandEncoder::set_visibility_result_mode` counts samples into.
+    pub visibility_result_buffer: Option<&'a D::Buffer>,
+}
+
+/// What draws record into the visibility result buffer of their pass: the GPU side of
+/// occlusion queries.
+#[deriving(Clone, PartialEq, Show)]
+pub enum VisibilityResultMode {
+    /// Nothing is recorded.
+    Disabled,
+    /// The value is set to some non-zero number if any sample passes the depth and stencil
+    /// tests. Cheaper than `Counting` on some GPUs.
+    Boolean,
+    /// The number of samples which pass the depth and stencil tests is added to the value.
+    Counting,
+}
+
+/// Why a `RenderPassDescriptor` is invalid.
+#[deriving(Show)]
+pub enum RenderPassError {
+    /// More than `MAX_COLOR_ATTACHMENTS` color attachments, or more than the device's
+    /// `max_color_attachments` (see `validate_limits`).
+    TooManyColorAttachments,
+    /// The color attachment at this index has a depth format.
+    NotColorFormat(uint),
+    /// The clear value of the color attachment at this index is of the wrong kind for its format.
+    ClearColorMismatch(uint),
+    /// The depth attachment has a format without depth.
+    NotDepthFormat,
+    /// The depth attachment's clear value is not within `[0, 1]`.
+    ClearDepthOutOfRange,
+    /// The stencil attachment has a format without stencil.
+    NotStencilFormat,
+    /// The stencil attachment's clear value does not fit in its 8 bits.
+    ClearStencilOutOfRange,
+}
+
+impl<'a, D: Device> RenderPassDescriptor<'a, D> {
+    /// Check that every attachment has a format of the right class, and a clear value that
+    /// format can hold.
+    ///
+    /// Backends call this when a pass begins (`CommandBuffer::render_command_encoder`), and panic
+    /// on an error, so nothing is rendered with garbage clear values.
+    pub fn validate(&self) -> Result<(), RenderPassError> {
+        if self.color_attachments.len() > MAX_COLOR_ATTACHMENTS {
+            return Err(RenderPassError::TooManyColorAttachments);
+        }
+        for (i, attachment) in self.color_attachments.iter().enumerate() {
+            let format = attachment.texture.pixel_format();
+            if format.has_depth() {
+                return Err(RenderPassError::NotColorFormat(i));
+            }
+            if !attachment.clear_color.matches(&format) {
+                return Err(RenderPassError::ClearColorMismatch(i));
+            }
+        }
+        if let Some(ref depth) = self.depth_attachment {
+            if !depth.texture.pixel_format().has_depth() {
+                return Err(RenderPassError::NotDepthFormat);
+            }
+            if !(depth.clear_depth >= 0.0 && depth.clear_depth <= 1.0) {
+                return Err(RenderPassError::ClearDepthOutOfRange);
+            }
+        }
+        if let Some(ref stencil) = self.stencil_attachment {
+            if !stencil.texture.pixel_format().has_stencil() {
+                return Err(RenderPassError::NotStencilFormat);
+            }
+            if stencil.clear_stencil > 0xff {
+                return Err(RenderPassError::ClearStencilOutOfRange);
+            }
+        }
+        Ok(())
+    }
+
+    /// `validate`, also checking the attachments against the device's `limits`.
+    ///
+    /// Backends call this rather than `validate` when they know their limits, as they all do.
+    pub fn validate_limits(&self, limits: &DeviceLimits) -> Result<(), RenderPassError> {
+        if self.color_attachments.len() > limits.max_color_attachments {
+            return Err(RenderPassError::TooManyColorAttachments);
+        }
+        self.validate()
+    }
+
+    /// The formats of the attachments, which the pipelines set in the pass must have been
+    /// created for (see `RenderPipelineDescriptor::attachment_formats`).
+    pub fn attachment_formats(&self) -> AttachmentFormats {
+        AttachmentFormats {
+            color: self.color_attachments.iter().map(|attachment| attachment.texture.pixel_format()).collect(),
+            depth: self.depth_attachment.as_ref().map(|attachment| attachment.texture.pixel_format()),
+            stencil: self.stencil_attachment.as_ref().map(|attachment| attachment.texture.pixel_format()),
+        }
+    }
+}
+
+/// The attachments of a render pass without their textures: the format of each, and what
+/// happens to it when the pass begins and ends.
+///
+/// Layouts are plain data, serializable with the `serde` feature, so the passes of a renderer can
+/// be described in its data files next to its pipelines (whose `attachment_formats` are the
+/// layout's `formats`). `resolve` makes the pass's descriptor, given the textures it renders into
+/// this frame:
+///
+/// ```ignore
+/// let layout: AttachmentLayout = try!(serde_json::from_str(gbuffer_json));
+/// let desc = try!(layout.resolve(vec![AttachmentTexture::new(&albedo), AttachmentTexture::new(&normals)],
+///                                Some(AttachmentTexture::new(&depth)), None));
+/// let encoder = commands.render_command_encoder(desc);
+/// ```
+#[deriving(Clone, PartialEq, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct AttachmentLayout {
+    pub color: Vec<ColorAttachmentLayout>,
+    pub depth: Option<DepthAttachmentLayout>,
+    pub stencil: Option<StencilAttachmentLayout>,
+}
+
+/// A color attachment of an `AttachmentLayout`.
+#[deriving(Clone, PartialEq, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct ColorAttachmentLayout {
+    pub format: PixelFormat,
+    pub load_action: LoadAction,
+    pub store_action: StoreAction,
+    /// Used if `load_action` is `Clear`.
+    pub clear_color: ClearColor,
+}
+
+/// The depth attachment of an `AttachmentLayout`.
+#[deriving(Clone, PartialEq, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct DepthAttachmentLayout {
+    pub format: PixelFormat,
+    pub load_action: LoadAction,
+    pub store_action: StoreAction,
+    /// Used if `load_action` is `Clear`.
+    pub clear_depth: f64,
+}
+
+/// The stencil attachment of an `AttachmentLayout`.
+#[deriving(Clone, PartialEq, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct StencilAttachmentLayout {
+    pub format: PixelFormat,
+    pub load_action: LoadAction,
+    pub store_action: StoreAction,
+    /// Used if `load_action` is `Clear`.
+    pub clear_stencil: u32,
+}
+
+/// The texture, mip level and slice an attachment of an `AttachmentLayout` renders into.
+pub struct AttachmentTexture<'a, D: Device> {
+    pub texture: &'a D::Texture,
+    pub mipmap_level: uint,
+    /// The array slice, or the depth plane of a `Type3D` texture.
+    pub slice: uint,
+}
+
+impl<'a, D: Device> AttachmentTexture<'a, D> {
+    /// The first mip level and slice of `texture`.
+    pub fn new(texture: &'a D::Texture) -> AttachmentTexture<'a, D> {
+        AttachmentTexture { texture: texture, mipmap_level: 0, slice: 0 }
+    }
+}
+
+/// Why textures do not fit an `AttachmentLayout`.
+#[deriving(Show)]
+pub enum AttachmentLayoutError {
+    /// There are not as many color textures as the layout has color attachments.
+    ColorCountMismatch,
+    /// The color texture at this index is not of its attachment's format.
+    ColorFormatMismatch(uint),
+    /// A depth texture was given to a layout without a depth attachment, or the other way around,
+    /// or it is not of the attachment's format.
+    DepthMismatch,
+    /// As `DepthMismatch`, for the stencil attachment.
+    StencilMismatch,
+}
+
+impl AttachmentLayout {
+    /// The formats of the attachments, for the `attachment_formats` of the pipelines used in the
+    /// pass.
+    pub fn formats(&self) -> AttachmentFormats {
+        AttachmentFormats {
+            color: self.color.iter().map(|attachment| attachment.format.clone()).collect(),
+            depth: self.depth.as_ref().map(|attachment| attachment.format.clone()),
+            stencil: self.stencil.as_ref().map(|attachment| attachment.format.clone()),
+        }
+    }
+
+    /// The descriptor of a pass rendering into `color`, `depth` and `stencil`, one texture for
+    /// each attachment of the layout, of its format.
+    ///
+    /// The descriptor has the standard sample positions and no visibility result buffer, for
+    /// the caller to set.
+    pub fn resolve<'a, D: Device>(&self, color: Vec<AttachmentTexture<'a, D>>, depth: Option<AttachmentTexture<'a, D>>,
+                                  stencil: Option<AttachmentTexture<'a, D>>)
+                                  -> Result<RenderPassDescriptor<'a, D>, AttachmentLayoutError> {
+        if color.len() != self.color.len() {
+            return Err(AttachmentLayoutError::ColorCountMismatch);
+        }
+        let mut color_attachments = Vec::with_capacity(color.len());
+        for (i, (target, layout)) in color.into_iter().zip(self.color.iter()).enumerate() {
+            if target.texture.pixel_format() != layout.format {
+                return Err(AttachmentLayoutError::ColorFormatMismatch(i));
+            }
+            color_attachments.push(RenderPassColorAttachment {
+                texture: target.texture,
+                mipmap_level: target.mipmap_level,
+                slice: target.slice,
+                load_action: layout.load_action.clone(),
+                store_action: layout.store_action.clone(),
+                clear_color: layout.clear_color.clone(),
+            });
+        }
+        let depth_attachment = match (depth, self.depth.as_ref()) {
+            (None, None) => None,
+            (Some(ref target), Some(layout)) if target.texture.pixel_format() == layout.format => {
+                Some(RenderPassDepthAttachment {
+                    texture: target.texture,
+                    mipmap_level: target.mipmap_level,
+                    slice: target.slice,
+                    load_action: layout.load_action.clone(),
+                    store_action: layout.store_action.clone(),
+                    clear_depth: layout.clear_depth,
+                })
+            }
+            _ => return Err(AttachmentLayoutError::DepthMismatch),
+        };
+        let stencil_attachment = match (stencil, self.stencil.as_ref()) {
+            (None, None) => None,
+            (Some(ref target), Some(layout)) if target.texture.pixel_format() == layout.format => {
+                Some(RenderPassStencilAttachment {
+                    texture: target.texture,
+                    mipmap_level: target.mipmap_level,
+                    slice: target.slice,
+                    load_action: layout.load_action.clone(),
+                    store_action: layout.store_action.clone(),
+                    clear_stencil: layout.clear_stencil,
+                })
+            }
+            _ => return Err(AttachmentLayoutError::StencilMismatch),
+        };
+        Ok(RenderPassDescriptor {
+            color_attachments: color_attachments,
+            depth_attachment: depth_attachment,
+            stencil_attachment: stencil_attachment,
+            sample_positions: None,
+            visibility_result_buffer: None,
+        })
+    }
+}
+
+/// A position within a pixel, from `(0, 0)` at its top left corner to `(1, 1)` at its bottom
+/// right. Positions are quantized to `1/16` of a pixel, and must be less than `1`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct SamplePosition {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// The standard sample positions for each power of two `sample_count` up to `16`, which every
+/// backend uses for multisampled attachments unless told otherwise, in sample order.
+///
+/// These are the positions d3d and vulkan specify, and that metal uses. Temporal antialiasing
+/// and checkerboard rendering need them to know what each sample covered.
+pub fn standard_sample_positions(sample_count: uint) -> Option<Vec<SamplePosition>> {
+    // in sixteenths of a pixel, from the pixel center
+    static ONE: [(i8, i8), ..1] = [(0, 0)];
+    static TWO: [(i8, i8), ..2] = [(4, 4), (-4, -4)];
+    static FOUR: [(i8, i8), ..4] = [(-2, -6), (6, -2), (-6, 2), (2, 6)];
+    static EIGHT: [(i8, i8), ..8] = [(1, -3), (-1, 3), (5, 1), (-3, -5), (-5, 5), (-7, -1), (3, 7), (7, -7)];
+    static SIXTEEN: [(i8, i8), ..16] = [(1, 1), (-1, -3), (-3, 2), (4, -1), (-5, -2), (2, 5), (5, 3), (3, -5),
+                                        (-2, 6), (0, -7), (-4, -6), (-6, 4), (-8, 0), (7, -4), (6, 7), (-7, -8)];
+    let offsets: &[(i8, i8)] = match sample_count {
+        1 => &ONE,
+        2 => &TWO,
+        4 => &FOUR,
+        8 => &EIGHT,
+        16 => &SIXTEEN,
+        _ => return None,
+    };
+    Some(offsets.iter().map(|&(x, y)| SamplePosition {
+        x: 0.5 + x as f32 / 16.0,
+        y: 0.5 + y as f32 / 16.0,
+    }).collect())
+}
+
+/// The transform from normalized device coordinates to the pixels of the attachments.
+#[deriving(Clone)]
+pub struct Viewport {
+    /// The top left corner, in pixels.
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// The depth range normalized device depths of `0` and `1` are mapped to.
+    pub znear: f64,
+    pub zfar: f64,
+}
+
+/// Records the draws of a render pass. See `CommandBuffer::render_command_encoder`.
+pub trait RenderCommandEncoder<D: Device> {
+    /// Set the render pipeline used by subsequent draws.
+    fn set_render_pipeline(&mut self, pipeline: &D::RenderPipeline);
+
+    /// Set the depth and stencil tests used by subsequent draws.
+    ///
+    /// When a pass begins, depth and stencil testing are disabled, and depth is not written.
+    fn set_depth_stencil_state(&mut self, state: &D::DepthStencilState);
+
+    /// Set the reference value of the stencil test, for both faces. It is `0` when a pass begins.
+    ///
+    /// Pipelines whose fragment function exports stencil values ignore it.
+    fn set_stencil_reference_value(&mut self, value: u32);
+
+    /// Set the viewport used by subsequent draws.
+    ///
+    /// When a pass begins, the viewport covers the whole of the attachments, with a depth range of
+    /// `0` to `1`.
+    fn set_viewport(&mut self, viewport: Viewport);
+
+    /// Set how subsequent draws rasterize triangles. It is `Fill` when a pass begins.
+    ///
+    /// `Lines` requires the `fill_mode_lines` device feature. Points and lines are rasterized as
+    /// they are whatever the mode.
+    fn set_triangle_fill_mode(&mut self, mode: TriangleFillMode);
+
+    /// Offset the depth of the fragments of subsequent draws by `bias` times the smallest
+    /// resolvable depth difference plus `slope_scale` times the depth slope of the primitive,
+    /// with the offset clamped to at most `clamp` in magnitude (`0` does not clamp). Bias is `0`
+    /// when a pass begins.
+    ///
+    /// Negative values move fragments towards the camera, for drawing over geometry at the same
+    /// depth (decals, wireframes); positive values away from it, which is how shadow maps avoid
+    /// self-shadowing.
+    fn set_depth_bias(&mut self, bias: f32, slope_scale: f32, clamp: f32);
+
+    /// Begin predicated rendering.
+    ///
+    /// Until the matching `end_predication`, draw calls encoded with this encoder are executed or
+    /// skipped depending on the predicate stored at `offset` in `buffer`, as chosen by `op`. The
+    /// predicate is read by the GPU when the draws execute, not when they are encoded, so it may
+    /// be written by earlier GPU work (typically a culling compute shader) in the same command
+    /// buffer without a round trip to the CPU.
+    ///
+    /// `buffer` must have been created with the `predicate` hint, and `offset` must be a multiple
+    /// of `PREDICATE_SIZE`.
+
+    // note: predication does not nest. Calling `begin_predication` twice without an intervening
+    // `end_predication` is a usage error.
+    fn begin_predication(&mut self, buffer: &D::Buffer, offset: uint, op: PredicationOp);
+
+    /// End predicated rendering.
+    ///
+    /// Draw calls encoded after this point execute unconditionally.
+    fn end_predication(&mut self);
+
+    /// Bind a buffer for use by the vertex function.
+    ///
+    /// The slice is bound at `index`, and the function sees it as starting at its first byte.
+    /// Buffers bound here are used both by the vertex fetch stage (per the pipeline's
+    /// `VertexDescriptor`) and as plain buffer arguments to the vertex function.
+    fn set_vertex_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint);
+
+    /// Bind a small amount of data for use by the vertex function, without creating a buffer.
+    ///
+    /// As for `ComputeCommandEncoder::set_bytes`.
+    fn set_vertex_bytes(&mut self, bytes: &[u8], index: uint);
+
+    /// Bind a texture for use by the vertex function at `index`.
+    fn set_vertex_texture(&mut self, texture: &D::Texture, index: uint);
+
+    /// Bind a sampler for use by the vertex function at `index`.
+    fn set_vertex_sampler(&mut self, sampler: &D::Sampler, index: uint);
+
+    /// Bind a buffer slice for use by the fragment function at `index`.
+    fn set_fragment_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint);
+
+    /// Bind a small amount of data for use by the fragment function, without creating a buffer.
+    ///
+    /// As for `ComputeCommandEncoder::set_bytes`.
+    fn set_fragment_bytes(&mut self, bytes: &[u8], index: uint);
+
+    /// Bind a texture for use by the fragment function at `index`.
+    fn set_fragment_texture(&mut self, texture: &D::Texture, index: uint);
+
+    /// Bind a sampler for use by the fragment function at `index`.
+    fn set_fragment_sampler(&mut self, sampler: &D::Sampler, index: uint);
+
+    /// Restrict subsequent draws to a rectangle of the attachments.
+    ///
+    /// Fragments outside of `rect` are discarded. `rect` must lie within the attachments. The
+    /// scissor rectangle covers the whole of the attachments when a pass begins.
+    fn set_scissor_rect(&mut self, rect: ScissorRect);
+
+    /// Clear `rects` of the pass's `attachments`, leaving the rest of them as they are.
+    ///
+    /// This is how only part of a target is redrawn, such as the damaged regions of a user
+    /// interface or the tiles of a terrain texture which changed: load the attachments, clear
+    /// the rectangles first thing in the pass, and draw over them. Clearing ignores the
+    /// pipeline, depth stencil state, viewport, scissor rectangle and predication, and leaves
+    /// them as they were. Every rectangle must lie within the attachments, and every attachment
+    /// must be one of the pass. Backends without a native partial clear (Metal) draw the
+    /// rectangles, which costs as much as a draw.
+    fn clear_rects(&mut self, attachments: &[ClearAttachment], rects: &[ScissorRect]);
+
+    /// Record the visibility of subsequent draws into the `u64` at byte `offset` of the pass's
+    /// `visibility_result_buffer`, as `mode` says, until the mode is set again.
+    ///
+    /// `offset` must be a multiple of `8`. Values are never cleared by the pass, so they should be
+    /// zeroed (with `fill_buffer`) before it. A pass starts out `Disabled`. Results are only
+    /// available once the command buffer has completed, which is well after the frame that
+    /// needs them; see `util::occlusion_queries` for using them without stalling.
+    fn set_visibility_result_mode(&mut self, mode: VisibilityResultMode, offset: uint);
+
+    /// Bind a bind group at `index` for every stage in its layout's entries' visibility.
+    ///
+    /// The bind group's layout must match the layout the current pipeline expects at `index`.
+    fn set_bind_group(&mut self, group: &D::BindGroup, index: uint);
+
+    /// Declare that a resource is accessed by subsequent draws without being bound.
+    ///
+    /// This is needed for resources which shaders reach only indirectly, through a GPU address
+    /// (see `Buffer::gpu_address`) or an entry in another buffer, so that the backend makes them
+    /// resident and tracks the hazards. `writable` should be set if any shader may write to it.
+    fn use_resource(&mut self, resource: ResourceRef<D>, writable: bool);
+
+    /// Bind the index buffer used by subsequent indexed draws.
+    ///
+    /// Indices of type `format` are read from `slice`, so many meshes' index data can be packed
+    /// into one buffer (and mixed `UInt16`/`UInt32` data even, with each mesh bound as its own
+    /// slice and format). The `first_index` of a draw is counted in indices from the start of
+    /// the slice.
+    ///
+    /// The slice's offset must be a multiple of `format.size()`. That is the strictest
+    /// requirement of any supported backend (vulkan, d3d12 and GL all require it, metal requires
+    /// 4-byte alignment for `UInt32` and is lenient for `UInt16` on some devices), so validation
+    /// enforces it everywhere rather than letting code work on one backend and break on another.
+    fn set_index_buffer(&mut self, slice: BufferSlice<D::Buffer>, format: IndexFormat);
+
+    /// Issue a draw.
+    ///
+    /// `instance_count` instances of `vertex_count` vertices each are drawn, starting from vertex
+    /// `first_vertex`. The instance ID seen by shaders starts at `base_instance`, and per-instance
+    /// vertex attributes are fetched starting from the `base_instance`th element.
+    fn draw(&mut self, primitive: PrimitiveType, first_vertex: uint, vertex_count: uint,
+            instance_count: uint, base_instance: uint);
+
+    /// Issue an indexed draw.
+    ///
+    /// `index_count` indices are read from the currently bound index buffer starting at index
+    /// `first_index`. `base_vertex` is added to each index before it is used to fetch vertex
+    /// attributes (and is included in the vertex ID), which is what allows many meshes to share a
+    /// single vertex buffer while keeping their indices relative to their own first vertex.
+    /// `base_instance` works as for `draw`.
+    fn draw_indexed(&mut self, primitive: PrimitiveType, first_index: uint, index_count: uint,
+                    instance_count: uint, base_vertex: int, base_instance: uint);
+
+    /// Bind a buffer to capture vertex function outputs into.
+    ///
+    /// Outputs are written from the start of `slice` onward, and not past its end. Its buffer must
+    /// have been created with the `stream_output` hint, and the device must support the
+    /// `stream_output` feature.
+
+    // todo: which outputs are captured into which target belongs in the pipeline descriptor.
+    fn set_stream_output_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint);
+
+    /// Draw every vertex captured into a stream output buffer.
+    ///
+    /// The number of vertices is the number of bytes captured into `buffer` by the last draw that
+    /// targeted it, divided by `stride`. That count is only known to the GPU, so this avoids
+    /// having to read it back. `buffer` must also be bound with `set_vertex_buffer` for its
+    /// contents to be fetched, and may no longer be bound as a stream output target.
+    /// `instance_count` and `base_instance` work as for `draw`.
+    fn draw_from_stream_output(&mut self, primitive: PrimitiveType, buffer: &D::Buffer, stride: uint,
+                               instance_count: uint, base_instance: uint);
+
+    /// Issue a draw whose arguments are read from a buffer by the GPU.
+    ///
+    /// A single `DrawIndirectArgs` is read from `args` at `offset`, which must be a multiple of
+    /// 4, and used as the arguments to `draw`.
+    fn draw_indirect(&mut self, primitive: PrimitiveType, args: &D::Buffer, offset: uint);
+
+    /// Issue an indexed draw whose arguments are read from a buffer by the GPU.
+    ///
+    /// A single `DrawIndexedIndirectArgs` is read from `args` at `offset`, which must be a
+    /// multiple of 4, and used as the arguments to `draw_indexed`.
+    fn draw_indexed_indirect(&mut self, primitive: PrimitiveType, args: &D::Buffer, offset: uint);
+
+    /// Issue a number of indexed indirect draws, where the number is read from a buffer by the
+    /// GPU.
+    ///
+    /// A 32-bit unsigned draw count is read from `count` at `count_offset`, and then that many
+    /// (but never more than `max_draws`) consecutive `DrawIndexedIndirectArgs` are read from
+    /// `args` starting at `args_offset` and drawn in order. This is what lets a culling compute
+    /// shader decide both what and how much to draw, typically appending to `args` and counting
+    /// with a buffer counter (see `copy_counter_value`).
+    ///
+    /// If the device does not report the `multi_draw_indirect_count` feature, the default
+    /// implementation is used, which issues all `max_draws` draws with `draw_indexed_indirect`
+    /// and ignores `count`. In that case every entry of `args` past the GPU-written count must
+    /// have an `instance_count` of `0`, which turns it into a no-op. Clearing `args` before the
+    /// culling pass is enough to guarantee this.
+    fn multi_draw_indexed_indirect_count(&mut self, primitive: PrimitiveType,
+                                         args: &D::Buffer, args_offset: uint,
+                                         count: &D::Buffer, count_offset: uint, max_draws: uint) {
+        let _ = (count, count_offset);
+        let stride = core::mem::size_of::<DrawIndexedIndirectArgs>();
+        for i in range(0, max_draws) {
+            self.draw_indexed_indirect(primitive, args, args_offset + i * stride);
+        }
+    }
+
+    /// What is bound right now, as the next draw would see it.
+    ///
+    /// This is for debuggers and validation reports, and only exists with the `debug_state`
+    /// feature, which makes backends keep track of bindings they would otherwise forget.
+    #[cfg(feature = "debug_state")]
+    fn debug_state(&self) -> RenderEncoderState<D>;
+
+    /// End the render pass, storing the attachments.
+    fn end_encoding(self);
+}
+
+/// Records copies and fills. See `CommandBuffer::blit_command_encoder`.
+pub trait BlitCommandEncoder<D: Device> {
+    /// Copy the value of a buffer's counter into another buffer.
+    ///
+    /// The current value of `src`'s counter is written as a 32-bit unsigned integer at
+    /// `dst_offset` in `dst`, which must be a multiple of 4. This is what allows, for example, the
+    /// number of surviving particles after a compaction pass to be used as the count of an
+    /// indirect draw without reading it back.
+    ///
+    /// `src` must have been created with the `counter` hint.
+    fn copy_counter_value(&mut self, src: &D::Buffer, dst: &D::Buffer, dst_offset: uint);
+
+    /// Set the value of a buffer's counter.
+    ///
+    /// `buffer` must have been created with the `counter` hint. This is typically done with a
+    /// `value` of `0` once per frame, before the passes that append to the buffer.
+    fn reset_counter(&mut self, buffer: &D::Buffer, value: u32);
+
+    /// Set every byte of a buffer slice to `value`.
+    fn fill_buffer(&mut self, slice: BufferSlice<D::Buffer>, value: u8);
+
+    /// Copy the bytes of a buffer slice into another buffer.
+    ///
+    /// The bytes of `src` are copied to `dst` from `dst_offset` on, which must hold them. The
+    /// offsets and the size of the copy must be multiples of 4 (vulkan's and metal's
+    /// requirement), and the regions must not overlap if `dst` is the buffer of `src`.
+    fn copy_buffer(&mut self, src: BufferSlice<D::Buffer>, dst: &D::Buffer, dst_offset: uint);
+
+    /// Copy a region of one texture into another.
+    ///
+    /// The `size` region at `src_origin` of mip level `src_level` of array slice `src_slice` of
+    /// `src` is copied to `dst_origin` of the given level and slice of `dst`. Both textures must
+    /// have the same pixel format, and the regions must lie within their levels. Copying between
+    /// overlapping regions of the same texture level is not allowed.
+    fn copy_texture(&mut self, src: &D::Texture, src_level: uint, src_slice: uint, src_origin: Origin, size: Size,
+                    dst: &D::Texture, dst_level: uint, dst_slice: uint, dst_origin: Origin);
+
+    /// Copy texels from a buffer into a texture.
+    ///
+    /// The buffer data is laid out as for `Device::write_texture`, from the start of `src`, whose
+    /// offset must be a multiple of `COPY_OFFSET_ALIGNMENT`, with `bytes_per_row` a multiple of
+    /// `COPY_ROW_ALIGNMENT`. `src` must hold every row of the copy. Unlike `write_texture`, this
+    /// is ordered with the other commands of the command buffer, so it sees (and is seen by) the
+    /// copies and passes around it.
+    fn copy_buffer_to_texture(&mut self, src: BufferSlice<D::Buffer>, bytes_per_row: uint,
+                              dst: &D::Texture, dst_level: uint, dst_slice: uint, dst_origin: Origin, size: Size);
+
+    /// Copy texels from a texture into a buffer.
+    ///
+    /// The inverse of `copy_buffer_to_texture`, with the same layout and alignment requirements
+    /// on `dst` and `bytes_per_row`. This is how textures are read back: copy into a buffer (see
+    /// `util::readback_heap`), and read the buffer once the command buffer has completed.
+    fn copy_texture_to_buffer(&mut self, src: &D::Texture, src_level: uint, src_slice: uint, src_origin: Origin, size: Size,
+                              dst: BufferSlice<D::Buffer>, bytes_per_row: uint);
+
+    /// Finish encoding. The copies run before the commands of any later encoder.
+    fn end_encoding(self);
+
+    // todo: mipmap generation
+}
+
+/// The alignment of the buffer offset of `copy_buffer_to_texture` and `copy_texture_to_buffer`.
+///
+/// This and `COPY_ROW_ALIGNMENT` are the strictest requirements of any backend (d3d12's), so that
+/// copies which work on one backend work on all of them.
+pub const COPY_OFFSET_ALIGNMENT: uint = 512;
+
+/// The alignment of the `bytes_per_row` of `copy_buffer_to_texture` and `copy_texture_to_buffer`.
+pub const COPY_ROW_ALIGNMENT: uint = 256;
+
+/// A three dimensional size, used for compute grids and texture regions.
+pub struct Size {
+    pub width: uint,
+    pub height: uint,
+    pub depth: uint,
+}
+
+/// The position of a texel in a texture.
+pub struct Origin {
+    pub x: uint,
+    pub y: uint,
+    pub z: uint,
+}
+
+/// Records compute dispatches. See `CommandBuffer::compute_command_encoder`.
+pub trait ComputeCommandEncoder<D: Device> {
+    /// Set the compute pipeline used by subsequent dispatches.
+    fn set_compute_pipeline(&mut self, pipeline: &D::ComputePipeline);
+
+    /// Bind a buffer for use by the compute function.
+    ///
+    /// The slice is bound at `index`, and the function sees it as starting at its first byte.
+    fn set_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint);
+
+    /// Bind a small amount of data for use by the compute function, without creating a buffer.
+    ///
+    /// The data is copied when this is called, so `bytes` may be reused immediately. Backends may
+    /// limit the length of `bytes` (metal only guarantees 4KiB); larger data should go in a
+    /// buffer.
+    fn set_bytes(&mut self, bytes: &[u8], index: uint);
+
+    /// Bind a texture for use by the compute function at `index`.
+    fn set_texture(&mut self, texture: &D::Texture, index: uint);
+
+    /// Bind a sampler for use by the compute function at `index`.
+    fn set_sampler(&mut self, sampler: &D::Sampler, index: uint);
+
+    /// Set the length, in bytes, of the `[[threadgroup(index)]]` argument of subsequent
+    /// dispatches, overriding the current pipeline's `threadgroup_memory_lengths`.
+    ///
+    /// `length` must be a multiple of 16. The override lasts until the pipeline is set again.
+    fn set_threadgroup_memory_length(&mut self, length: uint, index: uint);
+
+    /// Bind a bind group at `index`.
+    ///
+    /// As for `RenderCommandEncoder::set_bind_group`.
+    fn set_bind_group(&mut self, group: &D::BindGroup, index: uint);
+
+    /// Declare that a resource is accessed by subsequent dispatches without being bound.
+    ///
+    /// As for `RenderCommandEncoder::use_resource`.
+    fn use_resource(&mut self, resource: ResourceRef<D>, writable: bool);
+
+    /// Dispatch the current compute pipeline.
+    ///
+    /// `threadgroups` threadgroups of `threads_per_threadgroup` threads each are run.
+    ///
+    /// Dispatches on the same encoder run in order: the writes of one dispatch are visible to
+    /// every later dispatch, so chains of passes (like building a mip chain level by level) need
+    /// no extra synchronization.
+    fn dispatch_threadgroups(&mut self, threadgroups: Size, threads_per_threadgroup: Size);
+
+    /// Dispatch the current compute pipeline, with the number of threadgroups read from a buffer
+    /// by the GPU.
+    ///
+    /// A single `DispatchIndirectArgs` is read from `args` at `offset`, which must be a multiple
+    /// of `INDIRECT_ARGS_ALIGNMENT`. A count of `0` along any dimension dispatches nothing.
+    fn dispatch_threadgroups_indirect(&mut self, args: &D::Buffer, offset: uint, threads_per_threadgroup: Size);
+
+    /// What is bound right now, as the next dispatch would see it. As for render encoders, this
+    /// only exists with the `debug_state` feature.
+    #[cfg(feature = "debug_state")]
+    fn debug_state(&self) -> ComputeEncoderState<D>;
+
+    /// Finish encoding. The dispatches run before the commands of any later encoder.
+    fn end_encoding(self);
+}
+
+/// How important it is for a resource to stay in GPU memory. See
+/// `Device::set_residency_priority`.
+///
+/// Backends which manage residency (d3d12 with `MakeResident`/`Evict` and residency priorities,
+/// Metal's heap and resource purgeability) evict lower priorities first under memory pressure,
+/// and make higher priorities resident first when memory frees up. Render targets which every
+/// frame needs are `High`; streamed data which can be fetched again is `Low`.
+#[deriving(Clone, PartialEq, PartialOrd, Show)]
+pub enum ResidencyPriority {
+    Low,
+    Normal,
+    High,
+}
+
+/// A reference to any resource created by a device.
+pub enum ResourceRef<'a, D: Device> {
+    Buffer(&'a D::Buffer),
+    Texture(&'a D::Texture),
+}
+
+/// What is bound to a buffer index of an encoder.
+#[cfg(feature = "debug_state")]
+pub enum BoundBuffer<'a, D: Device> {
+    Buffer(BufferSlice<'a, D::Buffer>),
+    /// `set_bytes` data of this length.
+    Bytes(uint),
+}
+
+/// The bindings of a `RenderCommandEncoder` (see `RenderCommandEncoder::debug_state`).
+///
+/// Each vector is indexed by binding index, and is only as long as the highest index bound.
+#[cfg(feature = "debug_state")]
+pub struct RenderEncoderState<'a, D: Device> {
+    pub pipeline: Option<&'a D::RenderPipeline>,
+    pub depth_stencil_state: Option<&'a D::DepthStencilState>,
+    pub bind_groups: Vec<Option<&'a D::BindGroup>>,
+    pub vertex_buffers: Vec<Option<BoundBuffer<'a, D>>>,
+    pub fragment_buffers: Vec<Option<BoundBuffer<'a, D>>>,
+    pub vertex_textures: Vec<Option<&'a D::Texture>>,
+    pub fragment_textures: Vec<Option<&'a D::Texture>>,
+    /// The index buffer, and the format of its indices.
+    pub index_buffer: Option<(BufferSlice<'a, D::Buffer>, IndexFormat)>,
+    pub viewport: Option<Viewport>,
+    pub scissor_rect: Option<ScissorRect>,
+}
+
+/// The bindings of a `ComputeCommandEncoder` (see `ComputeCommandEncoder::debug_state`).
+///
+/// As for `RenderEncoderState`, each vector is indexed by binding index.
+#[cfg(feature = "debug_state")]
+pub struct ComputeEncoderState<'a, D: Device> {
+    pub pipeline: Option<&'a D::ComputePipeline>,
+    pub bind_groups: Vec<Option<&'a D::BindGroup>>,
+    pub buffers: Vec<Option<BoundBuffer<'a, D>>>,
+    pub textures: Vec<Option<&'a D::Texture>>,
+    pub threadgroup_memory_lengths: Vec<uint>,
+}
+
+/// An extension for backends that can hand crate resources to a native framework.
+///
+/// This is the integration point for backend-specific machine learning frameworks (MPS Graph on
+/// metal, DirectML on d3d12) and anything else which records its own GPU work on top of the
+/// backend's native objects. The external work is inserted into a command buffer at the point
+/// `encode_external` is called, ordered with respect to the crate's own commands in that command
+/// buffer like any other pass.
+pub trait NativeInterop : Device {
+    /// The backend's native buffer (`MTLBuffer`, `ID3D12Resource`, ...).
+    type NativeBuffer;
+    /// The backend's native texture (`MTLTexture`, `ID3D12Resource`, ...).
+    type NativeTexture;
+    /// The object the external framework records its work into (`MTLCommandBuffer` for MPS
+    /// Graph, `ID3D12GraphicsCommandList` for DirectML).
+    type NativeCommandRecorder;
+
+    /// Get the native object backing `buffer`.
+    ///
+    /// The native object is only valid for as long as `buffer` is. It must only be used inside of
+    /// `encode_external`, or the crate's hazard tracking will not know about the access.
+    unsafe fn native_buffer(&self, buffer: &Buffer) -> NativeBuffer;
+
+    /// Get the native object backing `texture`.
+    ///
+    /// The same restrictions as `native_buffer` apply.
+    unsafe fn native_texture(&self, texture: &Texture) -> NativeTexture;
+
+    /// Record external work into `commands`.
+    ///
+    /// `f` is called with the native command recorder and must record all of its work before
+    /// returning. `reads` and `writes` are every resource that work accesses: the backend inserts
+    /// whatever synchronization is needed so that earlier commands writing to them (or reading
+    /// from `writes`) complete first, and so that later commands see the external writes.
+    ///
+    /// No encoder may be open on `commands` while this is called.
+
+    // note: the framework is free to change native state (bound pipelines etc). backends must not
+    // assume that any state they set before `f` is still set after it.
+    unsafe fn encode_external<F: FnOnce(&mut NativeCommandRecorder)>(&mut self,
+                                                                      commands: &mut CommandBuffer,
+                                                                      reads: &[ResourceRef<Self>],
+                                                                      writes: &[ResourceRef<Self>],
+                                                                      f: F);
+}
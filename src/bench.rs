@@ -0,0 +1,232 @@
+//! Benchmarks of the overhead of the calls applications make most often.
+//!
+//! These measure the CPU cost of the abstraction and the backend underneath it, not GPU
+//! performance, so regressions in either show up directly. A backend's benchmark target registers
+//! them for its device:
+//!
+//! ```ignore
+//! fn backend_overhead(c: &mut Criterion) {
+//!     let mut device = open_device();
+//!     let program = compile(&mut device, metal_sketch::bench::DRAW_SOURCE);
+//!     metal_sketch::bench::benchmarks(c, "metal", &mut device, Some(&program));
+//! }
+//!
+//! criterion_group!(benches, backend_overhead);
+//! criterion_main!(benches);
+//! ```
+//!
+//! Benchmarks are named `<backend>/<call>/<parameters>`, so results for different backends can
+//! be compared side by side. The encoding benchmarks draw, so they need a shader program of
+//! `DRAW_SOURCE`; without one, they are left out.
+
+use criterion::{Criterion, black_box};
+
+use {Device, CommandBuffer, CommandQueue, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor};
+use {BindGroupLayoutEntry, BindingCount, BindingResource, BindingType, BufferSlice, ShaderStages};
+use {AttachmentFormats, ClearColor, IndexFormat, LoadAction, PixelFormat, PrimitiveType, RenderCommandEncoder};
+use {RenderPassColorAttachment, RenderPassDescriptor, RenderPipelineDescriptor, StoreAction, TextureDescriptor};
+use {TextureType, TextureUsage};
+
+const BUFFER_SIZES: [uint, ..3] = [256, 65536, 16777216];
+const WRITE_SIZES: [uint, ..3] = [64, 4096, 1048576];
+/// The number of commands encoded into one render pass per iteration of the encoding benchmarks.
+const ENCODE_COUNTS: [uint, ..3] = [1, 100, 10000];
+
+/// Register every benchmark for `device`, under `backend`. `draw_program` is a shader program of
+/// `DRAW_SOURCE`, for the encoding benchmarks.
+pub fn benchmarks<D: Device>(c: &mut Criterion, backend: &str, device: &mut D,
+                             draw_program: Option<&D::ShaderProgram>) {
+    buffer_benchmarks(c, backend, device);
+    bind_group_benchmarks(c, backend, device);
+    submit_benchmarks(c, backend, device);
+    if let Some(program) = draw_program {
+        encode_benchmarks(c, backend, device, program);
+    }
+}
+
+/// The shader source of the encoding benchmarks: a vertex function which needs no vertex
+/// buffers, and a fragment function of a constant color.
+pub const DRAW_SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+vertex float4 bench_vertex(uint vid [[vertex_id]])
+{
+    return float4(float(vid & 1), float(vid >> 1), 0.0f, 1.0f);
+}
+
+fragment float4 bench_fragment()
+{
+    return float4(1.0f);
+}
+"#;
+
+fn buffer_benchmarks<D: Device>(c: &mut Criterion, backend: &str, device: &mut D) {
+    let mut group = c.benchmark_group(format!("{}/create_buffer", backend));
+    for &size in BUFFER_SIZES.iter() {
+        group.bench_function(format!("{}", size), |b| b.iter(|| {
+            black_box(device.create_buffer(size, None).ok().expect("create_buffer failed"))
+        }));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}/write_buffer", backend));
+    for &size in WRITE_SIZES.iter() {
+        let buffer = device.create_buffer(size, None).ok().expect("create_buffer failed");
+        let data = Vec::from_elem(size, 0xa5u8);
+        group.bench_function(format!("{}", size), |b| b.iter(|| {
+            device.write_buffer(&buffer, 0, data.as_slice())
+        }));
+    }
+    group.finish();
+}
+
+fn bind_group_benchmarks<D: Device>(c: &mut Criterion, backend: &str, device: &mut D) {
+    let layout = device.create_bind_group_layout(BindGroupLayoutDescriptor {
+        entries: vec![BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages { vertex: true, fragment: true, compute: true },
+            ty: BindingType::UniformBuffer,
+            count: BindingCount::Single,
+            partially_bound: false,
+            update_after_bind: false,
+        }],
+    }).ok().expect("create_bind_group_layout failed");
+    let buffer = device.create_buffer(256, None).ok().expect("create_buffer failed");
+
+    c.bench_function(format!("{}/create_bind_group", backend), |b| b.iter(|| {
+        black_box(device.create_bind_group(BindGroupDescriptor {
+            layout: &layout,
+            entries: vec![BindGroupEntry {
+                binding: 0,
+                array_element: 0,
+                resource: BindingResource::Buffer(BufferSlice::new(&buffer, 0, 256)),
+            }],
+            unsized_length: 0,
+        }).ok().expect("create_bind_group failed"))
+    }));
+}
+
+fn submit_benchmarks<D: Device>(c: &mut Criterion, backend: &str, device: &mut D) {
+    let mut queue = device.create_command_queue(None).ok().expect("create_command_queue failed");
+    c.bench_function(format!("{}/submit/empty", backend), |b| b.iter(|| {
+        queue.acquire_command_buffer().commit()
+    }));
+}
+
+fn render_pass<D: Device>(target: &D::Texture) -> RenderPassDescriptor<D> {
+    RenderPassDescriptor {
+        color_attachments: vec![RenderPassColorAttachment {
+            texture: target,
+            mipmap_level: 0,
+            slice: 0,
+            load_action: LoadAction::DontCare,
+            store_action: StoreAction::DontCare,
+            clear_color: ClearColor::Float { red: 0.0, green: 0.0, blue: 0.0, alpha: 0.0 },
+        }],
+        depth_attachment: None,
+        stencil_attachment: None,
+        sample_positions: None,
+        visibility_result_buffer: None,
+    }
+}
+
+// Each iteration encodes `count` commands into a new render pass of a command buffer which is
+// dropped uncommitted, so nothing reaches the GPU and the cost of the pass is amortized over
+// the commands at the larger counts.
+fn encode_benchmarks<D: Device>(c: &mut Criterion, backend: &str, device: &mut D, program: &D::ShaderProgram) {
+    let target = device.create_texture(TextureDescriptor {
+        texture_type: TextureType::Type2D,
+        pixel_format: PixelFormat::RGBA8Unorm,
+        width: 64,
+        height: 64,
+        depth: 1,
+        mipmap_level_count: 1,
+        array_length: 1,
+        sample_count: 1,
+        usage: TextureUsage { shader_read: false, shader_write: false, render_target: true },
+    }).ok().expect("create_texture failed");
+    let pipeline = device.create_render_pipeline(program, RenderPipelineDescriptor {
+        label: Some("bench".to_string()),
+        robustness: None,
+        vertex_function: "bench_vertex".to_string(),
+        fragment_function: Some("bench_fragment".to_string()),
+        vertex_descriptor: None,
+        blending: Vec::new(),
+        sample_count: 1,
+        attachment_formats: Some(AttachmentFormats {
+            color: vec![PixelFormat::RGBA8Unorm],
+            depth: None,
+            stencil: None,
+        }),
+    }).ok().expect("create_render_pipeline failed");
+    let mut queue = device.create_command_queue(None).ok().expect("create_command_queue failed");
+
+    // two groups, bound alternately, so no backend can skip a bind as redundant
+    let layout = device.create_bind_group_layout(BindGroupLayoutDescriptor {
+        entries: vec![BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages { vertex: true, fragment: true, compute: false },
+            ty: BindingType::UniformBuffer,
+            count: BindingCount::Single,
+            partially_bound: false,
+            update_after_bind: false,
+        }],
+    }).ok().expect("create_bind_group_layout failed");
+    let buffer = device.create_buffer(512, None).ok().expect("create_buffer failed");
+    let groups: Vec<D::BindGroup> = range(0u, 2).map(|i| device.create_bind_group(BindGroupDescriptor {
+        layout: &layout,
+        entries: vec![BindGroupEntry {
+            binding: 0,
+            array_element: 0,
+            resource: BindingResource::Buffer(BufferSlice::new(&buffer, i * 256, 256)),
+        }],
+        unsized_length: 0,
+    }).ok().expect("create_bind_group failed")).collect();
+
+    let indices = device.create_buffer(6 * 2, None).ok().expect("create_buffer failed");
+    device.write_buffer(&indices, 0, &[0, 0, 1, 0, 2, 0, 2, 0, 1, 0, 3, 0]);
+
+    let mut group = c.benchmark_group(format!("{}/set_bind_group", backend));
+    for &count in ENCODE_COUNTS.iter() {
+        group.bench_function(format!("{}", count), |b| b.iter(|| {
+            let mut commands = queue.acquire_command_buffer();
+            let mut encoder = commands.render_command_encoder(render_pass(&target));
+            encoder.set_render_pipeline(&pipeline);
+            for i in range(0, count) {
+                encoder.set_bind_group(&groups[i % 2], 0);
+            }
+            encoder.end_encoding();
+        }));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}/draw", backend));
+    for &count in ENCODE_COUNTS.iter() {
+        group.bench_function(format!("{}", count), |b| b.iter(|| {
+            let mut commands = queue.acquire_command_buffer();
+            let mut encoder = commands.render_command_encoder(render_pass(&target));
+            encoder.set_render_pipeline(&pipeline);
+            for _ in range(0, count) {
+                encoder.draw(PrimitiveType::TriangleStrip, 0, 4, 1, 0);
+            }
+            encoder.end_encoding();
+        }));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}/draw_indexed", backend));
+    for &count in ENCODE_COUNTS.iter() {
+        group.bench_function(format!("{}", count), |b| b.iter(|| {
+            let mut commands = queue.acquire_command_buffer();
+            let mut encoder = commands.render_command_encoder(render_pass(&target));
+            encoder.set_render_pipeline(&pipeline);
+            encoder.set_index_buffer(BufferSlice::whole(&indices), IndexFormat::UInt16);
+            for _ in range(0, count) {
+                encoder.draw_indexed(PrimitiveType::Triangle, 0, 6, 1, 0, 0);
+            }
+            encoder.end_encoding();
+        }));
+    }
+    group.finish();
+}
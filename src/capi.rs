@@ -0,0 +1,913 @@
+//! A C API, for engines written in C, C++ or anything else with a C FFI.
+//!
+//! The API is declared in `include/metal_sketch.h`, and is a thin layer over `DeviceObject`: the
+//! C side gets an `MsDevice` from whichever part of the application opened it (usually with
+//! `auto`, then `device_into_raw`), and creates and updates resources through it. Every object is
+//! an opaque pointer, destroyed with its `ms_*_destroy` function.
+//!
+//! Functions which can fail return an `MsStatus`, and leave a description of the last failure on
+//! the device for `ms_device_last_error`. Enumerations are passed as integers, numbered as the
+//! header numbers them: in the order the values were added to the header, which is not always
+//! the order of the Rust enum's variants. New values get the next number.
+//!
+//! Commands are recorded into an `MsCommandBuffer`, pass by pass, and submitted with
+//! `ms_command_buffer_commit`, which returns an `MsFence` to wait on. Recording never fails on
+//! the spot: the first misuse (a null pointer, a bad enumeration value, a command outside a pass
+//! of its kind) is kept, and `ms_command_buffer_commit` reports it without submitting anything.
+//! Every object a command uses must outlive the commit.
+//!
+//! Additions are made at the end of the header only, and existing declarations never change, so
+//! code compiled against an older header keeps working.
+
+use std::c_str::{CString, ToCStr};
+use libc::{c_char, size_t, uint32_t};
+
+use {AnyDevice, DeviceObject, Origin, PixelFormat, ShaderArchive, Size, TextureDescriptor, TextureType};
+use {TextureUsage, TextureViewDescriptor};
+use {AttachmentFormats, ClearColor, CommandBufferError, CommandBufferStatus, ComputePipelineDescriptor, IndexFormat};
+use {LoadAction, PrimitiveType, RenderPipelineDescriptor, StoreAction};
+use any_device::{AnyBuffer, AnyShaderProgram, AnyTexture, BackendKind, ObjectError};
+use any_device::{AnyBlitCommand, AnyColorAttachment, AnyCommandQueue, AnyComputeCommand, AnyComputePipeline};
+use any_device::{AnyDepthAttachment, AnyPass, AnyRenderCommand, AnyRenderPass, AnyRenderPipeline};
+use util::fence::Fence;
+
+/// The result of a fallible call.
+#[repr(C)]
+pub enum MsStatus {
+    Ok = 0,
+    /// An argument was null or out of range.
+    InvalidArgument = 1,
+    /// An object was created by a device of another backend.
+    WrongBackend = 2,
+    /// The backend failed.
+    Backend = 3,
+}
+
+/// A device, with the description of its last error.
+pub struct MsDevice {
+    device: AnyDevice,
+    last_error: CString,
+}
+
+pub type MsBuffer = AnyBuffer;
+pub type MsTexture = AnyTexture;
+pub type MsShaderProgram = AnyShaderProgram;
+pub type MsComputePipeline = AnyComputePipeline;
+pub type MsRenderPipeline = AnyRenderPipeline;
+pub type MsCommandQueue = AnyCommandQueue;
+pub type MsFence = Fence;
+
+/// Commands being recorded, for `ms_command_buffer_commit`.
+///
+/// The references are made from the pointers the C side passes in; they must outlive the commit.
+pub struct MsCommandBuffer {
+    passes: Vec<AnyPass<'static>>,
+    /// The pass being recorded, if any.
+    open: Option<AnyPass<'static>>,
+    /// The first misuse, reported on commit.
+    error: Option<String>,
+}
+
+#[repr(C)]
+pub struct MsTextureDescriptor {
+    pub texture_type: uint32_t,
+    pub pixel_format: uint32_t,
+    pub width: uint32_t,
+    pub height: uint32_t,
+    pub depth: uint32_t,
+    pub mipmap_level_count: uint32_t,
+    pub array_length: uint32_t,
+    /// `1` for textures which are not multisampled.
+    pub sample_count: uint32_t,
+    /// `MS_TEXTURE_USAGE_*` bits.
+    pub usage: uint32_t,
+}
+
+#[repr(C)]
+pub struct MsTextureViewDescriptor {
+    pub pixel_format: uint32_t,
+    pub base_mipmap_level: uint32_t,
+    pub mipmap_level_count: uint32_t,
+    pub base_array_slice: uint32_t,
+    pub array_length: uint32_t,
+}
+
+/// A region of a texture.
+#[repr(C)]
+pub struct MsRegion {
+    pub x: uint32_t,
+    pub y: uint32_t,
+    pub z: uint32_t,
+    pub width: uint32_t,
+    pub height: uint32_t,
+    pub depth: uint32_t,
+}
+
+pub const MS_TEXTURE_USAGE_SHADER_READ: uint32_t = 1;
+pub const MS_TEXTURE_USAGE_SHADER_WRITE: uint32_t = 2;
+pub const MS_TEXTURE_USAGE_RENDER_TARGET: uint32_t = 4;
+
+/// The `depth_format` of an `MsRenderPipelineDescriptor` with no depth attachment.
+pub const MS_PIXEL_FORMAT_NONE: uint32_t = 0xffffffff;
+
+#[repr(C)]
+pub struct MsRenderPipelineDescriptor {
+    pub vertex_function: *const c_char,
+    /// Null for a pipeline which only rasterizes.
+    pub fragment_function: *const c_char,
+    pub color_formats: *const uint32_t,
+    pub color_format_count: size_t,
+    /// `MS_PIXEL_FORMAT_NONE` for no depth attachment.
+    pub depth_format: uint32_t,
+    pub sample_count: uint32_t,
+}
+
+#[repr(C)]
+pub struct MsColorAttachment {
+    pub texture: *const MsTexture,
+    pub mipmap_level: uint32_t,
+    pub slice: uint32_t,
+    pub load_action: uint32_t,
+    pub store_action: uint32_t,
+    /// Red, green, blue and alpha, for `MS_LOAD_ACTION_CLEAR`.
+    pub clear_color: [f64, ..4],
+}
+
+#[repr(C)]
+pub struct MsDepthAttachment {
+    pub texture: *const MsTexture,
+    pub mipmap_level: uint32_t,
+    pub slice: uint32_t,
+    pub load_action: uint32_t,
+    pub store_action: uint32_t,
+    pub clear_depth: f64,
+}
+
+/// The `MS_COMMAND_BUFFER_*` status of a fence's command buffer.
+pub const MS_COMMAND_BUFFER_COMPLETED: uint32_t = 0;
+pub const MS_COMMAND_BUFFER_TIMEOUT: uint32_t = 1;
+pub const MS_COMMAND_BUFFER_PAGE_FAULT: uint32_t = 2;
+pub const MS_COMMAND_BUFFER_OUT_OF_MEMORY: uint32_t = 3;
+pub const MS_COMMAND_BUFFER_DEVICE_LOST: uint32_t = 4;
+
+/// Hand a device over to the C API.
+///
+/// The pointer is freed with `ms_device_destroy`, which also drops the device.
+pub fn device_into_raw(device: AnyDevice) -> *mut MsDevice {
+    into_raw(MsDevice { device: device, last_error: "".to_c_str() })
+}
+
+fn into_raw<T>(value: T) -> *mut T {
+    unsafe { std::mem::transmute(box value) }
+}
+
+unsafe fn destroy<T>(ptr: *mut T) {
+    if !ptr.is_null() {
+        drop(std::mem::transmute::<*mut T, Box<T>>(ptr));
+    }
+}
+
+fn pixel_format(n: uint32_t) -> Option<PixelFormat> {
+    Some(match n {
+        0 => PixelFormat::R8Unorm,
+        1 => PixelFormat::RG8Unorm,
+        2 => PixelFormat::RGBA8Unorm,
+        3 => PixelFormat::RGBA8UnormSrgb,
+        4 => PixelFormat::BGRA8Unorm,
+        5 => PixelFormat::BGRA8UnormSrgb,
+        6 => PixelFormat::R16Float,
+        7 => PixelFormat::RG16Float,
+        8 => PixelFormat::RGBA16Float,
+        9 => PixelFormat::R32Float,
+        10 => PixelFormat::RG32Float,
+        11 => PixelFormat::RGBA32Float,
+        12 => PixelFormat::R32Uint,
+        13 => PixelFormat::Depth32Float,
+        14 => PixelFormat::Depth24UnormStencil8,
+        15 => PixelFormat::Depth32FloatStencil8,
+        16 => PixelFormat::RG32Uint,
+        17 => PixelFormat::R32Sint,
+        18 => PixelFormat::RG11B10Float,
+        19 => PixelFormat::RGB9E5Float,
+        _ => return None,
+    })
+}
+
+fn texture_type(n: uint32_t) -> Option<TextureType> {
+    Some(match n {
+        0 => TextureType::Type2D,
+        1 => TextureType::Type2DArray,
+        2 => TextureType::Type3D,
+        3 => TextureType::TypeCube,
+        _ => return None,
+    })
+}
+
+fn load_action(n: uint32_t) -> Option<LoadAction> {
+    Some(match n {
+        0 => LoadAction::DontCare,
+        1 => LoadAction::Load,
+        2 => LoadAction::Clear,
+        _ => return None,
+    })
+}
+
+fn store_action(n: uint32_t) -> Option<StoreAction> {
+    Some(match n {
+        0 => StoreAction::DontCare,
+        1 => StoreAction::Store,
+        _ => return None,
+    })
+}
+
+fn primitive_type(n: uint32_t) -> Option<PrimitiveType> {
+    Some(match n {
+        0 => PrimitiveType::Point,
+        1 => PrimitiveType::Line,
+        2 => PrimitiveType::LineStrip,
+        3 => PrimitiveType::Triangle,
+        4 => PrimitiveType::TriangleStrip,
+        _ => return None,
+    })
+}
+
+fn index_format(n: uint32_t) -> Option<IndexFormat> {
+    Some(match n {
+        0 => IndexFormat::UInt16,
+        1 => IndexFormat::UInt32,
+        _ => return None,
+    })
+}
+
+fn command_buffer_status(status: CommandBufferStatus) -> uint32_t {
+    match status {
+        CommandBufferStatus::Completed => MS_COMMAND_BUFFER_COMPLETED,
+        CommandBufferStatus::Failed(CommandBufferError::Timeout) => MS_COMMAND_BUFFER_TIMEOUT,
+        CommandBufferStatus::Failed(CommandBufferError::PageFault) => MS_COMMAND_BUFFER_PAGE_FAULT,
+        CommandBufferStatus::Failed(CommandBufferError::OutOfMemory) => MS_COMMAND_BUFFER_OUT_OF_MEMORY,
+        CommandBufferStatus::Failed(CommandBufferError::DeviceLost) => MS_COMMAND_BUFFER_DEVICE_LOST,
+    }
+}
+
+unsafe fn string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    CString::new(ptr, false).as_str().map(|s| s.to_string())
+}
+
+impl MsDevice {
+    fn fail(&mut self, error: ObjectError) -> MsStatus {
+        self.last_error = format!("{}", error).to_c_str();
+        match error {
+            ObjectError::WrongBackend => MsStatus::WrongBackend,
+            ObjectError::Backend(_) => MsStatus::Backend,
+            ObjectError::OutOfBounds | ObjectError::InvalidPass(_) => MsStatus::InvalidArgument,
+        }
+    }
+
+    fn invalid(&mut self, what: &str) -> MsStatus {
+        self.last_error = format!("invalid argument: {}", what).to_c_str();
+        MsStatus::InvalidArgument
+    }
+
+    fn finish<T>(&mut self, result: Result<T, ObjectError>, out: *mut *mut T) -> MsStatus {
+        match result {
+            Ok(value) => {
+                unsafe { *out = into_raw(value) };
+                MsStatus::Ok
+            }
+            Err(e) => self.fail(e),
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_device_destroy(device: *mut MsDevice) {
+    destroy(device);
+}
+
+/// The `MS_BACKEND_*` value of the device's backend.
+#[no_mangle]
+pub unsafe extern "C" fn ms_device_backend(device: *const MsDevice) -> uint32_t {
+    match (*device).device.backend() {
+        BackendKind::Metal => 0,
+        BackendKind::Vulkan => 1,
+        BackendKind::D3D12 => 2,
+        BackendKind::OpenGl => 3,
+    }
+}
+
+/// The description of the last failure on `device`, valid until the next call with `device`.
+#[no_mangle]
+pub unsafe extern "C" fn ms_device_last_error(device: *const MsDevice) -> *const c_char {
+    (*device).last_error.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_shader_program_create_from_archive(device: *mut MsDevice, data: *const u8, length: size_t,
+                                                               out: *mut *mut MsShaderProgram) -> MsStatus {
+    let device = &mut *device;
+    if data.is_null() || out.is_null() {
+        return device.invalid("null pointer");
+    }
+    let archive = match ShaderArchive::decode(std::slice::from_raw_buf(&data, length as uint)) {
+        Ok(archive) => archive,
+        Err(e) => return device.invalid(format!("bad archive: {}", e).as_slice()),
+    };
+    let result = device.device.object().create_shader_program_from_archive(&archive);
+    device.finish(result, out)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_shader_program_destroy(program: *mut MsShaderProgram) {
+    destroy(program);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_buffer_create(device: *mut MsDevice, length: size_t, out: *mut *mut MsBuffer) -> MsStatus {
+    let device = &mut *device;
+    if out.is_null() {
+        return device.invalid("null pointer");
+    }
+    let result = device.device.object().create_buffer(length as uint, None);
+    device.finish(result, out)
+}
+
+/// Write `length` bytes from `data` into `buffer`, starting at byte `offset`.
+#[no_mangle]
+pub unsafe extern "C" fn ms_buffer_write(device: *mut MsDevice, buffer: *const MsBuffer, offset: size_t,
+                                         data: *const u8, length: size_t) -> MsStatus {
+    let device = &mut *device;
+    if buffer.is_null() || data.is_null() {
+        return device.invalid("null pointer");
+    }
+    let data = std::slice::from_raw_buf(&data, length as uint);
+    match device.device.object().write_buffer(&*buffer, offset as uint, data) {
+        Ok(()) => MsStatus::Ok,
+        Err(e) => device.fail(e),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_buffer_destroy(buffer: *mut MsBuffer) {
+    destroy(buffer);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_texture_create(device: *mut MsDevice, desc: *const MsTextureDescriptor,
+                                           out: *mut *mut MsTexture) -> MsStatus {
+    let device = &mut *device;
+    if desc.is_null() || out.is_null() {
+        return device.invalid("null pointer");
+    }
+    let desc = &*desc;
+    let (texture_type, pixel_format) = match (texture_type(desc.texture_type), pixel_format(desc.pixel_format)) {
+        (Some(t), Some(f)) => (t, f),
+        _ => return device.invalid("texture type or pixel format"),
+    };
+    let result = device.device.object().create_texture(TextureDescriptor {
+        texture_type: texture_type,
+        pixel_format: pixel_format,
+        width: desc.width as uint,
+        height: desc.height as uint,
+        depth: desc.depth as uint,
+        mipmap_level_count: desc.mipmap_level_count as uint,
+        array_length: desc.array_length as uint,
+        sample_count: desc.sample_count as uint,
+        usage: TextureUsage {
+            shader_read: desc.usage & MS_TEXTURE_USAGE_SHADER_READ != 0,
+            shader_write: desc.usage & MS_TEXTURE_USAGE_SHADER_WRITE != 0,
+            render_target: desc.usage & MS_TEXTURE_USAGE_RENDER_TARGET != 0,
+        },
+    });
+    device.finish(result, out)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_texture_create_view(device: *mut MsDevice, texture: *const MsTexture,
+                                                desc: *const MsTextureViewDescriptor, out: *mut *mut MsTexture) -> MsStatus {
+    let device = &mut *device;
+    if texture.is_null() || desc.is_null() || out.is_null() {
+        return device.invalid("null pointer");
+    }
+    let desc = &*desc;
+    let pixel_format = match pixel_format(desc.pixel_format) {
+        Some(f) => f,
+        None => return device.invalid("pixel format"),
+    };
+    let result = device.device.object().create_texture_view(&*texture, TextureViewDescriptor {
+        pixel_format: pixel_format,
+        base_mipmap_level: desc.base_mipmap_level as uint,
+        mipmap_level_count: desc.mipmap_level_count as uint,
+        base_array_slice: desc.base_array_slice as uint,
+        array_length: desc.array_length as uint,
+    });
+    device.finish(result, out)
+}
+
+/// Write texels into `region` of a mip level and array slice of `texture`, as for
+/// `Device::write_texture`.
+#[no_mangle]
+pub unsafe extern "C" fn ms_texture_write(device: *mut MsDevice, texture: *const MsTexture, mipmap_level: uint32_t,
+                                          slice: uint32_t, region: *const MsRegion, data: *const u8, length: size_t,
+                                          bytes_per_row: size_t) -> MsStatus {
+    let device = &mut *device;
+    if texture.is_null() || region.is_null() || data.is_null() {
+        return device.invalid("null pointer");
+    }
+    let region = &*region;
+    let data = std::slice::from_raw_buf(&data, length as uint);
+    match device.device.object().write_texture(&*texture, mipmap_level as uint, slice as uint,
+                                               Origin { x: region.x as uint, y: region.y as uint, z: region.z as uint },
+                                               Size { width: region.width as uint, height: region.height as uint,
+                                                      depth: region.depth as uint },
+                                               data, bytes_per_row as uint) {
+        Ok(()) => MsStatus::Ok,
+        Err(e) => device.fail(e),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_texture_destroy(texture: *mut MsTexture) {
+    destroy(texture);
+}
+
+/// Read `length` bytes of `buffer`, starting at byte `offset`, into `data`, as for
+/// `Device::read_buffer`.
+#[no_mangle]
+pub unsafe extern "C" fn ms_buffer_read(device: *mut MsDevice, buffer: *const MsBuffer, offset: size_t,
+                                        data: *mut u8, length: size_t) -> MsStatus {
+    let device = &mut *device;
+    if buffer.is_null() || data.is_null() {
+        return device.invalid("null pointer");
+    }
+    let data = std::slice::from_raw_mut_buf(&data, length as uint);
+    match device.device.object().read_buffer(&*buffer, offset as uint, data) {
+        Ok(()) => MsStatus::Ok,
+        Err(e) => device.fail(e),
+    }
+}
+
+/// Create a compute pipeline of the function `function` of `program`.
+#[no_mangle]
+pub unsafe extern "C" fn ms_compute_pipeline_create(device: *mut MsDevice, program: *const MsShaderProgram,
+                                                    function: *const c_char, out: *mut *mut MsComputePipeline)
+                                                    -> MsStatus {
+    let device = &mut *device;
+    if program.is_null() || out.is_null() {
+        return device.invalid("null pointer");
+    }
+    let function = match string(function) {
+        Some(function) => function,
+        None => return device.invalid("function name"),
+    };
+    let result = device.device.object().create_compute_pipeline(&*program, ComputePipelineDescriptor {
+        label: Some(function.clone()),
+        robustness: None,
+        required_subgroup_size: None,
+        cooperative_matrices: Vec::new(),
+        compute_function: function,
+        threadgroup_memory_lengths: Vec::new(),
+    });
+    device.finish(result, out)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_compute_pipeline_destroy(pipeline: *mut MsComputePipeline) {
+    destroy(pipeline);
+}
+
+/// Create a render pipeline of functions of `program`, without vertex descriptor or blending.
+#[no_mangle]
+pub unsafe extern "C" fn ms_render_pipeline_create(device: *mut MsDevice, program: *const MsShaderProgram,
+                                                   desc: *const MsRenderPipelineDescriptor,
+                                                   out: *mut *mut MsRenderPipeline) -> MsStatus {
+    let device = &mut *device;
+    if program.is_null() || desc.is_null() || out.is_null() {
+        return device.invalid("null pointer");
+    }
+    let desc = &*desc;
+    let vertex_function = match string(desc.vertex_function) {
+        Some(function) => function,
+        None => return device.invalid("vertex function name"),
+    };
+    if desc.color_formats.is_null() && desc.color_format_count != 0 {
+        return device.invalid("null pointer");
+    }
+    let mut color = Vec::with_capacity(desc.color_format_count as uint);
+    if desc.color_format_count != 0 {
+        for &n in std::slice::from_raw_buf(&desc.color_formats, desc.color_format_count as uint).iter() {
+            match pixel_format(n) {
+                Some(format) => color.push(format),
+                None => return device.invalid("color pixel format"),
+            }
+        }
+    }
+    let depth = match desc.depth_format {
+        MS_PIXEL_FORMAT_NONE => None,
+        n => match pixel_format(n) {
+            Some(format) => Some(format),
+            None => return device.invalid("depth pixel format"),
+        },
+    };
+    let result = device.device.object().create_render_pipeline(&*program, RenderPipelineDescriptor {
+        label: Some(vertex_function.clone()),
+        robustness: None,
+        vertex_function: vertex_function,
+        fragment_function: string(desc.fragment_function),
+        vertex_descriptor: None,
+        blending: Vec::new(),
+        sample_count: desc.sample_count as uint,
+        attachment_formats: Some(AttachmentFormats { color: color, depth: depth, stencil: None }),
+    });
+    device.finish(result, out)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_render_pipeline_destroy(pipeline: *mut MsRenderPipeline) {
+    destroy(pipeline);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_command_queue_create(device: *mut MsDevice, out: *mut *mut MsCommandQueue) -> MsStatus {
+    let device = &mut *device;
+    if out.is_null() {
+        return device.invalid("null pointer");
+    }
+    let result = device.device.object().create_command_queue(None);
+    device.finish(result, out)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_command_queue_destroy(queue: *mut MsCommandQueue) {
+    destroy(queue);
+}
+
+impl MsCommandBuffer {
+    fn misuse(&mut self, what: &str) {
+        if self.error.is_none() {
+            self.error = Some(what.to_string());
+        }
+    }
+
+    fn end_pass(&mut self) {
+        if let Some(pass) = self.open.take() {
+            self.passes.push(pass);
+        }
+    }
+
+    fn compute(&mut self, command: AnyComputeCommand<'static>) {
+        match self.open {
+            Some(AnyPass::Compute(ref mut commands)) => return commands.push(command),
+            _ => {}
+        }
+        self.misuse("compute command outside a compute pass")
+    }
+
+    fn blit(&mut self, command: AnyBlitCommand<'static>) {
+        match self.open {
+            Some(AnyPass::Blit(ref mut commands)) => return commands.push(command),
+            _ => {}
+        }
+        self.misuse("blit command outside a blit pass")
+    }
+
+    fn render(&mut self, command: AnyRenderCommand<'static>) {
+        match self.open {
+            Some(AnyPass::Render(ref mut pass)) => return pass.commands.push(command),
+            _ => {}
+        }
+        self.misuse("render command outside a render pass")
+    }
+}
+
+/// Begin recording commands. Nothing is encoded until `ms_command_buffer_commit`.
+#[no_mangle]
+pub unsafe extern "C" fn ms_command_buffer_create() -> *mut MsCommandBuffer {
+    into_raw(MsCommandBuffer { passes: Vec::new(), open: None, error: None })
+}
+
+/// Drop the commands of a command buffer which was not committed.
+#[no_mangle]
+pub unsafe extern "C" fn ms_command_buffer_destroy(commands: *mut MsCommandBuffer) {
+    destroy(commands);
+}
+
+/// Begin a compute pass, ending the pass being recorded, if any.
+#[no_mangle]
+pub unsafe extern "C" fn ms_command_buffer_begin_compute(commands: *mut MsCommandBuffer) {
+    let commands = &mut *commands;
+    commands.end_pass();
+    commands.open = Some(AnyPass::Compute(Vec::new()));
+}
+
+/// Begin a blit pass, ending the pass being recorded, if any.
+#[no_mangle]
+pub unsafe extern "C" fn ms_command_buffer_begin_blit(commands: *mut MsCommandBuffer) {
+    let commands = &mut *commands;
+    commands.end_pass();
+    commands.open = Some(AnyPass::Blit(Vec::new()));
+}
+
+/// Begin a render pass into `color_count` color attachments and, unless `depth` is null, a
+/// depth attachment, ending the pass being recorded, if any.
+#[no_mangle]
+pub unsafe extern "C" fn ms_command_buffer_begin_render(commands: *mut MsCommandBuffer,
+                                                        colors: *const MsColorAttachment, color_count: size_t,
+                                                        depth: *const MsDepthAttachment) {
+    let commands = &mut *commands;
+    commands.end_pass();
+    commands.open = Some(AnyPass::Render(AnyRenderPass {
+        color_attachments: Vec::new(),
+        depth_attachment: None,
+        commands: Vec::new(),
+    }));
+    if colors.is_null() && color_count != 0 {
+        return commands.misuse("null pointer");
+    }
+    let mut color_attachments = Vec::with_capacity(color_count as uint);
+    if color_count != 0 {
+        for color in std::slice::from_raw_buf(&colors, color_count as uint).iter() {
+            let (load, store) = match (load_action(color.load_action), store_action(color.store_action)) {
+                (Some(load), Some(store)) => (load, store),
+                _ => return commands.misuse("load or store action"),
+            };
+            if color.texture.is_null() {
+                return commands.misuse("null pointer");
+            }
+            color_attachments.push(AnyColorAttachment {
+                texture: &*color.texture,
+                mipmap_level: color.mipmap_level as uint,
+                slice: color.slice as uint,
+                load_action: load,
+                store_action: store,
+                clear_color: ClearColor::Float { red: color.clear_color[0], green: color.clear_color[1],
+                                                 blue: color.clear_color[2], alpha: color.clear_color[3] },
+            });
+        }
+    }
+    let depth_attachment = if depth.is_null() {
+        None
+    } else {
+        let depth = &*depth;
+        let (load, store) = match (load_action(depth.load_action), store_action(depth.store_action)) {
+            (Some(load), Some(store)) => (load, store),
+            _ => return commands.misuse("load or store action"),
+        };
+        if depth.texture.is_null() {
+            return commands.misuse("null pointer");
+        }
+        Some(AnyDepthAttachment {
+            texture: &*depth.texture,
+            mipmap_level: depth.mipmap_level as uint,
+            slice: depth.slice as uint,
+            load_action: load,
+            store_action: store,
+            clear_depth: depth.clear_depth,
+        })
+    };
+    commands.open = Some(AnyPass::Render(AnyRenderPass {
+        color_attachments: color_attachments,
+        depth_attachment: depth_attachment,
+        commands: Vec::new(),
+    }));
+}
+
+/// End the pass being recorded.
+#[no_mangle]
+pub unsafe extern "C" fn ms_command_buffer_end_pass(commands: *mut MsCommandBuffer) {
+    let commands = &mut *commands;
+    if commands.open.is_none() {
+        return commands.misuse("ended a pass with no pass open");
+    }
+    commands.end_pass();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_compute_set_pipeline(commands: *mut MsCommandBuffer, pipeline: *const MsComputePipeline) {
+    let commands = &mut *commands;
+    if pipeline.is_null() {
+        return commands.misuse("null pointer");
+    }
+    commands.compute(AnyComputeCommand::SetPipeline(&*pipeline));
+}
+
+/// Bind `size` bytes of `buffer`, from byte `offset`, to buffer index `index`.
+#[no_mangle]
+pub unsafe extern "C" fn ms_compute_set_buffer(commands: *mut MsCommandBuffer, buffer: *const MsBuffer,
+                                               offset: size_t, size: size_t, index: uint32_t) {
+    let commands = &mut *commands;
+    if buffer.is_null() {
+        return commands.misuse("null pointer");
+    }
+    commands.compute(AnyComputeCommand::SetBuffer { buffer: &*buffer, offset: offset as uint, size: size as uint,
+                                                    index: index as uint });
+}
+
+/// Bind a copy of `length` bytes of `data` to buffer index `index`.
+#[no_mangle]
+pub unsafe extern "C" fn ms_compute_set_bytes(commands: *mut MsCommandBuffer, data: *const u8, length: size_t,
+                                              index: uint32_t) {
+    let commands = &mut *commands;
+    if data.is_null() {
+        return commands.misuse("null pointer");
+    }
+    let bytes = std::slice::from_raw_buf(&data, length as uint).to_vec();
+    commands.compute(AnyComputeCommand::SetBytes { bytes: bytes, index: index as uint });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_compute_set_texture(commands: *mut MsCommandBuffer, texture: *const MsTexture,
+                                                index: uint32_t) {
+    let commands = &mut *commands;
+    if texture.is_null() {
+        return commands.misuse("null pointer");
+    }
+    commands.compute(AnyComputeCommand::SetTexture { texture: &*texture, index: index as uint });
+}
+
+/// Dispatch `threadgroups` threadgroups of `threads_per_threadgroup` threads each, as
+/// `MsRegion` sizes (only `width`, `height` and `depth` are read).
+#[no_mangle]
+pub unsafe extern "C" fn ms_compute_dispatch(commands: *mut MsCommandBuffer, threadgroups: *const MsRegion,
+                                             threads_per_threadgroup: *const MsRegion) {
+    let commands = &mut *commands;
+    if threadgroups.is_null() || threads_per_threadgroup.is_null() {
+        return commands.misuse("null pointer");
+    }
+    let (groups, threads) = (&*threadgroups, &*threads_per_threadgroup);
+    commands.compute(AnyComputeCommand::Dispatch {
+        threadgroups: Size { width: groups.width as uint, height: groups.height as uint, depth: groups.depth as uint },
+        threads_per_threadgroup: Size { width: threads.width as uint, height: threads.height as uint,
+                                        depth: threads.depth as uint },
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_blit_fill_buffer(commands: *mut MsCommandBuffer, buffer: *const MsBuffer, offset: size_t,
+                                             size: size_t, value: u8) {
+    let commands = &mut *commands;
+    if buffer.is_null() {
+        return commands.misuse("null pointer");
+    }
+    commands.blit(AnyBlitCommand::FillBuffer { buffer: &*buffer, offset: offset as uint, size: size as uint,
+                                               value: value });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_blit_copy_buffer(commands: *mut MsCommandBuffer, src: *const MsBuffer, src_offset: size_t,
+                                             size: size_t, dst: *const MsBuffer, dst_offset: size_t) {
+    let commands = &mut *commands;
+    if src.is_null() || dst.is_null() {
+        return commands.misuse("null pointer");
+    }
+    commands.blit(AnyBlitCommand::CopyBuffer { src: &*src, src_offset: src_offset as uint, size: size as uint,
+                                               dst: &*dst, dst_offset: dst_offset as uint });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_render_set_pipeline(commands: *mut MsCommandBuffer, pipeline: *const MsRenderPipeline) {
+    let commands = &mut *commands;
+    if pipeline.is_null() {
+        return commands.misuse("null pointer");
+    }
+    commands.render(AnyRenderCommand::SetPipeline(&*pipeline));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_render_set_vertex_buffer(commands: *mut MsCommandBuffer, buffer: *const MsBuffer,
+                                                     offset: size_t, size: size_t, index: uint32_t) {
+    let commands = &mut *commands;
+    if buffer.is_null() {
+        return commands.misuse("null pointer");
+    }
+    commands.render(AnyRenderCommand::SetVertexBuffer { buffer: &*buffer, offset: offset as uint, size: size as uint,
+                                                        index: index as uint });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_render_set_fragment_buffer(commands: *mut MsCommandBuffer, buffer: *const MsBuffer,
+                                                       offset: size_t, size: size_t, index: uint32_t) {
+    let commands = &mut *commands;
+    if buffer.is_null() {
+        return commands.misuse("null pointer");
+    }
+    commands.render(AnyRenderCommand::SetFragmentBuffer { buffer: &*buffer, offset: offset as uint,
+                                                          size: size as uint, index: index as uint });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_render_set_fragment_texture(commands: *mut MsCommandBuffer, texture: *const MsTexture,
+                                                        index: uint32_t) {
+    let commands = &mut *commands;
+    if texture.is_null() {
+        return commands.misuse("null pointer");
+    }
+    commands.render(AnyRenderCommand::SetFragmentTexture { texture: &*texture, index: index as uint });
+}
+
+/// Set the index buffer of `draw_indexed`s, of `MS_INDEX_FORMAT_*` indices.
+#[no_mangle]
+pub unsafe extern "C" fn ms_render_set_index_buffer(commands: *mut MsCommandBuffer, buffer: *const MsBuffer,
+                                                    offset: size_t, size: size_t, format: uint32_t) {
+    let commands = &mut *commands;
+    if buffer.is_null() {
+        return commands.misuse("null pointer");
+    }
+    let format = match index_format(format) {
+        Some(format) => format,
+        None => return commands.misuse("index format"),
+    };
+    commands.render(AnyRenderCommand::SetIndexBuffer { buffer: &*buffer, offset: offset as uint, size: size as uint,
+                                                       format: format });
+}
+
+/// Draw `MS_PRIMITIVE_TYPE_*` primitives, as `RenderCommandEncoder::draw`.
+#[no_mangle]
+pub unsafe extern "C" fn ms_render_draw(commands: *mut MsCommandBuffer, primitive: uint32_t, first_vertex: uint32_t,
+                                        vertex_count: uint32_t, instance_count: uint32_t, base_instance: uint32_t) {
+    let commands = &mut *commands;
+    let primitive = match primitive_type(primitive) {
+        Some(primitive) => primitive,
+        None => return commands.misuse("primitive type"),
+    };
+    commands.render(AnyRenderCommand::Draw {
+        primitive: primitive,
+        first_vertex: first_vertex as uint,
+        vertex_count: vertex_count as uint,
+        instance_count: instance_count as uint,
+        base_instance: base_instance as uint,
+    });
+}
+
+/// Draw indexed `MS_PRIMITIVE_TYPE_*` primitives, as `RenderCommandEncoder::draw_indexed`.
+#[no_mangle]
+pub unsafe extern "C" fn ms_render_draw_indexed(commands: *mut MsCommandBuffer, primitive: uint32_t,
+                                                first_index: uint32_t, index_count: uint32_t,
+                                                instance_count: uint32_t, base_vertex: i32, base_instance: uint32_t) {
+    let commands = &mut *commands;
+    let primitive = match primitive_type(primitive) {
+        Some(primitive) => primitive,
+        None => return commands.misuse("primitive type"),
+    };
+    commands.render(AnyRenderCommand::DrawIndexed {
+        primitive: primitive,
+        first_index: first_index as uint,
+        index_count: index_count as uint,
+        instance_count: instance_count as uint,
+        base_vertex: base_vertex as int,
+        base_instance: base_instance as uint,
+    });
+}
+
+/// Submit the commands to `queue`, ending the pass being recorded, and destroy `commands`.
+///
+/// `out` gets a fence signaled once the commands have completed. If recording went wrong, or a
+/// handle is of another backend or a range out of bounds, nothing is submitted. `commands` is
+/// destroyed whether or not this succeeds, even if `queue` or `out` is null.
+#[no_mangle]
+pub unsafe extern "C" fn ms_command_buffer_commit(device: *mut MsDevice, queue: *mut MsCommandQueue,
+                                                  commands: *mut MsCommandBuffer, out: *mut *mut MsFence)
+                                                  -> MsStatus {
+    let device = &mut *device;
+    if queue.is_null() || commands.is_null() || out.is_null() {
+        destroy(commands);
+        return device.invalid("null pointer");
+    }
+    let mut commands = std::mem::transmute::<*mut MsCommandBuffer, Box<MsCommandBuffer>>(commands);
+    commands.end_pass();
+    let MsCommandBuffer { passes, error, .. } = *commands;
+    if let Some(error) = error {
+        return device.invalid(error.as_slice());
+    }
+    let result = device.device.object().submit(&mut *queue, passes);
+    device.finish(result, out)
+}
+
+/// Wait for the fence's command buffer, and return its `MS_COMMAND_BUFFER_*` status.
+#[no_mangle]
+pub unsafe extern "C" fn ms_fence_wait(fence: *const MsFence) -> uint32_t {
+    command_buffer_status((*fence).wait())
+}
+
+/// Wait at most `timeout_ms` milliseconds for the fence's command buffer. Returns `1`, with its
+/// `MS_COMMAND_BUFFER_*` status in `status`, if it did complete, and `0` otherwise.
+#[no_mangle]
+pub unsafe extern "C" fn ms_fence_wait_timeout(fence: *const MsFence, timeout_ms: uint32_t, status: *mut uint32_t)
+                                               -> uint32_t {
+    match (*fence).wait_timeout(timeout_ms as uint) {
+        Some(result) => {
+            if !status.is_null() {
+                *status = command_buffer_status(result);
+            }
+            1
+        }
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ms_fence_destroy(fence: *mut MsFence) {
+    destroy(fence);
+}
@@ -0,0 +1,1231 @@
+//! Recording the commands of a frame, and printing them for bug reports and frame diffs.
+//!
+//! A `CommandRecorder` begins passes on a command buffer of any backend and hands back encoders
+//! which record every command, with its arguments, before passing it on. Resources and state
+//! objects appear by the label given with `CommandRecorder::label`, or by a name made up from
+//! their kind and the order they were first seen in (`buffer3`), which stays the same for as long
+//! as the recorder does. `take` returns what was recorded, and `Recording::dump` prints it:
+//!
+//! ```ignore
+//! recorder.label(&scene_pipeline, "scene");
+//! recorder.label(&vertices, "scene vertices");
+//!
+//! let mut encoder = recorder.begin_render_pass(&mut commands, pass_descriptor);
+//! encoder.set_render_pipeline(&scene_pipeline);
+//! encoder.set_vertex_buffer(BufferSlice::whole(&vertices), 0);
+//! encoder.draw(PrimitiveType::Triangle, 0, 36, 1, 0);
+//! encoder.end_encoding();
+//! println!("{}", recorder.take().dump());
+//! ```
+//!
+//! prints
+//!
+//! ```text
+//! begin_render_pass
+//!   color_attachment index=0 texture=texture0 level=0 slice=0 load=Clear store=Store clear=Float { red: 0, green: 0, blue: 0, alpha: 1 }
+//!   set_render_pipeline pipeline=scene
+//!   set_vertex_buffer slice=scene vertices[0..4096] index=0
+//!   draw primitive=Triangle first_vertex=0 vertex_count=36 instance_count=1 base_instance=0
+//! end_encoding
+//! ```
+//!
+//! `Recording::diff` compares two recordings, such as the same frame before and after a change,
+//! and reports the commands which were added, removed or given different arguments, for finding
+//! out why a frame suddenly renders differently or takes longer.
+//!
+//! `Recording::undefined_reads` checks a recording for commands which read contents that are
+//! undefined (see `Device::create_buffer` and `CommandBuffer::discard_contents`), which happen to
+//! work on drivers which zero new memory and break on the others.
+//!
+//! `Recording::aliasing_hazards` checks the uses of resources placed at overlapping ranges of a
+//! heap (see `Device::create_heap`), as told to the recorder with `place`: aliased resources
+//! used in the same pass, or one after another without a `CommandBuffer::aliasing_barrier`
+//! handing the memory over. Either corrupts both resources on some GPUs and not others, in ways
+//! which look nothing like their cause.
+//!
+//! Objects are told apart by address, so an object created where a dropped one was takes over
+//! its name. Label objects which replace others again as they are created.
+//!
+//! Made up names depend on the order objects are first used in, which can change from run to
+//! run. Captures which must refer to the same objects across runs (for replay, or rendering over
+//! the network) should assign IDs to objects as they are created, with `ids_mut` (see
+//! `resource_ids`): objects with an ID are recorded by it instead of by name, and every command
+//! has a `CommandId`. With the `serde` feature, recordings can be serialized.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+use {Device, BlitCommandEncoder, BufferSlice, ClearAttachment, CommandBuffer, ComputeCommandEncoder, RenderCommandEncoder};
+use {IndexFormat, Origin, PredicationOp, PrimitiveType, RenderPassDescriptor, ResourceRef, ScissorRect, Size};
+use {TriangleFillMode, Viewport, VisibilityResultMode};
+use resource_ids::{CommandId, ResourceId, ResourceIds};
+#[cfg(feature = "debug_state")]
+use {ComputeEncoderState, RenderEncoderState};
+#[cfg(feature = "xr")]
+use xr::XrRenderPass;
+
+/// How many bytes of `set_bytes` data `dump` prints before eliding the rest.
+const DUMPED_BYTES: uint = 64;
+
+/// The value of an argument of a recorded command.
+#[deriving(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", deriving(Serialize))]
+pub enum Argument {
+    Uint(uint),
+    Int(int),
+    Float(f64),
+    Bool(bool),
+    /// A resource or state object, by name.
+    Object(String),
+    /// A buffer slice: the buffer's name, the offset and the size.
+    Slice(String, uint, uint),
+    /// A resource or state object with an ID.
+    Resource(ResourceId),
+    /// A buffer slice of a buffer with an ID, the offset and the size.
+    ResourceSlice(ResourceId, uint, uint),
+    /// `set_bytes` data.
+    Bytes(Vec<u8>),
+    /// Anything else (enum values, sizes, rectangles), as printed.
+    Value(String),
+}
+
+impl fmt::Show for Argument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Argument::Uint(value) => write!(f, "{}", value),
+            Argument::Int(value) => write!(f, "{}", value),
+            Argument::Float(value) => write!(f, "{}", value),
+            Argument::Bool(value) => write!(f, "{}", value),
+            Argument::Object(ref name) => write!(f, "{}", name),
+            Argument::Slice(ref name, offset, size) => write!(f, "{}[{}..{}]", name, offset, offset + size),
+            Argument::Resource(ref id) => write!(f, "{}", id),
+            Argument::ResourceSlice(ref id, offset, size) => write!(f, "{}[{}..{}]", id, offset, offset + size),
+            Argument::Bytes(ref bytes) => {
+                try!(write!(f, "<{} bytes:", bytes.len()));
+                for byte in bytes.iter().take(DUMPED_BYTES) {
+                    try!(write!(f, " {:02x}", *byte));
+                }
+                if bytes.len() > DUMPED_BYTES {
+                    try!(write!(f, " ..."));
+                }
+                write!(f, ">")
+            }
+            Argument::Value(ref value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// A recorded command: the name of the method, and its arguments, by parameter name.
+#[deriving(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", deriving(Serialize))]
+pub struct RecordedCommand {
+    pub name: &'static str,
+    pub arguments: Vec<(&'static str, Argument)>,
+}
+
+impl RecordedCommand {
+    /// The argument named `name`, if the command has one.
+    pub fn argument(&self, name: &str) -> Option<&Argument> {
+        self.arguments.iter().find(|&&(argument, _)| argument == name).map(|&(_, ref value)| value)
+    }
+}
+
+impl fmt::Show for RecordedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}", self.name));
+        for &(name, ref value) in self.arguments.iter() {
+            try!(write!(f, " {}={}", name, value));
+        }
+        Ok(())
+    }
+}
+
+/// The commands recorded between two `CommandRecorder::take`s, in the order they were encoded.
+#[deriving(Clone)]
+#[cfg_attr(feature = "serde", deriving(Serialize))]
+pub struct Recording {
+    /// How many recordings the recorder returned before this one.
+    pub sequence: u64,
+    pub commands: Vec<RecordedCommand>,
+}
+
+impl Recording {
+    /// The ID of the `index`th command.
+    pub fn command_id(&self, index: uint) -> CommandId {
+        CommandId { recording: self.sequence, index: index as u32 }
+    }
+
+    /// The commands, one per line, indented within passes, predication and debug groups.
+    pub fn dump(&self) -> String {
+        let mut text = String::new();
+        let mut depth = 0u;
+        for command in self.commands.iter() {
+            match command.name {
+                "end_encoding" | "end_predication" | "pop_debug_group" => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            for _ in range(0, depth) {
+                text.push_str("  ");
+            }
+            text.push_str(format!("{}\n", command).as_slice());
+            match command.name {
+                "begin_render_pass" | "begin_compute_pass" | "begin_blit_pass" | "begin_predication" |
+                "push_debug_group" => depth += 1,
+                _ => {}
+            }
+        }
+        text
+    }
+
+    /// How many draw commands (of any kind) were recorded.
+    pub fn draw_count(&self) -> uint {
+        self.commands.iter().filter(|command| is_draw(command.name)).count()
+    }
+
+    /// Find the commands which read objects whose contents are undefined: the objects in
+    /// `undefined` (as recorded, with `Object` or `Resource`) until something writes them, and
+    /// every object from its `discard_contents` until something writes it again.
+    ///
+    /// `undefined` should hold the objects which are new when the recording starts, less those
+    /// written from the CPU with `write_buffer` or `write_texture`. Contents are tracked per
+    /// object, so writing part of one counts as writing all of it. Only unambiguous reads are
+    /// checked: buffers and textures bound to compute functions, and bind groups, may be written
+    /// as well as read, and count as writes. Attachments which are loaded count as reads, and
+    /// those which are not stored become undefined at the end of their pass.
+    pub fn undefined_reads(&self, undefined: &[Argument]) -> Vec<UndefinedRead> {
+        let mut undefined: Vec<Argument> = undefined.iter().filter_map(object_of).collect();
+        let mut reads = Vec::new();
+        // attachments which are not stored, until the end of their pass
+        let mut not_stored = Vec::new();
+        for (index, command) in self.commands.iter().enumerate() {
+            let argument = |name: &str| command.argument(name).and_then(object_of);
+            let (read, written): (Vec<&str>, Vec<&str>) = match command.name {
+                "color_attachment" | "depth_attachment" | "stencil_attachment" => {
+                    let loaded = command.argument("load") == Some(&Argument::Value("Load".to_string()));
+                    if command.argument("store") != Some(&Argument::Value("Store".to_string())) {
+                        not_stored.extend(argument("texture").into_iter());
+                    }
+                    if loaded { (vec!["texture"], Vec::new()) } else { (Vec::new(), vec!["texture"]) }
+                }
+                "set_vertex_buffer" | "set_index_buffer" => (vec!["slice"], Vec::new()),
+                "set_vertex_texture" | "set_fragment_texture" => (vec!["texture"], Vec::new()),
+                "draw_indirect" | "draw_indexed_indirect" | "dispatch_threadgroups_indirect" => (vec!["args"], Vec::new()),
+                "multi_draw_indexed_indirect_count" => (vec!["args", "count"], Vec::new()),
+                "begin_predication" | "draw_from_stream_output" => (vec!["buffer"], Vec::new()),
+                "use_resource" if command.argument("writable") == Some(&Argument::Bool(false)) => (vec!["resource"], Vec::new()),
+                "use_resource" => (Vec::new(), vec!["resource"]),
+                "set_buffer" | "set_stream_output_buffer" | "fill_buffer" => (Vec::new(), vec!["slice"]),
+                "set_texture" => (Vec::new(), vec!["texture"]),
+                "visibility_result_buffer" | "reset_counter" => (Vec::new(), vec!["buffer"]),
+                "copy_counter_value" | "copy_buffer" | "copy_texture" | "copy_buffer_to_texture" | "copy_texture_to_buffer" => {
+                    (vec!["src"], vec!["dst"])
+                }
+                "discard_contents" => {
+                    undefined.extend(argument("resource").into_iter());
+                    (Vec::new(), Vec::new())
+                }
+                "aliasing_barrier" => {
+                    undefined.extend(argument("after").into_iter());
+                    (Vec::new(), Vec::new())
+                }
+                "end_encoding" => {
+                    undefined.extend(not_stored.drain());
+                    (Vec::new(), Vec::new())
+                }
+                _ => (Vec::new(), Vec::new()),
+            };
+            for name in read.into_iter() {
+                if let Some(object) = argument(name) {
+                    if undefined.contains(&object) {
+                        reads.push(UndefinedRead { index: index, object: object });
+                    }
+                }
+            }
+            for name in written.into_iter() {
+                if let Some(object) = argument(name) {
+                    undefined.retain(|undefined| *undefined != object);
+                }
+            }
+        }
+        reads
+    }
+
+    /// Find the uses of aliased resources which are not separated as they must be: resources
+    /// whose `placements` overlap used in the same pass, and a resource used after another it
+    /// aliases with no `aliasing_barrier` to it in between. `placements` are usually the
+    /// recorder's (`CommandRecorder::placements`).
+    ///
+    /// The first use of a resource, before any resource aliasing it, needs no barrier. Every
+    /// recorded argument naming a placed resource counts as a use, whether it reads or writes.
+    pub fn aliasing_hazards(&self, placements: &[Placement]) -> Vec<AliasingHazard> {
+        let placement_of = |object: &Argument| placements.iter().find(|placement| placement.resource == *object);
+        let aliases = |a: &Argument, b: &Argument| match (placement_of(a), placement_of(b)) {
+            (Some(a), Some(b)) => a.resource != b.resource && a.overlaps(b),
+            _ => false,
+        };
+        let mut hazards = Vec::new();
+        // the resources which own their memory, as of the last barrier or use
+        let mut active: Vec<Argument> = Vec::new();
+        // the placed resources used in the current pass
+        let mut in_pass: Vec<Argument> = Vec::new();
+        for (index, command) in self.commands.iter().enumerate() {
+            match command.name {
+                "begin_render_pass" | "begin_compute_pass" | "begin_blit_pass" | "end_encoding" => {
+                    in_pass.clear();
+                    continue;
+                }
+                "aliasing_barrier" => {
+                    if let Some(after) = command.argument("after").and_then(object_of) {
+                        active.retain(|resource| !aliases(resource, &after));
+                        if !active.contains(&after) {
+                            active.push(after);
+                        }
+                    }
+                    continue;
+                }
+                // not a use of the memory, which may still belong to another resource
+                "discard_contents" => continue,
+                _ => {}
+            }
+            for &(_, ref argument) in command.arguments.iter() {
+                let object = match object_of(argument) {
+                    Some(ref object) if placement_of(object).is_some() => object.clone(),
+                    _ => continue,
+                };
+                if !in_pass.contains(&object) {
+                    for other in in_pass.iter().filter(|other| aliases(*other, &object)) {
+                        hazards.push(AliasingHazard {
+                            index: index,
+                            object: object.clone(),
+                            aliased: other.clone(),
+                            kind: AliasingHazardKind::SamePass,
+                        });
+                    }
+                    in_pass.push(object.clone());
+                }
+                if !active.contains(&object) {
+                    if let Some(other) = active.iter().find(|other| aliases(*other, &object)) {
+                        hazards.push(AliasingHazard {
+                            index: index,
+                            object: object.clone(),
+                            aliased: other.clone(),
+                            kind: AliasingHazardKind::MissingBarrier,
+                        });
+                    }
+                    active.retain(|resource| !aliases(resource, &object));
+                    active.push(object);
+                }
+            }
+        }
+        hazards
+    }
+
+    /// What changed from `self` to `after`: the commands only one of them has, and those whose
+    /// arguments changed, in command order.
+    ///
+    /// Commands are matched up as the longest common subsequence of the two recordings, and
+    /// unmatched commands of the same name between two matched ones are taken as changed rather
+    /// than removed and added. The subsequence is found with Myers' algorithm, in time linear in
+    /// the length of the recordings times the number of commands removed and added, so frames
+    /// which differ in only a few places diff quickly however long they are. Past
+    /// `MAX_EDIT_DISTANCE` removed and added commands, the part between the common start and end
+    /// of the recordings is taken as removed and added as a whole.
+    pub fn diff(&self, after: &Recording) -> Diff {
+        enter_span!("Recording::diff", before = self.commands.len(), after = after.commands.len());
+        let before = self.commands.as_slice();
+        let after_commands = after.commands.as_slice();
+        let mut start = 0;
+        while start < before.len() && start < after_commands.len() && before[start] == after_commands[start] {
+            start += 1;
+        }
+        let mut end = 0;
+        while end < before.len() - start && end < after_commands.len() - start &&
+              before[before.len() - 1 - end] == after_commands[after_commands.len() - 1 - end] {
+            end += 1;
+        }
+        let old = before.slice(start, before.len() - end);
+        let new = after_commands.slice(start, after_commands.len() - end);
+
+        let mut changes = Vec::new();
+        let mut removed = Vec::new();
+        let mut added = Vec::new();
+        for edit in edit_script(old, new).into_iter() {
+            match edit {
+                Edit::Keep => pair_up(old, new, start, &mut removed, &mut added, &mut changes),
+                Edit::Remove(i) => removed.push(i),
+                Edit::Add(j) => added.push(j),
+            }
+        }
+        pair_up(old, new, start, &mut removed, &mut added, &mut changes);
+        Diff { changes: changes, draws: (self.draw_count(), after.draw_count()) }
+    }
+}
+
+/// The most commands `Recording::diff` removes and adds while matching commands up. See `diff`.
+pub const MAX_EDIT_DISTANCE: uint = 1024;
+
+/// A step of an edit script from `old` to `new`: keep the next command of both, or remove the
+/// command of `old` or add the command of `new` at an index.
+enum Edit {
+    Keep,
+    Remove(uint),
+    Add(uint),
+}
+
+/// The shortest edit script from `old` to `new` (Myers, "An O(ND) difference algorithm and its
+/// variations"), removing before adding, or, if it is longer than `MAX_EDIT_DISTANCE`, the
+/// script removing all of `old` and adding all of `new`.
+///
+/// Takes time O((N + M) D) and memory O(D²) for an edit distance of D.
+fn edit_script(old: &[RecordedCommand], new: &[RecordedCommand]) -> Vec<Edit> {
+    let (n, m) = (old.len() as int, new.len() as int);
+    let max = n + m;
+    // v[max + k] is the furthest x reached so far on the diagonal k = x - y
+    let mut v = Vec::from_elem(2 * max as uint + 2, 0i);
+    // the diagonals -d to d of `v` at the start of each round d, to walk back through
+    let mut trace: Vec<Vec<int>> = Vec::new();
+    let mut done = false;
+    for d in range(0, std::cmp::min(max, MAX_EDIT_DISTANCE as int) + 1) {
+        trace.push(v.slice((max - d) as uint, (max + d + 1) as uint).to_vec());
+        for k in std::iter::range_step_inclusive(-d, d, 2) {
+            // step down (an addition) from diagonal k + 1, or right (a removal) from k - 1,
+            // whichever got further
+            let mut x = if k == -d || (k != d && v[(max + k - 1) as uint] < v[(max + k + 1) as uint]) {
+                v[(max + k + 1) as uint]
+            } else {
+                v[(max + k - 1) as uint] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as uint] == new[y as uint] {
+                x += 1;
+                y += 1;
+            }
+            v[(max + k) as uint] = x;
+            if x >= n && y >= m {
+                done = true;
+                break;
+            }
+        }
+        if done {
+            break;
+        }
+    }
+    if !done {
+        let mut edits: Vec<Edit> = range(0, old.len()).map(Edit::Remove).collect();
+        edits.extend(range(0, new.len()).map(Edit::Add));
+        return edits;
+    }
+
+    // walk back from the end, one removal or addition per round, keeping the diagonal runs
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in range(1, trace.len() as int).rev() {
+        let snapshot = &trace[d as uint];
+        let at = |k: int| snapshot[(k + d) as uint];
+        let k = x - y;
+        let previous = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let (previous_x, previous_y) = (at(previous), at(previous) - previous);
+        while x > previous_x && y > previous_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        edits.push(if x == previous_x { Edit::Add((y - 1) as uint) } else { Edit::Remove((x - 1) as uint) });
+        x = previous_x;
+        y = previous_y;
+    }
+    // round 0 only follows the diagonal from the start
+    for _ in range(0, x) {
+        edits.push(Edit::Keep);
+    }
+    edits.reverse();
+    edits
+}
+
+fn is_draw(name: &str) -> bool {
+    name.starts_with("draw") || name.starts_with("multi_draw")
+}
+
+/// Turn a run of `removed` commands of `old` and `added` commands of `new` into changes, taking
+/// each removed command and the next added one of the same name as one changed command.
+/// `start` is where `old` and `new` start in their recordings.
+fn pair_up(old: &[RecordedCommand], new: &[RecordedCommand], start: uint,
+           removed: &mut Vec<uint>, added: &mut Vec<uint>, changes: &mut Vec<Change>) {
+    let mut next_added = 0;
+    for &i in removed.iter() {
+        match added.slice_from(next_added).iter().position(|&j| new[j].name == old[i].name) {
+            Some(position) => {
+                for &j in added.slice(next_added, next_added + position).iter() {
+                    changes.push(Change::Added { index: start + j, command: new[j].clone() });
+                }
+                let j = added[next_added + position];
+                changes.push(Change::Changed {
+                    before: start + i,
+                    after: start + j,
+                    name: old[i].name,
+                    arguments: changed_arguments(&old[i], &new[j]),
+                });
+                next_added += position + 1;
+            }
+            None => changes.push(Change::Removed { index: start + i, command: old[i].clone() }),
+        }
+    }
+    for &j in added.slice_from(next_added).iter() {
+        changes.push(Change::Added { index: start + j, command: new[j].clone() });
+    }
+    removed.clear();
+    added.clear();
+}
+
+/// The arguments which differ between two commands of the same name, with their values before
+/// and after. An argument only one of them has is shown as `Value("-")` in the other.
+fn changed_arguments(before: &RecordedCommand, after: &RecordedCommand) -> Vec<(&'static str, Argument, Argument)> {
+    let missing = Argument::Value("-".to_string());
+    let mut arguments = Vec::new();
+    for &(name, ref value) in before.arguments.iter() {
+        match after.argument(name) {
+            Some(new) if new == value => {}
+            Some(new) => arguments.push((name, value.clone(), new.clone())),
+            None => arguments.push((name, value.clone(), missing.clone())),
+        }
+    }
+    for &(name, ref value) in after.arguments.iter() {
+        if before.argument(name).is_none() {
+            arguments.push((name, missing.clone(), value.clone()));
+        }
+    }
+    arguments
+}
+
+/// A difference between two recordings. Indices are of commands in their recording.
+#[deriving(Clone, PartialEq)]
+pub enum Change {
+    /// A command only the later recording has.
+    Added { index: uint, command: RecordedCommand },
+    /// A command only the earlier recording has.
+    Removed { index: uint, command: RecordedCommand },
+    /// A command whose arguments changed: each changed argument, before and after.
+    Changed { before: uint, after: uint, name: &'static str, arguments: Vec<(&'static str, Argument, Argument)> },
+}
+
+impl fmt::Show for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Added { index, ref command } => write!(f, "+ {}: {}", index, command),
+            Change::Removed { index, ref command } => write!(f, "- {}: {}", index, command),
+            Change::Changed { before, after, name, ref arguments } => {
+                try!(write!(f, "~ {} -> {}: {}", before, after, name));
+                for &(argument, ref old, ref new) in arguments.iter() {
+                    try!(write!(f, " {}={} -> {}", argument, old, new));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A read of undefined contents, found by `Recording::undefined_reads`.
+#[deriving(Clone, Show)]
+pub struct UndefinedRead {
+    /// The index of the reading command.
+    pub index: uint,
+    /// The object read, as an `Object` or a `Resource`.
+    pub object: Argument,
+}
+
+/// Where a resource is placed in a heap. See `CommandRecorder::place`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Placement {
+    /// The resource and its heap, as recorded.
+    pub resource: Argument,
+    pub heap: Argument,
+    /// The range of the heap the resource covers, in bytes.
+    pub offset: uint,
+    pub size: uint,
+}
+
+impl Placement {
+    /// Whether `self` and `other` share memory.
+    pub fn overlaps(&self, other: &Placement) -> bool {
+        self.heap == other.heap && self.offset < other.offset + other.size && other.offset < self.offset + self.size
+    }
+}
+
+/// How aliased resources were used wrongly. See `Recording::aliasing_hazards`.
+#[deriving(Clone, PartialEq, Show)]
+pub enum AliasingHazardKind {
+    /// Both were used in one pass.
+    SamePass,
+    /// One was used after the other without an `aliasing_barrier` to it.
+    MissingBarrier,
+}
+
+/// A use of a resource aliasing another, found by `Recording::aliasing_hazards`.
+#[deriving(Clone, Show)]
+pub struct AliasingHazard {
+    /// The index of the using command.
+    pub index: uint,
+    /// The resource used, and the resource it aliases, as `Object`s or `Resource`s.
+    pub object: Argument,
+    pub aliased: Argument,
+    pub kind: AliasingHazardKind,
+}
+
+/// The object an argument refers to, without the range of a slice.
+fn object_of(argument: &Argument) -> Option<Argument> {
+    match *argument {
+        Argument::Object(_) | Argument::Resource(_) => Some(argument.clone()),
+        Argument::Slice(ref name, _, _) => Some(Argument::Object(name.clone())),
+        Argument::ResourceSlice(ref id, _, _) => Some(Argument::Resource(id.clone())),
+        _ => None,
+    }
+}
+
+/// What changed between two recorded frames. Returned by `Recording::diff`.
+#[deriving(Clone)]
+pub struct Diff {
+    pub changes: Vec<Change>,
+    /// The number of draws in the earlier and the later recording.
+    pub draws: (uint, uint),
+}
+
+impl Diff {
+    /// Whether the recordings are the same.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The changes, one per line, after a line comparing the number of draws:
+    ///
+    /// ```text
+    /// draws: 412 -> 415
+    /// ~ 37 -> 37: set_render_pipeline pipeline=scene -> scene_transparent
+    /// + 120: draw_indexed primitive=Triangle first_index=0 index_count=3072 ...
+    /// ```
+    pub fn dump(&self) -> String {
+        let (before, after) = self.draws;
+        let mut text = format!("draws: {} -> {}\n", before, after);
+        for change in self.changes.iter() {
+            text.push_str(format!("{}\n", change).as_slice());
+        }
+        text
+    }
+}
+
+fn value<T: fmt::Show>(value: T) -> Argument {
+    Argument::Value(format!("{}", value))
+}
+
+fn size(size: &Size) -> Argument {
+    Argument::Value(format!("{}x{}x{}", size.width, size.height, size.depth))
+}
+
+fn origin(origin: &Origin) -> Argument {
+    Argument::Value(format!("({}, {}, {})", origin.x, origin.y, origin.z))
+}
+
+/// Names objects, and records the commands of the encoders it begins. See the module
+/// documentation.
+pub struct CommandRecorder {
+    /// The name of every object seen, by address.
+    names: HashMap<uint, String>,
+    /// How many unlabeled objects of each kind have been named.
+    counts: HashMap<&'static str, uint>,
+    ids: ResourceIds,
+    /// What `place` was told, by address of the resource.
+    placements: Vec<(uint, Placement)>,
+    commands: Vec<RecordedCommand>,
+    sequence: u64,
+}
+
+impl CommandRecorder {
+    pub fn new() -> CommandRecorder {
+        CommandRecorder {
+            names: HashMap::new(),
+            counts: HashMap::new(),
+            ids: ResourceIds::new(),
+            placements: Vec::new(),
+            commands: Vec::new(),
+            sequence: 0,
+        }
+    }
+
+    /// The IDs objects are recorded by.
+    pub fn ids(&self) -> &ResourceIds {
+        &self.ids
+    }
+
+    /// The IDs objects are recorded by, to assign more.
+    pub fn ids_mut(&mut self) -> &mut ResourceIds {
+        &mut self.ids
+    }
+
+    /// Name `object` (a resource, pipeline, bind group or other state object) `label` in
+    /// recordings.
+    pub fn label<T>(&mut self, object: &T, label: &str) {
+        self.names.insert(object as *const T as uint, label.to_string());
+    }
+
+    /// Note that `resource` is placed `offset` bytes into `heap`, covering `size` bytes (as
+    /// `Device::buffer_placement_requirements` or `texture_placement_requirements` reported),
+    /// for `Recording::aliasing_hazards`. Call this where the resource is created, after
+    /// labeling it or assigning it an ID.
+    pub fn place<D: Device>(&mut self, resource: ResourceRef<D>, heap: &D::Heap, offset: uint, size: uint) {
+        let address = match resource {
+            ResourceRef::Buffer(buffer) => buffer as *const D::Buffer as uint,
+            ResourceRef::Texture(texture) => texture as *const D::Texture as uint,
+        };
+        let placement = Placement {
+            resource: self.resource(&resource),
+            heap: self.object("heap", heap),
+            offset: offset,
+            size: size,
+        };
+        self.placements.retain(|&(placed, _)| placed != address);
+        self.placements.push((address, placement));
+    }
+
+    /// Forget the placement of `resource`, which is about to be dropped.
+    pub fn unplace<D: Device>(&mut self, resource: ResourceRef<D>) {
+        let address = match resource {
+            ResourceRef::Buffer(buffer) => buffer as *const D::Buffer as uint,
+            ResourceRef::Texture(texture) => texture as *const D::Texture as uint,
+        };
+        self.placements.retain(|&(placed, _)| placed != address);
+    }
+
+    /// Where the resources `place` was told about are placed.
+    pub fn placements(&self) -> Vec<Placement> {
+        self.placements.iter().map(|&(_, ref placement)| placement.clone()).collect()
+    }
+
+    /// The commands recorded since the last `take`.
+    pub fn take(&mut self) -> Recording {
+        let sequence = self.sequence;
+        self.sequence += 1;
+        Recording { sequence: sequence, commands: std::mem::replace(&mut self.commands, Vec::new()) }
+    }
+
+    fn name<T>(&mut self, kind: &'static str, object: &T) -> String {
+        let address = object as *const T as uint;
+        if let Some(name) = self.names.get(&address) {
+            return name.clone();
+        }
+        let count = self.counts.get(&kind).map(|&count| count).unwrap_or(0);
+        self.counts.insert(kind, count + 1);
+        let name = format!("{}{}", kind, count);
+        self.names.insert(address, name.clone());
+        name
+    }
+
+    fn object<T>(&mut self, kind: &'static str, object: &T) -> Argument {
+        match self.ids.get(object) {
+            Some(id) => Argument::Resource(id),
+            None => Argument::Object(self.name(kind, object)),
+        }
+    }
+
+    fn slice<B>(&mut self, slice: &BufferSlice<B>) -> Argument {
+        match self.ids.get(slice.buffer()) {
+            Some(id) => Argument::ResourceSlice(id, slice.offset(), slice.size()),
+            None => Argument::Slice(self.name("buffer", slice.buffer()), slice.offset(), slice.size()),
+        }
+    }
+
+    fn resource<D: Device>(&mut self, resource: &ResourceRef<D>) -> Argument {
+        match *resource {
+            ResourceRef::Buffer(buffer) => self.object("buffer", buffer),
+            ResourceRef::Texture(texture) => self.object("texture", texture),
+        }
+    }
+
+    fn record(&mut self, name: &'static str, arguments: Vec<(&'static str, Argument)>) {
+        self.commands.push(RecordedCommand { name: name, arguments: arguments });
+    }
+
+    /// Begin a render pass on `commands`, recording its attachments and returning an encoder
+    /// which records its commands.
+    pub fn begin_render_pass<'r, D: Device, C: CommandBuffer<D>>(&'r mut self, commands: &mut C, desc: RenderPassDescriptor<D>)
+                                                                 -> RecordingRenderEncoder<'r, D, D::RenderCommandEncoder> {
+        self.record("begin_render_pass", Vec::new());
+        self.record_attachments(&desc);
+        let encoder = commands.render_command_encoder(desc);
+        RecordingRenderEncoder { encoder: encoder, recorder: self, marker: PhantomData }
+    }
+
+    /// Begin an XR render pass on `commands`, as `begin_render_pass` does, also recording its
+    /// view count and whether it is foveated.
+    #[cfg(feature = "xr")]
+    pub fn begin_xr_render_pass<'r, D: Device, C: CommandBuffer<D>>(&'r mut self, commands: &mut C, desc: RenderPassDescriptor<D>,
+                                                                    xr: XrRenderPass<D>)
+                                                                    -> RecordingRenderEncoder<'r, D, D::RenderCommandEncoder> {
+        self.record("begin_render_pass", Vec::new());
+        self.record_attachments(&desc);
+        self.record("xr_views", vec![("view_count", Argument::Uint(xr.view_count)),
+                                     ("foveated", Argument::Bool(xr.rasterization_rate_map.is_some()))]);
+        let encoder = commands.xr_render_command_encoder(desc, xr);
+        RecordingRenderEncoder { encoder: encoder, recorder: self, marker: PhantomData }
+    }
+
+    fn record_attachments<D: Device>(&mut self, desc: &RenderPassDescriptor<D>) {
+        for (index, attachment) in desc.color_attachments.iter().enumerate() {
+            let texture = self.object("texture", attachment.texture);
+            self.record("color_attachment", vec![("index", Argument::Uint(index)), ("texture", texture),
+                                                 ("level", Argument::Uint(attachment.mipmap_level)),
+                                                 ("slice", Argument::Uint(attachment.slice)),
+                                                 ("load", value(&attachment.load_action)),
+                                                 ("store", value(&attachment.store_action)),
+                                                 ("clear", value(&attachment.clear_color))]);
+        }
+        if let Some(ref attachment) = desc.depth_attachment {
+            let texture = self.object("texture", attachment.texture);
+            self.record("depth_attachment", vec![("texture", texture),
+                                                 ("level", Argument::Uint(attachment.mipmap_level)),
+                                                 ("slice", Argument::Uint(attachment.slice)),
+                                                 ("load", value(&attachment.load_action)),
+                                                 ("store", value(&attachment.store_action)),
+                                                 ("clear", Argument::Float(attachment.clear_depth))]);
+        }
+        if let Some(ref attachment) = desc.stencil_attachment {
+            let texture = self.object("texture", attachment.texture);
+            self.record("stencil_attachment", vec![("texture", texture),
+                                                   ("level", Argument::Uint(attachment.mipmap_level)),
+                                                   ("slice", Argument::Uint(attachment.slice)),
+                                                   ("load", value(&attachment.load_action)),
+                                                   ("store", value(&attachment.store_action)),
+                                                   ("clear", Argument::Uint(attachment.clear_stencil as uint))]);
+        }
+        if let Some(buffer) = desc.visibility_result_buffer {
+            let buffer = self.object("buffer", buffer);
+            self.record("visibility_result_buffer", vec![("buffer", buffer)]);
+        }
+    }
+
+    /// Begin a compute pass on `commands`, returning an encoder which records its commands.
+    pub fn begin_compute_pass<'r, D: Device, C: CommandBuffer<D>>(&'r mut self, commands: &mut C)
+                                                                  -> RecordingComputeEncoder<'r, D, D::ComputeCommandEncoder> {
+        self.record("begin_compute_pass", Vec::new());
+        let encoder = commands.compute_command_encoder();
+        RecordingComputeEncoder { encoder: encoder, recorder: self, marker: PhantomData }
+    }
+
+    /// Begin a blit pass on `commands`, returning an encoder which records its commands.
+    pub fn begin_blit_pass<'r, D: Device, C: CommandBuffer<D>>(&'r mut self, commands: &mut C)
+                                                               -> RecordingBlitEncoder<'r, D, D::BlitCommandEncoder> {
+        self.record("begin_blit_pass", Vec::new());
+        let encoder = commands.blit_command_encoder();
+        RecordingBlitEncoder { encoder: encoder, recorder: self, marker: PhantomData }
+    }
+
+    /// `CommandBuffer::push_debug_group`, recorded.
+    pub fn push_debug_group<D: Device, C: CommandBuffer<D>>(&mut self, commands: &mut C, label: &str) {
+        self.record("push_debug_group", vec![("label", Argument::Value(label.to_string()))]);
+        commands.push_debug_group(label);
+    }
+
+    /// `CommandBuffer::pop_debug_group`, recorded.
+    pub fn pop_debug_group<D: Device, C: CommandBuffer<D>>(&mut self, commands: &mut C) {
+        self.record("pop_debug_group", Vec::new());
+        commands.pop_debug_group();
+    }
+
+    /// `CommandBuffer::encode_signal_event`, recorded.
+    pub fn encode_signal_event<D: Device, C: CommandBuffer<D>>(&mut self, commands: &mut C, event: &D::Event, value: u64) {
+        let name = self.object("event", event);
+        self.record("encode_signal_event", vec![("event", name), ("value", Argument::Uint(value as uint))]);
+        commands.encode_signal_event(event, value);
+    }
+
+    /// `CommandBuffer::discard_contents`, recorded.
+    pub fn discard_contents<D: Device, C: CommandBuffer<D>>(&mut self, commands: &mut C, resource: ResourceRef<D>) {
+        let name = self.resource(&resource);
+        self.record("discard_contents", vec![("resource", name)]);
+        commands.discard_contents(resource);
+    }
+
+    /// `CommandBuffer::aliasing_barrier`, recorded.
+    pub fn aliasing_barrier<D: Device, C: CommandBuffer<D>>(&mut self, commands: &mut C, before: Option<ResourceRef<D>>,
+                                                           after: ResourceRef<D>) {
+        let before_name = match before {
+            Some(ref before) => self.resource(before),
+            None => Argument::Value("None".to_string()),
+        };
+        let after_name = self.resource(&after);
+        self.record("aliasing_barrier", vec![("before", before_name), ("after", after_name)]);
+        commands.aliasing_barrier(before, after);
+    }
+
+    /// `CommandBuffer::encode_wait_for_event`, recorded.
+    pub fn encode_wait_for_event<D: Device, C: CommandBuffer<D>>(&mut self, commands: &mut C, event: &D::Event, value: u64) {
+        let name = self.object("event", event);
+        self.record("encode_wait_for_event", vec![("event", name), ("value", Argument::Uint(value as uint))]);
+        commands.encode_wait_for_event(event, value);
+    }
+}
+
+/// A render command encoder which records its commands. Returned by
+/// `CommandRecorder::begin_render_pass`.
+pub struct RecordingRenderEncoder<'r, D: Device, E> {
+    encoder: E,
+    recorder: &'r mut CommandRecorder,
+    marker: PhantomData<D>,
+}
+
+impl<'r, D: Device, E: RenderCommandEncoder<D>> RenderCommandEncoder<D> for RecordingRenderEncoder<'r, D, E> {
+    fn set_render_pipeline(&mut self, pipeline: &D::RenderPipeline) {
+        let name = self.recorder.object("render_pipeline", pipeline);
+        self.recorder.record("set_render_pipeline", vec![("pipeline", name)]);
+        self.encoder.set_render_pipeline(pipeline);
+    }
+
+    fn set_depth_stencil_state(&mut self, state: &D::DepthStencilState) {
+        let name = self.recorder.object("depth_stencil_state", state);
+        self.recorder.record("set_depth_stencil_state", vec![("state", name)]);
+        self.encoder.set_depth_stencil_state(state);
+    }
+
+    fn set_stencil_reference_value(&mut self, value: u32) {
+        self.recorder.record("set_stencil_reference_value", vec![("value", Argument::Uint(value as uint))]);
+        self.encoder.set_stencil_reference_value(value);
+    }
+
+    fn set_viewport(&mut self, viewport: Viewport) {
+        self.recorder.record("set_viewport", vec![("x", Argument::Float(viewport.x)), ("y", Argument::Float(viewport.y)),
+                                                  ("width", Argument::Float(viewport.width)),
+                                                  ("height", Argument::Float(viewport.height)),
+                                                  ("znear", Argument::Float(viewport.znear)),
+                                                  ("zfar", Argument::Float(viewport.zfar))]);
+        self.encoder.set_viewport(viewport);
+    }
+
+    fn set_triangle_fill_mode(&mut self, mode: TriangleFillMode) {
+        self.recorder.record("set_triangle_fill_mode", vec![("mode", value(&mode))]);
+        self.encoder.set_triangle_fill_mode(mode);
+    }
+
+    fn set_depth_bias(&mut self, bias: f32, slope_scale: f32, clamp: f32) {
+        self.recorder.record("set_depth_bias", vec![("bias", Argument::Float(bias as f64)),
+                                                    ("slope_scale", Argument::Float(slope_scale as f64)),
+                                                    ("clamp", Argument::Float(clamp as f64))]);
+        self.encoder.set_depth_bias(bias, slope_scale, clamp);
+    }
+
+    fn begin_predication(&mut self, buffer: &D::Buffer, offset: uint, op: PredicationOp) {
+        let name = self.recorder.object("buffer", buffer);
+        self.recorder.record("begin_predication", vec![("buffer", name), ("offset", Argument::Uint(offset)), ("op", value(&op))]);
+        self.encoder.begin_predication(buffer, offset, op);
+    }
+
+    fn end_predication(&mut self) {
+        self.recorder.record("end_predication", Vec::new());
+        self.encoder.end_predication();
+    }
+
+    fn set_vertex_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint) {
+        let argument = self.recorder.slice(&slice);
+        self.recorder.record("set_vertex_buffer", vec![("slice", argument), ("index", Argument::Uint(index))]);
+        self.encoder.set_vertex_buffer(slice, index);
+    }
+
+    fn set_vertex_bytes(&mut self, bytes: &[u8], index: uint) {
+        self.recorder.record("set_vertex_bytes", vec![("bytes", Argument::Bytes(bytes.to_vec())), ("index", Argument::Uint(index))]);
+        self.encoder.set_vertex_bytes(bytes, index);
+    }
+
+    fn set_vertex_texture(&mut self, texture: &D::Texture, index: uint) {
+        let name = self.recorder.object("texture", texture);
+        self.recorder.record("set_vertex_texture", vec![("texture", name), ("index", Argument::Uint(index))]);
+        self.encoder.set_vertex_texture(texture, index);
+    }
+
+    fn set_vertex_sampler(&mut self, sampler: &D::Sampler, index: uint) {
+        let name = self.recorder.object("sampler", sampler);
+        self.recorder.record("set_vertex_sampler", vec![("sampler", name), ("index", Argument::Uint(index))]);
+        self.encoder.set_vertex_sampler(sampler, index);
+    }
+
+    fn set_fragment_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint) {
+        let argument = self.recorder.slice(&slice);
+        self.recorder.record("set_fragment_buffer", vec![("slice", argument), ("index", Argument::Uint(index))]);
+        self.encoder.set_fragment_buffer(slice, index);
+    }
+
+    fn set_fragment_bytes(&mut self, bytes: &[u8], index: uint) {
+        self.recorder.record("set_fragment_bytes", vec![("bytes", Argument::Bytes(bytes.to_vec())), ("index", Argument::Uint(index))]);
+        self.encoder.set_fragment_bytes(bytes, index);
+    }
+
+    fn set_fragment_texture(&mut self, texture: &D::Texture, index: uint) {
+        let name = self.recorder.object("texture", texture);
+        self.recorder.record("set_fragment_texture", vec![("texture", name), ("index", Argument::Uint(index))]);
+        self.encoder.set_fragment_texture(texture, index);
+    }
+
+    fn set_fragment_sampler(&mut self, sampler: &D::Sampler, index: uint) {
+        let name = self.recorder.object("sampler", sampler);
+        self.recorder.record("set_fragment_sampler", vec![("sampler", name), ("index", Argument::Uint(index))]);
+        self.encoder.set_fragment_sampler(sampler, index);
+    }
+
+    fn set_scissor_rect(&mut self, rect: ScissorRect) {
+        self.recorder.record("set_scissor_rect", vec![("x", Argument::Uint(rect.x)), ("y", Argument::Uint(rect.y)),
+                                                      ("width", Argument::Uint(rect.width)),
+                                                      ("height", Argument::Uint(rect.height))]);
+        self.encoder.set_scissor_rect(rect);
+    }
+
+    fn clear_rects(&mut self, attachments: &[ClearAttachment], rects: &[ScissorRect]) {
+        self.recorder.record("clear_rects", vec![("attachments", value(attachments)), ("rects", value(rects))]);
+        self.encoder.clear_rects(attachments, rects);
+    }
+
+    fn set_visibility_result_mode(&mut self, mode: VisibilityResultMode, offset: uint) {
+        self.recorder.record("set_visibility_result_mode", vec![("mode", value(&mode)), ("offset", Argument::Uint(offset))]);
+        self.encoder.set_visibility_result_mode(mode, offset);
+    }
+
+    fn set_bind_group(&mut self, group: &D::BindGroup, index: uint) {
+        let name = self.recorder.object("bind_group", group);
+        self.recorder.record("set_bind_group", vec![("group", name), ("index", Argument::Uint(index))]);
+        self.encoder.set_bind_group(group, index);
+    }
+
+    fn use_resource(&mut self, resource: ResourceRef<D>, writable: bool) {
+        let name = self.recorder.resource(&resource);
+        self.recorder.record("use_resource", vec![("resource", name), ("writable", Argument::Bool(writable))]);
+        self.encoder.use_resource(resource, writable);
+    }
+
+    fn set_index_buffer(&mut self, slice: BufferSlice<D::Buffer>, format: IndexFormat) {
+        let argument = self.recorder.slice(&slice);
+        self.recorder.record("set_index_buffer", vec![("slice", argument), ("format", value(&format))]);
+        self.encoder.set_index_buffer(slice, format);
+    }
+
+    fn draw(&mut self, primitive: PrimitiveType, first_vertex: uint, vertex_count: uint,
+            instance_count: uint, base_instance: uint) {
+        self.recorder.record("draw", vec![("primitive", value(&primitive)), ("first_vertex", Argument::Uint(first_vertex)),
+                                          ("vertex_count", Argument::Uint(vertex_count)),
+                                          ("instance_count", Argument::Uint(instance_count)),
+                                          ("base_instance", Argument::Uint(base_instance))]);
+        self.encoder.draw(primitive, first_vertex, vertex_count, instance_count, base_instance);
+    }
+
+    fn draw_indexed(&mut self, primitive: PrimitiveType, first_index: uint, index_count: uint,
+                    instance_count: uint, base_vertex: int, base_instance: uint) {
+        self.recorder.record("draw_indexed", vec![("primitive", value(&primitive)), ("first_index", Argument::Uint(first_index)),
+                                                  ("index_count", Argument::Uint(index_count)),
+                                                  ("instance_count", Argument::Uint(instance_count)),
+                                                  ("base_vertex", Argument::Int(base_vertex)),
+                                                  ("base_instance", Argument::Uint(base_instance))]);
+        self.encoder.draw_indexed(primitive, first_index, index_count, instance_count, base_vertex, base_instance);
+    }
+
+    fn set_stream_output_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint) {
+        let argument = self.recorder.slice(&slice);
+        self.recorder.record("set_stream_output_buffer", vec![("slice", argument), ("index", Argument::Uint(index))]);
+        self.encoder.set_stream_output_buffer(slice, index);
+    }
+
+    fn draw_from_stream_output(&mut self, primitive: PrimitiveType, buffer: &D::Buffer, stride: uint,
+                               instance_count: uint, base_instance: uint) {
+        let name = self.recorder.object("buffer", buffer);
+        self.recorder.record("draw_from_stream_output", vec![("primitive", value(&primitive)), ("buffer", name),
+                                                             ("stride", Argument::Uint(stride)),
+                                                             ("instance_count", Argument::Uint(instance_count)),
+                                                             ("base_instance", Argument::Uint(base_instance))]);
+        self.encoder.draw_from_stream_output(primitive, buffer, stride, instance_count, base_instance);
+    }
+
+    fn draw_indirect(&mut self, primitive: PrimitiveType, args: &D::Buffer, offset: uint) {
+        let name = self.recorder.object("buffer", args);
+        self.recorder.record("draw_indirect", vec![("primitive", value(&primitive)), ("args", name),
+                                                   ("offset", Argument::Uint(offset))]);
+        self.encoder.draw_indirect(primitive, args, offset);
+    }
+
+    fn draw_indexed_indirect(&mut self, primitive: PrimitiveType, args: &D::Buffer, offset: uint) {
+        let name = self.recorder.object("buffer", args);
+        self.recorder.record("draw_indexed_indirect", vec![("primitive", value(&primitive)), ("args", name),
+                                                           ("offset", Argument::Uint(offset))]);
+        self.encoder.draw_indexed_indirect(primitive, args, offset);
+    }
+
+    fn multi_draw_indexed_indirect_count(&mut self, primitive: PrimitiveType,
+                                         args: &D::Buffer, args_offset: uint,
+                                         count: &D::Buffer, count_offset: uint, max_draws: uint) {
+        let args_name = self.recorder.object("buffer", args);
+        let count_name = self.recorder.object("buffer", count);
+        self.recorder.record("multi_draw_indexed_indirect_count", vec![("primitive", value(&primitive)), ("args", args_name),
+                                                                       ("args_offset", Argument::Uint(args_offset)),
+                                                                       ("count", count_name),
+                                                                       ("count_offset", Argument::Uint(count_offset)),
+                                                                       ("max_draws", Argument::Uint(max_draws))]);
+        self.encoder.multi_draw_indexed_indirect_count(primitive, args, args_offset, count, count_offset, max_draws);
+    }
+
+    #[cfg(feature = "debug_state")]
+    fn debug_state(&self) -> RenderEncoderState<D> {
+        self.encoder.debug_state()
+    }
+
+    fn end_encoding(self) {
+        self.recorder.record("end_encoding", Vec::new());
+        self.encoder.end_encoding();
+    }
+}
+
+/// A compute command encoder which records its commands. Returned by
+/// `CommandRecorder::begin_compute_pass`.
+pub struct RecordingComputeEncoder<'r, D: Device, E> {
+    encoder: E,
+    recorder: &'r mut CommandRecorder,
+    marker: PhantomData<D>,
+}
+
+impl<'r, D: Device, E: ComputeCommandEncoder<D>> ComputeCommandEncoder<D> for RecordingComputeEncoder<'r, D, E> {
+    fn set_compute_pipeline(&mut self, pipeline: &D::ComputePipeline) {
+        let name = self.recorder.object("compute_pipeline", pipeline);
+        self.recorder.record("set_compute_pipeline", vec![("pipeline", name)]);
+        self.encoder.set_compute_pipeline(pipeline);
+    }
+
+    fn set_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint) {
+        let argument = self.recorder.slice(&slice);
+        self.recorder.record("set_buffer", vec![("slice", argument), ("index", Argument::Uint(index))]);
+        self.encoder.set_buffer(slice, index);
+    }
+
+    fn set_bytes(&mut self, bytes: &[u8], index: uint) {
+        self.recorder.record("set_bytes", vec![("bytes", Argument::Bytes(bytes.to_vec())), ("index", Argument::Uint(index))]);
+        self.encoder.set_bytes(bytes, index);
+    }
+
+    fn set_texture(&mut self, texture: &D::Texture, index: uint) {
+        let name = self.recorder.object("texture", texture);
+        self.recorder.record("set_texture", vec![("texture", name), ("index", Argument::Uint(index))]);
+        self.encoder.set_texture(texture, index);
+    }
+
+    fn set_sampler(&mut self, sampler: &D::Sampler, index: uint) {
+        let name = self.recorder.object("sampler", sampler);
+        self.recorder.record("set_sampler", vec![("sampler", name), ("index", Argument::Uint(index))]);
+        self.encoder.set_sampler(sampler, index);
+    }
+
+    fn set_threadgroup_memory_length(&mut self, length: uint, index: uint) {
+        self.recorder.record("set_threadgroup_memory_length", vec![("length", Argument::Uint(length)), ("index", Argument::Uint(index))]);
+        self.encoder.set_threadgroup_memory_length(length, index);
+    }
+
+    fn set_bind_group(&mut self, group: &D::BindGroup, index: uint) {
+        let name = self.recorder.object("bind_group", group);
+        self.recorder.record("set_bind_group", vec![("group", name), ("index", Argument::Uint(index))]);
+        self.encoder.set_bind_group(group, index);
+    }
+
+    fn use_resource(&mut self, resource: ResourceRef<D>, writable: bool) {
+        let name = self.recorder.resource(&resource);
+        self.recorder.record("use_resource", vec![("resource", name), ("writable", Argument::Bool(writable))]);
+        self.encoder.use_resource(resource, writable);
+    }
+
+    fn dispatch_threadgroups(&mut self, threadgroups: Size, threads_per_threadgroup: Size) {
+        self.recorder.record("dispatch_threadgroups", vec![("threadgroups", size(&threadgroups)),
+                                                           ("threads_per_threadgroup", size(&threads_per_threadgroup))]);
+        self.encoder.dispatch_threadgroups(threadgroups, threads_per_threadgroup);
+    }
+
+    fn dispatch_threadgroups_indirect(&mut self, args: &D::Buffer, offset: uint, threads_per_threadgroup: Size) {
+        let name = self.recorder.object("buffer", args);
+        self.recorder.record("dispatch_threadgroups_indirect", vec![("args", name), ("offset", Argument::Uint(offset)),
+                                                                    ("threads_per_threadgroup", size(&threads_per_threadgroup))]);
+        self.encoder.dispatch_threadgroups_indirect(args, offset, threads_per_threadgroup);
+    }
+
+    #[cfg(feature = "debug_state")]
+    fn debug_state(&self) -> ComputeEncoderState<D> {
+        self.encoder.debug_state()
+    }
+
+    fn end_encoding(self) {
+        self.recorder.record("end_encoding", Vec::new());
+        self.encoder.end_encoding();
+    }
+}
+
+/// A blit command encoder which records its commands. Returned by
+/// `CommandRecorder::begin_blit_pass`.
+pub struct RecordingBlitEncoder<'r, D: Device, E> {
+    encoder: E,
+    recorder: &'r mut CommandRecorder,
+    marker: PhantomData<D>,
+}
+
+impl<'r, D: Device, E: BlitCommandEncoder<D>> BlitCommandEncoder<D> for RecordingBlitEncoder<'r, D, E> {
+    fn copy_counter_value(&mut self, src: &D::Buffer, dst: &D::Buffer, dst_offset: uint) {
+        let src_name = self.recorder.object("buffer", src);
+        let dst_name = self.recorder.object("buffer", dst);
+        self.recorder.record("copy_counter_value", vec![("src", src_name), ("dst", dst_name), ("dst_offset", Argument::Uint(dst_offset))]);
+        self.encoder.copy_counter_value(src, dst, dst_offset);
+    }
+
+    fn reset_counter(&mut self, buffer: &D::Buffer, value: u32) {
+        let name = self.recorder.object("buffer", buffer);
+        self.recorder.record("reset_counter", vec![("buffer", name), ("value", Argument::Uint(value as uint))]);
+        self.encoder.reset_counter(buffer, value);
+    }
+
+    fn fill_buffer(&mut self, slice: BufferSlice<D::Buffer>, value: u8) {
+        let argument = self.recorder.slice(&slice);
+        self.recorder.record("fill_buffer", vec![("slice", argument), ("value", Argument::Uint(value as uint))]);
+        self.encoder.fill_buffer(slice, value);
+    }
+
+    fn copy_buffer(&mut self, src: BufferSlice<D::Buffer>, dst: &D::Buffer, dst_offset: uint) {
+        let argument = self.recorder.slice(&src);
+        let dst_name = self.recorder.object("buffer", dst);
+        self.recorder.record("copy_buffer", vec![("src", argument), ("dst", dst_name), ("dst_offset", Argument::Uint(dst_offset))]);
+        self.encoder.copy_buffer(src, dst, dst_offset);
+    }
+
+    fn copy_texture(&mut self, src: &D::Texture, src_level: uint, src_slice: uint, src_origin: Origin, copy_size: Size,
+                    dst: &D::Texture, dst_level: uint, dst_slice: uint, dst_origin: Origin) {
+        let src_name = self.recorder.object("texture", src);
+        let dst_name = self.recorder.object("texture", dst);
+        self.recorder.record("copy_texture", vec![("src", src_name), ("src_level", Argument::Uint(src_level)),
+                                                  ("src_slice", Argument::Uint(src_slice)), ("src_origin", origin(&src_origin)),
+                                                  ("size", size(&copy_size)), ("dst", dst_name),
+                                                  ("dst_level", Argument::Uint(dst_level)),
+                                                  ("dst_slice", Argument::Uint(dst_slice)), ("dst_origin", origin(&dst_origin))]);
+        self.encoder.copy_texture(src, src_level, src_slice, src_origin, copy_size, dst, dst_level, dst_slice, dst_origin);
+    }
+
+    fn copy_buffer_to_texture(&mut self, src: BufferSlice<D::Buffer>, bytes_per_row: uint,
+                              dst: &D::Texture, dst_level: uint, dst_slice: uint, dst_origin: Origin, copy_size: Size) {
+        let argument = self.recorder.slice(&src);
+        let dst_name = self.recorder.object("texture", dst);
+        self.recorder.record("copy_buffer_to_texture", vec![("src", argument), ("bytes_per_row", Argument::Uint(bytes_per_row)),
+                                                            ("dst", dst_name), ("dst_level", Argument::Uint(dst_level)),
+                                                            ("dst_slice", Argument::Uint(dst_slice)),
+                                                            ("dst_origin", origin(&dst_origin)), ("size", size(&copy_size))]);
+        self.encoder.copy_buffer_to_texture(src, bytes_per_row, dst, dst_level, dst_slice, dst_origin, copy_size);
+    }
+
+    fn copy_texture_to_buffer(&mut self, src: &D::Texture, src_level: uint, src_slice: uint, src_origin: Origin, copy_size: Size,
+                              dst: BufferSlice<D::Buffer>, bytes_per_row: uint) {
+        let src_name = self.recorder.object("texture", src);
+        let argument = self.recorder.slice(&dst);
+        self.recorder.record("copy_texture_to_buffer", vec![("src", src_name), ("src_level", Argument::Uint(src_level)),
+                                                            ("src_slice", Argument::Uint(src_slice)),
+                                                            ("src_origin", origin(&src_origin)), ("size", size(&copy_size)),
+                                                            ("dst", argument), ("bytes_per_row", Argument::Uint(bytes_per_row))]);
+        self.encoder.copy_texture_to_buffer(src, src_level, src_slice, src_origin, copy_size, dst, bytes_per_row);
+    }
+
+    fn end_encoding(self) {
+        self.recorder.record("end_encoding", Vec::new());
+        self.encoder.end_encoding();
+    }
+}
@@ -0,0 +1,786 @@
+//! Behavioral tests any backend can be checked against.
+//!
+//! `tests` lists every test, each a function from a device to either success or a description of
+//! what went wrong. Backend authors run them against their device, usually with `run`, and should
+//! expect every one to pass:
+//!
+//! ```ignore
+//! let program = Rc::new(try!(device.create_shader_program(compile(conformance::BLEND_SOURCE))));
+//! let report = conformance::run(&mut device, None, Some(program));
+//! for &(ref name, ref why) in report.failed.iter() {
+//!     println!("{} failed: {}", name, why);
+//! }
+//! ```
+//!
+//! Tests are named `group/case/parameters...`, and the filter given to `run` selects the tests
+//! whose names start with it. Each test leaves nothing behind on the device besides what it
+//! dropped, so the tests can run in any order and any subset.
+//!
+//! The blending tests draw, so they need a shader program of `BLEND_SOURCE`, compiled however
+//! the backend compiles shaders; without one, they are left out.
+
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUint, SeqCst};
+
+use {Device, CommandBuffer, CommandBufferStatus, CommandQueue, DeviceLimits, PixelFormat, TextureDescriptor, TextureType, TextureUsage};
+use {TextureViewDescriptor, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor};
+use {BindGroupLayoutEntry, BindingCount, BindingResource, BindingType, BufferSlice, ShaderStages};
+use {ClearColor, LoadAction, Origin, RenderCommandEncoder, RenderPassColorAttachment, RenderPassDescriptor};
+use {Size, StoreAction};
+use {AttachmentFormats, BlendDescriptor, BlendFactor, BlendOperation, BlitCommandEncoder, PrimitiveType};
+use {RenderPipelineDescriptor};
+use golden::{Image, Tolerance, compare, read_back};
+use texture::math::{aligned_bytes_per_row, bytes_per_row, mip_size, region_byte_size};
+use util::as_bytes;
+
+/// A single conformance test.
+pub struct ConformanceTest<D: Device> {
+    pub name: String,
+    pub run: Box<Fn(&mut D) -> Result<(), String> + 'static>,
+}
+
+/// The outcome of `run`.
+pub struct ConformanceReport {
+    pub passed: Vec<String>,
+    /// Each failed test, with why it failed.
+    pub failed: Vec<(String, String)>,
+}
+
+/// Run every test whose name starts with `filter` (all of them for `None`) against `device`.
+///
+/// `blend_program` is a shader program of `BLEND_SOURCE`, for the blending tests.
+pub fn run<D: Device + 'static>(device: &mut D, filter: Option<&str>, blend_program: Option<Rc<D::ShaderProgram>>)
+                                -> ConformanceReport {
+    let mut report = ConformanceReport { passed: Vec::new(), failed: Vec::new() };
+    for test in tests::<D>(blend_program).into_iter() {
+        if filter.map_or(false, |f| !test.name.as_slice().starts_with(f)) {
+            continue;
+        }
+        match (test.run)(device) {
+            Ok(()) => report.passed.push(test.name),
+            Err(why) => report.failed.push((test.name, why)),
+        }
+    }
+    report
+}
+
+macro_rules! check {
+    ($cond:expr, $($arg:tt)*) => {
+        if !$cond {
+            return Err(format!($($arg)*));
+        }
+    }
+}
+
+macro_rules! attempt {
+    ($e:expr, $what:expr) => {
+        match $e {
+            Ok(value) => value,
+            Err(e) => return Err(format!("{} failed: {}", $what, e)),
+        }
+    }
+}
+
+fn test<D: Device, F: Fn(&mut D) -> Result<(), String> + 'static>(tests: &mut Vec<ConformanceTest<D>>, name: String, f: F) {
+    tests.push(ConformanceTest { name: name, run: box f });
+}
+
+/// A byte pattern which differs at every offset, and between seeds.
+fn pattern(length: uint, seed: u8) -> Vec<u8> {
+    range(0, length).map(|i| (i * 7 + (i >> 8) * 13) as u8 ^ seed).collect()
+}
+
+const BUFFER_SIZES: [uint, ..10] = [1, 3, 4, 16, 255, 256, 1024, 4099, 65536, 1048579];
+const BUFFER_OFFSETS: [uint, ..5] = [0, 1, 4, 256, 4096];
+
+const PIXEL_FORMATS: [PixelFormat, ..20] = [
+    PixelFormat::R8Unorm, PixelFormat::RG8Unorm, PixelFormat::RGBA8Unorm, PixelFormat::RGBA8UnormSrgb,
+    PixelFormat::BGRA8Unorm, PixelFormat::BGRA8UnormSrgb, PixelFormat::R16Float, PixelFormat::RG16Float,
+    PixelFormat::RGBA16Float, PixelFormat::R32Float, PixelFormat::RG32Float, PixelFormat::RGBA32Float,
+    PixelFormat::R32Uint, PixelFormat::RG32Uint, PixelFormat::R32Sint, PixelFormat::RG11B10Float, PixelFormat::RGB9E5Float,
+    PixelFormat::Depth32Float, PixelFormat::Depth24UnormStencil8, PixelFormat::Depth32FloatStencil8,
+];
+
+/// Every conformance test, in a fixed order. The blending tests are only included with a
+/// `blend_program` (see `run`).
+pub fn tests<D: Device + 'static>(blend_program: Option<Rc<D::ShaderProgram>>) -> Vec<ConformanceTest<D>> {
+    let mut tests = Vec::new();
+    buffer_tests(&mut tests);
+    limit_tests(&mut tests);
+    bind_group_tests(&mut tests);
+    queue_tests(&mut tests);
+    render_pass_tests(&mut tests);
+    texture_tests(&mut tests);
+    timestamp_tests(&mut tests);
+    copy_tests(&mut tests);
+    if let Some(program) = blend_program {
+        blending_tests(&mut tests, program);
+    }
+    // todo: sampler edge behavior, checked with `golden`.
+    tests
+}
+
+fn buffer_tests<D: Device + 'static>(tests: &mut Vec<ConformanceTest<D>>) {
+    for &size in BUFFER_SIZES.iter() {
+        for &offset in BUFFER_OFFSETS.iter().filter(|&&o| o < size) {
+            test(tests, format!("buffer/round_trip/{}/{}", size, offset), move |device: &mut D| {
+                let buffer = attempt!(device.create_buffer(size, None), "create_buffer");
+                let data = pattern(size - offset, 1);
+                device.write_buffer(&buffer, offset, data.as_slice());
+                let mut read = Vec::from_elem(size - offset, 0u8);
+                device.read_buffer(&buffer, offset, read.as_mut_slice());
+                check!(read == data, "read back data differs from what was written");
+                Ok(())
+            });
+
+            test(tests, format!("buffer/read_subrange/{}/{}", size, offset), move |device: &mut D| {
+                let buffer = attempt!(device.create_buffer(size, None), "create_buffer");
+                let data = pattern(size, 2);
+                device.write_buffer(&buffer, 0, data.as_slice());
+                let mut read = Vec::from_elem(size - offset, 0u8);
+                device.read_buffer(&buffer, offset, read.as_mut_slice());
+                check!(read.as_slice() == data.slice_from(offset), "read back data differs from what was written");
+                Ok(())
+            });
+        }
+
+        test(tests, format!("buffer/partial_overwrite/{}", size), move |device: &mut D| {
+            let buffer = attempt!(device.create_buffer(size, None), "create_buffer");
+            let mut expected = pattern(size, 3);
+            device.write_buffer(&buffer, 0, expected.as_slice());
+            let (start, end) = (size / 3, size - size / 3);
+            let overwrite = pattern(end - start, 4);
+            device.write_buffer(&buffer, start, overwrite.as_slice());
+            for (i, &b) in overwrite.iter().enumerate() {
+                expected[start + i] = b;
+            }
+            let mut read = Vec::from_elem(size, 0u8);
+            device.read_buffer(&buffer, 0, read.as_mut_slice());
+            check!(read == expected, "bytes outside of the overwritten range changed, or the overwrite was lost");
+            Ok(())
+        });
+
+        test(tests, format!("buffer/last_write_wins/{}", size), move |device: &mut D| {
+            let buffer = attempt!(device.create_buffer(size, None), "create_buffer");
+            for seed in range(0u8, 8) {
+                device.write_buffer(&buffer, 0, pattern(size, seed).as_slice());
+            }
+            let mut read = Vec::from_elem(size, 0u8);
+            device.read_buffer(&buffer, 0, read.as_mut_slice());
+            check!(read == pattern(size, 7), "writes were not applied in order");
+            Ok(())
+        });
+    }
+
+    test(tests, "buffer/empty_write".to_string(), |device: &mut D| {
+        let buffer = attempt!(device.create_buffer(16, None), "create_buffer");
+        let data = pattern(16, 5);
+        device.write_buffer(&buffer, 0, data.as_slice());
+        device.write_buffer(&buffer, 16, &[]);
+        let mut read = Vec::from_elem(16, 0u8);
+        device.read_buffer(&buffer, 0, read.as_mut_slice());
+        check!(read == data, "an empty write changed the buffer");
+        Ok(())
+    });
+}
+
+fn limit_tests<D: Device + 'static>(tests: &mut Vec<ConformanceTest<D>>) {
+    test(tests, "limits/baseline".to_string(), |device: &mut D| {
+        let unmet = device.limits().unmet(&DeviceLimits::baseline());
+        check!(unmet.is_empty(), "the device does not meet the baseline limits: {}", unmet);
+        Ok(())
+    });
+
+    test(tests, "limits/uniform_alignment_power_of_two".to_string(), |device: &mut D| {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment;
+        check!(alignment.is_power_of_two(), "{} is not a power of two", alignment);
+        Ok(())
+    });
+
+    test(tests, "limits/storage_alignment_power_of_two".to_string(), |device: &mut D| {
+        let alignment = device.limits().min_storage_buffer_offset_alignment;
+        check!(alignment.is_power_of_two(), "{} is not a power of two", alignment);
+        Ok(())
+    });
+
+    test(tests, "limits/bindless_without_features".to_string(), |device: &mut D| {
+        let (features, limits) = (device.features(), device.limits());
+        check!(features.bindless_textures || limits.max_bindless_textures == 0,
+               "max_bindless_textures is nonzero without the bindless_textures feature");
+        check!(features.bindless_samplers || limits.max_bindless_samplers == 0,
+               "max_bindless_samplers is nonzero without the bindless_samplers feature");
+        Ok(())
+    });
+}
+
+fn buffer_binding_layout(uniform: bool) -> BindGroupLayoutDescriptor {
+    BindGroupLayoutDescriptor {
+        entries: vec![BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages { vertex: true, fragment: true, compute: true },
+            ty: if uniform { BindingType::UniformBuffer } else { BindingType::StorageBuffer { read_only: false } },
+            count: BindingCount::Single,
+            partially_bound: false,
+            update_after_bind: false,
+        }],
+    }
+}
+
+fn bind_buffer_at<D: Device>(device: &mut D, uniform: bool, offset: uint) -> Result<bool, String> {
+    let layout = attempt!(device.create_bind_group_layout(buffer_binding_layout(uniform)), "create_bind_group_layout");
+    let buffer = attempt!(device.create_buffer(offset + 256, None), "create_buffer");
+    let result = device.create_bind_group(BindGroupDescriptor {
+        layout: &layout,
+        entries: vec![BindGroupEntry {
+            binding: 0,
+            array_element: 0,
+            resource: BindingResource::Buffer(BufferSlice::new(&buffer, offset, 256)),
+        }],
+        unsized_length: 0,
+    });
+    Ok(result.is_ok())
+}
+
+fn bind_group_tests<D: Device + 'static>(tests: &mut Vec<ConformanceTest<D>>) {
+    for &uniform in [true, false].iter() {
+        let kind = if uniform { "uniform" } else { "storage" };
+        let alignment = move |device: &D| {
+            let limits = device.limits();
+            if uniform { limits.min_uniform_buffer_offset_alignment } else { limits.min_storage_buffer_offset_alignment }
+        };
+
+        for multiple in range(0u, 4) {
+            test(tests, format!("bind_group/aligned_offset/{}/{}", kind, multiple), move |device: &mut D| {
+                let offset = alignment(device) * multiple;
+                check!(try!(bind_buffer_at(device, uniform, offset)), "binding at offset {} failed", offset);
+                Ok(())
+            });
+        }
+
+        for &misalignment in [1u, 4, 16, 64].iter() {
+            test(tests, format!("bind_group/misaligned_offset/{}/{}", kind, misalignment), move |device: &mut D| {
+                let alignment = alignment(device);
+                if misalignment >= alignment {
+                    // aligned on this device, nothing to check
+                    return Ok(());
+                }
+                check!(!try!(bind_buffer_at(device, uniform, alignment + misalignment)),
+                       "binding at a misaligned offset was accepted");
+                Ok(())
+            });
+        }
+    }
+}
+
+fn queue_tests<D: Device + 'static>(tests: &mut Vec<ConformanceTest<D>>) {
+    for count in range(1u, 5) {
+        test(tests, format!("command_queue/capacity/{}", count), move |device: &mut D| {
+            let queue = attempt!(device.create_command_queue(Some(count)), "create_command_queue");
+            check!(queue.capacity() == Some(count), "capacity is {}", queue.capacity());
+            Ok(())
+        });
+
+        test(tests, format!("command_queue/full/{}", count), move |device: &mut D| {
+            let mut queue = attempt!(device.create_command_queue(Some(count)), "create_command_queue");
+            let mut held = Vec::new();
+            for i in range(0, count) {
+                match queue.try_acquire_command_buffer() {
+                    Some(commands) => held.push(commands),
+                    None => return Err(format!("acquiring command buffer {} of {} failed", i + 1, count)),
+                }
+            }
+            check!(queue.try_acquire_command_buffer().is_none(), "acquired more command buffers than the queue has slots");
+            held.pop();
+            check!(queue.try_acquire_command_buffer().is_some(), "dropping an uncommitted command buffer did not free its slot");
+            Ok(())
+        });
+    }
+
+    test(tests, "command_queue/completed_handlers".to_string(), |device: &mut D| {
+        let mut queue = attempt!(device.create_command_queue(None), "create_command_queue");
+        let mut commands = queue.acquire_command_buffer();
+        let calls = Arc::new(AtomicUint::new(0));
+        for i in range(0u, 3) {
+            let calls = calls.clone();
+            commands.add_completed_handler(box move |status: CommandBufferStatus| {
+                // each handler runs after the ones added before it
+                match status {
+                    CommandBufferStatus::Completed => { calls.compare_and_swap(i, i + 1, SeqCst); }
+                    CommandBufferStatus::Failed(_) => {}
+                }
+            });
+        }
+        match commands.commit_and_wait() {
+            CommandBufferStatus::Completed => {}
+            CommandBufferStatus::Failed(_) => return Err("an empty command buffer failed".to_string()),
+        }
+        check!(calls.load(SeqCst) == 3, "the completed handlers ran out of order, or not at all");
+        Ok(())
+    });
+
+    test(tests, "command_queue/unbounded".to_string(), |device: &mut D| {
+        let mut queue = attempt!(device.create_command_queue(None), "create_command_queue");
+        check!(queue.capacity().is_none(), "capacity is {}", queue.capacity());
+        let mut held = Vec::new();
+        for _ in range(0u, 64) {
+            match queue.try_acquire_command_buffer() {
+                Some(commands) => held.push(commands),
+                None => return Err("an unbounded queue was full".to_string()),
+            }
+        }
+        Ok(())
+    });
+}
+
+fn render_pass_tests<D: Device + 'static>(tests: &mut Vec<ConformanceTest<D>>) {
+    let colors = [[0u8, 0, 0, 0], [255, 255, 255, 255], [255, 0, 0, 255], [51, 102, 153, 204]];
+    for (i, _) in colors.iter().enumerate() {
+        test(tests, format!("render_pass/clear/{}", i), move |device: &mut D| {
+            let expected = colors[i];
+            let mut desc = texture_descriptor(TextureType::Type2D, PixelFormat::RGBA8Unorm, 1);
+            desc.usage.render_target = true;
+            let (width, height) = (desc.width, desc.height);
+            let texture = attempt!(device.create_texture(desc), "create_texture");
+            let mut queue = attempt!(device.create_command_queue(None), "create_command_queue");
+
+            let mut commands = queue.acquire_command_buffer();
+            let encoder = commands.render_command_encoder(RenderPassDescriptor {
+                color_attachments: vec![RenderPassColorAttachment {
+                    texture: &texture,
+                    mipmap_level: 0,
+                    slice: 0,
+                    load_action: LoadAction::Clear,
+                    store_action: StoreAction::Store,
+                    clear_color: ClearColor::Float {
+                        red: expected[0] as f64 / 255.0,
+                        green: expected[1] as f64 / 255.0,
+                        blue: expected[2] as f64 / 255.0,
+                        alpha: expected[3] as f64 / 255.0,
+                    },
+                }],
+                depth_attachment: None,
+                stencil_attachment: None,
+                sample_positions: None,
+                visibility_result_buffer: None,
+            });
+            encoder.end_encoding();
+            match commands.commit_and_wait() {
+                CommandBufferStatus::Completed => {}
+                CommandBufferStatus::Failed(_) => return Err("the command buffer failed".to_string()),
+            }
+
+            let mut data = Vec::from_elem(width * height * 4, 0u8);
+            device.read_texture(&texture, 0, 0, Origin { x: 0, y: 0, z: 0 }, Size { width: width, height: height, depth: 1 },
+                                data.as_mut_slice(), width * 4);
+            for (j, texel) in data.as_slice().chunks(4).enumerate() {
+                check!(texel == expected.as_slice(), "texel {} is {}, not {}", j, texel, expected.as_slice());
+            }
+            Ok(())
+        });
+    }
+
+    // integer attachments are cleared to the exact value, with no normalization
+    test(tests, "render_pass/clear_uint".to_string(), |device: &mut D| {
+        let value = 0xdeadbeefu32;
+        let mut desc = texture_descriptor(TextureType::Type2D, PixelFormat::R32Uint, 1);
+        desc.usage.render_target = true;
+        let (width, height) = (desc.width, desc.height);
+        let texture = attempt!(device.create_texture(desc), "create_texture");
+        let mut queue = attempt!(device.create_command_queue(None), "create_command_queue");
+
+        let mut commands = queue.acquire_command_buffer();
+        let encoder = commands.render_command_encoder(RenderPassDescriptor {
+            color_attachments: vec![RenderPassColorAttachment {
+                texture: &texture,
+                mipmap_level: 0,
+                slice: 0,
+                load_action: LoadAction::Clear,
+                store_action: StoreAction::Store,
+                clear_color: ClearColor::Uint([value, 0, 0, 0]),
+            }],
+            depth_attachment: None,
+            stencil_attachment: None,
+            sample_positions: None,
+            visibility_result_buffer: None,
+        });
+        encoder.end_encoding();
+        match commands.commit_and_wait() {
+            CommandBufferStatus::Completed => {}
+            CommandBufferStatus::Failed(_) => return Err("the command buffer failed".to_string()),
+        }
+
+        let mut data = Vec::from_elem(width * height * 4, 0u8);
+        device.read_texture(&texture, 0, 0, Origin { x: 0, y: 0, z: 0 }, Size { width: width, height: height, depth: 1 },
+                            data.as_mut_slice(), width * 4);
+        for (j, texel) in data.as_slice().chunks(4).enumerate() {
+            let read = texel[0] as u32 | (texel[1] as u32 << 8) | (texel[2] as u32 << 16) | (texel[3] as u32 << 24);
+            check!(read == value, "texel {} is {:x}, not {:x}", j, read, value);
+        }
+        Ok(())
+    });
+}
+
+fn texture_descriptor(texture_type: TextureType, format: PixelFormat, levels: uint) -> TextureDescriptor {
+    let (depth, array_length) = match texture_type {
+        TextureType::Type3D => (4, 1),
+        TextureType::Type2DArray => (1, 4),
+        TextureType::TypeCube => (1, 6),
+        TextureType::Type2D => (1, 1),
+    };
+    TextureDescriptor {
+        texture_type: texture_type,
+        pixel_format: format,
+        width: 64,
+        height: 64,
+        depth: depth,
+        mipmap_level_count: levels,
+        array_length: array_length,
+        sample_count: 1,
+        usage: TextureUsage { shader_read: true, shader_write: false, render_target: false },
+    }
+}
+
+fn texture_tests<D: Device + 'static>(tests: &mut Vec<ConformanceTest<D>>) {
+    for (i, _) in PIXEL_FORMATS.iter().enumerate() {
+        test(tests, format!("texture/create_2d/{}", i), move |device: &mut D| {
+            attempt!(device.create_texture(texture_descriptor(TextureType::Type2D, PIXEL_FORMATS[i], 1)), "create_texture");
+            Ok(())
+        });
+    }
+
+    for (name, texture_type) in vec![("2d_array", TextureType::Type2DArray), ("3d", TextureType::Type3D),
+                                     ("cube", TextureType::TypeCube)].into_iter() {
+        test(tests, format!("texture/create/{}", name), move |device: &mut D| {
+            attempt!(device.create_texture(texture_descriptor(texture_type, PixelFormat::RGBA8Unorm, 1)), "create_texture");
+            Ok(())
+        });
+    }
+
+    // a 64x64 texture has 7 levels
+    for level in range(0u, 7) {
+        test(tests, format!("texture/view_level/{}", level), move |device: &mut D| {
+            let texture = attempt!(device.create_texture(texture_descriptor(TextureType::Type2D, PixelFormat::RGBA8Unorm, 7)),
+                                   "create_texture");
+            attempt!(device.create_texture_view(&texture, TextureViewDescriptor {
+                pixel_format: PixelFormat::RGBA8Unorm,
+                base_mipmap_level: level,
+                mipmap_level_count: 1,
+                base_array_slice: 0,
+                array_length: 1,
+            }), "create_texture_view");
+            Ok(())
+        });
+    }
+
+    test(tests, "texture/view_out_of_range".to_string(), |device: &mut D| {
+        let texture = attempt!(device.create_texture(texture_descriptor(TextureType::Type2D, PixelFormat::RGBA8Unorm, 7)),
+                               "create_texture");
+        let view = device.create_texture_view(&texture, TextureViewDescriptor {
+            pixel_format: PixelFormat::RGBA8Unorm,
+            base_mipmap_level: 6,
+            mipmap_level_count: 2,
+            base_array_slice: 0,
+            array_length: 1,
+        });
+        check!(view.is_err(), "a view of mip levels past the end of the texture was created");
+        Ok(())
+    });
+}
+
+fn timestamp_tests<D: Device + 'static>(tests: &mut Vec<ConformanceTest<D>>) {
+    test(tests, "timestamps/monotonic".to_string(), |device: &mut D| {
+        let (first, second) = match (device.calibrate_timestamps(), device.calibrate_timestamps()) {
+            (Some(first), Some(second)) => (first, second),
+            (None, None) => return Ok(()),
+            _ => return Err("calibration only sometimes succeeds".to_string()),
+        };
+        check!(second.cpu_ns >= first.cpu_ns, "the CPU timestamp went backwards");
+        check!(second.gpu_ticks >= first.gpu_ticks, "the GPU timestamp went backwards");
+        check!(first.gpu_tick_ns > 0.0, "the GPU tick length is not positive");
+        Ok(())
+    });
+}
+
+fn commit<D: Device>(mut commands: D::CommandBuffer) -> Result<(), String> {
+    match commands.commit_and_wait() {
+        CommandBufferStatus::Completed => Ok(()),
+        CommandBufferStatus::Failed(_) => Err("the command buffer failed".to_string()),
+    }
+}
+
+/// Texel data for `format`: `pattern`, except that depth texels hold values from `0` to `1`, as
+/// copies into depth textures may clamp anything else.
+fn texel_data(format: &PixelFormat, length: uint, seed: u8) -> Vec<u8> {
+    match *format {
+        PixelFormat::Depth32Float => {
+            let mut data = Vec::with_capacity(length);
+            for i in range(0, length / 4) {
+                let value = (i ^ seed as uint) as f32 / (length / 4) as f32;
+                data.push_all(as_bytes(&value));
+            }
+            data
+        }
+        _ => pattern(length, seed),
+    }
+}
+
+/// Copy texel data from a buffer into mip level `level` of array slice `slice` of a texture
+/// created with `desc`, covering the whole level, then back into another buffer, with rows
+/// `bytes_per_row` apart in both, and check that the texels came back unchanged.
+///
+/// Packed depth and stencil formats are copied one aspect at a time by some backends, so the
+/// layout of their copies is not portable: for those, only the copies themselves are checked.
+fn copy_round_trip<D: Device>(device: &mut D, desc: TextureDescriptor, level: uint, slice: uint, bytes_per_row: uint)
+                              -> Result<(), String> {
+    let format = desc.pixel_format.clone();
+    let size = mip_size(&desc, level);
+    let row = self::bytes_per_row(&format, size.width);
+    let length = region_byte_size(&format, &size, bytes_per_row);
+    let data = texel_data(&format, length, 9);
+
+    let texture = attempt!(device.create_texture(desc), "create_texture");
+    let src = attempt!(device.create_buffer(length, None), "create_buffer");
+    let dst = attempt!(device.create_buffer(length, None), "create_buffer");
+    device.write_buffer(&src, 0, data.as_slice());
+    device.write_buffer(&dst, 0, Vec::from_elem(length, 0u8).as_slice());
+    let mut queue = attempt!(device.create_command_queue(None), "create_command_queue");
+
+    let mut commands = queue.acquire_command_buffer();
+    let mut blit = commands.blit_command_encoder();
+    let origin = || Origin { x: 0, y: 0, z: 0 };
+    let region = || Size { width: size.width, height: size.height, depth: size.depth };
+    blit.copy_buffer_to_texture(BufferSlice::whole(&src), bytes_per_row, &texture, level, slice, origin(), region());
+    blit.copy_texture_to_buffer(&texture, level, slice, origin(), region(), BufferSlice::whole(&dst), bytes_per_row);
+    blit.end_encoding();
+    try!(commit::<D>(commands));
+    if format.has_stencil() {
+        return Ok(());
+    }
+
+    let mut read = Vec::from_elem(length, 0u8);
+    device.read_buffer(&dst, 0, read.as_mut_slice());
+    // the padding at the end of each row is not part of the copy
+    for start in range(0, size.height * size.depth).map(|i| i * bytes_per_row) {
+        check!(read.slice(start, start + row) == data.slice(start, start + row),
+               "the row at byte {} differs from what was copied in", start);
+    }
+    Ok(())
+}
+
+fn copy_tests<D: Device + 'static>(tests: &mut Vec<ConformanceTest<D>>) {
+    for (i, _) in PIXEL_FORMATS.iter().enumerate() {
+        test(tests, format!("copy/buffer_texture_round_trip/{}", i), move |device: &mut D| {
+            let desc = texture_descriptor(TextureType::Type2D, PIXEL_FORMATS[i], 1);
+            let bytes_per_row = aligned_bytes_per_row(&desc.pixel_format, desc.width);
+            copy_round_trip(device, desc, 0, 0, bytes_per_row)
+        });
+    }
+
+    // a 64x64 texture has 7 levels, the last ones narrower than a row's alignment
+    for level in range(0u, 7) {
+        test(tests, format!("copy/mip_level/{}", level), move |device: &mut D| {
+            let desc = texture_descriptor(TextureType::Type2D, PixelFormat::RGBA8Unorm, 7);
+            let bytes_per_row = aligned_bytes_per_row(&desc.pixel_format, mip_size(&desc, level).width);
+            copy_round_trip(device, desc, level, 0, bytes_per_row)
+        });
+    }
+
+    for (name, texture_type, slices) in vec![("2d_array", TextureType::Type2DArray, 4u),
+                                             ("cube", TextureType::TypeCube, 6)].into_iter() {
+        for slice in range(0, slices) {
+            test(tests, format!("copy/slice/{}/{}", name, slice), move |device: &mut D| {
+                let desc = texture_descriptor(texture_type, PixelFormat::RGBA8Unorm, 2);
+                let bytes_per_row = aligned_bytes_per_row(&desc.pixel_format, mip_size(&desc, 1).width);
+                copy_round_trip(device, desc, 1, slice, bytes_per_row)
+            });
+        }
+    }
+
+    test(tests, "copy/slice/3d".to_string(), |device: &mut D| {
+        let desc = texture_descriptor(TextureType::Type3D, PixelFormat::RGBA8Unorm, 1);
+        let bytes_per_row = aligned_bytes_per_row(&desc.pixel_format, desc.width);
+        copy_round_trip(device, desc, 0, 0, bytes_per_row)
+    });
+
+    // rows further apart than they need to be, of a width which is not a multiple of anything
+    for &extra_rows in [1u, 2, 5].iter() {
+        test(tests, format!("copy/row_padding/{}", extra_rows), move |device: &mut D| {
+            let mut desc = texture_descriptor(TextureType::Type2D, PixelFormat::R8Unorm, 1);
+            desc.width = 13;
+            desc.height = 7;
+            let bytes_per_row = aligned_bytes_per_row(&desc.pixel_format, desc.width) * (1 + extra_rows);
+            copy_round_trip(device, desc, 0, 0, bytes_per_row)
+        });
+    }
+}
+
+/// The shader source of the blending tests: a triangle covering the whole attachment, of the
+/// color bound with `set_fragment_bytes` at index `0`.
+pub const BLEND_SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+vertex float4 blend_vertex(uint vid [[vertex_id]])
+{
+    return float4(vid == 1 ? 3.0f : -1.0f, vid == 2 ? 3.0f : -1.0f, 0.0f, 1.0f);
+}
+
+fragment float4 blend_fragment(constant float4 &color [[buffer(0)]])
+{
+    return color;
+}
+"#;
+
+const BLEND_FACTORS: [BlendFactor, ..10] = [
+    BlendFactor::Zero, BlendFactor::One, BlendFactor::SourceColor, BlendFactor::OneMinusSourceColor,
+    BlendFactor::SourceAlpha, BlendFactor::OneMinusSourceAlpha, BlendFactor::DestinationColor,
+    BlendFactor::OneMinusDestinationColor, BlendFactor::DestinationAlpha, BlendFactor::OneMinusDestinationAlpha,
+];
+
+const BLEND_OPERATIONS: [BlendOperation, ..5] = [
+    BlendOperation::Add, BlendOperation::Subtract, BlendOperation::ReverseSubtract, BlendOperation::Min,
+    BlendOperation::Max,
+];
+
+/// The source and destination colors of the blending tests, as bytes of the attachment.
+const BLEND_SOURCE_COLOR: [u8, ..4] = [191, 128, 64, 128];
+const BLEND_DESTINATION_COLOR: [u8, ..4] = [64, 128, 192, 255];
+
+/// The factor `factor` for channel `channel` (`3` being alpha) of `source` blended onto
+/// `destination`.
+fn blend_factor(factor: &BlendFactor, source: &[f32, ..4], destination: &[f32, ..4], channel: uint) -> f32 {
+    match *factor {
+        BlendFactor::Zero => 0.0,
+        BlendFactor::One => 1.0,
+        BlendFactor::SourceColor => source[channel],
+        BlendFactor::OneMinusSourceColor => 1.0 - source[channel],
+        BlendFactor::SourceAlpha => source[3],
+        BlendFactor::OneMinusSourceAlpha => 1.0 - source[3],
+        BlendFactor::DestinationColor => destination[channel],
+        BlendFactor::OneMinusDestinationColor => 1.0 - destination[channel],
+        BlendFactor::DestinationAlpha => destination[3],
+        BlendFactor::OneMinusDestinationAlpha => 1.0 - destination[3],
+    }
+}
+
+/// What a `BlendDescriptor::uniform` blend ought to leave in an `RGBA8Unorm` attachment.
+fn expected_blend(source_factor: &BlendFactor, destination_factor: &BlendFactor, operation: &BlendOperation)
+                  -> [u8, ..4] {
+    let unorm = |bytes: [u8, ..4]| [bytes[0] as f32 / 255.0, bytes[1] as f32 / 255.0, bytes[2] as f32 / 255.0,
+                                   bytes[3] as f32 / 255.0];
+    let (source, destination) = (unorm(BLEND_SOURCE_COLOR), unorm(BLEND_DESTINATION_COLOR));
+    let mut out = [0u8, ..4];
+    for channel in range(0u, 4) {
+        let s = source[channel] * blend_factor(source_factor, &source, &destination, channel);
+        let d = destination[channel] * blend_factor(destination_factor, &source, &destination, channel);
+        let value = match *operation {
+            BlendOperation::Add => s + d,
+            BlendOperation::Subtract => s - d,
+            BlendOperation::ReverseSubtract => d - s,
+            BlendOperation::Min => source[channel].min(destination[channel]),
+            BlendOperation::Max => source[channel].max(destination[channel]),
+        };
+        out[channel] = (value.max(0.0).min(1.0) * 255.0).round() as u8;
+    }
+    out
+}
+
+/// Clear an `RGBA8Unorm` target to `BLEND_DESTINATION_COLOR`, draw `BLEND_SOURCE_COLOR` over all
+/// of it with `blend`, and compare the result against `expected`.
+fn check_blend<D: Device>(device: &mut D, program: &D::ShaderProgram, blend: BlendDescriptor, expected: [u8, ..4])
+                          -> Result<(), String> {
+    let mut desc = texture_descriptor(TextureType::Type2D, PixelFormat::RGBA8Unorm, 1);
+    desc.width = 16;
+    desc.height = 16;
+    desc.usage.render_target = true;
+    let (width, height) = (desc.width, desc.height);
+    let target = attempt!(device.create_texture(desc), "create_texture");
+    let pipeline = attempt!(device.create_render_pipeline(program, RenderPipelineDescriptor {
+        label: Some("conformance blending".to_string()),
+        robustness: None,
+        vertex_function: "blend_vertex".to_string(),
+        fragment_function: Some("blend_fragment".to_string()),
+        vertex_descriptor: None,
+        blending: vec![Some(blend)],
+        sample_count: 1,
+        attachment_formats: Some(AttachmentFormats {
+            color: vec![PixelFormat::RGBA8Unorm],
+            depth: None,
+            stencil: None,
+        }),
+    }), "create_render_pipeline");
+    let mut queue = attempt!(device.create_command_queue(None), "create_command_queue");
+
+    let destination = BLEND_DESTINATION_COLOR;
+    let source = BLEND_SOURCE_COLOR;
+    let color = [source[0] as f32 / 255.0, source[1] as f32 / 255.0, source[2] as f32 / 255.0,
+                 source[3] as f32 / 255.0];
+    let mut commands = queue.acquire_command_buffer();
+    let mut encoder = commands.render_command_encoder(RenderPassDescriptor {
+        color_attachments: vec![RenderPassColorAttachment {
+            texture: &target,
+            mipmap_level: 0,
+            slice: 0,
+            load_action: LoadAction::Clear,
+            store_action: StoreAction::Store,
+            clear_color: ClearColor::Float {
+                red: destination[0] as f64 / 255.0,
+                green: destination[1] as f64 / 255.0,
+                blue: destination[2] as f64 / 255.0,
+                alpha: destination[3] as f64 / 255.0,
+            },
+        }],
+        depth_attachment: None,
+        stencil_attachment: None,
+        sample_positions: None,
+        visibility_result_buffer: None,
+    });
+    encoder.set_render_pipeline(&pipeline);
+    encoder.set_fragment_bytes(as_bytes(&color), 0);
+    encoder.draw(PrimitiveType::Triangle, 0, 3, 1, 0);
+    encoder.end_encoding();
+    try!(commit::<D>(commands));
+
+    let actual = read_back(device, &target, width, height);
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for _ in range(0, width * height) {
+        pixels.push_all(expected.as_slice());
+    }
+    let reference = Image { width: width, height: height, pixels: pixels };
+    let comparison = compare(&actual, &reference, &Tolerance::default());
+    check!(comparison.passed, "{} pixels differ from {} (by up to {} ΔE)", comparison.differing, expected.as_slice(),
+           comparison.max_delta_e);
+    Ok(())
+}
+
+fn blending_tests<D: Device + 'static>(tests: &mut Vec<ConformanceTest<D>>, program: Rc<D::ShaderProgram>) {
+    for (i, _) in BLEND_FACTORS.iter().enumerate() {
+        let source_program = program.clone();
+        test(tests, format!("blending/source_factor/{}", i), move |device: &mut D| {
+            let (factor, operation) = (BLEND_FACTORS[i], BlendOperation::Add);
+            let expected = expected_blend(&factor, &BlendFactor::One, &operation);
+            check_blend(device, &*source_program, BlendDescriptor::uniform(factor, BlendFactor::One, operation),
+                        expected)
+        });
+
+        let destination_program = program.clone();
+        test(tests, format!("blending/destination_factor/{}", i), move |device: &mut D| {
+            let (factor, operation) = (BLEND_FACTORS[i], BlendOperation::Add);
+            let expected = expected_blend(&BlendFactor::One, &factor, &operation);
+            check_blend(device, &*destination_program, BlendDescriptor::uniform(BlendFactor::One, factor, operation),
+                        expected)
+        });
+    }
+
+    // halving both terms keeps every sum in range, so the operations differ
+    for (i, _) in BLEND_OPERATIONS.iter().enumerate() {
+        let program = program.clone();
+        test(tests, format!("blending/operation/{}", i), move |device: &mut D| {
+            let (factor, operation) = (BlendFactor::SourceAlpha, BLEND_OPERATIONS[i]);
+            let expected = expected_blend(&factor, &factor, &operation);
+            check_blend(device, &*program, BlendDescriptor::uniform(BlendFactor::SourceAlpha, factor, operation),
+                        expected)
+        });
+    }
+}
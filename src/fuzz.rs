@@ -0,0 +1,421 @@
+//! Random, valid command sequences for stress testing encoders.
+//!
+//! `generate` produces a sequence of render encoder commands that follows every usage rule of
+//! `RenderCommandEncoder` (draws only with a pipeline set, indexed draws only with an index buffer
+//! bound and aligned, predication never nested, bytes and offsets within bounds, ...), and
+//! `replay` records it into an encoder. `generate_compute` and `replay_compute`, and
+//! `generate_blit` and `replay_blit`, do the same for compute and blit encoders. A backend's
+//! validation and hazard tracking should accept every such sequence without panicking, erroring
+//! or deadlocking, however odd it is:
+//!
+//! ```ignore
+//! for seed in range(0, 10000) {
+//!     let ops = fuzz::generate(seed, &counts, 200);
+//!     let mut encoder = begin_pass(&mut commands);
+//!     fuzz::replay(&mut encoder, &resources, ops.as_slice());
+//!     end_pass(encoder);
+//! }
+//! ```
+//!
+//! `run` does that loop for whole command buffers: for every seed, it hands the seed's
+//! `FuzzSequence` to a closure which encodes its passes and commits them, and reports the first
+//! seed whose closure panicked, or whose command buffer failed or did not complete in time.
+//!
+//! Sequences are a pure function of their seed, and the ops are `Show`, so a failing seed can be
+//! printed, replayed and reduced by hand.
+
+use std::any::Any;
+use std::ops::Range;
+use std::sync::Arc;
+
+use {Device, BlitCommandEncoder, BufferSlice, ComputeCommandEncoder, RenderCommandEncoder, IndexFormat, PredicationOp};
+use {CommandBufferError, CommandBufferStatus, PrimitiveType, ResourceRef, ScissorRect, Size};
+use {PREDICATE_SIZE};
+use util::fence::Fence;
+
+/// A small, fast, deterministic random number generator (xorshift64*).
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed.wrapping_mul(0x9e3779b97f4a7c15) | 1 }
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// A number in `[0, n)`.
+    pub fn below(&mut self, n: uint) -> uint {
+        (self.next() % n as u64) as uint
+    }
+
+    pub fn chance(&mut self, percent: uint) -> bool {
+        self.below(100) < percent
+    }
+}
+
+/// One render encoder command. Resources are indices into the `FuzzResources` replayed with.
+#[deriving(Show, Clone)]
+pub enum RenderOp {
+    SetPipeline(uint),
+    SetVertexBuffer { buffer: uint, offset: uint, index: uint },
+    SetVertexBytes { length: uint, index: uint },
+    SetScissorRect { x: uint, y: uint, width: uint, height: uint },
+    SetBindGroup(uint),
+    SetIndexBuffer { buffer: uint, offset: uint, uint32: bool },
+    UseBuffer { buffer: uint, writable: bool },
+    BeginPredication { buffer: uint, index: uint, draw_if_zero: bool },
+    EndPredication,
+    Draw { primitive: uint, first_vertex: uint, vertex_count: uint, instance_count: uint, base_instance: uint },
+    DrawIndexed { primitive: uint, first_index: uint, index_count: uint, instance_count: uint, base_vertex: int,
+                  base_instance: uint },
+}
+
+/// One compute encoder command. Resources are indices into the `FuzzResources` replayed with.
+#[deriving(Show, Clone)]
+pub enum ComputeOp {
+    SetPipeline(uint),
+    SetBuffer { buffer: uint, offset: uint, index: uint },
+    SetBytes { length: uint, index: uint },
+    UseBuffer { buffer: uint, writable: bool },
+    Dispatch { threadgroups: [uint, ..3], threads_per_threadgroup: [uint, ..3] },
+}
+
+/// One blit encoder command. Offsets and sizes are in bytes, and multiples of 4.
+#[deriving(Show, Clone)]
+pub enum BlitOp {
+    Fill { buffer: uint, offset: uint, size: uint, value: u8 },
+    Copy { src: uint, src_offset: uint, dst: uint, dst_offset: uint, size: uint },
+}
+
+/// How many of each resource the sequence may refer to, and the size of the pass.
+pub struct FuzzCounts {
+    pub pipelines: uint,
+    pub compute_pipelines: uint,
+    pub buffers: uint,
+    /// The size of every buffer, in bytes.
+    pub buffer_size: uint,
+    /// The number of leading buffers which were created with the `predicate` hint.
+    pub predicate_buffers: uint,
+    pub bind_groups: uint,
+    /// The size of the pass's attachments.
+    pub width: uint,
+    pub height: uint,
+}
+
+/// The resources a sequence is replayed with.
+///
+/// The pipelines must not read vertex buffers through a `VertexDescriptor` (the generated draws
+/// use arbitrary vertex ranges), and every bind group must be compatible with every pipeline at
+/// index 0. The compute pipelines must not touch their buffers (the generated dispatches bind
+/// whatever buffers at whatever offsets, and run any number of threads): a kernel doing nothing
+/// will do.
+pub struct FuzzResources<'a, D: Device> {
+    pub pipelines: &'a [D::RenderPipeline],
+    pub compute_pipelines: &'a [D::ComputePipeline],
+    pub buffers: &'a [D::Buffer],
+    pub bind_groups: &'a [D::BindGroup],
+}
+
+const PRIMITIVES: uint = 5;
+
+fn primitive(n: uint) -> PrimitiveType {
+    match n {
+        0 => PrimitiveType::Point,
+        1 => PrimitiveType::Line,
+        2 => PrimitiveType::LineStrip,
+        3 => PrimitiveType::Triangle,
+        _ => PrimitiveType::TriangleStrip,
+    }
+}
+
+/// Generate a valid sequence of `length` commands (a few more if predication needs closing).
+///
+/// Commands whose resources `counts` has none of, or none large enough for, are left out.
+pub fn generate(seed: u64, counts: &FuzzCounts, length: uint) -> Vec<RenderOp> {
+    let mut rng = Rng::new(seed);
+    let mut ops = Vec::with_capacity(length + 1);
+    let mut pipeline_set = false;
+    // the number of indices the bound index buffer has past its offset
+    let mut index_buffer: Option<uint> = None;
+    let mut predicating = false;
+
+    while ops.len() < length {
+        let op = match rng.below(11) {
+            0 if counts.pipelines > 0 => {
+                pipeline_set = true;
+                RenderOp::SetPipeline(rng.below(counts.pipelines))
+            }
+            1 if counts.buffers > 0 && counts.buffer_size >= 4 => RenderOp::SetVertexBuffer {
+                buffer: rng.below(counts.buffers),
+                offset: rng.below(counts.buffer_size / 4) * 4,
+                index: rng.below(4),
+            },
+            2 => RenderOp::SetVertexBytes { length: (rng.below(64) + 1) * 16, index: 4 + rng.below(2) },
+            3 if counts.width > 0 && counts.height > 0 => {
+                let (x, y) = (rng.below(counts.width), rng.below(counts.height));
+                RenderOp::SetScissorRect { x: x, y: y, width: rng.below(counts.width - x) + 1,
+                                           height: rng.below(counts.height - y) + 1 }
+            }
+            4 if counts.bind_groups > 0 => RenderOp::SetBindGroup(rng.below(counts.bind_groups)),
+            5 if counts.buffers > 0 && counts.buffer_size >= 4 => {
+                let uint32 = rng.chance(50);
+                let size = if uint32 { 4 } else { 2 };
+                let offset = rng.below(counts.buffer_size / size) * size;
+                index_buffer = Some((counts.buffer_size - offset) / size);
+                RenderOp::SetIndexBuffer { buffer: rng.below(counts.buffers), offset: offset, uint32: uint32 }
+            }
+            6 if counts.buffers > 0 => RenderOp::UseBuffer { buffer: rng.below(counts.buffers), writable: rng.chance(30) },
+            7 if counts.predicate_buffers > 0 && counts.buffer_size >= PREDICATE_SIZE => {
+                if predicating {
+                    predicating = false;
+                    RenderOp::EndPredication
+                } else {
+                    predicating = true;
+                    RenderOp::BeginPredication {
+                        buffer: rng.below(counts.predicate_buffers),
+                        index: rng.below(counts.buffer_size / PREDICATE_SIZE),
+                        draw_if_zero: rng.chance(50),
+                    }
+                }
+            }
+            8 | 9 if pipeline_set => RenderOp::Draw {
+                primitive: rng.below(PRIMITIVES),
+                first_vertex: rng.below(1024),
+                vertex_count: rng.below(64),
+                instance_count: rng.below(4),
+                base_instance: rng.below(16),
+            },
+            10 if pipeline_set && index_buffer.map_or(false, |n| n > 0) => {
+                let available = index_buffer.unwrap();
+                let first = rng.below(available);
+                RenderOp::DrawIndexed {
+                    primitive: rng.below(PRIMITIVES),
+                    first_index: first,
+                    index_count: rng.below(std::cmp::min(available - first, 64) + 1),
+                    instance_count: rng.below(4),
+                    base_vertex: rng.below(64) as int - 32,
+                    base_instance: rng.below(16),
+                }
+            }
+            _ => continue,
+        };
+        ops.push(op);
+    }
+    if predicating {
+        ops.push(RenderOp::EndPredication);
+    }
+    ops
+}
+
+/// Record `ops` into `encoder`.
+pub fn replay<'a, D: Device, E: RenderCommandEncoder<D>>(encoder: &mut E, resources: &FuzzResources<'a, D>, ops: &[RenderOp]) {
+    let zeros = [0u8, ..1024];
+    for op in ops.iter() {
+        match *op {
+            RenderOp::SetPipeline(i) => encoder.set_render_pipeline(&resources.pipelines[i]),
+            RenderOp::SetVertexBuffer { buffer, offset, index } => encoder.set_vertex_buffer(BufferSlice::from_offset(&resources.buffers[buffer], offset), index),
+            RenderOp::SetVertexBytes { length, index } => encoder.set_vertex_bytes(zeros.slice_to(length), index),
+            RenderOp::SetScissorRect { x, y, width, height } =>
+                encoder.set_scissor_rect(ScissorRect { x: x, y: y, width: width, height: height }),
+            RenderOp::SetBindGroup(i) => encoder.set_bind_group(&resources.bind_groups[i], 0),
+            RenderOp::SetIndexBuffer { buffer, offset, uint32 } =>
+                encoder.set_index_buffer(BufferSlice::from_offset(&resources.buffers[buffer], offset),
+                                         if uint32 { IndexFormat::UInt32 } else { IndexFormat::UInt16 }),
+            RenderOp::UseBuffer { buffer, writable } =>
+                encoder.use_resource(ResourceRef::Buffer(&resources.buffers[buffer]), writable),
+            RenderOp::BeginPredication { buffer, index, draw_if_zero } =>
+                encoder.begin_predication(&resources.buffers[buffer], index * PREDICATE_SIZE,
+                                          if draw_if_zero { PredicationOp::DrawIfZero } else { PredicationOp::DrawIfNonZero }),
+            RenderOp::EndPredication => encoder.end_predication(),
+            RenderOp::Draw { primitive: p, first_vertex, vertex_count, instance_count, base_instance } =>
+                encoder.draw(primitive(p), first_vertex, vertex_count, instance_count, base_instance),
+            RenderOp::DrawIndexed { primitive: p, first_index, index_count, instance_count, base_vertex, base_instance } =>
+                encoder.draw_indexed(primitive(p), first_index, index_count, instance_count, base_vertex, base_instance),
+        }
+    }
+}
+
+/// Generate a valid sequence of `length` compute encoder commands.
+pub fn generate_compute(seed: u64, counts: &FuzzCounts, length: uint) -> Vec<ComputeOp> {
+    let mut rng = Rng::new(seed);
+    let mut ops = Vec::with_capacity(length);
+    let mut pipeline_set = false;
+
+    while ops.len() < length {
+        let op = match rng.below(6) {
+            0 if counts.compute_pipelines > 0 => {
+                pipeline_set = true;
+                ComputeOp::SetPipeline(rng.below(counts.compute_pipelines))
+            }
+            1 if counts.buffers > 0 && counts.buffer_size >= 4 => ComputeOp::SetBuffer {
+                buffer: rng.below(counts.buffers),
+                offset: rng.below(counts.buffer_size / 4) * 4,
+                index: rng.below(4),
+            },
+            2 => ComputeOp::SetBytes { length: (rng.below(64) + 1) * 16, index: 4 + rng.below(2) },
+            3 if counts.buffers > 0 => ComputeOp::UseBuffer { buffer: rng.below(counts.buffers), writable: rng.chance(30) },
+            4 | 5 if pipeline_set => ComputeOp::Dispatch {
+                threadgroups: [rng.below(8), rng.below(4), rng.below(2) + 1],
+                threads_per_threadgroup: [rng.below(8) + 1, rng.below(4) + 1, 1],
+            },
+            _ => continue,
+        };
+        ops.push(op);
+    }
+    ops
+}
+
+/// Record `ops` into `encoder`.
+pub fn replay_compute<'a, D: Device, E: ComputeCommandEncoder<D>>(encoder: &mut E, resources: &FuzzResources<'a, D>,
+                                                                   ops: &[ComputeOp]) {
+    let zeros = [0u8, ..1024];
+    for op in ops.iter() {
+        match *op {
+            ComputeOp::SetPipeline(i) => encoder.set_compute_pipeline(&resources.compute_pipelines[i]),
+            ComputeOp::SetBuffer { buffer, offset, index } =>
+                encoder.set_buffer(BufferSlice::from_offset(&resources.buffers[buffer], offset), index),
+            ComputeOp::SetBytes { length, index } => encoder.set_bytes(zeros.slice_to(length), index),
+            ComputeOp::UseBuffer { buffer, writable } =>
+                encoder.use_resource(ResourceRef::Buffer(&resources.buffers[buffer]), writable),
+            ComputeOp::Dispatch { threadgroups: g, threads_per_threadgroup: t } =>
+                encoder.dispatch_threadgroups(Size { width: g[0], height: g[1], depth: g[2] },
+                                              Size { width: t[0], height: t[1], depth: t[2] }),
+        }
+    }
+}
+
+/// Generate a valid sequence of `length` blit encoder commands, or none if `counts` has no
+/// buffers of at least 4 bytes.
+pub fn generate_blit(seed: u64, counts: &FuzzCounts, length: uint) -> Vec<BlitOp> {
+    let mut rng = Rng::new(seed);
+    let words = counts.buffer_size / 4;
+    if counts.buffers == 0 || words == 0 {
+        return Vec::new();
+    }
+    let mut ops = Vec::with_capacity(length);
+
+    while ops.len() < length {
+        let size = rng.below(words) + 1;
+        let offset = rng.below(words - size + 1);
+        let op = if rng.chance(50) {
+            let buffer = rng.below(counts.buffers);
+            BlitOp::Fill { buffer: buffer, offset: offset * 4, size: size * 4, value: rng.next() as u8 }
+        } else {
+            let (src, dst) = (rng.below(counts.buffers), rng.below(counts.buffers));
+            let dst_offset = rng.below(words - size + 1);
+            // copies within a buffer must not overlap
+            if src == dst && offset < dst_offset + size && dst_offset < offset + size {
+                continue;
+            }
+            BlitOp::Copy { src: src, src_offset: offset * 4, dst: dst, dst_offset: dst_offset * 4, size: size * 4 }
+        };
+        ops.push(op);
+    }
+    ops
+}
+
+/// Record `ops` into `encoder`.
+pub fn replay_blit<'a, D: Device, E: BlitCommandEncoder<D>>(encoder: &mut E, resources: &FuzzResources<'a, D>, ops: &[BlitOp]) {
+    for op in ops.iter() {
+        match *op {
+            BlitOp::Fill { buffer, offset, size, value } =>
+                encoder.fill_buffer(BufferSlice::new(&resources.buffers[buffer], offset, size), value),
+            BlitOp::Copy { src, src_offset, dst, dst_offset, size } =>
+                encoder.copy_buffer(BufferSlice::new(&resources.buffers[src], src_offset, size),
+                                    &resources.buffers[dst], dst_offset),
+        }
+    }
+}
+
+/// The number of commands `run` generates for each kind of encoder.
+pub const RUN_LENGTH: uint = 200;
+
+/// How long `run` waits for a seed's command buffer before reporting it deadlocked, in
+/// milliseconds.
+pub const RUN_TIMEOUT_MS: uint = 10000;
+
+/// The sequences of a seed, one for each kind of encoder.
+#[deriving(Show, Clone)]
+pub struct FuzzSequence {
+    pub seed: u64,
+    pub render: Vec<RenderOp>,
+    pub compute: Vec<ComputeOp>,
+    pub blit: Vec<BlitOp>,
+}
+
+impl FuzzSequence {
+    /// Generate the sequences of `seed`, each of `length` commands.
+    pub fn generate(seed: u64, counts: &FuzzCounts, length: uint) -> FuzzSequence {
+        FuzzSequence {
+            seed: seed,
+            render: generate(seed, counts, length),
+            compute: generate_compute(seed, counts, length),
+            blit: generate_blit(seed, counts, length),
+        }
+    }
+}
+
+/// Why a seed failed in `run`.
+#[deriving(Show)]
+pub enum FuzzFailure {
+    /// Encoding the seed's passes panicked, with this message.
+    Panicked { seed: u64, message: String },
+    /// The seed's command buffer failed.
+    Failed { seed: u64, error: CommandBufferError },
+    /// The seed's command buffer did not complete within `RUN_TIMEOUT_MS`.
+    Deadlocked { seed: u64 },
+}
+
+impl FuzzFailure {
+    /// The seed which failed, to replay it with `FuzzSequence::generate`.
+    pub fn seed(&self) -> u64 {
+        match *self {
+            FuzzFailure::Panicked { seed, .. } | FuzzFailure::Failed { seed, .. } => seed,
+            FuzzFailure::Deadlocked { seed } => seed,
+        }
+    }
+}
+
+fn panic_message(payload: &Box<Any + Send>) -> String {
+    match payload.downcast_ref::<&'static str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "a panic without a message".to_string(),
+        },
+    }
+}
+
+/// Run the sequences of every seed of `seeds`, stopping at the first which fails.
+///
+/// `make_pass` encodes the seed's sequences into a command buffer, however many passes of each
+/// kind the caller wants (typically a compute pass, a blit pass and a render pass, with the
+/// resources `counts` describes), commits it, and returns a fence its completed handler signals.
+/// It runs in a task of its own for each seed, so a panic is caught and reported with the seed
+/// rather than ending the run; the device it encodes with must be shareable between tasks.
+pub fn run<F>(seeds: Range<u64>, counts: &FuzzCounts, make_pass: F) -> Result<(), FuzzFailure>
+        where F: Fn(&FuzzSequence) -> Fence + Send + Sync {
+    enter_span!("fuzz::run", first = seeds.start, end = seeds.end);
+    let make_pass = Arc::new(make_pass);
+    for seed in seeds {
+        let sequence = FuzzSequence::generate(seed, counts, RUN_LENGTH);
+        let make_pass = make_pass.clone();
+        let fence = match std::task::try(move || (*make_pass)(&sequence)) {
+            Ok(fence) => fence,
+            Err(payload) => return Err(FuzzFailure::Panicked { seed: seed, message: panic_message(&payload) }),
+        };
+        match fence.wait_timeout(RUN_TIMEOUT_MS) {
+            Some(CommandBufferStatus::Completed) => {}
+            Some(CommandBufferStatus::Failed(error)) => return Err(FuzzFailure::Failed { seed: seed, error: error }),
+            None => return Err(FuzzFailure::Deadlocked { seed: seed }),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+//! Golden image tests: comparing what a device rendered against reference images.
+//!
+//! A test renders into an `RGBA8Unorm` (or `RGBA8UnormSrgb`) texture however it likes, then hands
+//! the texture to `check_golden` along with the path of its reference image:
+//!
+//! ```ignore
+//! render_scene(&mut device, &target);
+//! try!(golden::check_golden(&mut device, &target, 256, 256, &Path::new("golden/shadows.pam"), Tolerance::default()));
+//! ```
+//!
+//! Images are compared perceptually: a pixel differs if its CIE76 color difference (ΔE, with
+//! about 2.3 being just noticeable) from the reference is above the tolerance, and the comparison
+//! fails if more than a fraction of the pixels differ. Backends rasterize and filter slightly
+//! differently, so exact comparisons would fail for no visible reason.
+//!
+//! On failure, the rendered image and a difference image (differing pixels in red over a faded
+//! copy of the reference) are written next to the reference, as `<reference>.actual.pam` and
+//! `<reference>.diff.pam`. With the `UPDATE_GOLDEN` environment variable set, missing or failing
+//! references are replaced by the rendered image instead.
+//!
+//! Images are stored as uncompressed PAM (`P7`, `RGB_ALPHA`) files, which need no dependencies to
+//! read and write, and which most image viewers and converters understand.
+
+use std::io::{File, IoError};
+
+use {Device, Origin, Size};
+
+/// An 8-bit RGBA image, row by row from the top.
+#[deriving(Clone)]
+pub struct Image {
+    pub width: uint,
+    pub height: uint,
+    pub pixels: Vec<u8>,
+}
+
+/// Why a PAM file could not be decoded.
+#[deriving(Show)]
+pub enum PamError {
+    BadHeader,
+    /// Only 8-bit `RGB_ALPHA` files are supported.
+    Unsupported,
+    Truncated,
+}
+
+impl Image {
+    /// Encode the image as a PAM file.
+    pub fn encode_pam(&self) -> Vec<u8> {
+        let mut out = format!("P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+                              self.width, self.height).into_bytes();
+        out.push_all(self.pixels.as_slice());
+        out
+    }
+
+    /// Decode an 8-bit `RGB_ALPHA` PAM file.
+    pub fn decode_pam(data: &[u8]) -> Result<Image, PamError> {
+        let end = "ENDHDR\n";
+        let header_len = match range(0, data.len()).find(|&i| data.slice_from(i).starts_with(end.as_bytes())) {
+            Some(i) => i + end.len(),
+            None => return Err(PamError::BadHeader),
+        };
+        let header = match std::str::from_utf8(data.slice_to(header_len)) {
+            Some(header) => header,
+            None => return Err(PamError::BadHeader),
+        };
+        let mut lines = header.lines();
+        if lines.next() != Some("P7") {
+            return Err(PamError::BadHeader);
+        }
+
+        let (mut width, mut height, mut depth, mut maxval) = (None, None, None, None);
+        for line in lines {
+            let mut words = line.words();
+            match (words.next(), words.next()) {
+                (Some("WIDTH"), Some(n)) => width = from_str::<uint>(n),
+                (Some("HEIGHT"), Some(n)) => height = from_str::<uint>(n),
+                (Some("DEPTH"), Some(n)) => depth = from_str::<uint>(n),
+                (Some("MAXVAL"), Some(n)) => maxval = from_str::<uint>(n),
+                _ => {}
+            }
+        }
+        let (width, height) = match (width, height, depth, maxval) {
+            (Some(w), Some(h), Some(4), Some(255)) => (w, h),
+            (Some(_), Some(_), Some(_), Some(_)) => return Err(PamError::Unsupported),
+            _ => return Err(PamError::BadHeader),
+        };
+        let pixels = data.slice_from(header_len);
+        if pixels.len() < width * height * 4 {
+            return Err(PamError::Truncated);
+        }
+        Ok(Image { width: width, height: height, pixels: pixels.slice_to(width * height * 4).to_vec() })
+    }
+
+    fn pixel(&self, x: uint, y: uint) -> &[u8] {
+        let i = (y * self.width + x) * 4;
+        self.pixels.slice(i, i + 4)
+    }
+}
+
+/// Read back mip level 0 of an `RGBA8Unorm` or `RGBA8UnormSrgb` texture.
+pub fn read_back<D: Device>(device: &mut D, texture: &D::Texture, width: uint, height: uint) -> Image {
+    let mut pixels = Vec::from_elem(width * height * 4, 0u8);
+    device.read_texture(texture, 0, 0, Origin { x: 0, y: 0, z: 0 }, Size { width: width, height: height, depth: 1 },
+                        pixels.as_mut_slice(), width * 4);
+    Image { width: width, height: height, pixels: pixels }
+}
+
+/// How different an image may be from its reference.
+pub struct Tolerance {
+    /// The largest color difference (ΔE) a pixel may have without counting as different.
+    pub max_delta_e: f32,
+    /// The fraction of pixels which may differ.
+    pub max_differing_fraction: f32,
+}
+
+impl Tolerance {
+    /// Allows differences which are barely noticeable, in at most a thousandth of the pixels.
+    pub fn default() -> Tolerance {
+        Tolerance { max_delta_e: 2.3, max_differing_fraction: 0.001 }
+    }
+}
+
+/// The result of comparing two images of the same size.
+pub struct Comparison {
+    /// The number of pixels whose difference exceeds the tolerance.
+    pub differing: uint,
+    /// The largest difference of any pixel.
+    pub max_delta_e: f32,
+    /// Whether the difference is within the tolerance.
+    pub passed: bool,
+    pub diff: Image,
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// CIE L*a*b* of an sRGB color, with alpha premultiplied in (over black) so that differences in
+/// transparent pixels count for less.
+fn lab(p: &[u8]) -> [f32, ..3] {
+    let a = p[3] as f32 / 255.0;
+    let (r, g, b) = (srgb_to_linear(p[0]) * a, srgb_to_linear(p[1]) * a, srgb_to_linear(p[2]) * a);
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f32| if t > 0.008856 { t.powf(1.0 / 3.0) } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Compare `actual` against `reference`, which must be the same size.
+pub fn compare(actual: &Image, reference: &Image, tolerance: &Tolerance) -> Comparison {
+    assert!(actual.width == reference.width && actual.height == reference.height);
+    let mut diff = Vec::with_capacity(reference.pixels.len());
+    let mut differing = 0;
+    let mut max_delta_e = 0.0f32;
+    for y in range(0, reference.height) {
+        for x in range(0, reference.width) {
+            let (a, r) = (lab(actual.pixel(x, y)), lab(reference.pixel(x, y)));
+            let delta_e = ((a[0] - r[0]).powi(2) + (a[1] - r[1]).powi(2) + (a[2] - r[2]).powi(2)).sqrt();
+            max_delta_e = max_delta_e.max(delta_e);
+            if delta_e > tolerance.max_delta_e {
+                differing += 1;
+                diff.push_all(&[255, 0, 0, 255]);
+            } else {
+                let p = reference.pixel(x, y);
+                diff.push_all(&[p[0] / 4 + 96, p[1] / 4 + 96, p[2] / 4 + 96, 255]);
+            }
+        }
+    }
+    let pixel_count = reference.width * reference.height;
+    Comparison {
+        differing: differing,
+        max_delta_e: max_delta_e,
+        passed: differing as f32 <= tolerance.max_differing_fraction * pixel_count as f32,
+        diff: Image { width: reference.width, height: reference.height, pixels: diff },
+    }
+}
+
+/// Why a golden image check failed.
+#[deriving(Show)]
+pub enum GoldenError {
+    /// There is no reference image (and `UPDATE_GOLDEN` is not set).
+    MissingReference,
+    BadReference(PamError),
+    /// The reference image is `(width, height)`, which is not the size rendered.
+    SizeMismatch(uint, uint),
+    /// Too many pixels differ.
+    Mismatch { differing: uint, max_delta_e: f32 },
+    Io(IoError),
+}
+
+fn write(path: &Path, image: &Image) -> Result<(), GoldenError> {
+    File::create(path).write(image.encode_pam().as_slice()).map_err(GoldenError::Io)
+}
+
+/// Read back `texture` and compare it against the reference image at `reference`.
+///
+/// See the module documentation for what happens on failure.
+pub fn check_golden<D: Device>(device: &mut D, texture: &D::Texture, width: uint, height: uint,
+                               reference: &Path, tolerance: Tolerance) -> Result<(), GoldenError> {
+    let actual = read_back(device, texture, width, height);
+    let update = std::os::getenv("UPDATE_GOLDEN").is_some();
+
+    let expected = match File::open(reference).read_to_end() {
+        Ok(data) => match Image::decode_pam(data.as_slice()) {
+            Ok(image) => image,
+            Err(e) => return Err(GoldenError::BadReference(e)),
+        },
+        Err(_) if update => return write(reference, &actual),
+        Err(_) => return Err(GoldenError::MissingReference),
+    };
+    if expected.width != width || expected.height != height {
+        if update {
+            return write(reference, &actual);
+        }
+        return Err(GoldenError::SizeMismatch(expected.width, expected.height));
+    }
+
+    let comparison = compare(&actual, &expected, &tolerance);
+    if comparison.passed {
+        return Ok(());
+    }
+    if update {
+        return write(reference, &actual);
+    }
+    try!(write(&Path::new(format!("{}.actual.pam", reference.display())), &actual));
+    try!(write(&Path::new(format!("{}.diff.pam", reference.display())), &comparison.diff));
+    Err(GoldenError::Mismatch { differing: comparison.differing, max_delta_e: comparison.max_delta_e })
+}
@@ -1,102 +1,101 @@
-pub trait Resource;
-pub trait Buffer : Resource { }
-pub trait Texture : Resource { }
-pub trait Sampler;
-pub trait ShaderProgram;
-pub trait CommandQueue;
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "tracing", feature(phase))]
 
-pub struct BufferHints;
-pub struct TextureDescriptor;
-pub struct SamplerDescriptor;
-pub struct DepthStencilStateDescriptor;
-pub struct RenderPipelineDescriptor;
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+#[cfg(not(feature = "std"))]
+extern crate collections;
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "egui")]
+extern crate egui;
+#[cfg(feature = "capi")]
+extern crate libc;
+#[cfg(feature = "bench")]
+extern crate criterion;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "async_std_runtime")]
+extern crate "async-std" as async_std;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(any(feature = "pipeline_loader", feature = "remote"))]
+extern crate serde_json;
+#[cfg(feature = "tokio_runtime")]
+extern crate tokio;
+#[cfg(feature = "tracing")]
+#[phase(plugin, link)]
+extern crate tracing;
 
-pub trait Device {
-    // error types. use proposed ToError/Error trait instead of Show.
-    type ShaderProgramCreationError : Show;
-    type CommandQueueCreationError : Show;
-    type BufferCreationError : Show;
-    type SamplerCreationError : Show;
-    type RenderPipelineCreationError : Show;
-
-    // input types
-    type ShaderProgramInput;
-
-    // "handle" types
-    type ShaderProgram : ShaderProgram;
-    type CommandQueue : CommandQueue;
-    type Buffer : Buffer;
-    type Texture : Texture;
-    type Sampler : Sampler;
-    type DepthStencilState;
-    type RenderPipeline;
-
-    /// Create a new shader program.
-    ///
-    /// The new shader program is a "ready to use" shader program that can be used for rendering.
-    ///
-    /// See the `ShaderProgram` trait for more details on how a shader program is used.
-    fn create_shader_program(&mut self, source: ShaderProgramInput) -> Result<ShaderProgram, ShaderProgramCreationError>;
-
-    /// Create a new command queue.
-    ///
-    /// The command queue will have a maximum of `count` uncompleted command buffers if it is
-    /// `Some`, otherwise it will be unbounded and grow as necessary.
-    ///
-    /// See the `CommandQueue` trait for more details on how a `CommandQueue` is used.
-    fn create_command_queue(&mut self, count: Option<uint>) -> Result<CommandQueue, CommandQueueCreationError>;
-
-    /// Create a new buffer.
-    ///
-    /// The buffer will have the capacity to store `length` bytes. If `Some`, the `hints` will
-    /// influence the behavior of the created buffer. Otherwise, the default hints shall be used.
-    /// These hints cannot be changed after creation.
-    ///
-    /// See the `BufferHints` struct for the exact set of hints which may be used.
-    fn create_buffer(&mut self, length: uint, hints: Option<BufferHints>) -> Result<Buffer, BufferCreationError>;
-
-    // todo: no-copy buffer creation, `newBufferWithBytesNoCopy:length:options:deallocator` in
-    // metal-speak. in particular, it takes a page-aligned buffer and shares it with the gpu
-    // directly, calling the deallocator function when it is done with it.
-
-    /// Create a new texture.
-    ///
-    /// The `desc` describes the layout that this texture has.
-    ///
-    /// See the `TextureDescriptor` struct for the exact set of properties that a texture
-    /// encompasses.
-    fn create_texture(&mut self, desc: TextureDescriptor) -> Result<Texture, TextureCreationError>;
-
-    /// Create a new sampler.
-    ///
-    /// A sampler is a description of how a shader should sample a texture. Once created, it cannot
-    /// be changed. The `desc` describes the properties the sampler will have.
-    ///
-    /// See the `SamplerDescriptor` struct for the exact set of properties that a sampler
-    /// encompasses.
-    fn create_sampler(&mut self, desc: SamplerDescriptor) -> Result<Sampler, SamplerCreationError>;
-
-    /// Create a new depth/stencil state.
-    ///
-    /// This depth/stencil test state is used to configure the depth and stencil stages of the
-    /// rendering pipeline. The `desc` is the configuration that this state will use.
-    ///
-    /// See the `DepthStencilStateDescriptor` struct for the configuration options available.
+// Enter a `tracing` span for the rest of the enclosing block, when built with the `tracing`
+// feature, so the crate's own work shows up in profilers alongside the application's: creation,
+// encoding, the crate's own submission paths and fence waits. Named apart from tracing's own
+// `span!`, which the crate imports with the rest of its macros.
+macro_rules! enter_span {
+    ($name:expr $(, $field:ident = $value:expr)*) => (
+        #[cfg(feature = "tracing")]
+        let _span = trace_span!($name $(, $field = $value)*).entered();
+    )
+}
 
-    // note: this can't fail. There really isn't any work a device would have to do for this, and I
-    // suspect most implementations will use the DepthStencilStateDescriptor as the
-    // DepthStencilState directly!
-    fn create_depth_stencil_state(&mut self, desc: DepthStencilStateDescriptor) -> DepthStencilState;
+#[cfg(feature = "std")]
+pub mod adapter_snapshot;
+#[cfg(feature = "std")]
+pub mod any_device;
+pub mod api;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "std")]
+pub mod command_recording;
+#[cfg(feature = "std")]
+pub mod conformance;
+#[cfg(feature = "std")]
+pub mod fuzz;
+#[cfg(feature = "std")]
+pub mod golden;
+#[cfg(feature = "std")]
+pub mod limits_override;
+#[cfg(feature = "material")]
+pub mod material;
+#[cfg(feature = "openxr")]
+pub mod openxr;
+#[cfg(feature = "pipeline_loader")]
+pub mod pipeline_loader;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "std")]
+pub mod resource_ids;
+#[cfg(feature = "std")]
+pub mod samples;
+pub mod shader_archive;
+#[cfg(feature = "pipeline_loader")]
+pub mod shader_permutations;
+#[cfg(feature = "std")]
+pub mod shader_preprocessor;
+#[cfg(feature = "shared_swapchain")]
+pub mod shared_swapchain;
+#[cfg(feature = "std")]
+pub mod texture;
+#[cfg(feature = "std")]
+pub mod typed_bindings;
+#[cfg(feature = "std")]
+pub mod util;
+pub mod vertex_pulling;
+#[cfg(feature = "xr")]
+pub mod xr;
 
-    /// Create a render pipeline.
-    ///
-    /// A render pipeline encodes the state required to issue a draw call. It can be relatively
-    /// expensive to construct a render pipeline, so it should be done as infrequently as possible.
-    /// The `desc` contains the state the render pipeline encodes.
-    ///
-    /// See the `RenderPipelineDescriptor` struct for the state that must be set.
-    fn create_render_pipeline(&mut self, desc: RenderPipelineDescriptor) -> Result<RenderPipeline, RenderPipelineCreationError>;
+pub use api::*;
+#[cfg(feature = "std")]
+pub use any_device::{AnyDevice, DeviceObject, auto};
+pub use shader_archive::ShaderArchive;
 
-    // todo: pipeline reflection
-    // todo: compute
+// `deriving` refers to `std`, which is `core` and `collections` without the `std` feature.
+#[cfg(not(feature = "std"))]
+mod std {
+    pub use core::{clone, cmp, default, fmt, hash, iter, kinds, option};
+    pub use collections::vec;
 }
@@ -0,0 +1,1214 @@
+//! Running on a device as if it were a lesser one, for portability testing.
+//!
+//! A `LimitsOverrideDevice` wraps a device of any backend and reports lower features, limits and
+//! descriptor indexing tier than the device has, such as those of a mobile GPU, and fails
+//! whatever the lesser device would fail: creating a pipeline with a vertex format it lacks, a
+//! bind group layout with an unsized or partially bound binding, a texture with more samples
+//! than it can do. Desktop developers find out that their renderer needs more than the target
+//! hardware has without having the hardware:
+//!
+//! ```ignore
+//! let device = try!(adapter.open(desc));
+//! // the baseline limits, and no optional features
+//! let mut device = LimitsOverrideDevice::new(device, Default::default(), DeviceLimits::baseline(),
+//!                                            DescriptorIndexingTier::Uniform);
+//! let renderer = try!(Renderer::new(&mut device));
+//! ```
+//!
+//! Creation which the lesser device would fail returns `LimitError::Exceeded`, naming the
+//! features and limits it needs. Encoding which it would not allow (a dispatch with more threads
+//! per threadgroup than `max_threads_per_threadgroup`, a timestamp write without
+//! `timestamp_writes`) panics, as invalid render pass descriptors do. Creating a texture from a
+//! descriptor no device could create returns `LimitError::InvalidDescriptor` (see
+//! `texture::math::validate_descriptor`). Where a missing feature makes a call do nothing
+//! (`set_long_running`, `set_shader_debug_callback`) or be emulated
+//! (`multi_draw_indexed_indirect_count`), the wrapper behaves as the lesser device would.
+//!
+//! The wrapper is a `Device` itself, so it can be handed to `AnyDevice` and everything in `util`.
+//! Its resources, pipelines and other objects are those of the wrapped device, apart from bind
+//! group layouts, which remember what their bindings need.
+
+use {Device, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindingCount, BindingResource, BindingType};
+use {BlitCommandEncoder, BufferHints, BufferSlice, ClearAttachment, CommandBuffer, CommandBufferPooling};
+use {CommandBufferPoolStatistics, CommandBufferStatus, CommandQueue, DeterministicReplay};
+use {ComputeCommandEncoder, ComputePipelineDescriptor, CooperativeMatrixProperties, DepthStencilStateDescriptor};
+use {DescriptorIndexingTier, DeviceFeatures, DeviceLimits, DrawIndexedIndirectArgs, FormatCapabilities, FrameStatistics};
+use {IndexFormat, LatencyMarker, NumericValidationReport, Origin, PipelineCreationFeedback, PixelFormat, PowerState};
+use {PrimitiveType, RenderCommandEncoder, RenderPassColorAttachment, RenderPassDepthAttachment, RenderPassDescriptor};
+use {QueuePriority, RenderPassStencilAttachment, RenderPipelineDescriptor, ResidencyPriority, ResourceRef, Robustness};
+use {BoundsViolationReport, SamplerDescriptor, ScissorRect, Severity, ShaderArchive, ShaderDebugMessage, ShaderProgram};
+use {Size, SubgroupProperties, TextureDescriptor, TextureViewDescriptor, TimestampCalibration, TriangleFillMode, Viewport};
+use {HeapDescriptor, PlacementRequirements, PredicationOp, Texture, VisibilityResultMode, Watchdog};
+use texture::math::{TextureError, validate_data, validate_descriptor, validate_region, validate_view};
+#[cfg(feature = "debug_state")]
+use {BoundBuffer, ComputeEncoderState, RenderEncoderState};
+#[cfg(feature = "raw_handles")]
+use RawHandle;
+#[cfg(feature = "openxr")]
+use openxr::GraphicsBinding;
+#[cfg(feature = "shared_swapchain")]
+use shared_swapchain::ExternalHandle;
+#[cfg(feature = "xr")]
+use xr::{RasterizationRateMapDescriptor, XrRenderPass};
+
+/// Why a `LimitsOverrideDevice` could not create an object.
+#[deriving(Show)]
+pub enum LimitError<E> {
+    /// The object needs these features or limits, which the device reports it lacks.
+    Exceeded(Vec<&'static str>),
+    /// The wrapped device failed to create it.
+    Backend(E),
+    /// The texture descriptor describes a texture no device could create.
+    InvalidDescriptor(TextureError),
+}
+
+fn exceeded<T, E>(names: Vec<&'static str>) -> Result<T, LimitError<E>> {
+    Err(LimitError::Exceeded(names))
+}
+
+fn backend<T, E>(result: Result<T, E>) -> Result<T, LimitError<E>> {
+    result.map_err(LimitError::Backend)
+}
+
+fn valid<E>(desc: &TextureDescriptor) -> Result<(), LimitError<E>> {
+    validate_descriptor(desc).map_err(LimitError::InvalidDescriptor)
+}
+
+/// What the wrapper reports, shared with its queues, command buffers and encoders.
+#[deriving(Clone)]
+struct Limits {
+    features: DeviceFeatures,
+    limits: DeviceLimits,
+    descriptor_indexing_tier: DescriptorIndexingTier,
+}
+
+/// A device which reports lower features and limits than it has, and validates against them.
+/// See the module documentation.
+pub struct LimitsOverrideDevice<D: Device> {
+    device: D,
+    limits: Limits,
+}
+
+impl<D: Device> LimitsOverrideDevice<D> {
+    /// Wrap `device`, reporting `features` and `limits` instead of its own, and the lesser of
+    /// `descriptor_indexing_tier` and its own.
+    ///
+    /// The override can only lower what the device has: panics if `device` lacks any of
+    /// `features`, or does not meet `limits`. It must also stay a device which could exist:
+    /// panics if `features` has either bindless feature and the tier is below `NonUniform`.
+    pub fn new(device: D, features: DeviceFeatures, limits: DeviceLimits, descriptor_indexing_tier: DescriptorIndexingTier)
+               -> LimitsOverrideDevice<D> {
+        let missing = device.features().missing(&features);
+        if !missing.is_empty() {
+            panic!("cannot override a device's features with features it lacks: {}", missing);
+        }
+        let unmet = device.limits().unmet(&limits);
+        if !unmet.is_empty() {
+            panic!("cannot override a device's limits with better limits: {}", unmet);
+        }
+        let own_tier = device.descriptor_indexing_tier();
+        let tier = if descriptor_indexing_tier < own_tier { descriptor_indexing_tier } else { own_tier };
+        if (features.bindless_textures || features.bindless_samplers) && tier < DescriptorIndexingTier::NonUniform {
+            panic!("cannot override a device with bindless features to a descriptor indexing tier below NonUniform");
+        }
+        LimitsOverrideDevice {
+            device: device,
+            limits: Limits { features: features, limits: limits, descriptor_indexing_tier: tier },
+        }
+    }
+
+    /// The wrapped device.
+    pub fn inner(&self) -> &D {
+        &self.device
+    }
+
+    /// The wrapped device, mutably. What is done through it is not validated.
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    /// Unwrap the device.
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+
+    fn check_features(&self, required: &DeviceFeatures) -> Vec<&'static str> {
+        self.limits.features.missing(required)
+    }
+
+    fn check_buffer_hints(&self, hints: &Option<BufferHints>) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if let Some(ref hints) = *hints {
+            if hints.stream_output && !self.limits.features.stream_output {
+                missing.push("stream_output");
+            }
+            if hints.device_address && !self.limits.features.buffer_device_address {
+                missing.push("buffer_device_address");
+            }
+        }
+        missing
+    }
+
+    fn check_render_pipeline(&self, program: &D::ShaderProgram, desc: &RenderPipelineDescriptor) -> Vec<&'static str> {
+        let mut exceeded = self.check_features(&program.required_features());
+        if let Some(ref vertex) = desc.vertex_descriptor {
+            for attribute in vertex.attributes.iter() {
+                for name in self.check_features(&attribute.format.required_features()).into_iter() {
+                    if !exceeded.contains(&name) {
+                        exceeded.push(name);
+                    }
+                }
+            }
+            if vertex.layouts.iter().any(|layout| layout.step_rate != 1) && !self.limits.features.instance_step_rate &&
+               !exceeded.contains(&"instance_step_rate") {
+                exceeded.push("instance_step_rate");
+            }
+        }
+        if desc.sample_count > self.limits.limits.max_sample_count {
+            exceeded.push("max_sample_count");
+        }
+        if desc.attachment_formats.as_ref().map_or(false, |formats| formats.color.len() > self.limits.limits.max_color_attachments) {
+            exceeded.push("max_color_attachments");
+        }
+        exceeded
+    }
+}
+
+/// A bind group layout of a `LimitsOverrideDevice`: the wrapped device's layout, and what its
+/// bindings need of the buffers bound to them.
+pub struct LimitedBindGroupLayout<D: Device> {
+    layout: D::BindGroupLayout,
+    /// The offset alignment of each buffer binding, by binding number, and the limit it is.
+    alignments: Vec<(uint, uint, &'static str)>,
+    /// The longest the unsized binding may be, and the limit that is, if the layout has one.
+    max_unsized_length: Option<(uint, &'static str)>,
+}
+
+impl<D: Device> LimitedBindGroupLayout<D> {
+    /// The wrapped device's layout.
+    pub fn inner(&self) -> &D::BindGroupLayout {
+        &self.layout
+    }
+}
+
+fn resource_ref<'a, D: Device>(resource: ResourceRef<'a, LimitsOverrideDevice<D>>) -> ResourceRef<'a, D> {
+    match resource {
+        ResourceRef::Buffer(buffer) => ResourceRef::Buffer(buffer),
+        ResourceRef::Texture(texture) => ResourceRef::Texture(texture),
+    }
+}
+
+fn bind_group_entries<'a, D: Device>(entries: Vec<BindGroupEntry<'a, LimitsOverrideDevice<D>>>) -> Vec<BindGroupEntry<'a, D>> {
+    entries.into_iter().map(|entry| {
+        let resource = match entry.resource {
+            BindingResource::Buffer(slice) => BindingResource::Buffer(slice),
+            BindingResource::Texture(texture) => BindingResource::Texture(texture),
+            BindingResource::Sampler(sampler) => BindingResource::Sampler(sampler),
+        };
+        BindGroupEntry { binding: entry.binding, array_element: entry.array_element, resource: resource }
+    }).collect()
+}
+
+fn no_feedback() -> PipelineCreationFeedback {
+    PipelineCreationFeedback { duration_ms: 0.0, cache_hit: None, compiler_statistics: Vec::new() }
+}
+
+impl<D: Device> Device for LimitsOverrideDevice<D> {
+    type ShaderProgramCreationError = LimitError<D::ShaderProgramCreationError>;
+    type CommandQueueCreationError = D::CommandQueueCreationError;
+    type BufferCreationError = LimitError<D::BufferCreationError>;
+    type TextureCreationError = LimitError<D::TextureCreationError>;
+    type SamplerCreationError = D::SamplerCreationError;
+    type RenderPipelineCreationError = LimitError<D::RenderPipelineCreationError>;
+    type ComputePipelineCreationError = LimitError<D::ComputePipelineCreationError>;
+    type BindGroupLayoutCreationError = LimitError<D::BindGroupLayoutCreationError>;
+    type BindGroupCreationError = LimitError<D::BindGroupCreationError>;
+    type HeapCreationError = LimitError<D::HeapCreationError>;
+
+    type ShaderProgramInput = D::ShaderProgramInput;
+
+    type ShaderProgram = D::ShaderProgram;
+    type CommandQueue = LimitedCommandQueue<D>;
+    type CommandBuffer = LimitedCommandBuffer<D>;
+    type RenderCommandEncoder = LimitedRenderEncoder<D>;
+    type ComputeCommandEncoder = LimitedComputeEncoder<D>;
+    type BlitCommandEncoder = LimitedBlitEncoder<D>;
+    type Buffer = D::Buffer;
+    type Texture = D::Texture;
+    type Sampler = D::Sampler;
+    type DepthStencilState = D::DepthStencilState;
+    type RenderPipeline = D::RenderPipeline;
+    type ComputePipeline = D::ComputePipeline;
+    type BindGroupLayout = LimitedBindGroupLayout<D>;
+    type BindGroup = D::BindGroup;
+    type Event = D::Event;
+    type Heap = D::Heap;
+    #[cfg(feature = "xr")]
+    type RasterizationRateMapCreationError = LimitError<D::RasterizationRateMapCreationError>;
+    #[cfg(feature = "xr")]
+    type RasterizationRateMap = D::RasterizationRateMap;
+    #[cfg(feature = "shared_swapchain")]
+    type ExternalHandleError = LimitError<D::ExternalHandleError>;
+
+    fn features(&self) -> DeviceFeatures {
+        self.limits.features.clone()
+    }
+
+    fn subgroup_properties(&self) -> SubgroupProperties {
+        self.device.subgroup_properties()
+    }
+
+    fn cooperative_matrix_properties(&self) -> Vec<CooperativeMatrixProperties> {
+        if self.limits.features.cooperative_matrix {
+            self.device.cooperative_matrix_properties()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn limits(&self) -> DeviceLimits {
+        self.limits.limits.clone()
+    }
+
+    fn deterministic_replay(&self) -> Option<DeterministicReplay> {
+        self.device.deterministic_replay()
+    }
+
+    fn descriptor_indexing_tier(&self) -> DescriptorIndexingTier {
+        self.limits.descriptor_indexing_tier.clone()
+    }
+
+    fn format_capabilities(&self, format: PixelFormat) -> FormatCapabilities {
+        let mut capabilities = self.device.format_capabilities(format);
+        capabilities.multisample = capabilities.multisample && self.limits.limits.max_sample_count > 1;
+        capabilities
+    }
+
+    #[cfg(feature = "raw_handles")]
+    unsafe fn as_raw(&self) -> RawHandle {
+        self.device.as_raw()
+    }
+
+    #[cfg(feature = "openxr")]
+    unsafe fn openxr_graphics_binding(&self, queue: &LimitedCommandQueue<D>) -> Option<GraphicsBinding> {
+        self.device.openxr_graphics_binding(&queue.queue)
+    }
+
+    fn set_error_callback(&mut self, callback: Option<Box<Fn(Severity, &str) + Send + Sync>>) {
+        self.device.set_error_callback(callback);
+    }
+
+    fn watchdog(&self) -> Watchdog {
+        self.device.watchdog()
+    }
+
+    fn power_state(&self) -> PowerState {
+        self.device.power_state()
+    }
+
+    fn set_power_state_callback(&mut self, callback: Option<Box<FnMut(&PowerState) + Send>>) {
+        self.device.set_power_state_callback(callback);
+    }
+
+    fn calibrate_timestamps(&self) -> Option<TimestampCalibration> {
+        self.device.calibrate_timestamps()
+    }
+
+    fn set_latency_marker(&mut self, frame: u64, marker: LatencyMarker) {
+        if self.limits.features.latency_markers {
+            self.device.set_latency_marker(frame, marker);
+        }
+    }
+
+    fn latency_sleep(&mut self) {
+        if self.limits.features.latency_markers {
+            self.device.latency_sleep();
+        }
+    }
+
+    fn set_shader_debug_callback(&mut self, callback: Option<Box<FnMut(&ShaderDebugMessage) + Send>>) {
+        if self.limits.features.shader_debug_printf {
+            self.device.set_shader_debug_callback(callback);
+        }
+    }
+
+    fn set_numeric_validation_callback(&mut self, callback: Option<Box<FnMut(&NumericValidationReport) + Send>>) {
+        self.device.set_numeric_validation_callback(callback);
+    }
+
+    fn set_robustness(&mut self, robustness: Robustness, report: Option<Box<FnMut(&BoundsViolationReport) + Send>>) {
+        let robustness = match robustness {
+            Robustness::Zeroed if !self.limits.features.robust_access_zeroed => Robustness::Clamped,
+            robustness => robustness,
+        };
+        self.device.set_robustness(robustness, report);
+    }
+
+    fn set_statistics_enabled(&mut self, enabled: bool) {
+        self.device.set_statistics_enabled(enabled);
+    }
+
+    fn take_statistics(&mut self) -> FrameStatistics {
+        self.device.take_statistics()
+    }
+
+    fn create_shader_program(&mut self, source: D::ShaderProgramInput) -> Result<D::ShaderProgram, LimitError<D::ShaderProgramCreationError>> {
+        let program = try!(backend(self.device.create_shader_program(source)));
+        let missing = self.check_features(&program.required_features());
+        if !missing.is_empty() {
+            return exceeded(missing);
+        }
+        Ok(program)
+    }
+
+    fn create_shader_program_from_archive(&mut self, archive: &ShaderArchive) -> Result<D::ShaderProgram, LimitError<D::ShaderProgramCreationError>> {
+        let program = try!(backend(self.device.create_shader_program_from_archive(archive)));
+        let missing = self.check_features(&program.required_features());
+        if !missing.is_empty() {
+            return exceeded(missing);
+        }
+        Ok(program)
+    }
+
+    fn create_command_queue(&mut self, count: Option<uint>) -> Result<LimitedCommandQueue<D>, D::CommandQueueCreationError> {
+        let queue = try!(self.device.create_command_queue(count));
+        Ok(LimitedCommandQueue { queue: queue, limits: self.limits.clone() })
+    }
+
+    fn create_command_queue_with_priority(&mut self, count: Option<uint>, priority: QueuePriority) -> Result<LimitedCommandQueue<D>, D::CommandQueueCreationError> {
+        let priority = if self.limits.features.queue_priority { priority } else { QueuePriority::Normal };
+        let queue = try!(self.device.create_command_queue_with_priority(count, priority));
+        Ok(LimitedCommandQueue { queue: queue, limits: self.limits.clone() })
+    }
+
+    fn wait_idle(&mut self) {
+        self.device.wait_idle();
+    }
+
+    fn in_flight_command_buffers(&self) -> uint {
+        self.device.in_flight_command_buffers()
+    }
+
+    #[cfg(feature = "raw_handles")]
+    unsafe fn command_queue_from_raw(&mut self, queue: RawHandle, count: Option<uint>) -> Result<LimitedCommandQueue<D>, D::CommandQueueCreationError> {
+        let queue = try!(self.device.command_queue_from_raw(queue, count));
+        Ok(LimitedCommandQueue { queue: queue, limits: self.limits.clone() })
+    }
+
+    #[cfg(feature = "raw_handles")]
+    unsafe fn buffer_from_raw(&mut self, buffer: RawHandle, length: uint) -> Result<D::Buffer, LimitError<D::BufferCreationError>> {
+        backend(self.device.buffer_from_raw(buffer, length))
+    }
+
+    #[cfg(feature = "raw_handles")]
+    unsafe fn texture_from_raw(&mut self, texture: RawHandle, desc: TextureDescriptor) -> Result<D::Texture, LimitError<D::TextureCreationError>> {
+        try!(valid(&desc));
+        if desc.sample_count > self.limits.limits.max_sample_count {
+            return exceeded(vec!["max_sample_count"]);
+        }
+        backend(self.device.texture_from_raw(texture, desc))
+    }
+
+    fn create_buffer(&mut self, length: uint, hints: Option<BufferHints>) -> Result<D::Buffer, LimitError<D::BufferCreationError>> {
+        let missing = self.check_buffer_hints(&hints);
+        if !missing.is_empty() {
+            return exceeded(missing);
+        }
+        backend(self.device.create_buffer(length, hints))
+    }
+
+    fn write_buffer(&mut self, buffer: &D::Buffer, offset: uint, data: &[u8]) {
+        self.device.write_buffer(buffer, offset, data);
+    }
+
+    fn read_buffer(&mut self, buffer: &D::Buffer, offset: uint, data: &mut [u8]) {
+        self.device.read_buffer(buffer, offset, data);
+    }
+
+    fn write_texture(&mut self, texture: &D::Texture, mipmap_level: uint, slice: uint, origin: Origin, size: Size,
+                     data: &[u8], bytes_per_row: uint) {
+        if let Err(error) = validate_data(texture.descriptor(), mipmap_level, slice, &origin, &size, data.len(),
+                                          bytes_per_row) {
+            panic!("invalid texture write: {}", error);
+        }
+        self.device.write_texture(texture, mipmap_level, slice, origin, size, data, bytes_per_row);
+    }
+
+    fn read_texture(&mut self, texture: &D::Texture, mipmap_level: uint, slice: uint, origin: Origin, size: Size,
+                    data: &mut [u8], bytes_per_row: uint) {
+        if let Err(error) = validate_data(texture.descriptor(), mipmap_level, slice, &origin, &size, data.len(),
+                                          bytes_per_row) {
+            panic!("invalid texture read: {}", error);
+        }
+        self.device.read_texture(texture, mipmap_level, slice, origin, size, data, bytes_per_row);
+    }
+
+    fn create_texture(&mut self, desc: TextureDescriptor) -> Result<D::Texture, LimitError<D::TextureCreationError>> {
+        try!(valid(&desc));
+        if desc.sample_count > self.limits.limits.max_sample_count {
+            return exceeded(vec!["max_sample_count"]);
+        }
+        backend(self.device.create_texture(desc))
+    }
+
+    fn create_texture_view(&mut self, texture: &D::Texture, desc: TextureViewDescriptor) -> Result<D::Texture, LimitError<D::TextureCreationError>> {
+        try!(validate_view(texture.descriptor(), &desc).map_err(LimitError::InvalidDescriptor));
+        backend(self.device.create_texture_view(texture, desc))
+    }
+
+    fn create_heap(&mut self, desc: HeapDescriptor) -> Result<D::Heap, LimitError<D::HeapCreationError>> {
+        if !self.limits.features.placed_resources {
+            return exceeded(vec!["placed_resources"]);
+        }
+        backend(self.device.create_heap(desc))
+    }
+
+    #[cfg(feature = "xr")]
+    fn create_rasterization_rate_map(&mut self, desc: RasterizationRateMapDescriptor)
+                                     -> Result<D::RasterizationRateMap, LimitError<D::RasterizationRateMapCreationError>> {
+        if !self.limits.features.rasterization_rate_maps {
+            return exceeded(vec!["rasterization_rate_maps"]);
+        }
+        backend(self.device.create_rasterization_rate_map(desc))
+    }
+
+    fn buffer_placement_requirements(&self, length: uint, hints: Option<BufferHints>) -> PlacementRequirements {
+        self.device.buffer_placement_requirements(length, hints)
+    }
+
+    fn texture_placement_requirements(&self, desc: &TextureDescriptor) -> PlacementRequirements {
+        self.device.texture_placement_requirements(desc)
+    }
+
+    fn create_placed_buffer(&mut self, heap: &D::Heap, offset: uint, length: uint, hints: Option<BufferHints>)
+                            -> Result<D::Buffer, LimitError<D::BufferCreationError>> {
+        let missing = self.check_buffer_hints(&hints);
+        if !missing.is_empty() {
+            return exceeded(missing);
+        }
+        backend(self.device.create_placed_buffer(heap, offset, length, hints))
+    }
+
+    fn create_placed_texture(&mut self, heap: &D::Heap, offset: uint, desc: TextureDescriptor)
+                             -> Result<D::Texture, LimitError<D::TextureCreationError>> {
+        try!(valid(&desc));
+        if desc.sample_count > self.limits.limits.max_sample_count {
+            return exceeded(vec!["max_sample_count"]);
+        }
+        backend(self.device.create_placed_texture(heap, offset, desc))
+    }
+
+    fn set_residency_priority(&mut self, resource: ResourceRef<LimitsOverrideDevice<D>>, priority: ResidencyPriority) {
+        self.device.set_residency_priority(resource_ref(resource), priority);
+    }
+
+    fn create_sampler(&mut self, desc: SamplerDescriptor) -> Result<D::Sampler, D::SamplerCreationError> {
+        self.device.create_sampler(desc)
+    }
+
+    fn create_depth_stencil_state(&mut self, desc: DepthStencilStateDescriptor) -> D::DepthStencilState {
+        self.device.create_depth_stencil_state(desc)
+    }
+
+    fn create_event(&mut self) -> D::Event {
+        self.device.create_event()
+    }
+
+    fn event_value(&self, event: &D::Event) -> u64 {
+        self.device.event_value(event)
+    }
+
+    fn notify_event(&self, event: &D::Event, value: u64, f: Box<FnOnce() + Send>) {
+        self.device.notify_event(event, value, f);
+    }
+
+    #[cfg(feature = "shared_swapchain")]
+    fn create_shareable_texture(&mut self, desc: TextureDescriptor) -> Result<D::Texture, LimitError<D::TextureCreationError>> {
+        try!(valid(&desc));
+        if !self.limits.features.external_memory {
+            return exceeded(vec!["external_memory"]);
+        }
+        backend(self.device.create_shareable_texture(desc))
+    }
+
+    #[cfg(feature = "shared_swapchain")]
+    fn export_texture(&self, texture: &D::Texture) -> Result<ExternalHandle, LimitError<D::ExternalHandleError>> {
+        backend(self.device.export_texture(texture))
+    }
+
+    #[cfg(feature = "shared_swapchain")]
+    unsafe fn import_texture(&mut self, handle: ExternalHandle, desc: TextureDescriptor)
+                             -> Result<D::Texture, LimitError<D::TextureCreationError>> {
+        try!(valid(&desc));
+        if !self.limits.features.external_memory {
+            return exceeded(vec!["external_memory"]);
+        }
+        backend(self.device.import_texture(handle, desc))
+    }
+
+    #[cfg(feature = "shared_swapchain")]
+    fn create_shareable_event(&mut self) -> Result<D::Event, LimitError<D::ExternalHandleError>> {
+        if !self.limits.features.external_memory {
+            return exceeded(vec!["external_memory"]);
+        }
+        backend(self.device.create_shareable_event())
+    }
+
+    #[cfg(feature = "shared_swapchain")]
+    fn export_event(&self, event: &D::Event) -> Result<ExternalHandle, LimitError<D::ExternalHandleError>> {
+        backend(self.device.export_event(event))
+    }
+
+    #[cfg(feature = "shared_swapchain")]
+    unsafe fn import_event(&mut self, handle: ExternalHandle) -> Result<D::Event, LimitError<D::ExternalHandleError>> {
+        if !self.limits.features.external_memory {
+            return exceeded(vec!["external_memory"]);
+        }
+        backend(self.device.import_event(handle))
+    }
+
+    fn create_render_pipeline(&mut self, program: &D::ShaderProgram, desc: RenderPipelineDescriptor)
+                              -> Result<D::RenderPipeline, LimitError<D::RenderPipelineCreationError>> {
+        let exceeded_limits = self.check_render_pipeline(program, &desc);
+        if !exceeded_limits.is_empty() {
+            return exceeded(exceeded_limits);
+        }
+        backend(self.device.create_render_pipeline(program, desc))
+    }
+
+    #[cfg(feature = "xr")]
+    fn create_multiview_render_pipeline(&mut self, program: &D::ShaderProgram, desc: RenderPipelineDescriptor, view_count: uint)
+                                        -> Result<D::RenderPipeline, LimitError<D::RenderPipelineCreationError>> {
+        let mut exceeded_limits = self.check_render_pipeline(program, &desc);
+        if view_count > 1 && !self.limits.features.multiview {
+            exceeded_limits.push("multiview");
+        }
+        if view_count > self.limits.limits.max_multiview_view_count {
+            exceeded_limits.push("max_multiview_view_count");
+        }
+        if !exceeded_limits.is_empty() {
+            return exceeded(exceeded_limits);
+        }
+        backend(self.device.create_multiview_render_pipeline(program, desc, view_count))
+    }
+
+    fn create_render_pipeline_with_feedback(&mut self, program: &D::ShaderProgram, desc: RenderPipelineDescriptor)
+                                            -> (Result<D::RenderPipeline, LimitError<D::RenderPipelineCreationError>>, PipelineCreationFeedback) {
+        let exceeded_limits = self.check_render_pipeline(program, &desc);
+        if !exceeded_limits.is_empty() {
+            return (exceeded(exceeded_limits), no_feedback());
+        }
+        let (result, feedback) = self.device.create_render_pipeline_with_feedback(program, desc);
+        (backend(result), feedback)
+    }
+
+    fn create_render_pipeline_async(&mut self, program: &D::ShaderProgram, desc: RenderPipelineDescriptor,
+                                    callback: Box<FnOnce(Result<D::RenderPipeline, LimitError<D::RenderPipelineCreationError>>,
+                                                         PipelineCreationFeedback) + Send>) {
+        let exceeded_limits = self.check_render_pipeline(program, &desc);
+        if !exceeded_limits.is_empty() {
+            return callback(exceeded(exceeded_limits), no_feedback());
+        }
+        self.device.create_render_pipeline_async(program, desc, box move |result, feedback| {
+            callback(backend(result), feedback)
+        });
+    }
+
+    fn create_compute_pipeline(&mut self, program: &D::ShaderProgram, desc: ComputePipelineDescriptor)
+                               -> Result<D::ComputePipeline, LimitError<D::ComputePipelineCreationError>> {
+        let mut exceeded_limits = self.check_features(&program.required_features());
+        if !desc.cooperative_matrices.is_empty() && !self.limits.features.cooperative_matrix &&
+           !exceeded_limits.contains(&"cooperative_matrix") {
+            exceeded_limits.push("cooperative_matrix");
+        }
+        let threadgroup_memory = desc.threadgroup_memory_lengths.iter().fold(0, |total, &length| total + length);
+        if threadgroup_memory > self.limits.limits.max_threadgroup_memory_length {
+            exceeded_limits.push("max_threadgroup_memory_length");
+        }
+        if !exceeded_limits.is_empty() {
+            return exceeded(exceeded_limits);
+        }
+        backend(self.device.create_compute_pipeline(program, desc))
+    }
+
+    fn create_bind_group_layout(&mut self, desc: BindGroupLayoutDescriptor)
+                                -> Result<LimitedBindGroupLayout<D>, LimitError<D::BindGroupLayoutCreationError>> {
+        let mut missing = Vec::new();
+        let mut alignments = Vec::new();
+        let mut max_unsized_length = None;
+        for entry in desc.entries.iter() {
+            if (entry.partially_bound && self.limits.descriptor_indexing_tier < DescriptorIndexingTier::PartiallyBound) ||
+               (entry.update_after_bind && self.limits.descriptor_indexing_tier < DescriptorIndexingTier::UpdateAfterBind) {
+                if !missing.contains(&"descriptor_indexing_tier") {
+                    missing.push("descriptor_indexing_tier");
+                }
+            }
+            match (&entry.ty, &entry.count) {
+                (&BindingType::SampledTexture, &BindingCount::Unsized) => {
+                    if !self.limits.features.bindless_textures {
+                        missing.push("bindless_textures");
+                    }
+                    max_unsized_length = Some((self.limits.limits.max_bindless_textures, "max_bindless_textures"));
+                }
+                (&BindingType::Sampler, &BindingCount::Unsized) => {
+                    if !self.limits.features.bindless_samplers {
+                        missing.push("bindless_samplers");
+                    }
+                    max_unsized_length = Some((self.limits.limits.max_bindless_samplers, "max_bindless_samplers"));
+                }
+                (&BindingType::UniformBuffer, _) => {
+                    alignments.push((entry.binding, self.limits.limits.min_uniform_buffer_offset_alignment,
+                                     "min_uniform_buffer_offset_alignment"));
+                }
+                (&BindingType::StorageBuffer { .. }, _) => {
+                    alignments.push((entry.binding, self.limits.limits.min_storage_buffer_offset_alignment,
+                                     "min_storage_buffer_offset_alignment"));
+                }
+                _ => {}
+            }
+        }
+        if !missing.is_empty() {
+            return exceeded(missing);
+        }
+        let layout = try!(backend(self.device.create_bind_group_layout(desc)));
+        Ok(LimitedBindGroupLayout { layout: layout, alignments: alignments, max_unsized_length: max_unsized_length })
+    }
+
+    fn create_bind_group(&mut self, desc: BindGroupDescriptor<LimitsOverrideDevice<D>>)
+                         -> Result<D::BindGroup, LimitError<D::BindGroupCreationError>> {
+        let layout = desc.layout;
+        let mut exceeded_limits = Vec::new();
+        if let Some((max, name)) = layout.max_unsized_length {
+            if desc.unsized_length > max {
+                exceeded_limits.push(name);
+            }
+        }
+        for entry in desc.entries.iter() {
+            if let BindingResource::Buffer(ref slice) = entry.resource {
+                let alignment = layout.alignments.iter().find(|&&(binding, _, _)| binding == entry.binding);
+                if let Some(&(_, alignment, name)) = alignment {
+                    if slice.offset() % alignment != 0 {
+                        if !exceeded_limits.contains(&name) {
+                            exceeded_limits.push(name);
+                        }
+                    }
+                }
+            }
+        }
+        if !exceeded_limits.is_empty() {
+            return exceeded(exceeded_limits);
+        }
+        backend(self.device.create_bind_group(BindGroupDescriptor {
+            layout: &layout.layout,
+            entries: bind_group_entries(desc.entries),
+            unsized_length: desc.unsized_length,
+        }))
+    }
+
+    // todo: the offsets of updated entries are not checked against the lowered alignments, as
+    // bind groups do not know their layout.
+    fn update_bind_group(&mut self, group: &D::BindGroup, entries: Vec<BindGroupEntry<LimitsOverrideDevice<D>>>)
+                         -> Result<(), LimitError<D::BindGroupCreationError>> {
+        backend(self.device.update_bind_group(group, bind_group_entries(entries)))
+    }
+}
+
+/// A command queue of a `LimitsOverrideDevice`.
+pub struct LimitedCommandQueue<D: Device> {
+    queue: D::CommandQueue,
+    limits: Limits,
+}
+
+impl<D: Device> CommandQueue<LimitsOverrideDevice<D>> for LimitedCommandQueue<D> {
+    fn acquire_command_buffer(&mut self) -> LimitedCommandBuffer<D> {
+        LimitedCommandBuffer { commands: self.queue.acquire_command_buffer(), limits: self.limits.clone() }
+    }
+
+    fn try_acquire_command_buffer(&mut self) -> Option<LimitedCommandBuffer<D>> {
+        let limits = &self.limits;
+        self.queue.try_acquire_command_buffer().map(|commands| LimitedCommandBuffer { commands: commands, limits: limits.clone() })
+    }
+
+    fn notify_when_available(&self, f: Box<FnOnce() + Send>) {
+        self.queue.notify_when_available(f);
+    }
+
+    fn capacity(&self) -> Option<uint> {
+        self.queue.capacity()
+    }
+
+    fn priority(&self) -> QueuePriority {
+        self.queue.priority()
+    }
+
+    fn set_pooling(&mut self, pooling: CommandBufferPooling) {
+        self.queue.set_pooling(pooling);
+    }
+
+    fn reset_pool(&mut self) {
+        self.queue.reset_pool();
+    }
+
+    fn pool_statistics(&self) -> CommandBufferPoolStatistics {
+        self.queue.pool_statistics()
+    }
+
+    fn reset_pool_statistics(&mut self) {
+        self.queue.reset_pool_statistics();
+    }
+
+    fn wait_idle(&self) {
+        self.queue.wait_idle();
+    }
+
+    #[cfg(feature = "raw_handles")]
+    unsafe fn as_raw(&self) -> RawHandle {
+        self.queue.as_raw()
+    }
+}
+
+/// Check `desc` against `limits`, panicking as the lesser device would, and unwrap it into a
+/// descriptor of the wrapped device.
+fn render_pass_descriptor<'a, D: Device>(limits: &Limits, desc: RenderPassDescriptor<'a, LimitsOverrideDevice<D>>)
+                                         -> RenderPassDescriptor<'a, D> {
+    if desc.sample_positions.is_some() && !limits.features.programmable_sample_positions {
+        panic!("render pass sample positions need the programmable_sample_positions feature");
+    }
+    if desc.color_attachments.len() > limits.limits.max_color_attachments {
+        panic!("a render pass with {} color attachments exceeds max_color_attachments ({})",
+               desc.color_attachments.len(), limits.limits.max_color_attachments);
+    }
+    RenderPassDescriptor {
+        color_attachments: desc.color_attachments.into_iter().map(|attachment| RenderPassColorAttachment {
+            texture: attachment.texture,
+            mipmap_level: attachment.mipmap_level,
+            slice: attachment.slice,
+            load_action: attachment.load_action,
+            store_action: attachment.store_action,
+            clear_color: attachment.clear_color,
+        }).collect(),
+        depth_attachment: desc.depth_attachment.map(|attachment| RenderPassDepthAttachment {
+            texture: attachment.texture,
+            mipmap_level: attachment.mipmap_level,
+            slice: attachment.slice,
+            load_action: attachment.load_action,
+            store_action: attachment.store_action,
+            clear_depth: attachment.clear_depth,
+        }),
+        stencil_attachment: desc.stencil_attachment.map(|attachment| RenderPassStencilAttachment {
+            texture: attachment.texture,
+            mipmap_level: attachment.mipmap_level,
+            slice: attachment.slice,
+            load_action: attachment.load_action,
+            store_action: attachment.store_action,
+            clear_stencil: attachment.clear_stencil,
+        }),
+        sample_positions: desc.sample_positions,
+        visibility_result_buffer: desc.visibility_result_buffer,
+    }
+}
+
+/// A command buffer of a `LimitsOverrideDevice`.
+pub struct LimitedCommandBuffer<D: Device> {
+    commands: D::CommandBuffer,
+    limits: Limits,
+}
+
+impl<D: Device> CommandBuffer<LimitsOverrideDevice<D>> for LimitedCommandBuffer<D> {
+    fn render_command_encoder(&mut self, desc: RenderPassDescriptor<LimitsOverrideDevice<D>>) -> LimitedRenderEncoder<D> {
+        let desc = render_pass_descriptor(&self.limits, desc);
+        LimitedRenderEncoder { encoder: self.commands.render_command_encoder(desc), limits: self.limits.clone() }
+    }
+
+    #[cfg(feature = "xr")]
+    fn xr_render_command_encoder(&mut self, desc: RenderPassDescriptor<LimitsOverrideDevice<D>>,
+                                 xr: XrRenderPass<LimitsOverrideDevice<D>>) -> LimitedRenderEncoder<D> {
+        if xr.view_count > 1 && !self.limits.features.multiview {
+            panic!("a render pass of {} views needs the multiview feature", xr.view_count);
+        }
+        if xr.view_count > self.limits.limits.max_multiview_view_count {
+            panic!("a render pass of {} views exceeds max_multiview_view_count ({})",
+                   xr.view_count, self.limits.limits.max_multiview_view_count);
+        }
+        if xr.rasterization_rate_map.is_some() && !self.limits.features.rasterization_rate_maps {
+            panic!("a render pass with a rasterization rate map needs the rasterization_rate_maps feature");
+        }
+        let desc = render_pass_descriptor(&self.limits, desc);
+        let xr = XrRenderPass { view_count: xr.view_count, rasterization_rate_map: xr.rasterization_rate_map };
+        LimitedRenderEncoder { encoder: self.commands.xr_render_command_encoder(desc, xr), limits: self.limits.clone() }
+    }
+
+    fn compute_command_encoder(&mut self) -> LimitedComputeEncoder<D> {
+        LimitedComputeEncoder { encoder: self.commands.compute_command_encoder(), limits: self.limits.clone() }
+    }
+
+    fn blit_command_encoder(&mut self) -> LimitedBlitEncoder<D> {
+        LimitedBlitEncoder { encoder: self.commands.blit_command_encoder() }
+    }
+
+    fn write_timestamp(&mut self, buffer: &D::Buffer, offset: uint) {
+        if !self.limits.features.timestamp_writes {
+            panic!("write_timestamp needs the timestamp_writes feature");
+        }
+        self.commands.write_timestamp(buffer, offset);
+    }
+
+    fn push_debug_group(&mut self, label: &str) {
+        self.commands.push_debug_group(label);
+    }
+
+    fn pop_debug_group(&mut self) {
+        self.commands.pop_debug_group();
+    }
+
+    fn encode_signal_event(&mut self, event: &D::Event, value: u64) {
+        self.commands.encode_signal_event(event, value);
+    }
+
+    fn encode_wait_for_event(&mut self, event: &D::Event, value: u64) {
+        self.commands.encode_wait_for_event(event, value);
+    }
+
+    fn add_completed_handler(&mut self, handler: Box<FnOnce(CommandBufferStatus) + Send>) {
+        self.commands.add_completed_handler(handler);
+    }
+
+    fn enqueue(&mut self) {
+        self.commands.enqueue();
+    }
+
+    fn commit(self) {
+        enter_span!("LimitedCommandBuffer::commit");
+        self.commands.commit();
+    }
+
+    fn commit_and_wait(self) -> CommandBufferStatus {
+        enter_span!("LimitedCommandBuffer::commit_and_wait");
+        self.commands.commit_and_wait()
+    }
+
+    fn set_long_running(&mut self) {
+        if self.limits.features.long_running_command_buffers {
+            self.commands.set_long_running();
+        }
+    }
+
+    fn discard_contents(&mut self, resource: ResourceRef<LimitsOverrideDevice<D>>) {
+        self.commands.discard_contents(resource_ref(resource));
+    }
+
+    fn aliasing_barrier(&mut self, before: Option<ResourceRef<LimitsOverrideDevice<D>>>, after: ResourceRef<LimitsOverrideDevice<D>>) {
+        self.commands.aliasing_barrier(before.map(resource_ref), resource_ref(after));
+    }
+}
+
+#[cfg(feature = "debug_state")]
+fn bound_buffers<'a, D: Device>(buffers: Vec<Option<BoundBuffer<'a, D>>>) -> Vec<Option<BoundBuffer<'a, LimitsOverrideDevice<D>>>> {
+    buffers.into_iter().map(|buffer| buffer.map(|buffer| match buffer {
+        BoundBuffer::Buffer(slice) => BoundBuffer::Buffer(slice),
+        BoundBuffer::Bytes(length) => BoundBuffer::Bytes(length),
+    })).collect()
+}
+
+/// A render command encoder of a `LimitsOverrideDevice`.
+pub struct LimitedRenderEncoder<D: Device> {
+    encoder: D::RenderCommandEncoder,
+    limits: Limits,
+}
+
+impl<D: Device> RenderCommandEncoder<LimitsOverrideDevice<D>> for LimitedRenderEncoder<D> {
+    fn set_render_pipeline(&mut self, pipeline: &D::RenderPipeline) {
+        self.encoder.set_render_pipeline(pipeline);
+    }
+
+    fn set_depth_stencil_state(&mut self, state: &D::DepthStencilState) {
+        self.encoder.set_depth_stencil_state(state);
+    }
+
+    fn set_stencil_reference_value(&mut self, value: u32) {
+        self.encoder.set_stencil_reference_value(value);
+    }
+
+    fn set_viewport(&mut self, viewport: Viewport) {
+        self.encoder.set_viewport(viewport);
+    }
+
+    fn set_triangle_fill_mode(&mut self, mode: TriangleFillMode) {
+        if mode == TriangleFillMode::Lines && !self.limits.features.fill_mode_lines {
+            panic!("TriangleFillMode::Lines needs the fill_mode_lines feature");
+        }
+        self.encoder.set_triangle_fill_mode(mode);
+    }
+
+    fn set_depth_bias(&mut self, bias: f32, slope_scale: f32, clamp: f32) {
+        self.encoder.set_depth_bias(bias, slope_scale, clamp);
+    }
+
+    fn begin_predication(&mut self, buffer: &D::Buffer, offset: uint, op: PredicationOp) {
+        self.encoder.begin_predication(buffer, offset, op);
+    }
+
+    fn end_predication(&mut self) {
+        self.encoder.end_predication();
+    }
+
+    fn set_vertex_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint) {
+        self.encoder.set_vertex_buffer(slice, index);
+    }
+
+    fn set_vertex_bytes(&mut self, bytes: &[u8], index: uint) {
+        self.encoder.set_vertex_bytes(bytes, index);
+    }
+
+    fn set_vertex_texture(&mut self, texture: &D::Texture, index: uint) {
+        self.encoder.set_vertex_texture(texture, index);
+    }
+
+    fn set_vertex_sampler(&mut self, sampler: &D::Sampler, index: uint) {
+        self.encoder.set_vertex_sampler(sampler, index);
+    }
+
+    fn set_fragment_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint) {
+        self.encoder.set_fragment_buffer(slice, index);
+    }
+
+    fn set_fragment_bytes(&mut self, bytes: &[u8], index: uint) {
+        self.encoder.set_fragment_bytes(bytes, index);
+    }
+
+    fn set_fragment_texture(&mut self, texture: &D::Texture, index: uint) {
+        self.encoder.set_fragment_texture(texture, index);
+    }
+
+    fn set_fragment_sampler(&mut self, sampler: &D::Sampler, index: uint) {
+        self.encoder.set_fragment_sampler(sampler, index);
+    }
+
+    fn set_scissor_rect(&mut self, rect: ScissorRect) {
+        self.encoder.set_scissor_rect(rect);
+    }
+
+    fn clear_rects(&mut self, attachments: &[ClearAttachment], rects: &[ScissorRect]) {
+        self.encoder.clear_rects(attachments, rects);
+    }
+
+    fn set_visibility_result_mode(&mut self, mode: VisibilityResultMode, offset: uint) {
+        self.encoder.set_visibility_result_mode(mode, offset);
+    }
+
+    fn set_bind_group(&mut self, group: &D::BindGroup, index: uint) {
+        self.encoder.set_bind_group(group, index);
+    }
+
+    fn use_resource(&mut self, resource: ResourceRef<LimitsOverrideDevice<D>>, writable: bool) {
+        self.encoder.use_resource(resource_ref(resource), writable);
+    }
+
+    fn set_index_buffer(&mut self, slice: BufferSlice<D::Buffer>, format: IndexFormat) {
+        self.encoder.set_index_buffer(slice, format);
+    }
+
+    fn draw(&mut self, primitive: PrimitiveType, first_vertex: uint, vertex_count: uint,
+            instance_count: uint, base_instance: uint) {
+        self.encoder.draw(primitive, first_vertex, vertex_count, instance_count, base_instance);
+    }
+
+    fn draw_indexed(&mut self, primitive: PrimitiveType, first_index: uint, index_count: uint,
+                    instance_count: uint, base_vertex: int, base_instance: uint) {
+        self.encoder.draw_indexed(primitive, first_index, index_count, instance_count, base_vertex, base_instance);
+    }
+
+    fn set_stream_output_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint) {
+        if !self.limits.features.stream_output {
+            panic!("set_stream_output_buffer needs the stream_output feature");
+        }
+        self.encoder.set_stream_output_buffer(slice, index);
+    }
+
+    fn draw_from_stream_output(&mut self, primitive: PrimitiveType, buffer: &D::Buffer, stride: uint,
+                               instance_count: uint, base_instance: uint) {
+        if !self.limits.features.stream_output {
+            panic!("draw_from_stream_output needs the stream_output feature");
+        }
+        self.encoder.draw_from_stream_output(primitive, buffer, stride, instance_count, base_instance);
+    }
+
+    fn draw_indirect(&mut self, primitive: PrimitiveType, args: &D::Buffer, offset: uint) {
+        self.encoder.draw_indirect(primitive, args, offset);
+    }
+
+    fn draw_indexed_indirect(&mut self, primitive: PrimitiveType, args: &D::Buffer, offset: uint) {
+        self.encoder.draw_indexed_indirect(primitive, args, offset);
+    }
+
+    fn multi_draw_indexed_indirect_count(&mut self, primitive: PrimitiveType,
+                                         args: &D::Buffer, args_offset: uint,
+                                         count: &D::Buffer, count_offset: uint, max_draws: uint) {
+        if self.limits.features.multi_draw_indirect_count {
+            return self.encoder.multi_draw_indexed_indirect_count(primitive, args, args_offset, count, count_offset, max_draws);
+        }
+        // as a device without the feature emulates it: every draw up to `max_draws`
+        let stride = std::mem::size_of::<DrawIndexedIndirectArgs>();
+        for i in range(0, max_draws) {
+            self.encoder.draw_indexed_indirect(primitive, args, args_offset + i * stride);
+        }
+    }
+
+    #[cfg(feature = "debug_state")]
+    fn debug_state(&self) -> RenderEncoderState<LimitsOverrideDevice<D>> {
+        let state = self.encoder.debug_state();
+        RenderEncoderState {
+            pipeline: state.pipeline,
+            depth_stencil_state: state.depth_stencil_state,
+            bind_groups: state.bind_groups,
+            vertex_buffers: bound_buffers(state.vertex_buffers),
+            fragment_buffers: bound_buffers(state.fragment_buffers),
+            vertex_textures: state.vertex_textures,
+            fragment_textures: state.fragment_textures,
+            index_buffer: state.index_buffer,
+            viewport: state.viewport,
+            scissor_rect: state.scissor_rect,
+        }
+    }
+
+    fn end_encoding(self) {
+        self.encoder.end_encoding();
+    }
+}
+
+/// A compute command encoder of a `LimitsOverrideDevice`.
+pub struct LimitedComputeEncoder<D: Device> {
+    encoder: D::ComputeCommandEncoder,
+    limits: Limits,
+}
+
+impl<D: Device> LimitedComputeEncoder<D> {
+    fn check_threadgroup(&self, threads_per_threadgroup: &Size) {
+        let threads = threads_per_threadgroup.width * threads_per_threadgroup.height * threads_per_threadgroup.depth;
+        if threads > self.limits.limits.max_threads_per_threadgroup {
+            panic!("a threadgroup of {} threads exceeds max_threads_per_threadgroup ({})",
+                   threads, self.limits.limits.max_threads_per_threadgroup);
+        }
+    }
+}
+
+impl<D: Device> ComputeCommandEncoder<LimitsOverrideDevice<D>> for LimitedComputeEncoder<D> {
+    fn set_compute_pipeline(&mut self, pipeline: &D::ComputePipeline) {
+        self.encoder.set_compute_pipeline(pipeline);
+    }
+
+    fn set_buffer(&mut self, slice: BufferSlice<D::Buffer>, index: uint) {
+        self.encoder.set_buffer(slice, index);
+    }
+
+    fn set_bytes(&mut self, bytes: &[u8], index: uint) {
+        self.encoder.set_bytes(bytes, index);
+    }
+
+    fn set_texture(&mut self, texture: &D::Texture, index: uint) {
+        self.encoder.set_texture(texture, index);
+    }
+
+    fn set_sampler(&mut self, sampler: &D::Sampler, index: uint) {
+        self.encoder.set_sampler(sampler, index);
+    }
+
+    fn set_threadgroup_memory_length(&mut self, length: uint, index: uint) {
+        if length > self.limits.limits.max_threadgroup_memory_length {
+            panic!("{} bytes of threadgroup memory exceed max_threadgroup_memory_length ({})",
+                   length, self.limits.limits.max_threadgroup_memory_length);
+        }
+        self.encoder.set_threadgroup_memory_length(length, index);
+    }
+
+    fn set_bind_group(&mut self, group: &D::BindGroup, index: uint) {
+        self.encoder.set_bind_group(group, index);
+    }
+
+    fn use_resource(&mut self, resource: ResourceRef<LimitsOverrideDevice<D>>, writable: bool) {
+        self.encoder.use_resource(resource_ref(resource), writable);
+    }
+
+    fn dispatch_threadgroups(&mut self, threadgroups: Size, threads_per_threadgroup: Size) {
+        self.check_threadgroup(&threads_per_threadgroup);
+        self.encoder.dispatch_threadgroups(threadgroups, threads_per_threadgroup);
+    }
+
+    fn dispatch_threadgroups_indirect(&mut self, args: &D::Buffer, offset: uint, threads_per_threadgroup: Size) {
+        self.check_threadgroup(&threads_per_threadgroup);
+        self.encoder.dispatch_threadgroups_indirect(args, offset, threads_per_threadgroup);
+    }
+
+    #[cfg(feature = "debug_state")]
+    fn debug_state(&self) -> ComputeEncoderState<LimitsOverrideDevice<D>> {
+        let state = self.encoder.debug_state();
+        ComputeEncoderState {
+            pipeline: state.pipeline,
+            bind_groups: state.bind_groups,
+            buffers: bound_buffers(state.buffers),
+            textures: state.textures,
+            threadgroup_memory_lengths: state.threadgroup_memory_lengths,
+        }
+    }
+
+    fn end_encoding(self) {
+        self.encoder.end_encoding();
+    }
+}
+
+/// A blit command encoder of a `LimitsOverrideDevice`. Nothing a blit does depends on features
+/// or limits.
+pub struct LimitedBlitEncoder<D: Device> {
+    encoder: D::BlitCommandEncoder,
+}
+
+impl<D: Device> BlitCommandEncoder<LimitsOverrideDevice<D>> for LimitedBlitEncoder<D> {
+    fn copy_counter_value(&mut self, src: &D::Buffer, dst: &D::Buffer, dst_offset: uint) {
+        self.encoder.copy_counter_value(src, dst, dst_offset);
+    }
+
+    fn reset_counter(&mut self, buffer: &D::Buffer, value: u32) {
+        self.encoder.reset_counter(buffer, value);
+    }
+
+    fn fill_buffer(&mut self, slice: BufferSlice<D::Buffer>, value: u8) {
+        self.encoder.fill_buffer(slice, value);
+    }
+
+    fn copy_buffer(&mut self, src: BufferSlice<D::Buffer>, dst: &D::Buffer, dst_offset: uint) {
+        self.encoder.copy_buffer(src, dst, dst_offset);
+    }
+
+    fn copy_texture(&mut self, src: &D::Texture, src_level: uint, src_slice: uint, src_origin: Origin, size: Size,
+                    dst: &D::Texture, dst_level: uint, dst_slice: uint, dst_origin: Origin) {
+        if let Err(error) = validate_region(src.descriptor(), src_level, src_slice, &src_origin, &size) {
+            panic!("invalid texture copy source: {}", error);
+        }
+        if let Err(error) = validate_region(dst.descriptor(), dst_level, dst_slice, &dst_origin, &size) {
+            panic!("invalid texture copy destination: {}", error);
+        }
+        self.encoder.copy_texture(src, src_level, src_slice, src_origin, size, dst, dst_level, dst_slice, dst_origin);
+    }
+
+    fn copy_buffer_to_texture(&mut self, src: BufferSlice<D::Buffer>, bytes_per_row: uint,
+                              dst: &D::Texture, dst_level: uint, dst_slice: uint, dst_origin: Origin, size: Size) {
+        if let Err(error) = validate_data(dst.descriptor(), dst_level, dst_slice, &dst_origin, &size, src.size(),
+                                          bytes_per_row) {
+            panic!("invalid buffer to texture copy: {}", error);
+        }
+        self.encoder.copy_buffer_to_texture(src, bytes_per_row, dst, dst_level, dst_slice, dst_origin, size);
+    }
+
+    fn copy_texture_to_buffer(&mut self, src: &D::Texture, src_level: uint, src_slice: uint, src_origin: Origin, size: Size,
+                              dst: BufferSlice<D::Buffer>, bytes_per_row: uint) {
+        if let Err(error) = validate_data(src.descriptor(), src_level, src_slice, &src_origin, &size, dst.size(),
+                                          bytes_per_row) {
+            panic!("invalid texture to buffer copy: {}", error);
+        }
+        self.encoder.copy_texture_to_buffer(src, src_level, src_slice, src_origin, size, dst, bytes_per_row);
+    }
+
+    fn end_encoding(self) {
+        self.encoder.end_encoding();
+    }
+}
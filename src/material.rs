@@ -0,0 +1,451 @@
+//! Materials: a shader, a block of parameters and a set of textures, and everything generated
+//! from them.
+//!
+//! A `MaterialDefinition` describes a kind of material the way an artist-facing tool would: the
+//! parameters and textures it has, with their defaults, the shader features it can switch, and
+//! the passes it is drawn in. A `MaterialType` turns that into what the API needs: a bind group
+//! layout, default textures and a default bind group, the MSL declarations of the parameter
+//! block for the shader (`MaterialType::shader_header`), and the pipeline variants of every pass,
+//! compiled through `shader_permutations`. Each `MaterialInstance` is then a parameter buffer
+//! and a bind group:
+//!
+//! ```ignore
+//! let mut definition = MaterialDefinition::new("pbr", "pbr.metal", source);
+//! definition.parameter("base_color", ParameterValue::Float4([1.0, 1.0, 1.0, 1.0]));
+//! definition.parameter("roughness", ParameterValue::Float(0.5));
+//! definition.texture("albedo", DefaultTexture::White);
+//! let alpha_test = definition.features.bool_feature("ALPHA_TEST");
+//! definition.pass("forward", &[]);
+//! definition.pass("shadow", &[("SHADOW_PASS", 1)]);
+//! let mut pbr = try!(MaterialType::new(&mut device, definition));
+//! let keys = pbr.features().all();
+//! try!(pbr.compile(&mut device, &mut registry, &mut preprocessor, keys.as_slice(),
+//!                  |source| source, |pass, _| descriptor_for(pass)));
+//! let gold = try!(pbr.create_instance(&mut device, &[("base_color", ParameterValue::Float4(GOLD))], &[]));
+//!
+//! encoder.set_render_pipeline(pbr.pipeline(&registry, 0, key).unwrap());
+//! gold.bind(&mut encoder, MATERIAL_GROUP);
+//! ```
+//!
+//! The bind group of a material has the parameter block at binding `0` (if there are any
+//! parameters), then the textures in the order they were declared, then one sampler. The header
+//! defines macros for all of their indices, so shaders never spell them out.
+
+use {Device, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingCount};
+use {BindingResource, BindingType, BufferSlice, Origin, PixelFormat, RenderPipelineDescriptor, SamplerDescriptor};
+use {RenderCommandEncoder, ShaderStages, Size, TextureDescriptor, TextureType, TextureUsage, TriangleFillMode};
+use pipeline_loader::PipelineRegistry;
+use shader_permutations::{PermutationError, PermutationSpace, PermutedShader};
+use shader_preprocessor::{IncludeResolver, Preprocessor};
+use util::{as_bytes, slice_as_bytes};
+
+/// The value of a material parameter, which also decides its type.
+#[deriving(Clone, PartialEq, Show)]
+pub enum ParameterValue {
+    Float(f32),
+    Float2([f32, ..2]),
+    Float3([f32, ..3]),
+    Float4([f32, ..4]),
+    Int(i32),
+    UInt(u32),
+}
+
+impl ParameterValue {
+    /// The MSL type, size and alignment of the value in a constant buffer.
+    fn layout(&self) -> (&'static str, uint, uint) {
+        match *self {
+            ParameterValue::Float(_) => ("float", 4, 4),
+            ParameterValue::Float2(_) => ("float2", 8, 8),
+            // as in MSL, three component vectors take the space of four
+            ParameterValue::Float3(_) => ("float3", 16, 16),
+            ParameterValue::Float4(_) => ("float4", 16, 16),
+            ParameterValue::Int(_) => ("int", 4, 4),
+            ParameterValue::UInt(_) => ("uint", 4, 4),
+        }
+    }
+
+    fn same_type(&self, other: &ParameterValue) -> bool {
+        self.layout().0 == other.layout().0
+    }
+
+    /// Write the value at the start of `bytes`.
+    fn write(&self, bytes: &mut [u8]) {
+        let value = match *self {
+            ParameterValue::Float(ref x) => as_bytes(x),
+            ParameterValue::Float2(ref v) => slice_as_bytes(v),
+            ParameterValue::Float3(ref v) => slice_as_bytes(v),
+            ParameterValue::Float4(ref v) => slice_as_bytes(v),
+            ParameterValue::Int(ref x) => as_bytes(x),
+            ParameterValue::UInt(ref x) => as_bytes(x),
+        };
+        bytes.slice_to_mut(value.len()).copy_from(value);
+    }
+}
+
+/// What a texture slot holds when an instance does not set it.
+#[deriving(Clone, PartialEq, Show)]
+pub enum DefaultTexture {
+    White,
+    Black,
+    /// A tangent space normal map of a flat surface.
+    FlatNormal,
+}
+
+impl DefaultTexture {
+    fn texel(&self) -> [u8, ..4] {
+        match *self {
+            DefaultTexture::White => [255, 255, 255, 255],
+            DefaultTexture::Black => [0, 0, 0, 255],
+            DefaultTexture::FlatNormal => [128, 128, 255, 255],
+        }
+    }
+}
+
+/// A pass a material is drawn in, such as a depth prepass, shadows or forward shading.
+pub struct MaterialPass {
+    pub name: String,
+    /// Macros defined in every variant of the pass, for the shader to tell the passes apart.
+    pub defines: Vec<(String, i64)>,
+}
+
+/// A kind of material. See the module documentation.
+pub struct MaterialDefinition {
+    pub name: String,
+    /// The file name of the shader source, for includes and error messages.
+    pub source_name: String,
+    pub source: String,
+    pub parameters: Vec<(String, ParameterValue)>,
+    pub textures: Vec<(String, DefaultTexture)>,
+    /// The features variants of the shader switch.
+    pub features: PermutationSpace,
+    pub passes: Vec<MaterialPass>,
+}
+
+impl MaterialDefinition {
+    pub fn new(name: &str, source_name: &str, source: &str) -> MaterialDefinition {
+        MaterialDefinition {
+            name: name.to_string(),
+            source_name: source_name.to_string(),
+            source: source.to_string(),
+            parameters: Vec::new(),
+            textures: Vec::new(),
+            features: PermutationSpace::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    /// Add a parameter, whose type is that of its `default`.
+    pub fn parameter(&mut self, name: &str, default: ParameterValue) {
+        self.parameters.push((name.to_string(), default));
+    }
+
+    /// Add a texture slot.
+    pub fn texture(&mut self, name: &str, default: DefaultTexture) {
+        self.textures.push((name.to_string(), default));
+    }
+
+    /// Add a pass, whose variants define `defines`.
+    pub fn pass(&mut self, name: &str, defines: &[(&str, i64)]) {
+        self.passes.push(MaterialPass {
+            name: name.to_string(),
+            defines: defines.iter().map(|&(name, value)| (name.to_string(), value)).collect(),
+        });
+    }
+}
+
+/// Why a material type or instance could not be created.
+pub enum MaterialError<D: Device> {
+    /// There is no parameter of that name.
+    UnknownParameter(String),
+    /// The value given for the parameter is not of its type.
+    ParameterType(String),
+    /// There is no texture slot of that name.
+    UnknownTexture(String),
+    BindGroupLayout(D::BindGroupLayoutCreationError),
+    BindGroup(D::BindGroupCreationError),
+    Buffer(D::BufferCreationError),
+    Texture(D::TextureCreationError),
+    Sampler(D::SamplerCreationError),
+    Permutation(PermutationError<D>),
+}
+
+/// The generated objects of a `MaterialDefinition`.
+pub struct MaterialType<D: Device> {
+    definition: MaterialDefinition,
+    layout: D::BindGroupLayout,
+    /// The offset of each parameter in the parameter block.
+    offsets: Vec<uint>,
+    block_size: uint,
+    default_textures: Vec<D::Texture>,
+    sampler: D::Sampler,
+    default_instance: Option<MaterialInstance<D>>,
+    /// The shader of each pass, with the pass's defines as features of a single value.
+    passes: Vec<PermutedShader>,
+}
+
+/// A material: parameter values, and textures in some of the slots.
+pub struct MaterialInstance<D: Device> {
+    parameters: Vec<u8>,
+    buffer: Option<D::Buffer>,
+    group: D::BindGroup,
+    fill_mode: TriangleFillMode,
+}
+
+impl<D: Device> MaterialInstance<D> {
+    /// The bind group to draw with.
+    pub fn bind_group(&self) -> &D::BindGroup {
+        &self.group
+    }
+
+    /// How the instance's triangles are rasterized. Instances start out `Fill`.
+    pub fn fill_mode(&self) -> TriangleFillMode {
+        self.fill_mode.clone()
+    }
+
+    /// Draw the instance as `mode`, such as `Lines` for a wireframe material in an editor. This
+    /// takes effect through `bind`, and `Lines` needs the `fill_mode_lines` device feature.
+    pub fn set_fill_mode(&mut self, mode: TriangleFillMode) {
+        self.fill_mode = mode;
+    }
+
+    /// Bind the instance for the draws after it: its bind group at `index`, and its fill mode.
+    pub fn bind<E: RenderCommandEncoder<D>>(&self, encoder: &mut E, index: uint) {
+        encoder.set_bind_group(&self.group, index);
+        encoder.set_triangle_fill_mode(self.fill_mode.clone());
+    }
+}
+
+fn round_up(value: uint, alignment: uint) -> uint {
+    (value + alignment - 1) / alignment * alignment
+}
+
+impl<D: Device> MaterialType<D> {
+    pub fn new(device: &mut D, definition: MaterialDefinition) -> Result<MaterialType<D>, MaterialError<D>> {
+        enter_span!("MaterialType::new", material = definition.name.as_slice());
+        let mut offsets = Vec::with_capacity(definition.parameters.len());
+        let (mut size, mut alignment) = (0, 4);
+        for &(_, ref default) in definition.parameters.iter() {
+            let (_, parameter_size, parameter_alignment) = default.layout();
+            size = round_up(size, parameter_alignment);
+            offsets.push(size);
+            size += parameter_size;
+            alignment = std::cmp::max(alignment, parameter_alignment);
+        }
+        let block_size = round_up(size, alignment);
+
+        let visibility = || ShaderStages { vertex: true, fragment: true, compute: false };
+        let single = |binding: uint, ty: BindingType| BindGroupLayoutEntry {
+            binding: binding,
+            visibility: visibility(),
+            ty: ty,
+            count: BindingCount::Single,
+            partially_bound: false,
+            update_after_bind: false,
+        };
+        let mut entries = Vec::new();
+        if block_size > 0 {
+            entries.push(single(0, BindingType::UniformBuffer));
+        }
+        for i in range(0, definition.textures.len()) {
+            entries.push(single(1 + i, BindingType::SampledTexture));
+        }
+        entries.push(single(1 + definition.textures.len(), BindingType::Sampler));
+        let layout = match device.create_bind_group_layout(BindGroupLayoutDescriptor { entries: entries }) {
+            Ok(layout) => layout,
+            Err(e) => return Err(MaterialError::BindGroupLayout(e)),
+        };
+
+        let mut default_textures = Vec::with_capacity(definition.textures.len());
+        for &(_, ref default) in definition.textures.iter() {
+            let texture = match device.create_texture(TextureDescriptor {
+                texture_type: TextureType::Type2D,
+                pixel_format: PixelFormat::RGBA8Unorm,
+                width: 1,
+                height: 1,
+                depth: 1,
+                mipmap_level_count: 1,
+                array_length: 1,
+                sample_count: 1,
+                usage: TextureUsage { shader_read: true, shader_write: false, render_target: false },
+            }) {
+                Ok(texture) => texture,
+                Err(e) => return Err(MaterialError::Texture(e)),
+            };
+            device.write_texture(&texture, 0, 0, Origin { x: 0, y: 0, z: 0 }, Size { width: 1, height: 1, depth: 1 },
+                                 &default.texel(), 4);
+            default_textures.push(texture);
+        }
+        let sampler = match device.create_sampler(SamplerDescriptor) {
+            Ok(sampler) => sampler,
+            Err(e) => return Err(MaterialError::Sampler(e)),
+        };
+
+        let passes = definition.passes.iter().map(|pass| {
+            let mut features = definition.features.clone();
+            for &(ref name, value) in pass.defines.iter() {
+                features.int_feature(name.as_slice(), &[value]);
+            }
+            PermutedShader::new(features, format!("{}.{}", definition.name, pass.name).as_slice(),
+                                definition.source_name.as_slice(), definition.source.as_slice())
+        }).collect();
+
+        let mut material = MaterialType {
+            definition: definition,
+            layout: layout,
+            offsets: offsets,
+            block_size: block_size,
+            default_textures: default_textures,
+            sampler: sampler,
+            default_instance: None,
+            passes: passes,
+        };
+        let default_instance = try!(material.create_instance(device, &[], &[]));
+        material.default_instance = Some(default_instance);
+        Ok(material)
+    }
+
+    pub fn definition(&self) -> &MaterialDefinition {
+        &self.definition
+    }
+
+    /// The features of the material's shader. The keys of `pipeline` and `compile` are keys of
+    /// this space.
+    pub fn features(&self) -> &PermutationSpace {
+        &self.definition.features
+    }
+
+    /// The layout of every instance's bind group, for the pipeline layouts of the passes.
+    pub fn layout(&self) -> &D::BindGroupLayout {
+        &self.layout
+    }
+
+    /// The instance with every parameter and texture at its default.
+    pub fn default_instance(&self) -> &MaterialInstance<D> {
+        self.default_instance.as_ref().unwrap()
+    }
+
+    /// MSL declarations of the parameter block (`struct MaterialParameters`) and macros of the
+    /// binding indices, to include in the material's shader.
+    pub fn shader_header(&self) -> String {
+        let mut header = String::new();
+        if self.block_size > 0 {
+            header.push_str("#define MATERIAL_PARAMETERS_BINDING 0\nstruct MaterialParameters {\n");
+            for &(ref name, ref default) in self.definition.parameters.iter() {
+                header.push_str(format!("    {} {};\n", default.layout().0, name).as_slice());
+            }
+            header.push_str("};\n");
+        }
+        for (i, &(ref name, _)) in self.definition.textures.iter().enumerate() {
+            header.push_str(format!("#define MATERIAL_TEXTURE_{} {}\n", name.to_ascii_upper(), 1 + i).as_slice());
+        }
+        header.push_str(format!("#define MATERIAL_SAMPLER {}\n", 1 + self.definition.textures.len()).as_slice());
+        header
+    }
+
+    fn parameter_index(&self, name: &str, value: &ParameterValue) -> Result<uint, MaterialError<D>> {
+        match self.definition.parameters.iter().position(|&(ref parameter, _)| parameter.as_slice() == name) {
+            Some(index) if self.definition.parameters[index].1.same_type(value) => Ok(index),
+            Some(_) => Err(MaterialError::ParameterType(name.to_string())),
+            None => Err(MaterialError::UnknownParameter(name.to_string())),
+        }
+    }
+
+    /// Create an instance with `parameters` and `textures` set, and everything else at its
+    /// default.
+    ///
+    /// The textures are bound when the instance is created; changing them takes a new instance.
+    pub fn create_instance(&self, device: &mut D, parameters: &[(&str, ParameterValue)],
+                           textures: &[(&str, &D::Texture)]) -> Result<MaterialInstance<D>, MaterialError<D>> {
+        let mut block = Vec::from_elem(self.block_size, 0u8);
+        for (i, &(_, ref default)) in self.definition.parameters.iter().enumerate() {
+            default.write(block.slice_from_mut(self.offsets[i]));
+        }
+        for &(name, ref value) in parameters.iter() {
+            let index = try!(self.parameter_index(name, value));
+            value.write(block.slice_from_mut(self.offsets[index]));
+        }
+        for &(name, _) in textures.iter() {
+            if !self.definition.textures.iter().any(|&(ref slot, _)| slot.as_slice() == name) {
+                return Err(MaterialError::UnknownTexture(name.to_string()));
+            }
+        }
+
+        let buffer = if self.block_size > 0 {
+            let buffer = match device.create_buffer(self.block_size, None) {
+                Ok(buffer) => buffer,
+                Err(e) => return Err(MaterialError::Buffer(e)),
+            };
+            device.write_buffer(&buffer, 0, block.as_slice());
+            Some(buffer)
+        } else {
+            None
+        };
+        let group = {
+            let mut entries = Vec::new();
+            if let Some(ref buffer) = buffer {
+                entries.push(BindGroupEntry { binding: 0, array_element: 0, resource: BindingResource::Buffer(BufferSlice::whole(buffer)) });
+            }
+            for (i, &(ref slot, _)) in self.definition.textures.iter().enumerate() {
+                let texture = match textures.iter().find(|&&(name, _)| name == slot.as_slice()) {
+                    Some(&(_, texture)) => texture,
+                    None => &self.default_textures[i],
+                };
+                entries.push(BindGroupEntry { binding: 1 + i, array_element: 0, resource: BindingResource::Texture(texture) });
+            }
+            entries.push(BindGroupEntry {
+                binding: 1 + self.definition.textures.len(),
+                array_element: 0,
+                resource: BindingResource::Sampler(&self.sampler),
+            });
+            match device.create_bind_group(BindGroupDescriptor { layout: &self.layout, entries: entries, unsized_length: 0 }) {
+                Ok(group) => group,
+                Err(e) => return Err(MaterialError::BindGroup(e)),
+            }
+        };
+        Ok(MaterialInstance { parameters: block, buffer: buffer, group: group, fill_mode: TriangleFillMode::Fill })
+    }
+
+    /// Change a parameter of `instance`, which must be an instance of this type.
+    ///
+    /// The new value is written to the instance's buffer like `write_buffer` writes, so command
+    /// buffers committed before still see the old value only if they have finished.
+    pub fn set_parameter(&self, device: &mut D, instance: &mut MaterialInstance<D>, name: &str,
+                         value: ParameterValue) -> Result<(), MaterialError<D>> {
+        let index = try!(self.parameter_index(name, &value));
+        let offset = self.offsets[index];
+        value.write(instance.parameters.slice_from_mut(offset));
+        let (_, size, _) = value.layout();
+        if let Some(ref buffer) = instance.buffer {
+            device.write_buffer(buffer, offset, instance.parameters.slice(offset, offset + size));
+        }
+        Ok(())
+    }
+
+    /// Start compiling the pipeline variants `keys` of every pass into `registry`, in the
+    /// background.
+    ///
+    /// As for `PermutedShader::compile`, `input` turns preprocessed sources into shader program
+    /// inputs. `descriptor` gives the pipeline descriptor of a pass and variant; the material's
+    /// bind group layout must be part of the pipeline's layout wherever the shader declares it.
+    pub fn compile<R, I, F>(&mut self, device: &mut D, registry: &mut PipelineRegistry<D>, preprocessor: &mut Preprocessor<R>,
+                            keys: &[u64], input: I, descriptor: F) -> Result<(), MaterialError<D>>
+            where D::RenderPipeline: Send, D::RenderPipelineCreationError: Send, R: IncludeResolver,
+                  I: FnMut(String) -> D::ShaderProgramInput, F: FnMut(&MaterialPass, u64) -> RenderPipelineDescriptor {
+        let (mut input, mut descriptor) = (input, descriptor);
+        for (i, shader) in self.passes.iter_mut().enumerate() {
+            let pass = &self.definition.passes[i];
+            let result = shader.compile(device, registry, preprocessor, keys, |source| input(source),
+                                        |key| descriptor(pass, key));
+            if let Err(e) = result {
+                return Err(MaterialError::Permutation(e));
+            }
+        }
+        Ok(())
+    }
+
+    /// The pipeline of the `pass`th pass for the variant `key`, if it is compiled and ready.
+    pub fn pipeline<'r>(&self, registry: &'r PipelineRegistry<D>, pass: uint, key: u64) -> Option<&'r D::RenderPipeline>
+            where D::RenderPipeline: Send, D::RenderPipelineCreationError: Send {
+        self.passes[pass].get(registry, key)
+    }
+}
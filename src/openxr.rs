@@ -0,0 +1,220 @@
+//! The graphics binding glue of OpenXR sessions.
+//!
+//! An OpenXR session renders with the application's device: it is created with a graphics
+//! binding naming the native device and queue (`XrGraphicsBindingVulkanKHR`,
+//! `XrGraphicsBindingD3D12KHR`, `XrGraphicsBindingMetalKHR`), and hands the application native
+//! swapchain images to render into. This module is the crate's side of that, without depending
+//! on any OpenXR bindings: `Device::openxr_graphics_binding` gives the handles of a binding, to
+//! copy into the struct of whichever bindings the application uses, and `SwapchainImages`
+//! imports the images of a swapchain as textures:
+//!
+//! ```ignore
+//! // open the device as the runtime requires: on vulkan, on the device xrCreateVulkanDeviceKHR
+//! // created, with Adapter::open_raw
+//! let binding = unsafe { device.openxr_graphics_binding(&queue) }.expect("no OpenXR binding for this backend");
+//! let session = create_session(instance, system, binding); // with the application's bindings
+//!
+//! let desc = || eye_target_descriptor(width, height); // two array slices, one per eye
+//! let info = swapchain_create_info(&binding.api(), &desc()).expect("format cannot be a swapchain format");
+//! let swapchain = create_swapchain(session, info);
+//! let images = try!(unsafe { SwapchainImages::import(&mut device, enumerate_images(swapchain), desc) });
+//!
+//! // every frame
+//! let index = acquire_and_wait(swapchain);
+//! let target = images.texture(index);   // render both eyes into it, with xr::XrRenderPass
+//! ```
+//!
+//! Swapchain images belong to the runtime: they are only rendered into between acquiring and
+//! releasing them, and the command buffers rendering into them must be committed before they
+//! are released. Backends leave imported images in the state the runtime expects them in
+//! (`COLOR_ATTACHMENT_OPTIMAL` or `DEPTH_STENCIL_ATTACHMENT_OPTIMAL` on vulkan, the
+//! `RENDER_TARGET` or `DEPTH_WRITE` state on d3d12) at the end of every command buffer which
+//! used them.
+
+#[cfg(not(feature = "std"))]
+use core::prelude::*;
+#[cfg(not(feature = "std"))]
+use collections::Vec;
+
+use {Device, PixelFormat, RawHandle, TextureDescriptor, TextureType};
+
+/// The graphics APIs OpenXR has bindings for that the crate has backends for.
+#[deriving(Clone, PartialEq, Show)]
+pub enum GraphicsApi {
+    Vulkan,
+    D3D12,
+    Metal,
+}
+
+/// The handles of an OpenXR graphics binding, field for field. Returned by
+/// `Device::openxr_graphics_binding`.
+#[deriving(Clone, Show)]
+pub enum GraphicsBinding {
+    /// `XrGraphicsBindingVulkanKHR`. The handles are as in `RawHandle::Vulkan`.
+    Vulkan {
+        instance: u64,
+        physical_device: u64,
+        device: u64,
+        queue_family_index: u32,
+        queue_index: u32,
+    },
+    /// `XrGraphicsBindingD3D12KHR`: the `ID3D12Device *` and `ID3D12CommandQueue *`.
+    D3D12 {
+        device: *mut u8,
+        queue: *mut u8,
+    },
+    /// `XrGraphicsBindingMetalKHR`: the `id<MTLCommandQueue>`.
+    Metal {
+        command_queue: *mut u8,
+    },
+}
+
+impl GraphicsBinding {
+    pub fn api(&self) -> GraphicsApi {
+        match *self {
+            GraphicsBinding::Vulkan { .. } => GraphicsApi::Vulkan,
+            GraphicsBinding::D3D12 { .. } => GraphicsApi::D3D12,
+            GraphicsBinding::Metal { .. } => GraphicsApi::Metal,
+        }
+    }
+}
+
+/// Every pixel format, with its `VkFormat`, `DXGI_FORMAT` and `MTLPixelFormat`.
+const NATIVE_FORMATS: [(PixelFormat, i64, i64, i64), ..20] = [
+    (PixelFormat::R8Unorm, 9, 61, 10),
+    (PixelFormat::RG8Unorm, 16, 49, 30),
+    (PixelFormat::RGBA8Unorm, 37, 28, 70),
+    (PixelFormat::RGBA8UnormSrgb, 43, 29, 71),
+    (PixelFormat::BGRA8Unorm, 44, 87, 80),
+    (PixelFormat::BGRA8UnormSrgb, 50, 91, 81),
+    (PixelFormat::R16Float, 76, 54, 25),
+    (PixelFormat::RG16Float, 83, 34, 65),
+    (PixelFormat::RGBA16Float, 97, 10, 115),
+    (PixelFormat::R32Float, 100, 41, 55),
+    (PixelFormat::RG32Float, 103, 16, 105),
+    (PixelFormat::RGBA32Float, 109, 2, 125),
+    (PixelFormat::R32Uint, 98, 42, 53),
+    (PixelFormat::RG32Uint, 101, 17, 103),
+    (PixelFormat::R32Sint, 99, 43, 54),
+    (PixelFormat::RG11B10Float, 122, 26, 92),
+    (PixelFormat::RGB9E5Float, 123, 67, 93),
+    (PixelFormat::Depth32Float, 126, 40, 252),
+    (PixelFormat::Depth24UnormStencil8, 129, 45, 255),
+    (PixelFormat::Depth32FloatStencil8, 130, 20, 260),
+];
+
+fn native(api: &GraphicsApi, entry: &(PixelFormat, i64, i64, i64)) -> i64 {
+    match *api {
+        GraphicsApi::Vulkan => entry.1,
+        GraphicsApi::D3D12 => entry.2,
+        GraphicsApi::Metal => entry.3,
+    }
+}
+
+/// The native format code of `format` for `api`, as swapchains are created with and
+/// `xrEnumerateSwapchainFormats` lists.
+pub fn native_format(api: &GraphicsApi, format: &PixelFormat) -> i64 {
+    native(api, NATIVE_FORMATS.iter().find(|entry| entry.0 == *format).unwrap())
+}
+
+/// The pixel format of the native format code `format` of `api`, or `None` if the crate has no
+/// such pixel format.
+pub fn pixel_format(api: &GraphicsApi, format: i64) -> Option<PixelFormat> {
+    NATIVE_FORMATS.iter().find(|entry| native(api, *entry) == format).map(|entry| entry.0.clone())
+}
+
+/// The first format of `supported`, the native formats a runtime lists in order of preference,
+/// which the crate has a pixel format for and `accept` accepts: a color format for the eye
+/// targets (preferring sRGB, usually), or a depth format for the depth swapchain.
+pub fn choose_format<F: Fn(&PixelFormat) -> bool>(api: &GraphicsApi, supported: &[i64], accept: F) -> Option<PixelFormat> {
+    supported.iter().filter_map(|&format| pixel_format(api, format)).find(|format| accept(format))
+}
+
+/// `XrSwapchainUsageFlags` bits.
+pub const SWAPCHAIN_USAGE_COLOR_ATTACHMENT: u64 = 0x1;
+pub const SWAPCHAIN_USAGE_DEPTH_STENCIL_ATTACHMENT: u64 = 0x2;
+pub const SWAPCHAIN_USAGE_UNORDERED_ACCESS: u64 = 0x4;
+pub const SWAPCHAIN_USAGE_SAMPLED: u64 = 0x20;
+
+/// The fields of an `XrSwapchainCreateInfo` creating images `desc` describes.
+#[deriving(Clone, Show)]
+pub struct SwapchainCreateInfo {
+    pub usage_flags: u64,
+    pub format: i64,
+    pub sample_count: u32,
+    pub width: u32,
+    pub height: u32,
+    pub face_count: u32,
+    pub array_size: u32,
+    pub mip_count: u32,
+}
+
+/// The create info of a swapchain of images `desc` describes, with the usage flags of its
+/// usage (render targets of depth formats are depth stencil attachments), or `None` if the
+/// images cannot be swapchain images: swapchains only have 2D images, arrays of them (one slice
+/// per view, for multiview) and cubes.
+pub fn swapchain_create_info(api: &GraphicsApi, desc: &TextureDescriptor) -> Option<SwapchainCreateInfo> {
+    let (face_count, array_size) = match desc.texture_type {
+        TextureType::Type2D => (1, 1),
+        TextureType::Type2DArray => (1, desc.array_length),
+        TextureType::TypeCube => (6, 1),
+        TextureType::Type3D => return None,
+    };
+    let mut usage_flags = 0;
+    if desc.usage.render_target {
+        usage_flags |= if desc.pixel_format.has_depth() {
+            SWAPCHAIN_USAGE_DEPTH_STENCIL_ATTACHMENT
+        } else {
+            SWAPCHAIN_USAGE_COLOR_ATTACHMENT
+        };
+    }
+    if desc.usage.shader_read {
+        usage_flags |= SWAPCHAIN_USAGE_SAMPLED;
+    }
+    if desc.usage.shader_write {
+        usage_flags |= SWAPCHAIN_USAGE_UNORDERED_ACCESS;
+    }
+    Some(SwapchainCreateInfo {
+        usage_flags: usage_flags,
+        format: native_format(api, &desc.pixel_format),
+        sample_count: desc.sample_count as u32,
+        width: desc.width as u32,
+        height: desc.height as u32,
+        face_count: face_count,
+        array_size: array_size as u32,
+        mip_count: desc.mipmap_level_count as u32,
+    })
+}
+
+/// The images of an OpenXR swapchain, imported as textures.
+pub struct SwapchainImages<D: Device> {
+    textures: Vec<D::Texture>,
+}
+
+impl<D: Device> SwapchainImages<D> {
+    /// Import `images`, the native images `xrEnumerateSwapchainImages` lists (the `image` of
+    /// each `XrSwapchainImageVulkanKHR`, the `texture` of each `XrSwapchainImageD3D12KHR` or
+    /// `XrSwapchainImageMetalKHR`), of a swapchain created with `swapchain_create_info` of the
+    /// descriptor `desc` returns.
+    ///
+    /// Unsafe, as for `Device::texture_from_raw`: the images must outlive the textures, so the
+    /// swapchain must be destroyed after them.
+    pub unsafe fn import<F: Fn() -> TextureDescriptor>(device: &mut D, images: &[RawHandle], desc: F)
+                                                       -> Result<SwapchainImages<D>, D::TextureCreationError> {
+        enter_span!("SwapchainImages::import", images = images.len());
+        let mut textures = Vec::with_capacity(images.len());
+        for image in images.iter() {
+            textures.push(try!(device.texture_from_raw(image.clone(), desc())));
+        }
+        Ok(SwapchainImages { textures: textures })
+    }
+
+    /// The texture of the image of index `index`, as `xrAcquireSwapchainImage` returns it.
+    pub fn texture(&self, index: uint) -> &D::Texture {
+        &self.textures[index]
+    }
+
+    pub fn len(&self) -> uint {
+        self.textures.len()
+    }
+}
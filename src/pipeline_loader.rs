@@ -0,0 +1,307 @@
+//! Loading render pipelines from data files.
+//!
+//! A pipeline definition file lists named pipelines, each with the shader program it uses and its
+//! `RenderPipelineDescriptor` in the descriptor's serialized form. Adding a pipeline variant is
+//! then a matter of editing the file, not the application:
+//!
+//! ```json
+//! {
+//!     "pipelines": [
+//!         {
+//!             "name": "opaque",
+//!             "shader": "mesh",
+//!             "placeholder": null,
+//!             "descriptor": {
+//!                 "label": "opaque",
+//!                 "robustness": null,
+//!                 "vertex_function": "mesh_vertex",
+//!                 "fragment_function": "mesh_fragment",
+//!                 "vertex_descriptor": null,
+//!                 "blending": [],
+//!                 "sample_count": 1,
+//!                 "attachment_formats": null
+//!             }
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! Shader programs are referred to by name, and found through a `ShaderResolver`. The stock
+//! `ArchiveDirectoryResolver` looks for shader archives named `<shader>.shar` in a directory.
+//!
+//! `load` creates every pipeline up front. `load_deferred` instead compiles them in the background
+//! into a `PipelineRegistry`, which hands out each pipeline's `placeholder` (typically a slower
+//! uber-shader pipeline, created with `load` or `PipelineRegistry::insert`) until the pipeline
+//! itself is ready, so a new variant never stalls the frame that first needs it.
+
+use std::collections::HashMap;
+use std::comm::{channel, Receiver, Sender};
+use std::io::File;
+
+use {Device, PipelineCreationFeedback, RenderPipelineDescriptor};
+use shader_archive::ShaderArchive;
+
+/// A single entry of a pipeline definition file.
+#[deriving(Serialize, Deserialize)]
+pub struct PipelineDefinition {
+    /// The name the pipeline is looked up by.
+    pub name: String,
+    /// The name of the shader program, as understood by the `ShaderResolver`.
+    pub shader: String,
+    /// The name of the pipeline `PipelineRegistry::get` returns in place of this one while it
+    /// compiles. Only used by `load_deferred`.
+    pub placeholder: Option<String>,
+    pub descriptor: RenderPipelineDescriptor,
+}
+
+/// The contents of a pipeline definition file.
+#[deriving(Serialize, Deserialize)]
+pub struct PipelineFile {
+    pub pipelines: Vec<PipelineDefinition>,
+}
+
+/// Finds the shader programs that pipeline definitions refer to.
+pub trait ShaderResolver<D: Device> {
+    /// Find, or load and create, the shader program called `name`.
+    ///
+    /// Returns `None` if there is no such program.
+    fn resolve(&mut self, device: &mut D, name: &str) -> Option<&D::ShaderProgram>;
+}
+
+/// Why pipelines could not be loaded.
+pub enum LoadError<D: Device> {
+    /// The definition file is not valid.
+    Parse(String),
+    /// A pipeline refers to a shader program that could not be resolved.
+    UnknownShader { pipeline: String, shader: String },
+    /// Two pipelines have the same name.
+    DuplicateName(String),
+    /// The device failed to create a pipeline.
+    Creation { pipeline: String, error: D::RenderPipelineCreationError },
+}
+
+/// Parse a pipeline definition file.
+pub fn parse(text: &str) -> Result<PipelineFile, String> {
+    serde_json::from_str(text).map_err(|e| format!("{}", e))
+}
+
+/// Create every pipeline in a definition file.
+///
+/// Returns the pipelines by name. Either every pipeline is created or, on the first error,
+/// none are.
+pub fn load<D: Device, R: ShaderResolver<D>>(device: &mut D, resolver: &mut R, text: &str) -> Result<HashMap<String, D::RenderPipeline>, LoadError<D>> {
+    enter_span!("pipeline_loader::load");
+    let file = match parse(text) {
+        Ok(file) => file,
+        Err(e) => return Err(LoadError::Parse(e)),
+    };
+
+    let mut pipelines = HashMap::new();
+    for def in file.pipelines.into_iter() {
+        if pipelines.contains_key(&def.name) {
+            return Err(LoadError::DuplicateName(def.name));
+        }
+        let pipeline = {
+            let program = match resolver.resolve(device, def.shader.as_slice()) {
+                Some(program) => program,
+                None => return Err(LoadError::UnknownShader { pipeline: def.name, shader: def.shader }),
+            };
+            match device.create_render_pipeline(program, def.descriptor) {
+                Ok(pipeline) => pipeline,
+                Err(e) => return Err(LoadError::Creation { pipeline: def.name, error: e }),
+            }
+        };
+        pipelines.insert(def.name, pipeline);
+    }
+    Ok(pipelines)
+}
+
+/// Start compiling every pipeline in a definition file into `registry`, in the background.
+///
+/// Until a pipeline is ready, `registry.get` returns its placeholder instead. Names must not be
+/// in the registry already. Nothing is started unless every pipeline's shader program resolves;
+/// creation errors are reported later, by `PipelineRegistry::poll`.
+pub fn load_deferred<D: Device, R: ShaderResolver<D>>(device: &mut D, resolver: &mut R, text: &str,
+                                                      registry: &mut PipelineRegistry<D>) -> Result<(), LoadError<D>>
+        where D::RenderPipeline: Send, D::RenderPipelineCreationError: Send {
+    enter_span!("pipeline_loader::load_deferred");
+    let file = match parse(text) {
+        Ok(file) => file,
+        Err(e) => return Err(LoadError::Parse(e)),
+    };
+    for (i, def) in file.pipelines.iter().enumerate() {
+        if registry.contains(def.name.as_slice()) || file.pipelines.slice_to(i).iter().any(|other| other.name == def.name) {
+            return Err(LoadError::DuplicateName(def.name.clone()));
+        }
+        if resolver.resolve(device, def.shader.as_slice()).is_none() {
+            return Err(LoadError::UnknownShader { pipeline: def.name.clone(), shader: def.shader.clone() });
+        }
+    }
+    for def in file.pipelines.into_iter() {
+        let program = resolver.resolve(device, def.shader.as_slice()).unwrap();
+        registry.compile(device, program, def.name, def.descriptor, def.placeholder);
+    }
+    Ok(())
+}
+
+/// Named render pipelines, some of which may still be compiling in the background.
+pub struct PipelineRegistry<D: Device> {
+    ready: HashMap<String, D::RenderPipeline>,
+    /// The placeholder of each pipeline which is still compiling.
+    pending: HashMap<String, Option<String>>,
+    /// What the backend reported about each pipeline compiled through `compile`.
+    feedback: HashMap<String, PipelineCreationFeedback>,
+    sender: Sender<(String, Result<D::RenderPipeline, D::RenderPipelineCreationError>, PipelineCreationFeedback)>,
+    receiver: Receiver<(String, Result<D::RenderPipeline, D::RenderPipelineCreationError>, PipelineCreationFeedback)>,
+}
+
+impl<D: Device> PipelineRegistry<D> where D::RenderPipeline: Send, D::RenderPipelineCreationError: Send {
+    pub fn new() -> PipelineRegistry<D> {
+        let (sender, receiver) = channel();
+        PipelineRegistry {
+            ready: HashMap::new(),
+            pending: HashMap::new(),
+            feedback: HashMap::new(),
+            sender: sender,
+            receiver: receiver,
+        }
+    }
+
+    /// Add a pipeline which is ready to use (the result of `create_render_pipeline`, typically a
+    /// placeholder), replacing any pipeline of the same name.
+    pub fn insert(&mut self, name: String, pipeline: D::RenderPipeline) {
+        self.pending.remove(&name);
+        self.ready.insert(name, pipeline);
+    }
+
+    /// Start compiling a pipeline in the background, with `create_render_pipeline_async`.
+    ///
+    /// `placeholder` names the pipeline `get` returns instead until this one is ready. It must be
+    /// interchangeable with this one where it is used: the same vertex layout, bindings and
+    /// attachments, differing only in how well or how quickly it renders.
+    pub fn compile(&mut self, device: &mut D, program: &D::ShaderProgram, name: String,
+                   desc: RenderPipelineDescriptor, placeholder: Option<String>) {
+        self.pending.insert(name.clone(), placeholder);
+        let sender = self.sender.clone();
+        device.create_render_pipeline_async(program, desc, box move |result: Result<D::RenderPipeline, D::RenderPipelineCreationError>,
+                                                                     feedback: PipelineCreationFeedback| {
+            // the registry may be gone by now, and then nobody is interested
+            let _ = sender.send_opt((name, result, feedback));
+        });
+    }
+
+    /// Swap in every pipeline which finished compiling since the last call.
+    ///
+    /// Returns the pipelines which failed to compile; `get` keeps returning their placeholder.
+    pub fn poll(&mut self) -> Vec<(String, D::RenderPipelineCreationError)> {
+        enter_span!("PipelineRegistry::poll");
+        let mut failed = Vec::new();
+        loop {
+            let (name, result, feedback) = match self.receiver.try_recv() {
+                Ok(message) => message,
+                Err(_) => break,
+            };
+            // a pipeline inserted in the meantime wins over this result
+            if !self.pending.contains_key(&name) {
+                continue;
+            }
+            self.feedback.insert(name.clone(), feedback);
+            match result {
+                Ok(pipeline) => self.insert(name, pipeline),
+                Err(e) => failed.push((name, e)),
+            }
+        }
+        failed
+    }
+
+    /// The pipeline called `name` if it is ready, or else the first ready pipeline down its chain
+    /// of placeholders.
+    ///
+    /// Returns `None` if there is no such pipeline, or neither it nor a placeholder is ready.
+    pub fn get(&self, name: &str) -> Option<&D::RenderPipeline> {
+        let mut name = name;
+        // more steps than there are pending pipelines means the placeholders form a cycle
+        for _ in range(0, self.pending.len() + 1) {
+            if let Some(pipeline) = self.ready.get(name) {
+                return Some(pipeline);
+            }
+            match self.pending.get(name) {
+                Some(&Some(ref placeholder)) => name = placeholder.as_slice(),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Whether the pipeline called `name` itself is ready, rather than standing in for by a
+    /// placeholder.
+    pub fn is_ready(&self, name: &str) -> bool {
+        self.ready.contains_key(name) && !self.pending.contains_key(name)
+    }
+
+    /// Whether there is a pipeline called `name`, ready or not.
+    pub fn contains(&self, name: &str) -> bool {
+        self.ready.contains_key(name) || self.pending.contains_key(name)
+    }
+
+    /// The number of pipelines which are not ready: still compiling, or failed to.
+    pub fn pending_count(&self) -> uint {
+        self.pending.len()
+    }
+
+    /// What the backend reported about the last compilation of the pipeline called `name`,
+    /// once `poll` has seen it finish, whether it worked or not.
+    pub fn feedback(&self, name: &str) -> Option<&PipelineCreationFeedback> {
+        self.feedback.get(name)
+    }
+
+    /// The feedback of every pipeline compiled so far, by name, for telemetry.
+    pub fn all_feedback(&self) -> Vec<(&str, &PipelineCreationFeedback)> {
+        self.feedback.iter().map(|(name, feedback)| (name.as_slice(), feedback)).collect()
+    }
+
+    /// The fraction of the compiled pipelines which came from a cache, among those whose
+    /// backend could tell, or `None` if there are none of those.
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let known: Vec<bool> = self.feedback.values().filter_map(|feedback| feedback.cache_hit).collect();
+        if known.is_empty() {
+            return None;
+        }
+        Some(known.iter().filter(|&&hit| hit).count() as f64 / known.len() as f64)
+    }
+}
+
+/// Resolves shader names to the shader archives `<directory>/<name>.shar`.
+///
+/// Each program is created once, the first time it is needed, and reused afterwards.
+pub struct ArchiveDirectoryResolver<D: Device> {
+    directory: Path,
+    programs: HashMap<String, D::ShaderProgram>,
+}
+
+impl<D: Device> ArchiveDirectoryResolver<D> {
+    pub fn new(directory: Path) -> ArchiveDirectoryResolver<D> {
+        ArchiveDirectoryResolver { directory: directory, programs: HashMap::new() }
+    }
+}
+
+impl<D: Device> ShaderResolver<D> for ArchiveDirectoryResolver<D> {
+    fn resolve(&mut self, device: &mut D, name: &str) -> Option<&D::ShaderProgram> {
+        if !self.programs.contains_key(name) {
+            let path = self.directory.join(format!("{}.shar", name));
+            let data = match File::open(&path).read_to_end() {
+                Ok(data) => data,
+                Err(_) => return None,
+            };
+            let archive = match ShaderArchive::decode(data.as_slice()) {
+                Ok(archive) => archive,
+                Err(_) => return None,
+            };
+            match device.create_shader_program_from_archive(&archive) {
+                Ok(program) => { self.programs.insert(name.to_string(), program); }
+                Err(_) => return None,
+            }
+        }
+        self.programs.get(name)
+    }
+}
@@ -0,0 +1,191 @@
+//! Python bindings, for using the crate's compute path from Python.
+//!
+//! The bindings are not an extension module on their own: which backends exist is up to the
+//! application, so the crate building the extension module registers them along with the
+//! bindings' classes:
+//!
+//! ```ignore
+//! #[pymodule]
+//! fn gpu(py: Python, m: &PyModule) -> PyResult<()> {
+//!     metal_sketch::python::register(py, m, || vec![BackendEntry::new(BackendKind::Metal, metal::adapters)])
+//! }
+//! ```
+//!
+//! after which, from Python:
+//!
+//! ```python
+//! device = gpu.open()
+//! buffer = device.create_buffer(1024)
+//! device.upload(buffer, bytes(1024))
+//! pipeline = device.create_compute_pipeline(device.create_shader_program(archive), "double")
+//! device.dispatch(pipeline, [buffer], (4, 1, 1), (64, 1, 1))
+//! data = device.readback(buffer)
+//! ```
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::types::{PyBytes, PyCFunction};
+
+use {AnyDevice, CommandBufferStatus, ComputePipelineDescriptor, DeviceDescriptor, DeviceLimits, Size};
+use any_device::{AnyBuffer, AnyComputePipeline, AnyShaderProgram, BackendEntry, DEFAULT_BACKEND_ORDER, ObjectError, auto};
+use any_device::{AnyComputeCommand, AnyPass};
+use shader_archive::ShaderArchive;
+
+fn error(e: ObjectError) -> PyErr {
+    PyRuntimeError::new_err(format!("{}", e))
+}
+
+/// A device, opened with `open`.
+#[pyclass(name = "Device", unsendable)]
+pub struct PyDevice {
+    device: AnyDevice,
+}
+
+/// A buffer, created with `Device.create_buffer`.
+#[pyclass(name = "Buffer", unsendable)]
+pub struct PyBuffer {
+    buffer: AnyBuffer,
+    length: uint,
+}
+
+/// A shader program, created with `Device.create_shader_program`.
+#[pyclass(name = "ShaderProgram", unsendable)]
+pub struct PyShaderProgram {
+    program: AnyShaderProgram,
+}
+
+/// A compute pipeline, created with `Device.create_compute_pipeline`.
+#[pyclass(name = "ComputePipeline", unsendable)]
+pub struct PyComputePipeline {
+    pipeline: AnyComputePipeline,
+}
+
+#[pymethods]
+impl PyBuffer {
+    #[getter]
+    fn length(&self) -> uint {
+        self.length
+    }
+}
+
+#[pymethods]
+impl PyDevice {
+    /// The name of the backend, e.g. `"Metal"`.
+    #[getter]
+    fn backend(&self) -> String {
+        format!("{}", self.device.backend())
+    }
+
+    #[getter]
+    fn adapter_name(&self) -> String {
+        self.device.adapter_name().to_string()
+    }
+
+    /// Create a buffer of `length` bytes.
+    fn create_buffer(&mut self, length: uint) -> PyResult<PyBuffer> {
+        match self.device.object().create_buffer(length, None) {
+            Ok(buffer) => Ok(PyBuffer { buffer: buffer, length: length }),
+            Err(e) => Err(error(e)),
+        }
+    }
+
+    /// Copy `data` (anything supporting the buffer protocol) into `buffer` at byte `offset`.
+    #[pyo3(signature = (buffer, data, offset = 0))]
+    fn upload(&mut self, buffer: &PyBuffer, data: &[u8], offset: uint) -> PyResult<()> {
+        if offset + data.len() > buffer.length {
+            return Err(PyRuntimeError::new_err("upload past the end of the buffer"));
+        }
+        self.device.object().write_buffer(&buffer.buffer, offset, data).map_err(error)
+    }
+
+    /// Read `length` bytes of `buffer` from byte `offset` (by default, all of it), waiting for
+    /// all submitted work first.
+    #[pyo3(signature = (buffer, offset = 0, length = None))]
+    fn readback<'py>(&mut self, py: Python<'py>, buffer: &PyBuffer, offset: uint, length: Option<uint>) -> PyResult<&'py PyBytes> {
+        let length = length.unwrap_or(buffer.length.saturating_sub(offset));
+        if offset + length > buffer.length {
+            return Err(PyRuntimeError::new_err("readback past the end of the buffer"));
+        }
+        let mut data = Vec::from_elem(length, 0u8);
+        try!(self.device.object().read_buffer(&buffer.buffer, offset, data.as_mut_slice()).map_err(error));
+        Ok(PyBytes::new(py, data.as_slice()))
+    }
+
+    /// Create a shader program from an encoded shader archive.
+    fn create_shader_program(&mut self, archive: &[u8]) -> PyResult<PyShaderProgram> {
+        let archive = match ShaderArchive::decode(archive) {
+            Ok(archive) => archive,
+            Err(e) => return Err(PyRuntimeError::new_err(format!("bad shader archive: {}", e))),
+        };
+        match self.device.object().create_shader_program_from_archive(&archive) {
+            Ok(program) => Ok(PyShaderProgram { program: program }),
+            Err(e) => Err(error(e)),
+        }
+    }
+
+    /// Create a compute pipeline running the function named `function` of `program`.
+    #[pyo3(signature = (program, function, threadgroup_memory_lengths = Vec::new()))]
+    fn create_compute_pipeline(&mut self, program: &PyShaderProgram, function: String,
+                               threadgroup_memory_lengths: Vec<uint>) -> PyResult<PyComputePipeline> {
+        let desc = ComputePipelineDescriptor {
+            label: Some(function.clone()),
+            robustness: None,
+            required_subgroup_size: None,
+            cooperative_matrices: Vec::new(),
+            compute_function: function,
+            threadgroup_memory_lengths: threadgroup_memory_lengths,
+        };
+        match self.device.object().create_compute_pipeline(&program.program, desc) {
+            Ok(pipeline) => Ok(PyComputePipeline { pipeline: pipeline }),
+            Err(e) => Err(error(e)),
+        }
+    }
+
+    /// Run `pipeline` over a grid of `grid` threadgroups of `threads_per_group` threads each,
+    /// with the whole of the `n`th of `buffers` bound at buffer index `n`, and wait for it to
+    /// finish.
+    fn dispatch(&mut self, pipeline: &PyComputePipeline, buffers: Vec<PyRef<PyBuffer>>, grid: (uint, uint, uint),
+                threads_per_group: (uint, uint, uint)) -> PyResult<()> {
+        let mut commands = vec![AnyComputeCommand::SetPipeline(&pipeline.pipeline)];
+        for (index, buffer) in buffers.iter().enumerate() {
+            commands.push(AnyComputeCommand::SetBuffer { buffer: &buffer.buffer, offset: 0, size: buffer.length,
+                                                         index: index });
+        }
+        commands.push(AnyComputeCommand::Dispatch {
+            threadgroups: Size { width: grid.0, height: grid.1, depth: grid.2 },
+            threads_per_threadgroup: Size { width: threads_per_group.0, height: threads_per_group.1,
+                                            depth: threads_per_group.2 },
+        });
+        let mut queue = try!(self.device.object().create_command_queue(None).map_err(error));
+        let fence = try!(self.device.object().submit(&mut queue, vec![AnyPass::Compute(commands)]).map_err(error));
+        match fence.wait() {
+            CommandBufferStatus::Completed => Ok(()),
+            CommandBufferStatus::Failed(e) => Err(PyRuntimeError::new_err(format!("the dispatch failed: {}", e))),
+        }
+    }
+}
+
+/// Add the bindings' classes to `module`, along with an `open()` function which opens a device
+/// with the first of `backends` that works, in the `DEFAULT_BACKEND_ORDER`.
+///
+/// Devices are opened without any optional features, and with the baseline limits.
+pub fn register<F>(py: Python, module: &PyModule, backends: F) -> PyResult<()>
+        where F: Fn() -> Vec<BackendEntry> + Send + 'static {
+    try!(module.add_class::<PyDevice>());
+    try!(module.add_class::<PyBuffer>());
+    try!(module.add_class::<PyShaderProgram>());
+    try!(module.add_class::<PyComputePipeline>());
+    let open = try!(PyCFunction::new_closure(py, Some("open"), Some("Open a device."), move |_args, _kwargs| {
+        let desc = DeviceDescriptor {
+            required_features: Default::default(),
+            required_limits: DeviceLimits::baseline(),
+            host_allocator: None,
+            deterministic_replay: None,
+        };
+        match auto(&desc, &DEFAULT_BACKEND_ORDER, backends()) {
+            Ok(device) => Ok(PyDevice { device: device }),
+            Err(e) => Err(PyRuntimeError::new_err(format!("no backend could open a device: {}", e))),
+        }
+    }));
+    module.add_function(open)
+}
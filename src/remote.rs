@@ -0,0 +1,639 @@
+//! An experimental transport of command streams and resource updates to another process, for
+//! debugging rendering on a thin client (an embedded device, a console devkit) from a desktop.
+//!
+//! The application side records its commands with a `CommandRecorder` and sends them, along with
+//! the creation of resources and the data written to them, with a `RemoteSender` over any
+//! `Writer` (usually a `TcpStream`). The remote process reads them back with a `RemoteReceiver`
+//! and carries them out on a backend of its own:
+//!
+//! ```ignore
+//! // on the application side
+//! let mut sender = try!(RemoteSender::new(try!(TcpStream::connect("192.168.1.20:7777"))));
+//! let id = recorder.ids_mut().assign(ResourceKind::Buffer, &vertices);
+//! try!(sender.send(&Message::CreateBuffer { id: id.clone(), length: vertices.length() }));
+//! try!(sender.send(&Message::WriteBuffer { id: id, offset: 0, data: vertex_data }));
+//! // ... encode the frame through the recorder
+//! try!(sender.send_recording(&recorder.take()));
+//! try!(sender.end_frame());
+//!
+//! // in the remote process
+//! let mut receiver = try!(RemoteReceiver::new(stream));
+//! while let Some(message) = try!(receiver.receive()) {
+//!     match message {
+//!         Message::CreateBuffer { id, length } => {
+//!             objects.buffers.insert(id, try!(device.create_buffer(length, None)));
+//!         }
+//!         Message::Commands { commands, .. } => replay(&mut device, &objects, commands),
+//!         // ... and the rest of the messages
+//!     }
+//! }
+//! ```
+//!
+//! Pipelines, bind groups and the other objects created from descriptors are sent with their
+//! descriptors, in which objects are replaced by their IDs, and shader programs are sent as
+//! shader archives.
+//!
+//! Resources are referred to by `ResourceId`, so every resource commands use must be assigned an
+//! ID (see `resource_ids`) and have its creation sent before the first recording using it.
+//! Objects recorded by name cannot be resolved by the remote process.
+//!
+//! # Format
+//!
+//! The stream starts with the magic `"MSRT"` and a `u32` version (currently 1), followed by
+//! messages, each a `u32` length, then that many bytes: a `u32` tag, then the fields of the
+//! message. As in `shader_archive`, integers are little endian, and strings and blobs are a `u32`
+//! length followed by that many bytes. Descriptors without objects in them are a string of their
+//! serialized form, as in `pipeline_loader`.
+//!
+//! The format is not stable: both sides must be built from the same version of the crate.
+//
+// todo: a player which replays received recordings on a `Device`, mapping each command's name and
+// arguments back to the encoder method, so the remote process does not have to write its own.
+
+use std::io::{EndOfFile, IoError, IoResult};
+
+use serde::{Deserialize, Serialize};
+
+use {Origin, PixelFormat, Size, TextureDescriptor, TextureType, TextureUsage};
+use {BindGroupLayoutDescriptor, ComputePipelineDescriptor, DepthStencilStateDescriptor, RenderPipelineDescriptor, SamplerDescriptor};
+use command_recording::{Argument, RecordedCommand, Recording};
+use resource_ids::{ResourceId, ResourceKind};
+use shader_archive::{ArchiveError, ShaderArchive};
+
+static MAGIC: &'static [u8] = b"MSRT";
+
+/// The current (and only) version of the format.
+pub const VERSION: u32 = 1;
+
+/// A recorded command, as received: as a `RecordedCommand`, but owning its names.
+#[deriving(Clone, PartialEq, Show)]
+pub struct RemoteCommand {
+    pub name: String,
+    pub arguments: Vec<(String, Argument)>,
+}
+
+impl RemoteCommand {
+    pub fn from_recorded(command: &RecordedCommand) -> RemoteCommand {
+        RemoteCommand {
+            name: command.name.to_string(),
+            arguments: command.arguments.iter().map(|&(name, ref value)| (name.to_string(), value.clone())).collect(),
+        }
+    }
+
+    /// The argument named `name`, if the command has one.
+    pub fn argument(&self, name: &str) -> Option<&Argument> {
+        self.arguments.iter().find(|&&(ref argument, _)| argument.as_slice() == name).map(|&(_, ref value)| value)
+    }
+}
+
+/// A resource of a bind group, by ID, as in `BindingResource`.
+#[deriving(Clone, PartialEq, Show)]
+pub enum RemoteBindingResource {
+    /// A buffer, and the offset and size of the slice bound.
+    Buffer(ResourceId, uint, uint),
+    Texture(ResourceId),
+    Sampler(ResourceId),
+}
+
+/// An entry of a bind group, by ID, as in `BindGroupEntry`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct RemoteBindGroupEntry {
+    pub binding: uint,
+    pub array_element: uint,
+    pub resource: RemoteBindingResource,
+}
+
+/// The messages of the transport.
+pub enum Message {
+    /// A `length` byte buffer was created.
+    CreateBuffer { id: ResourceId, length: uint },
+    /// A texture was created.
+    CreateTexture { id: ResourceId, desc: TextureDescriptor },
+    /// A resource was destroyed. Its ID is not used again.
+    Destroy { id: ResourceId },
+    /// `Device::write_buffer`.
+    WriteBuffer { id: ResourceId, offset: uint, data: Vec<u8> },
+    /// `Device::write_texture`, with the data tightly packed.
+    WriteTexture { id: ResourceId, mipmap_level: uint, slice: uint, origin: Origin, size: Size, data: Vec<u8> },
+    /// The commands of a `Recording`, to be encoded and committed in order.
+    Commands { sequence: u64, commands: Vec<RemoteCommand> },
+    /// The `frame`th frame is complete, and may be presented.
+    EndFrame { frame: u64 },
+    /// A shader program was created from `archive`.
+    CreateShaderProgram { id: ResourceId, archive: ShaderArchive },
+    /// A render pipeline was created from the shader program `program`.
+    CreateRenderPipeline { id: ResourceId, program: ResourceId, desc: RenderPipelineDescriptor },
+    /// A compute pipeline was created from the shader program `program`.
+    CreateComputePipeline { id: ResourceId, program: ResourceId, desc: ComputePipelineDescriptor },
+    CreateSampler { id: ResourceId, desc: SamplerDescriptor },
+    CreateDepthStencilState { id: ResourceId, desc: DepthStencilStateDescriptor },
+    CreateBindGroupLayout { id: ResourceId, desc: BindGroupLayoutDescriptor },
+    /// A bind group of the bind group layout `layout` was created, as by a `BindGroupDescriptor`.
+    CreateBindGroup { id: ResourceId, layout: ResourceId, entries: Vec<RemoteBindGroupEntry>, unsized_length: uint },
+}
+
+/// Why a stream could not be read.
+#[deriving(Show)]
+pub enum RemoteError {
+    Io(IoError),
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// An enum value or message was not one this version knows.
+    InvalidTag(u32),
+    /// A message ended before its fields did, a string was not UTF-8, or a descriptor could not
+    /// be parsed.
+    Malformed,
+    /// The shader archive of a `CreateShaderProgram` could not be decoded.
+    Archive(ArchiveError),
+}
+
+/// Sends messages over `W`. See the module documentation.
+pub struct RemoteSender<W> {
+    writer: W,
+    frame: u64,
+}
+
+impl<W: Writer> RemoteSender<W> {
+    /// Start a stream on `writer`, writing its header.
+    pub fn new(writer: W) -> IoResult<RemoteSender<W>> {
+        let mut writer = writer;
+        try!(writer.write(MAGIC));
+        try!(writer.write_le_u32(VERSION));
+        Ok(RemoteSender { writer: writer, frame: 0 })
+    }
+
+    pub fn send(&mut self, message: &Message) -> IoResult<()> {
+        enter_span!("RemoteSender::send");
+        let encoded = encode(message);
+        try!(self.writer.write_le_u32(encoded.len() as u32));
+        self.writer.write(encoded.as_slice())
+    }
+
+    /// Send the commands of `recording`.
+    pub fn send_recording(&mut self, recording: &Recording) -> IoResult<()> {
+        self.send(&Message::Commands {
+            sequence: recording.sequence,
+            commands: recording.commands.iter().map(RemoteCommand::from_recorded).collect(),
+        })
+    }
+
+    /// Send the end of the current frame, and flush the writer.
+    pub fn end_frame(&mut self) -> IoResult<()> {
+        let frame = self.frame;
+        self.frame += 1;
+        try!(self.send(&Message::EndFrame { frame: frame }));
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Receives messages from `R`. See the module documentation.
+pub struct RemoteReceiver<R> {
+    reader: R,
+}
+
+impl<R: Reader> RemoteReceiver<R> {
+    /// Start reading a stream from `reader`, checking its header.
+    pub fn new(reader: R) -> Result<RemoteReceiver<R>, RemoteError> {
+        let mut reader = reader;
+        if try!(reader.read_exact(MAGIC.len()).map_err(RemoteError::Io)).as_slice() != MAGIC {
+            return Err(RemoteError::BadMagic);
+        }
+        let version = try!(reader.read_le_u32().map_err(RemoteError::Io));
+        if version != VERSION {
+            return Err(RemoteError::UnsupportedVersion(version));
+        }
+        Ok(RemoteReceiver { reader: reader })
+    }
+
+    /// The next message, waiting for it to arrive, or `None` once the sender has closed the
+    /// stream.
+    pub fn receive(&mut self) -> Result<Option<Message>, RemoteError> {
+        let length = match self.reader.read_le_u32() {
+            Ok(length) => length as uint,
+            Err(ref e) if e.kind == EndOfFile => return Ok(None),
+            Err(e) => return Err(RemoteError::Io(e)),
+        };
+        let data = try!(self.reader.read_exact(length).map_err(RemoteError::Io));
+        decode(data.as_slice()).map(Some)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+fn encode(message: &Message) -> Vec<u8> {
+    let mut out = Vec::new();
+    match *message {
+        Message::CreateBuffer { ref id, length } => {
+            put_u32(&mut out, 0);
+            put_id(&mut out, id);
+            put_u64(&mut out, length as u64);
+        }
+        Message::CreateTexture { ref id, ref desc } => {
+            put_u32(&mut out, 1);
+            put_id(&mut out, id);
+            put_u32(&mut out, texture_type_tag(&desc.texture_type));
+            put_u32(&mut out, pixel_format_tag(&desc.pixel_format));
+            for &value in [desc.width, desc.height, desc.depth, desc.mipmap_level_count, desc.array_length,
+                           desc.sample_count].iter() {
+                put_u32(&mut out, value as u32);
+            }
+            let usage = &desc.usage;
+            put_u32(&mut out, usage.shader_read as u32 | (usage.shader_write as u32) << 1 | (usage.render_target as u32) << 2);
+        }
+        Message::Destroy { ref id } => {
+            put_u32(&mut out, 2);
+            put_id(&mut out, id);
+        }
+        Message::WriteBuffer { ref id, offset, ref data } => {
+            put_u32(&mut out, 3);
+            put_id(&mut out, id);
+            put_u64(&mut out, offset as u64);
+            put_blob(&mut out, data.as_slice());
+        }
+        Message::WriteTexture { ref id, mipmap_level, slice, ref origin, ref size, ref data } => {
+            put_u32(&mut out, 4);
+            put_id(&mut out, id);
+            for &value in [mipmap_level, slice, origin.x, origin.y, origin.z, size.width, size.height, size.depth].iter() {
+                put_u32(&mut out, value as u32);
+            }
+            put_blob(&mut out, data.as_slice());
+        }
+        Message::Commands { sequence, ref commands } => {
+            put_u32(&mut out, 5);
+            put_u64(&mut out, sequence);
+            put_u32(&mut out, commands.len() as u32);
+            for command in commands.iter() {
+                put_blob(&mut out, command.name.as_bytes());
+                put_u32(&mut out, command.arguments.len() as u32);
+                for &(ref name, ref value) in command.arguments.iter() {
+                    put_blob(&mut out, name.as_bytes());
+                    put_argument(&mut out, value);
+                }
+            }
+        }
+        Message::EndFrame { frame } => {
+            put_u32(&mut out, 6);
+            put_u64(&mut out, frame);
+        }
+        Message::CreateShaderProgram { ref id, ref archive } => {
+            put_u32(&mut out, 7);
+            put_id(&mut out, id);
+            put_blob(&mut out, archive.encode().as_slice());
+        }
+        Message::CreateRenderPipeline { ref id, ref program, ref desc } => {
+            put_u32(&mut out, 8);
+            put_id(&mut out, id);
+            put_id(&mut out, program);
+            put_descriptor(&mut out, desc);
+        }
+        Message::CreateComputePipeline { ref id, ref program, ref desc } => {
+            put_u32(&mut out, 9);
+            put_id(&mut out, id);
+            put_id(&mut out, program);
+            put_descriptor(&mut out, desc);
+        }
+        Message::CreateSampler { ref id, ref desc } => {
+            put_u32(&mut out, 10);
+            put_id(&mut out, id);
+            put_descriptor(&mut out, desc);
+        }
+        Message::CreateDepthStencilState { ref id, ref desc } => {
+            put_u32(&mut out, 11);
+            put_id(&mut out, id);
+            put_descriptor(&mut out, desc);
+        }
+        Message::CreateBindGroupLayout { ref id, ref desc } => {
+            put_u32(&mut out, 12);
+            put_id(&mut out, id);
+            put_descriptor(&mut out, desc);
+        }
+        Message::CreateBindGroup { ref id, ref layout, ref entries, unsized_length } => {
+            put_u32(&mut out, 13);
+            put_id(&mut out, id);
+            put_id(&mut out, layout);
+            put_u64(&mut out, unsized_length as u64);
+            put_u32(&mut out, entries.len() as u32);
+            for entry in entries.iter() {
+                put_u32(&mut out, entry.binding as u32);
+                put_u32(&mut out, entry.array_element as u32);
+                match entry.resource {
+                    RemoteBindingResource::Buffer(ref id, offset, size) => {
+                        put_u32(&mut out, 0);
+                        put_id(&mut out, id);
+                        put_u64(&mut out, offset as u64);
+                        put_u64(&mut out, size as u64);
+                    }
+                    RemoteBindingResource::Texture(ref id) => { put_u32(&mut out, 1); put_id(&mut out, id); }
+                    RemoteBindingResource::Sampler(ref id) => { put_u32(&mut out, 2); put_id(&mut out, id); }
+                }
+            }
+        }
+    }
+    out
+}
+
+fn decode(data: &[u8]) -> Result<Message, RemoteError> {
+    let mut r = MessageReader { data: data, pos: 0 };
+    let message = match try!(r.u32()) {
+        0 => Message::CreateBuffer { id: try!(r.id()), length: try!(r.u64()) as uint },
+        1 => {
+            let id = try!(r.id());
+            let texture_type = try!(texture_type_from_tag(try!(r.u32())));
+            let pixel_format = try!(pixel_format_from_tag(try!(r.u32())));
+            let mut values = [0u, ..6];
+            for value in values.iter_mut() {
+                *value = try!(r.u32()) as uint;
+            }
+            let usage = try!(r.u32());
+            Message::CreateTexture {
+                id: id,
+                desc: TextureDescriptor {
+                    texture_type: texture_type,
+                    pixel_format: pixel_format,
+                    width: values[0],
+                    height: values[1],
+                    depth: values[2],
+                    mipmap_level_count: values[3],
+                    array_length: values[4],
+                    sample_count: values[5],
+                    usage: TextureUsage {
+                        shader_read: usage & 1 != 0,
+                        shader_write: usage & 2 != 0,
+                        render_target: usage & 4 != 0,
+                    },
+                },
+            }
+        }
+        2 => Message::Destroy { id: try!(r.id()) },
+        3 => {
+            let id = try!(r.id());
+            let offset = try!(r.u64()) as uint;
+            Message::WriteBuffer { id: id, offset: offset, data: try!(r.blob()).to_vec() }
+        }
+        4 => {
+            let id = try!(r.id());
+            let mut values = [0u, ..8];
+            for value in values.iter_mut() {
+                *value = try!(r.u32()) as uint;
+            }
+            Message::WriteTexture {
+                id: id,
+                mipmap_level: values[0],
+                slice: values[1],
+                origin: Origin { x: values[2], y: values[3], z: values[4] },
+                size: Size { width: values[5], height: values[6], depth: values[7] },
+                data: try!(r.blob()).to_vec(),
+            }
+        }
+        5 => {
+            let sequence = try!(r.u64());
+            let mut commands = Vec::new();
+            for _ in range(0, try!(r.u32())) {
+                let name = try!(r.string());
+                let mut arguments = Vec::new();
+                for _ in range(0, try!(r.u32())) {
+                    let argument = try!(r.string());
+                    arguments.push((argument, try!(r.argument())));
+                }
+                commands.push(RemoteCommand { name: name, arguments: arguments });
+            }
+            Message::Commands { sequence: sequence, commands: commands }
+        }
+        6 => Message::EndFrame { frame: try!(r.u64()) },
+        7 => {
+            let id = try!(r.id());
+            let archive = try!(ShaderArchive::decode(try!(r.blob())).map_err(RemoteError::Archive));
+            Message::CreateShaderProgram { id: id, archive: archive }
+        }
+        8 => {
+            let id = try!(r.id());
+            let program = try!(r.id());
+            Message::CreateRenderPipeline { id: id, program: program, desc: try!(r.descriptor()) }
+        }
+        9 => {
+            let id = try!(r.id());
+            let program = try!(r.id());
+            Message::CreateComputePipeline { id: id, program: program, desc: try!(r.descriptor()) }
+        }
+        10 => {
+            let id = try!(r.id());
+            Message::CreateSampler { id: id, desc: try!(r.descriptor()) }
+        }
+        11 => {
+            let id = try!(r.id());
+            Message::CreateDepthStencilState { id: id, desc: try!(r.descriptor()) }
+        }
+        12 => {
+            let id = try!(r.id());
+            Message::CreateBindGroupLayout { id: id, desc: try!(r.descriptor()) }
+        }
+        13 => {
+            let id = try!(r.id());
+            let layout = try!(r.id());
+            let unsized_length = try!(r.u64()) as uint;
+            let mut entries = Vec::new();
+            for _ in range(0, try!(r.u32())) {
+                let binding = try!(r.u32()) as uint;
+                let array_element = try!(r.u32()) as uint;
+                let resource = match try!(r.u32()) {
+                    0 => {
+                        let id = try!(r.id());
+                        let offset = try!(r.u64()) as uint;
+                        RemoteBindingResource::Buffer(id, offset, try!(r.u64()) as uint)
+                    }
+                    1 => RemoteBindingResource::Texture(try!(r.id())),
+                    2 => RemoteBindingResource::Sampler(try!(r.id())),
+                    tag => return Err(RemoteError::InvalidTag(tag)),
+                };
+                entries.push(RemoteBindGroupEntry { binding: binding, array_element: array_element, resource: resource });
+            }
+            Message::CreateBindGroup { id: id, layout: layout, entries: entries, unsized_length: unsized_length }
+        }
+        tag => return Err(RemoteError::InvalidTag(tag)),
+    };
+    if r.pos != data.len() {
+        return Err(RemoteError::Malformed);
+    }
+    Ok(message)
+}
+
+const RESOURCE_KINDS: [ResourceKind, ..11] = [
+    ResourceKind::Buffer, ResourceKind::Texture, ResourceKind::Sampler, ResourceKind::ShaderProgram,
+    ResourceKind::RenderPipeline, ResourceKind::ComputePipeline, ResourceKind::DepthStencilState,
+    ResourceKind::BindGroupLayout, ResourceKind::BindGroup, ResourceKind::Event, ResourceKind::Fence,
+];
+
+const PIXEL_FORMATS: [PixelFormat, ..20] = [
+    PixelFormat::R8Unorm, PixelFormat::RG8Unorm, PixelFormat::RGBA8Unorm, PixelFormat::RGBA8UnormSrgb,
+    PixelFormat::BGRA8Unorm, PixelFormat::BGRA8UnormSrgb, PixelFormat::R16Float, PixelFormat::RG16Float,
+    PixelFormat::RGBA16Float, PixelFormat::R32Float, PixelFormat::RG32Float, PixelFormat::RGBA32Float,
+    PixelFormat::R32Uint, PixelFormat::RG32Uint, PixelFormat::R32Sint, PixelFormat::RG11B10Float,
+    PixelFormat::RGB9E5Float, PixelFormat::Depth32Float, PixelFormat::Depth24UnormStencil8,
+    PixelFormat::Depth32FloatStencil8,
+];
+
+fn pixel_format_tag(format: &PixelFormat) -> u32 {
+    PIXEL_FORMATS.iter().position(|f| f == format).unwrap() as u32
+}
+
+fn pixel_format_from_tag(tag: u32) -> Result<PixelFormat, RemoteError> {
+    match PIXEL_FORMATS.get(tag as uint) {
+        Some(format) => Ok(format.clone()),
+        None => Err(RemoteError::InvalidTag(tag)),
+    }
+}
+
+fn texture_type_tag(ty: &TextureType) -> u32 {
+    match *ty {
+        TextureType::Type2D => 0,
+        TextureType::Type2DArray => 1,
+        TextureType::Type3D => 2,
+        TextureType::TypeCube => 3,
+    }
+}
+
+fn texture_type_from_tag(tag: u32) -> Result<TextureType, RemoteError> {
+    match tag {
+        0 => Ok(TextureType::Type2D),
+        1 => Ok(TextureType::Type2DArray),
+        2 => Ok(TextureType::Type3D),
+        3 => Ok(TextureType::TypeCube),
+        _ => Err(RemoteError::InvalidTag(tag)),
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    for i in range(0, 4u) {
+        out.push((v >> (i * 8)) as u8);
+    }
+}
+
+fn put_u64(out: &mut Vec<u8>, v: u64) {
+    for i in range(0, 8u) {
+        out.push((v >> (i * 8)) as u8);
+    }
+}
+
+fn put_blob(out: &mut Vec<u8>, data: &[u8]) {
+    put_u32(out, data.len() as u32);
+    out.push_all(data);
+}
+
+fn put_id(out: &mut Vec<u8>, id: &ResourceId) {
+    put_u32(out, RESOURCE_KINDS.iter().position(|kind| *kind == id.kind).unwrap() as u32);
+    put_u32(out, id.index);
+}
+
+/// Put `desc` in its serialized form, as a string.
+fn put_descriptor<T: Serialize>(out: &mut Vec<u8>, desc: &T) {
+    put_blob(out, serde_json::to_string(desc).unwrap().as_bytes());
+}
+
+fn put_argument(out: &mut Vec<u8>, argument: &Argument) {
+    match *argument {
+        Argument::Uint(value) => { put_u32(out, 0); put_u64(out, value as u64); }
+        Argument::Int(value) => { put_u32(out, 1); put_u64(out, value as i64 as u64); }
+        Argument::Float(value) => { put_u32(out, 2); put_u64(out, unsafe { std::mem::transmute(value) }); }
+        Argument::Bool(value) => { put_u32(out, 3); put_u32(out, value as u32); }
+        Argument::Object(ref name) => { put_u32(out, 4); put_blob(out, name.as_bytes()); }
+        Argument::Slice(ref name, offset, size) => {
+            put_u32(out, 5);
+            put_blob(out, name.as_bytes());
+            put_u64(out, offset as u64);
+            put_u64(out, size as u64);
+        }
+        Argument::Resource(ref id) => { put_u32(out, 6); put_id(out, id); }
+        Argument::ResourceSlice(ref id, offset, size) => {
+            put_u32(out, 7);
+            put_id(out, id);
+            put_u64(out, offset as u64);
+            put_u64(out, size as u64);
+        }
+        Argument::Bytes(ref bytes) => { put_u32(out, 8); put_blob(out, bytes.as_slice()); }
+        Argument::Value(ref value) => { put_u32(out, 9); put_blob(out, value.as_bytes()); }
+    }
+}
+
+struct MessageReader<'a> {
+    data: &'a [u8],
+    pos: uint,
+}
+
+impl<'a> MessageReader<'a> {
+    fn bytes(&mut self, n: uint) -> Result<&'a [u8], RemoteError> {
+        if self.data.len() - self.pos < n {
+            return Err(RemoteError::Malformed);
+        }
+        let bytes = self.data.slice(self.pos, self.pos + n);
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, RemoteError> {
+        let b = try!(self.bytes(4));
+        Ok(range(0, 4u).fold(0, |v, i| v | (b[i] as u32 << (i * 8))))
+    }
+
+    fn u64(&mut self) -> Result<u64, RemoteError> {
+        let b = try!(self.bytes(8));
+        Ok(range(0, 8u).fold(0, |v, i| v | (b[i] as u64 << (i * 8))))
+    }
+
+    fn blob(&mut self) -> Result<&'a [u8], RemoteError> {
+        let len = try!(self.u32()) as uint;
+        self.bytes(len)
+    }
+
+    fn string(&mut self) -> Result<String, RemoteError> {
+        match String::from_utf8(try!(self.blob()).to_vec()) {
+            Ok(string) => Ok(string),
+            Err(_) => Err(RemoteError::Malformed),
+        }
+    }
+
+    fn id(&mut self) -> Result<ResourceId, RemoteError> {
+        let tag = try!(self.u32());
+        let kind = match RESOURCE_KINDS.get(tag as uint) {
+            Some(kind) => kind.clone(),
+            None => return Err(RemoteError::InvalidTag(tag)),
+        };
+        Ok(ResourceId { kind: kind, index: try!(self.u32()) })
+    }
+
+    fn descriptor<T: Deserialize>(&mut self) -> Result<T, RemoteError> {
+        match serde_json::from_str(try!(self.string()).as_slice()) {
+            Ok(desc) => Ok(desc),
+            Err(_) => Err(RemoteError::Malformed),
+        }
+    }
+
+    fn argument(&mut self) -> Result<Argument, RemoteError> {
+        Ok(match try!(self.u32()) {
+            0 => Argument::Uint(try!(self.u64()) as uint),
+            1 => Argument::Int(try!(self.u64()) as i64 as int),
+            2 => Argument::Float(unsafe { std::mem::transmute(try!(self.u64())) }),
+            3 => Argument::Bool(try!(self.u32()) != 0),
+            4 => Argument::Object(try!(self.string())),
+            5 => {
+                let name = try!(self.string());
+                let offset = try!(self.u64()) as uint;
+                Argument::Slice(name, offset, try!(self.u64()) as uint)
+            }
+            6 => Argument::Resource(try!(self.id())),
+            7 => {
+                let id = try!(self.id());
+                let offset = try!(self.u64()) as uint;
+                Argument::ResourceSlice(id, offset, try!(self.u64()) as uint)
+            }
+            8 => Argument::Bytes(try!(self.blob()).to_vec()),
+            9 => Argument::Value(try!(self.string())),
+            tag => return Err(RemoteError::InvalidTag(tag)),
+        })
+    }
+}
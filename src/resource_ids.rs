@@ -0,0 +1,121 @@
+//! Stable IDs for resources and commands, for tools which capture command streams and refer to
+//! them later: networked renderers, replayers, and comparisons of captures across runs.
+//!
+//! Backends hand out objects at whatever address they are allocated at, and names made up from
+//! the order objects are first used in (as `CommandRecorder` makes up for unlabeled ones) change
+//! whenever culling or scheduling does. `ResourceIds` instead numbers objects in the order they are
+//! created, per kind, when the application asks it to. An application which creates its objects in
+//! the same order gets the same IDs every run, on every machine:
+//!
+//! ```ignore
+//! let vertices = try!(device.create_buffer(size, None));
+//! let id = recorder.ids_mut().assign(ResourceKind::Buffer, &vertices); // buffer#0
+//! ```
+//!
+//! IDs are opt-in: objects never assigned one are recorded by label or made up name, as before.
+//! Objects created at times which depend on the machine (streamed textures, say) should be
+//! assigned IDs from a separate `ResourceIds`, or not at all, so they do not shift everyone else's.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// What kind of object a `ResourceId` is for. Each kind is numbered separately.
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub enum ResourceKind {
+    Buffer,
+    Texture,
+    Sampler,
+    ShaderProgram,
+    RenderPipeline,
+    ComputePipeline,
+    DepthStencilState,
+    BindGroupLayout,
+    BindGroup,
+    Event,
+    Fence,
+}
+
+impl ResourceKind {
+    /// The name of the kind, as IDs print it.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ResourceKind::Buffer => "buffer",
+            ResourceKind::Texture => "texture",
+            ResourceKind::Sampler => "sampler",
+            ResourceKind::ShaderProgram => "shader_program",
+            ResourceKind::RenderPipeline => "render_pipeline",
+            ResourceKind::ComputePipeline => "compute_pipeline",
+            ResourceKind::DepthStencilState => "depth_stencil_state",
+            ResourceKind::BindGroupLayout => "bind_group_layout",
+            ResourceKind::BindGroup => "bind_group",
+            ResourceKind::Event => "event",
+            ResourceKind::Fence => "fence",
+        }
+    }
+}
+
+/// The ID of an object: the `index`th of its `kind` to be assigned one. Prints as `buffer#3`.
+#[deriving(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct ResourceId {
+    pub kind: ResourceKind,
+    pub index: u32,
+}
+
+impl fmt::Show for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}#{}", self.kind.name(), self.index)
+    }
+}
+
+/// The ID of a recorded command: the `index`th command of the `recording`th recording a
+/// `CommandRecorder` took.
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+#[cfg_attr(feature = "serde", deriving(Serialize, Deserialize))]
+pub struct CommandId {
+    pub recording: u64,
+    pub index: u32,
+}
+
+/// The IDs assigned to objects so far. See the module documentation.
+pub struct ResourceIds {
+    /// The ID of every object assigned one, by address.
+    ids: HashMap<uint, ResourceId>,
+    /// The next index of each kind.
+    next: HashMap<ResourceKind, u32>,
+}
+
+impl ResourceIds {
+    pub fn new() -> ResourceIds {
+        ResourceIds { ids: HashMap::new(), next: HashMap::new() }
+    }
+
+    /// Give `object` the next ID of `kind`. Call this where the object is created.
+    ///
+    /// Objects are told apart by address, so this must also be called for an object created
+    /// where a dropped one was, which then gets an ID of its own. Indices are never reused.
+    pub fn assign<T>(&mut self, kind: ResourceKind, object: &T) -> ResourceId {
+        let index = self.next.get(&kind).map(|&index| index).unwrap_or(0);
+        self.next.insert(kind.clone(), index + 1);
+        let id = ResourceId { kind: kind, index: index };
+        self.ids.insert(object as *const T as uint, id.clone());
+        id
+    }
+
+    /// The ID assigned to `object`, if any.
+    pub fn get<T>(&self, object: &T) -> Option<ResourceId> {
+        self.ids.get(&(object as *const T as uint)).map(|id| id.clone())
+    }
+
+    /// Forget the ID of `object`, which is about to be dropped, so that an object which is created
+    /// where it was and never assigned an ID is not mistaken for it.
+    pub fn forget<T>(&mut self, object: &T) {
+        self.ids.remove(&(object as *const T as uint));
+    }
+
+    /// How many IDs of `kind` have been assigned.
+    pub fn count(&self, kind: &ResourceKind) -> u32 {
+        self.next.get(kind).map(|&index| index).unwrap_or(0)
+    }
+}
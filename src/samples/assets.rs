@@ -0,0 +1,153 @@
+//! Loading sample assets: meshes from Wavefront OBJ files and images from PAM files.
+//!
+//! Assets live in a directory given to `Assets::new`, and are loaded by their path relative to
+//! it. Only what the samples use is supported; this is not a general purpose asset pipeline.
+
+use std::io::{File, IoError};
+
+use golden::{Image, PamError};
+
+/// A mesh vertex, laid out as the samples' vertex descriptors expect it.
+#[repr(C)]
+#[deriving(Clone, PartialEq)]
+pub struct MeshVertex {
+    pub position: [f32, ..3],
+    pub normal: [f32, ..3],
+    pub uv: [f32, ..2],
+}
+
+/// An indexed triangle mesh.
+pub struct Mesh {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+/// Why an asset could not be loaded.
+#[deriving(Show)]
+pub enum AssetError {
+    Io(IoError),
+    /// The OBJ file is malformed, on this (1-based) line.
+    Obj(uint),
+    Image(PamError),
+}
+
+/// The sample asset directory.
+pub struct Assets {
+    root: Path,
+}
+
+impl Assets {
+    pub fn new(root: Path) -> Assets {
+        Assets { root: root }
+    }
+
+    /// The contents of a file.
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, AssetError> {
+        File::open(&self.root.join(name)).read_to_end().map_err(AssetError::Io)
+    }
+
+    pub fn image(&self, name: &str) -> Result<Image, AssetError> {
+        Image::decode_pam(try!(self.read(name)).as_slice()).map_err(AssetError::Image)
+    }
+
+    /// Load an OBJ mesh. Polygons are triangulated as fans; groups and materials are ignored.
+    pub fn mesh(&self, name: &str) -> Result<Mesh, AssetError> {
+        let data = try!(self.read(name));
+        parse_obj(String::from_utf8_lossy(data.as_slice()).as_slice())
+    }
+}
+
+fn floats(words: &[&str], line: uint) -> Result<Vec<f32>, AssetError> {
+    let mut values = Vec::with_capacity(words.len());
+    for word in words.iter() {
+        match from_str::<f32>(*word) {
+            Some(value) => values.push(value),
+            None => return Err(AssetError::Obj(line)),
+        }
+    }
+    Ok(values)
+}
+
+/// Parse the `v`, `vt`, `vn` and `f` statements of an OBJ file.
+pub fn parse_obj(source: &str) -> Result<Mesh, AssetError> {
+    let (mut positions, mut uvs, mut normals) = (Vec::new(), Vec::new(), Vec::new());
+    let mut mesh = Mesh { vertices: Vec::new(), indices: Vec::new() };
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let words: Vec<&str> = line.words().collect();
+        if words.is_empty() {
+            continue;
+        }
+        match words[0] {
+            "v" if words.len() >= 4 => positions.push(try!(floats(words.slice(1, 4), line_number))),
+            "vt" if words.len() >= 3 => uvs.push(try!(floats(words.slice(1, 3), line_number))),
+            "vn" if words.len() >= 4 => normals.push(try!(floats(words.slice(1, 4), line_number))),
+            "f" if words.len() >= 4 => {
+                let first = mesh.vertices.len() as u32;
+                for corner in words.slice_from(1).iter() {
+                    let mut parts = corner.split('/');
+                    let mut index = |list_len: uint| -> Result<Option<uint>, AssetError> {
+                        match parts.next() {
+                            None | Some("") => Ok(None),
+                            Some(s) => match from_str::<int>(s) {
+                                // 1-based, or negative for relative to the end
+                                Some(n) if n > 0 && n as uint <= list_len => Ok(Some(n as uint - 1)),
+                                Some(n) if n < 0 && (-n) as uint <= list_len => Ok(Some(list_len - (-n) as uint)),
+                                _ => Err(AssetError::Obj(line_number)),
+                            },
+                        }
+                    };
+                    let p = match try!(index(positions.len())) {
+                        Some(p) => p,
+                        None => return Err(AssetError::Obj(line_number)),
+                    };
+                    let t = try!(index(uvs.len()));
+                    let n = try!(index(normals.len()));
+                    let position: &Vec<f32> = &positions[p];
+                    mesh.vertices.push(MeshVertex {
+                        position: [position[0], position[1], position[2]],
+                        normal: n.map_or([0.0, 1.0, 0.0], |n| [normals[n][0], normals[n][1], normals[n][2]]),
+                        // obj has v going up, textures have it going down
+                        uv: t.map_or([0.0, 0.0], |t| [uvs[t][0], 1.0 - uvs[t][1]]),
+                    });
+                }
+                let corners = mesh.vertices.len() as u32 - first;
+                for k in range(1, corners - 1) {
+                    mesh.indices.push_all(&[first, first + k, first + k + 1]);
+                }
+            }
+            "v" | "vt" | "vn" | "f" => return Err(AssetError::Obj(line_number)),
+            _ => {}
+        }
+    }
+    Ok(mesh)
+}
+
+/// A unit cube centered on the origin, with a face per side so each has its own normal and
+/// texture coordinates.
+pub fn cube() -> Mesh {
+    // (normal, right, up) of each face
+    let faces = [
+        ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ];
+    let mut mesh = Mesh { vertices: Vec::new(), indices: Vec::new() };
+    for &(n, r, u) in faces.iter() {
+        let first = mesh.vertices.len() as u32;
+        for &(x, y) in [(-1.0f32, -1.0f32), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter() {
+            mesh.vertices.push(MeshVertex {
+                position: [0.5 * (n[0] + x * r[0] + y * u[0]), 0.5 * (n[1] + x * r[1] + y * u[1]),
+                           0.5 * (n[2] + x * r[2] + y * u[2])],
+                normal: n,
+                uv: [(x + 1.0) / 2.0, (1.0 - y) / 2.0],
+            });
+        }
+        mesh.indices.push_all(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+    mesh
+}
@@ -0,0 +1,135 @@
+//! A fly-through camera, and the little matrix math samples need.
+//!
+//! Matrices are column major `[f32, ..16]`, as shaders expect them, and clip space depth is
+//! `[0, 1]`.
+
+use samples::{Input, Key};
+
+pub type Matrix = [f32, ..16];
+
+pub fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [0.0, ..16];
+    for column in range(0, 4) {
+        for row in range(0, 4) {
+            m[column * 4 + row] = range(0, 4).fold(0.0, |sum, k| sum + a[k * 4 + row] * b[column * 4 + k]);
+        }
+    }
+    m
+}
+
+/// A right handed perspective projection, looking down `-z`, with `fov_y` in radians.
+pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Matrix {
+    let f = 1.0 / (fov_y / 2.0).tan();
+    let range = near - far;
+    [f / aspect, 0.0, 0.0, 0.0,
+     0.0, f, 0.0, 0.0,
+     0.0, 0.0, far / range, -1.0,
+     0.0, 0.0, near * far / range, 0.0]
+}
+
+fn cross(a: [f32, ..3], b: [f32, ..3]) -> [f32, ..3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn dot(a: [f32, ..3], b: [f32, ..3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(v: [f32, ..3]) -> [f32, ..3] {
+    let length = dot(v, v).sqrt();
+    [v[0] / length, v[1] / length, v[2] / length]
+}
+
+/// The view matrix of a camera at `eye` looking along `forward`, with `+y` up.
+pub fn look_to(eye: [f32, ..3], forward: [f32, ..3]) -> Matrix {
+    let f = normalize(forward);
+    let s = normalize(cross(f, [0.0, 1.0, 0.0]));
+    let u = cross(s, f);
+    [s[0], u[0], -f[0], 0.0,
+     s[1], u[1], -f[1], 0.0,
+     s[2], u[2], -f[2], 0.0,
+     -dot(s, eye), -dot(u, eye), dot(f, eye), 1.0]
+}
+
+/// A rotation of `angle` radians around the `y` axis.
+pub fn rotation_y(angle: f32) -> Matrix {
+    let (s, c) = (angle.sin(), angle.cos());
+    [c, 0.0, -s, 0.0,
+     0.0, 1.0, 0.0, 0.0,
+     s, 0.0, c, 0.0,
+     0.0, 0.0, 0.0, 1.0]
+}
+
+/// A camera moved with WASD (and Q/E for down/up), and turned by dragging the mouse.
+pub struct Camera {
+    pub position: [f32, ..3],
+    /// Rotation around `+y`, in radians. `0` looks down `-z`.
+    pub yaw: f32,
+    /// Rotation up from the horizon, in radians.
+    pub pitch: f32,
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+    /// Movement speed, in units per second.
+    pub speed: f32,
+    /// Turning speed, in radians per pixel dragged.
+    pub sensitivity: f32,
+}
+
+impl Camera {
+    pub fn new(position: [f32, ..3]) -> Camera {
+        Camera {
+            position: position,
+            yaw: 0.0,
+            pitch: 0.0,
+            fov_y: std::f32::consts::FRAC_PI_3,
+            near: 0.1,
+            far: 100.0,
+            speed: 3.0,
+            sensitivity: 0.005,
+        }
+    }
+
+    pub fn forward(&self) -> [f32, ..3] {
+        [-self.yaw.sin() * self.pitch.cos(), self.pitch.sin(), -self.yaw.cos() * self.pitch.cos()]
+    }
+
+    /// Move and turn according to the input of a frame `delta_time` seconds long.
+    pub fn update(&mut self, input: &Input, delta_time: f32) {
+        self.yaw -= input.drag.0 * self.sensitivity;
+        self.pitch = (self.pitch - input.drag.1 * self.sensitivity).max(-1.5).min(1.5);
+
+        let forward = self.forward();
+        let right = normalize(cross(forward, [0.0, 1.0, 0.0]));
+        let step = self.speed * delta_time;
+        let mut moves = Vec::new();
+        if input.is_pressed(Key::W) { moves.push((forward, step)); }
+        if input.is_pressed(Key::S) { moves.push((forward, -step)); }
+        if input.is_pressed(Key::D) { moves.push((right, step)); }
+        if input.is_pressed(Key::A) { moves.push((right, -step)); }
+        if input.is_pressed(Key::E) { moves.push(([0.0, 1.0, 0.0], step)); }
+        if input.is_pressed(Key::Q) { moves.push(([0.0, 1.0, 0.0], -step)); }
+        for &(direction, distance) in moves.iter() {
+            for i in range(0, 3) {
+                self.position[i] += direction[i] * distance;
+            }
+        }
+    }
+
+    /// The view to world space transform: the inverse of `look_to(position, forward())`.
+    pub fn inverse_view(&self) -> Matrix {
+        let f = normalize(self.forward());
+        let s = normalize(cross(f, [0.0, 1.0, 0.0]));
+        let u = cross(s, f);
+        let p = self.position;
+        [s[0], s[1], s[2], 0.0,
+         u[0], u[1], u[2], 0.0,
+         -f[0], -f[1], -f[2], 0.0,
+         p[0], p[1], p[2], 1.0]
+    }
+
+    /// The world to clip space transform, for attachments of the given aspect ratio.
+    pub fn view_projection(&self, aspect: f32) -> Matrix {
+        multiply(&perspective(self.fov_y, aspect, self.near, self.far), &look_to(self.position, self.forward()))
+    }
+}
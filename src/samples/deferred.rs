@@ -0,0 +1,338 @@
+//! A grid of cubes lit by many moving point lights, with deferred shading.
+//!
+//! `encode_passes` draws the cubes once into a G-buffer of three color attachments (albedo, the
+//! world space normal and the world space position) and depth. `render` then shades every pixel
+//! of the window once with one fullscreen triangle, whose fragment function reads the G-buffer
+//! back and adds up every light. The cost of the lights is per pixel on screen, however many
+//! cubes are behind it.
+
+use {Device, BufferSlice, CommandBuffer, IndexFormat, PrimitiveType, RenderCommandEncoder, RenderPipelineDescriptor};
+use {ClearColor, LoadAction, RenderPassColorAttachment, RenderPassDepthAttachment, RenderPassDescriptor, StoreAction};
+use {CompareFunction, DepthStencilStateDescriptor};
+use {PixelFormat, TextureDescriptor, TextureType, TextureUsage};
+use samples::{Frame, Sample};
+use samples::assets::{cube, MeshVertex};
+use samples::camera::{Camera, Matrix};
+use util::{as_bytes, slice_as_bytes};
+
+/// The cubes are drawn in a `GRID` by `GRID` square.
+const GRID: uint = 12;
+
+/// The number of point lights, which must match `LIGHT_COUNT` in `SOURCE`.
+const LIGHT_COUNT: uint = 32;
+
+#[repr(C)]
+struct GeometryUniforms {
+    view_projection: Matrix,
+    grid: u32,
+    _padding: [u32, ..3],
+}
+
+#[repr(C)]
+struct LightingUniforms {
+    /// The position of each light, and the distance it reaches.
+    light_positions: [[f32, ..4], ..LIGHT_COUNT],
+    light_colors: [[f32, ..4], ..LIGHT_COUNT],
+    camera_position: [f32, ..4],
+}
+
+/// The formats of the G-buffer's color attachments: albedo, normal and position.
+fn gbuffer_formats() -> [PixelFormat, ..3] {
+    [PixelFormat::RGBA8Unorm, PixelFormat::RGBA16Float, PixelFormat::RGBA32Float]
+}
+
+pub struct Deferred<D: Device> {
+    geometry_pipeline: D::RenderPipeline,
+    lighting_pipeline: D::RenderPipeline,
+    depth_state: D::DepthStencilState,
+    vertices: D::Buffer,
+    indices: D::Buffer,
+    index_count: uint,
+    /// The G-buffer's color attachments, its depth, and their size.
+    gbuffer: Option<(Vec<D::Texture>, D::Texture, (uint, uint))>,
+    pub camera: Camera,
+    time: f32,
+    geometry: GeometryUniforms,
+    lighting: LightingUniforms,
+}
+
+/// Why a `Deferred` sample could not be created.
+pub enum DeferredCreationError<D: Device> {
+    Pipeline(D::RenderPipelineCreationError),
+    Buffer(D::BufferCreationError),
+    Texture(D::TextureCreationError),
+}
+
+fn pipeline<D: Device>(device: &mut D, program: &D::ShaderProgram, label: &str, vertex: &str,
+                       fragment: &str) -> Result<D::RenderPipeline, DeferredCreationError<D>> {
+    match device.create_render_pipeline(program, RenderPipelineDescriptor {
+        label: Some(label.to_string()),
+        robustness: None,
+        vertex_function: vertex.to_string(),
+        fragment_function: Some(fragment.to_string()),
+        vertex_descriptor: None,
+        blending: Vec::new(),
+        sample_count: 1,
+        attachment_formats: None,
+    }) {
+        Ok(pipeline) => Ok(pipeline),
+        Err(e) => Err(DeferredCreationError::Pipeline(e)),
+    }
+}
+
+fn attachment<D: Device>(device: &mut D, format: PixelFormat, size: (uint, uint)) -> Result<D::Texture, D::TextureCreationError> {
+    let (width, height) = size;
+    device.create_texture(TextureDescriptor {
+        texture_type: TextureType::Type2D,
+        pixel_format: format,
+        width: width,
+        height: height,
+        depth: 1,
+        mipmap_level_count: 1,
+        array_length: 1,
+        sample_count: 1,
+        usage: TextureUsage { shader_read: true, shader_write: false, render_target: true },
+    })
+}
+
+impl<D: Device> Deferred<D> {
+    /// Create the sample, with a G-buffer of `size` pixels.
+    ///
+    /// `program` must be a shader program created from `SOURCE`.
+    pub fn new(device: &mut D, program: &D::ShaderProgram, size: (uint, uint)) -> Result<Deferred<D>, DeferredCreationError<D>> {
+        let mesh = cube();
+        let vertices = match device.create_buffer(mesh.vertices.len() * std::mem::size_of::<MeshVertex>(), None) {
+            Ok(buffer) => buffer,
+            Err(e) => return Err(DeferredCreationError::Buffer(e)),
+        };
+        device.write_buffer(&vertices, 0, slice_as_bytes(mesh.vertices.as_slice()));
+        let indices = match device.create_buffer(mesh.indices.len() * 4, None) {
+            Ok(buffer) => buffer,
+            Err(e) => return Err(DeferredCreationError::Buffer(e)),
+        };
+        device.write_buffer(&indices, 0, slice_as_bytes(mesh.indices.as_slice()));
+
+        let geometry_pipeline = try!(pipeline(device, program, "deferred geometry", "geometry_vertex", "geometry_fragment"));
+        let lighting_pipeline = try!(pipeline(device, program, "deferred lighting", "lighting_vertex", "lighting_fragment"));
+        let depth_state = device.create_depth_stencil_state(DepthStencilStateDescriptor {
+            depth_compare_function: CompareFunction::Less,
+            depth_write_enabled: true,
+            front_face_stencil: None,
+            back_face_stencil: None,
+        });
+
+        let mut sample = Deferred {
+            geometry_pipeline: geometry_pipeline,
+            lighting_pipeline: lighting_pipeline,
+            depth_state: depth_state,
+            vertices: vertices,
+            indices: indices,
+            index_count: mesh.indices.len(),
+            gbuffer: None,
+            camera: Camera::new([0.0, 5.0, 12.0]),
+            time: 0.0,
+            geometry: GeometryUniforms { view_projection: [0.0, ..16], grid: GRID as u32, _padding: [0, ..3] },
+            lighting: LightingUniforms {
+                light_positions: [[0.0, ..4], ..LIGHT_COUNT],
+                light_colors: [[0.0, ..4], ..LIGHT_COUNT],
+                camera_position: [0.0, ..4],
+            },
+        };
+        match sample.resize(device, size) {
+            Ok(()) => Ok(sample),
+            Err(e) => Err(DeferredCreationError::Texture(e)),
+        }
+    }
+
+    /// Recreate the G-buffer at `size` pixels.
+    pub fn resize(&mut self, device: &mut D, size: (uint, uint)) -> Result<(), D::TextureCreationError> {
+        let mut colors = Vec::with_capacity(3);
+        for format in gbuffer_formats().iter() {
+            colors.push(try!(attachment(device, format.clone(), size)));
+        }
+        let depth = try!(attachment(device, PixelFormat::Depth32Float, size));
+        self.gbuffer = Some((colors, depth, size));
+        Ok(())
+    }
+}
+
+impl<D: Device> Sample<D> for Deferred<D> {
+    fn name(&self) -> &'static str {
+        "deferred shading"
+    }
+
+    fn update(&mut self, device: &mut D, frame: &Frame) {
+        if self.gbuffer.as_ref().map_or(true, |&(_, _, size)| size != frame.size) {
+            // if this fails, the old G-buffer is kept, and only the part of the window it covers
+            // is lit
+            let _ = self.resize(device, frame.size);
+        }
+        self.camera.update(frame.input, frame.delta_time);
+        self.time += frame.delta_time;
+        let (width, height) = frame.size;
+        self.geometry.view_projection = self.camera.view_projection(width as f32 / height as f32);
+
+        // the lights circle the grid in three rings, every other light the other way around
+        let extent = GRID as f32;
+        for i in range(0, LIGHT_COUNT) {
+            let ring = (i % 3) as f32;
+            let angle = self.time * (0.3 + 0.2 * ring) * if i % 2 == 0 { 1.0 } else { -1.0 } +
+                        i as f32 * std::f32::consts::PI * 2.0 / LIGHT_COUNT as f32;
+            let distance = extent * (0.2 + 0.25 * ring);
+            self.lighting.light_positions[i] = [angle.cos() * distance, 0.8 + ring * 0.6, angle.sin() * distance, 4.0];
+            self.lighting.light_colors[i] = [0.5 + 0.5 * (i as f32 * 1.3).sin(), 0.5 + 0.5 * (i as f32 * 2.1).sin(),
+                                             0.5 + 0.5 * (i as f32 * 0.7).cos(), 1.0];
+        }
+        let p = self.camera.position;
+        self.lighting.camera_position = [p[0], p[1], p[2], 1.0];
+    }
+
+    fn encode_passes(&mut self, commands: &mut D::CommandBuffer, _: &Frame) {
+        let (colors, depth) = match self.gbuffer {
+            Some((ref colors, ref depth, _)) => (colors, depth),
+            None => return,
+        };
+        let mut encoder = commands.render_command_encoder(RenderPassDescriptor {
+            color_attachments: colors.iter().map(|texture| RenderPassColorAttachment {
+                texture: texture, mipmap_level: 0, slice: 0,
+                load_action: LoadAction::Clear, store_action: StoreAction::Store,
+                // a zero normal marks the pixels nothing was drawn in
+                clear_color: ClearColor::Float { red: 0.0, green: 0.0, blue: 0.0, alpha: 0.0 },
+            }).collect(),
+            depth_attachment: Some(RenderPassDepthAttachment {
+                texture: depth, mipmap_level: 0, slice: 0,
+                load_action: LoadAction::Clear, store_action: StoreAction::DontCare, clear_depth: 1.0,
+            }),
+            stencil_attachment: None,
+            sample_positions: None,
+            visibility_result_buffer: None,
+        });
+        encoder.set_render_pipeline(&self.geometry_pipeline);
+        encoder.set_depth_stencil_state(&self.depth_state);
+        encoder.set_vertex_bytes(as_bytes(&self.geometry), 0);
+        encoder.set_vertex_buffer(BufferSlice::whole(&self.vertices), 1);
+        encoder.set_index_buffer(BufferSlice::whole(&self.indices), IndexFormat::UInt32);
+        encoder.draw_indexed(PrimitiveType::Triangle, 0, self.index_count, GRID * GRID, 0, 0);
+        encoder.end_encoding();
+    }
+
+    fn render<E: RenderCommandEncoder<D>>(&mut self, encoder: &mut E, _: &Frame) {
+        let colors = match self.gbuffer {
+            Some((ref colors, _, _)) => colors,
+            None => return,
+        };
+        encoder.set_render_pipeline(&self.lighting_pipeline);
+        encoder.set_fragment_bytes(as_bytes(&self.lighting), 0);
+        for (index, texture) in colors.iter().enumerate() {
+            encoder.set_fragment_texture(texture, index);
+        }
+        encoder.draw(PrimitiveType::Triangle, 0, 3, 1, 0);
+    }
+}
+
+/// The metal shading language source of the deferred shading sample.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+#define LIGHT_COUNT 32
+
+struct MeshVertex {
+    packed_float3 position;
+    packed_float3 normal;
+    float2 uv;
+};
+
+struct GeometryUniforms {
+    float4x4 view_projection;
+    uint grid;
+};
+
+struct LightingUniforms {
+    float4 light_positions[LIGHT_COUNT];
+    float4 light_colors[LIGHT_COUNT];
+    float4 camera_position;
+};
+
+struct GeometryFragment {
+    float4 position [[position]];
+    float3 world_position;
+    float3 normal;
+    float3 albedo [[flat]];
+};
+
+struct GBuffer {
+    float4 albedo [[color(0)]];
+    float4 normal [[color(1)]];
+    float4 position [[color(2)]];
+};
+
+vertex GeometryFragment geometry_vertex(constant GeometryUniforms &u [[buffer(0)]],
+                                        const device MeshVertex *vertices [[buffer(1)]],
+                                        uint vid [[vertex_id]],
+                                        uint iid [[instance_id]])
+{
+    float2 cell = float2(iid % u.grid, iid / u.grid) - float(u.grid - 1) * 0.5f;
+    float height = 0.5f + float((iid * 7) % 5) * 0.4f;
+    float3 local = float3(vertices[vid].position) * float3(0.8f, height, 0.8f);
+    float3 world = local + float3(cell.x * 1.5f, height * 0.5f, cell.y * 1.5f);
+
+    GeometryFragment out;
+    out.position = u.view_projection * float4(world, 1.0f);
+    out.world_position = world;
+    out.normal = float3(vertices[vid].normal);
+    out.albedo = float3(0.6f + 0.1f * float(iid % 4), 0.6f, 0.6f + 0.1f * float(iid % 3));
+    return out;
+}
+
+fragment GBuffer geometry_fragment(GeometryFragment in [[stage_in]])
+{
+    GBuffer out;
+    out.albedo = float4(in.albedo, 1.0f);
+    out.normal = float4(normalize(in.normal), 1.0f);
+    out.position = float4(in.world_position, 1.0f);
+    return out;
+}
+
+vertex float4 lighting_vertex(uint vid [[vertex_id]])
+{
+    float2 uv = float2((vid << 1) & 2, vid & 2);
+    return float4(uv * 2.0f - 1.0f, 0.0f, 1.0f);
+}
+
+fragment float4 lighting_fragment(float4 position [[position]],
+                                  constant LightingUniforms &u [[buffer(0)]],
+                                  texture2d<float> albedo [[texture(0)]],
+                                  texture2d<float> normals [[texture(1)]],
+                                  texture2d<float> positions [[texture(2)]])
+{
+    uint2 pixel = uint2(position.xy);
+    float4 background = float4(0.02f, 0.02f, 0.03f, 1.0f);
+    if (pixel.x >= albedo.get_width() || pixel.y >= albedo.get_height()) {
+        return background;
+    }
+    float4 normal = normals.read(pixel);
+    if (normal.w == 0.0f) {
+        return background;
+    }
+    float3 n = normal.xyz;
+    float3 p = positions.read(pixel).xyz;
+    float3 to_eye = normalize(u.camera_position.xyz - p);
+
+    float3 color = float3(0.03f);
+    for (uint i = 0; i < LIGHT_COUNT; i++) {
+        float3 to_light = u.light_positions[i].xyz - p;
+        float distance = length(to_light);
+        float reach = u.light_positions[i].w;
+        if (distance >= reach) {
+            continue;
+        }
+        float3 l = to_light / distance;
+        float falloff = 1.0f - distance / reach;
+        float diffuse = saturate(dot(n, l));
+        float specular = pow(saturate(dot(n, normalize(l + to_eye))), 32.0f);
+        color += u.light_colors[i].rgb * (diffuse + specular * 0.5f) * falloff * falloff;
+    }
+    return float4(albedo.read(pixel).rgb * color, 1.0f);
+}
+"#;
@@ -0,0 +1,291 @@
+//! A framework for small sample applications, and ports of the classic samples.
+//!
+//! Samples implement `Sample`, and get everything else from here: a `Window` abstraction any
+//! windowing library can sit behind, a fly-through `Camera`, and the `assets` loaders. Each sample
+//! takes the same path through the API that a real application would, so running all of them on
+//! a backend (for example checking their frames with `golden`) doubles as an integration test of
+//! that backend.
+//!
+//! The samples so far:
+//!
+//! * `triangle`: the smallest possible pipeline and draw.
+//! * `textured_cube`: vertex and index buffers, a texture and sampler in a bind group, and a
+//!   camera.
+//! * `visibility_buffer`: an offscreen pass into an integer ID attachment and depth, and a
+//!   shading pass which reconstructs every pixel's triangle from the IDs.
+//! * `shadows`: cascaded shadow maps, each cascade a depth only pass into a slice of an array
+//!   texture, sampled with a comparison sampler.
+//! * `particles`: GPU particles simulated and emitted in compute passes, and drawn with an
+//!   indirect draw whose instance count the GPU wrote.
+//! * `deferred`: a G-buffer pass into several color attachments, and a lighting pass of many
+//!   lights reading them back.
+//!
+//! A `Runner` drives a sample frame by frame, into attachments of its own. `Runner::run` shows
+//! the frames in a `Window`, and `Runner::run_frames` renders a fixed number of frames with a
+//! fixed time step, so that a test can check the last one against a reference image:
+//!
+//! ```ignore
+//! let mut runner = try!(Runner::new(&mut device, queue, (256, 256)));
+//! let mut sample = try!(Triangle::new(&mut device, &program));
+//! try!(runner.run_frames(&mut device, &mut sample, 10, 1.0 / 60.0));
+//! try!(golden::check_golden(&mut device, runner.color(), 256, 256, &reference, Tolerance::default()));
+//! ```
+
+pub mod assets;
+pub mod camera;
+pub mod deferred;
+pub mod particles;
+pub mod shadows;
+pub mod textured_cube;
+pub mod triangle;
+pub mod visibility_buffer;
+
+use {Device, CommandBuffer, CommandBufferError, CommandBufferStatus, CommandQueue, RenderCommandEncoder};
+use {ClearColor, LoadAction, RenderPassColorAttachment, RenderPassDepthAttachment, RenderPassDescriptor, StoreAction};
+use {PixelFormat, TextureDescriptor, TextureType, TextureUsage};
+
+/// A key, for the handful of keys samples react to.
+#[deriving(PartialEq, Clone, Show)]
+pub enum Key {
+    W,
+    A,
+    S,
+    D,
+    Q,
+    E,
+    Space,
+    Escape,
+}
+
+/// Something that happened to the window.
+#[deriving(Clone, Show)]
+pub enum Event {
+    /// The drawable area now is `(width, height)` pixels.
+    Resized(uint, uint),
+    Key { key: Key, pressed: bool },
+    /// The mouse moved by `(dx, dy)` pixels while a button was held.
+    Dragged(f32, f32),
+    CloseRequested,
+}
+
+/// A window samples render into, implemented on top of a windowing library.
+pub trait Window {
+    /// The drawable area, in pixels.
+    fn size(&self) -> (uint, uint);
+
+    /// Every event since the last call.
+    fn poll_events(&mut self) -> Vec<Event>;
+}
+
+/// The state of the input devices, as accumulated from events.
+pub struct Input {
+    pressed: Vec<Key>,
+    /// How far the mouse was dragged since the last frame.
+    pub drag: (f32, f32),
+}
+
+impl Input {
+    pub fn new() -> Input {
+        Input { pressed: Vec::new(), drag: (0.0, 0.0) }
+    }
+
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    /// Fold an event into the state.
+    pub fn handle(&mut self, event: &Event) {
+        match *event {
+            Event::Key { ref key, pressed: true } if !self.is_pressed(key.clone()) => self.pressed.push(key.clone()),
+            Event::Key { ref key, pressed: false } => self.pressed.retain(|k| k != key),
+            Event::Dragged(dx, dy) => {
+                self.drag.0 += dx;
+                self.drag.1 += dy;
+            }
+            _ => {}
+        }
+    }
+
+    /// Forget per-frame state, at the end of a frame.
+    pub fn end_frame(&mut self) {
+        self.drag = (0.0, 0.0);
+    }
+}
+
+/// What a sample needs to know about the frame it is rendering.
+pub struct Frame<'a> {
+    /// The size of the attachments, in pixels.
+    pub size: (uint, uint),
+    /// The time since the previous frame, in seconds.
+    pub delta_time: f32,
+    pub input: &'a Input,
+}
+
+/// A sample application.
+pub trait Sample<D: Device> {
+    /// The name of the sample, as shown in window titles.
+    fn name(&self) -> &'static str;
+
+    /// Update the sample's state, and write whatever buffers the frame will use.
+    fn update(&mut self, device: &mut D, frame: &Frame);
+
+    /// Encode whatever offscreen passes the frame needs into `commands`, before the window's pass.
+    ///
+    /// Samples which only draw into the window need not implement this.
+    fn encode_passes(&mut self, commands: &mut D::CommandBuffer, frame: &Frame) {
+        let _ = (commands, frame);
+    }
+
+    /// Encode the frame, into a render pass which clears the window's attachments: a color
+    /// attachment, and a `Depth32Float` depth attachment.
+    fn render<E: RenderCommandEncoder<D>>(&mut self, encoder: &mut E, frame: &Frame);
+}
+
+/// Why a `Runner` stopped.
+pub enum RunnerError<D: Device> {
+    /// The attachments could not be created at the window's size.
+    Texture(D::TextureCreationError),
+    /// The command buffer of the frame of that index (counting from `0`) failed.
+    Frame(u64, CommandBufferError),
+}
+
+/// Drives a sample frame by frame, into an `RGBA8Unorm` color attachment and a `Depth32Float`
+/// depth attachment of its own. See the module documentation.
+///
+/// Every frame is committed to the runner's queue and waited for before the next one is
+/// encoded: samples are written to be simple rather than fast, and this keeps any failure in the
+/// frame which caused it.
+pub struct Runner<D: Device> {
+    queue: D::CommandQueue,
+    color: D::Texture,
+    depth: D::Texture,
+    size: (uint, uint),
+    input: Input,
+    frame_index: u64,
+}
+
+fn attachment<D: Device>(device: &mut D, format: PixelFormat, size: (uint, uint)) -> Result<D::Texture, D::TextureCreationError> {
+    let (width, height) = size;
+    device.create_texture(TextureDescriptor {
+        texture_type: TextureType::Type2D,
+        pixel_format: format,
+        width: width,
+        height: height,
+        depth: 1,
+        mipmap_level_count: 1,
+        array_length: 1,
+        sample_count: 1,
+        usage: TextureUsage { shader_read: true, shader_write: false, render_target: true },
+    })
+}
+
+impl<D: Device> Runner<D> {
+    /// Create a runner committing to `queue`, with attachments of `size` pixels.
+    pub fn new(device: &mut D, queue: D::CommandQueue, size: (uint, uint)) -> Result<Runner<D>, RunnerError<D>> {
+        let color = try!(attachment(device, PixelFormat::RGBA8Unorm, size).map_err(RunnerError::Texture));
+        let depth = try!(attachment(device, PixelFormat::Depth32Float, size).map_err(RunnerError::Texture));
+        Ok(Runner { queue: queue, color: color, depth: depth, size: size, input: Input::new(), frame_index: 0 })
+    }
+
+    /// Recreate the attachments at `size` pixels, if they are of another size.
+    pub fn resize(&mut self, device: &mut D, size: (uint, uint)) -> Result<(), RunnerError<D>> {
+        if size != self.size {
+            self.color = try!(attachment(device, PixelFormat::RGBA8Unorm, size).map_err(RunnerError::Texture));
+            self.depth = try!(attachment(device, PixelFormat::Depth32Float, size).map_err(RunnerError::Texture));
+            self.size = size;
+        }
+        Ok(())
+    }
+
+    /// The color attachment, holding the last frame rendered.
+    pub fn color(&self) -> &D::Texture {
+        &self.color
+    }
+
+    pub fn size(&self) -> (uint, uint) {
+        self.size
+    }
+
+    /// The input the next frame sees. `run` folds the window's events into it; tests driving
+    /// `frame` themselves can press keys with `Input::handle`.
+    pub fn input(&mut self) -> &mut Input {
+        &mut self.input
+    }
+
+    /// The number of frames rendered so far.
+    pub fn frame_index(&self) -> u64 {
+        self.frame_index
+    }
+
+    /// Render one frame of `sample`, `delta_time` seconds after the last one, and wait for it to
+    /// complete.
+    pub fn frame<S: Sample<D>>(&mut self, device: &mut D, sample: &mut S, delta_time: f32) -> Result<(), RunnerError<D>> {
+        enter_span!("Runner::frame", sample = sample.name(), frame = self.frame_index);
+        let status = {
+            let frame = Frame { size: self.size, delta_time: delta_time, input: &self.input };
+            sample.update(device, &frame);
+
+            let mut commands = self.queue.acquire_command_buffer();
+            sample.encode_passes(&mut commands, &frame);
+            let mut encoder = commands.render_command_encoder(RenderPassDescriptor {
+                color_attachments: vec![RenderPassColorAttachment {
+                    texture: &self.color, mipmap_level: 0, slice: 0,
+                    load_action: LoadAction::Clear, store_action: StoreAction::Store,
+                    clear_color: ClearColor::Float { red: 0.0, green: 0.0, blue: 0.0, alpha: 1.0 },
+                }],
+                depth_attachment: Some(RenderPassDepthAttachment {
+                    texture: &self.depth, mipmap_level: 0, slice: 0,
+                    load_action: LoadAction::Clear, store_action: StoreAction::DontCare, clear_depth: 1.0,
+                }),
+                stencil_attachment: None,
+                sample_positions: None,
+                visibility_result_buffer: None,
+            });
+            sample.render(&mut encoder, &frame);
+            encoder.end_encoding();
+            commands.commit_and_wait()
+        };
+
+        let index = self.frame_index;
+        self.frame_index += 1;
+        self.input.end_frame();
+        match status {
+            CommandBufferStatus::Completed => Ok(()),
+            CommandBufferStatus::Failed(e) => Err(RunnerError::Frame(index, e)),
+        }
+    }
+
+    /// Render `count` frames of `sample`, each `delta_time` seconds after the last, for tests
+    /// which need the same frames on every run.
+    pub fn run_frames<S: Sample<D>>(&mut self, device: &mut D, sample: &mut S, count: uint, delta_time: f32)
+                                    -> Result<(), RunnerError<D>> {
+        for _ in range(0, count) {
+            try!(self.frame(device, sample, delta_time));
+        }
+        Ok(())
+    }
+
+    /// Render frames of `sample` until `window` is closed or escape is pressed, showing each in
+    /// the window with `present`, which is given the device and the color attachment.
+    ///
+    /// The attachments follow the window's size, and frames are timed with the clock.
+    pub fn run<S, W, F>(&mut self, device: &mut D, sample: &mut S, window: &mut W, mut present: F) -> Result<(), RunnerError<D>>
+            where S: Sample<D>, W: Window, F: FnMut(&mut D, &D::Texture) {
+        try!(self.resize(device, window.size()));
+        let mut last = std::time::precise_time_ns();
+        loop {
+            for event in window.poll_events().iter() {
+                match *event {
+                    Event::CloseRequested | Event::Key { key: Key::Escape, pressed: true } => return Ok(()),
+                    Event::Resized(width, height) => try!(self.resize(device, (width, height))),
+                    _ => self.input.handle(event),
+                }
+            }
+            let now = std::time::precise_time_ns();
+            let delta_time = (now - last) as f32 * 1e-9;
+            last = now;
+            try!(self.frame(device, sample, delta_time));
+            present(device, &self.color);
+        }
+    }
+}
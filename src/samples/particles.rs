@@ -0,0 +1,127 @@
+//! A fountain of GPU particles.
+//!
+//! Everything runs through `util::particles::ParticleSystem`: `encode_passes` starts the frame
+//! with a blit pass, and simulates and emits the particles in a compute pass, and `render` draws
+//! them into the window with one indirect draw. The CPU never learns how many particles are alive.
+//! Holding space stops the emitter, to watch the fountain die down.
+
+use {Device, BlitCommandEncoder, CommandBuffer, ComputeCommandEncoder, ComputePipelineDescriptor, RenderCommandEncoder};
+use samples::{Frame, Key, Sample};
+use samples::camera::Camera;
+use util::particles::{Emitter, ParticleSystem, ParticleSystemCreationError, ParticleView, SimulateParams};
+
+/// The most particles alive at once.
+const CAPACITY: uint = 65536;
+
+/// The number of particles emitted per second.
+const RATE: f32 = 8000.0;
+
+pub struct Particles<D: Device> {
+    system: ParticleSystem<D>,
+    pub camera: Camera,
+    /// Whether the particles have been cleared, which the first frame does.
+    cleared: bool,
+    /// The particles due to be emitted, with the fraction left over from the last frame.
+    due: f32,
+    frame_index: u32,
+    view: ParticleView,
+}
+
+/// Why a `Particles` sample could not be created.
+pub enum ParticlesCreationError<D: Device> {
+    ComputePipeline(D::ComputePipelineCreationError),
+    System(ParticleSystemCreationError<D>),
+}
+
+fn kernel<D: Device>(device: &mut D, program: &D::ShaderProgram, function: &str)
+                     -> Result<D::ComputePipeline, ParticlesCreationError<D>> {
+    match device.create_compute_pipeline(program, ComputePipelineDescriptor {
+        label: Some(function.to_string()),
+        robustness: None,
+        required_subgroup_size: None,
+        cooperative_matrices: Vec::new(),
+        compute_function: function.to_string(),
+        threadgroup_memory_lengths: Vec::new(),
+    }) {
+        Ok(pipeline) => Ok(pipeline),
+        Err(e) => Err(ParticlesCreationError::ComputePipeline(e)),
+    }
+}
+
+impl<D: Device> Particles<D> {
+    /// Create the sample.
+    ///
+    /// `program` must be a shader program created from `util::particles::SOURCE`.
+    pub fn new(device: &mut D, program: &D::ShaderProgram) -> Result<Particles<D>, ParticlesCreationError<D>> {
+        let simulate = try!(kernel(device, program, "particle_simulate"));
+        let emit = try!(kernel(device, program, "particle_emit"));
+        let system = match ParticleSystem::new(device, program, simulate, emit, CAPACITY) {
+            Ok(system) => system,
+            Err(e) => return Err(ParticlesCreationError::System(e)),
+        };
+        Ok(Particles {
+            system: system,
+            camera: Camera::new([0.0, 3.0, 12.0]),
+            cleared: false,
+            due: 0.0,
+            frame_index: 0,
+            view: ParticleView { view_projection: [0.0, ..16], right: [1.0, 0.0, 0.0], up: [0.0, 1.0, 0.0] },
+        })
+    }
+}
+
+impl<D: Device> Sample<D> for Particles<D> {
+    fn name(&self) -> &'static str {
+        "particles"
+    }
+
+    fn update(&mut self, _: &mut D, frame: &Frame) {
+        self.camera.update(frame.input, frame.delta_time);
+        let (width, height) = frame.size;
+        // the camera's right is level with the horizon, so it is its forward turned around `y`
+        let f = self.camera.forward();
+        let length = (f[0] * f[0] + f[2] * f[2]).sqrt();
+        let right = [-f[2] / length, 0.0, f[0] / length];
+        let up = [right[1] * f[2] - right[2] * f[1], right[2] * f[0] - right[0] * f[2],
+                  right[0] * f[1] - right[1] * f[0]];
+        self.view = ParticleView {
+            view_projection: self.camera.view_projection(width as f32 / height as f32),
+            right: right,
+            up: up,
+        };
+        if !frame.input.is_pressed(Key::Space) {
+            self.due += RATE * frame.delta_time;
+        }
+    }
+
+    fn encode_passes(&mut self, commands: &mut D::CommandBuffer, frame: &Frame) {
+        let mut blit = commands.blit_command_encoder();
+        if !self.cleared {
+            self.system.clear(&mut blit);
+            self.cleared = true;
+        }
+        self.system.begin_frame(&mut blit);
+        blit.end_encoding();
+
+        let count = self.due as uint;
+        self.due -= count as f32;
+        self.frame_index += 1;
+        let mut compute = commands.compute_command_encoder();
+        self.system.simulate(&mut compute, &SimulateParams { dt: frame.delta_time, gravity: [0.0, -9.8, 0.0], drag: 0.2 });
+        self.system.emit(&mut compute, &Emitter {
+            count: count,
+            position: [0.0, 0.0, 0.0],
+            radius: 0.1,
+            velocity: [0.0, 8.0, 0.0],
+            velocity_spread: 2.0,
+            lifetime: (1.5, 2.5),
+            color: [0.3, 0.6, 1.0, 1.0],
+            size: [0.08, 0.02],
+        }, self.frame_index);
+        compute.end_encoding();
+    }
+
+    fn render<E: RenderCommandEncoder<D>>(&mut self, encoder: &mut E, _: &Frame) {
+        self.system.draw(encoder, &self.view);
+    }
+}
@@ -0,0 +1,267 @@
+//! Cubes standing on a floor, lit by a directional light with cascaded shadow maps.
+//!
+//! `encode_passes` draws the whole scene into every cascade of a `util::shadows::CascadedShadows`
+//! with a depth only pipeline. `render` draws it again into the window, and the fragment function
+//! darkens whatever the light does not reach with `shadow_factor`. The floor is an instance of the
+//! cube mesh like the others, only flattened.
+
+use {Device, BufferSlice, IndexFormat, PrimitiveType, RenderCommandEncoder, RenderPipelineDescriptor};
+use {CompareFunction, DepthStencilStateDescriptor};
+use samples::{Frame, Sample};
+use samples::assets::{cube, MeshVertex};
+use samples::camera::{look_to, Camera, Matrix};
+use util::{as_bytes, slice_as_bytes};
+use util::shadows::{CascadedShadows, ShadowCamera};
+
+/// The direction the light shines in.
+const LIGHT_DIRECTION: [f32, ..3] = [0.4, -1.0, 0.3];
+
+/// The resolution of every cascade.
+const SHADOW_RESOLUTION: uint = 2048;
+
+#[repr(C)]
+struct Instance {
+    model: Matrix,
+    color: [f32, ..4],
+}
+
+#[repr(C)]
+struct Uniforms {
+    view_projection: Matrix,
+    view: Matrix,
+    light_direction: [f32, ..4],
+}
+
+/// The floor, then a row of cubes of growing height.
+fn instances() -> Vec<Instance> {
+    let mut instances = vec![Instance {
+        model: [30.0, 0.0, 0.0, 0.0,
+                0.0, 0.2, 0.0, 0.0,
+                0.0, 0.0, 30.0, 0.0,
+                0.0, -0.1, 0.0, 1.0],
+        color: [0.7, 0.7, 0.7, 1.0],
+    }];
+    for i in range(0u, 8) {
+        let height = 1.0 + i as f32 * 0.5;
+        let angle = i as f32 * 0.8;
+        instances.push(Instance {
+            model: [1.0, 0.0, 0.0, 0.0,
+                    0.0, height, 0.0, 0.0,
+                    0.0, 0.0, 1.0, 0.0,
+                    angle.cos() * (3.0 + i as f32), height * 0.5, angle.sin() * (3.0 + i as f32), 1.0],
+            color: [0.9, 0.4 + i as f32 * 0.07, 0.3, 1.0],
+        });
+    }
+    instances
+}
+
+pub struct Shadows<D: Device> {
+    caster_pipeline: D::RenderPipeline,
+    lit_pipeline: D::RenderPipeline,
+    depth_state: D::DepthStencilState,
+    vertices: D::Buffer,
+    indices: D::Buffer,
+    index_count: uint,
+    instances: D::Buffer,
+    instance_count: uint,
+    shadows: CascadedShadows<D>,
+    pub camera: Camera,
+    uniforms: Uniforms,
+}
+
+/// Why a `Shadows` sample could not be created.
+pub enum ShadowsCreationError<D: Device> {
+    Pipeline(D::RenderPipelineCreationError),
+    Buffer(D::BufferCreationError),
+    Texture(D::TextureCreationError),
+}
+
+fn pipeline<D: Device>(device: &mut D, program: &D::ShaderProgram, label: &str, vertex: &str,
+                       fragment: Option<&str>) -> Result<D::RenderPipeline, ShadowsCreationError<D>> {
+    match device.create_render_pipeline(program, RenderPipelineDescriptor {
+        label: Some(label.to_string()),
+        robustness: None,
+        vertex_function: vertex.to_string(),
+        fragment_function: fragment.map(|f| f.to_string()),
+        vertex_descriptor: None,
+        blending: Vec::new(),
+        sample_count: 1,
+        attachment_formats: None,
+    }) {
+        Ok(pipeline) => Ok(pipeline),
+        Err(e) => Err(ShadowsCreationError::Pipeline(e)),
+    }
+}
+
+fn buffer<D: Device>(device: &mut D, data: &[u8]) -> Result<D::Buffer, ShadowsCreationError<D>> {
+    match device.create_buffer(data.len(), None) {
+        Ok(buffer) => {
+            device.write_buffer(&buffer, 0, data);
+            Ok(buffer)
+        }
+        Err(e) => Err(ShadowsCreationError::Buffer(e)),
+    }
+}
+
+impl<D: Device> Shadows<D> {
+    /// Create the sample, with `cascade_count` shadow map cascades.
+    ///
+    /// `program` must be a shader program created from `util::shadows::SHADER_HEADER` followed by
+    /// `SOURCE`.
+    pub fn new(device: &mut D, program: &D::ShaderProgram, cascade_count: uint) -> Result<Shadows<D>, ShadowsCreationError<D>> {
+        let mesh = cube();
+        let vertices = try!(buffer(device, slice_as_bytes(mesh.vertices.as_slice())));
+        let indices = try!(buffer(device, slice_as_bytes(mesh.indices.as_slice())));
+        let scene = instances();
+        let instances = try!(buffer(device, slice_as_bytes(scene.as_slice())));
+
+        let caster_pipeline = try!(pipeline(device, program, "shadow casters", "caster_vertex", None));
+        let lit_pipeline = try!(pipeline(device, program, "lit scene", "lit_vertex", Some("lit_fragment")));
+        let depth_state = device.create_depth_stencil_state(DepthStencilStateDescriptor {
+            depth_compare_function: CompareFunction::Less,
+            depth_write_enabled: true,
+            front_face_stencil: None,
+            back_face_stencil: None,
+        });
+        let shadows = match CascadedShadows::new(device, SHADOW_RESOLUTION, cascade_count, 0.7) {
+            Ok(shadows) => shadows,
+            Err(e) => return Err(ShadowsCreationError::Texture(e)),
+        };
+
+        Ok(Shadows {
+            caster_pipeline: caster_pipeline,
+            lit_pipeline: lit_pipeline,
+            depth_state: depth_state,
+            vertices: vertices,
+            indices: indices,
+            index_count: mesh.indices.len(),
+            instances: instances,
+            instance_count: scene.len(),
+            shadows: shadows,
+            camera: Camera::new([0.0, 4.0, 16.0]),
+            uniforms: Uniforms { view_projection: [0.0, ..16], view: [0.0, ..16], light_direction: [0.0, ..4] },
+        })
+    }
+
+    fn draw_scene<E: RenderCommandEncoder<D>>(&self, encoder: &mut E, view_projection: &Matrix) {
+        encoder.set_vertex_bytes(as_bytes(view_projection), 0);
+        encoder.set_vertex_buffer(BufferSlice::whole(&self.vertices), 1);
+        encoder.set_vertex_buffer(BufferSlice::whole(&self.instances), 2);
+        encoder.set_index_buffer(BufferSlice::whole(&self.indices), IndexFormat::UInt32);
+        encoder.draw_indexed(PrimitiveType::Triangle, 0, self.index_count, self.instance_count, 0, 0);
+    }
+}
+
+impl<D: Device> Sample<D> for Shadows<D> {
+    fn name(&self) -> &'static str {
+        "shadows"
+    }
+
+    fn update(&mut self, _: &mut D, frame: &Frame) {
+        self.camera.update(frame.input, frame.delta_time);
+        let (width, height) = frame.size;
+        let aspect = width as f32 / height as f32;
+        self.shadows.update(&ShadowCamera {
+            inverse_view: self.camera.inverse_view(),
+            fov_y: self.camera.fov_y,
+            aspect: aspect,
+            near: self.camera.near,
+            far: self.camera.far,
+        }, LIGHT_DIRECTION);
+        let d = LIGHT_DIRECTION;
+        self.uniforms = Uniforms {
+            view_projection: self.camera.view_projection(aspect),
+            // the lit pass picks each point's cascade by its distance in front of the camera
+            view: look_to(self.camera.position, self.camera.forward()),
+            light_direction: [d[0], d[1], d[2], 0.0],
+        };
+    }
+
+    fn encode_passes(&mut self, commands: &mut D::CommandBuffer, _: &Frame) {
+        for cascade in range(0, self.shadows.cascade_count()) {
+            self.shadows.encode_pass(commands, cascade, |encoder, view_projection| {
+                encoder.set_render_pipeline(&self.caster_pipeline);
+                encoder.set_depth_stencil_state(&self.depth_state);
+                self.draw_scene(encoder, view_projection);
+            });
+        }
+    }
+
+    fn render<E: RenderCommandEncoder<D>>(&mut self, encoder: &mut E, _: &Frame) {
+        encoder.set_render_pipeline(&self.lit_pipeline);
+        encoder.set_depth_stencil_state(&self.depth_state);
+        encoder.set_vertex_bytes(as_bytes(&self.uniforms), 3);
+        encoder.set_fragment_bytes(as_bytes(&self.uniforms), 0);
+        self.shadows.bind(encoder, self.shadows.texture(), 0, 1);
+        self.draw_scene(encoder, &self.uniforms.view_projection);
+    }
+}
+
+/// The metal shading language source of the shadows sample, to follow
+/// `util::shadows::SHADER_HEADER`.
+pub const SOURCE: &'static str = r#"
+struct MeshVertex {
+    packed_float3 position;
+    packed_float3 normal;
+    float2 uv;
+};
+
+struct Instance {
+    float4x4 model;
+    float4 color;
+};
+
+struct Uniforms {
+    float4x4 view_projection;
+    float4x4 view;
+    float4 light_direction;
+};
+
+vertex float4 caster_vertex(constant float4x4 &view_projection [[buffer(0)]],
+                            const device MeshVertex *vertices [[buffer(1)]],
+                            const device Instance *instances [[buffer(2)]],
+                            uint vid [[vertex_id]],
+                            uint iid [[instance_id]])
+{
+    return view_projection * instances[iid].model * float4(float3(vertices[vid].position), 1.0f);
+}
+
+struct LitFragment {
+    float4 position [[position]];
+    float3 world_position;
+    float3 normal;
+    float view_depth;
+    float4 color [[flat]];
+};
+
+vertex LitFragment lit_vertex(constant float4x4 &view_projection [[buffer(0)]],
+                              const device MeshVertex *vertices [[buffer(1)]],
+                              const device Instance *instances [[buffer(2)]],
+                              constant Uniforms &u [[buffer(3)]],
+                              uint vid [[vertex_id]],
+                              uint iid [[instance_id]])
+{
+    float4x4 model = instances[iid].model;
+    float4 world = model * float4(float3(vertices[vid].position), 1.0f);
+    LitFragment out;
+    out.position = view_projection * world;
+    out.world_position = world.xyz;
+    // the models only scale along their axes, so this is only off in length
+    out.normal = (model * float4(float3(vertices[vid].normal), 0.0f)).xyz;
+    out.view_depth = -(u.view * world).z;
+    out.color = instances[iid].color;
+    return out;
+}
+
+fragment float4 lit_fragment(LitFragment in [[stage_in]],
+                             constant Uniforms &u [[buffer(0)]],
+                             constant ShadowUniforms &shadows [[buffer(1)]],
+                             depth2d_array<float> maps [[texture(0)]])
+{
+    float3 normal = normalize(in.normal);
+    float3 to_light = -normalize(u.light_direction.xyz);
+    float diffuse = saturate(dot(normal, to_light));
+    float lit = diffuse > 0.0f ? shadow_factor(maps, shadows, in.world_position, in.view_depth, 0.002f) : 0.0f;
+    return float4(in.color.rgb * (0.25f + 0.75f * diffuse * lit), 1.0f);
+}
+"#;
@@ -0,0 +1,207 @@
+//! A textured, spinning cube, looked at through a `Camera`.
+//!
+//! This covers the usual path of putting a mesh on screen: vertex and index buffers, a vertex
+//! descriptor, a texture uploaded from an image, a bind group holding it with a sampler, and a
+//! per-frame transform.
+
+use {Device, BufferSlice, RenderCommandEncoder, RenderPipelineDescriptor, PrimitiveType, IndexFormat, Origin, Size};
+use {BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry};
+use {BindingCount, BindingResource, BindingType, ShaderStages, SamplerDescriptor};
+use {PixelFormat, TextureDescriptor, TextureType, TextureUsage};
+use {VertexAttribute, VertexBufferLayout, VertexDescriptor, VertexFormat, VertexStepFunction};
+use golden::Image;
+use samples::{Frame, Sample};
+use samples::assets::{cube, MeshVertex};
+use samples::camera::{Camera, Matrix, multiply, rotation_y};
+use util::{as_bytes, slice_as_bytes};
+
+pub struct TexturedCube<D: Device> {
+    pipeline: D::RenderPipeline,
+    vertices: D::Buffer,
+    indices: D::Buffer,
+    index_count: uint,
+    bind_group: D::BindGroup,
+    // kept alive for the bind group
+    _texture: D::Texture,
+    _sampler: D::Sampler,
+    pub camera: Camera,
+    angle: f32,
+    transform: Matrix,
+}
+
+/// Why a `TexturedCube` could not be created.
+pub enum TexturedCubeCreationError<D: Device> {
+    Pipeline(D::RenderPipelineCreationError),
+    Buffer(D::BufferCreationError),
+    Texture(D::TextureCreationError),
+    Sampler(D::SamplerCreationError),
+    BindGroupLayout(D::BindGroupLayoutCreationError),
+    BindGroup(D::BindGroupCreationError),
+}
+
+impl<D: Device> TexturedCube<D> {
+    /// Create the sample, with `image` on every face.
+    ///
+    /// `program` must be a shader program created from `SOURCE`.
+    pub fn new(device: &mut D, program: &D::ShaderProgram, image: &Image) -> Result<TexturedCube<D>, TexturedCubeCreationError<D>> {
+        let mesh = cube();
+        let vertices = match device.create_buffer(mesh.vertices.len() * std::mem::size_of::<MeshVertex>(), None) {
+            Ok(buffer) => buffer,
+            Err(e) => return Err(TexturedCubeCreationError::Buffer(e)),
+        };
+        device.write_buffer(&vertices, 0, slice_as_bytes(mesh.vertices.as_slice()));
+        let indices = match device.create_buffer(mesh.indices.len() * 4, None) {
+            Ok(buffer) => buffer,
+            Err(e) => return Err(TexturedCubeCreationError::Buffer(e)),
+        };
+        device.write_buffer(&indices, 0, slice_as_bytes(mesh.indices.as_slice()));
+
+        let texture = match device.create_texture(TextureDescriptor {
+            texture_type: TextureType::Type2D,
+            pixel_format: PixelFormat::RGBA8UnormSrgb,
+            width: image.width,
+            height: image.height,
+            depth: 1,
+            mipmap_level_count: 1,
+            array_length: 1,
+            sample_count: 1,
+            usage: TextureUsage { shader_read: true, shader_write: false, render_target: false },
+        }) {
+            Ok(texture) => texture,
+            Err(e) => return Err(TexturedCubeCreationError::Texture(e)),
+        };
+        device.write_texture(&texture, 0, 0, Origin { x: 0, y: 0, z: 0 },
+                             Size { width: image.width, height: image.height, depth: 1 },
+                             image.pixels.as_slice(), image.width * 4);
+        let sampler = match device.create_sampler(SamplerDescriptor) {
+            Ok(sampler) => sampler,
+            Err(e) => return Err(TexturedCubeCreationError::Sampler(e)),
+        };
+
+        let fragment = ShaderStages { vertex: false, fragment: true, compute: false };
+        let layout = match device.create_bind_group_layout(BindGroupLayoutDescriptor {
+            entries: vec![
+                BindGroupLayoutEntry { binding: 0, visibility: fragment, ty: BindingType::SampledTexture,
+                                       count: BindingCount::Single, partially_bound: false, update_after_bind: false },
+                BindGroupLayoutEntry { binding: 1, visibility: fragment, ty: BindingType::Sampler,
+                                       count: BindingCount::Single, partially_bound: false, update_after_bind: false },
+            ],
+        }) {
+            Ok(layout) => layout,
+            Err(e) => return Err(TexturedCubeCreationError::BindGroupLayout(e)),
+        };
+        let bind_group = match device.create_bind_group(BindGroupDescriptor {
+            layout: &layout,
+            entries: vec![
+                BindGroupEntry { binding: 0, array_element: 0, resource: BindingResource::Texture(&texture) },
+                BindGroupEntry { binding: 1, array_element: 0, resource: BindingResource::Sampler(&sampler) },
+            ],
+            unsized_length: 0,
+        }) {
+            Ok(group) => group,
+            Err(e) => return Err(TexturedCubeCreationError::BindGroup(e)),
+        };
+
+        let pipeline = match device.create_render_pipeline(program, RenderPipelineDescriptor {
+            label: Some("textured cube".to_string()),
+            robustness: None,
+            vertex_function: "cube_vertex".to_string(),
+            fragment_function: Some("cube_fragment".to_string()),
+            vertex_descriptor: Some(VertexDescriptor {
+                attributes: vec![
+                    VertexAttribute { format: VertexFormat::Float3, offset: 0, buffer_index: 1 },
+                    VertexAttribute { format: VertexFormat::Float3, offset: 12, buffer_index: 1 },
+                    VertexAttribute { format: VertexFormat::Float2, offset: 24, buffer_index: 1 },
+                ],
+                layouts: vec![
+                    VertexBufferLayout { stride: std::mem::size_of::<MeshVertex>(),
+                                         step_function: VertexStepFunction::PerVertex, step_rate: 1 },
+                ],
+            }),
+            blending: Vec::new(),
+            sample_count: 1,
+            attachment_formats: None,
+        }) {
+            Ok(pipeline) => pipeline,
+            Err(e) => return Err(TexturedCubeCreationError::Pipeline(e)),
+        };
+
+        Ok(TexturedCube {
+            pipeline: pipeline,
+            vertices: vertices,
+            indices: indices,
+            index_count: mesh.indices.len(),
+            bind_group: bind_group,
+            _texture: texture,
+            _sampler: sampler,
+            camera: Camera::new([0.0, 0.5, 2.5]),
+            angle: 0.0,
+            transform: [0.0, ..16],
+        })
+    }
+}
+
+impl<D: Device> Sample<D> for TexturedCube<D> {
+    fn name(&self) -> &'static str {
+        "textured cube"
+    }
+
+    fn update(&mut self, _: &mut D, frame: &Frame) {
+        self.camera.update(frame.input, frame.delta_time);
+        self.angle += frame.delta_time * 0.5;
+        let (width, height) = frame.size;
+        self.transform = multiply(&self.camera.view_projection(width as f32 / height as f32), &rotation_y(self.angle));
+    }
+
+    fn render<E: RenderCommandEncoder<D>>(&mut self, encoder: &mut E, _: &Frame) {
+        encoder.set_render_pipeline(&self.pipeline);
+        encoder.set_vertex_bytes(as_bytes(&self.transform), 0);
+        encoder.set_vertex_buffer(BufferSlice::whole(&self.vertices), 1);
+        encoder.set_bind_group(&self.bind_group, 0);
+        encoder.set_index_buffer(BufferSlice::whole(&self.indices), IndexFormat::UInt32);
+        encoder.draw_indexed(PrimitiveType::Triangle, 0, self.index_count, 1, 0, 0);
+    }
+}
+
+/// The metal shading language source of the textured cube sample.
+///
+/// The pass has no depth attachment, so the fragment function discards back faces instead, which
+/// is enough for a convex mesh.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct MeshVertex {
+    float3 position [[attribute(0)]];
+    float3 normal [[attribute(1)]];
+    float2 uv [[attribute(2)]];
+};
+
+struct CubeFragment {
+    float4 position [[position]];
+    float3 normal;
+    float2 uv;
+};
+
+vertex CubeFragment cube_vertex(MeshVertex in [[stage_in]],
+                                constant float4x4 &transform [[buffer(0)]])
+{
+    CubeFragment out;
+    out.position = transform * float4(in.position, 1.0f);
+    out.normal = in.normal;
+    out.uv = in.uv;
+    return out;
+}
+
+fragment float4 cube_fragment(CubeFragment in [[stage_in]],
+                              bool front_facing [[front_facing]],
+                              texture2d<float> texture [[texture(0)]],
+                              sampler s [[sampler(0)]])
+{
+    if (!front_facing) {
+        discard_fragment();
+    }
+    float light = 0.4f + 0.6f * saturate(dot(normalize(in.normal), normalize(float3(0.3f, 1.0f, 0.5f))));
+    return float4(texture.sample(s, in.uv).rgb * light, 1.0f);
+}
+"#;
@@ -0,0 +1,74 @@
+//! The classic first sample: a single spinning, colored triangle.
+//!
+//! There are no buffers at all; the vertex function computes the corners from the vertex index,
+//! and the angle comes in as vertex bytes.
+
+use {Device, RenderCommandEncoder, RenderPipelineDescriptor, PrimitiveType};
+use samples::{Frame, Sample};
+use util::as_bytes;
+
+pub struct Triangle<D: Device> {
+    pipeline: D::RenderPipeline,
+    angle: f32,
+}
+
+impl<D: Device> Triangle<D> {
+    /// `program` must be a shader program created from `SOURCE`.
+    pub fn new(device: &mut D, program: &D::ShaderProgram) -> Result<Triangle<D>, D::RenderPipelineCreationError> {
+        let pipeline = try!(device.create_render_pipeline(program, RenderPipelineDescriptor {
+            label: Some("triangle".to_string()),
+            robustness: None,
+            vertex_function: "triangle_vertex".to_string(),
+            fragment_function: Some("triangle_fragment".to_string()),
+            vertex_descriptor: None,
+            blending: Vec::new(),
+            sample_count: 1,
+            attachment_formats: None,
+        }));
+        Ok(Triangle { pipeline: pipeline, angle: 0.0 })
+    }
+}
+
+impl<D: Device> Sample<D> for Triangle<D> {
+    fn name(&self) -> &'static str {
+        "triangle"
+    }
+
+    fn update(&mut self, _: &mut D, frame: &Frame) {
+        self.angle += frame.delta_time;
+    }
+
+    fn render<E: RenderCommandEncoder<D>>(&mut self, encoder: &mut E, frame: &Frame) {
+        let (width, height) = frame.size;
+        let uniforms = [self.angle, height as f32 / width as f32];
+        encoder.set_render_pipeline(&self.pipeline);
+        encoder.set_vertex_bytes(as_bytes(&uniforms), 0);
+        encoder.draw(PrimitiveType::Triangle, 0, 3, 1, 0);
+    }
+}
+
+/// The metal shading language source of the triangle sample.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct TriangleFragment {
+    float4 position [[position]];
+    float4 color;
+};
+
+vertex TriangleFragment triangle_vertex(constant float2 &uniforms [[buffer(0)]],
+                                        uint vid [[vertex_id]])
+{
+    float angle = uniforms.x + float(vid) * (2.0f * M_PI_F / 3.0f);
+    TriangleFragment out;
+    out.position = float4(sin(angle) * 0.7f * uniforms.y, cos(angle) * 0.7f, 0.0f, 1.0f);
+    out.color = float4(vid == 0, vid == 1, vid == 2, 1.0f);
+    return out;
+}
+
+fragment float4 triangle_fragment(TriangleFragment in [[stage_in]])
+{
+    return in.color;
+}
+"#;
@@ -0,0 +1,417 @@
+//! A grid of spinning cubes, drawn through a visibility buffer.
+//!
+//! The offscreen pass rasterizes every cube into an `R32Uint` ID attachment, writing which
+//! instance and which triangle covers each pixel and nothing else. The window's pass then runs
+//! one fullscreen triangle, whose fragment function looks the triangle back up in the vertex and
+//! index buffers, recomputes its barycentric coordinates at the pixel, and shades it. Attributes
+//! are only ever fetched and interpolated for the visible surface.
+//!
+//! The IDs can be written in two ways, picked with `IdWrite`:
+//!
+//! * `RenderTarget` writes them as the color output of the fragment function, with a normal
+//!   depth test.
+//! * `Storage` writes them to a storage texture from the fragment function instead. Storage
+//!   writes are not ordered between overlapping fragments, so this needs a depth prepass first,
+//!   and a depth `Equal` test to leave exactly one fragment per pixel.
+
+use {Device, BufferSlice, CommandBuffer, RenderCommandEncoder, RenderPipelineDescriptor, PrimitiveType, IndexFormat};
+use {ClearColor, LoadAction, RenderPassColorAttachment, RenderPassDepthAttachment, RenderPassDescriptor, StoreAction};
+use {CompareFunction, DepthStencilStateDescriptor, DeviceFeatures};
+use {PixelFormat, TextureDescriptor, TextureType, TextureUsage};
+use samples::{Frame, Sample};
+use samples::assets::{cube, MeshVertex};
+use samples::camera::{Camera, Matrix};
+use util::{as_bytes, slice_as_bytes};
+
+/// The cubes are drawn in a `GRID` by `GRID` square.
+const GRID: uint = 16;
+
+/// How the visibility pass writes IDs. See the module documentation.
+#[deriving(PartialEq)]
+pub enum IdWrite {
+    RenderTarget,
+    Storage,
+}
+
+#[repr(C)]
+struct Uniforms {
+    view_projection: Matrix,
+    angle: f32,
+    grid: u32,
+    viewport_size: [f32, ..2],
+}
+
+pub struct VisibilityBuffer<D: Device> {
+    mode: IdWrite,
+    /// The prepass, in `Storage` mode.
+    depth_pipeline: Option<D::RenderPipeline>,
+    id_pipeline: D::RenderPipeline,
+    shade_pipeline: D::RenderPipeline,
+    depth_write: D::DepthStencilState,
+    depth_equal: D::DepthStencilState,
+    vertices: D::Buffer,
+    indices: D::Buffer,
+    index_count: uint,
+    /// The ID and depth attachments, and their size.
+    targets: Option<(D::Texture, D::Texture, (uint, uint))>,
+    pub camera: Camera,
+    angle: f32,
+    uniforms: Uniforms,
+}
+
+/// Why a `VisibilityBuffer` could not be created.
+pub enum VisibilityBufferCreationError<D: Device> {
+    /// The device lacks features the mode needs.
+    MissingFeatures(Vec<&'static str>),
+    Pipeline(D::RenderPipelineCreationError),
+    Buffer(D::BufferCreationError),
+    Texture(D::TextureCreationError),
+}
+
+fn pipeline<D: Device>(device: &mut D, program: &D::ShaderProgram, label: &str, vertex: &str,
+                       fragment: Option<&str>) -> Result<D::RenderPipeline, VisibilityBufferCreationError<D>> {
+    match device.create_render_pipeline(program, RenderPipelineDescriptor {
+        label: Some(label.to_string()),
+        robustness: None,
+        vertex_function: vertex.to_string(),
+        fragment_function: fragment.map(|f| f.to_string()),
+        vertex_descriptor: None,
+        blending: Vec::new(),
+        sample_count: 1,
+        attachment_formats: None,
+    }) {
+        Ok(pipeline) => Ok(pipeline),
+        Err(e) => Err(VisibilityBufferCreationError::Pipeline(e)),
+    }
+}
+
+impl<D: Device> VisibilityBuffer<D> {
+    /// Create the sample, with attachments of `size` pixels.
+    ///
+    /// `program` must be a shader program created from `SOURCE`. The device must support the
+    /// `primitive_id` feature, and for `IdWrite::Storage`, `fragment_storage_writes` too.
+    pub fn new(device: &mut D, program: &D::ShaderProgram, mode: IdWrite, size: (uint, uint)) -> Result<VisibilityBuffer<D>, VisibilityBufferCreationError<D>> {
+        let required = DeviceFeatures {
+            primitive_id: true,
+            fragment_storage_writes: mode == IdWrite::Storage,
+            .. Default::default()
+        };
+        let missing = device.features().missing(&required);
+        if !missing.is_empty() {
+            return Err(VisibilityBufferCreationError::MissingFeatures(missing));
+        }
+
+        let mesh = cube();
+        let vertices = match device.create_buffer(mesh.vertices.len() * std::mem::size_of::<MeshVertex>(), None) {
+            Ok(buffer) => buffer,
+            Err(e) => return Err(VisibilityBufferCreationError::Buffer(e)),
+        };
+        device.write_buffer(&vertices, 0, slice_as_bytes(mesh.vertices.as_slice()));
+        let indices = match device.create_buffer(mesh.indices.len() * 4, None) {
+            Ok(buffer) => buffer,
+            Err(e) => return Err(VisibilityBufferCreationError::Buffer(e)),
+        };
+        device.write_buffer(&indices, 0, slice_as_bytes(mesh.indices.as_slice()));
+
+        let (depth_pipeline, id_pipeline) = match mode {
+            IdWrite::RenderTarget => (None, try!(pipeline(device, program, "visibility ids", "visibility_vertex",
+                                                          Some("visibility_fragment")))),
+            IdWrite::Storage => (Some(try!(pipeline(device, program, "visibility depth", "visibility_vertex", None))),
+                                 try!(pipeline(device, program, "visibility ids", "visibility_vertex",
+                                               Some("visibility_store_fragment")))),
+        };
+        let shade_pipeline = try!(pipeline(device, program, "visibility shading", "shade_vertex", Some("shade_fragment")));
+        let depth_write = device.create_depth_stencil_state(DepthStencilStateDescriptor {
+            depth_compare_function: CompareFunction::Less,
+            depth_write_enabled: true,
+            front_face_stencil: None,
+            back_face_stencil: None,
+        });
+        let depth_equal = device.create_depth_stencil_state(DepthStencilStateDescriptor {
+            depth_compare_function: CompareFunction::Equal,
+            depth_write_enabled: false,
+            front_face_stencil: None,
+            back_face_stencil: None,
+        });
+
+        let mut sample = VisibilityBuffer {
+            mode: mode,
+            depth_pipeline: depth_pipeline,
+            id_pipeline: id_pipeline,
+            shade_pipeline: shade_pipeline,
+            depth_write: depth_write,
+            depth_equal: depth_equal,
+            vertices: vertices,
+            indices: indices,
+            index_count: mesh.indices.len(),
+            targets: None,
+            camera: Camera::new([0.0, 6.0, 14.0]),
+            angle: 0.0,
+            uniforms: Uniforms { view_projection: [0.0, ..16], angle: 0.0, grid: GRID as u32, viewport_size: [0.0, 0.0] },
+        };
+        match sample.resize(device, size) {
+            Ok(()) => Ok(sample),
+            Err(e) => Err(VisibilityBufferCreationError::Texture(e)),
+        }
+    }
+
+    /// Recreate the ID and depth attachments at `size` pixels.
+    pub fn resize(&mut self, device: &mut D, size: (uint, uint)) -> Result<(), D::TextureCreationError> {
+        let (width, height) = size;
+        let ids = try!(device.create_texture(TextureDescriptor {
+            texture_type: TextureType::Type2D,
+            pixel_format: PixelFormat::R32Uint,
+            width: width,
+            height: height,
+            depth: 1,
+            mipmap_level_count: 1,
+            array_length: 1,
+            sample_count: 1,
+            // a render target in both modes, as that is how it is cleared
+            usage: TextureUsage { shader_read: true, shader_write: self.mode == IdWrite::Storage, render_target: true },
+        }));
+        let depth = try!(device.create_texture(TextureDescriptor {
+            texture_type: TextureType::Type2D,
+            pixel_format: PixelFormat::Depth32Float,
+            width: width,
+            height: height,
+            depth: 1,
+            mipmap_level_count: 1,
+            array_length: 1,
+            sample_count: 1,
+            usage: TextureUsage { shader_read: false, shader_write: false, render_target: true },
+        }));
+        self.targets = Some((ids, depth, size));
+        Ok(())
+    }
+
+    fn draw_cubes<E: RenderCommandEncoder<D>>(&self, encoder: &mut E) {
+        encoder.set_vertex_bytes(as_bytes(&self.uniforms), 0);
+        encoder.set_vertex_buffer(BufferSlice::whole(&self.vertices), 1);
+        encoder.set_index_buffer(BufferSlice::whole(&self.indices), IndexFormat::UInt32);
+        encoder.draw_indexed(PrimitiveType::Triangle, 0, self.index_count, GRID * GRID, 0, 0);
+    }
+}
+
+impl<D: Device> Sample<D> for VisibilityBuffer<D> {
+    fn name(&self) -> &'static str {
+        "visibility buffer"
+    }
+
+    fn update(&mut self, device: &mut D, frame: &Frame) {
+        if self.targets.as_ref().map_or(true, |&(_, _, size)| size != frame.size) {
+            // if this fails, the old attachments are kept, and the shading pass only shades the
+            // part of the window they cover
+            let _ = self.resize(device, frame.size);
+        }
+        self.camera.update(frame.input, frame.delta_time);
+        self.angle += frame.delta_time * 0.5;
+        let (width, height) = frame.size;
+        self.uniforms = Uniforms {
+            view_projection: self.camera.view_projection(width as f32 / height as f32),
+            angle: self.angle,
+            grid: GRID as u32,
+            viewport_size: [width as f32, height as f32],
+        };
+    }
+
+    fn encode_passes(&mut self, commands: &mut D::CommandBuffer, _: &Frame) {
+        let (ids, depth, _) = match self.targets {
+            Some((ref ids, ref depth, size)) => (ids, depth, size),
+            None => return,
+        };
+
+        if let Some(ref depth_pipeline) = self.depth_pipeline {
+            let mut encoder = commands.render_command_encoder(RenderPassDescriptor {
+                color_attachments: Vec::new(),
+                depth_attachment: Some(RenderPassDepthAttachment {
+                    texture: depth, mipmap_level: 0, slice: 0,
+                    load_action: LoadAction::Clear, store_action: StoreAction::Store, clear_depth: 1.0,
+                }),
+                stencil_attachment: None,
+                sample_positions: None,
+                visibility_result_buffer: None,
+            });
+            encoder.set_render_pipeline(depth_pipeline);
+            encoder.set_depth_stencil_state(&self.depth_write);
+            self.draw_cubes(&mut encoder);
+            encoder.end_encoding();
+        }
+
+        // a storage write leaves the texture as it is where nothing is drawn, so it is cleared
+        // through an empty pass of its own
+        let clear = RenderPassColorAttachment {
+            texture: ids, mipmap_level: 0, slice: 0,
+            load_action: LoadAction::Clear, store_action: StoreAction::Store,
+            clear_color: ClearColor::Uint([0, 0, 0, 0]),
+        };
+        let mut encoder = match self.mode {
+            IdWrite::RenderTarget => commands.render_command_encoder(RenderPassDescriptor {
+                color_attachments: vec![clear],
+                depth_attachment: Some(RenderPassDepthAttachment {
+                    texture: depth, mipmap_level: 0, slice: 0,
+                    load_action: LoadAction::Clear, store_action: StoreAction::DontCare, clear_depth: 1.0,
+                }),
+                stencil_attachment: None,
+                sample_positions: None,
+                visibility_result_buffer: None,
+            }),
+            IdWrite::Storage => {
+                commands.render_command_encoder(RenderPassDescriptor {
+                    color_attachments: vec![clear],
+                    depth_attachment: None,
+                    stencil_attachment: None,
+                    sample_positions: None,
+                    visibility_result_buffer: None,
+                }).end_encoding();
+                commands.render_command_encoder(RenderPassDescriptor {
+                    color_attachments: Vec::new(),
+                    depth_attachment: Some(RenderPassDepthAttachment {
+                        texture: depth, mipmap_level: 0, slice: 0,
+                        load_action: LoadAction::Load, store_action: StoreAction::DontCare, clear_depth: 1.0,
+                    }),
+                    stencil_attachment: None,
+                    sample_positions: None,
+                    visibility_result_buffer: None,
+                })
+            }
+        };
+        encoder.set_render_pipeline(&self.id_pipeline);
+        match self.mode {
+            IdWrite::RenderTarget => encoder.set_depth_stencil_state(&self.depth_write),
+            IdWrite::Storage => {
+                encoder.set_depth_stencil_state(&self.depth_equal);
+                encoder.set_fragment_texture(ids, 0);
+            }
+        }
+        self.draw_cubes(&mut encoder);
+        encoder.end_encoding();
+    }
+
+    fn render<E: RenderCommandEncoder<D>>(&mut self, encoder: &mut E, _: &Frame) {
+        let ids = match self.targets {
+            Some((ref ids, _, _)) => ids,
+            None => return,
+        };
+        encoder.set_render_pipeline(&self.shade_pipeline);
+        encoder.set_fragment_bytes(as_bytes(&self.uniforms), 0);
+        encoder.set_fragment_buffer(BufferSlice::whole(&self.vertices), 1);
+        encoder.set_fragment_buffer(BufferSlice::whole(&self.indices), 2);
+        encoder.set_fragment_texture(ids, 0);
+        encoder.draw(PrimitiveType::Triangle, 0, 3, 1, 0);
+    }
+}
+
+/// The metal shading language source of the visibility buffer sample.
+///
+/// An ID is `(instance + 1) << 16 | triangle`, so `0` means nothing was drawn.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct MeshVertex {
+    packed_float3 position;
+    packed_float3 normal;
+    float2 uv;
+};
+
+struct Uniforms {
+    float4x4 view_projection;
+    float angle;
+    uint grid;
+    float2 viewport_size;
+};
+
+static float4x4 instance_transform(constant Uniforms &u, uint instance) {
+    float angle = u.angle + float(instance) * 0.37f;
+    float s = sin(angle), c = cos(angle);
+    float2 cell = float2(instance % u.grid, instance / u.grid) - float(u.grid - 1) * 0.5f;
+    return float4x4(float4(c, 0.0f, -s, 0.0f),
+                    float4(0.0f, 1.0f, 0.0f, 0.0f),
+                    float4(s, 0.0f, c, 0.0f),
+                    float4(cell.x * 2.0f, 0.0f, cell.y * 2.0f, 1.0f)) *
+           float4x4(float4(0.6f, 0.0f, 0.0f, 0.0f), float4(0.0f, 0.6f, 0.0f, 0.0f),
+                    float4(0.0f, 0.0f, 0.6f, 0.0f), float4(0.0f, 0.0f, 0.0f, 1.0f));
+}
+
+struct VisibilityFragment {
+    float4 position [[position]];
+    uint instance [[flat]];
+};
+
+vertex VisibilityFragment visibility_vertex(constant Uniforms &u [[buffer(0)]],
+                                            const device MeshVertex *vertices [[buffer(1)]],
+                                            uint vid [[vertex_id]],
+                                            uint iid [[instance_id]])
+{
+    VisibilityFragment out;
+    out.position = u.view_projection * instance_transform(u, iid) * float4(float3(vertices[vid].position), 1.0f);
+    out.instance = iid;
+    return out;
+}
+
+fragment uint visibility_fragment(VisibilityFragment in [[stage_in]],
+                                  uint primitive [[primitive_id]])
+{
+    return (in.instance + 1) << 16 | primitive;
+}
+
+[[early_fragment_tests]]
+fragment void visibility_store_fragment(VisibilityFragment in [[stage_in]],
+                                        uint primitive [[primitive_id]],
+                                        texture2d<uint, access::write> ids [[texture(0)]])
+{
+    ids.write((in.instance + 1) << 16 | primitive, uint2(in.position.xy));
+}
+
+vertex float4 shade_vertex(uint vid [[vertex_id]])
+{
+    float2 uv = float2((vid << 1) & 2, vid & 2);
+    return float4(uv * 2.0f - 1.0f, 0.0f, 1.0f);
+}
+
+fragment float4 shade_fragment(float4 position [[position]],
+                               constant Uniforms &u [[buffer(0)]],
+                               const device MeshVertex *vertices [[buffer(1)]],
+                               const device uint *indices [[buffer(2)]],
+                               texture2d<uint> ids [[texture(0)]])
+{
+    uint2 pixel = uint2(position.xy);
+    float4 background = float4(0.1f, 0.1f, 0.15f, 1.0f);
+    if (pixel.x >= ids.get_width() || pixel.y >= ids.get_height()) {
+        return background;
+    }
+    uint id = ids.read(pixel).r;
+    if (id == 0) {
+        return background;
+    }
+    uint instance = (id >> 16) - 1;
+    uint triangle = id & 0xffff;
+
+    float4x4 model = instance_transform(u, instance);
+    float4x4 transform = u.view_projection * model;
+    MeshVertex v[3];
+    float4 clip[3];
+    for (int i = 0; i < 3; i++) {
+        v[i] = vertices[indices[triangle * 3 + i]];
+        clip[i] = transform * float4(float3(v[i].position), 1.0f);
+    }
+
+    // perspective correct barycentrics of the pixel center within the triangle
+    float2 ndc = (position.xy / u.viewport_size) * float2(2.0f, -2.0f) + float2(-1.0f, 1.0f);
+    float2 p0 = clip[0].xy / clip[0].w, p1 = clip[1].xy / clip[1].w, p2 = clip[2].xy / clip[2].w;
+    float area = (p1.x - p0.x) * (p2.y - p0.y) - (p2.x - p0.x) * (p1.y - p0.y);
+    float b1 = ((ndc.x - p0.x) * (p2.y - p0.y) - (p2.x - p0.x) * (ndc.y - p0.y)) / area;
+    float b2 = ((p1.x - p0.x) * (ndc.y - p0.y) - (ndc.x - p0.x) * (p1.y - p0.y)) / area;
+    float3 b = float3(1.0f - b1 - b2, b1, b2) / float3(clip[0].w, clip[1].w, clip[2].w);
+    b /= b.x + b.y + b.z;
+
+    float3 normal = normalize((model * float4(float3(v[0].normal) * b.x + float3(v[1].normal) * b.y +
+                                              float3(v[2].normal) * b.z, 0.0f)).xyz);
+    float2 uv = v[0].uv * b.x + v[1].uv * b.y + v[2].uv * b.z;
+    float checker = (uint(uv.x * 4.0f) + uint(uv.y * 4.0f)) & 1 ? 1.0f : 0.6f;
+    float3 color = float3(float(instance % 3) * 0.3f + 0.4f, float(instance % 5) * 0.15f + 0.3f, 0.8f) * checker;
+    float light = 0.3f + 0.7f * saturate(dot(normal, normalize(float3(0.3f, 1.0f, 0.5f))));
+    return float4(color * light, 1.0f);
+}
+"#;
@@ -0,0 +1,544 @@
+//! A portable container for precompiled shaders.
+//!
+//! A shader archive holds the same shader program compiled for any number of backends (metal
+//! source or a metallib, SPIR-V, DXIL, ...), along with reflection information about the
+//! program's functions and hashes to detect corruption and stale builds. A shipping application
+//! builds its archives ahead of time, usually in a build script, and creates its shader programs
+//! with `Device::create_shader_program_from_archive`, so that no shader translation happens on
+//! the user's machine.
+//!
+//! The compilation itself is not done here; use the platform's compilers (`xcrun metal`, `dxc`,
+//! `glslangValidator`, ...) and hand their outputs to a `ShaderArchiveBuilder`:
+//!
+//! ```ignore
+//! // build.rs
+//! let source = File::open(&Path::new("shaders/particles.metal")).read_to_end().unwrap();
+//! let mut builder = ShaderArchiveBuilder::new(source.as_slice());
+//! builder.add_entry(ShaderBackend::MetalLib, metallib_bytes);
+//! builder.add_entry(ShaderBackend::SpirV, spirv_bytes);
+//! builder.add_function(FunctionReflection { name: "simulate".to_string(), stage: ShaderStage::Compute, bindings: bindings });
+//! File::create(&out_dir.join("particles.shar")).write(builder.build().encode().as_slice()).unwrap();
+//! ```
+//!
+//! # Format
+//!
+//! All integers are little endian. Strings and blobs are a `u32` length followed by that many
+//! bytes.
+//!
+//! ```text
+//! magic         "SHAR"
+//! version       u32 (currently 2)
+//! source_hash   u64
+//! entry_count   u32
+//! entries       entry_count * { backend: u32, hash: u64, data: blob }
+//! func_count    u32
+//! functions     func_count * { name: string, stage: u32, binding_count: u32,
+//!                              bindings: binding_count * { group: u32, binding: u32, ty: u32 } }
+//! ```
+//!
+//! Version 2 follows each entry's data with its binding remap:
+//!
+//! ```text
+//! remap_count   u32
+//! remaps        remap_count * { group: u32, binding: u32, target_count: u32,
+//!                               targets: target_count * { binding: u32, ty: u32 } }
+//! ```
+//!
+//! Hashes are 64-bit FNV-1a. Each entry's hash is of its data, and is checked when decoding.
+//! Archives of version 1 still decode, with empty remaps.
+//!
+//! # Binding remaps
+//!
+//! Translating a shader to another backend's language can move its bindings around: SPIR-V
+//! cross-compilers split the combined image samplers of GLSL into a texture and a sampler
+//! binding, and number bindings by the resources each backend has. Code which creates bind groups
+//! by hand, with the binding numbers of the source, then binds the wrong resources on every
+//! backend but the source's. A translator which reports where every moved binding went lets that
+//! code survive the translation: its `BindingRemap` goes into the archive entry with the
+//! translated code (`ShaderArchiveBuilder::add_remapped_entry`), programs created from the entry
+//! return it (`ShaderProgram::binding_remap`), and bind group layouts and bind groups written
+//! against the source are remapped on their way to the device:
+//!
+//! ```ignore
+//! let program = try!(device.create_shader_program_from_archive(&archive));
+//! let layout = try!(device.create_bind_group_layout(desc.remapped(0, program.binding_remap())));
+//! // the texture and the sampler of the source's combined `sampler2D albedo` at binding 1
+//! let group = try!(device.create_bind_group(BindGroupDescriptor {
+//!     layout: &layout,
+//!     entries: vec![
+//!         BindGroupEntry { binding: 1, array_element: 0, resource: BindingResource::Texture(&albedo) },
+//!         BindGroupEntry { binding: 1, array_element: 0, resource: BindingResource::Sampler(&sampler) },
+//!     ],
+//!     unsized_length: 0,
+//! }.remapped(0, program.binding_remap())));
+//! ```
+//!
+//! Remaps move bindings within their bind group: translators which move them between groups
+//! cannot be described.
+
+#[cfg(not(feature = "std"))]
+use core::prelude::*;
+#[cfg(not(feature = "std"))]
+use collections::{String, Vec};
+
+use {Device, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource};
+use BindingType;
+
+static MAGIC: &'static [u8] = b"SHAR";
+
+/// The current version of the format. Archives of every earlier version decode as well.
+pub const VERSION: u32 = 2;
+
+/// The kind of shader code in an archive entry.
+#[deriving(PartialEq)]
+pub enum ShaderBackend {
+    /// Metal shading language source.
+    MetalSource,
+    /// A compiled metal library.
+    MetalLib,
+    /// A SPIR-V module.
+    SpirV,
+    /// A DXIL container.
+    Dxil,
+    /// GLSL source.
+    Glsl,
+}
+
+impl ShaderBackend {
+    fn tag(&self) -> u32 {
+        match *self {
+            ShaderBackend::MetalSource => 0,
+            ShaderBackend::MetalLib => 1,
+            ShaderBackend::SpirV => 2,
+            ShaderBackend::Dxil => 3,
+            ShaderBackend::Glsl => 4,
+        }
+    }
+
+    fn from_tag(tag: u32) -> Option<ShaderBackend> {
+        match tag {
+            0 => Some(ShaderBackend::MetalSource),
+            1 => Some(ShaderBackend::MetalLib),
+            2 => Some(ShaderBackend::SpirV),
+            3 => Some(ShaderBackend::Dxil),
+            4 => Some(ShaderBackend::Glsl),
+            _ => None,
+        }
+    }
+}
+
+/// The stage a shader function runs in.
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Compute,
+}
+
+/// A single resource binding used by a shader function.
+pub struct BindingReflection {
+    /// The bind group index.
+    pub group: uint,
+    /// The binding number within the group.
+    pub binding: uint,
+    pub ty: BindingType,
+}
+
+/// What an archive records about each function of the shader program.
+pub struct FunctionReflection {
+    pub name: String,
+    pub stage: ShaderStage,
+    pub bindings: Vec<BindingReflection>,
+}
+
+/// Where a translator moved one binding of the source.
+pub struct RemappedBinding {
+    /// The bind group index, in the source and in the translated code.
+    pub group: uint,
+    /// The binding number in the source.
+    pub binding: uint,
+    /// The bindings it became in the translated code: one if it was only renumbered, a
+    /// `SampledTexture` and a `Sampler` binding for a split combined image sampler.
+    pub targets: Vec<BindingTarget>,
+}
+
+/// A binding of the translated code, standing for (part of) a binding of the source.
+#[deriving(Clone)]
+pub struct BindingTarget {
+    pub binding: uint,
+    pub ty: BindingType,
+}
+
+/// How the bindings of a translated shader differ from those of its source. Bindings the remap
+/// does not mention kept their number. See the module documentation.
+pub struct BindingRemap {
+    pub bindings: Vec<RemappedBinding>,
+}
+
+fn resource_matches<'a, D: Device>(resource: &BindingResource<'a, D>, ty: &BindingType) -> bool {
+    match (resource, ty) {
+        (&BindingResource::Buffer(_), &BindingType::UniformBuffer) => true,
+        (&BindingResource::Buffer(_), &BindingType::StorageBuffer { .. }) => true,
+        (&BindingResource::Texture(_), &BindingType::SampledTexture) => true,
+        (&BindingResource::Texture(_), &BindingType::StorageTexture) => true,
+        (&BindingResource::Sampler(_), &BindingType::Sampler) => true,
+        _ => false,
+    }
+}
+
+impl BindingRemap {
+    /// A remap which moves nothing.
+    pub fn new() -> BindingRemap {
+        BindingRemap { bindings: Vec::new() }
+    }
+
+    /// Record that binding `binding` of group `group` of the source became `targets`,
+    /// replacing whatever was recorded for it before.
+    pub fn add(&mut self, group: uint, binding: uint, targets: Vec<BindingTarget>) -> &mut BindingRemap {
+        self.bindings.retain(|b| b.group != group || b.binding != binding);
+        self.bindings.push(RemappedBinding { group: group, binding: binding, targets: targets });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+
+    /// What binding `binding` of group `group` of the source became, if it was moved.
+    pub fn targets(&self, group: uint, binding: uint) -> Option<&[BindingTarget]> {
+        self.bindings.iter().find(|b| b.group == group && b.binding == binding).map(|b| b.targets.as_slice())
+    }
+
+    /// The layout of bind group `group` of the translated code, from `desc`, written against the
+    /// source: every moved entry is replaced by an entry for each of its targets, with the
+    /// target's type and the entry's visibility, count and flags.
+    pub fn remap_layout(&self, group: uint, desc: BindGroupLayoutDescriptor) -> BindGroupLayoutDescriptor {
+        let mut entries = Vec::with_capacity(desc.entries.len());
+        for entry in desc.entries.into_iter() {
+            let targets = match self.targets(group, entry.binding) {
+                Some(targets) => targets,
+                None => {
+                    entries.push(entry);
+                    continue;
+                }
+            };
+            for target in targets.iter() {
+                entries.push(BindGroupLayoutEntry {
+                    binding: target.binding,
+                    visibility: entry.visibility.clone(),
+                    ty: target.ty.clone(),
+                    count: entry.count.clone(),
+                    partially_bound: entry.partially_bound,
+                    update_after_bind: entry.update_after_bind,
+                });
+            }
+        }
+        // an unsized binding must stay the highest numbered
+        entries.sort_by(|a, b| a.binding.cmp(&b.binding));
+        BindGroupLayoutDescriptor { entries: entries }
+    }
+
+    /// The resources of a bind group of group `group` of the translated code, from `desc`,
+    /// written against the source: every entry of a moved binding goes to the target of the type
+    /// its resource binds as (the texture and the sampler of a combined image sampler to their
+    /// own targets). Entries no target takes are left as they are, for creation to reject.
+    pub fn remap_bind_group<'a, D: Device>(&self, group: uint, desc: BindGroupDescriptor<'a, D>) -> BindGroupDescriptor<'a, D> {
+        let entries = desc.entries.into_iter().map(|entry| {
+            let binding = match self.targets(group, entry.binding) {
+                Some(targets) => match targets.iter().find(|target| resource_matches(&entry.resource, &target.ty)) {
+                    Some(target) => target.binding,
+                    None => entry.binding,
+                },
+                None => entry.binding,
+            };
+            BindGroupEntry { binding: binding, array_element: entry.array_element, resource: entry.resource }
+        }).collect();
+        BindGroupDescriptor { layout: desc.layout, entries: entries, unsized_length: desc.unsized_length }
+    }
+}
+
+/// The shader code for a single backend.
+pub struct ShaderArchiveEntry {
+    pub backend: ShaderBackend,
+    /// The FNV-1a hash of `data`.
+    pub hash: u64,
+    pub data: Vec<u8>,
+    /// How the translation to this backend moved the bindings of the source.
+    pub remap: BindingRemap,
+}
+
+/// A decoded shader archive.
+pub struct ShaderArchive {
+    /// The hash of the source the archive was built from, for detecting stale archives.
+    pub source_hash: u64,
+    pub entries: Vec<ShaderArchiveEntry>,
+    pub functions: Vec<FunctionReflection>,
+}
+
+/// Why an archive could not be decoded.
+#[deriving(Show)]
+pub enum ArchiveError {
+    /// The data does not start with the archive magic.
+    BadMagic,
+    /// The archive was written by an incompatible version of the format.
+    UnsupportedVersion(u32),
+    /// The data ended in the middle of the archive.
+    Truncated,
+    /// An entry's data does not match its hash.
+    HashMismatch,
+    /// A backend, stage or binding type tag is not known.
+    InvalidTag(u32),
+}
+
+/// Computes the 64-bit FNV-1a hash of `data`.
+pub fn hash(data: &[u8]) -> u64 {
+    let mut h = 0xcbf29ce484222325u64;
+    for &b in data.iter() {
+        h ^= b as u64;
+        h *= 0x100000001b3;
+    }
+    h
+}
+
+impl ShaderArchive {
+    /// The entry for `backend`, if the archive has one.
+    pub fn entry(&self, backend: ShaderBackend) -> Option<&ShaderArchiveEntry> {
+        self.entries.iter().find(|e| e.backend == backend)
+    }
+
+    /// How the entry for `backend` moved the bindings of the source, if the archive has an entry
+    /// for it.
+    pub fn binding_remap(&self, backend: ShaderBackend) -> Option<&BindingRemap> {
+        self.entry(backend).map(|e| &e.remap)
+    }
+
+    /// The reflection information of the function called `name`, if there is one.
+    pub fn function(&self, name: &str) -> Option<&FunctionReflection> {
+        self.functions.iter().find(|f| f.name.as_slice() == name)
+    }
+
+    /// Encode the archive in the archive format.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.push_all(MAGIC);
+        put_u32(&mut out, VERSION);
+        put_u64(&mut out, self.source_hash);
+
+        put_u32(&mut out, self.entries.len() as u32);
+        for entry in self.entries.iter() {
+            put_u32(&mut out, entry.backend.tag());
+            put_u64(&mut out, entry.hash);
+            put_blob(&mut out, entry.data.as_slice());
+            put_u32(&mut out, entry.remap.bindings.len() as u32);
+            for remapped in entry.remap.bindings.iter() {
+                put_u32(&mut out, remapped.group as u32);
+                put_u32(&mut out, remapped.binding as u32);
+                put_u32(&mut out, remapped.targets.len() as u32);
+                for target in remapped.targets.iter() {
+                    put_u32(&mut out, target.binding as u32);
+                    put_u32(&mut out, binding_type_tag(&target.ty));
+                }
+            }
+        }
+
+        put_u32(&mut out, self.functions.len() as u32);
+        for function in self.functions.iter() {
+            put_blob(&mut out, function.name.as_bytes());
+            put_u32(&mut out, stage_tag(&function.stage));
+            put_u32(&mut out, function.bindings.len() as u32);
+            for binding in function.bindings.iter() {
+                put_u32(&mut out, binding.group as u32);
+                put_u32(&mut out, binding.binding as u32);
+                put_u32(&mut out, binding_type_tag(&binding.ty));
+            }
+        }
+        out
+    }
+
+    /// Decode an archive, checking every entry's hash.
+    pub fn decode(data: &[u8]) -> Result<ShaderArchive, ArchiveError> {
+        let mut r = Reader { data: data, pos: 0 };
+        if try!(r.bytes(MAGIC.len())) != MAGIC {
+            return Err(ArchiveError::BadMagic);
+        }
+        let version = try!(r.u32());
+        if version == 0 || version > VERSION {
+            return Err(ArchiveError::UnsupportedVersion(version));
+        }
+        let source_hash = try!(r.u64());
+
+        let mut entries = Vec::new();
+        for _ in range(0, try!(r.u32())) {
+            let tag = try!(r.u32());
+            let backend = match ShaderBackend::from_tag(tag) {
+                Some(backend) => backend,
+                None => return Err(ArchiveError::InvalidTag(tag)),
+            };
+            let entry_hash = try!(r.u64());
+            let data = try!(r.blob());
+            if hash(data) != entry_hash {
+                return Err(ArchiveError::HashMismatch);
+            }
+            let mut remap = BindingRemap::new();
+            if version >= 2 {
+                for _ in range(0, try!(r.u32())) {
+                    let group = try!(r.u32()) as uint;
+                    let binding = try!(r.u32()) as uint;
+                    let mut targets = Vec::new();
+                    for _ in range(0, try!(r.u32())) {
+                        let target_binding = try!(r.u32()) as uint;
+                        let ty = try!(binding_type_from_tag(try!(r.u32())));
+                        targets.push(BindingTarget { binding: target_binding, ty: ty });
+                    }
+                    remap.bindings.push(RemappedBinding { group: group, binding: binding, targets: targets });
+                }
+            }
+            entries.push(ShaderArchiveEntry { backend: backend, hash: entry_hash, data: data.to_vec(), remap: remap });
+        }
+
+        let mut functions = Vec::new();
+        for _ in range(0, try!(r.u32())) {
+            let name = match String::from_utf8(try!(r.blob()).to_vec()) {
+                Ok(name) => name,
+                Err(_) => return Err(ArchiveError::Truncated),
+            };
+            let stage = try!(stage_from_tag(try!(r.u32())));
+            let mut bindings = Vec::new();
+            for _ in range(0, try!(r.u32())) {
+                let group = try!(r.u32()) as uint;
+                let binding = try!(r.u32()) as uint;
+                let ty = try!(binding_type_from_tag(try!(r.u32())));
+                bindings.push(BindingReflection { group: group, binding: binding, ty: ty });
+            }
+            functions.push(FunctionReflection { name: name, stage: stage, bindings: bindings });
+        }
+
+        Ok(ShaderArchive { source_hash: source_hash, entries: entries, functions: functions })
+    }
+}
+
+/// Assembles a `ShaderArchive` from compiled shader code, typically in a build script.
+pub struct ShaderArchiveBuilder {
+    archive: ShaderArchive,
+}
+
+impl ShaderArchiveBuilder {
+    /// Start an archive for the program compiled from `source`.
+    pub fn new(source: &[u8]) -> ShaderArchiveBuilder {
+        ShaderArchiveBuilder {
+            archive: ShaderArchive { source_hash: hash(source), entries: Vec::new(), functions: Vec::new() },
+        }
+    }
+
+    /// Add the compiled code for `backend`, replacing any earlier entry for it.
+    pub fn add_entry(&mut self, backend: ShaderBackend, data: Vec<u8>) -> &mut ShaderArchiveBuilder {
+        self.add_remapped_entry(backend, data, BindingRemap::new())
+    }
+
+    /// As `add_entry`, for code whose translation moved the bindings of the source as `remap`
+    /// records.
+    pub fn add_remapped_entry(&mut self, backend: ShaderBackend, data: Vec<u8>, remap: BindingRemap) -> &mut ShaderArchiveBuilder {
+        self.archive.entries.retain(|e| e.backend != backend);
+        let h = hash(data.as_slice());
+        self.archive.entries.push(ShaderArchiveEntry { backend: backend, hash: h, data: data, remap: remap });
+        self
+    }
+
+    /// Add the reflection information for one function of the program.
+    pub fn add_function(&mut self, function: FunctionReflection) -> &mut ShaderArchiveBuilder {
+        self.archive.functions.push(function);
+        self
+    }
+
+    /// Finish the archive.
+    pub fn build(self) -> ShaderArchive {
+        self.archive
+    }
+}
+
+fn stage_tag(stage: &ShaderStage) -> u32 {
+    match *stage {
+        ShaderStage::Vertex => 0,
+        ShaderStage::Fragment => 1,
+        ShaderStage::Compute => 2,
+    }
+}
+
+fn stage_from_tag(tag: u32) -> Result<ShaderStage, ArchiveError> {
+    match tag {
+        0 => Ok(ShaderStage::Vertex),
+        1 => Ok(ShaderStage::Fragment),
+        2 => Ok(ShaderStage::Compute),
+        _ => Err(ArchiveError::InvalidTag(tag)),
+    }
+}
+
+fn binding_type_tag(ty: &BindingType) -> u32 {
+    match *ty {
+        BindingType::UniformBuffer => 0,
+        BindingType::StorageBuffer { read_only: false } => 1,
+        BindingType::StorageBuffer { read_only: true } => 2,
+        BindingType::SampledTexture => 3,
+        BindingType::StorageTexture => 4,
+        BindingType::Sampler => 5,
+    }
+}
+
+fn binding_type_from_tag(tag: u32) -> Result<BindingType, ArchiveError> {
+    match tag {
+        0 => Ok(BindingType::UniformBuffer),
+        1 => Ok(BindingType::StorageBuffer { read_only: false }),
+        2 => Ok(BindingType::StorageBuffer { read_only: true }),
+        3 => Ok(BindingType::SampledTexture),
+        4 => Ok(BindingType::StorageTexture),
+        5 => Ok(BindingType::Sampler),
+        _ => Err(ArchiveError::InvalidTag(tag)),
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    for i in range(0, 4u) {
+        out.push((v >> (i * 8)) as u8);
+    }
+}
+
+fn put_u64(out: &mut Vec<u8>, v: u64) {
+    for i in range(0, 8u) {
+        out.push((v >> (i * 8)) as u8);
+    }
+}
+
+fn put_blob(out: &mut Vec<u8>, data: &[u8]) {
+    put_u32(out, data.len() as u32);
+    out.push_all(data);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: uint,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: uint) -> Result<&'a [u8], ArchiveError> {
+        if self.data.len() - self.pos < n {
+            return Err(ArchiveError::Truncated);
+        }
+        let bytes = self.data.slice(self.pos, self.pos + n);
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, ArchiveError> {
+        let b = try!(self.bytes(4));
+        Ok(range(0, 4u).fold(0, |v, i| v | (b[i] as u32 << (i * 8))))
+    }
+
+    fn u64(&mut self) -> Result<u64, ArchiveError> {
+        let b = try!(self.bytes(8));
+        Ok(range(0, 8u).fold(0, |v, i| v | (b[i] as u64 << (i * 8))))
+    }
+
+    fn blob(&mut self) -> Result<&'a [u8], ArchiveError> {
+        let len = try!(self.u32()) as uint;
+        self.bytes(len)
+    }
+}
@@ -0,0 +1,222 @@
+//! Shader variants from combinations of features.
+//!
+//! Uber shaders switch features on and off with preprocessor macros (`USE_SHADOWS`,
+//! `LIGHT_COUNT`, ...), and each combination in use is a pipeline of its own. A
+//! `PermutationSpace` declares those features, and packs a combination of their values into a
+//! `u64` key, so the pipeline of a draw is found with a few bit operations rather than by name:
+//!
+//! ```ignore
+//! let mut space = PermutationSpace::new();
+//! let shadows = space.bool_feature("USE_SHADOWS");
+//! let lights = space.int_feature("LIGHT_COUNT", &[1, 4, 16]);
+//! let mut lit = PermutedShader::new(space, "lit", "lit.metal", source);
+//! let keys = lit.space().all();
+//! try!(lit.compile(&mut device, &mut registry, &mut preprocessor, keys.as_slice(),
+//!                  |source| source, |_| descriptor()));
+//!
+//! // at draw time
+//! let key = lit.space().set_bool(lit.space().set(0, lights, 1), shadows, casts_shadows);
+//! encoder.set_render_pipeline(lit.get(&registry, key).unwrap());
+//! ```
+//!
+//! Every combination is `all()`, which grows quickly; `filtered` prunes the combinations that are
+//! never used, and any list of keys works. Variants are compiled in the background through a
+//! `PipelineRegistry`, under names like `lit[USE_SHADOWS=1,LIGHT_COUNT=4]`.
+
+use std::collections::HashMap;
+
+use {Device, RenderPipelineDescriptor};
+use pipeline_loader::PipelineRegistry;
+use shader_preprocessor::{IncludeResolver, PreprocessError, Preprocessor};
+
+/// A feature of a `PermutationSpace`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct FeatureId(uint);
+
+#[deriving(Clone)]
+struct Feature {
+    name: String,
+    /// The values the feature's macro can be defined as. `0` and `1` for boolean features.
+    values: Vec<i64>,
+    shift: uint,
+    bits: uint,
+}
+
+/// The features of a shader, and how their values pack into keys.
+#[deriving(Clone)]
+pub struct PermutationSpace {
+    features: Vec<Feature>,
+    bits: uint,
+}
+
+impl PermutationSpace {
+    pub fn new() -> PermutationSpace {
+        PermutationSpace { features: Vec::new(), bits: 0 }
+    }
+
+    /// Declare a feature whose macro is defined as one of `values`, picked by index. The value of
+    /// every feature starts out as its first.
+    ///
+    /// Panics if `values` is empty, or if the key no longer fits in 64 bits.
+    pub fn int_feature(&mut self, name: &str, values: &[i64]) -> FeatureId {
+        assert!(!values.is_empty(), "shader feature {} has no values", name);
+        let mut bits = 0;
+        while (1 << bits) < values.len() {
+            bits += 1;
+        }
+        assert!(self.bits + bits <= 64, "shader features do not fit in a 64 bit key");
+        self.features.push(Feature { name: name.to_string(), values: values.to_vec(), shift: self.bits, bits: bits });
+        self.bits += bits;
+        FeatureId(self.features.len() - 1)
+    }
+
+    /// Declare a feature whose macro is defined as `0` or `1`.
+    pub fn bool_feature(&mut self, name: &str) -> FeatureId {
+        self.int_feature(name, &[0, 1])
+    }
+
+    /// `key`, with the value of `feature` set to its `value`th.
+    ///
+    /// Panics if the feature has no such value.
+    pub fn set(&self, key: u64, feature: FeatureId, value: uint) -> u64 {
+        let FeatureId(index) = feature;
+        let feature = &self.features[index];
+        assert!(value < feature.values.len(), "shader feature {} has no value {}", feature.name, value);
+        let mask = ((1u64 << feature.bits) - 1) << feature.shift;
+        (key & !mask) | ((value as u64) << feature.shift)
+    }
+
+    /// `key`, with the boolean `feature` set to `enabled`.
+    pub fn set_bool(&self, key: u64, feature: FeatureId, enabled: bool) -> u64 {
+        self.set(key, feature, enabled as uint)
+    }
+
+    /// The index of the value `feature` has in `key`.
+    pub fn get(&self, key: u64, feature: FeatureId) -> uint {
+        let FeatureId(index) = feature;
+        let feature = &self.features[index];
+        ((key >> feature.shift) & ((1u64 << feature.bits) - 1)) as uint
+    }
+
+    /// Whether `key` is a combination of values of this space's features.
+    pub fn is_valid(&self, key: u64) -> bool {
+        (self.bits == 64 || key >> self.bits == 0)
+            && range(0, self.features.len()).all(|index| self.get(key, FeatureId(index)) < self.features[index].values.len())
+    }
+
+    /// The key of every combination of values.
+    pub fn all(&self) -> Vec<u64> {
+        let mut keys = vec![0u64];
+        for index in range(0, self.features.len()) {
+            let mut next = Vec::with_capacity(keys.len() * self.features[index].values.len());
+            for &key in keys.iter() {
+                for value in range(0, self.features[index].values.len()) {
+                    next.push(self.set(key, FeatureId(index), value));
+                }
+            }
+            keys = next;
+        }
+        keys
+    }
+
+    /// The key of every combination of values `keep` accepts.
+    pub fn filtered<F: FnMut(u64) -> bool>(&self, keep: F) -> Vec<u64> {
+        let mut keep = keep;
+        self.all().into_iter().filter(|&key| keep(key)).collect()
+    }
+
+    /// The macro definitions of the combination `key`.
+    pub fn defines(&self, key: u64) -> Vec<(String, String)> {
+        self.features.iter().enumerate().map(|(index, feature)| {
+            (feature.name.clone(), feature.values[self.get(key, FeatureId(index))].to_string())
+        }).collect()
+    }
+}
+
+/// Why variants could not be compiled.
+pub enum PermutationError<D: Device> {
+    /// The source of the variant `key` could not be preprocessed.
+    Preprocess { key: u64, error: PreprocessError },
+    /// The shader program of the variant `key` could not be created.
+    ShaderProgram { key: u64, error: D::ShaderProgramCreationError },
+}
+
+/// A shader source, and the pipelines of its variants.
+pub struct PermutedShader {
+    space: PermutationSpace,
+    name: String,
+    source_name: String,
+    source: String,
+    /// The registry name of every variant compiled.
+    variants: HashMap<u64, String>,
+}
+
+impl PermutedShader {
+    /// Variants of the source `source`, the file `source_name`, whose pipelines are named after
+    /// `name`.
+    pub fn new(space: PermutationSpace, name: &str, source_name: &str, source: &str) -> PermutedShader {
+        PermutedShader {
+            space: space,
+            name: name.to_string(),
+            source_name: source_name.to_string(),
+            source: source.to_string(),
+            variants: HashMap::new(),
+        }
+    }
+
+    pub fn space(&self) -> &PermutationSpace {
+        &self.space
+    }
+
+    /// The name of the pipeline of variant `key` in the registry.
+    pub fn variant_name(&self, key: u64) -> String {
+        let defines: Vec<String> = self.space.defines(key).into_iter()
+            .map(|(name, value)| format!("{}={}", name, value)).collect();
+        format!("{}[{}]", self.name, defines.connect(","))
+    }
+
+    /// The preprocessed source of variant `key`.
+    pub fn variant_source<R: IncludeResolver>(&self, preprocessor: &mut Preprocessor<R>, key: u64) -> Result<String, PreprocessError> {
+        preprocessor.process_with_defines(self.source_name.as_slice(), self.source.as_slice(),
+                                          self.space.defines(key).as_slice())
+    }
+
+    /// Start compiling the pipelines of the variants `keys` into `registry`, in the background.
+    ///
+    /// `input` turns a preprocessed source into what the device creates shader programs from, and
+    /// `descriptor` gives the descriptor of each variant's pipeline. Stops at the first variant
+    /// whose shader program cannot be created; pipeline creation errors are reported by
+    /// `PipelineRegistry::poll`, under the variant's name. Variants compiled already are
+    /// compiled again.
+    pub fn compile<D, R, I, F>(&mut self, device: &mut D, registry: &mut PipelineRegistry<D>, preprocessor: &mut Preprocessor<R>,
+                               keys: &[u64], input: I, descriptor: F) -> Result<(), PermutationError<D>>
+            where D: Device, D::RenderPipeline: Send, D::RenderPipelineCreationError: Send, R: IncludeResolver,
+                  I: FnMut(String) -> D::ShaderProgramInput, F: FnMut(u64) -> RenderPipelineDescriptor {
+        enter_span!("PermutedShader::compile", variants = keys.len());
+        let (mut input, mut descriptor) = (input, descriptor);
+        for &key in keys.iter() {
+            assert!(self.space.is_valid(key), "{:x} is not a key of the features of {}", key, self.name);
+            let source = match self.variant_source(preprocessor, key) {
+                Ok(source) => source,
+                Err(e) => return Err(PermutationError::Preprocess { key: key, error: e }),
+            };
+            let program = match device.create_shader_program(input(source)) {
+                Ok(program) => program,
+                Err(e) => return Err(PermutationError::ShaderProgram { key: key, error: e }),
+            };
+            let name = self.variant_name(key);
+            registry.compile(device, &program, name.clone(), descriptor(key), None);
+            self.variants.insert(key, name);
+        }
+        Ok(())
+    }
+
+    /// The pipeline of variant `key`, if it was compiled and is ready.
+    pub fn get<'r, D: Device>(&self, registry: &'r PipelineRegistry<D>, key: u64) -> Option<&'r D::RenderPipeline>
+            where D::RenderPipeline: Send, D::RenderPipelineCreationError: Send {
+        match self.variants.get(&key) {
+            Some(name) => registry.get(name.as_slice()),
+            None => None,
+        }
+    }
+}
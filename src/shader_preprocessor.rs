@@ -0,0 +1,217 @@
+//! Expanding `#include`s and injecting `#define`s in shader source.
+//!
+//! Shader source compiled at run time has no file system to include from, so libraries of shared
+//! headers would otherwise need an external preprocessing step. `Preprocessor` pastes the files
+//! named by quoted `#include`s into the source, found through an `IncludeResolver`, and puts the
+//! `#define`s of a permutation in front of it:
+//!
+//! ```ignore
+//! let mut preprocessor = Preprocessor::new(DirectoryIncludeResolver::new(vec![Path::new("shaders")]));
+//! preprocessor.define("MAX_LIGHTS", "64");
+//! preprocessor.define("USE_SHADOWS", "1");
+//! let source = try!(preprocessor.process("lighting.metal", lighting_source));
+//! let program = try!(device.create_shader_program(source));
+//! ```
+//!
+//! Only includes are expanded: the output still goes through the shader compiler's own
+//! preprocessor, which evaluates the defines, conditionals and include guards. Includes in angle
+//! brackets (`#include <metal_stdlib>`) are the compiler's, and left as they are. A file with
+//! `#pragma once` is pasted in at most once per source. `#line` directives are emitted around
+//! every included file, so compiler errors point into the right file and line.
+
+use std::collections::HashMap;
+use std::io::File;
+
+/// Finds the files shader sources include.
+pub trait IncludeResolver {
+    /// The canonical name and the contents of the file `path` names, as written in an
+    /// `#include` of the file `included_from` (itself a canonical name, or the name given to
+    /// `Preprocessor::process`).
+    ///
+    /// The canonical name is the same for every path naming the same file, however it was
+    /// written, as `#pragma once` and cycles are detected by name. It is also the name the file's
+    /// own includes are resolved from, and the one `#line` directives give.
+    ///
+    /// Returns `None` if there is no such file.
+    fn resolve(&mut self, path: &str, included_from: &str) -> Option<(String, String)>;
+}
+
+/// Includes from memory, by path, for shader libraries embedded in the executable. Paths are
+/// their own canonical names.
+impl IncludeResolver for HashMap<String, String> {
+    fn resolve(&mut self, path: &str, _: &str) -> Option<(String, String)> {
+        self.get(path).map(|source| (path.to_string(), source.clone()))
+    }
+}
+
+/// Includes from the file system: relative to the including file first, then to each of a list
+/// of directories, in order. The canonical name of a file is the path it was found at, with `.`
+/// and `..` components resolved.
+pub struct DirectoryIncludeResolver {
+    directories: Vec<Path>,
+}
+
+impl DirectoryIncludeResolver {
+    pub fn new(directories: Vec<Path>) -> DirectoryIncludeResolver {
+        DirectoryIncludeResolver { directories: directories }
+    }
+}
+
+impl IncludeResolver for DirectoryIncludeResolver {
+    fn resolve(&mut self, path: &str, included_from: &str) -> Option<(String, String)> {
+        let relative = Path::new(included_from).dir_path().join(path);
+        let candidates = Some(relative).into_iter().chain(self.directories.iter().map(|directory| directory.join(path)));
+        for candidate in candidates {
+            if let Ok(source) = File::open(&candidate).read_to_string() {
+                // joining normalizes the path
+                return Some((candidate.display().to_string(), source));
+            }
+        }
+        None
+    }
+}
+
+/// Why a source could not be preprocessed.
+#[deriving(Show)]
+pub enum PreprocessError {
+    /// An included file could not be resolved.
+    NotFound { path: String, file: String, line: uint },
+    /// An `#include` which names no file, or does not close its quotes.
+    Malformed { file: String, line: uint },
+    /// Files include each other without `#pragma once`, which would never end. Lists the
+    /// canonical names of the files from the outermost.
+    Cycle(Vec<String>),
+}
+
+/// What a line of source is, as far as the preprocessor cares.
+enum Directive<'a> {
+    Include(&'a str),
+    MalformedInclude,
+    PragmaOnce,
+    Other,
+}
+
+fn directive(line: &str) -> Directive {
+    let line = line.trim_left();
+    if !line.starts_with("#") {
+        return Directive::Other;
+    }
+    let line = line.slice_from(1).trim_left();
+    if line.starts_with("pragma") && line.slice_from(6).trim() == "once" {
+        return Directive::PragmaOnce;
+    }
+    if !line.starts_with("include") {
+        return Directive::Other;
+    }
+    let rest = line.slice_from(7).trim_left();
+    if rest.starts_with("<") {
+        return Directive::Other;
+    }
+    if !rest.starts_with("\"") {
+        return Directive::MalformedInclude;
+    }
+    match rest.slice_from(1).find('"') {
+        Some(end) if end > 0 => Directive::Include(rest.slice(1, end + 1)),
+        _ => Directive::MalformedInclude,
+    }
+}
+
+/// Expands includes with an `IncludeResolver`, and injects defines. See the module
+/// documentation.
+pub struct Preprocessor<R> {
+    resolver: R,
+    defines: Vec<(String, String)>,
+    line_directives: bool,
+}
+
+impl<R: IncludeResolver> Preprocessor<R> {
+    pub fn new(resolver: R) -> Preprocessor<R> {
+        Preprocessor { resolver: resolver, defines: Vec::new(), line_directives: true }
+    }
+
+    /// Define `name` as `value` in every source processed from now on, replacing any earlier
+    /// definition of it.
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.defines.retain(|&(ref defined, _)| defined.as_slice() != name);
+        self.defines.push((name.to_string(), value.to_string()));
+    }
+
+    /// Forget every define.
+    pub fn clear_defines(&mut self) {
+        self.defines.clear();
+    }
+
+    /// Whether to emit `#line` directives. On by default; compilers which do not support them
+    /// need them off, at the cost of line numbers in errors.
+    pub fn set_line_directives(&mut self, enabled: bool) {
+        self.line_directives = enabled;
+    }
+
+    /// Preprocess `source`, the file called `name`, into a source with no includes left to
+    /// resolve.
+    pub fn process(&mut self, name: &str, source: &str) -> Result<String, PreprocessError> {
+        self.process_with_defines(name, source, &[])
+    }
+
+    /// `process`, with `defines` defined after the preprocessor's own, for the defines of one
+    /// variant of a shader.
+    pub fn process_with_defines(&mut self, name: &str, source: &str,
+                                defines: &[(String, String)]) -> Result<String, PreprocessError> {
+        let mut output = String::new();
+        for &(ref name, ref value) in self.defines.iter().chain(defines.iter()) {
+            output.push_str(format!("#define {} {}\n", name, value).as_slice());
+        }
+        let mut stack = vec![name.to_string()];
+        let mut once = Vec::new();
+        try!(self.expand(name, source, &mut stack, &mut once, &mut output));
+        Ok(output)
+    }
+
+    fn expand(&mut self, name: &str, source: &str, stack: &mut Vec<String>, once: &mut Vec<String>,
+              output: &mut String) -> Result<(), PreprocessError> {
+        if self.line_directives {
+            output.push_str(format!("#line 1 \"{}\"\n", name).as_slice());
+        }
+        for (i, line) in source.lines().enumerate() {
+            match directive(line) {
+                Directive::Include(path) => {
+                    let (canonical, included) = match self.resolver.resolve(path, name) {
+                        Some(resolved) => resolved,
+                        None => return Err(PreprocessError::NotFound {
+                            path: path.to_string(),
+                            file: name.to_string(),
+                            line: i + 1,
+                        }),
+                    };
+                    if once.contains(&canonical) {
+                        continue;
+                    }
+                    if stack.contains(&canonical) {
+                        let mut cycle = stack.clone();
+                        cycle.push(canonical);
+                        return Err(PreprocessError::Cycle(cycle));
+                    }
+                    stack.push(canonical.clone());
+                    try!(self.expand(canonical.as_slice(), included.as_slice(), stack, once, output));
+                    stack.pop();
+                    if self.line_directives {
+                        output.push_str(format!("#line {} \"{}\"\n", i + 2, name).as_slice());
+                    }
+                }
+                Directive::MalformedInclude => {
+                    return Err(PreprocessError::Malformed { file: name.to_string(), line: i + 1 });
+                }
+                Directive::PragmaOnce => {
+                    once.push(name.to_string());
+                    // keeps the line numbers
+                    output.push('\n');
+                }
+                Directive::Other => {
+                    output.push_str(line);
+                    output.push('\n');
+                }
+            }
+        }
+        Ok(())
+    }
+}
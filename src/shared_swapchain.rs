@@ -0,0 +1,241 @@
+//! Swapchains shared with a compositor in another process.
+//!
+//! Embedded UI services, browser-style multi-process architectures and plugin hosts render in
+//! one process and show the result in another, which composites it with everything else. The
+//! frames go through shared memory rather than copies: the producer renders into textures the
+//! compositor imports, and the two order their work on the same GPU with a pair of shared
+//! events, so neither ever waits on the CPU:
+//!
+//! * `ready` is signaled by the producer with the number of each frame once it is rendered.
+//!   Frames are numbered from `1`, and frame `n` is rendered into image `(n - 1) % image_count`;
+//! * `released` is signaled by the compositor with the number of the last frame it has finished
+//!   reading, which releases that frame's image and every older one. The producer's frame `n`
+//!   waits for `released` to reach `n - image_count`, the frame last rendered into its image.
+//!
+//! The producer creates everything with a `SharedSwapchain`, and sends the native handles of
+//! `SharedSwapchain::export` to the compositor through whatever IPC the application has (unix
+//! domain sockets with `SCM_RIGHTS` for file descriptors, `DuplicateHandle` for NT handles, XPC
+//! on macOS). The compositor imports them with `CompositorSwapchain::import`.
+//!
+//! ```ignore
+//! // producer
+//! let mut swapchain = try!(SharedSwapchain::new(&mut device, 3, || ui_target_descriptor(width, height)));
+//! send_to_compositor(try!(swapchain.export(&device)));
+//! loop {
+//!     let mut commands = queue.create_command_buffer();
+//!     let index = swapchain.acquire(&mut commands);
+//!     render_ui(&mut commands, swapchain.texture(index));
+//!     swapchain.present(&mut commands);
+//!     commands.commit();
+//! }
+//!
+//! // compositor
+//! let mut client = try!(unsafe { CompositorSwapchain::import(&mut device, &handles, || ui_target_descriptor(width, height)) });
+//! // every frame of the compositor, if the client has presented a frame it has not read yet
+//! let frame = client.latest_frame(&device);
+//! let texture = client.begin_read(&mut commands, frame);
+//! composite(&mut commands, texture);
+//! client.end_read(&mut commands);
+//! ```
+//!
+//! A compositor which falls behind skips frames; one which stops reading holds the producer
+//! back once every image is in use, as its command buffers wait on `released`. Producers which
+//! should not block their queue check `SharedSwapchain::released_frame` (or wait for it with
+//! `Device::notify_event`) before acquiring.
+//!
+//! Everything here needs the `shared_swapchain` feature of the crate, and the `external_memory`
+//! device feature in both processes. The descriptor of the images must be the same on both
+//! sides, with a format the backends can share (`RGBA8Unorm`, `BGRA8Unorm` and their sRGB
+//! variants, and `RGBA16Float`, everywhere).
+
+#[cfg(not(feature = "std"))]
+use core::prelude::*;
+#[cfg(not(feature = "std"))]
+use collections::Vec;
+
+use {Device, CommandBuffer, TextureDescriptor};
+
+/// A native handle to memory or a synchronization object, which another process can import.
+///
+/// Handles are the caller's: those returned by exports are sent to the other process and closed
+/// there, or wherever the application is done with them, and imports do not take them over.
+#[deriving(Clone, PartialEq, Show)]
+pub enum ExternalHandle {
+    /// A POSIX file descriptor: an opaque vulkan memory or semaphore fd, or a dma-buf.
+    Fd(i32),
+    /// An NT `HANDLE`, of a shared D3D12 resource or fence, or of vulkan memory or a semaphore
+    /// exported for win32.
+    Win32(*mut u8),
+    /// An XPC object: of an `IOSurface` (`IOSurfaceCreateXPCObject`), or the
+    /// `MTLSharedEventHandle` of an event.
+    Xpc(*mut u8),
+}
+
+/// The handles of a `SharedSwapchain`, for its compositor to import.
+#[deriving(Clone, Show)]
+pub struct SharedSwapchainHandles {
+    /// The memory of every image, in order.
+    pub images: Vec<ExternalHandle>,
+    pub ready: ExternalHandle,
+    pub released: ExternalHandle,
+}
+
+/// Why a shared swapchain could not be created, exported or imported.
+pub enum SharedSwapchainError<D: Device> {
+    Texture(D::TextureCreationError),
+    Handle(D::ExternalHandleError),
+}
+
+/// The producer's side of a swapchain shared with a compositor. See the module documentation.
+pub struct SharedSwapchain<D: Device> {
+    images: Vec<D::Texture>,
+    ready: D::Event,
+    released: D::Event,
+    /// The number of the last frame acquired.
+    frame: u64,
+    acquired: bool,
+}
+
+impl<D: Device> SharedSwapchain<D> {
+    /// Create `image_count` shareable images, at least `2`, of the descriptor `desc` returns, and
+    /// the events ordering them.
+    pub fn new<F: Fn() -> TextureDescriptor>(device: &mut D, image_count: uint, desc: F)
+                                             -> Result<SharedSwapchain<D>, SharedSwapchainError<D>> {
+        enter_span!("SharedSwapchain::new", image_count = image_count);
+        assert!(image_count >= 2, "a shared swapchain needs at least two images, not {}", image_count);
+        let mut images = Vec::with_capacity(image_count);
+        for _ in range(0, image_count) {
+            images.push(try!(device.create_shareable_texture(desc()).map_err(SharedSwapchainError::Texture)));
+        }
+        let ready = try!(device.create_shareable_event().map_err(SharedSwapchainError::Handle));
+        let released = try!(device.create_shareable_event().map_err(SharedSwapchainError::Handle));
+        Ok(SharedSwapchain { images: images, ready: ready, released: released, frame: 0, acquired: false })
+    }
+
+    /// New handles of the images and events, for the compositor.
+    pub fn export(&self, device: &D) -> Result<SharedSwapchainHandles, D::ExternalHandleError> {
+        let mut images = Vec::with_capacity(self.images.len());
+        for image in self.images.iter() {
+            images.push(try!(device.export_texture(image)));
+        }
+        Ok(SharedSwapchainHandles {
+            images: images,
+            ready: try!(device.export_event(&self.ready)),
+            released: try!(device.export_event(&self.released)),
+        })
+    }
+
+    pub fn image_count(&self) -> uint {
+        self.images.len()
+    }
+
+    /// Begin the next frame in `commands`: hold back what is encoded after this until the
+    /// compositor has released the frame's image, and return its index.
+    ///
+    /// No encoder may be open, and the last frame acquired must have been presented.
+    pub fn acquire(&mut self, commands: &mut D::CommandBuffer) -> uint {
+        assert!(!self.acquired, "acquired a frame of a shared swapchain before presenting the last one");
+        self.frame += 1;
+        self.acquired = true;
+        let count = self.images.len() as u64;
+        if self.frame > count {
+            commands.encode_wait_for_event(&self.released, self.frame - count);
+        }
+        ((self.frame - 1) % count) as uint
+    }
+
+    /// The image of index `index`.
+    pub fn texture(&self, index: uint) -> &D::Texture {
+        &self.images[index]
+    }
+
+    /// Hand the acquired frame to the compositor once everything encoded in `commands` before
+    /// this has finished executing.
+    ///
+    /// No encoder may be open, and `commands` must be committed after the command buffers which
+    /// rendered the frame, to the same queue.
+    pub fn present(&mut self, commands: &mut D::CommandBuffer) {
+        assert!(self.acquired, "presented a shared swapchain with no frame acquired");
+        self.acquired = false;
+        commands.encode_signal_event(&self.ready, self.frame);
+    }
+
+    /// The number of the last frame the compositor has finished reading, or `0`. Frame `n`
+    /// can be acquired without waiting once this is at least `n - image_count`.
+    pub fn released_frame(&self, device: &D) -> u64 {
+        device.event_value(&self.released)
+    }
+
+    /// The events, to wait on them with `Device::notify_event`: `ready`, then `released`.
+    pub fn events(&self) -> (&D::Event, &D::Event) {
+        (&self.ready, &self.released)
+    }
+}
+
+/// The compositor's side of a swapchain shared by a producer in another process. See the module
+/// documentation.
+pub struct CompositorSwapchain<D: Device> {
+    images: Vec<D::Texture>,
+    ready: D::Event,
+    released: D::Event,
+    /// The number of the last frame read.
+    frame: u64,
+    reading: bool,
+}
+
+impl<D: Device> CompositorSwapchain<D> {
+    /// Import the images and events of a producer's `SharedSwapchain::export`, with the
+    /// descriptor `desc` returns, the producer's.
+    ///
+    /// Unsafe, as for `Device::import_texture`: the handles must be of a swapchain of that
+    /// descriptor.
+    pub unsafe fn import<F: Fn() -> TextureDescriptor>(device: &mut D, handles: &SharedSwapchainHandles, desc: F)
+                                                       -> Result<CompositorSwapchain<D>, SharedSwapchainError<D>> {
+        enter_span!("CompositorSwapchain::import", image_count = handles.images.len());
+        let mut images = Vec::with_capacity(handles.images.len());
+        for handle in handles.images.iter() {
+            images.push(try!(device.import_texture(handle.clone(), desc()).map_err(SharedSwapchainError::Texture)));
+        }
+        let ready = try!(device.import_event(handles.ready.clone()).map_err(SharedSwapchainError::Handle));
+        let released = try!(device.import_event(handles.released.clone()).map_err(SharedSwapchainError::Handle));
+        Ok(CompositorSwapchain { images: images, ready: ready, released: released, frame: 0, reading: false })
+    }
+
+    /// The number of the newest frame the producer has presented, or `0` if it has presented
+    /// none.
+    pub fn latest_frame(&self, device: &D) -> u64 {
+        device.event_value(&self.ready)
+    }
+
+    /// Begin reading frame `frame` in `commands`: hold back what is encoded after this until
+    /// the frame is ready, and return its image.
+    ///
+    /// No encoder may be open. Frames are read in increasing order, each after the last one read
+    /// was ended, and waiting on a frame the producer never presents never finishes: read
+    /// `latest_frame`, or one it is known to be about to present.
+    pub fn begin_read(&mut self, commands: &mut D::CommandBuffer, frame: u64) -> &D::Texture {
+        assert!(!self.reading, "began reading a frame of a shared swapchain before ending the last one");
+        assert!(frame > self.frame, "read frame {} of a shared swapchain after frame {}", frame, self.frame);
+        self.frame = frame;
+        self.reading = true;
+        commands.encode_wait_for_event(&self.ready, frame);
+        &self.images[((frame - 1) % self.images.len() as u64) as uint]
+    }
+
+    /// Release the frame being read, and every older one, to the producer once everything
+    /// encoded in `commands` before this has finished executing.
+    pub fn end_read(&mut self, commands: &mut D::CommandBuffer) {
+        assert!(self.reading, "ended reading a shared swapchain with no frame being read");
+        self.reading = false;
+        commands.encode_signal_event(&self.released, self.frame);
+    }
+
+    /// The number of the last frame read, or `0`.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn image_count(&self) -> uint {
+        self.images.len()
+    }
+}
@@ -0,0 +1,330 @@
+//! The size math of textures: mip chains, array slices, and the bytes texel data takes.
+//!
+//! Every upload and readback does this math, and an upload which does it slightly wrong (a mip
+//! level of an odd size rounded up instead of down, a buffer sized for padded rows including the
+//! last one, a cube face indexed as an array slice of the wrong level) writes garbage or reads
+//! past the end of its data, usually without any error. The functions here are the one copy of
+//! it: backends check descriptors and regions with `validate_descriptor`, `validate_view`,
+//! `validate_region` and `validate_data` (as `LimitsOverrideDevice` does for the textures it
+//! creates, views, writes, reads and copies), and applications size the data they upload with
+//! the same functions:
+//!
+//! ```ignore
+//! let levels = mip_level_count(1024, 512, 1);
+//! for level in range(0, levels) {
+//!     let size = mip_size(&desc, level);
+//!     let bytes_per_row = aligned_bytes_per_row(&desc.pixel_format, size.width);
+//!     let buffer = try!(device.create_buffer(region_byte_size(&desc.pixel_format, &size, bytes_per_row), None));
+//!     // fill buffer with the level's rows, bytes_per_row apart
+//!     blit.copy_buffer_to_texture(BufferSlice::whole(&buffer), bytes_per_row, &texture, level, 0, origin, size);
+//! }
+//! ```
+//!
+//! Sizes are counted in blocks of texels (see `BlockLayout`), so the math holds for block
+//! compressed formats as it does for the formats of one texel per block there are so far.
+
+use std::cmp::max;
+
+use {PixelFormat, Origin, Size, TextureDescriptor, TextureType, TextureViewDescriptor, COPY_ROW_ALIGNMENT};
+
+/// The number of faces of a cube texture, which are its array slices, in the order `+x`, `-x`,
+/// `+y`, `-y`, `+z`, `-z`.
+pub const CUBE_FACES: uint = 6;
+
+/// How the texels of a format are stored: in blocks of `width` by `height` texels, of `bytes`
+/// bytes each. Every format of `PixelFormat` so far has blocks of a single texel; block
+/// compressed formats have blocks of 4x4 texels (or more), which regions must be aligned to.
+#[deriving(Clone, PartialEq, Show)]
+pub struct BlockLayout {
+    pub width: uint,
+    pub height: uint,
+    pub bytes: uint,
+}
+
+/// The blocks `format` stores texels in.
+pub fn block_layout(format: &PixelFormat) -> BlockLayout {
+    BlockLayout { width: 1, height: 1, bytes: format.bytes_per_pixel() }
+}
+
+/// The number of levels of a full mip chain of a texture of this size, down to 1x1x1.
+pub fn mip_level_count(width: uint, height: uint, depth: uint) -> uint {
+    let mut size = max(max(width, height), depth);
+    let mut levels = 1;
+    while size > 1 {
+        size /= 2;
+        levels += 1;
+    }
+    levels
+}
+
+/// The size of mip level `level` along an axis whose size at level `0` is `size`: halved, and
+/// rounded down, per level, but never less than one texel.
+pub fn mip_dimension(size: uint, level: uint) -> uint {
+    let mut size = size;
+    for _ in range(0, level) {
+        if size <= 1 {
+            break;
+        }
+        size /= 2;
+    }
+    max(size, 1)
+}
+
+/// The size, in texels, of mip level `level` of a texture created with `desc`. Only `Type3D`
+/// textures halve in depth.
+pub fn mip_size(desc: &TextureDescriptor, level: uint) -> Size {
+    Size {
+        width: mip_dimension(desc.width, level),
+        height: mip_dimension(desc.height, level),
+        depth: match desc.texture_type {
+            TextureType::Type3D => mip_dimension(desc.depth, level),
+            _ => 1,
+        },
+    }
+}
+
+/// The number of array slices of a texture created with `desc`: `array_length` for arrays,
+/// `CUBE_FACES` for cubes, and one otherwise.
+pub fn slice_count(desc: &TextureDescriptor) -> uint {
+    match desc.texture_type {
+        TextureType::Type2DArray => desc.array_length,
+        TextureType::TypeCube => CUBE_FACES,
+        _ => 1,
+    }
+}
+
+/// The index of mip level `level` of array slice `slice` among every level of every slice of a
+/// texture created with `desc`, slice major, for keeping state per subresource in a flat array
+/// of `subresource_count(desc)` entries.
+pub fn subresource_index(desc: &TextureDescriptor, level: uint, slice: uint) -> uint {
+    slice * desc.mipmap_level_count + level
+}
+
+/// The number of subresources (mip levels of array slices) of a texture created with `desc`.
+pub fn subresource_count(desc: &TextureDescriptor) -> uint {
+    slice_count(desc) * desc.mipmap_level_count
+}
+
+/// The number of rows of blocks of a region `height` texels high.
+pub fn block_rows(format: &PixelFormat, height: uint) -> uint {
+    let layout = block_layout(format);
+    (height + layout.height - 1) / layout.height
+}
+
+/// The number of bytes of a row of blocks of a region `width` texels wide, without padding.
+pub fn bytes_per_row(format: &PixelFormat, width: uint) -> uint {
+    let layout = block_layout(format);
+    (width + layout.width - 1) / layout.width * layout.bytes
+}
+
+/// `bytes_per_row`, rounded up to `COPY_ROW_ALIGNMENT`, as the rows of buffers copied to and
+/// from textures must be.
+pub fn aligned_bytes_per_row(format: &PixelFormat, width: uint) -> uint {
+    let row = bytes_per_row(format, width);
+    (row + COPY_ROW_ALIGNMENT - 1) / COPY_ROW_ALIGNMENT * COPY_ROW_ALIGNMENT
+}
+
+/// The number of bytes the data of a `size` region takes, laid out as `Device::write_texture`
+/// lays it out, with rows `bytes_per_row` apart: every row but the very last is padded to
+/// `bytes_per_row`, and the depth slices of 3D regions follow each other without padding.
+pub fn region_byte_size(format: &PixelFormat, size: &Size, bytes_per_row: uint) -> uint {
+    let rows = block_rows(format, size.height) * size.depth;
+    if rows == 0 || size.width == 0 {
+        return 0;
+    }
+    bytes_per_row * (rows - 1) + self::bytes_per_row(format, size.width)
+}
+
+/// The number of bytes of the texels of mip level `level` of one array slice of a texture
+/// created with `desc`, tightly packed.
+pub fn level_byte_size(desc: &TextureDescriptor, level: uint) -> uint {
+    let size = mip_size(desc, level);
+    bytes_per_row(&desc.pixel_format, size.width) * block_rows(&desc.pixel_format, size.height) * size.depth
+}
+
+/// The number of bytes of the texels of every level of every slice (and sample) of a texture
+/// created with `desc`, tightly packed. Backends round each level up to their own alignments,
+/// so this is a lower bound of the memory the texture takes.
+pub fn texture_byte_size(desc: &TextureDescriptor) -> uint {
+    let mut bytes = 0;
+    for level in range(0, desc.mipmap_level_count) {
+        bytes += level_byte_size(desc, level);
+    }
+    bytes * slice_count(desc) * desc.sample_count
+}
+
+/// Why a texture descriptor, view or region is invalid.
+#[deriving(Clone, PartialEq, Show)]
+pub enum TextureError {
+    /// The width, height, depth, mip level count, array length or sample count is zero.
+    ZeroSize,
+    /// More mip levels than the full chain of the texture's size (see `mip_level_count`).
+    TooManyMipLevels,
+    /// A texture other than `Type3D` with a depth other than `1`, or other than `Type2DArray`
+    /// with an array length other than `1`.
+    DimensionMismatch,
+    /// A cube texture whose faces are not square.
+    CubeNotSquare,
+    /// A multisampled texture which is not a `Type2D` render target of a single mip level.
+    InvalidMultisampling,
+    /// There is no mip level of this index.
+    LevelOutOfRange(uint),
+    /// There is no array slice of this index.
+    SliceOutOfRange(uint),
+    /// A view's format has a different texel size than the texture's.
+    ViewFormatMismatch,
+    /// The region extends past the edges of its mip level.
+    RegionOutOfBounds,
+    /// The region does not start, or end, on a block boundary (or the edge of its mip level).
+    UnalignedRegion,
+    /// `bytes_per_row` is less than a row of the region.
+    RowTooShort,
+    /// The data is shorter than the region needs. Holds the size it needs.
+    DataTooShort(uint),
+}
+
+/// Check that `desc` describes a texture which can exist.
+///
+/// Backends call this from `Device::create_texture`, which fails on an error.
+pub fn validate_descriptor(desc: &TextureDescriptor) -> Result<(), TextureError> {
+    if desc.width == 0 || desc.height == 0 || desc.depth == 0 || desc.mipmap_level_count == 0 ||
+       desc.array_length == 0 || desc.sample_count == 0 {
+        return Err(TextureError::ZeroSize);
+    }
+    let is_3d = match desc.texture_type { TextureType::Type3D => true, _ => false };
+    let is_array = match desc.texture_type { TextureType::Type2DArray => true, _ => false };
+    if (!is_3d && desc.depth != 1) || (!is_array && desc.array_length != 1) {
+        return Err(TextureError::DimensionMismatch);
+    }
+    if let TextureType::TypeCube = desc.texture_type {
+        if desc.width != desc.height {
+            return Err(TextureError::CubeNotSquare);
+        }
+    }
+    if desc.mipmap_level_count > mip_level_count(desc.width, desc.height, if is_3d { desc.depth } else { 1 }) {
+        return Err(TextureError::TooManyMipLevels);
+    }
+    if desc.sample_count > 1 {
+        let is_2d = match desc.texture_type { TextureType::Type2D => true, _ => false };
+        if !is_2d || !desc.usage.render_target || desc.mipmap_level_count != 1 {
+            return Err(TextureError::InvalidMultisampling);
+        }
+    }
+    Ok(())
+}
+
+/// Check that `view` selects mip levels and array slices which a texture created with `desc`
+/// has, in a format of the same texel size.
+///
+/// Backends call this from `Device::create_texture_view`, which fails on an error.
+pub fn validate_view(desc: &TextureDescriptor, view: &TextureViewDescriptor) -> Result<(), TextureError> {
+    if view.mipmap_level_count == 0 || view.array_length == 0 {
+        return Err(TextureError::ZeroSize);
+    }
+    if block_layout(&view.pixel_format) != block_layout(&desc.pixel_format) {
+        return Err(TextureError::ViewFormatMismatch);
+    }
+    let last_level = view.base_mipmap_level + view.mipmap_level_count - 1;
+    if last_level >= desc.mipmap_level_count {
+        return Err(TextureError::LevelOutOfRange(last_level));
+    }
+    let last_slice = view.base_array_slice + view.array_length - 1;
+    if last_slice >= slice_count(desc) {
+        return Err(TextureError::SliceOutOfRange(last_slice));
+    }
+    Ok(())
+}
+
+/// Check that the `size` region at `origin` of mip level `level` of array slice `slice` lies
+/// within a texture created with `desc`, on block boundaries.
+///
+/// Backends call this from `write_texture`, `read_texture` and the copies of
+/// `BlitCommandEncoder`, and fail (or panic, for encoded copies) on an error.
+pub fn validate_region(desc: &TextureDescriptor, level: uint, slice: uint, origin: &Origin, size: &Size)
+                       -> Result<(), TextureError> {
+    if level >= desc.mipmap_level_count {
+        return Err(TextureError::LevelOutOfRange(level));
+    }
+    if slice >= slice_count(desc) {
+        return Err(TextureError::SliceOutOfRange(slice));
+    }
+    let extent = mip_size(desc, level);
+    if origin.x + size.width > extent.width || origin.y + size.height > extent.height ||
+       origin.z + size.depth > extent.depth {
+        return Err(TextureError::RegionOutOfBounds);
+    }
+    // a region may end at the edge of a level whose size is not a whole number of blocks
+    let layout = block_layout(&desc.pixel_format);
+    let aligned = |start: uint, length: uint, edge: uint, block: uint| {
+        start % block == 0 && (length % block == 0 || start + length == edge)
+    };
+    if !aligned(origin.x, size.width, extent.width, layout.width) ||
+       !aligned(origin.y, size.height, extent.height, layout.height) {
+        return Err(TextureError::UnalignedRegion);
+    }
+    Ok(())
+}
+
+/// `validate_region`, also checking that `data_length` bytes, in rows `bytes_per_row` apart,
+/// hold the whole region.
+pub fn validate_data(desc: &TextureDescriptor, level: uint, slice: uint, origin: &Origin, size: &Size,
+                     data_length: uint, bytes_per_row: uint) -> Result<(), TextureError> {
+    try!(validate_region(desc, level, slice, origin, size));
+    if bytes_per_row < self::bytes_per_row(&desc.pixel_format, size.width) {
+        return Err(TextureError::RowTooShort);
+    }
+    let needed = region_byte_size(&desc.pixel_format, size, bytes_per_row);
+    if data_length < needed {
+        return Err(TextureError::DataTooShort(needed));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {PixelFormat, Size};
+    use super::{mip_dimension, mip_level_count, region_byte_size};
+
+    #[test]
+    fn mip_dimension_rounds_odd_sizes_down() {
+        assert_eq!(mip_dimension(7, 0), 7);
+        assert_eq!(mip_dimension(7, 1), 3);
+        assert_eq!(mip_dimension(7, 2), 1);
+        assert_eq!(mip_dimension(5, 1), 2);
+        assert_eq!(mip_dimension(3, 1), 1);
+    }
+
+    #[test]
+    fn mip_dimension_stops_at_one() {
+        assert_eq!(mip_dimension(7, 5), 1);
+        assert_eq!(mip_dimension(1, 3), 1);
+    }
+
+    #[test]
+    fn mip_level_count_of_odd_sizes() {
+        assert_eq!(mip_level_count(1, 1, 1), 1);
+        assert_eq!(mip_level_count(7, 1, 1), 3);
+        assert_eq!(mip_level_count(5, 3, 1), 3);
+        assert_eq!(mip_level_count(1, 1, 9), 4);
+        assert_eq!(mip_level_count(1024, 512, 1), 11);
+        assert_eq!(mip_level_count(1023, 1, 1), 10);
+    }
+
+    #[test]
+    fn region_byte_size_does_not_pad_the_last_row() {
+        let size = |width, height, depth| Size { width: width, height: height, depth: depth };
+        // 3 texels of 4 bytes per row
+        assert_eq!(region_byte_size(&PixelFormat::RGBA8Unorm, &size(3, 1, 1), 256), 12);
+        assert_eq!(region_byte_size(&PixelFormat::RGBA8Unorm, &size(3, 2, 1), 256), 256 + 12);
+        // the depth slices of 3D regions follow each other without padding
+        assert_eq!(region_byte_size(&PixelFormat::RGBA8Unorm, &size(3, 2, 2), 256), 256 * 3 + 12);
+        assert_eq!(region_byte_size(&PixelFormat::R8Unorm, &size(5, 4, 1), 5), 20);
+    }
+
+    #[test]
+    fn region_byte_size_of_empty_regions() {
+        let size = |width, height, depth| Size { width: width, height: height, depth: depth };
+        assert_eq!(region_byte_size(&PixelFormat::RGBA8Unorm, &size(0, 4, 1), 256), 0);
+        assert_eq!(region_byte_size(&PixelFormat::RGBA8Unorm, &size(4, 0, 1), 256), 0);
+    }
+}
@@ -0,0 +1,3 @@
+//! Helpers for working with textures, independent of any backend.
+
+pub mod math;
@@ -0,0 +1,208 @@
+//! Bind groups and pipelines whose layouts are types, so mismatches fail to compile.
+//!
+//! `set_bind_group` with a group of the wrong layout, or at the wrong index, is only caught by
+//! validation (if at all) when the draw is encoded. Here, a bind group layout is a type
+//! implementing `BindGroupLayoutType`, and a pipeline layout a type saying which layout it takes
+//! at which group index, declared with `pipeline_layout!`. Bind groups and pipelines carry their
+//! layout types, and binding through a `TypedRenderEncoder` only compiles if they agree:
+//!
+//! ```ignore
+//! struct FrameLayout;
+//! impl BindGroupLayoutType for FrameLayout {
+//!     fn entries() -> Vec<BindGroupLayoutEntry> { /* generated from shader reflection */ }
+//! }
+//! struct MaterialLayout;
+//! impl BindGroupLayoutType for MaterialLayout { .. }
+//!
+//! struct Forward;
+//! pipeline_layout!(Forward { Group0 => FrameLayout, Group1 => MaterialLayout });
+//!
+//! let frame_layout = try!(TypedBindGroupLayout::<D, FrameLayout>::new(&mut device));
+//! let frame = try!(frame_layout.create_bind_group(&mut device, entries, 0));
+//! let forward = TypedRenderPipeline::<D, Forward>::assume(pipeline);
+//!
+//! let mut encoder = forward.bind(&mut encoder);
+//! encoder.set_bind_group(&frame, Group0);
+//! encoder.set_bind_group(&frame, Group1); // error: `Forward` does not take a `FrameLayout` at `Group1`
+//! ```
+//!
+//! The layout types themselves are meant to be generated, from shader reflection or from the
+//! parameter blocks of the code which fills the bind groups; nothing checks that a pipeline's
+//! shaders agree with the layout type it is given, which is why `assume` is named as it is.
+
+use std::marker::PhantomData;
+
+use {Device, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindGroupDescriptor};
+use {ComputeCommandEncoder, RenderCommandEncoder};
+
+/// The layout of a bind group, as a type.
+pub trait BindGroupLayoutType {
+    /// The entries of the layout.
+    fn entries() -> Vec<BindGroupLayoutEntry>;
+}
+
+/// A bind group index, as a type.
+pub trait GroupIndex {
+    fn index(&self) -> uint;
+}
+
+macro_rules! group_indices {
+    ($($name:ident = $index:expr),*) => {
+        $(
+            /// A bind group index, as a type.
+            pub struct $name;
+
+            impl GroupIndex for $name {
+                fn index(&self) -> uint {
+                    $index
+                }
+            }
+        )*
+    }
+}
+
+group_indices!(Group0 = 0, Group1 = 1, Group2 = 2, Group3 = 3, Group4 = 4, Group5 = 5, Group6 = 6, Group7 = 7);
+
+/// Implemented by a pipeline layout type for every index it takes a bind group at, with the
+/// bind group's layout type. Implement it with `pipeline_layout!`.
+pub trait Binds<I: GroupIndex, L: BindGroupLayoutType> {}
+
+/// Declare which bind group layout type the pipeline layout type `$pipeline` takes at each
+/// group index.
+#[macro_export]
+macro_rules! pipeline_layout {
+    ($pipeline:ty { $($index:ty => $layout:ty),* }) => {
+        $(impl $crate::typed_bindings::Binds<$index, $layout> for $pipeline {})*
+    }
+}
+
+/// A bind group layout of the layout type `L`.
+pub struct TypedBindGroupLayout<D: Device, L> {
+    layout: D::BindGroupLayout,
+    marker: PhantomData<L>,
+}
+
+impl<D: Device, L: BindGroupLayoutType> TypedBindGroupLayout<D, L> {
+    /// Create the layout from `L::entries()`.
+    pub fn new(device: &mut D) -> Result<TypedBindGroupLayout<D, L>, D::BindGroupLayoutCreationError> {
+        let layout = try!(device.create_bind_group_layout(BindGroupLayoutDescriptor { entries: L::entries() }));
+        Ok(TypedBindGroupLayout { layout: layout, marker: PhantomData })
+    }
+
+    /// The untyped layout.
+    pub fn layout(&self) -> &D::BindGroupLayout {
+        &self.layout
+    }
+
+    /// Create a bind group of this layout, as `Device::create_bind_group` does.
+    pub fn create_bind_group(&self, device: &mut D, entries: Vec<BindGroupEntry<D>>,
+                             unsized_length: uint) -> Result<TypedBindGroup<D, L>, D::BindGroupCreationError> {
+        let group = try!(device.create_bind_group(BindGroupDescriptor {
+            layout: &self.layout,
+            entries: entries,
+            unsized_length: unsized_length,
+        }));
+        Ok(TypedBindGroup { group: group, marker: PhantomData })
+    }
+}
+
+/// A bind group of the layout type `L`.
+pub struct TypedBindGroup<D: Device, L> {
+    group: D::BindGroup,
+    marker: PhantomData<L>,
+}
+
+impl<D: Device, L> TypedBindGroup<D, L> {
+    /// The untyped bind group.
+    pub fn group(&self) -> &D::BindGroup {
+        &self.group
+    }
+}
+
+/// A render pipeline of the pipeline layout type `P`.
+pub struct TypedRenderPipeline<D: Device, P> {
+    pipeline: D::RenderPipeline,
+    marker: PhantomData<P>,
+}
+
+impl<D: Device, P> TypedRenderPipeline<D, P> {
+    /// Take `pipeline` to have the layout `P`, which nothing checks.
+    pub fn assume(pipeline: D::RenderPipeline) -> TypedRenderPipeline<D, P> {
+        TypedRenderPipeline { pipeline: pipeline, marker: PhantomData }
+    }
+
+    /// The untyped pipeline.
+    pub fn pipeline(&self) -> &D::RenderPipeline {
+        &self.pipeline
+    }
+
+    /// Set the pipeline on `encoder`, and return the encoder with the pipeline's layout type.
+    pub fn bind<'e, E: RenderCommandEncoder<D>>(&self, encoder: &'e mut E) -> TypedRenderEncoder<'e, D, E, P> {
+        encoder.set_render_pipeline(&self.pipeline);
+        TypedRenderEncoder { encoder: encoder, marker: PhantomData }
+    }
+}
+
+/// A render command encoder whose current pipeline has the layout type `P`.
+pub struct TypedRenderEncoder<'e, D: Device, E: 'e, P> {
+    encoder: &'e mut E,
+    marker: PhantomData<(D, P)>,
+}
+
+impl<'e, D: Device, E: RenderCommandEncoder<D>, P> TypedRenderEncoder<'e, D, E, P> {
+    /// Bind `group` at `index`, which only compiles if `P` takes a bind group of its layout
+    /// type there.
+    pub fn set_bind_group<I: GroupIndex, L: BindGroupLayoutType>(&mut self, group: &TypedBindGroup<D, L>, index: I)
+            where P: Binds<I, L> {
+        self.encoder.set_bind_group(&group.group, index.index());
+    }
+
+    /// The encoder, for everything else.
+    pub fn encoder(&mut self) -> &mut E {
+        &mut *self.encoder
+    }
+}
+
+/// A compute pipeline of the pipeline layout type `P`.
+pub struct TypedComputePipeline<D: Device, P> {
+    pipeline: D::ComputePipeline,
+    marker: PhantomData<P>,
+}
+
+impl<D: Device, P> TypedComputePipeline<D, P> {
+    /// Take `pipeline` to have the layout `P`, which nothing checks.
+    pub fn assume(pipeline: D::ComputePipeline) -> TypedComputePipeline<D, P> {
+        TypedComputePipeline { pipeline: pipeline, marker: PhantomData }
+    }
+
+    /// The untyped pipeline.
+    pub fn pipeline(&self) -> &D::ComputePipeline {
+        &self.pipeline
+    }
+
+    /// Set the pipeline on `encoder`, and return the encoder with the pipeline's layout type.
+    pub fn bind<'e, E: ComputeCommandEncoder<D>>(&self, encoder: &'e mut E) -> TypedComputeEncoder<'e, D, E, P> {
+        encoder.set_compute_pipeline(&self.pipeline);
+        TypedComputeEncoder { encoder: encoder, marker: PhantomData }
+    }
+}
+
+/// A compute command encoder whose current pipeline has the layout type `P`.
+pub struct TypedComputeEncoder<'e, D: Device, E: 'e, P> {
+    encoder: &'e mut E,
+    marker: PhantomData<(D, P)>,
+}
+
+impl<'e, D: Device, E: ComputeCommandEncoder<D>, P> TypedComputeEncoder<'e, D, E, P> {
+    /// Bind `group` at `index`, which only compiles if `P` takes a bind group of its layout
+    /// type there.
+    pub fn set_bind_group<I: GroupIndex, L: BindGroupLayoutType>(&mut self, group: &TypedBindGroup<D, L>, index: I)
+            where P: Binds<I, L> {
+        self.encoder.set_bind_group(&group.group, index.index());
+    }
+
+    /// The encoder, for everything else.
+    pub fn encoder(&mut self) -> &mut E {
+        &mut *self.encoder
+    }
+}
@@ -0,0 +1,112 @@
+//! Futures of GPU work, for asset pipelines and loaders written with async Rust.
+//!
+//! Every wait on the GPU the API offers has a callback form: completed handlers for command
+//! buffers (through `util::fence::Fence`), `Device::notify_event` for event values, and
+//! `CommandQueue::notify_when_available` for queue slots. The futures here are built on those
+//! callbacks, so awaiting them never ties up a thread, and they work with any executor:
+//!
+//! ```ignore
+//! let fence = Fence::new();
+//! commands.add_completed_handler(fence.completed_handler());
+//! commands.commit();
+//! match fence.future().await {
+//!     CommandBufferStatus::Completed => textures.publish(upload),
+//!     CommandBufferStatus::Failed(e) => return Err(e),
+//! }
+//!
+//! // the compute queue got far enough for the next stage to start
+//! event_reached(&device, &timeline, stage_value).await;
+//! ```
+//!
+//! With the `tokio_runtime` or `async_std_runtime` features, `tokio_runtime::timeout` and
+//! `async_std_runtime::timeout` bound any of these with the runtime's timer.
+
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::pin::Pin;
+use std::future::Future;
+
+use {CommandQueue, Device};
+
+struct State {
+    notified: bool,
+    wakers: Vec<Waker>,
+}
+
+/// A future of a callback being called. Returned by `event_reached` and
+/// `command_buffer_available`.
+pub struct Notified {
+    state: Arc<Mutex<State>>,
+}
+
+/// A future, and the callback which completes it.
+fn notification() -> (Box<FnOnce() + Send>, Notified) {
+    let state = Arc::new(Mutex::new(State { notified: false, wakers: Vec::new() }));
+    let signaled = state.clone();
+    let callback = box move || {
+        let mut state = signaled.lock();
+        state.notified = true;
+        for waker in state.wakers.drain() {
+            waker.wake();
+        }
+    };
+    (callback, Notified { state: state })
+}
+
+impl Future for Notified {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Notified>, context: &mut Context) -> Poll<()> {
+        let mut state = self.state.lock();
+        if state.notified {
+            return Poll::Ready(());
+        }
+        let waker = context.waker();
+        if !state.wakers.iter().any(|registered| registered.will_wake(waker)) {
+            state.wakers.push(waker.clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// A future of `event` reaching at least `value`, on whichever queue signals it.
+pub fn event_reached<D: Device>(device: &D, event: &D::Event, value: u64) -> Notified {
+    let (callback, notified) = notification();
+    device.notify_event(event, value, callback);
+    notified
+}
+
+/// A future of a command buffer becoming available from `queue`.
+///
+/// As for `CommandQueue::notify_when_available`, another thread may take the command buffer
+/// first, so `try_acquire_command_buffer` can still fail after this completes.
+pub fn command_buffer_available<D: Device>(queue: &D::CommandQueue) -> Notified {
+    let (callback, notified) = notification();
+    queue.notify_when_available(callback);
+    notified
+}
+
+/// Adapters for the `tokio` runtime.
+#[cfg(feature = "tokio_runtime")]
+pub mod tokio_runtime {
+    use std::future::Future;
+    use std::time::Duration;
+
+    /// `future`, or an error if it takes longer than `timeout_ms` milliseconds, timed by tokio.
+    pub fn timeout<F: Future>(future: F, timeout_ms: uint) -> ::tokio::time::Timeout<F> {
+        ::tokio::time::timeout(Duration::from_millis(timeout_ms as u64), future)
+    }
+}
+
+/// Adapters for the `async-std` runtime.
+#[cfg(feature = "async_std_runtime")]
+pub mod async_std_runtime {
+    use std::future::Future;
+    use std::time::Duration;
+
+    /// `future`, or an error if it takes longer than `timeout_ms` milliseconds, timed by
+    /// async-std.
+    pub fn timeout<F: Future>(future: F, timeout_ms: uint) -> ::async_std::future::TimeoutFuture<F> {
+        ::async_std::future::timeout(Duration::from_millis(timeout_ms as u64), future)
+    }
+}
@@ -0,0 +1,429 @@
+//! Packing many small images into one texture.
+//!
+//! The packing itself is done by a `RectAllocator`, of which there are two:
+//!
+//! * `ShelfAllocator` packs rectangles into rows ("shelves") of similar heights. It is fast and
+//!   works well for rectangles of few distinct heights, like the glyphs of a few font sizes.
+//! * `GuillotineAllocator` keeps a list of free rectangles, splitting them as rectangles are
+//!   allocated and merging them back as they are freed. It packs rectangles of arbitrary sizes
+//!   (sprites, lightmap charts) more tightly, and reuses freed space better, at a higher cost.
+//!
+//! `TextureAtlas` puts an allocator in charge of a texture, and grows the texture when the
+//! allocator runs out of room: the contents are copied into the larger texture with the blit
+//! encoder, so nothing has to be kept on the CPU to be uploaded again. Uploads go through the
+//! blit encoder too, so they are ordered with those copies.
+
+use {Device, BlitCommandEncoder, Origin, PixelFormat, Size, TextureDescriptor, TextureType, TextureUsage};
+use {BufferSlice, COPY_ROW_ALIGNMENT};
+use util::round_up;
+use util::transient_buffer::{TransientBuffer, TransientUsage};
+
+/// A rectangle of an atlas, in texels.
+#[deriving(Clone, PartialEq, Show)]
+pub struct AtlasRect {
+    pub x: uint,
+    pub y: uint,
+    pub width: uint,
+    pub height: uint,
+}
+
+/// Packs rectangles into an area which can grow.
+pub trait RectAllocator {
+    /// The size of the area.
+    fn size(&self) -> (uint, uint);
+
+    /// Find room for a `width` by `height` rectangle, or return `None` if there is none.
+    fn allocate(&mut self, width: uint, height: uint) -> Option<AtlasRect>;
+
+    /// Free a rectangle returned by `allocate`.
+    fn deallocate(&mut self, rect: AtlasRect);
+
+    /// Grow the area to `width` by `height`, which are at least the current size. Allocated
+    /// rectangles stay where they are.
+    fn grow(&mut self, width: uint, height: uint);
+}
+
+struct Shelf {
+    y: uint,
+    height: uint,
+    /// The first free column.
+    x: uint,
+    allocated: uint,
+}
+
+pub struct ShelfAllocator {
+    width: uint,
+    height: uint,
+    shelves: Vec<Shelf>,
+}
+
+impl ShelfAllocator {
+    pub fn new(width: uint, height: uint) -> ShelfAllocator {
+        ShelfAllocator { width: width, height: height, shelves: Vec::new() }
+    }
+}
+
+impl RectAllocator for ShelfAllocator {
+    fn size(&self) -> (uint, uint) {
+        (self.width, self.height)
+    }
+
+    fn allocate(&mut self, width: uint, height: uint) -> Option<AtlasRect> {
+        if width > self.width {
+            return None;
+        }
+        // the best fitting shelf with room, ignoring those much taller than needed so small
+        // rectangles do not waste the space of big ones.
+        let mut best: Option<uint> = None;
+        for (i, shelf) in self.shelves.iter().enumerate() {
+            if shelf.height >= height && shelf.height <= height + height / 4 + 1 && shelf.x + width <= self.width &&
+               best.map_or(true, |b| shelf.height < self.shelves[b].height) {
+                best = Some(i);
+            }
+        }
+        let i = match best {
+            Some(i) => i,
+            None => {
+                let top = self.shelves.last().map_or(0, |s| s.y + s.height);
+                if top + height <= self.height {
+                    self.shelves.push(Shelf { y: top, height: height, x: 0, allocated: 0 });
+                    self.shelves.len() - 1
+                } else {
+                    // with no room for a new shelf, the shortest empty one which is tall enough
+                    // takes the rectangle, however much taller it is
+                    match self.shelves.iter().enumerate()
+                              .filter(|&(_, s)| s.allocated == 0 && s.height >= height)
+                              .min_by(|&(_, s)| s.height)
+                              .map(|(i, _)| i) {
+                        Some(i) => i,
+                        None => return None,
+                    }
+                }
+            }
+        };
+
+        let shelf = &mut self.shelves[i];
+        let rect = AtlasRect { x: shelf.x, y: shelf.y, width: width, height: height };
+        shelf.x += width;
+        shelf.allocated += 1;
+        Some(rect)
+    }
+
+    fn deallocate(&mut self, rect: AtlasRect) {
+        let i = match self.shelves.iter().position(|s| s.y == rect.y) {
+            Some(i) => i,
+            None => return,
+        };
+        {
+            let shelf = &mut self.shelves[i];
+            shelf.allocated -= 1;
+            if shelf.allocated == 0 {
+                shelf.x = 0;
+            } else if rect.x + rect.width == shelf.x {
+                shelf.x = rect.x;
+            }
+        }
+        // empty shelves at the top give their height back
+        while self.shelves.last().map_or(false, |s| s.allocated == 0) {
+            self.shelves.pop();
+        }
+    }
+
+    fn grow(&mut self, width: uint, height: uint) {
+        self.width = width;
+        self.height = height;
+    }
+}
+
+pub struct GuillotineAllocator {
+    width: uint,
+    height: uint,
+    free: Vec<AtlasRect>,
+}
+
+impl GuillotineAllocator {
+    pub fn new(width: uint, height: uint) -> GuillotineAllocator {
+        GuillotineAllocator { width: width, height: height, free: vec![AtlasRect { x: 0, y: 0, width: width, height: height }] }
+    }
+
+    fn add_free(&mut self, rect: AtlasRect) {
+        if rect.width > 0 && rect.height > 0 {
+            self.free.push(rect);
+        }
+    }
+
+    /// Merge free rectangles which share a whole edge, until none do.
+    fn merge(&mut self) {
+        let mut merged = true;
+        while merged {
+            merged = false;
+            'search: for i in range(0, self.free.len()) {
+                for j in range(0, self.free.len()) {
+                    let (a, b) = (self.free[i].clone(), self.free[j].clone());
+                    let rect = if i == j {
+                        continue;
+                    } else if a.y == b.y && a.height == b.height && a.x + a.width == b.x {
+                        AtlasRect { x: a.x, y: a.y, width: a.width + b.width, height: a.height }
+                    } else if a.x == b.x && a.width == b.width && a.y + a.height == b.y {
+                        AtlasRect { x: a.x, y: a.y, width: a.width, height: a.height + b.height }
+                    } else {
+                        continue;
+                    };
+                    self.free[i] = rect;
+                    self.free.swap_remove(j);
+                    merged = true;
+                    break 'search;
+                }
+            }
+        }
+    }
+}
+
+impl RectAllocator for GuillotineAllocator {
+    fn size(&self) -> (uint, uint) {
+        (self.width, self.height)
+    }
+
+    fn allocate(&mut self, width: uint, height: uint) -> Option<AtlasRect> {
+        // best short side fit
+        let mut best: Option<(uint, uint)> = None;
+        for (i, free) in self.free.iter().enumerate() {
+            if free.width >= width && free.height >= height {
+                let fit = std::cmp::min(free.width - width, free.height - height);
+                if best.map_or(true, |(_, f)| fit < f) {
+                    best = Some((i, fit));
+                }
+            }
+        }
+        let free = match best {
+            Some((i, _)) => self.free.swap_remove(i).unwrap(),
+            None => return None,
+        };
+
+        // split along the shorter leftover axis, keeping the larger leftover in one piece
+        let (right, bottom) = (free.width - width, free.height - height);
+        if right < bottom {
+            self.add_free(AtlasRect { x: free.x + width, y: free.y, width: right, height: height });
+            self.add_free(AtlasRect { x: free.x, y: free.y + height, width: free.width, height: bottom });
+        } else {
+            self.add_free(AtlasRect { x: free.x + width, y: free.y, width: right, height: free.height });
+            self.add_free(AtlasRect { x: free.x, y: free.y + height, width: width, height: bottom });
+        }
+        Some(AtlasRect { x: free.x, y: free.y, width: width, height: height })
+    }
+
+    fn deallocate(&mut self, rect: AtlasRect) {
+        self.add_free(rect);
+        self.merge();
+    }
+
+    fn grow(&mut self, width: uint, height: uint) {
+        let (old_width, old_height) = (self.width, self.height);
+        self.add_free(AtlasRect { x: old_width, y: 0, width: width - old_width, height: old_height });
+        self.add_free(AtlasRect { x: 0, y: old_height, width: width, height: height - old_height });
+        self.width = width;
+        self.height = height;
+        self.merge();
+    }
+}
+
+/// Why a `TextureAtlas` operation failed.
+pub enum AtlasError<D: Device> {
+    /// The rectangle does not fit, even with the atlas at its maximum size.
+    Full,
+    /// This frame's staging buffer has no room left for the upload.
+    StagingFull,
+    /// Creating a larger texture failed.
+    Texture(D::TextureCreationError),
+}
+
+/// Why a `TextureAtlas` could not be created.
+pub enum AtlasCreationError<D: Device> {
+    Texture(D::TextureCreationError),
+    Buffer(D::BufferCreationError),
+}
+
+/// A texture whose space is handed out by a `RectAllocator`.
+pub struct TextureAtlas<D: Device, A: RectAllocator> {
+    allocator: A,
+    texture: D::Texture,
+    format: PixelFormat,
+    usage: TextureUsage,
+    max_size: (uint, uint),
+    padding: uint,
+    staging: TransientBuffer<D>,
+    /// Textures replaced by growing, with the number of frames left until the GPU is done with
+    /// them.
+    retired: Vec<(D::Texture, uint)>,
+    frames_in_flight: uint,
+    generation: u64,
+}
+
+/// Zero a rectangle of a texture no command buffer has used yet, as new textures are undefined.
+fn clear<D: Device>(device: &mut D, texture: &D::Texture, format: &PixelFormat, x: uint, y: uint, width: uint, height: uint) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let row = width * format.bytes_per_pixel();
+    device.write_texture(texture, 0, 0, Origin { x: x, y: y, z: 0 }, Size { width: width, height: height, depth: 1 },
+                         Vec::from_elem(row * height, 0u8).as_slice(), row);
+}
+
+fn create_texture<D: Device>(device: &mut D, format: PixelFormat, usage: &TextureUsage, width: uint, height: uint) -> Result<D::Texture, D::TextureCreationError> {
+    device.create_texture(TextureDescriptor {
+        texture_type: TextureType::Type2D,
+        pixel_format: format,
+        width: width,
+        height: height,
+        depth: 1,
+        mipmap_level_count: 1,
+        array_length: 1,
+        sample_count: 1,
+        usage: TextureUsage { shader_read: usage.shader_read, shader_write: usage.shader_write, render_target: usage.render_target },
+    })
+}
+
+impl<D: Device, A: RectAllocator> TextureAtlas<D, A> {
+    /// Create an atlas the size of `allocator`, which may grow up to `max_size`.
+    ///
+    /// Every rectangle is allocated with `padding` texels of space on its right and bottom, so
+    /// filtering does not bleed between neighbours. The texture starts out cleared, and `upload`
+    /// clears the padding of the rectangles it writes. Uploads are staged in a buffer of
+    /// `staging_size` bytes per frame; as for `DebugDraw`, `frames_in_flight` must be at least the
+    /// number of frames the CPU can get ahead of the GPU.
+    pub fn new(device: &mut D, allocator: A, format: PixelFormat, usage: TextureUsage, max_size: (uint, uint),
+               padding: uint, staging_size: uint, frames_in_flight: uint) -> Result<TextureAtlas<D, A>, AtlasCreationError<D>> {
+        let (width, height) = allocator.size();
+        let texture = match create_texture(device, format.clone(), &usage, width, height) {
+            Ok(texture) => texture,
+            Err(e) => return Err(AtlasCreationError::Texture(e)),
+        };
+        clear(device, &texture, &format, 0, 0, width, height);
+        let staging = match TransientBuffer::new(device, staging_size, frames_in_flight) {
+            Ok(staging) => staging,
+            Err(e) => return Err(AtlasCreationError::Buffer(e)),
+        };
+        Ok(TextureAtlas {
+            allocator: allocator,
+            texture: texture,
+            format: format,
+            usage: usage,
+            max_size: max_size,
+            padding: padding,
+            staging: staging,
+            retired: Vec::new(),
+            frames_in_flight: frames_in_flight,
+            generation: 0,
+        })
+    }
+
+    /// The atlas texture. This may be a different texture after any call to `allocate`.
+    pub fn texture(&self) -> &D::Texture {
+        &self.texture
+    }
+
+    pub fn size(&self) -> (uint, uint) {
+        self.allocator.size()
+    }
+
+    /// Changes whenever the atlas grows, and so whenever normalized coordinates from `uv` change.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The rectangle in normalized `u0`, `v0`, `u1`, `v1` coordinates, at the current size.
+    pub fn uv(&self, rect: &AtlasRect) -> [f32, ..4] {
+        let (width, height) = self.size();
+        let (width, height) = (width as f32, height as f32);
+        [rect.x as f32 / width, rect.y as f32 / height,
+         (rect.x + rect.width) as f32 / width, (rect.y + rect.height) as f32 / height]
+    }
+
+    /// Start a new frame.
+    pub fn begin_frame(&mut self) {
+        self.staging.begin_frame();
+        for &mut (_, ref mut frames) in self.retired.iter_mut() {
+            *frames -= 1;
+        }
+        self.retired.retain(|&(_, frames)| frames > 0);
+    }
+
+    /// Allocate a `width` by `height` rectangle, growing the atlas if needed.
+    ///
+    /// Growing encodes a copy of the old contents into `blit`, so the command buffer `blit`
+    /// belongs to must be committed before any work using the new texture.
+    pub fn allocate<E: BlitCommandEncoder<D>>(&mut self, device: &mut D, blit: &mut E, width: uint, height: uint) -> Result<AtlasRect, AtlasError<D>> {
+        enter_span!("TextureAtlas::allocate");
+        loop {
+            match self.allocator.allocate(width + self.padding, height + self.padding) {
+                Some(rect) => return Ok(AtlasRect { x: rect.x, y: rect.y, width: width, height: height }),
+                None => {}
+            }
+            if self.size() == self.max_size {
+                return Err(AtlasError::Full);
+            }
+            try!(self.grow(device, blit));
+        }
+    }
+
+    /// Free a rectangle returned by `allocate`.
+    pub fn deallocate(&mut self, rect: AtlasRect) {
+        self.allocator.deallocate(AtlasRect { x: rect.x, y: rect.y, width: rect.width + self.padding,
+                                              height: rect.height + self.padding });
+    }
+
+    /// Upload the contents of a rectangle, and clear its padding. `data` holds rows of
+    /// `bytes_per_row` bytes.
+    pub fn upload<E: BlitCommandEncoder<D>>(&mut self, device: &mut D, blit: &mut E, rect: &AtlasRect, data: &[u8],
+                                            bytes_per_row: uint) -> Result<(), AtlasError<D>> {
+        enter_span!("TextureAtlas::upload");
+        if rect.width == 0 || rect.height == 0 {
+            return Ok(());
+        }
+        // the padding stays zero in the staged copy, which covers it too
+        let (width, height) = (rect.width + self.padding, rect.height + self.padding);
+        let row = rect.width * self.format.bytes_per_pixel();
+        let pitch = round_up(width * self.format.bytes_per_pixel(), COPY_ROW_ALIGNMENT);
+        let mut staged = Vec::from_elem(pitch * height, 0u8);
+        for y in range(0, rect.height) {
+            let src = data.slice(y * bytes_per_row, y * bytes_per_row + row);
+            for (i, &b) in src.iter().enumerate() {
+                staged[y * pitch + i] = b;
+            }
+        }
+        let offset = match self.staging.allocate(device, staged.as_slice(), TransientUsage::CopySource) {
+            Some(offset) => offset,
+            None => return Err(AtlasError::StagingFull),
+        };
+        blit.copy_buffer_to_texture(BufferSlice::new(self.staging.buffer(), offset, staged.len()), pitch, &self.texture, 0, 0,
+                                    Origin { x: rect.x, y: rect.y, z: 0 },
+                                    Size { width: width, height: height, depth: 1 });
+        Ok(())
+    }
+
+    fn grow<E: BlitCommandEncoder<D>>(&mut self, device: &mut D, blit: &mut E) -> Result<(), AtlasError<D>> {
+        // double the shorter side, so the atlas stays roughly square
+        let (width, height) = self.size();
+        let (max_width, max_height) = self.max_size;
+        let (new_width, new_height) = if (width <= height && width < max_width) || height >= max_height {
+            (std::cmp::min(width * 2, max_width), height)
+        } else {
+            (width, std::cmp::min(height * 2, max_height))
+        };
+
+        let texture = match create_texture(device, self.format.clone(), &self.usage, new_width, new_height) {
+            Ok(texture) => texture,
+            Err(e) => return Err(AtlasError::Texture(e)),
+        };
+        // the copy only covers the old area, so the rest of the texture is cleared
+        clear(device, &texture, &self.format, width, 0, new_width - width, height);
+        clear(device, &texture, &self.format, 0, height, new_width, new_height - height);
+        blit.copy_texture(&self.texture, 0, 0, Origin { x: 0, y: 0, z: 0 }, Size { width: width, height: height, depth: 1 },
+                          &texture, 0, 0, Origin { x: 0, y: 0, z: 0 });
+        let old = std::mem::replace(&mut self.texture, texture);
+        self.retired.push((old, self.frames_in_flight + 1));
+        self.allocator.grow(new_width, new_height);
+        self.generation += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+//! Running background GPU work (asset baking, global illumination updates, texture compression)
+//! alongside rendering without spiking frame times.
+//!
+//! Background work goes on a queue of its own, created with `QueuePriority::Low` so that devices
+//! with the `queue_priority` feature run it behind the frame. GPUs which cannot preempt, and
+//! devices without the feature, still run a submitted command buffer to the end once they start
+//! it, so the work must also come in small command buffers, and not too many of them at once.
+//! `BackgroundThrottle` hands out command buffers from the background queue only while that
+//! holds:
+//!
+//! ```ignore
+//! let mut background = try!(device.create_command_queue_with_priority(None, QueuePriority::Low));
+//! let mut throttle = BackgroundThrottle::new(2, 1);
+//!
+//! // every frame
+//! throttle.begin_frame();
+//! while let Some(mut commands) = throttle.try_acquire(&mut background) {
+//!     match baker.next_chunk() {
+//!         Some(chunk) => {
+//!             encode_bake(&mut commands, chunk);
+//!             commands.commit();
+//!         }
+//!         None => break,
+//!     }
+//! }
+//! ```
+//!
+//! Each chunk should take a small fraction of a frame on the GPU; as for
+//! `util::dispatch_splitting`, time it to find a good size.
+
+use {Device, CommandBuffer, CommandQueue};
+use util::fence::Fence;
+
+/// Limits how much background work is submitted. See the module documentation.
+pub struct BackgroundThrottle {
+    max_in_flight: uint,
+    per_frame: uint,
+    acquired_this_frame: uint,
+    /// The fences of the command buffers handed out and not completed yet.
+    in_flight: Vec<Fence>,
+}
+
+impl BackgroundThrottle {
+    /// Allow up to `max_in_flight` uncompleted background command buffers, and at most
+    /// `per_frame` new ones each frame.
+    pub fn new(max_in_flight: uint, per_frame: uint) -> BackgroundThrottle {
+        BackgroundThrottle { max_in_flight: max_in_flight, per_frame: per_frame, acquired_this_frame: 0, in_flight: Vec::new() }
+    }
+
+    /// Start a new frame, forgetting the command buffers which have completed.
+    pub fn begin_frame(&mut self) {
+        self.acquired_this_frame = 0;
+        self.in_flight.retain(|fence| !fence.is_signaled());
+    }
+
+    /// A command buffer from `queue` for more background work, if the limits allow one.
+    ///
+    /// It counts as in flight until it completes, so it must be committed: a command buffer
+    /// dropped without being committed never completes, and would count forever.
+    pub fn try_acquire<D: Device, Q: CommandQueue<D>>(&mut self, queue: &mut Q) -> Option<D::CommandBuffer> {
+        if self.acquired_this_frame >= self.per_frame {
+            return None;
+        }
+        self.in_flight.retain(|fence| !fence.is_signaled());
+        if self.in_flight.len() >= self.max_in_flight {
+            return None;
+        }
+        let mut commands = match queue.try_acquire_command_buffer() {
+            Some(commands) => commands,
+            None => return None,
+        };
+        let fence = Fence::new();
+        commands.add_completed_handler(fence.completed_handler());
+        self.in_flight.push(fence);
+        self.acquired_this_frame += 1;
+        Some(commands)
+    }
+
+    /// The number of background command buffers handed out and not known to have completed.
+    pub fn in_flight(&self) -> uint {
+        self.in_flight.len()
+    }
+}
@@ -0,0 +1,184 @@
+//! Filtered, format converting copies between textures.
+//!
+//! `BlitCommandEncoder::copy_texture` only copies between textures of the same format and size.
+//! `Blitter` draws the source over the destination instead, so it can scale with filtering and
+//! convert between any two float or normalized formats (`RGBA16Float` to `BGRA8Unorm` for
+//! presenting an HDR image without tonemapping, say), at the cost of a render pass per blit.
+//!
+//! Both textures are usually views, selecting a single mip level and slice (see
+//! `Device::create_texture_view`). The source must be `shader_read`, the destination a
+//! `render_target`.
+
+use {Device, CommandBuffer, PixelFormat, PrimitiveType, RenderCommandEncoder, RenderPipelineDescriptor, Texture};
+use {ClearColor, LoadAction, RenderPassColorAttachment, RenderPassDescriptor, StoreAction};
+use util::as_bytes;
+
+/// How the source is sampled, when it is not the same size as the destination.
+pub enum BlitFilter {
+    /// The nearest texel. Exact for integer scale factors.
+    Nearest,
+    /// Bilinear filtering between the four nearest texels. Downscaling by more than a factor of
+    /// two skips texels, so large reductions should blit through intermediate sizes (or the
+    /// mip chain) instead.
+    Linear,
+}
+
+/// Why a blit could not be encoded.
+#[deriving(Show)]
+pub enum BlitError {
+    /// One of the views has an integer format, which can be neither filtered nor converted.
+    IntegerFormat,
+    /// One of the views has a depth format, which can only be copied with `copy_texture`.
+    DepthFormat,
+}
+
+#[repr(C)]
+struct BlitUniforms {
+    /// The part of the source read, as a fraction of its size.
+    region: [f32, ..2],
+    linear_filter: u32,
+    /// Undo the hardware's sRGB decode of the source.
+    encode_source: u32,
+    /// Undo the hardware's sRGB encode of the destination.
+    decode_destination: u32,
+}
+
+fn is_srgb(format: &PixelFormat) -> bool {
+    match *format {
+        PixelFormat::RGBA8UnormSrgb | PixelFormat::BGRA8UnormSrgb => true,
+        _ => false,
+    }
+}
+
+fn check(format: &PixelFormat) -> Result<(), BlitError> {
+    if format.is_integer() {
+        Err(BlitError::IntegerFormat)
+    } else if format.has_depth() {
+        Err(BlitError::DepthFormat)
+    } else {
+        Ok(())
+    }
+}
+
+/// The pipeline behind `blit_with_filter`.
+pub struct Blitter<D: Device> {
+    pipeline: D::RenderPipeline,
+}
+
+impl<D: Device> Blitter<D> {
+    /// `program` must be a shader program created from `SOURCE`.
+    pub fn new(device: &mut D, program: &D::ShaderProgram) -> Result<Blitter<D>, D::RenderPipelineCreationError> {
+        let pipeline = try!(device.create_render_pipeline(program, RenderPipelineDescriptor {
+            label: Some("blit".to_string()),
+            robustness: None,
+            vertex_function: "blit_vertex".to_string(),
+            fragment_function: Some("blit_fragment".to_string()),
+            vertex_descriptor: None,
+            blending: Vec::new(),
+            sample_count: 1,
+            attachment_formats: None,
+        }));
+        Ok(Blitter { pipeline: pipeline })
+    }
+
+    /// Encode a render pass in `commands` which replaces all of `dst` with `src`, scaled to fit.
+    ///
+    /// With `color_space_aware`, colors are converted: an sRGB source is decoded to linear, and
+    /// an sRGB destination encoded from linear, the way sampling and rendering always do. Without
+    /// it, the stored values are copied as they are, so sRGB encoded bytes stay sRGB encoded bytes
+    /// whatever the formats say, which is what moving images between `Srgb` and plain `Unorm`
+    /// textures usually wants. Values out of the destination's range are clamped.
+    pub fn blit_with_filter(&self, commands: &mut D::CommandBuffer, src: &D::Texture, dst: &D::Texture,
+                            filter: BlitFilter, color_space_aware: bool) -> Result<(), BlitError> {
+        self.blit_region(commands, src, [1.0, 1.0], dst, filter, color_space_aware)
+    }
+
+    /// As `blit_with_filter`, but only the top left `region` of `src` is scaled over `dst`, as a
+    /// fraction of its width and height. Filtering never reads outside of the region.
+    pub fn blit_region(&self, commands: &mut D::CommandBuffer, src: &D::Texture, region: [f32, ..2],
+                       dst: &D::Texture, filter: BlitFilter, color_space_aware: bool) -> Result<(), BlitError> {
+        enter_span!("Blitter::blit_region");
+        let (src_format, dst_format) = (src.pixel_format(), dst.pixel_format());
+        try!(check(&src_format));
+        try!(check(&dst_format));
+        let uniforms = BlitUniforms {
+            region: region,
+            linear_filter: match filter { BlitFilter::Nearest => 0, BlitFilter::Linear => 1 },
+            encode_source: (!color_space_aware && is_srgb(&src_format)) as u32,
+            decode_destination: (!color_space_aware && is_srgb(&dst_format)) as u32,
+        };
+        let mut encoder = commands.render_command_encoder(RenderPassDescriptor {
+            color_attachments: vec![RenderPassColorAttachment {
+                texture: dst, mipmap_level: 0, slice: 0,
+                // every pixel is drawn over
+                load_action: LoadAction::DontCare, store_action: StoreAction::Store,
+                clear_color: ClearColor::Float { red: 0.0, green: 0.0, blue: 0.0, alpha: 0.0 },
+            }],
+            depth_attachment: None,
+            stencil_attachment: None,
+            sample_positions: None,
+            visibility_result_buffer: None,
+        });
+        encoder.set_render_pipeline(&self.pipeline);
+        encoder.set_fragment_texture(src, 0);
+        encoder.set_fragment_bytes(as_bytes(&uniforms), 0);
+        encoder.draw(PrimitiveType::Triangle, 0, 3, 1, 0);
+        encoder.end_encoding();
+        Ok(())
+    }
+}
+
+/// The metal shading language source of `Blitter`.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct BlitUniforms {
+    float2 region;
+    uint linear_filter;
+    uint encode_source;
+    uint decode_destination;
+};
+
+struct BlitVertex {
+    float4 position [[position]];
+    float2 uv;
+};
+
+vertex BlitVertex blit_vertex(uint vid [[vertex_id]])
+{
+    float2 uv = float2((vid << 1) & 2, vid & 2);
+    BlitVertex out;
+    out.position = float4(uv.x * 2.0f - 1.0f, 1.0f - uv.y * 2.0f, 0.0f, 1.0f);
+    out.uv = uv;
+    return out;
+}
+
+static float3 linear_to_srgb(float3 c) {
+    c = saturate(c);
+    return select(1.055f * pow(c, 1.0f / 2.4f) - 0.055f, c * 12.92f, c <= 0.0031308f);
+}
+
+static float3 srgb_to_linear(float3 c) {
+    return select(pow((c + 0.055f) / 1.055f, 2.4f), c / 12.92f, c <= 0.04045f);
+}
+
+fragment float4 blit_fragment(BlitVertex in [[stage_in]],
+                              texture2d<float> source [[texture(0)]],
+                              constant BlitUniforms &uniforms [[buffer(0)]])
+{
+    constexpr sampler nearest(filter::nearest, address::clamp_to_edge);
+    constexpr sampler linear(filter::linear, address::clamp_to_edge);
+    // keep the filter footprint within the region
+    float2 half_texel = 0.5f / float2(source.get_width(), source.get_height());
+    float2 uv = min(in.uv * uniforms.region, uniforms.region - half_texel);
+    float4 color = uniforms.linear_filter ? source.sample(linear, uv) : source.sample(nearest, uv);
+    if (uniforms.encode_source) {
+        color.rgb = linear_to_srgb(color.rgb);
+    }
+    if (uniforms.decode_destination) {
+        color.rgb = srgb_to_linear(saturate(color.rgb));
+    }
+    return color;
+}
+"#;
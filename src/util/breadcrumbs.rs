@@ -0,0 +1,173 @@
+//! Breadcrumbs: progress markers the GPU writes between passes, for finding out where it hung.
+//!
+//! When the GPU hangs, the command buffer fails with `DeviceLost` or `Timeout` and nothing says
+//! which of its passes never finished. With breadcrumbs, every marked point writes its slot of a
+//! buffer once everything encoded before it has finished executing, so after the hang the first
+//! slot left unwritten is the pass that never completed:
+//!
+//! ```ignore
+//! let mut breadcrumbs = try!(Breadcrumbs::new(&mut device, 64, 3));
+//!
+//! // every frame
+//! breadcrumbs.begin_frame(&mut commands);
+//! encode_shadows(&mut commands);
+//! breadcrumbs.mark(&mut commands, "shadows");
+//! encode_gbuffer(&mut commands);
+//! breadcrumbs.mark(&mut commands, "gbuffer");
+//!
+//! // once a command buffer fails with DeviceLost or Timeout
+//! for frame in breadcrumbs.report(&mut device).iter() {
+//!     println!("{}", frame); // frame 1204: shadows done, gbuffer NOT REACHED
+//! }
+//! ```
+//!
+//! Devices with the `timestamp_writes` feature write the GPU clock, so the report also tells how
+//! long each pass took; others fill the slot. Marks are what costs: each one waits for the
+//! work before it to finish, so breadcrumbs are opt-in, for builds chasing hangs rather than for
+//! every frame of a release. Reading the buffer after a hang works as far as the backend can
+//! still read buffers of a lost device, which it can for the host visible memory buffers read
+//! with `read_buffer` live in on every backend so far.
+
+use std::fmt;
+
+use {Device, BlitCommandEncoder, BufferSlice, CommandBuffer};
+
+/// The size of a slot: a GPU timestamp.
+const SLOT_SIZE: uint = 8;
+
+/// A marked point of a frame, as the GPU left it.
+#[deriving(Clone, Show)]
+pub struct Crumb {
+    pub label: String,
+    /// Whether everything encoded before the mark finished executing.
+    pub reached: bool,
+    /// When it did, in GPU ticks, on devices with `timestamp_writes`.
+    pub gpu_ticks: Option<u64>,
+}
+
+/// The crumbs of one frame. Prints as one line, the crumbs in order.
+#[deriving(Clone)]
+pub struct FrameCrumbs {
+    /// The number of frames begun before this one.
+    pub frame: u64,
+    pub crumbs: Vec<Crumb>,
+}
+
+impl FrameCrumbs {
+    /// The first crumb the GPU did not reach: the work encoded between the crumb before it (or
+    /// the start of the frame) and this one is where it hung.
+    pub fn first_unreached(&self) -> Option<&Crumb> {
+        self.crumbs.iter().find(|crumb| !crumb.reached)
+    }
+}
+
+impl fmt::Show for FrameCrumbs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "frame {}:", self.frame));
+        for (index, crumb) in self.crumbs.iter().enumerate() {
+            let separator = if index == 0 { " " } else { ", " };
+            try!(write!(f, "{}{} {}", separator, crumb.label, if crumb.reached { "done" } else { "NOT REACHED" }));
+        }
+        Ok(())
+    }
+}
+
+struct FrameLabels {
+    frame: u64,
+    labels: Vec<String>,
+}
+
+/// The breadcrumb buffer, and the labels of the frames it holds. See the module documentation.
+pub struct Breadcrumbs<D: Device> {
+    buffer: D::Buffer,
+    /// The number of slots of each frame.
+    capacity: uint,
+    timestamps: bool,
+    /// The labels of the frames the buffer holds, by the frame's part of the buffer.
+    frames: Vec<Option<FrameLabels>>,
+    /// The part of the buffer of the current frame.
+    current: uint,
+    next_frame: u64,
+}
+
+impl<D: Device> Breadcrumbs<D> {
+    /// Make room for `capacity` crumbs in each of the last `frames` frames, which should be how
+    /// many frames can be in flight: a hang can be in any of them. Later crumbs of a frame which
+    /// marks more are ignored.
+    pub fn new(device: &mut D, capacity: uint, frames: uint) -> Result<Breadcrumbs<D>, D::BufferCreationError> {
+        let buffer = try!(device.create_buffer(capacity * frames * SLOT_SIZE, None));
+        Ok(Breadcrumbs {
+            buffer: buffer,
+            capacity: capacity,
+            timestamps: device.features().timestamp_writes,
+            frames: range(0, frames).map(|_| None).collect(),
+            current: frames - 1,
+            next_frame: 0,
+        })
+    }
+
+    /// Start a new frame, in `commands`, the first command buffer it commits. This clears the
+    /// part of the buffer of the frame `frames` frames ago, which must not be in flight anymore.
+    pub fn begin_frame<C: CommandBuffer<D>>(&mut self, commands: &mut C) {
+        self.current = (self.current + 1) % self.frames.len();
+        self.frames[self.current] = Some(FrameLabels { frame: self.next_frame, labels: Vec::new() });
+        self.next_frame += 1;
+        let mut encoder = commands.blit_command_encoder();
+        encoder.fill_buffer(self.slots(self.current, 0, self.capacity), 0);
+        encoder.end_encoding();
+    }
+
+    /// Mark that the GPU got past everything encoded into `commands` so far. No encoder may be
+    /// open.
+    pub fn mark<C: CommandBuffer<D>>(&mut self, commands: &mut C, label: &str) {
+        let slot = match self.frames[self.current] {
+            Some(ref mut frame) if frame.labels.len() < self.capacity => {
+                frame.labels.push(label.to_string());
+                frame.labels.len() - 1
+            }
+            _ => return,
+        };
+        let offset = (self.current * self.capacity + slot) * SLOT_SIZE;
+        if self.timestamps {
+            commands.write_timestamp(&self.buffer, offset);
+        } else {
+            let mut encoder = commands.blit_command_encoder();
+            encoder.fill_buffer(BufferSlice::new(&self.buffer, offset, SLOT_SIZE), 0xff);
+            encoder.end_encoding();
+        }
+    }
+
+    /// What the GPU left in the buffer: the crumbs of each frame it holds, oldest first. Call
+    /// this after a hang, once the failed command buffer has completed.
+    pub fn report(&self, device: &mut D) -> Vec<FrameCrumbs> {
+        let mut data = Vec::from_elem(self.capacity * self.frames.len() * SLOT_SIZE, 0u8);
+        device.read_buffer(&self.buffer, 0, data.as_mut_slice());
+        let mut report = Vec::new();
+        for offset in range(1, self.frames.len() + 1) {
+            let part = (self.current + offset) % self.frames.len();
+            let frame = match self.frames[part] {
+                Some(ref frame) => frame,
+                None => continue,
+            };
+            let crumbs = frame.labels.iter().enumerate().map(|(slot, label)| {
+                let start = (part * self.capacity + slot) * SLOT_SIZE;
+                let mut value = 0u64;
+                for (index, &byte) in data.slice(start, start + SLOT_SIZE).iter().enumerate() {
+                    value |= (byte as u64) << (index * 8);
+                }
+                Crumb {
+                    label: label.clone(),
+                    reached: value != 0,
+                    gpu_ticks: if self.timestamps && value != 0 { Some(value) } else { None },
+                }
+            }).collect();
+            report.push(FrameCrumbs { frame: frame.frame, crumbs: crumbs });
+        }
+        report
+    }
+
+    /// The slots `first..first + count` of the frame whose part of the buffer is `part`.
+    fn slots(&self, part: uint, first: uint, count: uint) -> BufferSlice<D::Buffer> {
+        BufferSlice::new(&self.buffer, (part * self.capacity + first) * SLOT_SIZE, count * SLOT_SIZE)
+    }
+}
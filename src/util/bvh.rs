@@ -0,0 +1,431 @@
+//! Bounding volume hierarchies of triangle meshes, built and traversed in compute shaders, for
+//! ray traced effects (shadows, ambient occlusion, reflections) in software.
+//!
+//! `BvhBuilder` builds a linear BVH (Karras, "Maximizing Parallelism in the Construction of BVHs,
+//! Octrees, and k-d Trees", 2012) from the vertex and index buffers the mesh is drawn with, in a
+//! handful of dispatches, fast enough to rebuild every frame for animated meshes:
+//!
+//! 1. every triangle gets the Morton code of its centroid, within the bounds given;
+//! 2. the triangles are sorted by code, with `util::radix_sort`;
+//! 3. every internal node finds the range of triangles it covers and where to split it, at once;
+//! 4. bounds are propagated up from the leaves, each node by the last of its children to finish.
+//!
+//! ```ignore
+//! let sort = try!(RadixSort::new(&mut device, sort_pipelines, scan, triangles));
+//! let builder = try!(BvhBuilder::new(&mut device, pipelines, sort, triangles));
+//! let mut bvh = try!(Bvh::new(&mut device, triangles));
+//! builder.build(&mut compute, &mut bvh, &positions, &indices, triangle_count, (scene_min, scene_max));
+//!
+//! // and in shaders, with TRAVERSAL_HEADER:
+//! // if (bvh_intersect(nodes, positions, indices, ray, false, hit)) { .. }
+//! ```
+//!
+//! Positions are three packed floats (12 bytes) per vertex, and indices `u32`s, three per
+//! triangle. The tree is as good as Morton order makes it, which is worse than what a CPU builder
+//! with the surface area heuristic manages, but builds orders of magnitude faster.
+//!
+//! The crate does not expose hardware ray tracing, so this is the path on every device for now.
+//! Shaders which only trace through `bvh_intersect` and `bvh_occluded` keep one code path when it
+//! does: a hardware version of the same two functions can take their place.
+
+use {Device, BufferSlice, ComputeCommandEncoder, Size};
+use util::as_bytes;
+use util::radix_sort::RadixSort;
+
+/// The number of threads in each threadgroup of the build.
+const THREADS_PER_THREADGROUP: uint = 64;
+
+/// The size of a node, as `BvhNode` in `TRAVERSAL_HEADER` lays it out.
+pub const NODE_SIZE: uint = 32;
+
+/// The pipelines a `BvhBuilder` dispatches, created from the `bvh_morton`, `bvh_hierarchy` and
+/// `bvh_bounds` functions of `SOURCE`.
+pub struct BvhPipelines<D: Device> {
+    pub morton: D::ComputePipeline,
+    pub hierarchy: D::ComputePipeline,
+    pub bounds: D::ComputePipeline,
+}
+
+#[repr(C)]
+struct BvhUniforms {
+    scene_min: [f32, ..3],
+    count: u32,
+    /// One over the extent of the bounds, or zero along axes where it is empty.
+    scene_scale: [f32, ..3],
+    _padding: u32,
+}
+
+/// A BVH of up to `capacity` triangles: `2 * n - 1` nodes for `n` triangles, the `n - 1`
+/// internal nodes first, the root at index `0`, then the leaves.
+pub struct Bvh<D: Device> {
+    nodes: D::Buffer,
+    triangle_count: uint,
+    capacity: uint,
+}
+
+impl<D: Device> Bvh<D> {
+    pub fn new(device: &mut D, capacity: uint) -> Result<Bvh<D>, D::BufferCreationError> {
+        let nodes = try!(device.create_buffer(std::cmp::max(2 * capacity, 2) * NODE_SIZE, None));
+        Ok(Bvh { nodes: nodes, triangle_count: 0, capacity: capacity })
+    }
+
+    /// The nodes, to bind for `bvh_intersect` and `bvh_occluded`.
+    pub fn nodes(&self) -> &D::Buffer {
+        &self.nodes
+    }
+
+    /// The number of triangles of the last build.
+    pub fn triangle_count(&self) -> uint {
+        self.triangle_count
+    }
+
+    pub fn capacity(&self) -> uint {
+        self.capacity
+    }
+}
+
+/// The pipelines and scratch buffers of BVH builds of up to `capacity` triangles.
+pub struct BvhBuilder<D: Device> {
+    pipelines: BvhPipelines<D>,
+    sort: RadixSort<D>,
+    /// The Morton code of every triangle, and the triangles in Morton order.
+    keys: D::Buffer,
+    triangles: D::Buffer,
+    /// The parent of every node, and for every internal node the number of its children whose
+    /// bounds are done.
+    parents: D::Buffer,
+    flags: D::Buffer,
+    capacity: uint,
+}
+
+impl<D: Device> BvhBuilder<D> {
+    /// `sort` must have a capacity of at least `capacity`.
+    pub fn new(device: &mut D, pipelines: BvhPipelines<D>, sort: RadixSort<D>, capacity: uint)
+               -> Result<BvhBuilder<D>, D::BufferCreationError> {
+        assert!(sort.capacity() >= capacity, "the sort of a BVH of {} triangles needs a capacity of as many keys", capacity);
+        let keys = try!(device.create_buffer(capacity * 4, None));
+        let triangles = try!(device.create_buffer(capacity * 4, None));
+        let parents = try!(device.create_buffer(std::cmp::max(2 * capacity, 2) * 4, None));
+        let flags = try!(device.create_buffer(std::cmp::max(capacity, 1) * 4, None));
+        Ok(BvhBuilder {
+            pipelines: pipelines,
+            sort: sort,
+            keys: keys,
+            triangles: triangles,
+            parents: parents,
+            flags: flags,
+            capacity: capacity,
+        })
+    }
+
+    /// Build `bvh` over the first `triangle_count` triangles of `indices` into `positions`.
+    ///
+    /// `bounds` (the minimum and maximum corners) should contain every triangle's centroid, and
+    /// be about as tight as possible: triangles are ordered by where their centroid lies in
+    /// them, clamped to their edges.
+    pub fn build<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, bvh: &mut Bvh<D>, positions: &D::Buffer, indices: &D::Buffer,
+                                              triangle_count: uint, bounds: ([f32, ..3], [f32, ..3])) {
+        enter_span!("BvhBuilder::build", triangles = triangle_count);
+        assert!(triangle_count <= self.capacity && triangle_count <= bvh.capacity,
+                "a BVH of {} triangles exceeds the capacity of the builder or the BVH", triangle_count);
+        bvh.triangle_count = triangle_count;
+        if triangle_count == 0 {
+            return;
+        }
+        let (min, max) = bounds;
+        let scale = |axis: uint| if max[axis] > min[axis] { 1.0 / (max[axis] - min[axis]) } else { 0.0 };
+        let uniforms = BvhUniforms {
+            scene_min: min,
+            count: triangle_count as u32,
+            scene_scale: [scale(0), scale(1), scale(2)],
+            _padding: 0,
+        };
+        let groups = |count: uint| Size { width: (count + THREADS_PER_THREADGROUP - 1) / THREADS_PER_THREADGROUP, height: 1, depth: 1 };
+        let threads = || Size { width: THREADS_PER_THREADGROUP, height: 1, depth: 1 };
+
+        encoder.set_compute_pipeline(&self.pipelines.morton);
+        encoder.set_buffer(BufferSlice::whole(positions), 0);
+        encoder.set_buffer(BufferSlice::whole(indices), 1);
+        encoder.set_buffer(BufferSlice::whole(&self.keys), 2);
+        encoder.set_buffer(BufferSlice::whole(&self.triangles), 3);
+        encoder.set_bytes(as_bytes(&uniforms), 4);
+        encoder.dispatch_threadgroups(groups(triangle_count), threads());
+
+        // Morton codes of three 10 bit coordinates
+        self.sort.sort_pairs(encoder, &self.keys, &self.triangles, triangle_count, 32);
+
+        encoder.set_compute_pipeline(&self.pipelines.hierarchy);
+        encoder.set_buffer(BufferSlice::whole(positions), 0);
+        encoder.set_buffer(BufferSlice::whole(indices), 1);
+        encoder.set_buffer(BufferSlice::whole(&self.keys), 2);
+        encoder.set_buffer(BufferSlice::whole(&self.triangles), 3);
+        encoder.set_buffer(BufferSlice::whole(&bvh.nodes), 4);
+        encoder.set_buffer(BufferSlice::whole(&self.parents), 5);
+        encoder.set_buffer(BufferSlice::whole(&self.flags), 6);
+        encoder.set_bytes(as_bytes(&uniforms), 7);
+        encoder.dispatch_threadgroups(groups(triangle_count), threads());
+
+        encoder.set_compute_pipeline(&self.pipelines.bounds);
+        encoder.set_buffer(BufferSlice::whole(&bvh.nodes), 0);
+        encoder.set_buffer(BufferSlice::whole(&self.parents), 1);
+        encoder.set_buffer(BufferSlice::whole(&self.flags), 2);
+        encoder.set_bytes(as_bytes(&uniforms), 3);
+        encoder.dispatch_threadgroups(groups(triangle_count), threads());
+    }
+}
+
+/// Metal shading language declarations of the BVH layout, and the functions tracing rays
+/// through it, to prepend to the source of shaders which do (and of `SOURCE`).
+///
+/// `bvh_intersect` finds the closest hit along the ray (or, with `any_hit`, any hit), and
+/// `bvh_occluded` whether anything is hit at all, for shadow rays.
+pub const TRAVERSAL_HEADER: &'static str = r#"
+#include <metal_stdlib>
+
+struct BvhNode {
+    packed_float3 min;
+    // for internal nodes the children; for leaves the triangle, and -1
+    int left;
+    packed_float3 max;
+    int right;
+};
+
+struct BvhRay {
+    metal::float3 origin;
+    float tmin;
+    metal::float3 direction;
+    float tmax;
+};
+
+struct BvhHit {
+    float t;
+    uint triangle;
+    metal::float2 barycentrics;
+};
+
+#define BVH_STACK_SIZE 64
+
+static inline bool bvh_ray_box(metal::float3 origin, metal::float3 inverse_direction, float tmin, float tmax,
+                               metal::float3 lo, metal::float3 hi) {
+    metal::float3 t0 = (lo - origin) * inverse_direction;
+    metal::float3 t1 = (hi - origin) * inverse_direction;
+    metal::float3 near = metal::min(t0, t1);
+    metal::float3 far = metal::max(t0, t1);
+    float enter = metal::max(metal::max(near.x, near.y), metal::max(near.z, tmin));
+    float exit = metal::min(metal::min(far.x, far.y), metal::min(far.z, tmax));
+    return enter <= exit;
+}
+
+// Moller-Trumbore
+static inline bool bvh_ray_triangle(BvhRay ray, metal::float3 a, metal::float3 b, metal::float3 c,
+                                    thread float &t, thread metal::float2 &barycentrics) {
+    metal::float3 ab = b - a;
+    metal::float3 ac = c - a;
+    metal::float3 p = metal::cross(ray.direction, ac);
+    float determinant = metal::dot(ab, p);
+    if (metal::abs(determinant) < 1e-12f) {
+        return false;
+    }
+    float inverse = 1.0f / determinant;
+    metal::float3 s = ray.origin - a;
+    float u = metal::dot(s, p) * inverse;
+    metal::float3 q = metal::cross(s, ab);
+    float v = metal::dot(ray.direction, q) * inverse;
+    if (u < 0.0f || v < 0.0f || u + v > 1.0f) {
+        return false;
+    }
+    t = metal::dot(ac, q) * inverse;
+    barycentrics = metal::float2(u, v);
+    return t >= ray.tmin && t <= ray.tmax;
+}
+
+static bool bvh_intersect(const device BvhNode *nodes, const device packed_float3 *positions,
+                          const device uint *indices, BvhRay ray, bool any_hit, thread BvhHit &hit) {
+    metal::float3 inverse_direction = 1.0f / ray.direction;
+    int stack[BVH_STACK_SIZE];
+    int depth = 0;
+    stack[depth++] = 0;
+    bool found = false;
+    while (depth > 0) {
+        BvhNode node = nodes[stack[--depth]];
+        if (!bvh_ray_box(ray.origin, inverse_direction, ray.tmin, ray.tmax, node.min, node.max)) {
+            continue;
+        }
+        if (node.right < 0) {
+            uint triangle = uint(node.left);
+            float t;
+            metal::float2 barycentrics;
+            if (bvh_ray_triangle(ray, positions[indices[3 * triangle]], positions[indices[3 * triangle + 1]],
+                                 positions[indices[3 * triangle + 2]], t, barycentrics)) {
+                hit.t = t;
+                hit.triangle = triangle;
+                hit.barycentrics = barycentrics;
+                ray.tmax = t;
+                found = true;
+                if (any_hit) {
+                    return true;
+                }
+            }
+        } else if (depth + 2 <= BVH_STACK_SIZE) {
+            stack[depth++] = node.right;
+            stack[depth++] = node.left;
+        }
+    }
+    return found;
+}
+
+static inline bool bvh_occluded(const device BvhNode *nodes, const device packed_float3 *positions,
+                                const device uint *indices, BvhRay ray) {
+    BvhHit hit;
+    return bvh_intersect(nodes, positions, indices, ray, true, hit);
+}
+"#;
+
+/// Metal shading language source of the build kernels, to compile after `TRAVERSAL_HEADER`.
+pub const SOURCE: &'static str = r#"
+using namespace metal;
+
+struct BvhUniforms {
+    packed_float3 scene_min;
+    uint count;
+    packed_float3 scene_scale;
+    uint padding;
+};
+
+static uint expand_bits(uint v) {
+    v = (v * 0x00010001u) & 0xff0000ffu;
+    v = (v * 0x00000101u) & 0x0f00f00fu;
+    v = (v * 0x00000011u) & 0xc30c30c3u;
+    v = (v * 0x00000005u) & 0x49249249u;
+    return v;
+}
+
+static float3 triangle_vertex(const device packed_float3 *positions, const device uint *indices, uint triangle, uint corner) {
+    return float3(positions[indices[3 * triangle + corner]]);
+}
+
+kernel void bvh_morton(const device packed_float3 *positions [[buffer(0)]],
+                       const device uint *indices [[buffer(1)]],
+                       device uint *keys [[buffer(2)]],
+                       device uint *triangles [[buffer(3)]],
+                       constant BvhUniforms &u [[buffer(4)]],
+                       uint id [[thread_position_in_grid]]) {
+    if (id >= u.count) {
+        return;
+    }
+    float3 centroid = (triangle_vertex(positions, indices, id, 0) + triangle_vertex(positions, indices, id, 1) +
+                       triangle_vertex(positions, indices, id, 2)) / 3.0f;
+    float3 p = clamp((centroid - float3(u.scene_min)) * float3(u.scene_scale) * 1024.0f, 0.0f, 1023.0f);
+    keys[id] = expand_bits(uint(p.x)) * 4 + expand_bits(uint(p.y)) * 2 + expand_bits(uint(p.z));
+    triangles[id] = id;
+}
+
+// the length of the common prefix of the keys at i and j, with the indices breaking ties between
+// equal keys, or -1 past the ends
+static int common_prefix(const device uint *keys, int count, int i, int j) {
+    if (j < 0 || j >= count) {
+        return -1;
+    }
+    uint a = keys[i];
+    uint b = keys[j];
+    if (a == b) {
+        return 32 + int(clz(uint(i) ^ uint(j)));
+    }
+    return int(clz(a ^ b));
+}
+
+kernel void bvh_hierarchy(const device packed_float3 *positions [[buffer(0)]],
+                          const device uint *indices [[buffer(1)]],
+                          const device uint *keys [[buffer(2)]],
+                          const device uint *triangles [[buffer(3)]],
+                          device BvhNode *nodes [[buffer(4)]],
+                          device int *parents [[buffer(5)]],
+                          device atomic_uint *flags [[buffer(6)]],
+                          constant BvhUniforms &u [[buffer(7)]],
+                          uint id [[thread_position_in_grid]]) {
+    int n = int(u.count);
+    int i = int(id);
+    if (i >= n) {
+        return;
+    }
+    if (i == 0) {
+        parents[0] = -1;
+    }
+
+    // the leaf of the ith triangle in order
+    uint triangle = triangles[i];
+    float3 a = triangle_vertex(positions, indices, triangle, 0);
+    float3 b = triangle_vertex(positions, indices, triangle, 1);
+    float3 c = triangle_vertex(positions, indices, triangle, 2);
+    BvhNode leaf;
+    leaf.min = min(min(a, b), c);
+    leaf.max = max(max(a, b), c);
+    leaf.left = int(triangle);
+    leaf.right = -1;
+    nodes[n - 1 + i] = leaf;
+
+    if (i >= n - 1) {
+        return;
+    }
+    atomic_store_explicit(&flags[i], 0, memory_order_relaxed);
+
+    // the direction of the range of the internal node, and its other end
+    int d = common_prefix(keys, n, i, i + 1) - common_prefix(keys, n, i, i - 1) >= 0 ? 1 : -1;
+    int minimum = common_prefix(keys, n, i, i - d);
+    int length_max = 2;
+    while (common_prefix(keys, n, i, i + length_max * d) > minimum) {
+        length_max *= 2;
+    }
+    int length = 0;
+    for (int t = length_max / 2; t >= 1; t /= 2) {
+        if (common_prefix(keys, n, i, i + (length + t) * d) > minimum) {
+            length += t;
+        }
+    }
+    int j = i + length * d;
+
+    // where the range splits: the last key sharing more than the whole range's prefix with i
+    int prefix = common_prefix(keys, n, i, j);
+    int split = 0;
+    int t = length;
+    do {
+        t = (t + 1) / 2;
+        if (common_prefix(keys, n, i, i + (split + t) * d) > prefix) {
+            split += t;
+        }
+    } while (t > 1);
+    int gamma = i + split * d + min(d, 0);
+
+    int left = min(i, j) == gamma ? n - 1 + gamma : gamma;
+    int right = max(i, j) == gamma + 1 ? n - 1 + gamma + 1 : gamma + 1;
+    nodes[i].left = left;
+    nodes[i].right = right;
+    parents[left] = i;
+    parents[right] = i;
+}
+
+kernel void bvh_bounds(device BvhNode *nodes [[buffer(0)]],
+                       const device int *parents [[buffer(1)]],
+                       device atomic_uint *flags [[buffer(2)]],
+                       constant BvhUniforms &u [[buffer(3)]],
+                       uint id [[thread_position_in_grid]]) {
+    int n = int(u.count);
+    if (int(id) >= n) {
+        return;
+    }
+    int parent = parents[n - 1 + int(id)];
+    while (parent >= 0) {
+        // the first child to arrive leaves the node to the second, whose sibling is then done
+        atomic_thread_fence(mem_flags::mem_device, memory_order_seq_cst, thread_scope_device);
+        if (atomic_fetch_add_explicit(&flags[parent], 1, memory_order_relaxed) == 0) {
+            return;
+        }
+        atomic_thread_fence(mem_flags::mem_device, memory_order_seq_cst, thread_scope_device);
+        BvhNode left = nodes[nodes[parent].left];
+        BvhNode right = nodes[nodes[parent].right];
+        nodes[parent].min = min(float3(left.min), float3(right.min));
+        nodes[parent].max = max(float3(left.max), float3(right.max));
+        parent = parents[parent];
+    }
+}
+"#;
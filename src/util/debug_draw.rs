@@ -0,0 +1,328 @@
+//! Immediate mode debug drawing.
+//!
+//! `DebugDraw` collects lines, wire boxes and text labels from anywhere (it is `Sync`, so share
+//! it with an `Arc`), and draws everything collected since the last flush with a single draw
+//! call:
+//!
+//! ```ignore
+//! debug.line([0.0, 0.0, 0.0], [0.0, 1.0, 0.0], RED);
+//! debug.wire_box(bounds.min, bounds.max, GREEN);
+//! debug.text(enemy.position, "ANGRY", WHITE, 16.0);
+//! // ... once per frame, inside the final render pass ...
+//! debug.flush(&mut device, &mut encoder, &view_projection, (width, height));
+//! ```
+//!
+//! Text is drawn with a small built-in stroke font, always facing the camera and a fixed number
+//! of pixels tall. It covers digits, latin letters (lowercase is drawn as uppercase) and some
+//! punctuation; anything else is drawn as a box.
+
+use std::sync::Mutex;
+
+use {Device, BlendDescriptor, BufferSlice, RenderCommandEncoder, RenderPipelineDescriptor, PrimitiveType};
+use {VertexAttribute, VertexBufferLayout, VertexDescriptor, VertexFormat, VertexStepFunction};
+use util::{as_bytes, slice_as_bytes};
+
+/// A color, as `0xAABBGGRR` (so that it is `r, g, b, a` in memory on little endian machines).
+pub type Color = u32;
+
+pub const WHITE: Color = 0xffffffff;
+pub const RED: Color = 0xff0000ff;
+pub const GREEN: Color = 0xff00ff00;
+pub const BLUE: Color = 0xffff0000;
+pub const YELLOW: Color = 0xff00ffff;
+
+#[repr(C)]
+#[deriving(Clone)]
+struct DebugVertex {
+    /// The world space position. For text, this is the label's anchor.
+    position: [f32, ..3],
+    /// An offset from `position`, in pixels. Zero for everything but text.
+    offset: [f32, ..2],
+    color: Color,
+}
+
+#[repr(C)]
+struct DebugUniforms {
+    view_projection: [f32, ..16],
+    viewport_size: [f32, ..2],
+    _padding: [f32, ..2],
+}
+
+struct Batch {
+    vertices: Vec<DebugVertex>,
+    dropped: uint,
+}
+
+/// Collects debug geometry and draws it once per frame.
+pub struct DebugDraw<D: Device> {
+    pipeline: D::RenderPipeline,
+    /// One vertex buffer per frame in flight, used in rotation.
+    buffers: Vec<D::Buffer>,
+    next_buffer: Mutex<uint>,
+    capacity: uint,
+    batch: Mutex<Batch>,
+}
+
+impl<D: Device> DebugDraw<D> {
+    /// Create a debug drawer which draws up to `max_lines` lines per frame.
+    ///
+    /// `program` must be a shader program created from `SOURCE`. `frames_in_flight` must be at
+    /// least the number of frames the CPU can get ahead of the GPU (the capacity of the command
+    /// queue), as each flush writes into the buffer of the frame before it.
+    pub fn new(device: &mut D, program: &D::ShaderProgram, max_lines: uint, frames_in_flight: uint) -> Result<DebugDraw<D>, DebugDrawCreationError<D>> {
+        let vertex_size = std::mem::size_of::<DebugVertex>();
+        let desc = RenderPipelineDescriptor {
+            label: Some("debug_draw".to_string()),
+            robustness: None,
+            vertex_function: "debug_draw_vertex".to_string(),
+            fragment_function: Some("debug_draw_fragment".to_string()),
+            vertex_descriptor: Some(VertexDescriptor {
+                attributes: vec![
+                    VertexAttribute { format: VertexFormat::Float3, offset: 0, buffer_index: 0 },
+                    VertexAttribute { format: VertexFormat::Float2, offset: 12, buffer_index: 0 },
+                    VertexAttribute { format: VertexFormat::UChar4Normalized, offset: 20, buffer_index: 0 },
+                ],
+                layouts: vec![
+                    VertexBufferLayout { stride: vertex_size, step_function: VertexStepFunction::PerVertex, step_rate: 1 },
+                ],
+            }),
+            blending: vec![Some(BlendDescriptor::alpha())],
+            sample_count: 1,
+            attachment_formats: None,
+        };
+        let pipeline = match device.create_render_pipeline(program, desc) {
+            Ok(pipeline) => pipeline,
+            Err(e) => return Err(DebugDrawCreationError::Pipeline(e)),
+        };
+
+        let capacity = max_lines * 2;
+        let mut buffers = Vec::with_capacity(frames_in_flight);
+        for _ in range(0, frames_in_flight) {
+            match device.create_buffer(capacity * vertex_size, None) {
+                Ok(buffer) => buffers.push(buffer),
+                Err(e) => return Err(DebugDrawCreationError::Buffer(e)),
+            }
+        }
+
+        Ok(DebugDraw {
+            pipeline: pipeline,
+            buffers: buffers,
+            next_buffer: Mutex::new(0),
+            capacity: capacity,
+            batch: Mutex::new(Batch { vertices: Vec::with_capacity(capacity), dropped: 0 }),
+        })
+    }
+
+    fn push(&self, vertices: &[DebugVertex]) {
+        let mut batch = self.batch.lock();
+        for pair in vertices.chunks(2) {
+            if batch.vertices.len() + 2 > self.capacity {
+                batch.dropped += 1;
+            } else {
+                batch.vertices.push_all(pair);
+            }
+        }
+    }
+
+    /// Draw a line from `a` to `b`, in world space.
+    pub fn line(&self, a: [f32, ..3], b: [f32, ..3], color: Color) {
+        self.push(&[
+            DebugVertex { position: a, offset: [0.0, 0.0], color: color },
+            DebugVertex { position: b, offset: [0.0, 0.0], color: color },
+        ]);
+    }
+
+    /// Draw the edges of the axis aligned box from `min` to `max`, in world space.
+    pub fn wire_box(&self, min: [f32, ..3], max: [f32, ..3], color: Color) {
+        let corner = |i: uint| {
+            [if i & 1 == 0 { min[0] } else { max[0] },
+             if i & 2 == 0 { min[1] } else { max[1] },
+             if i & 4 == 0 { min[2] } else { max[2] }]
+        };
+        for i in range(0, 8u) {
+            // connect each corner to the neighbours with one more bit set, so every edge is drawn
+            // exactly once.
+            for bit in [1u, 2, 4].iter() {
+                if i & *bit == 0 {
+                    self.line(corner(i), corner(i | *bit), color);
+                }
+            }
+        }
+    }
+
+    /// Draw a text label at `position`, in world space.
+    ///
+    /// The text faces the camera, starts at `position` and is `height` pixels tall no matter how
+    /// far away it is. `\n` starts a new line.
+    pub fn text(&self, position: [f32, ..3], text: &str, color: Color, height: f32) {
+        // the font's cells are 4x6 units with 2 units of spacing, and 3 more between lines.
+        let scale = height / 6.0;
+        let (mut x, mut y) = (0.0f32, 0.0f32);
+        let mut vertices = Vec::new();
+        for c in text.chars() {
+            if c == '\n' {
+                x = 0.0;
+                y -= 9.0 * scale;
+                continue;
+            }
+            for stroke in glyph(c).split('|') {
+                let points: Vec<(f32, f32)> = stroke.split(' ').filter_map(parse_point).collect();
+                for w in points.as_slice().windows(2) {
+                    let ((ax, ay), (bx, by)) = (w[0], w[1]);
+                    vertices.push(DebugVertex { position: position, offset: [x + ax * scale, y + ay * scale], color: color });
+                    vertices.push(DebugVertex { position: position, offset: [x + bx * scale, y + by * scale], color: color });
+                }
+            }
+            x += 6.0 * scale;
+        }
+        self.push(vertices.as_slice());
+    }
+
+    /// Draw everything collected since the last flush, and start collecting anew.
+    ///
+    /// `view_projection` is the column major world to clip transform, and `viewport_size` the
+    /// size in pixels of the attachment being drawn to.
+    ///
+    /// Returns the number of lines that were dropped since the last flush because there was no
+    /// room for them.
+    pub fn flush<E: RenderCommandEncoder<D>>(&self, device: &mut D, encoder: &mut E,
+                                             view_projection: &[f32, ..16], viewport_size: (uint, uint)) -> uint {
+        enter_span!("DebugDraw::flush");
+        let (vertices, dropped) = {
+            let mut batch = self.batch.lock();
+            let vertices = std::mem::replace(&mut batch.vertices, Vec::with_capacity(self.capacity));
+            let dropped = batch.dropped;
+            batch.dropped = 0;
+            (vertices, dropped)
+        };
+        if vertices.is_empty() {
+            return dropped;
+        }
+
+        let buffer = {
+            let mut next = self.next_buffer.lock();
+            let buffer = &self.buffers[*next];
+            *next = (*next + 1) % self.buffers.len();
+            buffer
+        };
+        device.write_buffer(buffer, 0, slice_as_bytes(vertices.as_slice()));
+
+        let uniforms = DebugUniforms {
+            view_projection: *view_projection,
+            viewport_size: [viewport_size.0 as f32, viewport_size.1 as f32],
+            _padding: [0.0, 0.0],
+        };
+        encoder.set_render_pipeline(&self.pipeline);
+        encoder.set_vertex_buffer(BufferSlice::whole(buffer), 0);
+        encoder.set_vertex_bytes(as_bytes(&uniforms), 1);
+        encoder.draw(PrimitiveType::Line, 0, vertices.len(), 1, 0);
+        dropped
+    }
+}
+
+/// Why a `DebugDraw` could not be created.
+pub enum DebugDrawCreationError<D: Device> {
+    Pipeline(D::RenderPipelineCreationError),
+    Buffer(D::BufferCreationError),
+}
+
+fn parse_point(s: &str) -> Option<(f32, f32)> {
+    let mut parts = s.split(',');
+    match (parts.next().and_then(|x| from_str(x)), parts.next().and_then(|y| from_str(y))) {
+        (Some(x), Some(y)) => Some((x, y)),
+        _ => None,
+    }
+}
+
+/// The strokes of a character, as `|` separated polylines of `x,y` points on a 4x6 grid with the
+/// origin at the bottom left.
+fn glyph(c: char) -> &'static str {
+    match c.to_uppercase() {
+        ' ' => "",
+        '0' => "0,0 4,0 4,6 0,6 0,0|0,0 4,6",
+        '1' => "1,5 2,6 2,0|1,0 3,0",
+        '2' => "0,6 4,6 4,3 0,3 0,0 4,0",
+        '3' => "0,6 4,6 4,0 0,0|1,3 4,3",
+        '4' => "0,6 0,3 4,3|4,6 4,0",
+        '5' | 'S' => "4,6 0,6 0,3 4,3 4,0 0,0",
+        '6' => "4,6 0,6 0,0 4,0 4,3 0,3",
+        '7' => "0,6 4,6 1,0",
+        '8' => "0,0 4,0 4,6 0,6 0,0|0,3 4,3",
+        '9' => "4,3 0,3 0,6 4,6 4,0 0,0",
+        'A' => "0,0 0,4 2,6 4,4 4,0|0,3 4,3",
+        'B' => "0,0 0,6 3,6 4,5 4,4 3,3 0,3|3,3 4,2 4,1 3,0 0,0",
+        'C' => "4,6 0,6 0,0 4,0",
+        'D' => "0,0 0,6 2,6 4,4 4,2 2,0 0,0",
+        'E' => "4,6 0,6 0,0 4,0|0,3 3,3",
+        'F' => "4,6 0,6 0,0|0,3 3,3",
+        'G' => "4,6 0,6 0,0 4,0 4,3 2,3",
+        'H' => "0,6 0,0|4,6 4,0|0,3 4,3",
+        'I' => "1,6 3,6|2,6 2,0|1,0 3,0",
+        'J' => "4,6 4,0 0,0 0,2",
+        'K' => "0,6 0,0|4,6 0,3 4,0",
+        'L' => "0,6 0,0 4,0",
+        'M' => "0,0 0,6 2,3 4,6 4,0",
+        'N' => "0,0 0,6 4,0 4,6",
+        'O' => "0,0 4,0 4,6 0,6 0,0",
+        'P' => "0,0 0,6 4,6 4,3 0,3",
+        'Q' => "0,0 4,0 4,6 0,6 0,0|2,2 4,0",
+        'R' => "0,0 0,6 4,6 4,3 0,3 4,0",
+        'T' => "0,6 4,6|2,6 2,0",
+        'U' => "0,6 0,0 4,0 4,6",
+        'V' => "0,6 2,0 4,6",
+        'W' => "0,6 1,0 2,3 3,0 4,6",
+        'X' => "0,0 4,6|0,6 4,0",
+        'Y' => "0,6 2,3 4,6|2,3 2,0",
+        'Z' => "0,6 4,6 0,0 4,0",
+        '-' => "1,3 3,3",
+        '+' => "1,3 3,3|2,2 2,4",
+        '=' => "1,2 3,2|1,4 3,4",
+        '.' => "2,0 2,1",
+        ',' => "2,1 1,-1",
+        ':' => "2,1 2,2|2,4 2,5",
+        '/' => "0,0 4,6",
+        '_' => "0,0 4,0",
+        '(' => "3,6 1,4 1,2 3,0",
+        ')' => "1,6 3,4 3,2 1,0",
+        '%' => "0,0 4,6|0,6 0,5|4,0 4,1",
+        _ => "0,0 4,0 4,6 0,6 0,0",
+    }
+}
+
+/// The metal shading language source of the debug drawing shaders.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct DebugVertex {
+    float3 position [[attribute(0)]];
+    float2 offset [[attribute(1)]];
+    float4 color [[attribute(2)]];
+};
+
+struct DebugUniforms {
+    float4x4 view_projection;
+    float2 viewport_size;
+};
+
+struct DebugFragment {
+    float4 position [[position]];
+    float4 color;
+};
+
+vertex DebugFragment debug_draw_vertex(DebugVertex in [[stage_in]],
+                                       constant DebugUniforms &u [[buffer(1)]])
+{
+    DebugFragment out;
+    out.position = u.view_projection * float4(in.position, 1.0f);
+    // pixel offsets are applied after projection so that text stays the same size on screen
+    out.position.xy += in.offset * 2.0f / u.viewport_size * out.position.w;
+    out.color = in.color;
+    return out;
+}
+
+fragment float4 debug_draw_fragment(DebugFragment in [[stage_in]])
+{
+    return in.color;
+}
+"#;
@@ -0,0 +1,223 @@
+//! Hierarchical depth ("Hi-Z") pyramid generation.
+//!
+//! A depth pyramid is a single channel float texture whose every texel holds the minimum (or
+//! maximum) of the depth texels it covers. Level 0 is half the size of the depth attachment,
+//! rounded down, and every following level is half the size of the one before it, down to 1x1.
+//!
+//! The fiddly part is odd sizes: halving a level with an odd width (or height) rounds down, so
+//! the last column of the destination covers *three* source columns instead of two. The kernels
+//! here account for that, so no depth texel is ever left out of the pyramid and conservative tests
+//! against it stay conservative.
+
+use {Device, ComputeCommandEncoder, PixelFormat, Size, TextureDescriptor, TextureType};
+use {TextureUsage, TextureViewDescriptor};
+use texture::math::{mip_dimension, mip_level_count};
+use util::as_bytes;
+
+/// The width and height of each reduction threadgroup.
+const THREADGROUP_SIZE: uint = 8;
+
+/// How depth values are combined into a pyramid texel.
+pub enum DepthReduction {
+    /// Keep the smallest depth. With reversed depth, this is the farthest depth, which is what
+    /// occlusion culling needs.
+    Min,
+    /// Keep the largest depth.
+    Max,
+}
+
+#[repr(C)]
+struct ReduceUniforms {
+    src_size: [u32, ..2],
+    dst_size: [u32, ..2],
+    reduction: u32,
+}
+
+/// A depth pyramid for a depth attachment of a fixed size.
+pub struct DepthPyramid<D: Device> {
+    first: D::ComputePipeline,
+    downsample: D::ComputePipeline,
+    texture: D::Texture,
+    /// A single level view of each level of `texture`, for writing.
+    levels: Vec<D::Texture>,
+    width: uint,
+    height: uint,
+    depth_width: uint,
+    depth_height: uint,
+    reduction: DepthReduction,
+}
+
+impl<D: Device> DepthPyramid<D> {
+    /// Create a pyramid for a `depth_width` by `depth_height` depth attachment.
+    ///
+    /// `first` must be a compute pipeline created from the `depth_pyramid_first` function of
+    /// `SOURCE`, and `downsample` one created from `depth_pyramid_downsample`. The depth
+    /// attachment must be at least 2x2. If it is resized, a new pyramid is needed.
+    pub fn new(device: &mut D, first: D::ComputePipeline, downsample: D::ComputePipeline,
+               depth_width: uint, depth_height: uint, reduction: DepthReduction) -> Result<DepthPyramid<D>, D::TextureCreationError> {
+        let width = std::cmp::max(1, depth_width / 2);
+        let height = std::cmp::max(1, depth_height / 2);
+        let level_count = mip_level_count(width, height, 1);
+
+        let texture = try!(device.create_texture(TextureDescriptor {
+            texture_type: TextureType::Type2D,
+            pixel_format: PixelFormat::R32Float,
+            width: width,
+            height: height,
+            depth: 1,
+            mipmap_level_count: level_count,
+            array_length: 1,
+            sample_count: 1,
+            usage: TextureUsage { shader_read: true, shader_write: true, render_target: false },
+        }));
+
+        let mut levels = Vec::with_capacity(level_count);
+        for level in range(0, level_count) {
+            levels.push(try!(device.create_texture_view(&texture, TextureViewDescriptor {
+                pixel_format: PixelFormat::R32Float,
+                base_mipmap_level: level,
+                mipmap_level_count: 1,
+                base_array_slice: 0,
+                array_length: 1,
+            })));
+        }
+
+        Ok(DepthPyramid {
+            first: first,
+            downsample: downsample,
+            texture: texture,
+            levels: levels,
+            width: width,
+            height: height,
+            depth_width: depth_width,
+            depth_height: depth_height,
+            reduction: reduction,
+        })
+    }
+
+    /// The pyramid texture, with every level.
+    pub fn texture(&self) -> &D::Texture {
+        &self.texture
+    }
+
+    /// The size of level 0 of the pyramid, in texels.
+    pub fn size(&self) -> (uint, uint) {
+        (self.width, self.height)
+    }
+
+    /// The number of levels in the pyramid.
+    pub fn level_count(&self) -> uint {
+        self.levels.len()
+    }
+
+    /// Build the whole pyramid from `depth`.
+    ///
+    /// `depth` must be the size this pyramid was created for, and must have been created with the
+    /// `shader_read` usage.
+    pub fn encode<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, depth: &D::Texture) {
+        enter_span!("depth_pyramid::encode");
+        let reduction = match self.reduction { DepthReduction::Min => 0, DepthReduction::Max => 1 };
+        let (mut src_width, mut src_height) = (self.depth_width, self.depth_height);
+
+        for (level, dst) in self.levels.iter().enumerate() {
+            let dst_width = mip_dimension(self.width, level);
+            let dst_height = mip_dimension(self.height, level);
+
+            if level == 0 {
+                encoder.set_compute_pipeline(&self.first);
+                encoder.set_texture(depth, 0);
+            } else {
+                if level == 1 {
+                    encoder.set_compute_pipeline(&self.downsample);
+                }
+                encoder.set_texture(&self.levels[level - 1], 0);
+            }
+            encoder.set_texture(dst, 1);
+
+            let uniforms = ReduceUniforms {
+                src_size: [src_width as u32, src_height as u32],
+                dst_size: [dst_width as u32, dst_height as u32],
+                reduction: reduction,
+            };
+            encoder.set_bytes(as_bytes(&uniforms), 0);
+            encoder.dispatch_threadgroups(Size { width: (dst_width + THREADGROUP_SIZE - 1) / THREADGROUP_SIZE,
+                                                 height: (dst_height + THREADGROUP_SIZE - 1) / THREADGROUP_SIZE,
+                                                 depth: 1 },
+                                          Size { width: THREADGROUP_SIZE, height: THREADGROUP_SIZE, depth: 1 });
+
+            src_width = dst_width;
+            src_height = dst_height;
+        }
+    }
+}
+
+/// The metal shading language source of the reduction kernels.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct ReduceUniforms {
+    uint2 src_size;
+    uint2 dst_size;
+    uint reduction;
+};
+
+static float combine(float a, float b, uint reduction) {
+    return reduction == 0 ? min(a, b) : max(a, b);
+}
+
+// reads one texel of either source: depth textures read as a scalar, the pyramid's levels as the
+// red channel of a float4.
+static float read_texel(depth2d<float, access::read> src, uint2 p) {
+    return src.read(p);
+}
+
+static float read_texel(texture2d<float, access::read> src, uint2 p) {
+    return src.read(p).x;
+}
+
+// reduces the 2x2 footprint of `dst`, widened to 3 texels along any axis where the source size
+// is odd and `dst` is the last texel, so that the rounded-down destination still covers it all.
+template <typename T>
+static float reduce(T src, uint2 dst, constant ReduceUniforms &u) {
+    uint2 base = dst * 2;
+    uint2 extent = uint2(2, 2);
+    if (dst.x == u.dst_size.x - 1 && (u.src_size.x & 1) != 0 && u.src_size.x > 1) {
+        extent.x = 3;
+    }
+    if (dst.y == u.dst_size.y - 1 && (u.src_size.y & 1) != 0 && u.src_size.y > 1) {
+        extent.y = 3;
+    }
+
+    float value = u.reduction == 0 ? INFINITY : -INFINITY;
+    for (uint y = 0; y < extent.y; y++) {
+        for (uint x = 0; x < extent.x; x++) {
+            uint2 p = min(base + uint2(x, y), u.src_size - 1);
+            value = combine(value, read_texel(src, p), u.reduction);
+        }
+    }
+    return value;
+}
+
+kernel void depth_pyramid_first(depth2d<float, access::read> src [[texture(0)]],
+                                texture2d<float, access::write> dst [[texture(1)]],
+                                constant ReduceUniforms &u [[buffer(0)]],
+                                uint2 id [[thread_position_in_grid]])
+{
+    if (any(id >= u.dst_size)) {
+        return;
+    }
+    dst.write(float4(reduce(src, id, u)), id);
+}
+
+kernel void depth_pyramid_downsample(texture2d<float, access::read> src [[texture(0)]],
+                                     texture2d<float, access::write> dst [[texture(1)]],
+                                     constant ReduceUniforms &u [[buffer(0)]],
+                                     uint2 id [[thread_position_in_grid]])
+{
+    if (any(id >= u.dst_size)) {
+        return;
+    }
+    dst.write(float4(reduce(src, id, u)), id);
+}
+"#;
@@ -0,0 +1,99 @@
+//! Splitting long compute workloads into pieces that stay under the GPU watchdog.
+//!
+//! When a device enforces a watchdog (see `Device::watchdog`) and does not support long running
+//! command buffers, a single dispatch which runs for seconds will get the device reset. The fix is
+//! to split the dispatch into chunks, and submit each chunk (or a few of them) in its own command
+//! buffer, so that no single submission runs for longer than the timeout.
+//!
+//! `split_dispatch` does the bookkeeping. The compute function must be written to take the chunk's
+//! threadgroup offset as an argument and add it to its `[[threadgroup_position_in_grid]]`, as
+//! `encode_chunk` passes it:
+//!
+//! ```ignore
+//! for chunk in split_dispatch(grid, 4096).iter() {
+//!     let mut commands = queue.acquire_command_buffer();
+//!     let mut encoder = commands.compute_command_encoder();
+//!     encoder.set_compute_pipeline(&pipeline);
+//!     encode_chunk(&mut encoder, chunk, threads_per_threadgroup, 0);
+//!     // ...
+//! }
+//! ```
+//!
+//! A good chunk size is found by timing: aim for each chunk to take well under the timeout
+//! (a tenth of it is a comfortable margin), since other applications share the GPU too.
+
+use {Device, ComputeCommandEncoder, Size};
+use util::as_bytes;
+
+/// A piece of a split dispatch.
+pub struct DispatchChunk {
+    /// The position of the chunk's first threadgroup in the whole grid.
+    pub offset: Size,
+    /// The number of threadgroups in the chunk.
+    pub threadgroups: Size,
+}
+
+fn chunk(x: uint, y: uint, z: uint, width: uint, height: uint, depth: uint) -> DispatchChunk {
+    DispatchChunk {
+        offset: Size { width: x, height: y, depth: z },
+        threadgroups: Size { width: width, height: height, depth: depth },
+    }
+}
+
+/// Split a grid of `threadgroups` into chunks of at most `max_threadgroups_per_chunk`
+/// threadgroups each.
+///
+/// Whole slices of the grid are kept together where possible, then whole rows, so that there are
+/// as few chunks as possible and each one is a box. Together, the chunks cover the grid exactly
+/// once.
+pub fn split_dispatch(threadgroups: Size, max_threadgroups_per_chunk: uint) -> Vec<DispatchChunk> {
+    assert!(max_threadgroups_per_chunk > 0);
+    let Size { width, height, depth } = threadgroups;
+    let row = width;
+    let slice = width * height;
+    let mut chunks = Vec::new();
+
+    if slice <= max_threadgroups_per_chunk {
+        let slices = max_threadgroups_per_chunk / std::cmp::max(slice, 1);
+        let mut z = 0;
+        while z < depth {
+            let n = std::cmp::min(slices, depth - z);
+            chunks.push(chunk(0, 0, z, width, height, n));
+            z += n;
+        }
+    } else if row <= max_threadgroups_per_chunk {
+        let rows = max_threadgroups_per_chunk / row;
+        for z in range(0, depth) {
+            let mut y = 0;
+            while y < height {
+                let n = std::cmp::min(rows, height - y);
+                chunks.push(chunk(0, y, z, width, n, 1));
+                y += n;
+            }
+        }
+    } else {
+        for z in range(0, depth) {
+            for y in range(0, height) {
+                let mut x = 0;
+                while x < width {
+                    let n = std::cmp::min(max_threadgroups_per_chunk, width - x);
+                    chunks.push(chunk(x, y, z, n, 1, 1));
+                    x += n;
+                }
+            }
+        }
+    }
+
+    chunks
+}
+
+/// Dispatch a single chunk with the current pipeline.
+///
+/// The chunk's offset is passed to the compute function as a `uint3` (padded to 16 bytes) at
+/// buffer `offset_index`.
+pub fn encode_chunk<D: Device, E: ComputeCommandEncoder<D>>(encoder: &mut E, chunk: &DispatchChunk,
+                                                            threads_per_threadgroup: Size, offset_index: uint) {
+    let offset = [chunk.offset.width as u32, chunk.offset.height as u32, chunk.offset.depth as u32, 0];
+    encoder.set_bytes(as_bytes(&offset), offset_index);
+    encoder.dispatch_threadgroups(chunk.threadgroups, threads_per_threadgroup);
+}
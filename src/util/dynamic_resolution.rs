@@ -0,0 +1,133 @@
+//! Adjusting the render scale to hold a frame time.
+//!
+//! `DynamicResolution` picks a `RenderScale` scale each frame from how long the GPU took for
+//! recent frames, lowering it as soon as frames run over the target, and raising it again only
+//! once they have been comfortably under it for a while, so that the scale does not oscillate:
+//!
+//! ```ignore
+//! profiler.begin_frame(&mut device);
+//! if let Some(timing) = profiler.report().iter().find(|timing| timing.name.as_slice() == "frame") {
+//!     resolution.apply(&mut scale, timing.last_ms);
+//! }
+//! let frame = profiler.begin(&mut commands, "frame");
+//! // every pass rendering into the scaled targets
+//! frame.end(&mut profiler, &mut commands);
+//! ```
+//!
+//! The GPU time should only cover the passes whose cost follows the render scale; passes at the
+//! output resolution (the upscale itself, the UI) make the scale look less effective than it is.
+
+use Device;
+use util::render_scale::RenderScale;
+
+/// How `DynamicResolution` chooses the scale.
+#[deriving(Clone, Show)]
+pub struct DynamicResolutionSettings {
+    /// The GPU time to hold each frame to, in milliseconds.
+    pub target_ms: f64,
+    /// The scale is kept within `min_scale` and `max_scale`.
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// How far under the target frames must be, as a fraction of it, before the scale is raised.
+    pub headroom: f64,
+    /// How many frames to wait after a change before raising the scale. Should be at least the
+    /// number of frames the GPU time is read back after, so that a change is seen before the
+    /// next.
+    pub frames_between_increases: uint,
+    /// The largest change to the scale in one frame.
+    pub max_step: f32,
+    /// How much each frame's GPU time counts in the smoothed time the scale follows, between `0`
+    /// (never changes) and `1` (no smoothing).
+    pub smoothing: f64,
+}
+
+impl Default for DynamicResolutionSettings {
+    /// Hold 60 frames per second, between half and full resolution.
+    fn default() -> DynamicResolutionSettings {
+        DynamicResolutionSettings {
+            target_ms: 1000.0 / 60.0,
+            min_scale: 0.5,
+            max_scale: 1.0,
+            headroom: 0.15,
+            frames_between_increases: 8,
+            max_step: 0.1,
+            smoothing: 0.25,
+        }
+    }
+}
+
+/// Chooses a render scale per frame. See the module documentation.
+pub struct DynamicResolution {
+    pub settings: DynamicResolutionSettings,
+    scale: f32,
+    smoothed_ms: Option<f64>,
+    frames_since_change: uint,
+}
+
+fn clamp(value: f32, min: f32, max: f32) -> f32 {
+    if value < min { min } else if value > max { max } else { value }
+}
+
+impl DynamicResolution {
+    /// Start at `settings.max_scale`.
+    pub fn new(settings: DynamicResolutionSettings) -> DynamicResolution {
+        assert!(settings.min_scale > 0.0 && settings.min_scale <= settings.max_scale,
+                "invalid render scale bounds {} to {}", settings.min_scale, settings.max_scale);
+        DynamicResolution {
+            scale: settings.max_scale,
+            settings: settings,
+            smoothed_ms: None,
+            frames_since_change: 0,
+        }
+    }
+
+    /// The scale chosen last.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Fold in the GPU time of the latest frame known, and return the scale to render the next
+    /// frame at.
+    pub fn update(&mut self, gpu_ms: f64) -> f32 {
+        let smoothed = match self.smoothed_ms {
+            Some(smoothed) => smoothed + (gpu_ms - smoothed) * self.settings.smoothing,
+            None => gpu_ms,
+        };
+        self.smoothed_ms = Some(smoothed);
+        self.frames_since_change += 1;
+        if smoothed <= 0.0 {
+            return self.scale;
+        }
+
+        // the GPU time of the scaled passes goes with the number of pixels, the square of the
+        // scale
+        let ideal = self.scale * (self.settings.target_ms / smoothed).sqrt() as f32;
+        let over = smoothed > self.settings.target_ms;
+        let under = smoothed < self.settings.target_ms * (1.0 - self.settings.headroom)
+            && self.frames_since_change >= self.settings.frames_between_increases;
+        if over || under {
+            let step = self.settings.max_step;
+            let scale = clamp(clamp(ideal, self.scale - step, self.scale + step),
+                              self.settings.min_scale, self.settings.max_scale);
+            if scale != self.scale {
+                self.scale = scale;
+                self.frames_since_change = 0;
+            }
+        }
+        self.scale
+    }
+
+    /// `update` with `gpu_ms`, and set the resulting scale on `render_scale`.
+    pub fn apply<D: Device>(&mut self, render_scale: &mut RenderScale<D>, gpu_ms: f64) {
+        let scale = self.update(gpu_ms);
+        render_scale.set_scale(scale);
+    }
+
+    /// Forget the frame times seen so far, and go back to `settings.max_scale`, for when the
+    /// workload changes abruptly (a level is loaded, say).
+    pub fn reset(&mut self) {
+        self.scale = self.settings.max_scale;
+        self.smoothed_ms = None;
+        self.frames_since_change = 0;
+    }
+}
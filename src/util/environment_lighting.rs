@@ -0,0 +1,339 @@
+//! Image based lighting preprocessing: turning an environment image into the cubemaps a PBR
+//! shader samples.
+//!
+//! `EnvironmentLighting` takes an equirectangular (latitude-longitude) HDR image, and produces
+//!
+//! * the environment itself, as a cubemap with every mip level,
+//! * an irradiance cubemap, the cosine weighted average of the environment around each normal,
+//!   for diffuse lighting, and
+//! * a prefiltered cubemap, whose mip levels hold the environment convolved with the GGX
+//!   distribution of increasing roughness, for specular lighting.
+//!
+//! Together with `lookup_textures::BrdfLut`, that is the split-sum approximation: specular light
+//! is `prefiltered.sample(r, roughness * (levels - 1)) * (f0 * brdf.x + brdf.y)`.
+//!
+//! As with the lookup textures, the pipelines are created from the functions of `SOURCE` named in
+//! `new`'s documentation, and everything is regenerated by `encode` whenever the environment
+//! changes. Cubemaps are `RGBA16Float`.
+
+use {Device, ComputeCommandEncoder, PixelFormat, Size, TextureDescriptor, TextureType};
+use {TextureUsage, TextureViewDescriptor};
+use texture::math::mip_level_count;
+use util::as_bytes;
+
+/// The width and height of the threadgroups, which are dispatched once per cube face.
+const THREADGROUP_SIZE: uint = 8;
+
+#[repr(C)]
+struct FilterUniforms {
+    /// The width of the level written.
+    size: u32,
+    /// The width of level 0 of the environment.
+    source_size: u32,
+    roughness: f32,
+    sample_count: u32,
+}
+
+fn cubemap<D: Device>(device: &mut D, size: uint, levels: uint) -> Result<D::Texture, D::TextureCreationError> {
+    device.create_texture(TextureDescriptor {
+        texture_type: TextureType::TypeCube,
+        pixel_format: PixelFormat::RGBA16Float,
+        width: size,
+        height: size,
+        depth: 1,
+        mipmap_level_count: levels,
+        array_length: 6,
+        sample_count: 1,
+        usage: TextureUsage { shader_read: true, shader_write: true, render_target: false },
+    })
+}
+
+/// A single level view of every level of `texture`, for writing.
+fn level_views<D: Device>(device: &mut D, texture: &D::Texture, levels: uint) -> Result<Vec<D::Texture>, D::TextureCreationError> {
+    let mut views = Vec::with_capacity(levels);
+    for level in range(0, levels) {
+        views.push(try!(device.create_texture_view(texture, TextureViewDescriptor {
+            pixel_format: PixelFormat::RGBA16Float,
+            base_mipmap_level: level,
+            mipmap_level_count: 1,
+            base_array_slice: 0,
+            array_length: 6,
+        })));
+    }
+    Ok(views)
+}
+
+/// Dispatch a `size` by `size` grid over each of the six faces.
+fn dispatch_faces<D: Device, E: ComputeCommandEncoder<D>>(encoder: &mut E, size: uint) {
+    let groups = (size + THREADGROUP_SIZE - 1) / THREADGROUP_SIZE;
+    encoder.dispatch_threadgroups(Size { width: groups, height: groups, depth: 6 },
+                                  Size { width: THREADGROUP_SIZE, height: THREADGROUP_SIZE, depth: 1 });
+}
+
+/// The cubemaps of image based lighting. See the module documentation.
+pub struct EnvironmentLighting<D: Device> {
+    from_equirectangular: D::ComputePipeline,
+    downsample: D::ComputePipeline,
+    irradiance: D::ComputePipeline,
+    prefilter: D::ComputePipeline,
+    environment: D::Texture,
+    environment_levels: Vec<D::Texture>,
+    irradiance_map: D::Texture,
+    prefiltered: D::Texture,
+    prefiltered_levels: Vec<D::Texture>,
+    size: uint,
+    irradiance_size: uint,
+    prefiltered_size: uint,
+    sample_count: uint,
+}
+
+impl<D: Device> EnvironmentLighting<D> {
+    /// Create the cubemaps: the environment with faces of `size` texels, the irradiance map
+    /// with faces of `irradiance_size`, and the prefiltered map with faces of `prefiltered_size`
+    /// and `roughness_levels` mip levels, from roughness `0` to `1`. Each texel of the irradiance
+    /// and prefiltered maps is integrated with `sample_count` samples.
+    ///
+    /// `from_equirectangular`, `downsample`, `irradiance` and `prefilter` must be compute
+    /// pipelines created from the `environment_from_equirectangular`, `environment_downsample`,
+    /// `environment_irradiance` and `environment_prefilter` functions of `SOURCE`. 512, 32 and
+    /// 128 with 5 levels and 256 samples suit most scenes; `roughness_levels` is at most the
+    /// number of levels of a full mip chain of `prefiltered_size`.
+    pub fn new(device: &mut D, from_equirectangular: D::ComputePipeline, downsample: D::ComputePipeline,
+               irradiance: D::ComputePipeline, prefilter: D::ComputePipeline, size: uint, irradiance_size: uint,
+               prefiltered_size: uint, roughness_levels: uint, sample_count: uint) -> Result<EnvironmentLighting<D>, D::TextureCreationError> {
+        let levels = mip_level_count(size, size, 1);
+        let roughness_levels = std::cmp::min(roughness_levels, mip_level_count(prefiltered_size, prefiltered_size, 1));
+        let environment = try!(cubemap(device, size, levels));
+        let environment_levels = try!(level_views(device, &environment, levels));
+        let irradiance_map = try!(cubemap(device, irradiance_size, 1));
+        let prefiltered = try!(cubemap(device, prefiltered_size, roughness_levels));
+        let prefiltered_levels = try!(level_views(device, &prefiltered, roughness_levels));
+        Ok(EnvironmentLighting {
+            from_equirectangular: from_equirectangular,
+            downsample: downsample,
+            irradiance: irradiance,
+            prefilter: prefilter,
+            environment: environment,
+            environment_levels: environment_levels,
+            irradiance_map: irradiance_map,
+            prefiltered: prefiltered,
+            prefiltered_levels: prefiltered_levels,
+            size: size,
+            irradiance_size: irradiance_size,
+            prefiltered_size: prefiltered_size,
+            sample_count: sample_count,
+        })
+    }
+
+    /// The environment cubemap, with every mip level, for drawing the sky.
+    pub fn environment(&self) -> &D::Texture {
+        &self.environment
+    }
+
+    /// The irradiance cubemap.
+    pub fn irradiance(&self) -> &D::Texture {
+        &self.irradiance_map
+    }
+
+    /// The prefiltered cubemap, whose level `i` is for roughness `i / (levels - 1)`.
+    pub fn prefiltered(&self) -> &D::Texture {
+        &self.prefiltered
+    }
+
+    /// The number of levels of the prefiltered cubemap.
+    pub fn roughness_levels(&self) -> uint {
+        self.prefiltered_levels.len()
+    }
+
+    /// Regenerate every cubemap from `equirectangular`, a 2D texture mapping longitude along `x`
+    /// and latitude along `y`, with the `shader_read` usage. An HDR format keeps the sun and
+    /// other bright spots from being clipped before they are spread over the rough levels.
+    pub fn encode<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, equirectangular: &D::Texture) {
+        enter_span!("EnvironmentLighting::encode");
+        let mut uniforms = FilterUniforms {
+            size: self.size as u32,
+            source_size: self.size as u32,
+            roughness: 0.0,
+            sample_count: self.sample_count as u32,
+        };
+
+        encoder.set_compute_pipeline(&self.from_equirectangular);
+        encoder.set_texture(equirectangular, 0);
+        encoder.set_texture(&self.environment_levels[0], 1);
+        encoder.set_bytes(as_bytes(&uniforms), 0);
+        dispatch_faces(encoder, self.size);
+
+        // the mip chain both draws the sky blurred and keeps the filters from aliasing: they
+        // sample the level whose texels cover about as much of the sphere as each sample does
+        encoder.set_compute_pipeline(&self.downsample);
+        for level in range(1, self.environment_levels.len()) {
+            uniforms.size = std::cmp::max(1, self.size >> level) as u32;
+            encoder.set_texture(&self.environment_levels[level - 1], 0);
+            encoder.set_texture(&self.environment_levels[level], 1);
+            encoder.set_bytes(as_bytes(&uniforms), 0);
+            dispatch_faces(encoder, uniforms.size as uint);
+        }
+
+        uniforms.size = self.irradiance_size as u32;
+        encoder.set_compute_pipeline(&self.irradiance);
+        encoder.set_texture(&self.environment, 0);
+        encoder.set_texture(&self.irradiance_map, 1);
+        encoder.set_bytes(as_bytes(&uniforms), 0);
+        dispatch_faces(encoder, self.irradiance_size);
+
+        encoder.set_compute_pipeline(&self.prefilter);
+        let last = std::cmp::max(1, self.prefiltered_levels.len() - 1);
+        for (level, view) in self.prefiltered_levels.iter().enumerate() {
+            uniforms.size = std::cmp::max(1, self.prefiltered_size >> level) as u32;
+            uniforms.roughness = level as f32 / last as f32;
+            encoder.set_texture(view, 1);
+            encoder.set_bytes(as_bytes(&uniforms), 0);
+            dispatch_faces(encoder, uniforms.size as uint);
+        }
+    }
+}
+
+/// The metal shading language source of the preprocessing kernels.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct FilterUniforms {
+    uint size;
+    uint source_size;
+    float roughness;
+    uint sample_count;
+};
+
+// the direction through the center of texel `id` of face `face`, in metal's face order
+static float3 cube_direction(uint2 id, uint face, uint size) {
+    float2 uv = (float2(id) + 0.5f) / float(size) * 2.0f - 1.0f;
+    switch (face) {
+        case 0: return normalize(float3(1.0f, -uv.y, -uv.x));
+        case 1: return normalize(float3(-1.0f, -uv.y, uv.x));
+        case 2: return normalize(float3(uv.x, 1.0f, uv.y));
+        case 3: return normalize(float3(uv.x, -1.0f, -uv.y));
+        case 4: return normalize(float3(uv.x, -uv.y, 1.0f));
+        default: return normalize(float3(-uv.x, -uv.y, -1.0f));
+    }
+}
+
+// an orthonormal basis around `n`
+static float3x3 tangent_frame(float3 n) {
+    float3 up = abs(n.z) < 0.999f ? float3(0.0f, 0.0f, 1.0f) : float3(1.0f, 0.0f, 0.0f);
+    float3 t = normalize(cross(up, n));
+    return float3x3(t, cross(n, t), n);
+}
+
+static float2 hammersley(uint i, uint n) {
+    return float2(float(i) / float(n), float(reverse_bits(i)) * 2.3283064365386963e-10f);
+}
+
+static float3 importance_sample_ggx(float2 xi, float roughness) {
+    float a = roughness * roughness;
+    float phi = 2.0f * M_PI_F * xi.x;
+    float cos_theta = sqrt((1.0f - xi.y) / (1.0f + (a * a - 1.0f) * xi.y));
+    float sin_theta = sqrt(1.0f - cos_theta * cos_theta);
+    return float3(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+}
+
+static float3 sample_cosine(float2 xi) {
+    float phi = 2.0f * M_PI_F * xi.x;
+    float r = sqrt(xi.y);
+    return float3(r * cos(phi), r * sin(phi), sqrt(1.0f - xi.y));
+}
+
+// the level of a `size` cubemap whose texels cover the solid angle of one of `sample_count`
+// samples of density `pdf`
+static float filtered_level(float pdf, uint sample_count, uint size) {
+    float sample_angle = 1.0f / (float(sample_count) * pdf + 1e-4f);
+    float texel_angle = 4.0f * M_PI_F / (6.0f * float(size) * float(size));
+    return max(0.5f * log2(sample_angle / texel_angle) + 1.0f, 0.0f);
+}
+
+kernel void environment_from_equirectangular(texture2d<float, access::sample> src [[texture(0)]],
+                                             texturecube<float, access::write> dst [[texture(1)]],
+                                             constant FilterUniforms &u [[buffer(0)]],
+                                             uint3 id [[thread_position_in_grid]])
+{
+    constexpr sampler linear(filter::linear, s_address::repeat, t_address::clamp_to_edge);
+    if (any(id.xy >= u.size)) {
+        return;
+    }
+    float3 d = cube_direction(id.xy, id.z, u.size);
+    float2 uv = float2(atan2(d.z, d.x) / (2.0f * M_PI_F) + 0.5f, acos(clamp(d.y, -1.0f, 1.0f)) / M_PI_F);
+    dst.write(float4(src.sample(linear, uv, level(0.0f)).rgb, 1.0f), id.xy, id.z);
+}
+
+kernel void environment_downsample(texturecube<float, access::read> src [[texture(0)]],
+                                   texturecube<float, access::write> dst [[texture(1)]],
+                                   constant FilterUniforms &u [[buffer(0)]],
+                                   uint3 id [[thread_position_in_grid]])
+{
+    if (any(id.xy >= u.size)) {
+        return;
+    }
+    uint2 s = id.xy * 2;
+    float4 sum = src.read(s, id.z) + src.read(s + uint2(1, 0), id.z)
+               + src.read(s + uint2(0, 1), id.z) + src.read(s + uint2(1, 1), id.z);
+    dst.write(sum * 0.25f, id.xy, id.z);
+}
+
+// stores the irradiance divided by pi, so diffuse lighting is `albedo * irradiance`
+kernel void environment_irradiance(texturecube<float, access::sample> environment [[texture(0)]],
+                                   texturecube<float, access::write> dst [[texture(1)]],
+                                   constant FilterUniforms &u [[buffer(0)]],
+                                   uint3 id [[thread_position_in_grid]])
+{
+    constexpr sampler trilinear(filter::linear, mip_filter::linear);
+    if (any(id.xy >= u.size)) {
+        return;
+    }
+    float3 n = cube_direction(id.xy, id.z, u.size);
+    float3x3 frame = tangent_frame(n);
+    float3 sum = 0.0f;
+    for (uint i = 0; i < u.sample_count; i++) {
+        float3 l = sample_cosine(hammersley(i, u.sample_count));
+        float pdf = l.z / M_PI_F;
+        float lod = filtered_level(pdf, u.sample_count, u.source_size);
+        sum += environment.sample(trilinear, frame * l, level(lod)).rgb;
+    }
+    dst.write(float4(sum / float(u.sample_count), 1.0f), id.xy, id.z);
+}
+
+kernel void environment_prefilter(texturecube<float, access::sample> environment [[texture(0)]],
+                                  texturecube<float, access::write> dst [[texture(1)]],
+                                  constant FilterUniforms &u [[buffer(0)]],
+                                  uint3 id [[thread_position_in_grid]])
+{
+    constexpr sampler trilinear(filter::linear, mip_filter::linear);
+    if (any(id.xy >= u.size)) {
+        return;
+    }
+    // the split-sum assumption: the view, normal and reflection directions are the same
+    float3 n = cube_direction(id.xy, id.z, u.size);
+    if (u.roughness == 0.0f) {
+        dst.write(float4(environment.sample(trilinear, n, level(log2(float(u.source_size) / float(u.size)))).rgb, 1.0f),
+                  id.xy, id.z);
+        return;
+    }
+    float3x3 frame = tangent_frame(n);
+    float a2 = pow(u.roughness, 4.0f);
+    float3 sum = 0.0f;
+    float weight = 0.0f;
+    for (uint i = 0; i < u.sample_count; i++) {
+        float3 h = importance_sample_ggx(hammersley(i, u.sample_count), u.roughness);
+        float3 l = 2.0f * h.z * h - float3(0.0f, 0.0f, 1.0f);
+        if (l.z <= 0.0f) {
+            continue;
+        }
+        // the density of `l`, for the ggx distribution of `h` with n = v
+        float d = (h.z * h.z * (a2 - 1.0f) + 1.0f);
+        float pdf = a2 / (M_PI_F * d * d) * 0.25f;
+        float lod = filtered_level(pdf, u.sample_count, u.source_size);
+        sum += environment.sample(trilinear, frame * l, level(lod)).rgb * l.z;
+        weight += l.z;
+    }
+    dst.write(float4(sum / max(weight, 1e-4f), 1.0f), id.xy, id.z);
+}
+"#;
@@ -0,0 +1,156 @@
+//! Waiting for command buffers on the CPU, with timeouts and without blocking.
+//!
+//! A `Fence` is signaled by the completed handler of a command buffer, and can then be polled,
+//! waited on with or without a timeout, or (with the `futures` feature) awaited:
+//!
+//! ```ignore
+//! let fence = Fence::new();
+//! commands.add_completed_handler(fence.completed_handler());
+//! commands.commit();
+//!
+//! // in the frame loop, never blocking
+//! if fence.is_signaled() {
+//!     upload.recycle();
+//! }
+//! // or, at most 100 milliseconds
+//! match fence.wait_timeout(100) {
+//!     Some(CommandBufferStatus::Completed) => ..,
+//!     Some(CommandBufferStatus::Failed(e)) => ..,
+//!     None => println!("the GPU is taking its time"),
+//! }
+//! ```
+//!
+//! Fences are cheap to clone, and every clone is signaled together, so one command buffer can
+//! be watched from several places.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use CommandBufferStatus;
+
+struct State {
+    status: Option<CommandBufferStatus>,
+    /// The tasks of the `FenceFuture`s polled before the signal.
+    wakers: Vec<std::task::Waker>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    signaled: Condvar,
+}
+
+/// Whether a command buffer has finished executing, and how. See the module documentation.
+#[deriving(Clone)]
+pub struct Fence {
+    shared: Arc<Shared>,
+}
+
+impl Fence {
+    /// Create an unsignaled fence.
+    pub fn new() -> Fence {
+        Fence {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    status: None,
+                    wakers: Vec::new(),
+                }),
+                signaled: Condvar::new(),
+            }),
+        }
+    }
+
+    /// A completed handler which signals the fence, for `CommandBuffer::add_completed_handler`.
+    ///
+    /// A fence should only be signaled once; a later signal replaces the status of the earlier.
+    pub fn completed_handler(&self) -> Box<FnOnce(CommandBufferStatus) + Send> {
+        let fence = self.clone();
+        box move |status: CommandBufferStatus| fence.signal(status)
+    }
+
+    /// Signal the fence with `status`, waking everything waiting on it.
+    pub fn signal(&self, status: CommandBufferStatus) {
+        let mut state = self.shared.state.lock();
+        state.status = Some(status);
+        for waker in state.wakers.drain() {
+            waker.wake();
+        }
+        self.shared.signaled.notify_all();
+    }
+
+    /// Whether the fence is signaled, without blocking.
+    pub fn is_signaled(&self) -> bool {
+        self.shared.state.lock().status.is_some()
+    }
+
+    /// The status of the command buffer, if the fence is signaled. Never blocks.
+    pub fn status(&self) -> Option<CommandBufferStatus> {
+        self.shared.state.lock().status.clone()
+    }
+
+    /// Block until the fence is signaled.
+    pub fn wait(&self) -> CommandBufferStatus {
+        enter_span!("Fence::wait");
+        let mut state = self.shared.state.lock();
+        loop {
+            if let Some(ref status) = state.status {
+                return status.clone();
+            }
+            state = self.shared.signaled.wait(state);
+        }
+    }
+
+    /// Block until the fence is signaled, or for at most `timeout_ms` milliseconds.
+    ///
+    /// Returns `None` if the fence was not signaled in time. A timeout of `0` is `status`.
+    pub fn wait_timeout(&self, timeout_ms: uint) -> Option<CommandBufferStatus> {
+        enter_span!("Fence::wait_timeout", timeout_ms = timeout_ms);
+        let mut state = self.shared.state.lock();
+        let mut remaining = Duration::milliseconds(timeout_ms as i64);
+        loop {
+            if let Some(ref status) = state.status {
+                return Some(status.clone());
+            }
+            if remaining <= Duration::zero() {
+                return None;
+            }
+            // spurious wakeups come back here with the time left
+            let started = std::time::precise_time_ns();
+            let (next, _) = self.shared.signaled.wait_timeout(state, remaining);
+            state = next;
+            remaining = remaining - Duration::nanoseconds((std::time::precise_time_ns() - started) as i64);
+        }
+    }
+
+    /// A future of the fence's status, for awaiting command buffers from async code.
+    #[cfg(feature = "futures")]
+    pub fn future(&self) -> FenceFuture {
+        FenceFuture { fence: self.clone() }
+    }
+}
+
+/// The status of a fence, once it is signaled. Returned by `Fence::future`.
+#[cfg(feature = "futures")]
+pub struct FenceFuture {
+    fence: Fence,
+}
+
+#[cfg(feature = "futures")]
+impl std::future::Future for FenceFuture {
+    type Output = CommandBufferStatus;
+
+    fn poll(self: std::pin::Pin<&mut FenceFuture>, context: &mut std::task::Context) -> std::task::Poll<CommandBufferStatus> {
+        let mut state = self.fence.shared.state.lock();
+        match state.status {
+            Some(ref status) => std::task::Poll::Ready(status.clone()),
+            None => {
+                // completed handlers run on arbitrary threads, so the signal wakes the task
+                // rather than polling it
+                let waker = context.waker();
+                if !state.wakers.iter().any(|registered| registered.will_wake(waker)) {
+                    state.wakers.push(waker.clone());
+                }
+                std::task::Poll::Pending
+            }
+        }
+    }
+}
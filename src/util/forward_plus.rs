@@ -0,0 +1,391 @@
+//! Tiled forward ("forward+") shading: per-tile light lists culled against a depth prepass.
+//!
+//! The screen is split into tiles of `TILE_SIZE` pixels. After the depth prepass, a compute pass
+//! finds the depth range of every tile and lists the lights whose spheres touch the tile's piece
+//! of the view frustum; the forward pass then only shades each pixel with the lights of its tile.
+//! The per-tile frusta only depend on the projection and the screen size, and are rebuilt by a
+//! second kernel only when either changes:
+//!
+//! ```ignore
+//! forward_plus.set_projection(&inverse_projection);
+//! forward_plus.begin_frame(&mut device, lights.as_slice(), &view);
+//!
+//! let mut graph = RenderGraph::new(&mut resources, window_size);
+//! let depth = graph.create_texture("depth", GraphTextureDescriptor::relative(1.0, PixelFormat::Depth32Float));
+//! let hdr = graph.create_texture("hdr", GraphTextureDescriptor::relative(1.0, PixelFormat::RGBA16Float));
+//! graph.add_pass("depth_prepass", PassKind::Render, &[], &[depth], |commands, textures| { .. });
+//! let light_grid = forward_plus.add_passes(&mut graph, depth);
+//! graph.add_pass("forward", PassKind::Render, &[depth, light_grid], &[hdr], |commands, textures| {
+//!     // with `depth` loaded and compared equal
+//!     forward_plus.bind(&mut encoder, textures.texture(light_grid), 4, 4);
+//! });
+//! ```
+//!
+//! The forward pass's fragment shader includes `FRAGMENT_SOURCE`, and loops over
+//! `forward_plus_light_count` lights of `forward_plus_light`. Lights are point lights; the
+//! lights past `max_lights_per_tile` of a crowded tile are dropped.
+
+use std::cell::Cell;
+
+use {Device, BufferSlice, CommandBuffer, ComputeCommandEncoder, PixelFormat, RenderCommandEncoder, Size};
+use util::{as_bytes, slice_as_bytes};
+use util::render_graph::{GraphTextureDescriptor, PassKind, RenderGraph, TextureId, TextureSize};
+
+/// The width and height of a tile, in pixels, which is also the threadgroup size of the cull
+/// kernel.
+pub const TILE_SIZE: uint = 16;
+
+/// A point light, as laid out in the light buffer.
+#[repr(C)]
+pub struct PointLight {
+    /// The position, in world space.
+    pub position: [f32, ..3],
+    /// The distance the light reaches, past which it is culled.
+    pub radius: f32,
+    pub color: [f32, ..3],
+    pub intensity: f32,
+}
+
+#[repr(C)]
+struct CullUniforms {
+    view: [f32, ..16],
+    inverse_projection: [f32, ..16],
+    screen_size: [u32, ..2],
+    tile_count: [u32, ..2],
+    light_count: u32,
+    max_lights_per_tile: u32,
+}
+
+#[repr(C)]
+struct FragmentUniforms {
+    tile_count: [u32, ..2],
+    max_lights_per_tile: u32,
+}
+
+/// The light lists of a forward+ renderer. See the module documentation.
+pub struct ForwardPlus<D: Device> {
+    build_tiles: D::ComputePipeline,
+    cull: D::ComputePipeline,
+    /// Four view space planes per tile.
+    tile_planes: D::Buffer,
+    /// `max_lights_per_tile` light indices per tile.
+    light_indices: D::Buffer,
+    /// One light buffer per frame in flight.
+    light_buffers: Vec<D::Buffer>,
+    current: uint,
+    light_count: uint,
+    max_lights: uint,
+    max_lights_per_tile: uint,
+    screen_size: (uint, uint),
+    view: [f32, ..16],
+    inverse_projection: [f32, ..16],
+    tiles_dirty: Cell<bool>,
+}
+
+fn tile_count(screen_size: (uint, uint)) -> (uint, uint) {
+    let (width, height) = screen_size;
+    ((width + TILE_SIZE - 1) / TILE_SIZE, (height + TILE_SIZE - 1) / TILE_SIZE)
+}
+
+fn identity() -> [f32, ..16] {
+    [1.0, 0.0, 0.0, 0.0,
+     0.0, 1.0, 0.0, 0.0,
+     0.0, 0.0, 1.0, 0.0,
+     0.0, 0.0, 0.0, 1.0]
+}
+
+impl<D: Device> ForwardPlus<D> {
+    /// Create the light lists of a `screen_size` screen, for up to `max_lights` lights, of which
+    /// up to `max_lights_per_tile` are shaded in each tile.
+    ///
+    /// `build_tiles` and `cull` must be compute pipelines created from the
+    /// `forward_plus_build_tiles` and `forward_plus_cull` functions of `SOURCE`. As for
+    /// `DebugDraw`, `frames_in_flight` must be at least the number of frames the CPU can get
+    /// ahead of the GPU, as each frame's lights are written into a buffer of their own.
+    pub fn new(device: &mut D, build_tiles: D::ComputePipeline, cull: D::ComputePipeline, screen_size: (uint, uint),
+               max_lights: uint, max_lights_per_tile: uint, frames_in_flight: uint) -> Result<ForwardPlus<D>, D::BufferCreationError> {
+        let (tiles_x, tiles_y) = tile_count(screen_size);
+        let tile_planes = try!(device.create_buffer(tiles_x * tiles_y * 4 * 16, None));
+        let light_indices = try!(device.create_buffer(tiles_x * tiles_y * max_lights_per_tile * 4, None));
+        let mut light_buffers = Vec::with_capacity(frames_in_flight);
+        for _ in range(0, frames_in_flight) {
+            light_buffers.push(try!(device.create_buffer(max_lights * std::mem::size_of::<PointLight>(), None)));
+        }
+        Ok(ForwardPlus {
+            build_tiles: build_tiles,
+            cull: cull,
+            tile_planes: tile_planes,
+            light_indices: light_indices,
+            light_buffers: light_buffers,
+            current: frames_in_flight - 1,
+            light_count: 0,
+            max_lights: max_lights,
+            max_lights_per_tile: max_lights_per_tile,
+            screen_size: screen_size,
+            view: identity(),
+            inverse_projection: identity(),
+            tiles_dirty: Cell::new(true),
+        })
+    }
+
+    /// Resize the light lists for a new screen size.
+    pub fn resize(&mut self, device: &mut D, screen_size: (uint, uint)) -> Result<(), D::BufferCreationError> {
+        if screen_size == self.screen_size {
+            return Ok(());
+        }
+        let (tiles_x, tiles_y) = tile_count(screen_size);
+        self.tile_planes = try!(device.create_buffer(tiles_x * tiles_y * 4 * 16, None));
+        self.light_indices = try!(device.create_buffer(tiles_x * tiles_y * self.max_lights_per_tile * 4, None));
+        self.screen_size = screen_size;
+        self.tiles_dirty.set(true);
+        Ok(())
+    }
+
+    /// Set the column major inverse of the projection the depth prepass renders with. The tile
+    /// frusta are rebuilt the next frame if it changed.
+    pub fn set_projection(&mut self, inverse_projection: &[f32, ..16]) {
+        if self.inverse_projection.as_slice() != inverse_projection.as_slice() {
+            self.inverse_projection = *inverse_projection;
+            self.tiles_dirty.set(true);
+        }
+    }
+
+    /// Start a new frame with `lights`, seen through the column major world to view transform
+    /// `view`. Lights past `max_lights` are dropped.
+    pub fn begin_frame(&mut self, device: &mut D, lights: &[PointLight], view: &[f32, ..16]) {
+        enter_span!("ForwardPlus::begin_frame", lights = lights.len());
+        self.current = (self.current + 1) % self.light_buffers.len();
+        self.light_count = std::cmp::min(lights.len(), self.max_lights);
+        if self.light_count > 0 {
+            device.write_buffer(&self.light_buffers[self.current], 0, slice_as_bytes(lights.slice_to(self.light_count)));
+        }
+        self.view = *view;
+    }
+
+    /// The number of tiles across and down.
+    pub fn tile_count(&self) -> (uint, uint) {
+        tile_count(self.screen_size)
+    }
+
+    /// Add the light culling pass to `graph`, after the pass which writes `depth`, the depth of
+    /// the prepass. Returns the light grid it writes, a texture of a single `R32Uint` texel per
+    /// tile holding the number of lights of the tile, for the forward pass to read.
+    pub fn add_passes<'a>(&'a self, graph: &mut RenderGraph<'a, D>, depth: TextureId) -> TextureId {
+        let (tiles_x, tiles_y) = self.tile_count();
+        let light_grid = graph.create_texture("forward_plus.light_grid", GraphTextureDescriptor {
+            size: TextureSize::Absolute(tiles_x, tiles_y),
+            pixel_format: PixelFormat::R32Uint,
+            mipmap_level_count: 1,
+        });
+        let (depth_id, grid_id) = (depth.clone(), light_grid.clone());
+        graph.add_pass("forward_plus.cull", PassKind::Compute, &[depth], &[light_grid.clone()], move |commands, textures| {
+            let mut encoder = commands.compute_command_encoder();
+            self.encode(&mut encoder, textures.texture(depth_id.clone()), textures.texture(grid_id.clone()));
+            encoder.end_encoding();
+        });
+        light_grid
+    }
+
+    /// Cull the lights into `light_grid` against `depth`, for use without a render graph.
+    ///
+    /// `depth` must be the size of the screen, and `light_grid` a `shader_write` `R32Uint`
+    /// texture of `tile_count()` texels.
+    pub fn encode<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, depth: &D::Texture, light_grid: &D::Texture) {
+        enter_span!("ForwardPlus::encode", lights = self.light_count);
+        let (width, height) = self.screen_size;
+        let (tiles_x, tiles_y) = self.tile_count();
+        let uniforms = CullUniforms {
+            view: self.view,
+            inverse_projection: self.inverse_projection,
+            screen_size: [width as u32, height as u32],
+            tile_count: [tiles_x as u32, tiles_y as u32],
+            light_count: self.light_count as u32,
+            max_lights_per_tile: self.max_lights_per_tile as u32,
+        };
+        encoder.set_bytes(as_bytes(&uniforms), 0);
+        encoder.set_buffer(BufferSlice::whole(&self.tile_planes), 1);
+
+        if self.tiles_dirty.get() {
+            encoder.set_compute_pipeline(&self.build_tiles);
+            encoder.dispatch_threadgroups(Size { width: (tiles_x + 7) / 8, height: (tiles_y + 7) / 8, depth: 1 },
+                                          Size { width: 8, height: 8, depth: 1 });
+            self.tiles_dirty.set(false);
+        }
+
+        encoder.set_compute_pipeline(&self.cull);
+        encoder.set_texture(depth, 0);
+        encoder.set_texture(light_grid, 1);
+        encoder.set_buffer(BufferSlice::whole(&self.light_buffers[self.current]), 2);
+        encoder.set_buffer(BufferSlice::whole(&self.light_indices), 3);
+        encoder.dispatch_threadgroups(Size { width: tiles_x, height: tiles_y, depth: 1 },
+                                      Size { width: TILE_SIZE, height: TILE_SIZE, depth: 1 });
+    }
+
+    /// Bind the light lists for the forward pass's fragment shader: the light grid at fragment
+    /// texture `texture_index`, and the lights, the light indices and their uniforms at the
+    /// fragment buffers from `first_buffer` on, as `FRAGMENT_SOURCE` declares them.
+    pub fn bind<E: RenderCommandEncoder<D>>(&self, encoder: &mut E, light_grid: &D::Texture, texture_index: uint, first_buffer: uint) {
+        let (tiles_x, tiles_y) = self.tile_count();
+        let uniforms = FragmentUniforms {
+            tile_count: [tiles_x as u32, tiles_y as u32],
+            max_lights_per_tile: self.max_lights_per_tile as u32,
+        };
+        encoder.set_fragment_texture(light_grid, texture_index);
+        encoder.set_fragment_buffer(BufferSlice::whole(&self.light_buffers[self.current]), first_buffer);
+        encoder.set_fragment_buffer(BufferSlice::whole(&self.light_indices), first_buffer + 1);
+        encoder.set_fragment_bytes(as_bytes(&uniforms), first_buffer + 2);
+    }
+}
+
+/// The metal shading language source of the tile and culling kernels.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+#define TILE_SIZE 16
+
+struct CullUniforms {
+    float4x4 view;
+    float4x4 inverse_projection;
+    uint2 screen_size;
+    uint2 tile_count;
+    uint light_count;
+    uint max_lights_per_tile;
+};
+
+struct PointLight {
+    packed_float3 position;
+    float radius;
+    packed_float3 color;
+    float intensity;
+};
+
+// the view space point of the far plane under a pixel corner
+static float3 far_corner(uint2 pixel, constant CullUniforms &u) {
+    float2 ndc = float2(pixel) / float2(u.screen_size) * 2.0 - 1.0;
+    float4 v = u.inverse_projection * float4(ndc.x, -ndc.y, 1.0, 1.0);
+    return v.xyz / v.w;
+}
+
+// the planes through the eye and the sides of each tile, facing into the tile
+kernel void forward_plus_build_tiles(constant CullUniforms &u [[buffer(0)]],
+                                     device float4 *tile_planes [[buffer(1)]],
+                                     uint2 tile [[thread_position_in_grid]])
+{
+    if (any(tile >= u.tile_count)) {
+        return;
+    }
+    uint2 first = tile * TILE_SIZE;
+    uint2 last = min(first + TILE_SIZE, u.screen_size);
+    float3 corners[4] = {
+        far_corner(first, u),
+        far_corner(uint2(last.x, first.y), u),
+        far_corner(last, u),
+        far_corner(uint2(first.x, last.y), u),
+    };
+    float3 center = (corners[0] + corners[2]) * 0.5;
+    for (uint i = 0; i < 4; i++) {
+        float3 normal = normalize(cross(corners[i], corners[(i + 1) % 4]));
+        if (dot(normal, center) < 0.0) {
+            normal = -normal;
+        }
+        tile_planes[(tile.y * u.tile_count.x + tile.x) * 4 + i] = float4(normal, 0.0);
+    }
+}
+
+kernel void forward_plus_cull(depth2d<float, access::read> depth [[texture(0)]],
+                              texture2d<uint, access::write> light_grid [[texture(1)]],
+                              constant CullUniforms &u [[buffer(0)]],
+                              device const float4 *tile_planes [[buffer(1)]],
+                              device const PointLight *lights [[buffer(2)]],
+                              device uint *light_indices [[buffer(3)]],
+                              uint2 pixel [[thread_position_in_grid]],
+                              uint2 tile [[threadgroup_position_in_grid]],
+                              uint thread_index [[thread_index_in_threadgroup]])
+{
+    // view space distances are positive, so their bits order as the floats do
+    threadgroup atomic_uint min_bits;
+    threadgroup atomic_uint max_bits;
+    threadgroup atomic_uint count;
+    if (thread_index == 0) {
+        atomic_store_explicit(&min_bits, 0x7f7fffff, memory_order_relaxed);
+        atomic_store_explicit(&max_bits, 0, memory_order_relaxed);
+        atomic_store_explicit(&count, 0, memory_order_relaxed);
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+
+    if (all(pixel < u.screen_size)) {
+        float4 v = u.inverse_projection * float4(0.0, 0.0, depth.read(pixel), 1.0);
+        uint bits = as_type<uint>(max(-v.z / v.w, 0.0));
+        atomic_fetch_min_explicit(&min_bits, bits, memory_order_relaxed);
+        atomic_fetch_max_explicit(&max_bits, bits, memory_order_relaxed);
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+
+    float min_z = as_type<float>(atomic_load_explicit(&min_bits, memory_order_relaxed));
+    float max_z = as_type<float>(atomic_load_explicit(&max_bits, memory_order_relaxed));
+    uint tile_index = tile.y * u.tile_count.x + tile.x;
+    device const float4 *planes = tile_planes + tile_index * 4;
+    for (uint i = thread_index; i < u.light_count; i += TILE_SIZE * TILE_SIZE) {
+        PointLight light = lights[i];
+        float3 p = (u.view * float4(float3(light.position), 1.0)).xyz;
+        float distance = -p.z;
+        if (distance + light.radius < min_z || distance - light.radius > max_z) {
+            continue;
+        }
+        bool inside = true;
+        for (uint j = 0; j < 4; j++) {
+            inside = inside && dot(planes[j].xyz, p) > -light.radius;
+        }
+        if (inside) {
+            uint slot = atomic_fetch_add_explicit(&count, 1, memory_order_relaxed);
+            if (slot < u.max_lights_per_tile) {
+                light_indices[tile_index * u.max_lights_per_tile + slot] = i;
+            }
+        }
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+
+    if (thread_index == 0) {
+        uint total = atomic_load_explicit(&count, memory_order_relaxed);
+        light_grid.write(uint4(min(total, u.max_lights_per_tile)), tile);
+    }
+}
+"#;
+
+/// Metal shading language declarations for the forward pass's fragment shader, to paste in
+/// front of it. Declare the fragment arguments as `ForwardPlus::bind` binds them:
+///
+/// ```ignore
+/// texture2d<uint, access::read> light_grid [[texture(4)]],
+/// device const ForwardPlusLight *lights [[buffer(4)]],
+/// device const uint *light_indices [[buffer(5)]],
+/// constant ForwardPlusUniforms &forward_plus [[buffer(6)]],
+/// ```
+pub const FRAGMENT_SOURCE: &'static str = r#"
+#define FORWARD_PLUS_TILE_SIZE 16
+
+struct ForwardPlusLight {
+    packed_float3 position;
+    float radius;
+    packed_float3 color;
+    float intensity;
+};
+
+struct ForwardPlusUniforms {
+    uint2 tile_count;
+    uint max_lights_per_tile;
+};
+
+// the number of lights of the tile of the fragment at `position` (its `[[position]]`)
+static uint forward_plus_light_count(texture2d<uint, access::read> light_grid, float4 position) {
+    return light_grid.read(uint2(position.xy) / FORWARD_PLUS_TILE_SIZE).x;
+}
+
+// the `i`th light of the tile of the fragment at `position`
+static ForwardPlusLight forward_plus_light(device const ForwardPlusLight *lights, device const uint *light_indices,
+                                           constant ForwardPlusUniforms &u, float4 position, uint i) {
+    uint2 tile = uint2(position.xy) / FORWARD_PLUS_TILE_SIZE;
+    return lights[light_indices[(tile.y * u.tile_count.x + tile.x) * u.max_lights_per_tile + i]];
+}
+"#;
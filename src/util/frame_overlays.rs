@@ -0,0 +1,158 @@
+//! Diagnostic overlays (debug UI, performance HUDs, memory inspectors) drawn over every frame,
+//! whatever renders the rest of it.
+//!
+//! The crate has no presentation layer of its own: the application renders into whatever its
+//! window gives it and presents that. `FrameOverlays` is the one call it makes in between. Each
+//! tool registers a `FrameOverlay` once, and `compose` draws every enabled overlay into the final
+//! target, in one render pass which keeps what the renderer left there:
+//!
+//! ```ignore
+//! let mut overlays = FrameOverlays::new();
+//! let hud = overlays.register(box PerformanceHud::new(&mut device), 100);
+//!
+//! // every frame, after the renderer and before presenting
+//! overlays.compose(&mut device, &mut commands, &window_texture, window.size());
+//! commands.commit();
+//!
+//! // when the HUD key is pressed
+//! overlays.toggle(hud);
+//! ```
+//!
+//! The renderer need not know about any of it, and tools need not know about each other: they
+//! are drawn in the order of their `order` (lower first, so underneath), then of registration.
+
+use {Device, ClearColor, CommandBuffer, LoadAction, RenderCommandEncoder, RenderPassColorAttachment};
+use {RenderPassDescriptor, StoreAction, Texture};
+
+/// The final target of a frame, as overlays see it.
+pub struct OverlayTarget<'a, D: Device + 'a> {
+    pub texture: &'a D::Texture,
+    /// The size of `texture`, in pixels.
+    pub size: (uint, uint),
+    /// The number of frames composed before this one.
+    pub frame: u64,
+}
+
+/// Something drawn over every frame. See the module documentation.
+pub trait FrameOverlay<D: Device> {
+    /// The name of the overlay, for labels and for tools listing the overlays.
+    fn name(&self) -> &str;
+
+    /// Encode whatever the overlay needs before it draws (uploads, offscreen passes) into
+    /// `commands`, ahead of the overlay pass.
+    ///
+    /// Overlays which only draw need not implement this.
+    fn prepare(&mut self, device: &mut D, commands: &mut D::CommandBuffer, target: &OverlayTarget<D>) {
+        let _ = (device, commands, target);
+    }
+
+    /// Draw the overlay, into a render pass over the final target which already has the frame
+    /// and every overlay ordered before this one in it. Overlays must not rely on any state
+    /// left by the one before.
+    fn draw(&mut self, device: &mut D, encoder: &mut D::RenderCommandEncoder, target: &OverlayTarget<D>);
+}
+
+/// An overlay registered with `FrameOverlays`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct OverlayHandle(uint);
+
+struct Entry<D: Device> {
+    handle: uint,
+    order: i32,
+    enabled: bool,
+    overlay: Box<FrameOverlay<D> + 'static>,
+}
+
+/// The overlays drawn over every frame. See the module documentation.
+pub struct FrameOverlays<D: Device> {
+    /// Sorted by order, then handle.
+    entries: Vec<Entry<D>>,
+    next_handle: uint,
+    frame: u64,
+}
+
+impl<D: Device> FrameOverlays<D> {
+    pub fn new() -> FrameOverlays<D> {
+        FrameOverlays { entries: Vec::new(), next_handle: 0, frame: 0 }
+    }
+
+    /// Draw `overlay` over every frame from now on, after the overlays with a lower `order`.
+    pub fn register(&mut self, overlay: Box<FrameOverlay<D> + 'static>, order: i32) -> OverlayHandle {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        let position = self.entries.iter().position(|entry| entry.order > order).unwrap_or(self.entries.len());
+        self.entries.insert(position, Entry { handle: handle, order: order, enabled: true, overlay: overlay });
+        OverlayHandle(handle)
+    }
+
+    /// Stop drawing the overlay, and give it back.
+    pub fn unregister(&mut self, handle: OverlayHandle) -> Option<Box<FrameOverlay<D> + 'static>> {
+        match self.position(&handle) {
+            Some(index) => self.entries.remove(index).map(|entry| entry.overlay),
+            None => None,
+        }
+    }
+
+    /// Skip the overlay, or draw it again, without unregistering it.
+    pub fn set_enabled(&mut self, handle: OverlayHandle, enabled: bool) {
+        if let Some(index) = self.position(&handle) {
+            self.entries[index].enabled = enabled;
+        }
+    }
+
+    /// Enable the overlay if it is disabled, and disable it otherwise.
+    pub fn toggle(&mut self, handle: OverlayHandle) {
+        if let Some(index) = self.position(&handle) {
+            self.entries[index].enabled = !self.entries[index].enabled;
+        }
+    }
+
+    pub fn is_enabled(&self, handle: OverlayHandle) -> bool {
+        self.position(&handle).map(|index| self.entries[index].enabled).unwrap_or(false)
+    }
+
+    /// The names of the registered overlays, in the order they are drawn, and whether each is
+    /// enabled.
+    pub fn overlays(&self) -> Vec<(OverlayHandle, &str, bool)> {
+        self.entries.iter().map(|entry| (OverlayHandle(entry.handle), entry.overlay.name(), entry.enabled)).collect()
+    }
+
+    /// Draw every enabled overlay into `target`, the final target of the frame, `size` pixels,
+    /// with what `commands` already renders into it kept underneath. Call this once a frame, after
+    /// the renderer and before presenting, with no encoder open; with every overlay disabled
+    /// nothing is encoded.
+    pub fn compose(&mut self, device: &mut D, commands: &mut D::CommandBuffer, target: &D::Texture, size: (uint, uint)) {
+        enter_span!("FrameOverlays::compose", overlays = self.entries.len());
+        let frame = self.frame;
+        self.frame += 1;
+        if !self.entries.iter().any(|entry| entry.enabled) {
+            return;
+        }
+        let overlay_target = OverlayTarget { texture: target, size: size, frame: frame };
+        commands.push_debug_group("overlays");
+        for entry in self.entries.iter_mut().filter(|entry| entry.enabled) {
+            entry.overlay.prepare(device, commands, &overlay_target);
+        }
+        let mut encoder = commands.render_command_encoder(RenderPassDescriptor {
+            color_attachments: vec![RenderPassColorAttachment {
+                texture: target, mipmap_level: 0, slice: 0,
+                load_action: LoadAction::Load, store_action: StoreAction::Store,
+                clear_color: ClearColor::zero_for(&target.pixel_format()),
+            }],
+            depth_attachment: None,
+            stencil_attachment: None,
+            sample_positions: None,
+            visibility_result_buffer: None,
+        });
+        for entry in self.entries.iter_mut().filter(|entry| entry.enabled) {
+            entry.overlay.draw(device, &mut encoder, &overlay_target);
+        }
+        encoder.end_encoding();
+        commands.pop_debug_group();
+    }
+
+    fn position(&self, handle: &OverlayHandle) -> Option<uint> {
+        let OverlayHandle(handle) = *handle;
+        self.entries.iter().position(|entry| entry.handle == handle)
+    }
+}
@@ -0,0 +1,351 @@
+//! Many meshes in one vertex buffer and one index buffer ("global vertex buffer").
+//!
+//! Binding a vertex and index buffer per mesh costs state changes between every draw, and rules
+//! out drawing many meshes with a single indirect multi-draw. A `GeometryPool` sub-allocates
+//! the vertices and indices of every mesh from two shared buffers instead, so that the buffers
+//! are bound once and each draw only differs in its `first_index` and `base_vertex`:
+//!
+//! ```ignore
+//! let mut pool = try!(GeometryPool::new(&mut device, 32, 1 << 20, 1 << 22));
+//! let mesh = try!(pool.add(&mut device, &mut blit, &mut deletions, slice_as_bytes(vertices), indices));
+//!
+//! pool.bind(&mut encoder, 0);
+//! pool.draw(&mut encoder, &mesh, 1, 0);
+//! // or, for GPU-driven draws, pool.indirect_args(&mesh, 1, 0) into the culling input
+//! ```
+//!
+//! Meshes are written once, when they are added: their indices are relative to their own first
+//! vertex, and are never rewritten. The buffers grow (doubling) when a mesh does not fit, with
+//! the meshes already in them copied over by the blit encoder given to `add`. Removing meshes
+//! leaves holes which later meshes are placed in when they fit; `compact` packs every mesh
+//! together again when the holes add up to too much (see `fragmentation`).
+//!
+//! Growing and compacting replace the buffers. The old ones are handed to the given
+//! `DeletionQueue` (see `util::teardown`), as draws in flight and the copies read them, and
+//! draws encoded after either must bind the new buffers and read the ranges of their meshes
+//! again.
+
+use {Device, BlitCommandEncoder, BufferSlice, DrawIndexedIndirectArgs, IndexFormat, PrimitiveType, RenderCommandEncoder};
+use util::slice_as_bytes;
+use util::teardown::DeletionQueue;
+
+/// The format of the indices of the pool.
+pub const INDEX_FORMAT: IndexFormat = IndexFormat::UInt32;
+
+/// A mesh of a pool.
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+pub struct MeshId(uint);
+
+/// Where the vertices and indices of a mesh are in the buffers of its pool, in vertices and
+/// indices.
+#[deriving(Clone, PartialEq, Show)]
+pub struct MeshRange {
+    pub first_vertex: uint,
+    pub vertex_count: uint,
+    pub first_index: uint,
+    pub index_count: uint,
+}
+
+impl MeshRange {
+    /// The `base_vertex` to draw the mesh with.
+    pub fn base_vertex(&self) -> int {
+        self.first_vertex as int
+    }
+}
+
+/// One of the buffers of a pool, and its free ranges (offset and count, in elements, sorted by
+/// offset and never adjacent).
+struct Stream<D: Device> {
+    buffer: D::Buffer,
+    element_size: uint,
+    capacity: uint,
+    free: Vec<(uint, uint)>,
+}
+
+impl<D: Device> Stream<D> {
+    fn new(device: &mut D, element_size: uint, capacity: uint) -> Result<Stream<D>, D::BufferCreationError> {
+        let buffer = try!(device.create_buffer(std::cmp::max(capacity, 1) * element_size, None));
+        let free = if capacity > 0 { vec![(0, capacity)] } else { Vec::new() };
+        Ok(Stream { buffer: buffer, element_size: element_size, capacity: capacity, free: free })
+    }
+
+    /// The offset of `count` elements, from the first free range they fit in.
+    fn allocate(&mut self, count: uint) -> Option<uint> {
+        let index = match self.free.iter().position(|&(_, free)| free >= count) {
+            Some(index) => index,
+            None => return None,
+        };
+        let (offset, free) = self.free[index];
+        if free == count {
+            self.free.remove(index);
+        } else {
+            self.free[index] = (offset + count, free - count);
+        }
+        Some(offset)
+    }
+
+    fn release(&mut self, offset: uint, count: uint) {
+        if count == 0 {
+            return;
+        }
+        let index = self.free.iter().position(|&(free, _)| free > offset).unwrap_or(self.free.len());
+        self.free.insert(index, (offset, count));
+        // merge with the following range, then the preceding one
+        if index + 1 < self.free.len() && offset + count == self.free[index + 1].0 {
+            let (_, next) = self.free.remove(index + 1).unwrap();
+            self.free[index].1 += next;
+        }
+        if index > 0 && self.free[index - 1].0 + self.free[index - 1].1 == offset {
+            let (_, merged) = self.free.remove(index).unwrap();
+            self.free[index - 1].1 += merged;
+        }
+    }
+
+    fn free_count(&self) -> uint {
+        self.free.iter().fold(0, |sum, &(_, count)| sum + count)
+    }
+
+    /// Replace the buffer with one of `capacity` elements, copying `moves` (source offset,
+    /// destination offset and count) from the old one, which is deferred to `deletions`.
+    fn replace<E: BlitCommandEncoder<D>>(&mut self, device: &mut D, blit: &mut E, deletions: &mut DeletionQueue,
+                                         capacity: uint, moves: &[(uint, uint, uint)])
+                                         -> Result<(), D::BufferCreationError> where D::Buffer: 'static {
+        let buffer = try!(device.create_buffer(capacity * self.element_size, None));
+        for &(src, dst, count) in moves.iter() {
+            if count > 0 {
+                let slice = BufferSlice::new(&self.buffer, src * self.element_size, count * self.element_size);
+                blit.copy_buffer(slice, &buffer, dst * self.element_size);
+            }
+        }
+        deletions.defer(std::mem::replace(&mut self.buffer, buffer));
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    /// Grow to hold at least `count` more elements in one range, keeping every element where
+    /// it is, and return the offset of `count` elements allocated from that range.
+    fn grow<E: BlitCommandEncoder<D>>(&mut self, device: &mut D, blit: &mut E, deletions: &mut DeletionQueue, count: uint)
+                                      -> Result<uint, D::BufferCreationError> where D::Buffer: 'static {
+        let old = self.capacity;
+        let tail = match self.free.last() {
+            Some(&(offset, free)) if offset + free == old => free,
+            _ => 0,
+        };
+        // at least one element, so that an empty stream asked for nothing still gets a range
+        let capacity = std::cmp::max(old * 2, old - tail + std::cmp::max(count, 1));
+        // only the ranges in use are copied: what is written into the free ranges before the
+        // copy executes must not be overwritten by it
+        let mut moves = Vec::new();
+        let mut start = 0;
+        for &(offset, free) in self.free.iter() {
+            moves.push((start, start, offset - start));
+            start = offset + free;
+        }
+        moves.push((start, start, old - start));
+        try!(self.replace(device, blit, deletions, capacity, moves.as_slice()));
+        self.release(old, capacity - old);
+
+        // the new space, merged with the free tail, is the last range and has room for `count`
+        let index = self.free.len() - 1;
+        let (offset, free) = self.free[index];
+        if free == count {
+            self.free.pop();
+        } else {
+            self.free[index] = (offset + count, free - count);
+        }
+        Ok(offset)
+    }
+}
+
+/// Meshes sub-allocated from a shared vertex buffer and index buffer. See the module
+/// documentation.
+pub struct GeometryPool<D: Device> {
+    vertices: Stream<D>,
+    indices: Stream<D>,
+    /// The range of every mesh, by id, and `None` for removed meshes, whose ids are reused.
+    meshes: Vec<Option<MeshRange>>,
+    free_ids: Vec<uint>,
+}
+
+impl<D: Device> GeometryPool<D> {
+    /// Create a pool of vertices of `vertex_stride` bytes, a multiple of 4, with room for
+    /// `vertex_capacity` vertices and `index_capacity` indices to start with.
+    pub fn new(device: &mut D, vertex_stride: uint, vertex_capacity: uint, index_capacity: uint)
+               -> Result<GeometryPool<D>, D::BufferCreationError> {
+        assert!(vertex_stride > 0 && vertex_stride % 4 == 0, "the vertex stride of a geometry pool must be a multiple of 4, not {}",
+                vertex_stride);
+        Ok(GeometryPool {
+            vertices: try!(Stream::new(device, vertex_stride, vertex_capacity)),
+            indices: try!(Stream::new(device, INDEX_FORMAT.size(), index_capacity)),
+            meshes: Vec::new(),
+            free_ids: Vec::new(),
+        })
+    }
+
+    /// The buffer of the vertices of every mesh.
+    pub fn vertex_buffer(&self) -> &D::Buffer {
+        &self.vertices.buffer
+    }
+
+    /// The buffer of the indices of every mesh, in `INDEX_FORMAT`.
+    pub fn index_buffer(&self) -> &D::Buffer {
+        &self.indices.buffer
+    }
+
+    /// Add a mesh of the vertices `vertices` holds, `vertex_stride` bytes each, and of
+    /// `indices`, relative to its first vertex, growing the buffers if it does not fit. The
+    /// copies growing needs are encoded into `blit`, which must be committed before any draw of
+    /// the pool's meshes.
+    pub fn add<E: BlitCommandEncoder<D>>(&mut self, device: &mut D, blit: &mut E, deletions: &mut DeletionQueue,
+                                         vertices: &[u8], indices: &[u32]) -> Result<MeshId, D::BufferCreationError>
+                                         where D::Buffer: 'static {
+        enter_span!("GeometryPool::add", vertices = vertices.len(), indices = indices.len());
+        assert!(vertices.len() % self.vertices.element_size == 0,
+                "{} bytes of vertices are not a whole number of {} byte vertices", vertices.len(), self.vertices.element_size);
+        let vertex_count = vertices.len() / self.vertices.element_size;
+        let first_vertex = match self.vertices.allocate(vertex_count) {
+            Some(offset) => offset,
+            None => try!(self.vertices.grow(device, blit, deletions, vertex_count)),
+        };
+        let first_index = match self.indices.allocate(indices.len()) {
+            Some(offset) => offset,
+            None => try!(self.indices.grow(device, blit, deletions, indices.len())),
+        };
+        if vertex_count > 0 {
+            device.write_buffer(&self.vertices.buffer, first_vertex * self.vertices.element_size, vertices);
+        }
+        if !indices.is_empty() {
+            device.write_buffer(&self.indices.buffer, first_index * self.indices.element_size, slice_as_bytes(indices));
+        }
+
+        let range = MeshRange {
+            first_vertex: first_vertex,
+            vertex_count: vertex_count,
+            first_index: first_index,
+            index_count: indices.len(),
+        };
+        let id = match self.free_ids.pop() {
+            Some(id) => {
+                self.meshes[id] = Some(range);
+                id
+            }
+            None => {
+                self.meshes.push(Some(range));
+                self.meshes.len() - 1
+            }
+        };
+        Ok(MeshId(id))
+    }
+
+    /// Remove a mesh, so that later meshes can be placed where it was. Any command buffer which
+    /// draws it must have been committed: the data of the meshes placed there is written before
+    /// the command buffers committed after them execute, not before those already committed.
+    pub fn remove(&mut self, mesh: MeshId) {
+        let MeshId(id) = mesh;
+        let range = self.meshes[id].take().expect("removing a mesh which is not in the geometry pool");
+        self.vertices.release(range.first_vertex, range.vertex_count);
+        self.indices.release(range.first_index, range.index_count);
+        self.free_ids.push(id);
+    }
+
+    /// Where the vertices and indices of `mesh` are.
+    pub fn range(&self, mesh: &MeshId) -> MeshRange {
+        let MeshId(id) = *mesh;
+        self.meshes[id].clone().expect("a mesh which is not in the geometry pool")
+    }
+
+    /// The number of meshes in the pool.
+    pub fn len(&self) -> uint {
+        self.meshes.len() - self.free_ids.len()
+    }
+
+    /// Bind the vertex buffer of the pool at `vertex_buffer_index`, and its index buffer.
+    pub fn bind<E: RenderCommandEncoder<D>>(&self, encoder: &mut E, vertex_buffer_index: uint) {
+        encoder.set_vertex_buffer(BufferSlice::whole(&self.vertices.buffer), vertex_buffer_index);
+        encoder.set_index_buffer(BufferSlice::whole(&self.indices.buffer), INDEX_FORMAT);
+    }
+
+    /// Draw `instance_count` instances of the triangles of `mesh`, with the pool bound.
+    pub fn draw<E: RenderCommandEncoder<D>>(&self, encoder: &mut E, mesh: &MeshId, instance_count: uint, base_instance: uint) {
+        let range = self.range(mesh);
+        encoder.draw_indexed(PrimitiveType::Triangle, range.first_index, range.index_count, instance_count,
+                             range.base_vertex(), base_instance);
+    }
+
+    /// The arguments of an indirect draw of `mesh`, as `draw` would draw it.
+    pub fn indirect_args(&self, mesh: &MeshId, instance_count: uint, base_instance: uint) -> DrawIndexedIndirectArgs {
+        let range = self.range(mesh);
+        DrawIndexedIndirectArgs {
+            index_count: range.index_count as u32,
+            instance_count: instance_count as u32,
+            first_index: range.first_index as u32,
+            base_vertex: range.first_vertex as i32,
+            base_instance: base_instance as u32,
+        }
+    }
+
+    /// The elements used by meshes and the capacity of the buffers, as `(used, capacity)` pairs
+    /// of vertices and of indices.
+    pub fn usage(&self) -> ((uint, uint), (uint, uint)) {
+        ((self.vertices.capacity - self.vertices.free_count(), self.vertices.capacity),
+         (self.indices.capacity - self.indices.free_count(), self.indices.capacity))
+    }
+
+    /// The share of the free space of the buffers which is not at their ends, from `0` (none)
+    /// to `1`, of whichever buffer has the most: what `compact` would reclaim for large meshes.
+    pub fn fragmentation(&self) -> f32 {
+        let fragmentation = |stream: &Stream<D>| {
+            let free = stream.free_count();
+            let tail = match stream.free.last() {
+                Some(&(offset, count)) if offset + count == stream.capacity => count,
+                _ => 0,
+            };
+            if free == 0 { 0.0 } else { (free - tail) as f32 / free as f32 }
+        };
+        fragmentation(&self.vertices).max(fragmentation(&self.indices))
+    }
+
+    /// Pack every mesh to the start of the buffers, in the order they are in, leaving all of the
+    /// free space at the end. The copies are encoded into `blit`; the ranges of the meshes change.
+    pub fn compact<E: BlitCommandEncoder<D>>(&mut self, device: &mut D, blit: &mut E, deletions: &mut DeletionQueue)
+                                             -> Result<(), D::BufferCreationError> where D::Buffer: 'static {
+        enter_span!("GeometryPool::compact", meshes = self.len());
+        let mut vertex_spans = Vec::new();
+        let mut index_spans = Vec::new();
+        for (id, range) in self.meshes.iter().enumerate() {
+            if let Some(ref range) = *range {
+                vertex_spans.push((id, range.first_vertex, range.vertex_count));
+                index_spans.push((id, range.first_index, range.index_count));
+            }
+        }
+        let (vertex_moves, vertices) = pack(&mut vertex_spans);
+        let (index_moves, indices) = pack(&mut index_spans);
+
+        let (vertex_capacity, index_capacity) = (self.vertices.capacity, self.indices.capacity);
+        try!(self.vertices.replace(device, blit, deletions, vertex_capacity, vertex_moves.as_slice()));
+        try!(self.indices.replace(device, blit, deletions, index_capacity, index_moves.as_slice()));
+
+        for (&(id, _, _), &(_, dst, _)) in vertex_spans.iter().zip(vertex_moves.iter()) {
+            self.meshes[id].as_mut().unwrap().first_vertex = dst;
+        }
+        for (&(id, _, _), &(_, dst, _)) in index_spans.iter().zip(index_moves.iter()) {
+            self.meshes[id].as_mut().unwrap().first_index = dst;
+        }
+        self.vertices.free = if vertices < vertex_capacity { vec![(vertices, vertex_capacity - vertices)] } else { Vec::new() };
+        self.indices.free = if indices < index_capacity { vec![(indices, index_capacity - indices)] } else { Vec::new() };
+        Ok(())
+    }
+}
+
+/// Sort `spans` (id, offset and count) by offset, and return the moves (source, destination and
+/// count) packing them from offset `0` on, in that order, and the number of elements packed.
+fn pack(spans: &mut Vec<(uint, uint, uint)>) -> (Vec<(uint, uint, uint)>, uint) {
+    spans.sort_by(|a, b| a.1.cmp(&b.1));
+    let mut moves = Vec::with_capacity(spans.len());
+    let mut packed = 0;
+    for &(_, offset, count) in spans.iter() {
+        moves.push((offset, packed, count));
+        packed += count;
+    }
+    (moves, packed)
+}
@@ -0,0 +1,279 @@
+//! A glyph cache for text rendering.
+//!
+//! `GlyphAtlas` keeps rasterized glyphs in a single channel texture, rasterizing and uploading
+//! each glyph the first time it is asked for. Rasterization is left to a `GlyphRasterizer`, so any
+//! font library (or a signed distance field generator) can be plugged in.
+//!
+//! The glyphs are packed with a `util::atlas::ShelfAllocator`. The atlas starts small and doubles
+//! in size when it fills up, up to a maximum. Once at the maximum, it evicts rows of glyphs which
+//! have not been used recently. A glyph asked for in the current frame is never evicted, so
+//! everything used by a frame's text stays valid until `begin_frame` is called again.
+//!
+//! Growing and evicting both change where glyphs are, so every `GlyphEntry` obtained before is
+//! stale afterwards. `generation` changes whenever that happens, which is enough for callers
+//! caching laid out text to know when to redo it.
+
+use std::collections::HashMap;
+
+use {Device, Origin, PixelFormat, Size, TextureDescriptor, TextureType, TextureUsage};
+use util::atlas::{AtlasRect, RectAllocator, ShelfAllocator};
+
+/// Identifies a single rasterized glyph.
+#[deriving(PartialEq, Eq, Hash, Clone)]
+pub struct GlyphKey {
+    /// Identifies the font face, as understood by the rasterizer.
+    pub font: u32,
+    /// The glyph index within the font.
+    pub glyph: u32,
+    /// The rasterized size, in pixels per em.
+    pub size: u32,
+}
+
+/// A glyph as produced by a rasterizer.
+pub struct RasterizedGlyph {
+    pub width: uint,
+    pub height: uint,
+    /// The offset from the pen position to the top left corner of the bitmap, in pixels.
+    pub bearing: (i32, i32),
+    /// How far to move the pen after this glyph, in pixels.
+    pub advance: f32,
+    /// `width * height` coverage values, row by row from the top.
+    pub coverage: Vec<u8>,
+}
+
+/// Rasterizes glyphs on demand.
+pub trait GlyphRasterizer {
+    /// Rasterize the glyph `key`, or return `None` if the font has no such glyph.
+    fn rasterize(&mut self, key: &GlyphKey) -> Option<RasterizedGlyph>;
+}
+
+/// Where a glyph is in the atlas, and how to place it.
+pub struct GlyphEntry {
+    /// The glyph's rectangle in the atlas, as normalized `u0`, `v0`, `u1`, `v1`.
+    pub uv: [f32, ..4],
+    /// The size of the glyph's bitmap, in pixels.
+    pub size: (uint, uint),
+    pub bearing: (i32, i32),
+    pub advance: f32,
+}
+
+/// Empty space left around every glyph, so that filtering never bleeds between glyphs.
+const PADDING: uint = 1;
+
+struct Cached {
+    /// The glyph's rectangle, with its padding.
+    rect: AtlasRect,
+    bearing: (i32, i32),
+    advance: f32,
+    last_used: u64,
+}
+
+/// Why the atlas could not provide a glyph.
+pub enum GlyphError<D: Device> {
+    /// The font has no such glyph.
+    NoGlyph,
+    /// The glyph does not fit into the atlas even at its largest, with nothing evictable.
+    AtlasFull,
+    /// Creating a larger atlas texture failed.
+    Texture(D::TextureCreationError),
+}
+
+pub struct GlyphAtlas<D: Device> {
+    texture: D::Texture,
+    size: uint,
+    max_size: uint,
+    /// A copy of the atlas contents, for re-uploading on growth.
+    pixels: Vec<u8>,
+    allocator: ShelfAllocator,
+    glyphs: HashMap<GlyphKey, Cached>,
+    frame: u64,
+    generation: u64,
+}
+
+fn create_texture<D: Device>(device: &mut D, size: uint) -> Result<D::Texture, D::TextureCreationError> {
+    device.create_texture(TextureDescriptor {
+        texture_type: TextureType::Type2D,
+        pixel_format: PixelFormat::R8Unorm,
+        width: size,
+        height: size,
+        depth: 1,
+        mipmap_level_count: 1,
+        array_length: 1,
+        sample_count: 1,
+        usage: TextureUsage { shader_read: true, shader_write: false, render_target: false },
+    })
+}
+
+impl<D: Device> GlyphAtlas<D> {
+    /// Create an atlas which starts out `initial_size` pixels square and may grow up to
+    /// `max_size`.
+    pub fn new(device: &mut D, initial_size: uint, max_size: uint) -> Result<GlyphAtlas<D>, D::TextureCreationError> {
+        let texture = try!(create_texture(device, initial_size));
+        let pixels = Vec::from_elem(initial_size * initial_size, 0u8);
+        device.write_texture(&texture, 0, 0, Origin { x: 0, y: 0, z: 0 },
+                             Size { width: initial_size, height: initial_size, depth: 1 },
+                             pixels.as_slice(), initial_size);
+        Ok(GlyphAtlas {
+            texture: texture,
+            size: initial_size,
+            max_size: max_size,
+            pixels: pixels,
+            allocator: ShelfAllocator::new(initial_size, initial_size),
+            glyphs: HashMap::new(),
+            frame: 0,
+            generation: 0,
+        })
+    }
+
+    /// The atlas texture, in `R8Unorm` format.
+    ///
+    /// This may be a different texture after any call to `glyph`.
+    pub fn texture(&self) -> &D::Texture {
+        &self.texture
+    }
+
+    /// Changes whenever previously returned glyph entries become stale.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Start a new frame. Glyphs used only in earlier frames become evictable.
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Get a glyph, rasterizing and uploading it if it is not in the atlas yet.
+    pub fn glyph<R: GlyphRasterizer>(&mut self, device: &mut D, rasterizer: &mut R, key: &GlyphKey) -> Result<GlyphEntry, GlyphError<D>> {
+        if !self.glyphs.contains_key(key) {
+            let glyph = match rasterizer.rasterize(key) {
+                Some(glyph) => glyph,
+                None => return Err(GlyphError::NoGlyph),
+            };
+            try!(self.insert(device, key.clone(), glyph));
+        }
+
+        let frame = self.frame;
+        let size = self.size as f32;
+        let cached = self.glyphs.get_mut(key).unwrap();
+        cached.last_used = frame;
+        let (width, height) = (cached.rect.width - PADDING, cached.rect.height - PADDING);
+        let (x, y) = (cached.rect.x, cached.rect.y);
+        Ok(GlyphEntry {
+            uv: [x as f32 / size, y as f32 / size, (x + width) as f32 / size, (y + height) as f32 / size],
+            size: (width, height),
+            bearing: cached.bearing,
+            advance: cached.advance,
+        })
+    }
+
+    fn insert(&mut self, device: &mut D, key: GlyphKey, glyph: RasterizedGlyph) -> Result<(), GlyphError<D>> {
+        let (w, h) = (glyph.width + PADDING, glyph.height + PADDING);
+        if w > self.max_size || h > self.max_size {
+            return Err(GlyphError::AtlasFull);
+        }
+        // every eviction empties a row of glyphs, so this ends once there are none left to evict
+        let rect = loop {
+            match self.allocator.allocate(w, h) {
+                Some(rect) => break rect,
+                None => {}
+            }
+            if self.size < self.max_size {
+                try!(self.grow(device));
+            } else if !self.evict(device, h) {
+                return Err(GlyphError::AtlasFull);
+            }
+        };
+
+        let (x, y) = (rect.x, rect.y);
+        for row in range(0, glyph.height) {
+            let src = glyph.coverage.slice(row * glyph.width, (row + 1) * glyph.width);
+            let dst = (y + row) * self.size + x;
+            for (i, &c) in src.iter().enumerate() {
+                self.pixels[dst + i] = c;
+            }
+        }
+        if glyph.width > 0 && glyph.height > 0 {
+            device.write_texture(&self.texture, 0, 0, Origin { x: x, y: y, z: 0 },
+                                 Size { width: glyph.width, height: glyph.height, depth: 1 },
+                                 glyph.coverage.as_slice(), glyph.width);
+        }
+
+        self.glyphs.insert(key, Cached {
+            rect: rect,
+            bearing: glyph.bearing,
+            advance: glyph.advance,
+            last_used: self.frame,
+        });
+        Ok(())
+    }
+
+    /// Double the size of the atlas, keeping every glyph where it is.
+    fn grow(&mut self, device: &mut D) -> Result<(), GlyphError<D>> {
+        let size = std::cmp::min(self.size * 2, self.max_size);
+        let texture = match create_texture(device, size) {
+            Ok(texture) => texture,
+            Err(e) => return Err(GlyphError::Texture(e)),
+        };
+
+        let mut pixels = Vec::from_elem(size * size, 0u8);
+        for row in range(0, self.size) {
+            for col in range(0, self.size) {
+                pixels[row * size + col] = self.pixels[row * self.size + col];
+            }
+        }
+        device.write_texture(&texture, 0, 0, Origin { x: 0, y: 0, z: 0 },
+                             Size { width: size, height: size, depth: 1 },
+                             pixels.as_slice(), size);
+
+        self.texture = texture;
+        self.pixels = pixels;
+        self.allocator.grow(size, size);
+        self.size = size;
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Evict the least recently used row of glyphs not used this frame, preferring rows of glyphs
+    /// at least `h` tall (with their padding), whose shelf the new glyph fits on.
+    ///
+    /// The evicted glyphs are cleared, so that the padding of the glyphs put in their place is
+    /// empty again. Returns `false` if every glyph was used this frame.
+    fn evict(&mut self, device: &mut D, h: uint) -> bool {
+        // the rows by the `y` of their shelf, with the last frame any of their glyphs was used
+        // in and the height of the tallest
+        let mut rows: HashMap<uint, (u64, uint)> = HashMap::new();
+        for cached in self.glyphs.values() {
+            let (last_used, tallest) = rows.get(&cached.rect.y).map_or((0, 0), |&row| row);
+            rows.insert(cached.rect.y, (std::cmp::max(last_used, cached.last_used),
+                                        std::cmp::max(tallest, cached.rect.height)));
+        }
+        let frame = self.frame;
+        let victim = rows.iter()
+                         .filter(|&(_, &(last_used, _))| last_used < frame)
+                         .min_by(|&(_, &(last_used, tallest))| (tallest < h, last_used))
+                         .map(|(&y, _)| y);
+        let y = match victim {
+            Some(y) => y,
+            None => return false,
+        };
+
+        let evicted: Vec<GlyphKey> = self.glyphs.iter()
+                                         .filter(|&(_, c)| c.rect.y == y)
+                                         .map(|(k, _)| k.clone())
+                                         .collect();
+        for key in evicted.iter() {
+            let rect = self.glyphs.remove(key).unwrap().rect;
+            for row in range(rect.y, rect.y + rect.height) {
+                for p in self.pixels.slice_mut(row * self.size + rect.x, row * self.size + rect.x + rect.width).iter_mut() {
+                    *p = 0;
+                }
+            }
+            device.write_texture(&self.texture, 0, 0, Origin { x: rect.x, y: rect.y, z: 0 },
+                                 Size { width: rect.width, height: rect.height, depth: 1 },
+                                 Vec::from_elem(rect.width * rect.height, 0u8).as_slice(), rect.width);
+            self.allocator.deallocate(rect);
+        }
+        self.generation += 1;
+        true
+    }
+}
@@ -0,0 +1,181 @@
+//! Per-pass GPU timings.
+//!
+//! `GpuProfiler` brackets passes with timestamps and debug groups, and keeps a rolling average
+//! of how long each named pass took on the GPU:
+//!
+//! ```ignore
+//! profiler.begin_frame(&mut device);
+//! let pass = profiler.begin(&mut commands, "shadows");
+//! // any number of encoders
+//! pass.end(&mut profiler, &mut commands);
+//! for timing in profiler.report().iter() {
+//!     println!("{}: {:.2}ms", timing.name, timing.average_ms);
+//! }
+//! ```
+//!
+//! Passes may nest (a pass's time then includes its children's), and may be spread over several
+//! command buffers of the same frame, as long as every command buffer is committed before the
+//! next `begin_frame`.
+
+use {Device, CommandBuffer, DeviceFeatures};
+use util::slice_as_bytes_mut;
+
+/// The number of frames `PassTiming::average_ms` averages over.
+pub const HISTORY: uint = 64;
+
+/// Why a `GpuProfiler` could not be created.
+pub enum GpuProfilerCreationError<D: Device> {
+    /// The device lacks `timestamp_writes`.
+    MissingFeatures(Vec<&'static str>),
+    /// The device cannot tell how long its timestamp ticks are (`calibrate_timestamps` returned
+    /// `None`).
+    NoCalibration,
+    Buffer(D::BufferCreationError),
+}
+
+/// How long a pass took, over the last frames it ran in.
+pub struct PassTiming {
+    pub name: String,
+    /// The GPU time of the last frame the pass ran in, in milliseconds.
+    pub last_ms: f64,
+    /// The average GPU time over the last `HISTORY` frames it ran in, in milliseconds.
+    pub average_ms: f64,
+    samples: Vec<f64>,
+    next_sample: uint,
+}
+
+impl PassTiming {
+    fn add_sample(&mut self, ms: f64) {
+        if self.samples.len() < HISTORY {
+            self.samples.push(ms);
+        } else {
+            self.samples[self.next_sample] = ms;
+        }
+        self.next_sample = (self.next_sample + 1) % HISTORY;
+        self.last_ms = ms;
+        self.average_ms = self.samples.iter().fold(0.0, |sum, &sample| sum + sample) / self.samples.len() as f64;
+    }
+}
+
+/// A pass being timed, returned by `GpuProfiler::begin`.
+#[must_use]
+pub struct TimedPass {
+    /// `None` if the frame had no slot left for the pass.
+    slot: Option<uint>,
+}
+
+impl TimedPass {
+    /// End the pass, after the last command of it encoded in `commands`.
+    pub fn end<D: Device>(self, profiler: &mut GpuProfiler<D>, commands: &mut D::CommandBuffer) {
+        if let Some(slot) = self.slot {
+            commands.write_timestamp(&profiler.buffers[profiler.current], (slot * 2 + 1) * 8);
+        }
+        commands.pop_debug_group();
+    }
+}
+
+/// Timestamps for up to a fixed number of passes per frame, and the report they add up to.
+pub struct GpuProfiler<D: Device> {
+    /// Two `u64` timestamps per pass, per frame in flight.
+    buffers: Vec<D::Buffer>,
+    /// The `report` index of each pass begun in each frame in flight, in slot order.
+    passes: Vec<Vec<uint>>,
+    current: uint,
+    max_passes: uint,
+    tick_ns: f64,
+    report: Vec<PassTiming>,
+}
+
+impl<D: Device> GpuProfiler<D> {
+    /// Create a profiler for up to `max_passes` passes per frame.
+    ///
+    /// As for `DebugDraw`, `frames_in_flight` must be at least the number of frames the CPU can
+    /// get ahead of the GPU: a frame's timestamps are read back `frames_in_flight` frames later.
+    pub fn new(device: &mut D, max_passes: uint, frames_in_flight: uint) -> Result<GpuProfiler<D>, GpuProfilerCreationError<D>> {
+        let required = DeviceFeatures { timestamp_writes: true, .. Default::default() };
+        let missing = device.features().missing(&required);
+        if !missing.is_empty() {
+            return Err(GpuProfilerCreationError::MissingFeatures(missing));
+        }
+        let tick_ns = match device.calibrate_timestamps() {
+            Some(calibration) => calibration.gpu_tick_ns,
+            None => return Err(GpuProfilerCreationError::NoCalibration),
+        };
+        let mut buffers = Vec::with_capacity(frames_in_flight);
+        for _ in range(0, frames_in_flight) {
+            match device.create_buffer(max_passes * 2 * 8, None) {
+                Ok(buffer) => buffers.push(buffer),
+                Err(e) => return Err(GpuProfilerCreationError::Buffer(e)),
+            }
+        }
+        Ok(GpuProfiler {
+            buffers: buffers,
+            passes: Vec::from_fn(frames_in_flight, |_| Vec::new()),
+            current: frames_in_flight - 1,
+            max_passes: max_passes,
+            tick_ns: tick_ns,
+            report: Vec::new(),
+        })
+    }
+
+    /// Start a new frame, adding the timings of the frame `frames_in_flight` frames ago to the
+    /// report.
+    // todo: this reads back with `read_buffer`, which waits for every committed command buffer,
+    // not just the ones of that frame. Read without waiting once buffers can be mapped.
+    pub fn begin_frame(&mut self, device: &mut D) {
+        enter_span!("GpuProfiler::begin_frame");
+        self.current = (self.current + 1) % self.buffers.len();
+        let passes = &mut self.passes[self.current];
+        if passes.is_empty() {
+            return;
+        }
+        let mut ticks = Vec::from_elem(passes.len() * 2, 0u64);
+        device.read_buffer(&self.buffers[self.current], 0, slice_as_bytes_mut(ticks.as_mut_slice()));
+        for (slot, &index) in passes.iter().enumerate() {
+            let ms = ticks[slot * 2 + 1].saturating_sub(ticks[slot * 2]) as f64 * self.tick_ns / 1e6;
+            self.report[index].add_sample(ms);
+        }
+        passes.clear();
+    }
+
+    /// Begin timing a pass named `name`, before the first command of it encoded in `commands`.
+    ///
+    /// The pass is also a debug group of that name. If `max_passes` passes were begun this
+    /// frame already, it is only a debug group.
+    pub fn begin(&mut self, commands: &mut D::CommandBuffer, name: &str) -> TimedPass {
+        commands.push_debug_group(name);
+        let slot = self.passes[self.current].len();
+        if slot == self.max_passes {
+            return TimedPass { slot: None };
+        }
+        let index = match self.report.iter().position(|timing| timing.name.as_slice() == name) {
+            Some(index) => index,
+            None => {
+                self.report.push(PassTiming {
+                    name: name.to_string(),
+                    last_ms: 0.0,
+                    average_ms: 0.0,
+                    samples: Vec::with_capacity(HISTORY),
+                    next_sample: 0,
+                });
+                self.report.len() - 1
+            }
+        };
+        self.passes[self.current].push(index);
+        commands.write_timestamp(&self.buffers[self.current], slot * 2 * 8);
+        TimedPass { slot: Some(slot) }
+    }
+
+    /// The timings of every pass seen so far, in the order they were first begun.
+    pub fn report(&self) -> &[PassTiming] {
+        self.report.as_slice()
+    }
+
+    /// Forget every timing, for when the passes of a frame change for good.
+    pub fn clear_report(&mut self) {
+        self.report.clear();
+        for passes in self.passes.iter_mut() {
+            passes.clear();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Packing indirect arguments into buffers.
+//!
+//! Indirect draws and dispatches read their arguments from buffers, usually written by culling or
+//! binning shaders, but just as often seeded by the CPU. `IndirectArgsBuilder` packs arguments
+//! at their stride, with every offset aligned as every backend requires, so the offsets it hands
+//! out can be passed straight to `draw_indirect`, `draw_indexed_indirect` and
+//! `dispatch_threadgroups_indirect`:
+//!
+//! ```ignore
+//! let mut builder = IndirectArgsBuilder::new();
+//! let offset = builder.push(DrawIndexedIndirectArgs { index_count: 36, instance_count: 1, .. });
+//! let args = try!(builder.create_buffer(&mut device));
+//! encoder.draw_indexed_indirect(PrimitiveType::Triangle, &args, offset);
+//! ```
+//!
+//! Shaders which write arguments should declare them with the structs of `SHADER_HEADER`, which
+//! have the same layout.
+
+use {Device, DispatchIndirectArgs, DrawIndexedIndirectArgs, DrawIndirectArgs, INDIRECT_ARGS_ALIGNMENT};
+use util::{as_bytes, round_up};
+
+/// A type of indirect arguments, declared with the same name and layout in `SHADER_HEADER`.
+pub trait IndirectArgs { }
+
+impl IndirectArgs for DrawIndirectArgs { }
+impl IndirectArgs for DrawIndexedIndirectArgs { }
+impl IndirectArgs for DispatchIndirectArgs { }
+
+/// The distance between consecutive `T`s in an array of arguments, which is what
+/// `multi_draw_indexed_indirect_count` and shaders writing arrays expect.
+pub fn stride<T: IndirectArgs>() -> uint {
+    round_up(std::mem::size_of::<T>(), INDIRECT_ARGS_ALIGNMENT)
+}
+
+/// Arguments of one type, packed for upload.
+pub struct IndirectArgsBuilder<T> {
+    data: Vec<u8>,
+}
+
+impl<T: IndirectArgs> IndirectArgsBuilder<T> {
+    pub fn new() -> IndirectArgsBuilder<T> {
+        IndirectArgsBuilder { data: Vec::new() }
+    }
+
+    /// Add `args` after the arguments added before, returning their byte offset.
+    pub fn push(&mut self, args: T) -> uint {
+        let offset = self.data.len();
+        self.data.push_all(as_bytes(&args));
+        self.data.grow(stride::<T>() - std::mem::size_of::<T>(), 0);
+        offset
+    }
+
+    /// Add `count` arguments, all zero, returning the offset of the first. Zeroed draws and
+    /// dispatches do nothing, which is what a GPU pass which fills in only some of an array
+    /// should start from.
+    pub fn push_zeroed(&mut self, count: uint) -> uint {
+        let offset = self.data.len();
+        self.data.grow(count * stride::<T>(), 0);
+        offset
+    }
+
+    /// The number of arguments added.
+    pub fn len(&self) -> uint {
+        self.data.len() / stride::<T>()
+    }
+
+    /// The packed arguments.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    /// Create a buffer holding exactly the packed arguments.
+    pub fn create_buffer<D: Device>(&self, device: &mut D) -> Result<D::Buffer, D::BufferCreationError> {
+        let buffer = try!(device.create_buffer(self.data.len(), None));
+        device.write_buffer(&buffer, 0, self.data.as_slice());
+        Ok(buffer)
+    }
+
+    /// Write the packed arguments into `buffer` at `offset`, which must be a multiple of
+    /// `INDIRECT_ARGS_ALIGNMENT`. The offsets `push` returned are then relative to `offset`.
+    pub fn write<D: Device>(&self, device: &mut D, buffer: &D::Buffer, offset: uint) {
+        assert!(offset % INDIRECT_ARGS_ALIGNMENT == 0, "misaligned indirect arguments offset {}", offset);
+        device.write_buffer(buffer, offset, self.data.as_slice());
+    }
+
+    /// Forget every argument, keeping the allocation.
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+}
+
+/// Metal shading language declarations of the indirect argument types, to prepend to the source
+/// of shaders which write them.
+pub const SHADER_HEADER: &'static str = r#"
+struct DrawIndirectArgs {
+    uint vertex_count;
+    uint instance_count;
+    uint first_vertex;
+    uint base_instance;
+};
+
+struct DrawIndexedIndirectArgs {
+    uint index_count;
+    uint instance_count;
+    uint first_index;
+    int base_vertex;
+    uint base_instance;
+};
+
+struct DispatchIndirectArgs {
+    uint threadgroups_x;
+    uint threadgroups_y;
+    uint threadgroups_z;
+};
+"#;
@@ -0,0 +1,199 @@
+//! Measuring the latency of frames, from the input they react to until they are displayed.
+//!
+//! The application marks the points every frame passes through with a `LatencyTracker`, which
+//! hands the markers on to the device (for the platform's latency mechanism, with the
+//! `latency_markers` device feature) and times them on the CPU either way:
+//!
+//! ```ignore
+//! let mut latency = LatencyTracker::new(120);
+//!
+//! // every frame
+//! device.latency_sleep();
+//! latency.mark(&mut device, frame, LatencyMarker::InputSample);
+//! let input = window.poll_events();
+//! latency.mark(&mut device, frame, LatencyMarker::SimulationStart);
+//! game.update(input);
+//! latency.mark(&mut device, frame, LatencyMarker::SimulationEnd);
+//! latency.mark(&mut device, frame, LatencyMarker::RenderSubmitStart);
+//! commands.add_completed_handler(latency.completed_handler(frame));
+//! commands.commit();
+//! latency.mark(&mut device, frame, LatencyMarker::RenderSubmitEnd);
+//! latency.mark(&mut device, frame, LatencyMarker::PresentStart);
+//! window.present();
+//! latency.mark(&mut device, frame, LatencyMarker::PresentEnd);
+//! latency.collect();
+//! ```
+//!
+//! A frame is reported (`history`) once its last command buffer has completed, timed from its
+//! input sample. When the frame actually reached the display is only known to the platform: its
+//! present timing feedback, or a photodiode on the screen for the real thing. Whatever the
+//! application gets from there, converted to the `time::precise_time_ns` clock (as
+//! `TimestampCalibration` does for GPU times), goes to `set_display_time`, and completes the
+//! report with the present to photon part.
+
+use std::sync::{Arc, Mutex};
+
+use {Device, CommandBufferStatus, LatencyMarker};
+
+/// The latency of a frame, in nanoseconds from its `InputSample` marker to each later point.
+/// Points whose marker was not set are `None`.
+#[deriving(Clone, Show)]
+pub struct FrameLatency {
+    pub frame: u64,
+    pub simulation_end_ns: Option<u64>,
+    /// To `RenderSubmitEnd`.
+    pub submit_ns: Option<u64>,
+    /// To `PresentEnd`.
+    pub present_ns: Option<u64>,
+    /// To the completion of the command buffer the frame's `completed_handler` was added to.
+    pub gpu_complete_ns: Option<u64>,
+    /// To the frame's display time, once it is set.
+    pub display_ns: Option<u64>,
+}
+
+impl FrameLatency {
+    /// From the frame being handed to the presentation layer until it was displayed.
+    pub fn present_to_display_ns(&self) -> Option<u64> {
+        match (self.present_ns, self.display_ns) {
+            (Some(present), Some(display)) if display >= present => Some(display - present),
+            _ => None,
+        }
+    }
+}
+
+struct PendingFrame {
+    frame: u64,
+    /// The CPU time of each marker, indexed as `marker_index` does.
+    markers: [Option<u64>, ..7],
+    /// When the frame's last command buffer completed, set from its completed handler.
+    completed: Arc<Mutex<Option<u64>>>,
+    has_handler: bool,
+}
+
+/// Times the markers of frames. See the module documentation.
+pub struct LatencyTracker {
+    pending: Vec<PendingFrame>,
+    /// The reports of resolved frames, oldest first.
+    history: Vec<FrameLatency>,
+    /// The CPU time of the input sample of each frame in `history`, which display times are
+    /// measured from.
+    inputs: Vec<Option<u64>>,
+    capacity: uint,
+}
+
+fn marker_index(marker: &LatencyMarker) -> uint {
+    match *marker {
+        LatencyMarker::InputSample => 0,
+        LatencyMarker::SimulationStart => 1,
+        LatencyMarker::SimulationEnd => 2,
+        LatencyMarker::RenderSubmitStart => 3,
+        LatencyMarker::RenderSubmitEnd => 4,
+        LatencyMarker::PresentStart => 5,
+        LatencyMarker::PresentEnd => 6,
+    }
+}
+
+impl LatencyTracker {
+    /// Keep the reports of the last `capacity` frames.
+    pub fn new(capacity: uint) -> LatencyTracker {
+        LatencyTracker { pending: Vec::new(), history: Vec::new(), inputs: Vec::new(), capacity: capacity }
+    }
+
+    /// Mark that `frame` reached `marker`, now, on `device` as with `Device::set_latency_marker`
+    /// and in the tracker.
+    pub fn mark<D: Device>(&mut self, device: &mut D, frame: u64, marker: LatencyMarker) {
+        let now = std::time::precise_time_ns();
+        device.set_latency_marker(frame, marker.clone());
+        self.pending_frame(frame).markers[marker_index(&marker)] = Some(now);
+    }
+
+    /// A completed handler for the last command buffer of `frame`, which the frame's GPU
+    /// latency is timed to.
+    pub fn completed_handler(&mut self, frame: u64) -> Box<FnOnce(CommandBufferStatus) + Send> {
+        let pending = self.pending_frame(frame);
+        pending.has_handler = true;
+        let completed = pending.completed.clone();
+        box move |_: CommandBufferStatus| {
+            *completed.lock() = Some(std::time::precise_time_ns());
+        }
+    }
+
+    /// Report the frames which are done: those whose command buffer completed, and those which
+    /// were presented without a completed handler. Call this once a frame.
+    pub fn collect(&mut self) {
+        let mut index = 0;
+        while index < self.pending.len() {
+            let done = {
+                let pending = &self.pending[index];
+                if pending.has_handler {
+                    pending.completed.lock().is_some()
+                } else {
+                    pending.markers[marker_index(&LatencyMarker::PresentEnd)].is_some()
+                }
+            };
+            if !done {
+                index += 1;
+                continue;
+            }
+            let pending = self.pending.remove(index).unwrap();
+            let input = pending.markers[marker_index(&LatencyMarker::InputSample)];
+            let since_input = |time: Option<u64>| match (input, time) {
+                (Some(input), Some(time)) if time >= input => Some(time - input),
+                _ => None,
+            };
+            let completed = *pending.completed.lock();
+            self.history.push(FrameLatency {
+                frame: pending.frame,
+                simulation_end_ns: since_input(pending.markers[marker_index(&LatencyMarker::SimulationEnd)]),
+                submit_ns: since_input(pending.markers[marker_index(&LatencyMarker::RenderSubmitEnd)]),
+                present_ns: since_input(pending.markers[marker_index(&LatencyMarker::PresentEnd)]),
+                gpu_complete_ns: since_input(completed),
+                display_ns: None,
+            });
+            self.inputs.push(input);
+        }
+        while self.history.len() > self.capacity {
+            self.history.remove(0);
+            self.inputs.remove(0);
+        }
+    }
+
+    /// Set when `frame` was displayed, in nanoseconds of the `time::precise_time_ns` clock. See
+    /// the module documentation. Frames no longer in the history are ignored.
+    pub fn set_display_time(&mut self, frame: u64, display_ns: u64) {
+        let position = self.history.iter().position(|latency| latency.frame == frame);
+        if let Some(index) = position {
+            if let Some(input) = self.inputs[index] {
+                if display_ns >= input {
+                    self.history[index].display_ns = Some(display_ns - input);
+                }
+            }
+        }
+    }
+
+    /// The reports of the last frames, oldest first.
+    pub fn history(&self) -> &[FrameLatency] {
+        self.history.as_slice()
+    }
+
+    /// The report of the last frame to be resolved.
+    pub fn latest(&self) -> Option<&FrameLatency> {
+        self.history.last()
+    }
+
+    fn pending_frame(&mut self, frame: u64) -> &mut PendingFrame {
+        let index = match self.pending.iter().position(|pending| pending.frame == frame) {
+            Some(index) => index,
+            None => {
+                self.pending.push(PendingFrame {
+                    frame: frame,
+                    markers: [None, ..7],
+                    completed: Arc::new(Mutex::new(None)),
+                    has_handler: false,
+                });
+                self.pending.len() - 1
+            }
+        };
+        &mut self.pending[index]
+    }
+}
@@ -0,0 +1,240 @@
+//! GPU level of detail selection.
+//!
+//! Each mesh comes in levels of detail, finest first, each with the geometric error its
+//! simplification introduced. Every frame, a compute pass projects that error onto the screen
+//! for every object, picks the coarsest level whose error stays under a threshold in pixels, and
+//! appends the object's draw at that level as indirect draw arguments, which are then drawn with
+//! `multi_draw_indexed_indirect_count`, as `util::occlusion_culling` does:
+//!
+//! ```ignore
+//! selector.begin(&mut blit);
+//! selector.select(&mut compute, &objects, &lods, object_count, &view);
+//! selector.draw(&mut render, PrimitiveType::Triangle, object_count);
+//! ```
+//!
+//! The level chosen for each object is also kept in `selected_lods`, one `u32` per object, for
+//! passes which need it before drawing: skinning only the vertices of the chosen level of a
+//! skinned mesh (see `util::skinning`), say.
+//!
+//! Frustum and occlusion culling are separate passes. To combine them with selection, cull
+//! first and select from the survivors, or write `LodObject`s only for visible objects.
+
+use {Device, BlitCommandEncoder, BufferSlice, ComputeCommandEncoder, RenderCommandEncoder};
+use {DrawIndexedIndirectArgs, PrimitiveType, Size};
+use util::as_bytes;
+
+/// The number of threads in each selection threadgroup.
+const THREADS_PER_THREADGROUP: uint = 64;
+
+/// A level of detail of a mesh, as laid out in the levels buffer.
+#[repr(C)]
+pub struct LodLevel {
+    /// The indices of the level, in the index buffer all levels are drawn from.
+    pub first_index: u32,
+    pub index_count: u32,
+    pub base_vertex: i32,
+    /// How far the level's surface is from the finest level's, at most, in the units of the
+    /// mesh. Levels of a mesh are stored finest first, with non-decreasing errors; the finest
+    /// level usually has an error of `0`.
+    pub error: f32,
+}
+
+/// An object to select a level for, as laid out in the objects buffer.
+#[repr(C)]
+pub struct LodObject {
+    /// The bounding sphere of the object in world space: the center in `xyz` and the radius in
+    /// `w`.
+    pub sphere: [f32, ..4],
+    /// The index of the object's finest level in the levels buffer, and the number of levels.
+    pub first_lod: u32,
+    pub lod_count: u32,
+    /// The base instance to draw the object with, to find its per-instance data by.
+    pub base_instance: u32,
+    /// The scale from the units of the mesh to world space, which errors are multiplied by.
+    pub scale: f32,
+}
+
+/// The camera levels are selected for.
+pub struct LodView {
+    /// The position of the camera in world space.
+    pub camera_position: [f32, ..3],
+    /// The `y` scale term of the projection matrix (`P[1][1]`).
+    pub projection_scale: f32,
+    /// The height of the viewport, in pixels.
+    pub viewport_height: f32,
+    /// The largest error allowed on screen, in pixels. About `1.0` is indistinguishable from the
+    /// finest level.
+    pub error_threshold: f32,
+}
+
+#[repr(C)]
+struct LodUniforms {
+    camera_position: [f32, ..3],
+    /// Converts an error over a distance into pixels.
+    error_to_pixels: f32,
+    error_threshold: f32,
+    object_count: u32,
+    _padding: [u32, ..2],
+}
+
+/// The GPU state for selecting the levels of up to `capacity` objects.
+pub struct LodSelector<D: Device> {
+    pipeline: D::ComputePipeline,
+    /// One `u32` per object, the level chosen by the last `select`.
+    selected: D::Buffer,
+    /// The indirect draw arguments written by the last `select`.
+    draws: D::Buffer,
+    /// The number of valid entries in `draws`.
+    count: D::Buffer,
+    capacity: uint,
+}
+
+impl<D: Device> LodSelector<D> {
+    /// Create the selection state for up to `capacity` objects.
+    ///
+    /// `pipeline` must be a compute pipeline created from the `select_lods` function of
+    /// `SOURCE`.
+    pub fn new(device: &mut D, pipeline: D::ComputePipeline, capacity: uint) -> Result<LodSelector<D>, D::BufferCreationError> {
+        let draw_size = std::mem::size_of::<DrawIndexedIndirectArgs>();
+        let selected = try!(device.create_buffer(capacity * 4, None));
+        let draws = try!(device.create_buffer(capacity * draw_size, None));
+        let count = try!(device.create_buffer(4, None));
+        Ok(LodSelector {
+            pipeline: pipeline,
+            selected: selected,
+            draws: draws,
+            count: count,
+            capacity: capacity,
+        })
+    }
+
+    /// The maximum number of objects levels can be selected for at once.
+    pub fn capacity(&self) -> uint {
+        self.capacity
+    }
+
+    /// The level chosen for each object by the last `select`, one `u32` per object: the index of
+    /// the level among the object's levels, `0` being the finest.
+    pub fn selected_lods(&self) -> &D::Buffer {
+        &self.selected
+    }
+
+    /// Prepare for a selection by clearing the previous selection's draws.
+    pub fn begin<E: BlitCommandEncoder<D>>(&self, encoder: &mut E) {
+        let draw_size = std::mem::size_of::<DrawIndexedIndirectArgs>();
+        encoder.fill_buffer(BufferSlice::new(&self.count, 0, 4), 0);
+        // as for `OcclusionCuller::begin_phase`, for the non-native multi-draw fallback
+        encoder.fill_buffer(BufferSlice::new(&self.draws, 0, self.capacity * draw_size), 0);
+    }
+
+    /// Select levels for `object_count` `LodObject`s from `objects`, whose levels are the
+    /// `LodLevel`s of `lods`.
+    pub fn select<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, objects: &D::Buffer, lods: &D::Buffer,
+                                               object_count: uint, view: &LodView) {
+        enter_span!("LodSelector::select", objects = object_count);
+        assert!(object_count <= self.capacity);
+        let uniforms = LodUniforms {
+            camera_position: view.camera_position,
+            error_to_pixels: view.projection_scale * view.viewport_height * 0.5,
+            error_threshold: view.error_threshold,
+            object_count: object_count as u32,
+            _padding: [0, ..2],
+        };
+
+        encoder.set_compute_pipeline(&self.pipeline);
+        encoder.set_buffer(BufferSlice::whole(objects), 0);
+        encoder.set_buffer(BufferSlice::whole(lods), 1);
+        encoder.set_buffer(BufferSlice::whole(&self.selected), 2);
+        encoder.set_buffer(BufferSlice::whole(&self.draws), 3);
+        encoder.set_buffer(BufferSlice::whole(&self.count), 4);
+        encoder.set_bytes(as_bytes(&uniforms), 5);
+        let groups = (object_count + THREADS_PER_THREADGROUP - 1) / THREADS_PER_THREADGROUP;
+        encoder.dispatch_threadgroups(Size { width: groups, height: 1, depth: 1 },
+                                      Size { width: THREADS_PER_THREADGROUP, height: 1, depth: 1 });
+    }
+
+    /// Draw the objects at the levels the last `select` chose.
+    ///
+    /// `max_draws` should be the `object_count` that was selected for. The index and vertex
+    /// buffers the levels refer to must already be bound.
+    pub fn draw<E: RenderCommandEncoder<D>>(&self, encoder: &mut E, primitive: PrimitiveType, max_draws: uint) {
+        encoder.multi_draw_indexed_indirect_count(primitive, &self.draws, 0, &self.count, 0, max_draws);
+    }
+}
+
+/// The metal shading language source of the selection kernel.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct DrawArgs {
+    uint index_count;
+    uint instance_count;
+    uint first_index;
+    int base_vertex;
+    uint base_instance;
+};
+
+struct LodLevel {
+    uint first_index;
+    uint index_count;
+    int base_vertex;
+    float error;
+};
+
+struct LodObject {
+    float4 sphere;
+    uint first_lod;
+    uint lod_count;
+    uint base_instance;
+    float scale;
+};
+
+struct LodUniforms {
+    packed_float3 camera_position;
+    float error_to_pixels;
+    float error_threshold;
+    uint object_count;
+    uint padding[2];
+};
+
+kernel void select_lods(const device LodObject *objects [[buffer(0)]],
+                        const device LodLevel *lods [[buffer(1)]],
+                        device uint *selected [[buffer(2)]],
+                        device DrawArgs *draws [[buffer(3)]],
+                        device atomic_uint *draw_count [[buffer(4)]],
+                        constant LodUniforms &u [[buffer(5)]],
+                        uint id [[thread_position_in_grid]])
+{
+    if (id >= u.object_count) {
+        return;
+    }
+
+    LodObject object = objects[id];
+    if (object.lod_count == 0) {
+        return;
+    }
+
+    // the error is projected from the nearest point of the bounding sphere, so that no part of
+    // the object shows more of it than allowed
+    float distance = max(length(object.sphere.xyz - float3(u.camera_position)) - object.sphere.w, 1e-4f);
+    float pixels_per_unit = object.scale * u.error_to_pixels / distance;
+
+    uint lod = 0;
+    for (uint i = 1; i < object.lod_count; i++) {
+        if (lods[object.first_lod + i].error * pixels_per_unit > u.error_threshold) {
+            break;
+        }
+        lod = i;
+    }
+    selected[id] = lod;
+
+    LodLevel level = lods[object.first_lod + lod];
+    uint slot = atomic_fetch_add_explicit(draw_count, 1, memory_order_relaxed);
+    draws[slot].index_count = level.index_count;
+    draws[slot].instance_count = 1;
+    draws[slot].first_index = level.first_index;
+    draws[slot].base_vertex = level.base_vertex;
+    draws[slot].base_instance = object.base_instance;
+}
+"#;
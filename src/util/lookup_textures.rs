@@ -0,0 +1,651 @@
+//! Generators for the standard lookup textures of lighting and post-processing.
+//!
+//! Everything here runs on the GPU through compute pipelines, so nothing has to be shipped with an
+//! application or baked offline:
+//!
+//! * `NoiseTexture`: tileable 2D or 3D gradient noise with a full mip chain.
+//! * `BlueNoise`: a tileable blue noise dither texture, generated with void-and-cluster.
+//! * `BrdfLut`: the split-sum GGX environment BRDF table of image based lighting.
+//! * `encode_apply_lut`: color grading an image through a 3D lookup table.
+//!
+//! As with `DepthPyramid`, generators take the compute pipelines they need, each created from the
+//! function in `SOURCE` with the name given in their documentation. They are created once,
+//! encoded whenever their texture needs (re)generating, and keep the texture around.
+
+use {Device, ComputeCommandEncoder, PixelFormat, Size, TextureDescriptor, TextureType};
+use {BufferSlice, TextureUsage, TextureViewDescriptor};
+use texture::math::mip_level_count;
+use util::as_bytes;
+
+/// The width and height of the threadgroups of 2D kernels.
+const THREADGROUP_SIZE: uint = 8;
+/// The width, height and depth of the threadgroups of 3D kernels.
+const THREADGROUP_SIZE_3D: uint = 4;
+
+/// A conservative number of texels for `BlueNoise::encode_chunk` to pick per chunk: 2048
+/// dispatches, a sixteenth of a 128x128 texture.
+pub const BLUE_NOISE_RANKS_PER_CHUNK: uint = 1024;
+
+/// The number of threads `blue_noise_find` is dispatched with. Must match the kernel.
+const FIND_THREADS: uint = 1024;
+
+fn threadgroups(size: uint, group: uint) -> uint {
+    (size + group - 1) / group
+}
+
+fn dispatch_2d<D: Device, E: ComputeCommandEncoder<D>>(encoder: &mut E, width: uint, height: uint) {
+    encoder.dispatch_threadgroups(Size { width: threadgroups(width, THREADGROUP_SIZE),
+                                         height: threadgroups(height, THREADGROUP_SIZE),
+                                         depth: 1 },
+                                  Size { width: THREADGROUP_SIZE, height: THREADGROUP_SIZE, depth: 1 });
+}
+
+fn dispatch_3d<D: Device, E: ComputeCommandEncoder<D>>(encoder: &mut E, size: uint) {
+    let groups = threadgroups(size, THREADGROUP_SIZE_3D);
+    encoder.dispatch_threadgroups(Size { width: groups, height: groups, depth: groups },
+                                  Size { width: THREADGROUP_SIZE_3D, height: THREADGROUP_SIZE_3D, depth: THREADGROUP_SIZE_3D });
+}
+
+fn storage_texture<D: Device>(device: &mut D, texture_type: TextureType, format: PixelFormat, size: uint,
+                              depth: uint, levels: uint) -> Result<D::Texture, D::TextureCreationError> {
+    device.create_texture(TextureDescriptor {
+        texture_type: texture_type,
+        pixel_format: format,
+        width: size,
+        height: size,
+        depth: depth,
+        mipmap_level_count: levels,
+        array_length: 1,
+        sample_count: 1,
+        usage: TextureUsage { shader_read: true, shader_write: true, render_target: false },
+    })
+}
+
+/// The dimensionality of a `NoiseTexture`.
+pub enum NoiseDimensions {
+    Two,
+    Three,
+}
+
+#[repr(C)]
+struct NoiseUniforms {
+    size: u32,
+    /// The number of noise cells across the texture at the first octave.
+    period: u32,
+    octaves: u32,
+    seed: u32,
+}
+
+/// A square (or cubic) single channel texture of tileable fractal gradient noise in `[0, 1]`,
+/// with every mip level.
+///
+/// Lower levels are box filtered from the level above, not generated with fewer octaves, so
+/// sampling with trilinear filtering behaves exactly like for an authored texture.
+pub struct NoiseTexture<D: Device> {
+    generate: D::ComputePipeline,
+    downsample: D::ComputePipeline,
+    dimensions: NoiseDimensions,
+    texture: D::Texture,
+    /// A single level view of each level of `texture`.
+    levels: Vec<D::Texture>,
+    size: uint,
+    period: uint,
+    octaves: uint,
+}
+
+impl<D: Device> NoiseTexture<D> {
+    /// Create a `size` texel noise texture, in `R16Float` format.
+    ///
+    /// For two dimensional noise, `generate` must be created from `noise_2d` and `downsample`
+    /// from `downsample_2d`; for three dimensional noise, from `noise_3d` and `downsample_3d`.
+    /// `period` noise cells span the texture at the first of `octaves` octaves, doubling with
+    /// every further octave. `size` should be a multiple of `period` for the noise to be smooth
+    /// where it tiles.
+    pub fn new(device: &mut D, generate: D::ComputePipeline, downsample: D::ComputePipeline, dimensions: NoiseDimensions,
+               size: uint, period: uint, octaves: uint) -> Result<NoiseTexture<D>, D::TextureCreationError> {
+        let level_count = mip_level_count(size, size, 1);
+        let texture = try!(match dimensions {
+            NoiseDimensions::Two => storage_texture(device, TextureType::Type2D, PixelFormat::R16Float, size, 1, level_count),
+            NoiseDimensions::Three => storage_texture(device, TextureType::Type3D, PixelFormat::R16Float, size, size, level_count),
+        });
+
+        let mut levels = Vec::with_capacity(level_count);
+        for level in range(0, level_count) {
+            levels.push(try!(device.create_texture_view(&texture, TextureViewDescriptor {
+                pixel_format: PixelFormat::R16Float,
+                base_mipmap_level: level,
+                mipmap_level_count: 1,
+                base_array_slice: 0,
+                array_length: 1,
+            })));
+        }
+
+        Ok(NoiseTexture {
+            generate: generate,
+            downsample: downsample,
+            dimensions: dimensions,
+            texture: texture,
+            levels: levels,
+            size: size,
+            period: period,
+            octaves: octaves,
+        })
+    }
+
+    /// The noise texture, with every level.
+    pub fn texture(&self) -> &D::Texture {
+        &self.texture
+    }
+
+    /// Generate the noise for `seed`, and its mip chain.
+    pub fn encode<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, seed: u32) {
+        let uniforms = NoiseUniforms {
+            size: self.size as u32,
+            period: self.period as u32,
+            octaves: self.octaves as u32,
+            seed: seed,
+        };
+        encoder.set_compute_pipeline(&self.generate);
+        encoder.set_texture(&self.levels[0], 0);
+        encoder.set_bytes(as_bytes(&uniforms), 0);
+        match self.dimensions {
+            NoiseDimensions::Two => dispatch_2d(encoder, self.size, self.size),
+            NoiseDimensions::Three => dispatch_3d(encoder, self.size),
+        }
+
+        encoder.set_compute_pipeline(&self.downsample);
+        for level in range(1, self.levels.len()) {
+            let size = std::cmp::max(1, self.size >> level);
+            encoder.set_texture(&self.levels[level - 1], 0);
+            encoder.set_texture(&self.levels[level], 1);
+            encoder.set_bytes(as_bytes(&(size as u32)), 0);
+            match self.dimensions {
+                NoiseDimensions::Two => dispatch_2d(encoder, size, size),
+                NoiseDimensions::Three => dispatch_3d(encoder, size),
+            }
+        }
+    }
+}
+
+#[repr(C)]
+struct BlueNoiseUniforms {
+    size: u32,
+    count: u32,
+    rank: u32,
+    seed: u32,
+}
+
+/// A square, tileable blue noise texture in `R16Float` format, for dithering and for
+/// decorrelating stochastic effects between neighbouring pixels.
+///
+/// Every texel holds a distinct threshold in `(0, 1)`, ordered so that the texels below any
+/// threshold are spread as evenly as possible. Generation picks texels one at a time, so it takes
+/// two dispatches per texel: it is meant to be done once, at load time, and sizes are limited to
+/// 128x128.
+///
+/// At 128x128 that is over 32000 dispatches, which may run for longer than a GPU watchdog allows
+/// (see `Device::watchdog`) in one command buffer. `encode_chunk` spreads them over several
+/// command buffers instead, as `util::dispatch_splitting` does for single large dispatches:
+///
+/// ```ignore
+/// for chunk in range(0, noise.chunk_count(BLUE_NOISE_RANKS_PER_CHUNK)) {
+///     let mut commands = queue.acquire_command_buffer();
+///     let mut encoder = commands.compute_command_encoder();
+///     noise.encode_chunk(&mut encoder, seed, chunk, BLUE_NOISE_RANKS_PER_CHUNK);
+///     encoder.end_encoding();
+///     commands.commit();
+/// }
+/// ```
+pub struct BlueNoise<D: Device> {
+    init: D::ComputePipeline,
+    find: D::ComputePipeline,
+    splat: D::ComputePipeline,
+    resolve: D::ComputePipeline,
+    texture: D::Texture,
+    /// Per texel energy, and the rank assigned to each texel.
+    energy: D::Buffer,
+    ranks: D::Buffer,
+    /// The texel picked by the last `find`.
+    winner: D::Buffer,
+    size: uint,
+}
+
+/// Why a `BlueNoise` could not be created.
+pub enum BlueNoiseCreationError<D: Device> {
+    Texture(D::TextureCreationError),
+    Buffer(D::BufferCreationError),
+}
+
+impl<D: Device> BlueNoise<D> {
+    /// Create a `size` by `size` blue noise texture.
+    ///
+    /// The pipelines must be created from the `blue_noise_init`, `blue_noise_find`,
+    /// `blue_noise_splat` and `blue_noise_resolve` functions of `SOURCE`, respectively. `size` must
+    /// be at most 128.
+    pub fn new(device: &mut D, init: D::ComputePipeline, find: D::ComputePipeline, splat: D::ComputePipeline,
+               resolve: D::ComputePipeline, size: uint) -> Result<BlueNoise<D>, BlueNoiseCreationError<D>> {
+        assert!(size <= 128);
+        let texture = match storage_texture(device, TextureType::Type2D, PixelFormat::R16Float, size, 1, 1) {
+            Ok(texture) => texture,
+            Err(e) => return Err(BlueNoiseCreationError::Texture(e)),
+        };
+        let mut buffers = Vec::with_capacity(3);
+        for &length in [size * size * 4, size * size * 4, 4].iter() {
+            match device.create_buffer(length, None) {
+                Ok(buffer) => buffers.push(buffer),
+                Err(e) => return Err(BlueNoiseCreationError::Buffer(e)),
+            }
+        }
+        let winner = buffers.pop().unwrap();
+        let ranks = buffers.pop().unwrap();
+        let energy = buffers.pop().unwrap();
+
+        Ok(BlueNoise {
+            init: init,
+            find: find,
+            splat: splat,
+            resolve: resolve,
+            texture: texture,
+            energy: energy,
+            ranks: ranks,
+            winner: winner,
+            size: size,
+        })
+    }
+
+    /// The blue noise texture.
+    pub fn texture(&self) -> &D::Texture {
+        &self.texture
+    }
+
+    /// Generate the texture in one go. Different seeds give different, equally good, textures.
+    ///
+    /// On devices with a watchdog, use `encode_chunk` instead.
+    pub fn encode<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, seed: u32) {
+        let count = self.size * self.size;
+        self.encode_chunk(encoder, seed, 0, count);
+    }
+
+    /// The number of chunks `encode_chunk` splits generation into, with `ranks_per_chunk`
+    /// texels picked per chunk.
+    pub fn chunk_count(&self, ranks_per_chunk: uint) -> uint {
+        assert!(ranks_per_chunk > 0);
+        (self.size * self.size + ranks_per_chunk - 1) / ranks_per_chunk
+    }
+
+    /// Encode chunk `chunk` of `chunk_count(ranks_per_chunk)` of the generation, which picks the
+    /// texels of ranks `chunk * ranks_per_chunk` up to the next chunk's.
+    ///
+    /// The chunks must be encoded in order, with the same `seed` and `ranks_per_chunk`, and each
+    /// chunk's command buffer committed after the last one's; the texture is only complete after
+    /// the last chunk.
+    pub fn encode_chunk<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, seed: u32, chunk: uint, ranks_per_chunk: uint) {
+        let count = self.size * self.size;
+        let chunks = self.chunk_count(ranks_per_chunk);
+        assert!(chunk < chunks, "chunk {} of a blue noise generation of {} chunks", chunk, chunks);
+        let mut uniforms = BlueNoiseUniforms { size: self.size as u32, count: count as u32, rank: 0, seed: seed };
+        encoder.set_buffer(BufferSlice::whole(&self.energy), 1);
+        encoder.set_buffer(BufferSlice::whole(&self.ranks), 2);
+        encoder.set_buffer(BufferSlice::whole(&self.winner), 3);
+
+        if chunk == 0 {
+            encoder.set_compute_pipeline(&self.init);
+            encoder.set_bytes(as_bytes(&uniforms), 0);
+            dispatch_2d(encoder, self.size, self.size);
+        }
+
+        // void-and-cluster, starting from an empty pattern: the texel with the lowest energy (the
+        // largest void) gets the next rank, and spreads energy to its neighbours.
+        let first = chunk * ranks_per_chunk;
+        for rank in range(first, std::cmp::min(first + ranks_per_chunk, count)) {
+            uniforms.rank = rank as u32;
+            encoder.set_compute_pipeline(&self.find);
+            encoder.set_bytes(as_bytes(&uniforms), 0);
+            encoder.dispatch_threadgroups(Size { width: 1, height: 1, depth: 1 },
+                                          Size { width: FIND_THREADS, height: 1, depth: 1 });
+            encoder.set_compute_pipeline(&self.splat);
+            dispatch_2d(encoder, self.size, self.size);
+        }
+
+        if chunk == chunks - 1 {
+            encoder.set_compute_pipeline(&self.resolve);
+            encoder.set_bytes(as_bytes(&uniforms), 0);
+            encoder.set_texture(&self.texture, 0);
+            dispatch_2d(encoder, self.size, self.size);
+        }
+    }
+}
+
+/// The split-sum environment BRDF lookup table for GGX image based lighting.
+///
+/// The texture is `RG16Float`, indexed by `n·v` along `u` and roughness along `v`. The specular
+/// term of prefiltered environment lighting is `prefiltered * (f0 * lut.r + lut.g)`.
+pub struct BrdfLut<D: Device> {
+    pipeline: D::ComputePipeline,
+    texture: D::Texture,
+    size: uint,
+    sample_count: uint,
+}
+
+impl<D: Device> BrdfLut<D> {
+    /// Create a `size` by `size` table, integrated with `sample_count` samples per texel.
+    ///
+    /// `pipeline` must be created from the `brdf_lut` function of `SOURCE`. 32x32 with 512
+    /// samples is plenty for most uses.
+    pub fn new(device: &mut D, pipeline: D::ComputePipeline, size: uint, sample_count: uint) -> Result<BrdfLut<D>, D::TextureCreationError> {
+        let texture = try!(storage_texture(device, TextureType::Type2D, PixelFormat::RG16Float, size, 1, 1));
+        Ok(BrdfLut {
+            pipeline: pipeline,
+            texture: texture,
+            size: size,
+            sample_count: sample_count,
+        })
+    }
+
+    /// The lookup table.
+    pub fn texture(&self) -> &D::Texture {
+        &self.texture
+    }
+
+    /// Integrate the table.
+    pub fn encode<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E) {
+        let uniforms = [self.size as u32, self.sample_count as u32];
+        encoder.set_compute_pipeline(&self.pipeline);
+        encoder.set_texture(&self.texture, 0);
+        encoder.set_bytes(as_bytes(&uniforms), 0);
+        dispatch_2d(encoder, self.size, self.size);
+    }
+}
+
+/// Color grade `src` into `dst` through the 3D lookup table `lut`.
+///
+/// `pipeline` must be created from the `apply_lut` function of `SOURCE`. `src` and `dst` must be
+/// `width` by `height`, and `lut` a `Type3D` texture mapping input colors, with red along `x`,
+/// green along `y` and blue along `z`, to output colors. The table is interpolated linearly, and
+/// alpha is passed through.
+pub fn encode_apply_lut<D: Device, E: ComputeCommandEncoder<D>>(encoder: &mut E, pipeline: &D::ComputePipeline, src: &D::Texture,
+                                                                lut: &D::Texture, dst: &D::Texture, width: uint, height: uint) {
+    let size = [width as u32, height as u32];
+    encoder.set_compute_pipeline(pipeline);
+    encoder.set_texture(src, 0);
+    encoder.set_texture(lut, 1);
+    encoder.set_texture(dst, 2);
+    encoder.set_bytes(as_bytes(&size), 0);
+    dispatch_2d(encoder, width, height);
+}
+
+/// The metal shading language source of the generator kernels.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+static uint hash(uint x) {
+    x ^= x >> 16;
+    x *= 0x7feb352d;
+    x ^= x >> 15;
+    x *= 0x846ca68b;
+    x ^= x >> 16;
+    return x;
+}
+
+static float hash_float(uint x) {
+    return float(hash(x) >> 8) / 16777216.0f;
+}
+
+// noise
+
+struct NoiseUniforms {
+    uint size;
+    uint period;
+    uint octaves;
+    uint seed;
+};
+
+static float3 gradient(uint3 cell, uint seed) {
+    uint h = hash(cell.x ^ hash(cell.y ^ hash(cell.z ^ seed)));
+    float3 g = float3(hash_float(h), hash_float(h ^ 0x9e3779b9), hash_float(h ^ 0x7f4a7c15)) * 2.0f - 1.0f;
+    return normalize(g + 1e-6f);
+}
+
+static float fade(float t) {
+    return t * t * t * (t * (t * 6.0f - 15.0f) + 10.0f);
+}
+
+// gradient noise wrapping every `period` cells, in roughly [-1, 1]. 2D noise is the z = 0 slice.
+static float gradient_noise(float3 p, uint period, uint seed, bool three_d) {
+    float3 cell = floor(p);
+    float3 f = p - cell;
+    float3 u = float3(fade(f.x), fade(f.y), three_d ? fade(f.z) : 0.0f);
+    uint3 c = uint3(int3(cell));
+    float value = 0.0f;
+    for (uint i = 0; i < (three_d ? 8u : 4u); i++) {
+        uint3 corner = uint3(i & 1, (i >> 1) & 1, i >> 2);
+        float3 w = select(1.0f - u, u, bool3(corner));
+        float contribution = dot(gradient((c + corner) % period, seed), f - float3(corner));
+        value += w.x * w.y * w.z * contribution;
+    }
+    return value;
+}
+
+static float fractal_noise(float3 p, constant NoiseUniforms &u, bool three_d) {
+    float value = 0.0f, amplitude = 0.5f, total = 0.0f;
+    uint period = u.period;
+    for (uint octave = 0; octave < u.octaves; octave++) {
+        value += amplitude * gradient_noise(p * float(period), period, u.seed + octave, three_d);
+        total += amplitude;
+        amplitude *= 0.5f;
+        period *= 2;
+    }
+    return saturate(value / total * 0.5f + 0.5f);
+}
+
+kernel void noise_2d(texture2d<float, access::write> dst [[texture(0)]],
+                     constant NoiseUniforms &u [[buffer(0)]],
+                     uint2 id [[thread_position_in_grid]])
+{
+    if (any(id >= u.size)) {
+        return;
+    }
+    float3 p = float3((float2(id) + 0.5f) / float(u.size), 0.0f);
+    dst.write(float4(fractal_noise(p, u, false)), id);
+}
+
+kernel void noise_3d(texture3d<float, access::write> dst [[texture(0)]],
+                     constant NoiseUniforms &u [[buffer(0)]],
+                     uint3 id [[thread_position_in_grid]])
+{
+    if (any(id >= u.size)) {
+        return;
+    }
+    float3 p = (float3(id) + 0.5f) / float(u.size);
+    dst.write(float4(fractal_noise(p, u, true)), id);
+}
+
+kernel void downsample_2d(texture2d<float, access::read> src [[texture(0)]],
+                          texture2d<float, access::write> dst [[texture(1)]],
+                          constant uint &size [[buffer(0)]],
+                          uint2 id [[thread_position_in_grid]])
+{
+    if (any(id >= size)) {
+        return;
+    }
+    uint2 last = uint2(src.get_width() - 1, src.get_height() - 1);
+    float4 sum = 0.0f;
+    for (uint i = 0; i < 4; i++) {
+        sum += src.read(min(id * 2 + uint2(i & 1, i >> 1), last));
+    }
+    dst.write(sum * 0.25f, id);
+}
+
+kernel void downsample_3d(texture3d<float, access::read> src [[texture(0)]],
+                          texture3d<float, access::write> dst [[texture(1)]],
+                          constant uint &size [[buffer(0)]],
+                          uint3 id [[thread_position_in_grid]])
+{
+    if (any(id >= size)) {
+        return;
+    }
+    uint3 last = uint3(src.get_width() - 1, src.get_height() - 1, src.get_depth() - 1);
+    float4 sum = 0.0f;
+    for (uint i = 0; i < 8; i++) {
+        sum += src.read(min(id * 2 + uint3(i & 1, (i >> 1) & 1, i >> 2), last));
+    }
+    dst.write(sum * 0.125f, id);
+}
+
+// blue noise
+
+struct BlueNoiseUniforms {
+    uint size;
+    uint count;
+    uint rank;
+    uint seed;
+};
+
+constant uint UNRANKED = 0xffffffff;
+constant uint FIND_THREADS = 1024;
+
+kernel void blue_noise_init(constant BlueNoiseUniforms &u [[buffer(0)]],
+                            device float *energy [[buffer(1)]],
+                            device uint *ranks [[buffer(2)]],
+                            uint2 id [[thread_position_in_grid]])
+{
+    if (any(id >= u.size)) {
+        return;
+    }
+    uint i = id.y * u.size + id.x;
+    // a little white noise to break ties, so the first texels are not picked in scan order
+    energy[i] = hash_float(i ^ hash(u.seed)) * 1e-4f;
+    ranks[i] = UNRANKED;
+}
+
+kernel void blue_noise_find(constant BlueNoiseUniforms &u [[buffer(0)]],
+                            device const float *energy [[buffer(1)]],
+                            device const uint *ranks [[buffer(2)]],
+                            device uint *winner [[buffer(3)]],
+                            uint tid [[thread_index_in_threadgroup]])
+{
+    threadgroup float best_energy[FIND_THREADS];
+    threadgroup uint best_index[FIND_THREADS];
+
+    float e = INFINITY;
+    uint index = 0;
+    for (uint i = tid; i < u.count; i += FIND_THREADS) {
+        if (ranks[i] == UNRANKED && energy[i] < e) {
+            e = energy[i];
+            index = i;
+        }
+    }
+    best_energy[tid] = e;
+    best_index[tid] = index;
+
+    for (uint stride = FIND_THREADS / 2; stride > 0; stride /= 2) {
+        threadgroup_barrier(mem_flags::mem_threadgroup);
+        if (tid < stride && best_energy[tid + stride] < best_energy[tid]) {
+            best_energy[tid] = best_energy[tid + stride];
+            best_index[tid] = best_index[tid + stride];
+        }
+    }
+    if (tid == 0) {
+        winner[0] = best_index[0];
+    }
+}
+
+kernel void blue_noise_splat(constant BlueNoiseUniforms &u [[buffer(0)]],
+                             device float *energy [[buffer(1)]],
+                             device uint *ranks [[buffer(2)]],
+                             device const uint *winner [[buffer(3)]],
+                             uint2 id [[thread_position_in_grid]])
+{
+    if (any(id >= u.size)) {
+        return;
+    }
+    uint i = id.y * u.size + id.x;
+    uint w = winner[0];
+    if (i == w) {
+        ranks[i] = u.rank;
+    }
+    // toroidal distance, so the texture tiles
+    int2 d = abs(int2(id) - int2(w % u.size, w / u.size));
+    d = min(d, int2(u.size) - d);
+    const float sigma = 1.5f;
+    energy[i] += exp(-float(d.x * d.x + d.y * d.y) / (2.0f * sigma * sigma));
+}
+
+kernel void blue_noise_resolve(constant BlueNoiseUniforms &u [[buffer(0)]],
+                               device const uint *ranks [[buffer(2)]],
+                               texture2d<float, access::write> dst [[texture(0)]],
+                               uint2 id [[thread_position_in_grid]])
+{
+    if (any(id >= u.size)) {
+        return;
+    }
+    dst.write(float4((float(ranks[id.y * u.size + id.x]) + 0.5f) / float(u.count)), id);
+}
+
+// brdf lut
+
+static float2 hammersley(uint i, uint n) {
+    return float2(float(i) / float(n), float(reverse_bits(i)) * 2.3283064365386963e-10f);
+}
+
+static float3 importance_sample_ggx(float2 xi, float roughness) {
+    float a = roughness * roughness;
+    float phi = 2.0f * M_PI_F * xi.x;
+    float cos_theta = sqrt((1.0f - xi.y) / (1.0f + (a * a - 1.0f) * xi.y));
+    float sin_theta = sqrt(1.0f - cos_theta * cos_theta);
+    return float3(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+}
+
+static float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+    float k = roughness * roughness / 2.0f;
+    return n_dot_v / (n_dot_v * (1.0f - k) + k) * n_dot_l / (n_dot_l * (1.0f - k) + k);
+}
+
+kernel void brdf_lut(texture2d<float, access::write> dst [[texture(0)]],
+                     constant uint2 &u [[buffer(0)]],
+                     uint2 id [[thread_position_in_grid]])
+{
+    uint size = u.x, sample_count = u.y;
+    if (any(id >= size)) {
+        return;
+    }
+    float n_dot_v = (float(id.x) + 0.5f) / float(size);
+    float roughness = (float(id.y) + 0.5f) / float(size);
+    float3 v = float3(sqrt(1.0f - n_dot_v * n_dot_v), 0.0f, n_dot_v);
+
+    float2 sum = 0.0f;
+    for (uint i = 0; i < sample_count; i++) {
+        float3 h = importance_sample_ggx(hammersley(i, sample_count), roughness);
+        float3 l = 2.0f * dot(v, h) * h - v;
+        float n_dot_l = saturate(l.z);
+        float n_dot_h = saturate(h.z);
+        float v_dot_h = saturate(dot(v, h));
+        if (n_dot_l > 0.0f) {
+            float g_vis = geometry_smith(n_dot_v, n_dot_l, roughness) * v_dot_h / (n_dot_h * n_dot_v);
+            float fc = pow(1.0f - v_dot_h, 5.0f);
+            sum += float2((1.0f - fc) * g_vis, fc * g_vis);
+        }
+    }
+    dst.write(float4(sum / float(sample_count), 0.0f, 0.0f), id);
+}
+
+// 3d lut application
+
+kernel void apply_lut(texture2d<float, access::read> src [[texture(0)]],
+                      texture3d<float, access::sample> lut [[texture(1)]],
+                      texture2d<float, access::write> dst [[texture(2)]],
+                      constant uint2 &size [[buffer(0)]],
+                      uint2 id [[thread_position_in_grid]])
+{
+    constexpr sampler linear(filter::linear, address::clamp_to_edge);
+    if (any(id >= size)) {
+        return;
+    }
+    float4 color = src.read(id);
+    // map [0, 1] onto the centers of the first and last texels
+    float3 n = float3(lut.get_width(), lut.get_height(), lut.get_depth());
+    float3 uvw = saturate(color.rgb) * (n - 1.0f) / n + 0.5f / n;
+    dst.write(float4(lut.sample(linear, uvw).rgb, color.a), id);
+}
+"#;
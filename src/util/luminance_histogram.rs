@@ -0,0 +1,303 @@
+//! Luminance histograms of HDR targets, and the exposure adaptation auto-exposure is built on.
+//!
+//! Auto-exposure needs to know how bright the frame is, which is a reduction of every pixel of
+//! the HDR target and so belongs on the GPU: reading the target back would stall a frame. The
+//! usual way, and the one here, is to bin the log luminance of every pixel into a histogram,
+//! average the bins between two percentiles (so that a few very dark or very bright pixels, the
+//! sun or a shadowed corner, do not swing the exposure), and ease the adapted luminance towards
+//! that average over time, as eyes do:
+//!
+//! ```ignore
+//! let mut histogram = try!(LuminanceHistogram::new(&mut device, pipelines));
+//! // after the lighting passes, before tonemapping
+//! histogram.encode(&mut compute, &hdr_target, width, height, frame_seconds);
+//! // the tonemapping pass binds histogram.exposure() and reads it with SHADER_HEADER's functions
+//! ```
+//!
+//! Both passes stay on the GPU, and the results are only ever read by later passes (or read back
+//! with `util::readback_heap`, for a debug overlay): the exposure a frame is tonemapped with was
+//! computed from that frame's own target.
+
+use {Device, ComputeCommandEncoder, BufferSlice, Size};
+use util::as_bytes;
+
+/// The number of bins of the histogram. Bin `0` counts the pixels darker than the range,
+/// which are left out of the average, and the others split the log2 luminance range evenly.
+pub const HISTOGRAM_BINS: uint = 256;
+
+/// The width and height of each threadgroup of the histogram pass, whose threads clear and
+/// merge a bin each.
+const THREADGROUP_SIZE: uint = 16;
+
+/// The pipelines a `LuminanceHistogram` dispatches, each created from the function of `SOURCE`
+/// its field is named after.
+pub struct LuminanceHistogramPipelines<D: Device> {
+    pub build_histogram: D::ComputePipeline,
+    pub adapt_exposure: D::ComputePipeline,
+}
+
+/// How luminance is binned and the exposure adapts.
+#[deriving(Clone, Show)]
+pub struct ExposureSettings {
+    /// The log2 luminance range of the histogram. Pixels outside of it fall into the first or
+    /// last bin.
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    /// The pixels averaged, as fractions of the pixels counted from the darkest: the darkest
+    /// `low_percentile` of them and those brighter than `high_percentile` of them are left out.
+    pub low_percentile: f32,
+    pub high_percentile: f32,
+    /// How fast the adapted luminance follows the average, per second, when the frame gets
+    /// brighter and darker. Eyes adapt to brightness faster.
+    pub speed_up: f32,
+    pub speed_down: f32,
+    /// The luminance the adapted luminance is exposed to: the exposure is `key / adapted`.
+    pub key: f32,
+}
+
+impl Default for ExposureSettings {
+    fn default() -> ExposureSettings {
+        ExposureSettings {
+            min_log_luminance: -10.0,
+            max_log_luminance: 6.0,
+            low_percentile: 0.5,
+            high_percentile: 0.95,
+            speed_up: 3.0,
+            speed_down: 1.0,
+            key: 0.18,
+        }
+    }
+}
+
+#[repr(C)]
+struct HistogramUniforms {
+    size: [u32, ..2],
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    low_percentile: f32,
+    high_percentile: f32,
+    speed_up: f32,
+    speed_down: f32,
+    key: f32,
+    delta_time: f32,
+    reset: u32,
+    _padding: u32,
+}
+
+/// The size of the exposure state, declared as `ExposureState` in `SHADER_HEADER`: the average
+/// luminance of the last frame, the adapted luminance, and the exposure.
+pub const EXPOSURE_STATE_SIZE: uint = 16;
+
+/// The histogram and exposure of a series of frames. See the module documentation.
+pub struct LuminanceHistogram<D: Device> {
+    pipelines: LuminanceHistogramPipelines<D>,
+    settings: ExposureSettings,
+    /// `HISTOGRAM_BINS` `u32`s, cleared by the adaptation pass once it has read them.
+    histogram: D::Buffer,
+    exposure: D::Buffer,
+    reset: bool,
+}
+
+impl<D: Device> LuminanceHistogram<D> {
+    pub fn new(device: &mut D, pipelines: LuminanceHistogramPipelines<D>)
+               -> Result<LuminanceHistogram<D>, D::BufferCreationError> {
+        let histogram = try!(device.create_buffer(HISTOGRAM_BINS * 4, None));
+        device.write_buffer(&histogram, 0, Vec::from_elem(HISTOGRAM_BINS * 4, 0u8).as_slice());
+        let exposure = try!(device.create_buffer(EXPOSURE_STATE_SIZE, None));
+        device.write_buffer(&exposure, 0, Vec::from_elem(EXPOSURE_STATE_SIZE, 0u8).as_slice());
+        Ok(LuminanceHistogram {
+            pipelines: pipelines,
+            settings: Default::default(),
+            histogram: histogram,
+            exposure: exposure,
+            reset: true,
+        })
+    }
+
+    pub fn settings(&self) -> &ExposureSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ExposureSettings) {
+        assert!(settings.min_log_luminance < settings.max_log_luminance, "an empty log luminance range");
+        self.settings = settings;
+    }
+
+    /// The histogram, `HISTOGRAM_BINS` `u32` pixel counts. It only holds the counts of a frame
+    /// between the two passes of `encode`, as the adaptation pass clears it for the next frame.
+    pub fn histogram(&self) -> &D::Buffer {
+        &self.histogram
+    }
+
+    /// The `ExposureState` of the last frame encoded, for the tonemapping pass to bind.
+    pub fn exposure(&self) -> &D::Buffer {
+        &self.exposure
+    }
+
+    /// Make the next frame adapt at once instead of easing in, after a camera cut or teleport.
+    pub fn reset(&mut self) {
+        self.reset = true;
+    }
+
+    /// Bin the luminance of the `width` by `height` texels of `hdr`, a linear color texture
+    /// created with the `shader_read` usage, and adapt the exposure over `delta_time` seconds.
+    pub fn encode<E: ComputeCommandEncoder<D>>(&mut self, encoder: &mut E, hdr: &D::Texture, width: uint, height: uint,
+                                               delta_time: f32) {
+        enter_span!("LuminanceHistogram::encode", width = width, height = height);
+        let settings = self.settings.clone();
+        let uniforms = HistogramUniforms {
+            size: [width as u32, height as u32],
+            min_log_luminance: settings.min_log_luminance,
+            log_luminance_range: settings.max_log_luminance - settings.min_log_luminance,
+            low_percentile: settings.low_percentile,
+            high_percentile: settings.high_percentile,
+            speed_up: settings.speed_up,
+            speed_down: settings.speed_down,
+            key: settings.key,
+            delta_time: delta_time,
+            reset: self.reset as u32,
+            _padding: 0,
+        };
+        self.reset = false;
+
+        encoder.set_compute_pipeline(&self.pipelines.build_histogram);
+        encoder.set_texture(hdr, 0);
+        encoder.set_buffer(BufferSlice::whole(&self.histogram), 0);
+        encoder.set_bytes(as_bytes(&uniforms), 1);
+        encoder.dispatch_threadgroups(Size { width: (width + THREADGROUP_SIZE - 1) / THREADGROUP_SIZE,
+                                             height: (height + THREADGROUP_SIZE - 1) / THREADGROUP_SIZE,
+                                             depth: 1 },
+                                      Size { width: THREADGROUP_SIZE, height: THREADGROUP_SIZE, depth: 1 });
+
+        encoder.set_compute_pipeline(&self.pipelines.adapt_exposure);
+        encoder.set_buffer(BufferSlice::whole(&self.histogram), 0);
+        encoder.set_buffer(BufferSlice::whole(&self.exposure), 1);
+        encoder.set_bytes(as_bytes(&uniforms), 2);
+        encoder.dispatch_threadgroups(Size { width: 1, height: 1, depth: 1 },
+                                      Size { width: HISTOGRAM_BINS, height: 1, depth: 1 });
+    }
+}
+
+/// Metal shading language declarations of the exposure state, to prepend to the source of
+/// tonemapping shaders which read it.
+pub const SHADER_HEADER: &'static str = r#"
+#include <metal_stdlib>
+
+struct ExposureState {
+    // the percentile-clipped average luminance of the last frame
+    float average_luminance;
+    // the luminance the exposure has adapted to
+    float adapted_luminance;
+    // the factor to scale linear color by before tonemapping
+    float exposure;
+    float padding;
+};
+
+static inline metal::float3 apply_exposure(metal::float3 color, const device ExposureState &state) {
+    return color * state.exposure;
+}
+"#;
+
+/// Metal shading language source of the histogram and adaptation kernels.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+#define HISTOGRAM_BINS 256
+
+struct HistogramUniforms {
+    uint2 size;
+    float min_log_luminance;
+    float log_luminance_range;
+    float low_percentile;
+    float high_percentile;
+    float speed_up;
+    float speed_down;
+    float key;
+    float delta_time;
+    uint reset;
+    uint padding;
+};
+
+struct ExposureState {
+    float average_luminance;
+    float adapted_luminance;
+    float exposure;
+    float padding;
+};
+
+static uint luminance_bin(float3 color, constant HistogramUniforms &u) {
+    float luminance = dot(color, float3(0.2126f, 0.7152f, 0.0722f));
+    if (luminance < exp2(u.min_log_luminance)) {
+        return 0;
+    }
+    float t = saturate((log2(luminance) - u.min_log_luminance) / u.log_luminance_range);
+    return uint(t * (HISTOGRAM_BINS - 2) + 1.0f);
+}
+
+// the log2 luminance of the middle of a bin other than 0
+static float bin_log_luminance(uint bin, constant HistogramUniforms &u) {
+    return (float(bin) - 0.5f) / float(HISTOGRAM_BINS - 2) * u.log_luminance_range + u.min_log_luminance;
+}
+
+kernel void build_histogram(texture2d<float, access::read> hdr [[texture(0)]],
+                            device atomic_uint *histogram [[buffer(0)]],
+                            constant HistogramUniforms &u [[buffer(1)]],
+                            uint2 id [[thread_position_in_grid]],
+                            uint lid [[thread_index_in_threadgroup]]) {
+    threadgroup atomic_uint bins[HISTOGRAM_BINS];
+    atomic_store_explicit(&bins[lid], 0, memory_order_relaxed);
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+    if (id.x < u.size.x && id.y < u.size.y) {
+        atomic_fetch_add_explicit(&bins[luminance_bin(hdr.read(id).rgb, u)], 1, memory_order_relaxed);
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+    uint count = atomic_load_explicit(&bins[lid], memory_order_relaxed);
+    if (count != 0) {
+        atomic_fetch_add_explicit(&histogram[lid], count, memory_order_relaxed);
+    }
+}
+
+// a single threadgroup of a thread per bin: averages the bins between the percentiles, eases the
+// adapted luminance towards the average, and clears the histogram for the next frame
+kernel void adapt_exposure(device uint *histogram [[buffer(0)]],
+                           device ExposureState &state [[buffer(1)]],
+                           constant HistogramUniforms &u [[buffer(2)]],
+                           uint lid [[thread_index_in_threadgroup]]) {
+    threadgroup uint counts[HISTOGRAM_BINS];
+    counts[lid] = histogram[lid];
+    histogram[lid] = 0;
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+    if (lid != 0) {
+        return;
+    }
+
+    uint total = 0;
+    for (uint bin = 1; bin < HISTOGRAM_BINS; bin++) {
+        total += counts[bin];
+    }
+    float low = float(total) * u.low_percentile;
+    float high = float(total) * u.high_percentile;
+    // the bins (or the parts of them) between the percentiles
+    float below = 0.0f, weight = 0.0f, sum = 0.0f;
+    for (uint bin = 1; bin < HISTOGRAM_BINS; bin++) {
+        float count = float(counts[bin]);
+        float kept = max(min(below + count, high) - max(below, low), 0.0f);
+        sum += kept * bin_log_luminance(bin, u);
+        weight += kept;
+        below += count;
+    }
+    float average = weight > 0.0f ? exp2(sum / weight) : exp2(u.min_log_luminance);
+
+    float adapted = state.adapted_luminance;
+    if (u.reset != 0 || !(adapted > 0.0f)) {
+        adapted = average;
+    } else {
+        float speed = average > adapted ? u.speed_up : u.speed_down;
+        adapted += (average - adapted) * (1.0f - exp(-u.delta_time * speed));
+    }
+    state.average_luminance = average;
+    state.adapted_luminance = adapted;
+    state.exposure = u.key / max(adapted, 1e-6f);
+}
+"#;
@@ -0,0 +1,356 @@
+//! An in-app inspector of GPU memory: what is allocated, in which heap, for what, and what the
+//! textures in it look like.
+//!
+//! Backends do not report their allocations, so `MemoryInspector` keeps a ledger of the resources
+//! the application tells it about, by label and by heap (a real heap, or any grouping the
+//! application likes: "meshes", "streaming", "render targets"). `report` sums it up, and the
+//! overlay shows the summary, either as `DebugDraw` text or, with the `egui` feature, as an egui
+//! window with thumbnails of the textures:
+//!
+//! ```ignore
+//! inspector.track_texture(&albedo, &albedo_desc, "rock albedo", "streaming");
+//! inspector.track_buffer(&vertices, "terrain vertices", "meshes");
+//!
+//! // when the user selects the texture
+//! try!(inspector.capture_thumbnail(&mut device, &mut commands, &albedo));
+//!
+//! // every frame
+//! inspector.poll(&mut device);
+//! inspector.egui_window(&egui_context);
+//! // or, drawn with a `DebugDraw` which is flushed with an identity view projection
+//! inspector.draw_text(&overlay_text, [-0.95, 0.9], debug_draw::WHITE, 14.0);
+//! ```
+//!
+//! Thumbnails are downscaled with a `Blitter` into a `THUMBNAIL_SIZE` texture and read back with
+//! a `ReadbackHeap`, so they arrive a few frames after they are captured, and only textures a
+//! `Blitter` can read (`shader_read`, and neither integer nor depth) have them. Textures much
+//! larger than the thumbnail are point sampled rather than averaged; capture from a smaller mip
+//! level view for a smoother thumbnail.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use {Device, BlitCommandEncoder, Buffer, CommandBuffer, Origin, PixelFormat, Size, TextureDescriptor};
+use {TextureType, TextureUsage};
+use texture::math::texture_byte_size;
+use util::blit::{Blitter, BlitError, BlitFilter};
+use util::debug_draw::{Color, DebugDraw};
+use util::readback_heap::{ReadbackHeap, ReadbackId};
+
+/// The width and height of thumbnails. A row of `RGBA8Unorm` thumbnail texels is exactly
+/// `COPY_ROW_ALIGNMENT` bytes, so rows are read back without padding.
+pub const THUMBNAIL_SIZE: uint = 64;
+
+/// What kind of resource an allocation is.
+#[deriving(Clone, PartialEq, Show)]
+pub enum AllocationKind {
+    Buffer,
+    Texture,
+}
+
+/// A resource in the ledger.
+#[deriving(Clone)]
+pub struct Allocation {
+    pub label: String,
+    pub heap: String,
+    pub kind: AllocationKind,
+    /// The size in bytes: the length of a buffer, or the texels of every level, slice and sample
+    /// of a texture.
+    pub size: uint,
+    /// The dimensions and format of a texture, such as `1024x1024 RGBA8Unorm, 11 levels`. Empty
+    /// for buffers.
+    pub description: String,
+}
+
+/// The allocations of a heap, summed up.
+#[deriving(Clone, Show)]
+pub struct HeapUsage {
+    pub name: String,
+    pub allocations: uint,
+    pub size: uint,
+}
+
+/// A summary of the ledger, from `MemoryInspector::report`. Prints as the text overlay shows it.
+#[deriving(Clone)]
+pub struct MemoryReport {
+    /// Every heap, largest first.
+    pub heaps: Vec<HeapUsage>,
+    /// Every allocation, largest first.
+    pub allocations: Vec<Allocation>,
+    pub total: uint,
+}
+
+/// How many of the largest allocations the text overlay lists.
+const LISTED_ALLOCATIONS: uint = 16;
+
+impl fmt::Show for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "GPU MEMORY {} IN {} ALLOCATIONS", FormattedSize(self.total), self.allocations.len()));
+        for heap in self.heaps.iter() {
+            try!(write!(f, "\n  {}: {} IN {}", heap.name, FormattedSize(heap.size), heap.allocations));
+        }
+        for allocation in self.allocations.iter().take(LISTED_ALLOCATIONS) {
+            try!(write!(f, "\n  {} {} ({})", FormattedSize(allocation.size), allocation.label, allocation.heap));
+        }
+        if self.allocations.len() > LISTED_ALLOCATIONS {
+            try!(write!(f, "\n  ... {} MORE", self.allocations.len() - LISTED_ALLOCATIONS));
+        }
+        Ok(())
+    }
+}
+
+/// A size in bytes, printed in the largest unit it is at least one of.
+struct FormattedSize(uint);
+
+impl fmt::Show for FormattedSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let FormattedSize(size) = *self;
+        if size >= 1 << 30 {
+            write!(f, "{:.1} GIB", size as f64 / (1u << 30) as f64)
+        } else if size >= 1 << 20 {
+            write!(f, "{:.1} MIB", size as f64 / (1u << 20) as f64)
+        } else if size >= 1 << 10 {
+            write!(f, "{:.1} KIB", size as f64 / (1u << 10) as f64)
+        } else {
+            write!(f, "{} B", size)
+        }
+    }
+}
+
+/// A thumbnail of a texture, as `THUMBNAIL_SIZE` rows of `THUMBNAIL_SIZE` `RGBA8Unorm` texels
+/// from the top, scaled to fit whatever the texture's aspect ratio.
+pub struct Thumbnail {
+    pub pixels: Vec<u8>,
+}
+
+/// The egui textures of thumbnails, by the address of their texture, uploaded the first time the
+/// window shows them.
+#[cfg(feature = "egui")]
+type EguiTextures = HashMap<uint, egui::TextureHandle>;
+#[cfg(not(feature = "egui"))]
+type EguiTextures = ();
+
+/// Why a thumbnail could not be captured.
+pub enum ThumbnailError<D: Device> {
+    /// The texture is not in the ledger.
+    Untracked,
+    Blit(BlitError),
+    Readback(D::BufferCreationError),
+}
+
+/// The ledger, thumbnails and overlays. See the module documentation.
+pub struct MemoryInspector<D: Device> {
+    /// Every tracked resource, by address.
+    allocations: HashMap<uint, Allocation>,
+    blitter: Blitter<D>,
+    thumbnail: D::Texture,
+    readback: ReadbackHeap<D>,
+    /// Thumbnails being read back, and the address of their texture.
+    pending: Vec<(ReadbackId, uint)>,
+    thumbnails: HashMap<uint, Thumbnail>,
+    egui_textures: EguiTextures,
+}
+
+impl<D: Device> MemoryInspector<D> {
+    /// Create an inspector with an empty ledger, which captures thumbnails with `blitter`.
+    pub fn new(device: &mut D, blitter: Blitter<D>) -> Result<MemoryInspector<D>, D::TextureCreationError> {
+        let thumbnail = try!(device.create_texture(TextureDescriptor {
+            texture_type: TextureType::Type2D,
+            pixel_format: PixelFormat::RGBA8Unorm,
+            width: THUMBNAIL_SIZE,
+            height: THUMBNAIL_SIZE,
+            depth: 1,
+            mipmap_level_count: 1,
+            array_length: 1,
+            sample_count: 1,
+            usage: TextureUsage { shader_read: false, shader_write: false, render_target: true },
+        }));
+        Ok(MemoryInspector {
+            allocations: HashMap::new(),
+            blitter: blitter,
+            thumbnail: thumbnail,
+            readback: ReadbackHeap::new(THUMBNAIL_SIZE * THUMBNAIL_SIZE * 4 * 4),
+            pending: Vec::new(),
+            thumbnails: HashMap::new(),
+            egui_textures: Default::default(),
+        })
+    }
+
+    /// Add `buffer` to the ledger, as `label` in `heap`.
+    pub fn track_buffer(&mut self, buffer: &D::Buffer, label: &str, heap: &str) {
+        self.allocations.insert(buffer as *const D::Buffer as uint, Allocation {
+            label: label.to_string(),
+            heap: heap.to_string(),
+            kind: AllocationKind::Buffer,
+            size: buffer.length(),
+            description: String::new(),
+        });
+    }
+
+    /// Add `texture`, created with `desc`, to the ledger, as `label` in `heap`.
+    pub fn track_texture(&mut self, texture: &D::Texture, desc: &TextureDescriptor, label: &str, heap: &str) {
+        let address = texture as *const D::Texture as uint;
+        self.allocations.insert(address, Allocation {
+            label: label.to_string(),
+            heap: heap.to_string(),
+            kind: AllocationKind::Texture,
+            size: texture_byte_size(desc),
+            description: format!("{}x{}x{} {}, {} levels", desc.width, desc.height, desc.depth,
+                                 desc.pixel_format, desc.mipmap_level_count),
+        });
+        // an old thumbnail would be of whatever was at this address before
+        self.forget_thumbnail(address);
+    }
+
+    /// Remove `object` from the ledger, as it is about to be dropped. Resources are told apart by
+    /// address, so one created where a dropped one was is otherwise mistaken for it.
+    pub fn untrack<T>(&mut self, object: &T) {
+        let address = object as *const T as uint;
+        self.allocations.remove(&address);
+        self.forget_thumbnail(address);
+    }
+
+    fn forget_thumbnail(&mut self, address: uint) {
+        self.thumbnails.remove(&address);
+        self.pending.retain(|&(_, pending)| pending != address);
+        self.forget_egui_texture(address);
+    }
+
+    #[cfg(feature = "egui")]
+    fn forget_egui_texture(&mut self, address: uint) {
+        self.egui_textures.remove(&address);
+    }
+
+    #[cfg(not(feature = "egui"))]
+    fn forget_egui_texture(&mut self, _: uint) {}
+
+    /// The ledger's allocations, and their addresses, largest first. The labels break ties, so
+    /// the order does not change from frame to frame.
+    fn sorted_allocations(&self) -> Vec<(uint, &Allocation)> {
+        let mut allocations: Vec<(uint, &Allocation)> = self.allocations.iter()
+            .map(|(&address, allocation)| (address, allocation)).collect();
+        allocations.sort_by(|&(_, a), &(_, b)| match b.size.cmp(&a.size) {
+            Ordering::Equal => a.label.cmp(&b.label),
+            order => order,
+        });
+        allocations
+    }
+
+    /// Sum up the ledger.
+    pub fn report(&self) -> MemoryReport {
+        let mut heaps: Vec<HeapUsage> = Vec::new();
+        let mut allocations = Vec::with_capacity(self.allocations.len());
+        let mut total = 0;
+        for (_, allocation) in self.sorted_allocations().into_iter() {
+            match heaps.iter().position(|heap| heap.name == allocation.heap) {
+                Some(index) => {
+                    heaps[index].allocations += 1;
+                    heaps[index].size += allocation.size;
+                }
+                None => heaps.push(HeapUsage { name: allocation.heap.clone(), allocations: 1, size: allocation.size }),
+            }
+            total += allocation.size;
+            allocations.push(allocation.clone());
+        }
+        heaps.sort_by(|a, b| match b.size.cmp(&a.size) {
+            Ordering::Equal => a.name.cmp(&b.name),
+            order => order,
+        });
+        MemoryReport { heaps: heaps, allocations: allocations, total: total }
+    }
+
+    /// Encode a capture of a thumbnail of `texture`, which must be in the ledger, in `commands`,
+    /// which must not have been committed yet. The thumbnail replaces the texture's last one once
+    /// `poll` reads it back.
+    pub fn capture_thumbnail(&mut self, device: &mut D, commands: &mut D::CommandBuffer, texture: &D::Texture) -> Result<(), ThumbnailError<D>> {
+        enter_span!("MemoryInspector::capture_thumbnail");
+        let address = texture as *const D::Texture as uint;
+        if !self.allocations.contains_key(&address) {
+            return Err(ThumbnailError::Untracked);
+        }
+        let allocation = match self.readback.allocate(device, THUMBNAIL_SIZE * THUMBNAIL_SIZE * 4) {
+            Ok(allocation) => allocation,
+            Err(e) => return Err(ThumbnailError::Readback(e)),
+        };
+        if let Err(e) = self.blitter.blit_with_filter(commands, texture, &self.thumbnail, BlitFilter::Linear, false) {
+            // nothing was encoded, and the allocation is read back as garbage, then ignored
+            self.readback.submit(commands);
+            return Err(ThumbnailError::Blit(e));
+        }
+        {
+            let mut blit = commands.blit_command_encoder();
+            blit.copy_texture_to_buffer(&self.thumbnail, 0, 0, Origin { x: 0, y: 0, z: 0 },
+                                        Size { width: THUMBNAIL_SIZE, height: THUMBNAIL_SIZE, depth: 1 },
+                                        self.readback.slice(&allocation), THUMBNAIL_SIZE * 4);
+            blit.end_encoding();
+        }
+        self.readback.submit(commands);
+        self.pending.push((allocation.id, address));
+        Ok(())
+    }
+
+    /// Collect the thumbnails read back since the last `poll`. Never waits for the GPU.
+    pub fn poll(&mut self, device: &mut D) {
+        for readback in self.readback.poll(device).into_iter() {
+            let address = match self.pending.iter().position(|&(ref id, _)| *id == readback.id) {
+                Some(index) => self.pending.remove(index).unwrap().1,
+                // a capture which failed to blit, or a texture untracked since
+                None => continue,
+            };
+            self.thumbnails.insert(address, Thumbnail { pixels: readback.data });
+            self.forget_egui_texture(address);
+        }
+    }
+
+    /// The latest thumbnail of `texture`, if one was read back.
+    pub fn thumbnail(&self, texture: &D::Texture) -> Option<&Thumbnail> {
+        self.thumbnails.get(&(texture as *const D::Texture as uint))
+    }
+
+    /// Draw the report as text into `debug`, starting from `position` and `height` pixels tall.
+    ///
+    /// `DebugDraw` positions text in world space, so `debug` should be one only used for
+    /// overlays, flushed with an identity view projection: `position` is then in normalized
+    /// device coordinates, `[-1, -1]` being the bottom left of the screen.
+    pub fn draw_text(&self, debug: &DebugDraw<D>, position: [f32, ..2], color: Color, height: f32) {
+        let text = format!("{}", self.report());
+        debug.text([position[0], position[1], 0.0], text.as_slice(), color, height);
+    }
+
+    /// Show the report in an egui window, with the heaps, every allocation, and the thumbnails
+    /// of the textures which have one.
+    #[cfg(feature = "egui")]
+    pub fn egui_window(&mut self, ctx: &egui::Context) {
+        let report = self.report();
+        for (&address, thumbnail) in self.thumbnails.iter() {
+            if !self.egui_textures.contains_key(&address) {
+                let image = egui::ColorImage::from_rgba_unmultiplied([THUMBNAIL_SIZE, THUMBNAIL_SIZE], thumbnail.pixels.as_slice());
+                let name = format!("memory inspector thumbnail {}", address);
+                self.egui_textures.insert(address, ctx.load_texture(name, image, Default::default()));
+            }
+        }
+        let allocations = self.sorted_allocations();
+        let textures = &self.egui_textures;
+        egui::Window::new("GPU memory").show(ctx, |ui| {
+            ui.label(format!("{} in {} allocations", FormattedSize(report.total), report.allocations.len()));
+            for heap in report.heaps.iter() {
+                let fraction = if report.total == 0 { 0.0 } else { heap.size as f32 / report.total as f32 };
+                ui.add(egui::ProgressBar::new(fraction)
+                    .text(format!("{}: {} in {}", heap.name, FormattedSize(heap.size), heap.allocations)));
+            }
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for &(address, allocation) in allocations.iter() {
+                    ui.horizontal(|ui| {
+                        if let Some(texture) = textures.get(&address) {
+                            ui.image(texture);
+                        }
+                        ui.label(format!("{} {} ({}, {}) {}", FormattedSize(allocation.size), allocation.label,
+                                         allocation.heap, allocation.kind, allocation.description));
+                    });
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,93 @@
+//! Optional higher level building blocks, built only on the public traits of this crate.
+//!
+//! Nothing in here has any special access to a backend. Each utility is meant to be usable as is,
+//! but also to serve as an example of how to drive the lower level API.
+
+#[cfg(feature = "futures")]
+pub mod async_runtime;
+pub mod atlas;
+pub mod background_work;
+pub mod blit;
+pub mod breadcrumbs;
+pub mod bvh;
+pub mod debug_draw;
+pub mod depth_pyramid;
+pub mod dispatch_splitting;
+pub mod dynamic_resolution;
+pub mod environment_lighting;
+pub mod fence;
+pub mod forward_plus;
+pub mod frame_overlays;
+pub mod geometry_pool;
+pub mod glyph_atlas;
+pub mod gpu_profiler;
+pub mod indirect_args;
+pub mod latency;
+pub mod lod_selection;
+pub mod lookup_textures;
+pub mod luminance_histogram;
+pub mod memory_inspector;
+pub mod motion_vectors;
+pub mod occlusion_culling;
+pub mod occlusion_queries;
+pub mod oit;
+pub mod particles;
+pub mod picking;
+pub mod prefix_sum;
+pub mod radix_sort;
+pub mod readback_heap;
+pub mod render_graph;
+pub mod render_scale;
+pub mod shadows;
+pub mod skinning;
+pub mod sprites;
+pub mod teardown;
+pub mod texture_feedback;
+pub mod transient_buffer;
+pub mod ui;
+pub mod uniform_packing;
+pub mod vertex_packing;
+pub mod wireframe;
+
+/// View a plain-old-data value as its bytes, for `set_bytes` and buffer uploads.
+///
+/// `T` must be `#[repr(C)]` with no padding that the shader side interprets, as the bytes are
+/// handed to the GPU verbatim.
+pub fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe {
+        std::mem::transmute(std::raw::Slice {
+            data: value as *const T as *const u8,
+            len: std::mem::size_of::<T>(),
+        })
+    }
+}
+
+/// View a slice of plain-old-data values as its bytes.
+///
+/// The same requirements as for `as_bytes` apply to `T`.
+pub fn slice_as_bytes<T>(values: &[T]) -> &[u8] {
+    unsafe {
+        std::mem::transmute(std::raw::Slice {
+            data: values.as_ptr() as *const u8,
+            len: values.len() * std::mem::size_of::<T>(),
+        })
+    }
+}
+
+/// View a mutable slice of plain-old-data values as its bytes, for `read_buffer`.
+///
+/// The same requirements as for `as_bytes` apply to `T`, and any bytes written must make valid
+/// values of it.
+pub fn slice_as_bytes_mut<T>(values: &mut [T]) -> &mut [u8] {
+    unsafe {
+        std::mem::transmute(std::raw::Slice {
+            data: values.as_ptr() as *const u8,
+            len: values.len() * std::mem::size_of::<T>(),
+        })
+    }
+}
+
+/// Rounds `n` up to a multiple of `multiple`.
+pub fn round_up(n: uint, multiple: uint) -> uint {
+    (n + multiple - 1) / multiple * multiple
+}
@@ -0,0 +1,139 @@
+//! The motion vector convention of the crate, and helpers for writing motion vectors from vertex
+//! and fragment functions.
+//!
+//! Temporal antialiasing and temporal upscalers (see `util::render_scale`) reproject the last
+//! frame's result with the motion of every pixel. They only agree with the renderer writing that
+//! motion if both follow one convention, which is this one:
+//!
+//! * the target is `MOTION_VECTOR_FORMAT`, `RG16Float`, at the render resolution, written in the
+//!   same passes as color (an extra color attachment);
+//! * a texel holds where the surface it shows was in the last frame, relative to where it is
+//!   now, in texture coordinates: `previous_uv - current_uv`, with `y` pointing down, so that
+//!   `uv + motion` samples the last frame;
+//! * motion is computed from unjittered positions, so a static scene has zero motion whatever
+//!   the camera jitter. Upscalers which want motion in pixels scale it by `motion_vector_scale`;
+//! * texels nothing was drawn over are cleared to zero, so the background moves with the camera
+//!   only if the sky is drawn with motion vectors too.
+//!
+//! Vertex functions compute both positions with the matrices of `MotionVectorUniforms`, which
+//! `MotionVectorCamera` keeps from frame to frame, and fragment functions turn them into the
+//! texel with the functions of `SHADER_HEADER`:
+//!
+//! ```ignore
+//! let uniforms = camera.update(view_projection, scale.clip_jitter());
+//!
+//! // in the vertex function, with the object's model matrix of this frame and the last one:
+//! // MotionVectorPositions p = motion_vector_positions(u, model, previous_model, position);
+//! // out.position = p.jittered; out.current = p.current; out.previous = p.previous;
+//! // and in the fragment function:
+//! // out.motion = motion_vector(in.current, in.previous);
+//! ```
+//!
+//! Skinned and morphed meshes move without their model matrix changing, and need the last
+//! frame's deformed positions as well: keeping the last frame's output of `util::skinning` around
+//! and reading both is the usual way.
+
+use PixelFormat;
+
+/// The format of motion vector targets.
+pub const MOTION_VECTOR_FORMAT: PixelFormat = PixelFormat::RG16Float;
+
+/// The factors from motion vectors to motion in pixels of a `render_size` target, for
+/// upscalers which take their motion in pixels.
+pub fn motion_vector_scale(render_size: (uint, uint)) -> (f32, f32) {
+    (render_size.0 as f32, render_size.1 as f32)
+}
+
+/// The camera matrices motion vectors are computed with. Declared with the same layout in
+/// `SHADER_HEADER`.
+#[repr(C)]
+#[deriving(Clone)]
+pub struct MotionVectorUniforms {
+    /// The view projection matrix of this frame and of the last, column major and unjittered.
+    pub view_projection: [f32, ..16],
+    pub previous_view_projection: [f32, ..16],
+    /// The jitter of this frame, in clip space (see `RenderScale::clip_jitter`), added to the
+    /// rasterized position only.
+    pub clip_jitter: [f32, ..2],
+    pub _padding: [f32, ..2],
+}
+
+/// Keeps the last frame's view projection matrix for `MotionVectorUniforms`.
+pub struct MotionVectorCamera {
+    previous: Option<[f32, ..16]>,
+}
+
+impl MotionVectorCamera {
+    pub fn new() -> MotionVectorCamera {
+        MotionVectorCamera { previous: None }
+    }
+
+    /// The uniforms of a frame rendered with `view_projection`, unjittered, and `clip_jitter`.
+    /// The first frame, and the first after `reset`, has no motion from the camera.
+    pub fn update(&mut self, view_projection: [f32, ..16], clip_jitter: (f32, f32)) -> MotionVectorUniforms {
+        let previous = match self.previous {
+            Some(previous) => previous,
+            None => view_projection,
+        };
+        self.previous = Some(view_projection);
+        MotionVectorUniforms {
+            view_projection: view_projection,
+            previous_view_projection: previous,
+            clip_jitter: [clip_jitter.0, clip_jitter.1],
+            _padding: [0.0, ..2],
+        }
+    }
+
+    /// Forget the last frame's camera, after a camera cut, so that the next frame does not
+    /// report the cut as motion.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// Metal shading language declarations of `MotionVectorUniforms`, and the functions writing
+/// motion vectors by the convention of the module documentation, to prepend to the source of
+/// shaders which write (or read) them.
+pub const SHADER_HEADER: &'static str = r#"
+#include <metal_stdlib>
+
+struct MotionVectorUniforms {
+    metal::float4x4 view_projection;
+    metal::float4x4 previous_view_projection;
+    metal::float2 clip_jitter;
+    metal::float2 padding;
+};
+
+struct MotionVectorPositions {
+    // the position to rasterize, with the jitter
+    metal::float4 jittered;
+    // the unjittered clip positions of this frame and the last, to pass to the fragment function
+    metal::float4 current;
+    metal::float4 previous;
+};
+
+// the positions of a vertex at object space position, with the model matrices of this frame and
+// the last (the same matrix, for static objects)
+static inline MotionVectorPositions motion_vector_positions(constant MotionVectorUniforms &u, metal::float4x4 model,
+                                                            metal::float4x4 previous_model, metal::float3 position) {
+    MotionVectorPositions p;
+    p.current = u.view_projection * (model * metal::float4(position, 1.0f));
+    p.previous = u.previous_view_projection * (previous_model * metal::float4(position, 1.0f));
+    p.jittered = p.current;
+    p.jittered.xy += u.clip_jitter * p.current.w;
+    return p;
+}
+
+// the motion vector of a fragment, from the interpolated clip positions of
+// motion_vector_positions: previous_uv - current_uv
+static inline metal::float2 motion_vector(metal::float4 current, metal::float4 previous) {
+    metal::float2 ndc = current.xy / current.w;
+    metal::float2 previous_ndc = previous.xy / previous.w;
+    return (previous_ndc - ndc) * metal::float2(0.5f, -0.5f);
+}
+
+// where the surface at uv was in the last frame, for reprojecting history
+static inline metal::float2 reproject(metal::float2 uv, metal::float2 motion) {
+    return uv + motion;
+}
+"#;
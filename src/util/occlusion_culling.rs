@@ -0,0 +1,278 @@
+//! Two-phase GPU occlusion culling.
+//!
+//! Every object is tested against a hierarchical depth buffer (a "depth pyramid") on the GPU, and
+//! the surviving objects are written out as indirect draw arguments, so the CPU never learns (or
+//! needs to learn) what is visible. Each frame runs in two phases:
+//!
+//! 1. The *early* phase draws everything that was visible last frame, without testing it. This
+//!    is usually nearly everything that is visible this frame, too.
+//! 2. A depth pyramid is built from the depth buffer produced by the early phase (see
+//!    `util::depth_pyramid`, using `DepthReduction::Min`).
+//! 3. The *late* phase tests every object against that pyramid, draws those which are visible now
+//!    but were not drawn by the early phase, and records visibility for the next frame.
+//!
+//! Because the late phase only tests against what was really drawn this frame, nothing visible is
+//! ever culled, and objects which become visible are drawn in the same frame they appear.
+//!
+//! A frame looks like:
+//!
+//! ```ignore
+//! culler.begin_phase(&mut blit);
+//! culler.cull(&mut compute, CullPhase::Early, &objects, object_count, &view, pyramid.texture());
+//! culler.draw(&mut render, PrimitiveType::Triangle, object_count);
+//! pyramid.encode(&mut compute, &depth);
+//! culler.begin_phase(&mut blit);
+//! culler.cull(&mut compute, CullPhase::Late, &objects, object_count, &view, pyramid.texture());
+//! culler.draw(&mut render, PrimitiveType::Triangle, object_count);
+//! ```
+//!
+//! Only occlusion is tested; objects outside of the view frustum should be culled beforehand (or
+//! not included in `objects`).
+
+use {Device, BlitCommandEncoder, BufferSlice, ComputeCommandEncoder, RenderCommandEncoder};
+use {DrawIndexedIndirectArgs, PrimitiveType, Size};
+use util::as_bytes;
+
+/// The number of threads in each culling threadgroup.
+const THREADS_PER_THREADGROUP: uint = 64;
+
+/// An object to be culled, as laid out in the objects buffer.
+#[repr(C)]
+pub struct CullObject {
+    /// The bounding sphere of the object in world space: the center in `xyz` and the radius in
+    /// `w`.
+    pub sphere: [f32, ..4],
+    /// The draw to issue if the object is visible.
+    pub draw: DrawIndexedIndirectArgs,
+    pub _padding: [u32, ..3],
+}
+
+/// The camera the objects are culled for.
+pub struct CullView {
+    /// The world to view transform, column major. View space looks down `+z`.
+    pub view: [f32, ..16],
+    /// The `x` and `y` scale terms of the projection matrix (`P[0][0]`, `P[1][1]`).
+    pub projection_scale: [f32, ..2],
+    /// The distance to the near plane.
+    ///
+    /// Depth is assumed to be reversed and infinite (`depth = znear / z`), so larger depths are
+    /// nearer.
+    pub znear: f32,
+    /// The size of level 0 of the depth pyramid, in texels.
+    pub pyramid_size: [f32, ..2],
+}
+
+/// Which phase of culling is being run. See the module documentation.
+pub enum CullPhase {
+    Early,
+    Late,
+}
+
+#[repr(C)]
+struct CullUniforms {
+    view: [f32, ..16],
+    projection_scale: [f32, ..2],
+    pyramid_size: [f32, ..2],
+    znear: f32,
+    object_count: u32,
+    phase: u32,
+    _padding: u32,
+}
+
+/// The GPU state for two-phase occlusion culling of up to `capacity` objects.
+pub struct OcclusionCuller<D: Device> {
+    pipeline: D::ComputePipeline,
+    /// One `u32` per object, non-zero if the object was visible last frame.
+    visibility: D::Buffer,
+    /// The indirect draw arguments written by the most recent phase.
+    draws: D::Buffer,
+    /// The number of valid entries in `draws`.
+    count: D::Buffer,
+    capacity: uint,
+}
+
+impl<D: Device> OcclusionCuller<D> {
+    /// Create the culling state for up to `capacity` objects.
+    ///
+    /// `pipeline` must be a compute pipeline created from the `occlusion_cull` function of
+    /// `SOURCE`.
+    ///
+    /// The visibility history starts out undefined, so `reset` must be encoded before the first
+    /// frame.
+    pub fn new(device: &mut D, pipeline: D::ComputePipeline, capacity: uint) -> Result<OcclusionCuller<D>, D::BufferCreationError> {
+        let draw_size = std::mem::size_of::<DrawIndexedIndirectArgs>();
+        let visibility = try!(device.create_buffer(capacity * 4, None));
+        let draws = try!(device.create_buffer(capacity * draw_size, None));
+        let count = try!(device.create_buffer(4, None));
+        Ok(OcclusionCuller {
+            pipeline: pipeline,
+            visibility: visibility,
+            draws: draws,
+            count: count,
+            capacity: capacity,
+        })
+    }
+
+    /// The maximum number of objects which can be culled at once.
+    pub fn capacity(&self) -> uint {
+        self.capacity
+    }
+
+    /// Forget all visibility history.
+    ///
+    /// The next early phase will draw nothing, and the late phase after it will test (and draw)
+    /// everything. This must be done before the first frame, and is a good idea after camera
+    /// cuts.
+    pub fn reset<E: BlitCommandEncoder<D>>(&self, encoder: &mut E) {
+        encoder.fill_buffer(BufferSlice::new(&self.visibility, 0, self.capacity * 4), 0);
+    }
+
+    /// Prepare for a phase by clearing the previous phase's draws.
+    pub fn begin_phase<E: BlitCommandEncoder<D>>(&self, encoder: &mut E) {
+        let draw_size = std::mem::size_of::<DrawIndexedIndirectArgs>();
+        encoder.fill_buffer(BufferSlice::new(&self.count, 0, 4), 0);
+        // clearing the arguments too keeps the non-native `multi_draw_indexed_indirect_count`
+        // fallback correct, as it relies on unused draws having an instance count of 0.
+        encoder.fill_buffer(BufferSlice::new(&self.draws, 0, self.capacity * draw_size), 0);
+    }
+
+    /// Cull `object_count` `CullObject`s from `objects`.
+    ///
+    /// `pyramid` is the depth pyramid built from this frame's early depth. It is not read in the
+    /// early phase, but must still be bound.
+    pub fn cull<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, phase: CullPhase,
+                                             objects: &D::Buffer, object_count: uint,
+                                             view: &CullView, pyramid: &D::Texture) {
+        enter_span!("OcclusionCuller::cull");
+        assert!(object_count <= self.capacity);
+        let uniforms = CullUniforms {
+            view: view.view,
+            projection_scale: view.projection_scale,
+            pyramid_size: view.pyramid_size,
+            znear: view.znear,
+            object_count: object_count as u32,
+            phase: match phase { CullPhase::Early => 0, CullPhase::Late => 1 },
+            _padding: 0,
+        };
+
+        encoder.set_compute_pipeline(&self.pipeline);
+        encoder.set_buffer(BufferSlice::whole(objects), 0);
+        encoder.set_buffer(BufferSlice::whole(&self.visibility), 1);
+        encoder.set_buffer(BufferSlice::whole(&self.draws), 2);
+        encoder.set_buffer(BufferSlice::whole(&self.count), 3);
+        encoder.set_bytes(as_bytes(&uniforms), 4);
+        encoder.set_texture(pyramid, 0);
+        let groups = (object_count + THREADS_PER_THREADGROUP - 1) / THREADS_PER_THREADGROUP;
+        encoder.dispatch_threadgroups(Size { width: groups, height: 1, depth: 1 },
+                                      Size { width: THREADS_PER_THREADGROUP, height: 1, depth: 1 });
+    }
+
+    /// Draw the objects which survived the most recent phase.
+    ///
+    /// `max_draws` should be the `object_count` that was culled. The index and vertex buffers the
+    /// objects' draw arguments refer to must already be bound.
+    pub fn draw<E: RenderCommandEncoder<D>>(&self, encoder: &mut E, primitive: PrimitiveType, max_draws: uint) {
+        encoder.multi_draw_indexed_indirect_count(primitive, &self.draws, 0, &self.count, 0, max_draws);
+    }
+}
+
+/// The metal shading language source of the culling kernel.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct DrawArgs {
+    uint index_count;
+    uint instance_count;
+    uint first_index;
+    int base_vertex;
+    uint base_instance;
+};
+
+struct CullObject {
+    float4 sphere;
+    DrawArgs draw;
+    uint padding[3];
+};
+
+struct CullUniforms {
+    float4x4 view;
+    float2 projection_scale;
+    float2 pyramid_size;
+    float znear;
+    uint object_count;
+    uint phase;
+    uint padding;
+};
+
+// 2D Polyhedral Bounds of a Clipped, Perspective-Projected 3D Sphere. Michael Mara, Morgan
+// McGuire. 2013. Returns the bounds in uv space as (min.x, min.y, max.x, max.y).
+static bool project_sphere(float3 c, float r, float znear, float p00, float p11, thread float4 &aabb) {
+    if (c.z < r + znear) {
+        return false;
+    }
+
+    float3 cr = c * r;
+    float czr2 = c.z * c.z - r * r;
+
+    float vx = sqrt(c.x * c.x + czr2);
+    float minx = (vx * c.x - cr.z) / (vx * c.z + cr.x);
+    float maxx = (vx * c.x + cr.z) / (vx * c.z - cr.x);
+
+    float vy = sqrt(c.y * c.y + czr2);
+    float miny = (vy * c.y - cr.z) / (vy * c.z + cr.y);
+    float maxy = (vy * c.y + cr.z) / (vy * c.z - cr.y);
+
+    aabb = float4(minx * p00, miny * p11, maxx * p00, maxy * p11);
+    aabb = aabb.xwzy * float4(0.5f, -0.5f, 0.5f, -0.5f) + float4(0.5f);
+    return true;
+}
+
+kernel void occlusion_cull(const device CullObject *objects [[buffer(0)]],
+                           device uint *visibility [[buffer(1)]],
+                           device DrawArgs *draws [[buffer(2)]],
+                           device atomic_uint *draw_count [[buffer(3)]],
+                           constant CullUniforms &u [[buffer(4)]],
+                           texture2d<float> pyramid [[texture(0)]],
+                           uint id [[thread_position_in_grid]])
+{
+    if (id >= u.object_count) {
+        return;
+    }
+
+    bool was_visible = visibility[id] != 0;
+    if (u.phase == 0 && !was_visible) {
+        return;
+    }
+
+    bool visible = true;
+    if (u.phase == 1) {
+        float4 sphere = objects[id].sphere;
+        float3 c = (u.view * float4(sphere.xyz, 1.0f)).xyz;
+        float4 aabb;
+        // spheres crossing the near plane are always visible
+        if (project_sphere(c, sphere.w, u.znear, u.projection_scale.x, u.projection_scale.y, aabb)) {
+            float width = (aabb.z - aabb.x) * u.pyramid_size.x;
+            float height = (aabb.w - aabb.y) * u.pyramid_size.y;
+            float level = ceil(log2(max(width, height)));
+            uint lod = uint(clamp(level, 0.0f, float(pyramid.get_num_mip_levels() - 1)));
+
+            uint2 size = uint2(pyramid.get_width(lod), pyramid.get_height(lod));
+            uint2 lo = min(uint2(saturate(aabb.xy) * float2(size)), size - 1);
+            uint2 hi = min(uint2(saturate(aabb.zw) * float2(size)), size - 1);
+            float depth = min(min(pyramid.read(lo, lod).x, pyramid.read(uint2(hi.x, lo.y), lod).x),
+                              min(pyramid.read(uint2(lo.x, hi.y), lod).x, pyramid.read(hi, lod).x));
+
+            float sphere_depth = u.znear / (c.z - sphere.w);
+            visible = sphere_depth >= depth;
+        }
+        visibility[id] = visible ? 1 : 0;
+    }
+
+    // the early phase draws everything visible last frame, the late phase only what it missed.
+    if (visible && (u.phase == 0 || !was_visible)) {
+        uint slot = atomic_fetch_add_explicit(draw_count, 1, memory_order_relaxed);
+        draws[slot] = objects[id].draw;
+    }
+}
+"#;
@@ -0,0 +1,150 @@
+//! Occlusion queries that never stall.
+//!
+//! An occlusion query counts how many samples of a draw pass the depth test, which is the usual
+//! way to fade lens flares and coronas, or to skip objects that were hidden last frame. The
+//! result is only known once the GPU has finished the frame, so reading it the same frame waits
+//! for the GPU to drain, every frame. `OcclusionQueries` instead reads each frame's results
+//! `frames_in_flight` frames later, and hands out the latest result it has for every query:
+//!
+//! ```ignore
+//! queries.begin_frame(&mut device, &mut blit);
+//! let mut encoder = commands.render_command_encoder(RenderPassDescriptor {
+//!     visibility_result_buffer: Some(queries.buffer()),
+//!     ..
+//! });
+//! queries.begin_query(&mut encoder, SUN);
+//! // draw a small quad where the sun is, with depth writes off
+//! queries.end_query(&mut encoder);
+//! let fraction = queries.result(SUN).unwrap_or(0) as f32 / SUN_QUAD_SAMPLES;
+//! ```
+//!
+//! Query ids are small integers chosen by the caller, below the capacity, and stay meaningful
+//! across frames; a query need not be issued every frame.
+
+use {Device, BlitCommandEncoder, BufferSlice, RenderCommandEncoder, VisibilityResultMode};
+use util::slice_as_bytes_mut;
+
+/// Why a query could not be issued.
+#[deriving(Show)]
+pub enum QueryError {
+    /// The id is not below the capacity the queries were created with.
+    OutOfRange,
+    /// The query was already issued this frame.
+    AlreadyIssued,
+}
+
+/// A set of occlusion queries, with one result buffer per frame in flight.
+pub struct OcclusionQueries<D: Device> {
+    buffers: Vec<D::Buffer>,
+    /// The ids issued in each frame in flight.
+    issued: Vec<Vec<uint>>,
+    current: uint,
+    capacity: uint,
+    mode: VisibilityResultMode,
+    results: Vec<Option<u64>>,
+    /// The frame each result was issued in, to tell how old it is.
+    result_frames: Vec<u64>,
+    frame: u64,
+}
+
+impl<D: Device> OcclusionQueries<D> {
+    /// Create `capacity` queries recording in `mode`.
+    ///
+    /// As for `DebugDraw`, `frames_in_flight` must be at least the number of frames the CPU can
+    /// get ahead of the GPU: results are read back that many frames after they were issued.
+    pub fn new(device: &mut D, capacity: uint, mode: VisibilityResultMode,
+               frames_in_flight: uint) -> Result<OcclusionQueries<D>, D::BufferCreationError> {
+        let mut buffers = Vec::with_capacity(frames_in_flight);
+        for _ in range(0, frames_in_flight) {
+            buffers.push(try!(device.create_buffer(capacity * 8, None)));
+        }
+        Ok(OcclusionQueries {
+            buffers: buffers,
+            issued: Vec::from_fn(frames_in_flight, |_| Vec::new()),
+            current: frames_in_flight - 1,
+            capacity: capacity,
+            mode: mode,
+            results: Vec::from_elem(capacity, None),
+            result_frames: Vec::from_elem(capacity, 0),
+            frame: 0,
+        })
+    }
+
+    /// Start a new frame: pick up the results of the frame `frames_in_flight` frames ago, and
+    /// clear its buffer with `blit` for this frame's queries.
+    ///
+    /// `blit` must run before the passes which issue this frame's queries.
+    // todo: `read_buffer` waits for every committed command buffer, not just the ones of that
+    // frame (as in `gpu_profiler`). Read without waiting once buffers can be mapped.
+    pub fn begin_frame<E: BlitCommandEncoder<D>>(&mut self, device: &mut D, blit: &mut E) {
+        enter_span!("OcclusionQueries::begin_frame");
+        self.current = (self.current + 1) % self.buffers.len();
+        self.frame += 1;
+        let issued = &mut self.issued[self.current];
+        if !issued.is_empty() {
+            let mut values = Vec::from_elem(self.capacity, 0u64);
+            device.read_buffer(&self.buffers[self.current], 0, slice_as_bytes_mut(values.as_mut_slice()));
+            let issued_frame = self.frame - self.buffers.len() as u64;
+            for &id in issued.iter() {
+                self.results[id] = Some(values[id]);
+                self.result_frames[id] = issued_frame;
+            }
+            issued.clear();
+        }
+        blit.fill_buffer(BufferSlice::whole(&self.buffers[self.current]), 0);
+    }
+
+    /// The visibility result buffer of this frame, for the `RenderPassDescriptor` of every pass
+    /// which issues queries.
+    pub fn buffer(&self) -> &D::Buffer {
+        &self.buffers[self.current]
+    }
+
+    /// Start recording the draws of query `id` into its result.
+    ///
+    /// Only one query can record at a time, and each query at most once per frame (though it may
+    /// span any number of draws).
+    pub fn begin_query<E: RenderCommandEncoder<D>>(&mut self, encoder: &mut E, id: uint) -> Result<(), QueryError> {
+        if id >= self.capacity {
+            return Err(QueryError::OutOfRange);
+        }
+        let issued = &mut self.issued[self.current];
+        if issued.contains(&id) {
+            return Err(QueryError::AlreadyIssued);
+        }
+        issued.push(id);
+        encoder.set_visibility_result_mode(self.mode.clone(), id * 8);
+        Ok(())
+    }
+
+    /// Stop recording into the query begun last.
+    pub fn end_query<E: RenderCommandEncoder<D>>(&self, encoder: &mut E) {
+        encoder.set_visibility_result_mode(VisibilityResultMode::Disabled, 0);
+    }
+
+    /// The latest known result of query `id`: the number of samples that passed, or for
+    /// `VisibilityResultMode::Boolean`, zero or not.
+    ///
+    /// Returns `None` until a result of the query has been read back, which is
+    /// `frames_in_flight` frames after it was first issued.
+    pub fn result(&self, id: uint) -> Option<u64> {
+        self.results[id]
+    }
+
+    /// Whether the latest known result of query `id` saw any sample, or `default` if there is
+    /// none yet. Objects which are new on screen usually want `true` here, so they are not
+    /// hidden for their first frames.
+    pub fn is_visible(&self, id: uint, default: bool) -> bool {
+        self.results[id].map_or(default, |samples| samples > 0)
+    }
+
+    /// How many frames old the latest known result of query `id` is, if there is one.
+    pub fn result_age(&self, id: uint) -> Option<u64> {
+        self.results[id].map(|_| self.frame - self.result_frames[id])
+    }
+
+    /// Forget the result of query `id`, for when the id is reused for another object.
+    pub fn forget(&mut self, id: uint) {
+        self.results[id] = None;
+    }
+}
@@ -0,0 +1,230 @@
+//! Weighted blended order-independent transparency.
+//!
+//! Transparent surfaces are drawn in any order into two attachments: a sum of weighted,
+//! premultiplied colors, and the product of their `1 - alpha` (the "revealage" of what is behind
+//! them). A composite pass then turns the sum into a weighted average, and blends it over the
+//! opaque image by how much the revealage says was covered. Weights fall off with depth, so
+//! nearer surfaces dominate, which is usually close enough to sorted blending, and always free of
+//! popping. (McGuire and Bavoil, "Weighted Blended Order-Independent Transparency", 2013.)
+//!
+//! This runs on any device, where exact techniques need optional features (see
+//! `DeviceFeatures::raster_order_groups`). A frame looks like:
+//!
+//! ```ignore
+//! // opaque geometry into `color` and `depth` as usual, then:
+//! let mut encoder = commands.render_command_encoder(oit.accumulation_pass(&depth));
+//! encoder.set_depth_stencil_state(oit.depth_state());
+//! // draw transparent geometry with pipelines using `oit::blending()` and
+//! // `oit::attachment_formats(depth_format)`
+//! encoder.end_encoding();
+//! let mut encoder = commands.render_command_encoder(/* `color`, loaded */);
+//! oit.composite(&mut encoder);
+//! ```
+//!
+//! Transparent fragment functions must return an `OitOutput` made by `oit_output`, both from
+//! `SHADER_HEADER`, which should be prepended to their source.
+
+use {Device, AttachmentFormats, BlendDescriptor, BlendFactor, BlendOperation, RenderCommandEncoder, RenderPipelineDescriptor};
+use {ClearColor, LoadAction, RenderPassColorAttachment, RenderPassDepthAttachment, RenderPassDescriptor, StoreAction};
+use {CompareFunction, DepthStencilStateDescriptor, PixelFormat, PrimitiveType};
+use {TextureDescriptor, TextureType, TextureUsage};
+
+/// The blending transparent pipelines must use, for the two attachments of the accumulation pass.
+pub fn blending() -> Vec<Option<BlendDescriptor>> {
+    vec![
+        Some(BlendDescriptor::additive()),
+        Some(BlendDescriptor::uniform(BlendFactor::Zero, BlendFactor::OneMinusSourceColor, BlendOperation::Add)),
+    ]
+}
+
+/// The attachments of the accumulation pass, for transparent pipelines, with a depth attachment
+/// of `depth`.
+pub fn attachment_formats(depth: PixelFormat) -> AttachmentFormats {
+    AttachmentFormats {
+        color: vec![PixelFormat::RGBA16Float, PixelFormat::R16Float],
+        depth: Some(depth),
+        stencil: None,
+    }
+}
+
+/// Why a `WeightedBlendedOit` could not be created.
+pub enum OitCreationError<D: Device> {
+    Pipeline(D::RenderPipelineCreationError),
+    Texture(D::TextureCreationError),
+}
+
+/// The attachments and composite pass of weighted blended OIT.
+pub struct WeightedBlendedOit<D: Device> {
+    composite: D::RenderPipeline,
+    depth_state: D::DepthStencilState,
+    /// `RGBA16Float` premultiplied color sums.
+    accumulation: D::Texture,
+    /// `R16Float` revealage.
+    revealage: D::Texture,
+    size: (uint, uint),
+}
+
+fn target<D: Device>(device: &mut D, format: PixelFormat, size: (uint, uint)) -> Result<D::Texture, D::TextureCreationError> {
+    let (width, height) = size;
+    device.create_texture(TextureDescriptor {
+        texture_type: TextureType::Type2D,
+        pixel_format: format,
+        width: width,
+        height: height,
+        depth: 1,
+        mipmap_level_count: 1,
+        array_length: 1,
+        sample_count: 1,
+        usage: TextureUsage { shader_read: true, shader_write: false, render_target: true },
+    })
+}
+
+impl<D: Device> WeightedBlendedOit<D> {
+    /// Create the attachments, of `size` pixels.
+    ///
+    /// `program` must be a shader program created from `SOURCE`.
+    pub fn new(device: &mut D, program: &D::ShaderProgram, size: (uint, uint)) -> Result<WeightedBlendedOit<D>, OitCreationError<D>> {
+        let composite = match device.create_render_pipeline(program, RenderPipelineDescriptor {
+            label: Some("oit composite".to_string()),
+            robustness: None,
+            vertex_function: "oit_composite_vertex".to_string(),
+            fragment_function: Some("oit_composite_fragment".to_string()),
+            vertex_descriptor: None,
+            blending: vec![Some(BlendDescriptor::alpha())],
+            sample_count: 1,
+            attachment_formats: None,
+        }) {
+            Ok(pipeline) => pipeline,
+            Err(e) => return Err(OitCreationError::Pipeline(e)),
+        };
+        // transparent surfaces are hidden by opaque ones, but not by each other
+        let depth_state = device.create_depth_stencil_state(DepthStencilStateDescriptor {
+            depth_compare_function: CompareFunction::Less,
+            depth_write_enabled: false,
+            front_face_stencil: None,
+            back_face_stencil: None,
+        });
+        let accumulation = match target(device, PixelFormat::RGBA16Float, size) {
+            Ok(texture) => texture,
+            Err(e) => return Err(OitCreationError::Texture(e)),
+        };
+        let revealage = match target(device, PixelFormat::R16Float, size) {
+            Ok(texture) => texture,
+            Err(e) => return Err(OitCreationError::Texture(e)),
+        };
+        Ok(WeightedBlendedOit {
+            composite: composite,
+            depth_state: depth_state,
+            accumulation: accumulation,
+            revealage: revealage,
+            size: size,
+        })
+    }
+
+    /// Recreate the attachments at `size` pixels, if that is not their size already.
+    pub fn resize(&mut self, device: &mut D, size: (uint, uint)) -> Result<(), D::TextureCreationError> {
+        if size == self.size {
+            return Ok(());
+        }
+        self.accumulation = try!(target(device, PixelFormat::RGBA16Float, size));
+        self.revealage = try!(target(device, PixelFormat::R16Float, size));
+        self.size = size;
+        Ok(())
+    }
+
+    /// The depth state for drawing transparent surfaces: tested against, but not writing, the
+    /// opaque depth.
+    pub fn depth_state(&self) -> &D::DepthStencilState {
+        &self.depth_state
+    }
+
+    /// The render pass transparent surfaces are drawn in, testing against `depth`, the depth
+    /// attachment of the opaque pass.
+    pub fn accumulation_pass<'a>(&'a self, depth: &'a D::Texture) -> RenderPassDescriptor<'a, D> {
+        RenderPassDescriptor {
+            color_attachments: vec![
+                RenderPassColorAttachment {
+                    texture: &self.accumulation, mipmap_level: 0, slice: 0,
+                    load_action: LoadAction::Clear, store_action: StoreAction::Store,
+                    clear_color: ClearColor::Float { red: 0.0, green: 0.0, blue: 0.0, alpha: 0.0 },
+                },
+                RenderPassColorAttachment {
+                    texture: &self.revealage, mipmap_level: 0, slice: 0,
+                    load_action: LoadAction::Clear, store_action: StoreAction::Store,
+                    clear_color: ClearColor::Float { red: 1.0, green: 0.0, blue: 0.0, alpha: 0.0 },
+                },
+            ],
+            depth_attachment: Some(RenderPassDepthAttachment {
+                texture: depth, mipmap_level: 0, slice: 0,
+                load_action: LoadAction::Load, store_action: StoreAction::Store, clear_depth: 1.0,
+            }),
+            stencil_attachment: None,
+            sample_positions: None,
+            visibility_result_buffer: None,
+        }
+    }
+
+    /// Blend the accumulated transparency over the color attachment of the pass `encoder` records.
+    pub fn composite<E: RenderCommandEncoder<D>>(&self, encoder: &mut E) {
+        enter_span!("WeightedBlendedOit::composite");
+        encoder.set_render_pipeline(&self.composite);
+        encoder.set_fragment_texture(&self.accumulation, 0);
+        encoder.set_fragment_texture(&self.revealage, 1);
+        encoder.draw(PrimitiveType::Triangle, 0, 3, 1, 0);
+    }
+}
+
+/// Metal shading language for transparent fragment functions, to prepend to their source.
+///
+/// ```metal
+/// fragment OitOutput glass_fragment(GlassFragment in [[stage_in]]) {
+///     float4 color = ...;
+///     return oit_output(float4(color.rgb * color.a, color.a), in.position.z);
+/// }
+/// ```
+pub const SHADER_HEADER: &'static str = r#"
+struct OitOutput {
+    float4 accumulation [[color(0)]];
+    float revealage [[color(1)]];
+};
+
+// `color` is premultiplied, and `depth` is the fragment's window depth (`[[position]].z`).
+static OitOutput oit_output(float4 color, float depth) {
+    float a = min(1.0f, color.a * 10.0f) + 0.01f;
+    float b = 1.0f - depth * 0.9f;
+    float weight = clamp(a * a * a * 1e8f * b * b * b, 1e-2f, 3e3f);
+    OitOutput out;
+    out.accumulation = color * weight;
+    out.revealage = color.a;
+    return out;
+}
+"#;
+
+/// The metal shading language source of the composite pass.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+vertex float4 oit_composite_vertex(uint vid [[vertex_id]])
+{
+    float2 uv = float2((vid << 1) & 2, vid & 2);
+    return float4(uv * 2.0f - 1.0f, 0.0f, 1.0f);
+}
+
+fragment float4 oit_composite_fragment(float4 position [[position]],
+                                       texture2d<float> accumulation [[texture(0)]],
+                                       texture2d<float> revealage [[texture(1)]])
+{
+    uint2 pixel = uint2(position.xy);
+    float r = revealage.read(pixel).r;
+    if (r >= 1.0f) {
+        discard_fragment();
+    }
+    float4 sum = accumulation.read(pixel);
+    // guard against overflow of the half float sums
+    if (isinf(max(max(abs(sum.r), abs(sum.g)), abs(sum.b)))) {
+        sum.rgb = float3(sum.a);
+    }
+    return float4(sum.rgb / max(sum.a, 1e-5f), 1.0f - r);
+}
+"#;
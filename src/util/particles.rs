@@ -0,0 +1,436 @@
+//! A GPU particle system.
+//!
+//! Particles live entirely on the GPU, in two buffers used in turn. Each frame:
+//!
+//! 1. `simulate` integrates every live particle of the previous frame, and appends those which
+//!    are still alive to the other buffer. Appending uses an atomic counter, so the survivors end
+//!    up packed at the start of the buffer without any separate compaction pass, and the CPU
+//!    never learns how many there are.
+//! 2. `emit` appends new particles after the survivors.
+//! 3. `draw` draws one camera facing quad per live particle with `draw_indirect`. The counter
+//!    both passes append with is the `instance_count` of the indirect draw arguments, so the draw
+//!    needs no readback either.
+//!
+//! ```ignore
+//! particles.begin_frame(&mut blit);
+//! particles.simulate(&mut compute, &SimulateParams { dt: dt, gravity: [0.0, -9.8, 0.0], drag: 0.1 });
+//! particles.emit(&mut compute, &fountain, frame_index);
+//! particles.draw(&mut render, &camera);
+//! ```
+//!
+//! When the buffer is full, new particles are dropped until old ones die.
+
+use {Device, BlitCommandEncoder, BufferSlice, ComputeCommandEncoder, RenderCommandEncoder};
+use {BlendDescriptor, DrawIndirectArgs, PrimitiveType, RenderPipelineDescriptor, Size};
+use util::{as_bytes, slice_as_bytes};
+
+/// The number of threads in each simulation and emission threadgroup.
+const THREADS_PER_THREADGROUP: uint = 64;
+
+/// A particle, as laid out in the particle buffers.
+#[repr(C)]
+pub struct Particle {
+    /// The position in `xyz`, and the age in seconds in `w`.
+    pub position: [f32, ..4],
+    /// The velocity in `xyz`, and the lifetime in seconds in `w`.
+    pub velocity: [f32, ..4],
+    pub color: [f32, ..4],
+    /// The size of the quad at birth in `x` and death in `y`, in world units.
+    pub size: [f32, ..2],
+    pub _padding: [f32, ..2],
+}
+
+/// How particles move in one `simulate`.
+#[repr(C)]
+pub struct SimulateParams {
+    /// The time step, in seconds.
+    pub dt: f32,
+    /// An acceleration applied to every particle.
+    pub gravity: [f32, ..3],
+    /// The fraction of velocity lost per second.
+    pub drag: f32,
+}
+
+/// Where and how new particles are born.
+pub struct Emitter {
+    /// The number of particles to emit.
+    pub count: uint,
+    /// The center of the sphere particles are born in, and its radius.
+    pub position: [f32, ..3],
+    pub radius: f32,
+    /// The mean initial velocity, and how far it is randomly perturbed.
+    pub velocity: [f32, ..3],
+    pub velocity_spread: f32,
+    /// The range lifetimes are randomly picked from, in seconds.
+    pub lifetime: (f32, f32),
+    pub color: [f32, ..4],
+    /// See `Particle::size`.
+    pub size: [f32, ..2],
+}
+
+/// The camera particles are drawn for.
+pub struct ParticleView {
+    /// The world to clip space transform, column major.
+    pub view_projection: [f32, ..16],
+    /// The camera's right and up directions in world space, which quads are aligned with.
+    pub right: [f32, ..3],
+    pub up: [f32, ..3],
+}
+
+#[repr(C)]
+struct SimulateUniforms {
+    gravity: [f32, ..3],
+    dt: f32,
+    drag: f32,
+    capacity: u32,
+    _padding: [u32, ..2],
+}
+
+#[repr(C)]
+struct EmitUniforms {
+    position: [f32, ..3],
+    radius: f32,
+    velocity: [f32, ..3],
+    velocity_spread: f32,
+    color: [f32, ..4],
+    size: [f32, ..2],
+    lifetime: [f32, ..2],
+    count: u32,
+    capacity: u32,
+    seed: u32,
+    _padding: u32,
+}
+
+#[repr(C)]
+struct DrawUniforms {
+    view_projection: [f32, ..16],
+    right: [f32, ..4],
+    up: [f32, ..4],
+}
+
+/// Why a `ParticleSystem` could not be created.
+pub enum ParticleSystemCreationError<D: Device> {
+    Pipeline(D::RenderPipelineCreationError),
+    Buffer(D::BufferCreationError),
+}
+
+fn create_buffer<D: Device>(device: &mut D, size: uint) -> Result<D::Buffer, ParticleSystemCreationError<D>> {
+    match device.create_buffer(size, None) {
+        Ok(buffer) => Ok(buffer),
+        Err(e) => Err(ParticleSystemCreationError::Buffer(e)),
+    }
+}
+
+/// Up to `capacity` particles, and the pipelines which run them.
+pub struct ParticleSystem<D: Device> {
+    simulate: D::ComputePipeline,
+    emit: D::ComputePipeline,
+    draw: D::RenderPipeline,
+    /// The two particle buffers. `particles[current]` holds this frame's particles.
+    particles: [D::Buffer, ..2],
+    /// The indirect draw arguments of each particle buffer, whose `instance_count` is the number
+    /// of live particles.
+    args: [D::Buffer, ..2],
+    current: uint,
+    capacity: uint,
+}
+
+impl<D: Device> ParticleSystem<D> {
+    /// Create a particle system for up to `capacity` live particles.
+    ///
+    /// `simulate` and `emit` must be compute pipelines created from the `particle_simulate` and
+    /// `particle_emit` functions of `SOURCE`, and `program` a shader program created from
+    /// `SOURCE`.
+    pub fn new(device: &mut D, program: &D::ShaderProgram, simulate: D::ComputePipeline, emit: D::ComputePipeline,
+               capacity: uint) -> Result<ParticleSystem<D>, ParticleSystemCreationError<D>> {
+        let desc = RenderPipelineDescriptor {
+            label: Some("particles".to_string()),
+            robustness: None,
+            vertex_function: "particle_vertex".to_string(),
+            fragment_function: Some("particle_fragment".to_string()),
+            vertex_descriptor: None,
+            // the fragment function outputs premultiplied color with an alpha of 0
+            blending: vec![Some(BlendDescriptor::additive())],
+            sample_count: 1,
+            attachment_formats: None,
+        };
+        let draw = match device.create_render_pipeline(program, desc) {
+            Ok(pipeline) => pipeline,
+            Err(e) => return Err(ParticleSystemCreationError::Pipeline(e)),
+        };
+
+        let particle_size = std::mem::size_of::<Particle>();
+        let args_size = std::mem::size_of::<DrawIndirectArgs>();
+        let empty = [DrawIndirectArgs { vertex_count: 4, instance_count: 0, first_vertex: 0, base_instance: 0 }];
+        let particles = [try!(create_buffer(device, capacity * particle_size)),
+                         try!(create_buffer(device, capacity * particle_size))];
+        let args = [try!(create_buffer(device, args_size)), try!(create_buffer(device, args_size))];
+        for buffer in args.iter() {
+            device.write_buffer(buffer, 0, slice_as_bytes(empty.as_slice()));
+        }
+
+        Ok(ParticleSystem {
+            simulate: simulate,
+            emit: emit,
+            draw: draw,
+            particles: particles,
+            args: args,
+            current: 0,
+            capacity: capacity,
+        })
+    }
+
+    /// The maximum number of live particles.
+    pub fn capacity(&self) -> uint {
+        self.capacity
+    }
+
+    /// The buffer of this frame's particles, for passes of your own. The first
+    /// `instance_count` of `live_count_buffer` are live.
+    pub fn particle_buffer(&self) -> &D::Buffer {
+        &self.particles[self.current]
+    }
+
+    /// The `DrawIndirectArgs` of this frame's particles.
+    pub fn live_count_buffer(&self) -> &D::Buffer {
+        &self.args[self.current]
+    }
+
+    /// Kill every particle.
+    pub fn clear<E: BlitCommandEncoder<D>>(&self, encoder: &mut E) {
+        for buffer in self.args.iter() {
+            encoder.fill_buffer(BufferSlice::new(buffer, 4, 4), 0);
+        }
+    }
+
+    /// Start a new frame, making last frame's particles the source of `simulate`.
+    pub fn begin_frame<E: BlitCommandEncoder<D>>(&mut self, encoder: &mut E) {
+        self.current = 1 - self.current;
+        // only the instance count; the rest of the arguments never change
+        encoder.fill_buffer(BufferSlice::new(&self.args[self.current], 4, 4), 0);
+    }
+
+    /// Advance last frame's particles, keeping the survivors.
+    pub fn simulate<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, params: &SimulateParams) {
+        enter_span!("ParticleSystem::simulate");
+        let uniforms = SimulateUniforms {
+            gravity: params.gravity,
+            dt: params.dt,
+            drag: params.drag,
+            capacity: self.capacity as u32,
+            _padding: [0, 0],
+        };
+        let previous = 1 - self.current;
+        encoder.set_compute_pipeline(&self.simulate);
+        encoder.set_buffer(BufferSlice::whole(&self.particles[previous]), 0);
+        encoder.set_buffer(BufferSlice::whole(&self.args[previous]), 1);
+        encoder.set_buffer(BufferSlice::whole(&self.particles[self.current]), 2);
+        encoder.set_buffer(BufferSlice::whole(&self.args[self.current]), 3);
+        encoder.set_bytes(as_bytes(&uniforms), 4);
+        // the live count is only known to the GPU, so every slot gets a thread
+        let groups = (self.capacity + THREADS_PER_THREADGROUP - 1) / THREADS_PER_THREADGROUP;
+        encoder.dispatch_threadgroups(Size { width: groups, height: 1, depth: 1 },
+                                      Size { width: THREADS_PER_THREADGROUP, height: 1, depth: 1 });
+    }
+
+    /// Emit new particles. `seed` should change every call, for example to the frame number.
+    pub fn emit<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, emitter: &Emitter, seed: u32) {
+        enter_span!("ParticleSystem::emit");
+        if emitter.count == 0 {
+            return;
+        }
+        let (min_lifetime, max_lifetime) = emitter.lifetime;
+        let uniforms = EmitUniforms {
+            position: emitter.position,
+            radius: emitter.radius,
+            velocity: emitter.velocity,
+            velocity_spread: emitter.velocity_spread,
+            color: emitter.color,
+            size: emitter.size,
+            lifetime: [min_lifetime, max_lifetime],
+            count: emitter.count as u32,
+            capacity: self.capacity as u32,
+            seed: seed,
+            _padding: 0,
+        };
+        encoder.set_compute_pipeline(&self.emit);
+        encoder.set_buffer(BufferSlice::whole(&self.particles[self.current]), 0);
+        encoder.set_buffer(BufferSlice::whole(&self.args[self.current]), 1);
+        encoder.set_bytes(as_bytes(&uniforms), 2);
+        let groups = (emitter.count + THREADS_PER_THREADGROUP - 1) / THREADS_PER_THREADGROUP;
+        encoder.dispatch_threadgroups(Size { width: groups, height: 1, depth: 1 },
+                                      Size { width: THREADS_PER_THREADGROUP, height: 1, depth: 1 });
+    }
+
+    /// Draw this frame's particles.
+    pub fn draw<E: RenderCommandEncoder<D>>(&self, encoder: &mut E, view: &ParticleView) {
+        enter_span!("ParticleSystem::draw");
+        let uniforms = DrawUniforms {
+            view_projection: view.view_projection,
+            right: [view.right[0], view.right[1], view.right[2], 0.0],
+            up: [view.up[0], view.up[1], view.up[2], 0.0],
+        };
+        encoder.set_render_pipeline(&self.draw);
+        encoder.set_vertex_buffer(BufferSlice::whole(&self.particles[self.current]), 0);
+        encoder.set_vertex_bytes(as_bytes(&uniforms), 1);
+        encoder.draw_indirect(PrimitiveType::TriangleStrip, &self.args[self.current], 0);
+    }
+}
+
+/// The metal shading language source of the particle kernels and shaders.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct Particle {
+    float4 position;
+    float4 velocity;
+    float4 color;
+    float2 size;
+    float2 padding;
+};
+
+struct DrawArgs {
+    uint vertex_count;
+    atomic_uint instance_count;
+    uint first_vertex;
+    uint base_instance;
+};
+
+struct SimulateUniforms {
+    packed_float3 gravity;
+    float dt;
+    float drag;
+    uint capacity;
+    uint2 padding;
+};
+
+struct EmitUniforms {
+    packed_float3 position;
+    float radius;
+    packed_float3 velocity;
+    float velocity_spread;
+    float4 color;
+    float2 size;
+    float2 lifetime;
+    uint count;
+    uint capacity;
+    uint seed;
+    uint padding;
+};
+
+struct DrawUniforms {
+    float4x4 view_projection;
+    float4 right;
+    float4 up;
+};
+
+kernel void particle_simulate(const device Particle *src [[buffer(0)]],
+                              device DrawArgs &src_args [[buffer(1)]],
+                              device Particle *dst [[buffer(2)]],
+                              device DrawArgs &dst_args [[buffer(3)]],
+                              constant SimulateUniforms &u [[buffer(4)]],
+                              uint id [[thread_position_in_grid]])
+{
+    uint live = atomic_load_explicit(&src_args.instance_count, memory_order_relaxed);
+    if (id >= min(live, u.capacity)) {
+        return;
+    }
+
+    Particle p = src[id];
+    p.position.w += u.dt;
+    if (p.position.w >= p.velocity.w) {
+        return;
+    }
+    p.velocity.xyz = (p.velocity.xyz + float3(u.gravity) * u.dt) * max(1.0f - u.drag * u.dt, 0.0f);
+    p.position.xyz += p.velocity.xyz * u.dt;
+
+    // fewer particles survive than were alive, so there is always room
+    uint slot = atomic_fetch_add_explicit(&dst_args.instance_count, 1, memory_order_relaxed);
+    dst[slot] = p;
+}
+
+static uint hash(uint x) {
+    x ^= x >> 16;
+    x *= 0x7feb352du;
+    x ^= x >> 15;
+    x *= 0x846ca68bu;
+    x ^= x >> 16;
+    return x;
+}
+
+static float3 random_in_sphere(thread uint &state) {
+    for (int i = 0; i < 8; i++) {
+        state = hash(state);
+        float x = float(state) / 4294967295.0f;
+        state = hash(state);
+        float y = float(state) / 4294967295.0f;
+        state = hash(state);
+        float z = float(state) / 4294967295.0f;
+        float3 v = float3(x, y, z) * 2.0f - 1.0f;
+        if (length_squared(v) <= 1.0f) {
+            return v;
+        }
+    }
+    return float3(0.0f);
+}
+
+kernel void particle_emit(device Particle *dst [[buffer(0)]],
+                          device DrawArgs &args [[buffer(1)]],
+                          constant EmitUniforms &u [[buffer(2)]],
+                          uint id [[thread_position_in_grid]])
+{
+    if (id >= u.count) {
+        return;
+    }
+
+    uint slot = atomic_fetch_add_explicit(&args.instance_count, 1, memory_order_relaxed);
+    if (slot >= u.capacity) {
+        // full: give the slot back. the count ends up at the capacity once every thread is done.
+        atomic_fetch_sub_explicit(&args.instance_count, 1, memory_order_relaxed);
+        return;
+    }
+
+    uint state = hash(id ^ hash(u.seed));
+    Particle p;
+    p.position = float4(float3(u.position) + random_in_sphere(state) * u.radius, 0.0f);
+    state = hash(state);
+    float lifetime = mix(u.lifetime.x, u.lifetime.y, float(state) / 4294967295.0f);
+    p.velocity = float4(float3(u.velocity) + random_in_sphere(state) * u.velocity_spread, lifetime);
+    p.color = u.color;
+    p.size = u.size;
+    p.padding = float2(0.0f);
+    dst[slot] = p;
+}
+
+struct ParticleFragment {
+    float4 position [[position]];
+    float2 uv;
+    float4 color;
+};
+
+vertex ParticleFragment particle_vertex(const device Particle *particles [[buffer(0)]],
+                                        constant DrawUniforms &u [[buffer(1)]],
+                                        uint vid [[vertex_id]],
+                                        uint iid [[instance_id]])
+{
+    Particle p = particles[iid];
+    float t = saturate(p.position.w / p.velocity.w);
+    float size = mix(p.size.x, p.size.y, t);
+    float2 corner = float2(vid & 1, vid >> 1) * 2.0f - 1.0f;
+    float3 position = p.position.xyz + (u.right.xyz * corner.x + u.up.xyz * corner.y) * size * 0.5f;
+
+    ParticleFragment out;
+    out.position = u.view_projection * float4(position, 1.0f);
+    out.uv = corner;
+    // fade out over the last quarter of the particle's life
+    out.color = float4(p.color.rgb, p.color.a * saturate((1.0f - t) * 4.0f));
+    return out;
+}
+
+fragment float4 particle_fragment(ParticleFragment in [[stage_in]])
+{
+    float falloff = saturate(1.0f - length_squared(in.uv));
+    return float4(in.color.rgb * in.color.a * falloff, 0.0f);
+}
+"#;
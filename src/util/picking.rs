@@ -0,0 +1,228 @@
+//! Picking objects under the cursor from an ID buffer.
+//!
+//! Editors pick by rendering every pickable object's ID into an integer target and reading back
+//! the texel under the cursor. Reading back the whole target (or waiting for the frame) is what
+//! makes that slow; `Picking` instead gathers only the requested texels into a small buffer with
+//! a compute pass, and reads them back `frames_in_flight` frames later, handing each result to
+//! the callback of its request:
+//!
+//! ```ignore
+//! picking.begin_frame(&mut device);
+//! let mut ids = commands.render_command_encoder(RenderPassDescriptor {
+//!     color_attachments: vec![picking.color_attachment()],
+//!     ..
+//! });
+//! // draw the pickable objects, writing `id + 1` from the fragment shader
+//! ids.end_encoding();
+//! if clicked {
+//!     try!(picking.pick(cursor_x, cursor_y, box move |id: Option<u32>| selection.set(id)));
+//! }
+//! let mut gather = commands.compute_command_encoder();
+//! picking.encode(&mut gather);
+//! gather.end_encoding();
+//! ```
+//!
+//! Fragment shaders write the ID as a `uint` to `[[color(0)]]`. The target is cleared to `0`,
+//! which means no object: IDs written are one more than the IDs picked.
+
+use {Device, BufferSlice, ClearColor, ComputeCommandEncoder, LoadAction, PixelFormat};
+use {RenderPassColorAttachment, Size, StoreAction, TextureDescriptor, TextureType, TextureUsage};
+use util::{as_bytes, slice_as_bytes, slice_as_bytes_mut};
+
+/// The number of requests each gather threadgroup handles.
+const THREADGROUP_SIZE: uint = 64;
+
+/// Why picks could not be requested.
+#[deriving(Show)]
+pub enum PickError {
+    /// The frame already has as many requests as the picking was created for.
+    TooManyRequests,
+}
+
+/// Why a `Picking` could not be created.
+pub enum PickingCreationError<D: Device> {
+    Texture(D::TextureCreationError),
+    Buffer(D::BufferCreationError),
+}
+
+#[repr(C)]
+struct GatherUniforms {
+    size: [u32, ..2],
+    count: u32,
+}
+
+/// A request waiting for its result.
+struct Request {
+    x: uint,
+    y: uint,
+    callback: Box<FnOnce(Option<u32>) + 'static>,
+}
+
+/// An ID target, and the readback of the texels picked from it.
+pub struct Picking<D: Device> {
+    gather: D::ComputePipeline,
+    target: D::Texture,
+    width: uint,
+    height: uint,
+    /// One `u32` per request, per frame in flight.
+    buffers: Vec<D::Buffer>,
+    /// The requests of each frame in flight.
+    requests: Vec<Vec<Request>>,
+    current: uint,
+    max_requests: uint,
+}
+
+fn create_target<D: Device>(device: &mut D, width: uint, height: uint) -> Result<D::Texture, D::TextureCreationError> {
+    device.create_texture(TextureDescriptor {
+        texture_type: TextureType::Type2D,
+        pixel_format: PixelFormat::R32Uint,
+        width: width,
+        height: height,
+        depth: 1,
+        mipmap_level_count: 1,
+        array_length: 1,
+        sample_count: 1,
+        usage: TextureUsage { shader_read: true, shader_write: false, render_target: true },
+    })
+}
+
+impl<D: Device> Picking<D> {
+    /// Create a `width` by `height` ID target, and readback for up to `max_requests` picks per
+    /// frame.
+    ///
+    /// `gather` must be a compute pipeline created from the `picking_gather` function of
+    /// `SOURCE`. As for `DebugDraw`, `frames_in_flight` must be at least the number of frames the
+    /// CPU can get ahead of the GPU: results are read back that many frames after they were
+    /// requested.
+    pub fn new(device: &mut D, gather: D::ComputePipeline, width: uint, height: uint, max_requests: uint,
+               frames_in_flight: uint) -> Result<Picking<D>, PickingCreationError<D>> {
+        let target = match create_target(device, width, height) {
+            Ok(target) => target,
+            Err(e) => return Err(PickingCreationError::Texture(e)),
+        };
+        let mut buffers = Vec::with_capacity(frames_in_flight);
+        for _ in range(0, frames_in_flight) {
+            match device.create_buffer(max_requests * 4, None) {
+                Ok(buffer) => buffers.push(buffer),
+                Err(e) => return Err(PickingCreationError::Buffer(e)),
+            }
+        }
+        Ok(Picking {
+            gather: gather,
+            target: target,
+            width: width,
+            height: height,
+            buffers: buffers,
+            requests: Vec::from_fn(frames_in_flight, |_| Vec::new()),
+            current: frames_in_flight - 1,
+            max_requests: max_requests,
+        })
+    }
+
+    /// Recreate the ID target at a new size, for when the view is resized. Requests already
+    /// encoded still get the IDs of the old target.
+    pub fn resize(&mut self, device: &mut D, width: uint, height: uint) -> Result<(), D::TextureCreationError> {
+        self.target = try!(create_target(device, width, height));
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    /// The ID target, a `R32Uint` texture.
+    pub fn target(&self) -> &D::Texture {
+        &self.target
+    }
+
+    /// The size of the ID target, in texels.
+    pub fn size(&self) -> (uint, uint) {
+        (self.width, self.height)
+    }
+
+    /// A color attachment of the ID target, cleared to no object, for the pass which renders the
+    /// IDs.
+    pub fn color_attachment(&self) -> RenderPassColorAttachment<D> {
+        RenderPassColorAttachment {
+            texture: &self.target,
+            mipmap_level: 0,
+            slice: 0,
+            load_action: LoadAction::Clear,
+            store_action: StoreAction::Store,
+            clear_color: ClearColor::Uint([0, ..4]),
+        }
+    }
+
+    /// Start a new frame: read back the picks of the frame `frames_in_flight` frames ago, and call
+    /// their callbacks.
+    // todo: as in `occlusion_queries`, `read_buffer` waits for every committed command buffer.
+    pub fn begin_frame(&mut self, device: &mut D) {
+        enter_span!("Picking::begin_frame");
+        self.current = (self.current + 1) % self.buffers.len();
+        let requests = std::mem::replace(&mut self.requests[self.current], Vec::new());
+        if requests.is_empty() {
+            return;
+        }
+        let mut ids = Vec::from_elem(requests.len(), 0u32);
+        device.read_buffer(&self.buffers[self.current], 0, slice_as_bytes_mut(ids.as_mut_slice()));
+        for (request, &id) in requests.into_iter().zip(ids.iter()) {
+            (request.callback)(if id == 0 { None } else { Some(id - 1) });
+        }
+    }
+
+    /// Request the ID at `(x, y)` of the target, in texels from the top left, as it is after this
+    /// frame's ID pass. The callback gets the ID, or `None` if no object covers the texel (or the
+    /// texel is outside the target), in `begin_frame` `frames_in_flight` frames from now.
+    pub fn pick(&mut self, x: uint, y: uint, callback: Box<FnOnce(Option<u32>) + 'static>) -> Result<(), PickError> {
+        let requests = &mut self.requests[self.current];
+        if requests.len() == self.max_requests {
+            return Err(PickError::TooManyRequests);
+        }
+        requests.push(Request { x: x, y: y, callback: callback });
+        Ok(())
+    }
+
+    /// Gather the texels of this frame's requests, after the ID pass. Does nothing if there were
+    /// none.
+    pub fn encode<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E) {
+        enter_span!("Picking::encode");
+        let requests = &self.requests[self.current];
+        if requests.is_empty() {
+            return;
+        }
+        let positions: Vec<[u32, ..2]> = requests.iter().map(|request| [request.x as u32, request.y as u32]).collect();
+        let uniforms = GatherUniforms {
+            size: [self.width as u32, self.height as u32],
+            count: requests.len() as u32,
+        };
+        encoder.set_compute_pipeline(&self.gather);
+        encoder.set_texture(&self.target, 0);
+        encoder.set_bytes(slice_as_bytes(positions.as_slice()), 0);
+        encoder.set_buffer(BufferSlice::whole(&self.buffers[self.current]), 1);
+        encoder.set_bytes(as_bytes(&uniforms), 2);
+        encoder.dispatch_threadgroups(Size { width: (requests.len() + THREADGROUP_SIZE - 1) / THREADGROUP_SIZE, height: 1, depth: 1 },
+                                      Size { width: THREADGROUP_SIZE, height: 1, depth: 1 });
+    }
+}
+
+/// The metal shading language source of the gather kernel.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct GatherUniforms {
+    uint2 size;
+    uint count;
+};
+
+kernel void picking_gather(texture2d<uint, access::read> ids [[texture(0)]],
+                           constant uint2 *positions [[buffer(0)]],
+                           device uint *results [[buffer(1)]],
+                           constant GatherUniforms &u [[buffer(2)]],
+                           uint id [[thread_position_in_grid]])
+{
+    if (id >= u.count) {
+        return;
+    }
+    uint2 p = positions[id];
+    results[id] = all(p < u.size) ? ids.read(p).x : 0;
+}
+"#;
@@ -0,0 +1,263 @@
+//! GPU prefix sums ("scans") of `u32`s, plain or segmented.
+//!
+//! An exclusive prefix sum replaces every element with the sum of the elements before it. It is
+//! the building block of most parallel algorithms which have threads append to a shared output:
+//! stream compaction (scan the keep flags, and each kept element knows where it goes), radix sort
+//! (see `util::radix_sort`), light clustering and BVH builds. A segmented scan restarts the sum at
+//! every element whose flag is set, scanning many independent arrays in one go:
+//!
+//! ```ignore
+//! let scan = try!(PrefixSum::new(&mut device, pipelines, 1 << 20));
+//! scan.exclusive_scan(&mut compute, &keep, &offsets, count);
+//! // offsets[i] is where kept element i goes, and scan.total() holds how many were kept
+//! ```
+//!
+//! Scans run in a few dispatches: each threadgroup scans a block of `BLOCK_SIZE` elements and
+//! writes the block's sum, the block sums are scanned the same way (recursively, a level for
+//! every factor of `BLOCK_SIZE`), and the scanned sums are added back into the blocks. Sums wrap
+//! around on overflow.
+
+use {Device, BufferSlice, ComputeCommandEncoder, Size};
+use util::as_bytes;
+
+/// The number of elements each threadgroup scans, and the number of threads in it.
+pub const BLOCK_SIZE: uint = 256;
+
+/// The pipelines a `PrefixSum` dispatches, each created from the function of `SOURCE` its field
+/// is named after.
+pub struct PrefixSumPipelines<D: Device> {
+    pub scan_blocks: D::ComputePipeline,
+    pub scan_sums: D::ComputePipeline,
+    pub add_sums: D::ComputePipeline,
+    pub add_blocks: D::ComputePipeline,
+}
+
+#[repr(C)]
+struct ScanUniforms {
+    count: u32,
+    segmented: u32,
+    _padding: [u32, ..2],
+}
+
+/// The pipelines and the block sums of scans of up to `capacity` elements.
+pub struct PrefixSum<D: Device> {
+    pipelines: PrefixSumPipelines<D>,
+    /// The block sums of each level, as `uint2`s of the sum and whether a segment starts in the
+    /// block, and their number.
+    levels: Vec<(D::Buffer, uint)>,
+    /// The sum of the whole last scan, as a `uint2` like the block sums.
+    total: D::Buffer,
+    capacity: uint,
+}
+
+fn blocks(count: uint) -> uint {
+    (count + BLOCK_SIZE - 1) / BLOCK_SIZE
+}
+
+impl<D: Device> PrefixSum<D> {
+    /// Create the block sums of scans of up to `capacity` elements.
+    pub fn new(device: &mut D, pipelines: PrefixSumPipelines<D>, capacity: uint) -> Result<PrefixSum<D>, D::BufferCreationError> {
+        let mut levels = Vec::new();
+        let mut count = std::cmp::max(blocks(capacity), 1);
+        loop {
+            levels.push((try!(device.create_buffer(count * 8, None)), count));
+            if count == 1 {
+                break;
+            }
+            count = blocks(count);
+        }
+        let total = try!(device.create_buffer(8, None));
+        Ok(PrefixSum { pipelines: pipelines, levels: levels, total: total, capacity: capacity })
+    }
+
+    /// The most elements a scan can have.
+    pub fn capacity(&self) -> uint {
+        self.capacity
+    }
+
+    /// The sum of every element of the last scan, as a `u32`, followed by a `u32` which is
+    /// non-zero if the scan was segmented and any flag was set. For a segmented scan, the sum is
+    /// that of the last segment. Written by the GPU, to read in later passes (or indirect
+    /// arguments) without waiting for it.
+    pub fn total(&self) -> &D::Buffer {
+        &self.total
+    }
+
+    /// Write the exclusive prefix sum of the first `count` `u32`s of `input` to `output`, which
+    /// may be `input`.
+    pub fn exclusive_scan<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, input: &D::Buffer, output: &D::Buffer, count: uint) {
+        enter_span!("PrefixSum::exclusive_scan", count = count);
+        self.scan(encoder, input, input, output, count, false);
+    }
+
+    /// As `exclusive_scan`, but the sum starts again from zero at every element whose flag, the
+    /// `u32` at the same index of `flags`, is non-zero.
+    pub fn segmented_exclusive_scan<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, input: &D::Buffer, flags: &D::Buffer,
+                                                                 output: &D::Buffer, count: uint) {
+        enter_span!("PrefixSum::segmented_exclusive_scan", count = count);
+        self.scan(encoder, input, flags, output, count, true);
+    }
+
+    fn scan<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, input: &D::Buffer, flags: &D::Buffer, output: &D::Buffer,
+                                         count: uint, segmented: bool) {
+        assert!(count <= self.capacity, "a scan of {} elements exceeds the capacity of {}", count, self.capacity);
+        if count == 0 {
+            return;
+        }
+        let threads = || Size { width: BLOCK_SIZE, height: 1, depth: 1 };
+        let groups = |count: uint| Size { width: blocks(count), height: 1, depth: 1 };
+        let uniforms = |count: uint| ScanUniforms { count: count as u32, segmented: segmented as u32, _padding: [0, ..2] };
+
+        // the levels of block sums this scan uses, the last of which is a single block
+        let mut used = Vec::new();
+        let mut level_count = blocks(count);
+        for &(ref buffer, _) in self.levels.iter() {
+            used.push((buffer, level_count));
+            if level_count == 1 {
+                break;
+            }
+            level_count = blocks(level_count);
+        }
+
+        encoder.set_compute_pipeline(&self.pipelines.scan_blocks);
+        encoder.set_buffer(BufferSlice::whole(input), 0);
+        encoder.set_buffer(BufferSlice::whole(flags), 1);
+        encoder.set_buffer(BufferSlice::whole(output), 2);
+        encoder.set_buffer(BufferSlice::whole(used[0].0), 3);
+        encoder.set_bytes(as_bytes(&uniforms(count)), 4);
+        encoder.dispatch_threadgroups(groups(count), threads());
+
+        encoder.set_compute_pipeline(&self.pipelines.scan_sums);
+        for (index, &(sums, sum_count)) in used.iter().enumerate() {
+            let next = if index + 1 < used.len() { used[index + 1].0 } else { &self.total };
+            encoder.set_buffer(BufferSlice::whole(sums), 0);
+            encoder.set_buffer(BufferSlice::whole(next), 1);
+            encoder.set_bytes(as_bytes(&uniforms(sum_count)), 2);
+            encoder.dispatch_threadgroups(groups(sum_count), threads());
+        }
+
+        encoder.set_compute_pipeline(&self.pipelines.add_sums);
+        for index in range(1, used.len()).rev() {
+            let (sums, sum_count) = used[index - 1];
+            encoder.set_buffer(BufferSlice::whole(sums), 0);
+            encoder.set_buffer(BufferSlice::whole(used[index].0), 1);
+            encoder.set_bytes(as_bytes(&uniforms(sum_count)), 2);
+            encoder.dispatch_threadgroups(groups(sum_count), threads());
+        }
+
+        encoder.set_compute_pipeline(&self.pipelines.add_blocks);
+        encoder.set_buffer(BufferSlice::whole(flags), 0);
+        encoder.set_buffer(BufferSlice::whole(output), 1);
+        encoder.set_buffer(BufferSlice::whole(used[0].0), 2);
+        encoder.set_bytes(as_bytes(&uniforms(count)), 3);
+        encoder.dispatch_threadgroups(groups(count), threads());
+    }
+}
+
+/// Metal shading language source of the scan kernels.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+#define BLOCK_SIZE 256
+
+struct ScanUniforms {
+    uint count;
+    uint segmented;
+    uint padding[2];
+};
+
+// a partial sum, and whether a segment starts within it
+struct Seg {
+    uint value;
+    uint flag;
+};
+
+static Seg combine(Seg a, Seg b) {
+    return Seg { b.flag != 0 ? b.value : a.value + b.value, a.flag | b.flag };
+}
+
+// the inclusive scan of every thread's x within the threadgroup, also left in shared
+static Seg block_inclusive_scan(Seg x, threadgroup Seg *shared, uint lid) {
+    shared[lid] = x;
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+    for (uint offset = 1; offset < BLOCK_SIZE; offset <<= 1) {
+        Seg other = shared[lid >= offset ? lid - offset : lid];
+        threadgroup_barrier(mem_flags::mem_threadgroup);
+        if (lid >= offset) {
+            x = combine(other, x);
+        }
+        shared[lid] = x;
+        threadgroup_barrier(mem_flags::mem_threadgroup);
+    }
+    return x;
+}
+
+kernel void scan_blocks(const device uint *input [[buffer(0)]],
+                        const device uint *flags [[buffer(1)]],
+                        device uint *output [[buffer(2)]],
+                        device uint2 *sums [[buffer(3)]],
+                        constant ScanUniforms &u [[buffer(4)]],
+                        uint id [[thread_position_in_grid]],
+                        uint lid [[thread_position_in_threadgroup]],
+                        uint group [[threadgroup_position_in_grid]]) {
+    threadgroup Seg shared[BLOCK_SIZE];
+    Seg x = Seg { id < u.count ? input[id] : 0, (u.segmented != 0 && id < u.count && flags[id] != 0) ? 1u : 0u };
+    Seg inclusive = block_inclusive_scan(x, shared, lid);
+    if (id < u.count) {
+        output[id] = x.flag != 0 ? 0 : inclusive.value - x.value;
+    }
+    if (lid == BLOCK_SIZE - 1) {
+        sums[group] = uint2(inclusive.value, inclusive.flag);
+    }
+}
+
+// scans block sums in place, keeping in .y whether a segment starts before each sum in its
+// block (after which the sums of earlier blocks no longer count), and writes the sum of every
+// block to the next level
+kernel void scan_sums(device uint2 *sums [[buffer(0)]],
+                      device uint2 *next [[buffer(1)]],
+                      constant ScanUniforms &u [[buffer(2)]],
+                      uint id [[thread_position_in_grid]],
+                      uint lid [[thread_position_in_threadgroup]],
+                      uint group [[threadgroup_position_in_grid]]) {
+    threadgroup Seg shared[BLOCK_SIZE];
+    Seg x = id < u.count ? Seg { sums[id].x, sums[id].y } : Seg { 0, 0 };
+    Seg inclusive = block_inclusive_scan(x, shared, lid);
+    Seg exclusive = lid > 0 ? shared[lid - 1] : Seg { 0, 0 };
+    if (id < u.count) {
+        sums[id] = uint2(exclusive.value, exclusive.flag);
+    }
+    if (lid == BLOCK_SIZE - 1) {
+        next[group] = uint2(inclusive.value, inclusive.flag);
+    }
+}
+
+// adds the scanned sums of the next level to a level's, up to the first segment start of each
+// block
+kernel void add_sums(device uint2 *sums [[buffer(0)]],
+                     const device uint2 *next [[buffer(1)]],
+                     constant ScanUniforms &u [[buffer(2)]],
+                     uint id [[thread_position_in_grid]],
+                     uint group [[threadgroup_position_in_grid]]) {
+    if (id < u.count && sums[id].y == 0) {
+        sums[id].x += next[group].x;
+    }
+}
+
+kernel void add_blocks(const device uint *flags [[buffer(0)]],
+                       device uint *output [[buffer(1)]],
+                       const device uint2 *sums [[buffer(2)]],
+                       constant ScanUniforms &u [[buffer(3)]],
+                       uint id [[thread_position_in_grid]],
+                       uint lid [[thread_position_in_threadgroup]],
+                       uint group [[threadgroup_position_in_grid]]) {
+    threadgroup Seg shared[BLOCK_SIZE];
+    // whether a segment starts in the block at or before this element
+    Seg x = Seg { 0, (u.segmented != 0 && id < u.count && flags[id] != 0) ? 1u : 0u };
+    Seg started = block_inclusive_scan(x, shared, lid);
+    if (id < u.count && started.flag == 0) {
+        output[id] += sums[group].x;
+    }
+}
+"#;
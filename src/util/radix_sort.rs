@@ -0,0 +1,242 @@
+//! GPU radix sort of `u32` keys, with or without `u32` values.
+//!
+//! The sort is stable, and takes one pass per 4 bits of key: each threadgroup counts the digits
+//! of a block of keys, the counts are scanned with `util::prefix_sum` into where each block's
+//! keys of each digit go, and every key (and its value) is scattered there. Keys which only use
+//! their low bits sort in fewer passes:
+//!
+//! ```ignore
+//! let scan = try!(PrefixSum::new(&mut device, scan_pipelines, scan_capacity(1 << 20)));
+//! let sort = try!(RadixSort::new(&mut device, pipelines, scan, 1 << 20));
+//! // particles back to front: sortable depths as keys, particle indices as values
+//! sort.sort_pairs(&mut compute, &depths, &indices, count, 32);
+//! ```
+//!
+//! Floats sort as `float_key` makes them (`float_to_key` in `SOURCE`, for keys written by
+//! shaders). Segmented sorts, sorting many arrays in one go, are sorts by the segment index in
+//! the high bits of the key and the key in the low bits; the counts of each segment then come
+//! from a segmented scan (see `PrefixSum::segmented_exclusive_scan`).
+
+use {Device, BufferSlice, ComputeCommandEncoder, Size};
+use util::as_bytes;
+use util::prefix_sum::PrefixSum;
+
+/// The number of keys each threadgroup sorts, and the number of threads in it.
+pub const BLOCK_SIZE: uint = 256;
+
+/// The number of bits of key each pass sorts by.
+const DIGIT_BITS: uint = 4;
+const DIGITS: uint = 1 << DIGIT_BITS;
+
+/// The pipelines a `RadixSort` dispatches, created from the `radix_count` and `radix_scatter`
+/// functions of `SOURCE`.
+pub struct RadixSortPipelines<D: Device> {
+    pub count: D::ComputePipeline,
+    pub scatter: D::ComputePipeline,
+}
+
+#[repr(C)]
+struct SortUniforms {
+    count: u32,
+    shift: u32,
+    block_count: u32,
+    has_values: u32,
+}
+
+/// `value` as a key which sorts in the order of the floats: negative numbers, from the most
+/// negative, then positive numbers. NaNs sort past the infinities of their sign.
+pub fn float_key(value: f32) -> u32 {
+    let bits: u32 = unsafe { std::mem::transmute(value) };
+    if bits & 0x80000000 != 0 { !bits } else { bits | 0x80000000 }
+}
+
+/// The capacity the `PrefixSum` of sorts of up to `capacity` keys needs: a count for every
+/// digit of every block.
+pub fn scan_capacity(capacity: uint) -> uint {
+    std::cmp::max((capacity + BLOCK_SIZE - 1) / BLOCK_SIZE, 1) * DIGITS
+}
+
+/// The pipelines, counts and scratch buffers of sorts of up to `capacity` keys.
+pub struct RadixSort<D: Device> {
+    pipelines: RadixSortPipelines<D>,
+    scan: PrefixSum<D>,
+    /// The number of keys of every digit in every block, digit major, scanned into offsets.
+    counts: D::Buffer,
+    /// The keys and values between passes.
+    keys: D::Buffer,
+    values: D::Buffer,
+    capacity: uint,
+}
+
+impl<D: Device> RadixSort<D> {
+    /// Create the buffers of sorts of up to `capacity` keys. `scan` must have a capacity of at
+    /// least `scan_capacity(capacity)`.
+    pub fn new(device: &mut D, pipelines: RadixSortPipelines<D>, scan: PrefixSum<D>, capacity: uint)
+               -> Result<RadixSort<D>, D::BufferCreationError> {
+        let scanned = scan_capacity(capacity);
+        assert!(scan.capacity() >= scanned, "the scan of a sort of {} keys needs a capacity of {}", capacity, scanned);
+        let counts = try!(device.create_buffer(scanned * 4, None));
+        let keys = try!(device.create_buffer(capacity * 4, None));
+        let values = try!(device.create_buffer(capacity * 4, None));
+        Ok(RadixSort { pipelines: pipelines, scan: scan, counts: counts, keys: keys, values: values, capacity: capacity })
+    }
+
+    /// The most keys a sort can have.
+    pub fn capacity(&self) -> uint {
+        self.capacity
+    }
+
+    /// Sort the first `count` `u32`s of `keys`, in place, by their low `key_bits` bits, a
+    /// multiple of 8.
+    pub fn sort_keys<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, keys: &D::Buffer, count: uint, key_bits: uint) {
+        enter_span!("RadixSort::sort_keys", count = count);
+        self.sort(encoder, keys, None, count, key_bits);
+    }
+
+    /// As `sort_keys`, moving the `u32` at the same index of `values` along with every key.
+    pub fn sort_pairs<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, keys: &D::Buffer, values: &D::Buffer, count: uint,
+                                                   key_bits: uint) {
+        enter_span!("RadixSort::sort_pairs", count = count);
+        self.sort(encoder, keys, Some(values), count, key_bits);
+    }
+
+    fn sort<E: ComputeCommandEncoder<D>>(&self, encoder: &mut E, keys: &D::Buffer, values: Option<&D::Buffer>, count: uint,
+                                         key_bits: uint) {
+        assert!(count <= self.capacity, "a sort of {} keys exceeds the capacity of {}", count, self.capacity);
+        // an even number of passes ends with the keys back where they started
+        assert!(key_bits % 8 == 0 && key_bits <= 32, "sorting by {} bits of key, not a multiple of 8", key_bits);
+        if count == 0 {
+            return;
+        }
+        let block_count = (count + BLOCK_SIZE - 1) / BLOCK_SIZE;
+        let groups = || Size { width: block_count, height: 1, depth: 1 };
+        let threads = || Size { width: BLOCK_SIZE, height: 1, depth: 1 };
+
+        for pass in range(0, key_bits / DIGIT_BITS) {
+            let (source_keys, destination_keys) = if pass % 2 == 0 { (keys, &self.keys) } else { (&self.keys, keys) };
+            let (source_values, destination_values) = match values {
+                Some(values) if pass % 2 == 0 => (values, &self.values),
+                Some(values) => (&self.values, values),
+                None => (&self.values, &self.values),
+            };
+            let uniforms = SortUniforms {
+                count: count as u32,
+                shift: (pass * DIGIT_BITS) as u32,
+                block_count: block_count as u32,
+                has_values: values.is_some() as u32,
+            };
+
+            encoder.set_compute_pipeline(&self.pipelines.count);
+            encoder.set_buffer(BufferSlice::whole(source_keys), 0);
+            encoder.set_buffer(BufferSlice::whole(&self.counts), 1);
+            encoder.set_bytes(as_bytes(&uniforms), 2);
+            encoder.dispatch_threadgroups(groups(), threads());
+
+            self.scan.exclusive_scan(encoder, &self.counts, &self.counts, block_count * DIGITS);
+
+            encoder.set_compute_pipeline(&self.pipelines.scatter);
+            encoder.set_buffer(BufferSlice::whole(source_keys), 0);
+            encoder.set_buffer(BufferSlice::whole(source_values), 1);
+            encoder.set_buffer(BufferSlice::whole(destination_keys), 2);
+            encoder.set_buffer(BufferSlice::whole(destination_values), 3);
+            encoder.set_buffer(BufferSlice::whole(&self.counts), 4);
+            encoder.set_bytes(as_bytes(&uniforms), 5);
+            encoder.dispatch_threadgroups(groups(), threads());
+        }
+    }
+}
+
+/// Metal shading language source of the sort kernels.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+#define BLOCK_SIZE 256
+#define DIGITS 16
+
+struct SortUniforms {
+    uint count;
+    uint shift;
+    uint block_count;
+    uint has_values;
+};
+
+// a key which sorts in the order of the floats, as float_key does on the CPU
+static inline uint float_to_key(float value) {
+    uint bits = as_type<uint>(value);
+    return (bits & 0x80000000u) != 0 ? ~bits : bits | 0x80000000u;
+}
+
+kernel void radix_count(const device uint *keys [[buffer(0)]],
+                        device uint *counts [[buffer(1)]],
+                        constant SortUniforms &u [[buffer(2)]],
+                        uint id [[thread_position_in_grid]],
+                        uint lid [[thread_position_in_threadgroup]],
+                        uint group [[threadgroup_position_in_grid]]) {
+    threadgroup atomic_uint histogram[DIGITS];
+    if (lid < DIGITS) {
+        atomic_store_explicit(&histogram[lid], 0, memory_order_relaxed);
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+    if (id < u.count) {
+        atomic_fetch_add_explicit(&histogram[(keys[id] >> u.shift) & (DIGITS - 1)], 1, memory_order_relaxed);
+    }
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+    // digit major, so that scanning the counts orders keys by digit first and block second
+    if (lid < DIGITS) {
+        counts[lid * u.block_count + group] = atomic_load_explicit(&histogram[lid], memory_order_relaxed);
+    }
+}
+
+// the counts of the 16 digits, in 16 bit lanes
+struct DigitCounts {
+    uint lanes[DIGITS / 2];
+};
+
+kernel void radix_scatter(const device uint *keys [[buffer(0)]],
+                          const device uint *values [[buffer(1)]],
+                          device uint *sorted_keys [[buffer(2)]],
+                          device uint *sorted_values [[buffer(3)]],
+                          const device uint *offsets [[buffer(4)]],
+                          constant SortUniforms &u [[buffer(5)]],
+                          uint id [[thread_position_in_grid]],
+                          uint lid [[thread_position_in_threadgroup]],
+                          uint group [[threadgroup_position_in_grid]]) {
+    threadgroup DigitCounts shared[BLOCK_SIZE];
+    uint key = id < u.count ? keys[id] : 0;
+    uint digit = (key >> u.shift) & (DIGITS - 1);
+
+    // the rank of the key among the keys of its digit in the block, from an inclusive scan of
+    // one-hot digit counts; keys past the end count for no digit
+    DigitCounts x;
+    for (uint i = 0; i < DIGITS / 2; i++) {
+        x.lanes[i] = 0;
+    }
+    if (id < u.count) {
+        x.lanes[digit / 2] = 1u << (16 * (digit % 2));
+    }
+    shared[lid] = x;
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+    for (uint offset = 1; offset < BLOCK_SIZE; offset <<= 1) {
+        DigitCounts other = shared[lid >= offset ? lid - offset : lid];
+        threadgroup_barrier(mem_flags::mem_threadgroup);
+        if (lid >= offset) {
+            for (uint i = 0; i < DIGITS / 2; i++) {
+                x.lanes[i] += other.lanes[i];
+            }
+        }
+        shared[lid] = x;
+        threadgroup_barrier(mem_flags::mem_threadgroup);
+    }
+    if (id >= u.count) {
+        return;
+    }
+
+    uint rank = ((x.lanes[digit / 2] >> (16 * (digit % 2))) & 0xffff) - 1;
+    uint destination = offsets[digit * u.block_count + group] + rank;
+    sorted_keys[destination] = key;
+    if (u.has_values != 0) {
+        sorted_values[destination] = values[id];
+    }
+}
+"#;
@@ -0,0 +1,188 @@
+//! Reading GPU results back without allocating a buffer per readback.
+//!
+//! The readback side of `TransientBuffer`: `ReadbackHeap` sub-allocates slices of a pool of
+//! buffers for `copy_texture_to_buffer` (or compute shaders) to write into, watches the command
+//! buffer the copies were submitted in, and hands back the contents of every slice once it has
+//! completed. The buffers are then reused, so a steady stream of readbacks (screenshots, GPU
+//! picking, histograms) settles on a fixed set of buffers:
+//!
+//! ```ignore
+//! let slice = readback.allocate(&mut device, bytes_per_row * height).unwrap();
+//! let mut blit = commands.blit_command_encoder();
+//! blit.copy_texture_to_buffer(&frame, 0, 0, Origin { x: 0, y: 0, z: 0 }, size,
+//!                             readback.slice(&slice), bytes_per_row);
+//! blit.end_encoding();
+//! readback.submit(&mut commands);
+//! commands.commit();
+//!
+//! // any later frame
+//! for result in readback.poll(&mut device).into_iter() {
+//!     if result.id == slice.id {
+//!         save_screenshot(result.data.as_slice());
+//!     }
+//! }
+//! ```
+
+use {Device, BufferSlice, CommandBuffer, CommandBufferStatus, COPY_OFFSET_ALIGNMENT};
+use util::fence::Fence;
+use util::round_up;
+
+/// Identifies an allocation in the results of `poll`.
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+pub struct ReadbackId(u64);
+
+/// A slice of the heap to copy into, returned by `allocate`.
+pub struct ReadbackAllocation {
+    pub id: ReadbackId,
+    chunk: uint,
+    offset: uint,
+    size: uint,
+}
+
+/// The contents of an allocation, read back after its command buffer completed.
+pub struct Readback {
+    pub id: ReadbackId,
+    /// How the command buffer finished. The data is undefined if it failed.
+    pub status: CommandBufferStatus,
+    pub data: Vec<u8>,
+}
+
+struct Chunk<D: Device> {
+    buffer: D::Buffer,
+    size: uint,
+    used: uint,
+}
+
+/// The allocations of one `submit`, and the chunks they are in.
+struct Batch {
+    fence: Fence,
+    chunks: Vec<uint>,
+    allocations: Vec<ReadbackAllocation>,
+}
+
+/// A pool of readback buffers. See the module documentation.
+pub struct ReadbackHeap<D: Device> {
+    chunks: Vec<Chunk<D>>,
+    chunk_size: uint,
+    /// Chunks with nothing in flight.
+    free: Vec<uint>,
+    /// The chunks of the allocations not submitted yet, the last of which is allocated from.
+    open: Vec<uint>,
+    pending: Vec<ReadbackAllocation>,
+    /// Submitted batches, oldest first.
+    in_flight: Vec<Batch>,
+    next_id: u64,
+}
+
+impl<D: Device> ReadbackHeap<D> {
+    /// Create an empty heap, which allocates buffers of `chunk_size` bytes (or the size of an
+    /// allocation, if it is larger) as it needs them.
+    pub fn new(chunk_size: uint) -> ReadbackHeap<D> {
+        ReadbackHeap {
+            chunks: Vec::new(),
+            chunk_size: chunk_size,
+            free: Vec::new(),
+            open: Vec::new(),
+            pending: Vec::new(),
+            in_flight: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Allocate `size` bytes, at an offset which can be the destination of
+    /// `copy_texture_to_buffer`.
+    pub fn allocate(&mut self, device: &mut D, size: uint) -> Result<ReadbackAllocation, D::BufferCreationError> {
+        let fits = |chunk: &Chunk<D>| round_up(chunk.used, COPY_OFFSET_ALIGNMENT) + size <= chunk.size;
+        let current = match self.open.last() {
+            Some(&index) if fits(&self.chunks[index]) => index,
+            _ => {
+                let index = match self.free.iter().position(|&index| size <= self.chunks[index].size) {
+                    Some(position) => self.free.swap_remove(position).unwrap(),
+                    None => {
+                        let chunk_size = std::cmp::max(self.chunk_size, size);
+                        let buffer = try!(device.create_buffer(chunk_size, None));
+                        self.chunks.push(Chunk { buffer: buffer, size: chunk_size, used: 0 });
+                        self.chunks.len() - 1
+                    }
+                };
+                self.open.push(index);
+                index
+            }
+        };
+        let chunk = &mut self.chunks[current];
+        let offset = round_up(chunk.used, COPY_OFFSET_ALIGNMENT);
+        chunk.used = offset + size;
+        let id = ReadbackId(self.next_id);
+        self.next_id += 1;
+        self.pending.push(ReadbackAllocation { id: id.clone(), chunk: current, offset: offset, size: size });
+        Ok(ReadbackAllocation { id: id, chunk: current, offset: offset, size: size })
+    }
+
+    /// The buffer slice of `allocation`, to copy into.
+    pub fn slice(&self, allocation: &ReadbackAllocation) -> BufferSlice<D::Buffer> {
+        BufferSlice::new(&self.chunks[allocation.chunk].buffer, allocation.offset, allocation.size)
+    }
+
+    /// Mark every allocation since the last `submit` as written by `commands`, whose completion
+    /// makes them readable. `commands` must not have been committed yet.
+    pub fn submit<C: CommandBuffer<D>>(&mut self, commands: &mut C) {
+        enter_span!("ReadbackHeap::submit", allocations = self.pending.len());
+        if self.pending.is_empty() {
+            return;
+        }
+        let fence = Fence::new();
+        commands.add_completed_handler(fence.completed_handler());
+        self.in_flight.push(Batch {
+            fence: fence,
+            chunks: std::mem::replace(&mut self.open, Vec::new()),
+            allocations: std::mem::replace(&mut self.pending, Vec::new()),
+        });
+    }
+
+    /// The contents of every allocation whose command buffer has completed since the last `poll`.
+    /// The allocations of each `submit` are in the order they were allocated. Their chunks are
+    /// recycled.
+    ///
+    /// Only completed batches are read, but reading them back with `read_buffer` stalls until
+    /// every command buffer committed so far has completed, including those of later frames. If
+    /// nothing has completed, this returns without waiting.
+    // todo: `read_buffer` waits for every committed command buffer, not just the ones of the
+    // batches read (as in `gpu_profiler`). Read without waiting once buffers can be mapped.
+    pub fn poll(&mut self, device: &mut D) -> Vec<Readback> {
+        enter_span!("ReadbackHeap::poll");
+        let mut results = Vec::new();
+        // batches complete in the order their command buffers were committed, which need not be
+        // the order they were submitted in
+        let mut i = 0;
+        while i < self.in_flight.len() {
+            let status = match self.in_flight[i].fence.status() {
+                Some(status) => status,
+                None => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let batch = self.in_flight.remove(i).unwrap();
+            for allocation in batch.allocations.into_iter() {
+                let mut data = Vec::from_elem(allocation.size, 0u8);
+                device.read_buffer(&self.chunks[allocation.chunk].buffer, allocation.offset, data.as_mut_slice());
+                results.push(Readback { id: allocation.id, status: status.clone(), data: data });
+            }
+            for &index in batch.chunks.iter() {
+                self.chunks[index].used = 0;
+                self.free.push(index);
+            }
+        }
+        results
+    }
+
+    /// The number of submitted batches not read back yet.
+    pub fn in_flight(&self) -> uint {
+        self.in_flight.len()
+    }
+
+    /// The total size of the heap's buffers, in bytes.
+    pub fn capacity(&self) -> uint {
+        self.chunks.iter().fold(0, |sum, chunk| sum + chunk.size)
+    }
+}
@@ -0,0 +1,934 @@
+//! A render graph: the passes of a frame, declared with the textures they read and write.
+//!
+//! Passes are declared in the order they run, each with a closure encoding it. On `execute`, the
+//! graph culls the passes whose results nothing uses, gives every transient texture a texture of
+//! the long lived `GraphResources` pool (textures whose uses do not overlap share one), and runs
+//! what is left:
+//!
+//! ```ignore
+//! let mut graph = RenderGraph::new(&mut resources, window_size);
+//! let hdr = graph.create_texture("hdr", GraphTextureDescriptor::relative(1.0, PixelFormat::RGBA16Float));
+//! let taa = graph.history("taa", GraphTextureDescriptor::relative(1.0, PixelFormat::RGBA16Float));
+//! let window = graph.import_texture("window", &window_texture);
+//! graph.add_pass("scene", PassKind::Render, &[], &[hdr], |commands, textures| {
+//!     // render into textures.texture(hdr)
+//! });
+//! graph.add_pass("taa", PassKind::Compute, &[hdr, taa.previous], &[taa.current], |commands, textures| {
+//!     // blend with textures.texture(taa.previous), if textures.has_history(&taa)
+//! });
+//! graph.add_pass("present", PassKind::Render, &[taa.current], &[window], |commands, textures| { .. });
+//! try!(graph.execute(&mut device, &mut commands));
+//! ```
+//!
+//! A pass must declare every graph texture it touches: the graph has no other way of knowing the
+//! order to keep, or when a pooled texture is free for the next use.
+//!
+//! History textures are the ones which outlive a frame, for effects which reuse last frame's
+//! result (temporal antialiasing, eye adaptation, reflections reprojecting the last frame). Each
+//! is two textures of the pool which swap every frame it is written in: `current` is written this
+//! frame, and `previous` holds what was written the frame before, when there is such a frame.
+//!
+//! Compute passes hinted with `QueueHint::AsyncCompute` run on a second queue when the graph is
+//! executed with `execute_with_async_compute`. The graph works out from the declared textures
+//! which passes of one queue must wait for which of the other, and synchronizes just those with
+//! events; everything else overlaps.
+//!
+//! `execute_views` runs the whole graph several times in a frame, once per view (the players of
+//! a split screen, say), sharing the transient textures between them.
+//!
+//! With `GraphResources::set_reporting`, every execution leaves a `GraphReport` of what it
+//! decided: the passes culled, the queue of each pass and what it waits for, and which pooled
+//! texture each transient texture was given. `to_graphviz` and `to_json` export it, to see why
+//! a pass ran where it did or which textures share memory.
+
+use {Device, CommandBuffer, PixelFormat, ScissorRect, TextureDescriptor, TextureType, TextureUsage, Viewport};
+
+/// A texture of a `RenderGraph`, only meaningful in the graph which declared it.
+#[deriving(Clone, PartialEq, Show)]
+pub struct TextureId(uint);
+
+/// The two textures of a history, as declared in one frame.
+#[deriving(Clone, Show)]
+pub struct HistoryTexture {
+    /// The texture written this frame.
+    pub current: TextureId,
+    /// The texture written last frame.
+    pub previous: TextureId,
+}
+
+/// The size of a graph texture.
+#[deriving(Clone, PartialEq, Show)]
+pub enum TextureSize {
+    /// A fixed size, in pixels.
+    Absolute(uint, uint),
+    /// A fraction of the graph's output size, rounded, and at least one pixel.
+    Relative(f32),
+}
+
+/// A transient or history texture. Graph textures are always 2D, single sampled and
+/// `shader_read`; whether they are render targets or `shader_write` follows from the passes
+/// which write them.
+#[deriving(Clone)]
+pub struct GraphTextureDescriptor {
+    pub size: TextureSize,
+    pub pixel_format: PixelFormat,
+    pub mipmap_level_count: uint,
+}
+
+impl GraphTextureDescriptor {
+    /// A texture of `scale` times the output size, without mipmaps.
+    pub fn relative(scale: f32, pixel_format: PixelFormat) -> GraphTextureDescriptor {
+        GraphTextureDescriptor { size: TextureSize::Relative(scale), pixel_format: pixel_format, mipmap_level_count: 1 }
+    }
+}
+
+/// What kind of encoder a pass records into, which decides how the textures it writes are used.
+#[deriving(Clone, PartialEq, Show)]
+pub enum PassKind {
+    /// Writes its textures as attachments.
+    Render,
+    /// Writes its textures from shaders.
+    Compute,
+    /// Writes its textures with copies.
+    Blit,
+}
+
+/// A pass of a `RenderGraph`, only meaningful in the graph which declared it.
+#[deriving(Clone, PartialEq, Show)]
+pub struct PassId(uint);
+
+/// Which queue a pass would rather run on.
+#[deriving(Clone, PartialEq, Show)]
+pub enum QueueHint {
+    /// The queue of the graph's main command buffer, in the order the passes were declared.
+    Main,
+    /// The queue of the async compute command buffer, if `execute_with_async_compute` is given
+    /// one, overlapping with the main queue's passes it does not depend on. Only compute passes
+    /// can take this hint; any other pass stays on the main queue.
+    AsyncCompute,
+}
+
+/// One execution of a graph, for `execute_views`.
+#[deriving(Clone)]
+pub struct GraphView {
+    /// The part of the imported textures the view renders into, in pixels. Relative textures
+    /// are sized after it.
+    pub region: ScissorRect,
+}
+
+impl GraphView {
+    /// The viewport of the view's region, for passes rendering into the imported textures.
+    /// Passes rendering into graph textures render into all of them.
+    pub fn viewport(&self) -> Viewport {
+        Viewport {
+            x: self.region.x as f64,
+            y: self.region.y as f64,
+            width: self.region.width as f64,
+            height: self.region.height as f64,
+            znear: 0.0,
+            zfar: 1.0,
+        }
+    }
+}
+
+/// Why a graph could not be executed.
+pub enum GraphError<D: Device> {
+    /// A pass reads a transient texture, or the current texture of a history, which no pass
+    /// before it writes.
+    ReadBeforeWrite { pass: String, texture: String },
+    Texture(D::TextureCreationError),
+}
+
+/// Everything a concrete texture is created from, which textures must match to be reused.
+#[deriving(Clone, PartialEq)]
+struct TextureKey {
+    width: uint,
+    height: uint,
+    pixel_format: PixelFormat,
+    mipmap_level_count: uint,
+    render_target: bool,
+    shader_write: bool,
+}
+
+impl TextureKey {
+    fn create<D: Device>(&self, device: &mut D) -> Result<D::Texture, D::TextureCreationError> {
+        device.create_texture(TextureDescriptor {
+            texture_type: TextureType::Type2D,
+            pixel_format: self.pixel_format.clone(),
+            width: self.width,
+            height: self.height,
+            depth: 1,
+            mipmap_level_count: self.mipmap_level_count,
+            array_length: 1,
+            sample_count: 1,
+            usage: TextureUsage { shader_read: true, shader_write: self.shader_write, render_target: self.render_target },
+        })
+    }
+}
+
+fn texture_key(descriptor: &GraphTextureDescriptor, output_size: (uint, uint), render_target: bool, shader_write: bool) -> TextureKey {
+    let (width, height) = match descriptor.size {
+        TextureSize::Absolute(width, height) => (width, height),
+        TextureSize::Relative(scale) => {
+            let (width, height) = output_size;
+            (std::cmp::max(1, (width as f32 * scale).round() as uint),
+             std::cmp::max(1, (height as f32 * scale).round() as uint))
+        }
+    };
+    TextureKey {
+        width: width,
+        height: height,
+        pixel_format: descriptor.pixel_format.clone(),
+        mipmap_level_count: descriptor.mipmap_level_count,
+        render_target: render_target,
+        shader_write: shader_write,
+    }
+}
+
+struct PooledTexture<D: Device> {
+    key: TextureKey,
+    texture: D::Texture,
+    last_used: u64,
+}
+
+struct History<D: Device> {
+    name: String,
+    /// The index of the view the history is of, as each view of `execute_views` has its own.
+    view: uint,
+    key: TextureKey,
+    textures: Vec<D::Texture>,
+    /// The index in `textures` written last.
+    latest: uint,
+    /// The frame `latest` was written in, unless it has been invalidated since.
+    written: Option<u64>,
+}
+
+/// The textures graphs allocate from, kept from frame to frame.
+pub struct GraphResources<D: Device> {
+    pool: Vec<PooledTexture<D>>,
+    histories: Vec<History<D>>,
+    frame: u64,
+    /// The events the main and the async compute queue signal, once a graph has used the async
+    /// compute queue.
+    events: Option<(D::Event, D::Event)>,
+    /// The last value either event was signaled with.
+    event_value: u64,
+    /// The value the main queue signaled at the end of the last frame which used the async
+    /// compute queue.
+    frame_end_value: Option<u64>,
+    reporting: bool,
+    report: Option<GraphReport>,
+}
+
+impl<D: Device> GraphResources<D> {
+    pub fn new() -> GraphResources<D> {
+        GraphResources {
+            pool: Vec::new(),
+            histories: Vec::new(),
+            frame: 0,
+            events: None,
+            event_value: 0,
+            frame_end_value: None,
+            reporting: false,
+            report: None,
+        }
+    }
+
+    /// Whether executing graphs keeps a `GraphReport` of the execution. Off by default, as it
+    /// copies the names of every pass and texture each frame.
+    pub fn set_reporting(&mut self, enabled: bool) {
+        self.reporting = enabled;
+        if !enabled {
+            self.report = None;
+        }
+    }
+
+    /// The report of the last graph executed while reporting was on.
+    pub fn report(&self) -> Option<&GraphReport> {
+        self.report.as_ref()
+    }
+
+    /// Forget the contents of the history `name` in every view, so that `has_history` is
+    /// `false` for it next frame. For camera cuts, teleports and the like, where last frame's
+    /// result is of no use.
+    pub fn invalidate_history(&mut self, name: &str) {
+        for history in self.histories.iter_mut().filter(|history| history.name.as_slice() == name) {
+            history.written = None;
+        }
+    }
+
+    /// Drop the history `name` and its textures, for when the effect using it is turned off.
+    pub fn remove_history(&mut self, name: &str) {
+        self.histories.retain(|history| history.name.as_slice() != name);
+    }
+}
+
+enum Source<'a, D: Device + 'a> {
+    Transient(GraphTextureDescriptor),
+    Imported(&'a D::Texture),
+    HistoryCurrent(uint),
+    HistoryPrevious(uint),
+}
+
+struct GraphTexture<'a, D: Device + 'a> {
+    name: String,
+    source: Source<'a, D>,
+}
+
+struct DeclaredHistory {
+    name: String,
+    descriptor: GraphTextureDescriptor,
+    current: TextureId,
+}
+
+struct Pass<'a, D: Device> {
+    name: String,
+    kind: PassKind,
+    queue: QueueHint,
+    reads: Vec<TextureId>,
+    writes: Vec<TextureId>,
+    execute: Box<FnMut(&mut D::CommandBuffer, &PassResources<D>) + 'a>,
+}
+
+/// The textures of a graph, as the passes see them.
+pub struct PassResources<'r, D: Device + 'r> {
+    textures: Vec<Option<&'r D::Texture>>,
+    /// For each texture, whether its contents are meaningful before the frame writes it.
+    valid: Vec<bool>,
+    view_index: uint,
+    view: GraphView,
+}
+
+impl<'r, D: Device> PassResources<'r, D> {
+    /// The texture given to `id` this frame.
+    ///
+    /// Panics if `id` was not used by any pass that runs.
+    pub fn texture(&self, id: TextureId) -> &'r D::Texture {
+        let TextureId(index) = id;
+        self.textures[index].expect("texture not used by any pass that runs")
+    }
+
+    /// Whether `history.previous` holds last frame's result. It does not the first frame a
+    /// history is written in, after it was resized or invalidated, or after a frame in which it
+    /// was not written.
+    pub fn has_history(&self, history: &HistoryTexture) -> bool {
+        let TextureId(index) = history.previous;
+        self.valid[index]
+    }
+
+    /// Which of the views given to `execute_views` the passes are encoded for, to look up the
+    /// parameters of the view (its camera, say) with. Always `0` for `execute`.
+    pub fn view_index(&self) -> uint {
+        self.view_index
+    }
+
+    /// The view the passes are encoded for.
+    pub fn view(&self) -> &GraphView {
+        &self.view
+    }
+}
+
+/// The passes of one frame. See the module documentation.
+pub struct RenderGraph<'a, D: Device + 'a> {
+    resources: &'a mut GraphResources<D>,
+    output_size: (uint, uint),
+    textures: Vec<GraphTexture<'a, D>>,
+    histories: Vec<DeclaredHistory>,
+    passes: Vec<Pass<'a, D>>,
+    outputs: Vec<TextureId>,
+}
+
+impl<'a, D: Device> RenderGraph<'a, D> {
+    /// Start the graph of a new frame, whose relative textures are sized after `output_size`
+    /// (unless it is executed with `execute_views`).
+    pub fn new(resources: &'a mut GraphResources<D>, output_size: (uint, uint)) -> RenderGraph<'a, D> {
+        resources.frame += 1;
+        RenderGraph {
+            resources: resources,
+            output_size: output_size,
+            textures: Vec::new(),
+            histories: Vec::new(),
+            passes: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    fn add_texture(&mut self, name: &str, source: Source<'a, D>) -> TextureId {
+        self.textures.push(GraphTexture { name: name.to_string(), source: source });
+        TextureId(self.textures.len() - 1)
+    }
+
+    /// Declare a texture which lives for this frame only.
+    pub fn create_texture(&mut self, name: &str, descriptor: GraphTextureDescriptor) -> TextureId {
+        self.add_texture(name, Source::Transient(descriptor))
+    }
+
+    /// Declare a texture from outside of the graph, such as the window's. Passes writing it are
+    /// never culled.
+    pub fn import_texture(&mut self, name: &str, texture: &'a D::Texture) -> TextureId {
+        self.add_texture(name, Source::Imported(texture))
+    }
+
+    /// Declare the history `name`, creating it the first frame it is declared in.
+    ///
+    /// Passes writing `current` are never culled, as the next frame may read it. Changing the
+    /// descriptor (or the output size of a relative one) recreates both textures, so the next
+    /// frame has no history.
+    pub fn history(&mut self, name: &str, descriptor: GraphTextureDescriptor) -> HistoryTexture {
+        let index = self.histories.len();
+        let current = self.add_texture(name, Source::HistoryCurrent(index));
+        let previous = self.add_texture(name, Source::HistoryPrevious(index));
+        self.histories.push(DeclaredHistory { name: name.to_string(), descriptor: descriptor, current: current.clone() });
+        HistoryTexture { current: current, previous: previous }
+    }
+
+    /// Keep the passes writing `id`, even though no pass reads it, for a texture whose contents
+    /// are used outside of the graph.
+    pub fn mark_output(&mut self, id: TextureId) {
+        self.outputs.push(id);
+    }
+
+    /// Add a pass which reads the textures `reads` and writes `writes`, encoded by `execute`
+    /// into the graph's command buffer.
+    ///
+    /// A texture a pass both loads and writes (an attachment it draws over, say) is in both.
+    /// Passes are culled unless they write an imported texture, the current texture of a
+    /// history, an output, or a texture read by a pass that is kept.
+    pub fn add_pass<F>(&mut self, name: &str, kind: PassKind, reads: &[TextureId], writes: &[TextureId],
+                       execute: F) -> PassId
+            where F: FnMut(&mut D::CommandBuffer, &PassResources<D>) + 'a {
+        self.passes.push(Pass {
+            name: name.to_string(),
+            kind: kind,
+            queue: QueueHint::Main,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            execute: box execute,
+        });
+        PassId(self.passes.len() - 1)
+    }
+
+    /// Ask for `pass` to run on the queue `hint` names. Passes start out on `QueueHint::Main`.
+    ///
+    /// Async compute pays off for passes which keep different units of the GPU busy than the
+    /// passes around them, such as light culling or a particle simulation next to shadow map
+    /// rendering.
+    pub fn set_queue_hint(&mut self, pass: PassId, hint: QueueHint) {
+        let PassId(index) = pass;
+        self.passes[index].queue = hint;
+    }
+
+    /// Cull, allocate and encode the passes into `commands`, each in a debug group of its name.
+    ///
+    /// Every pass runs on the main queue, whatever its hint.
+    pub fn execute(self, device: &mut D, commands: &mut D::CommandBuffer) -> Result<(), GraphError<D>> {
+        let view = self.full_view();
+        self.run(device, commands, None, &[view])
+    }
+
+    /// `execute`, with the compute passes hinted `QueueHint::AsyncCompute` encoded into
+    /// `async_commands` instead, which should be of another queue than `commands`.
+    ///
+    /// The graph signals and waits on events wherever the passes of one queue depend on those of
+    /// the other, so the two may be committed in any order. Textures used on the async compute
+    /// queue are not shared with any other texture of the frame, and the main queue waits for the
+    /// async compute queue's passes at the end of the frame, so nothing of a frame overlaps with
+    /// the next.
+    pub fn execute_with_async_compute(self, device: &mut D, commands: &mut D::CommandBuffer,
+                                      async_commands: &mut D::CommandBuffer) -> Result<(), GraphError<D>> {
+        let view = self.full_view();
+        self.run(device, commands, Some(async_commands), &[view])
+    }
+
+    /// `execute`, once for each of `views`, one after the other: for split screen, or for the
+    /// extra views of portals and planar reflections.
+    ///
+    /// The passes see which view they are encoded for in their `PassResources`, and render into
+    /// its region of the imported textures. The views share the transient textures, so a frame
+    /// of several views takes no more pooled textures than one of the largest of them; every
+    /// view has its own history textures.
+    pub fn execute_views(self, device: &mut D, commands: &mut D::CommandBuffer, views: &[GraphView]) -> Result<(), GraphError<D>> {
+        self.run(device, commands, None, views)
+    }
+
+    fn full_view(&self) -> GraphView {
+        let (width, height) = self.output_size;
+        GraphView { region: ScissorRect { x: 0, y: 0, width: width, height: height } }
+    }
+
+    fn run(self, device: &mut D, commands: &mut D::CommandBuffer, async_commands: Option<&mut D::CommandBuffer>,
+           views: &[GraphView]) -> Result<(), GraphError<D>> {
+        enter_span!("RenderGraph::execute", passes = self.passes.len());
+        let texture_count = self.textures.len();
+
+        // every read of a texture with no contents yet must follow a write of it
+        let mut written = Vec::from_elem(texture_count, false);
+        for pass in self.passes.iter() {
+            for &TextureId(index) in pass.reads.iter() {
+                let fresh = match self.textures[index].source {
+                    Source::Transient(_) | Source::HistoryCurrent(_) => true,
+                    Source::Imported(_) | Source::HistoryPrevious(_) => false,
+                };
+                if fresh && !written[index] {
+                    return Err(GraphError::ReadBeforeWrite {
+                        pass: pass.name.clone(),
+                        texture: self.textures[index].name.clone(),
+                    });
+                }
+            }
+            for &TextureId(index) in pass.writes.iter() {
+                written[index] = true;
+            }
+        }
+
+        // walk back from the textures used outside of the graph, keeping the passes which
+        // contribute to them
+        let mut needed = Vec::from_fn(texture_count, |index| match self.textures[index].source {
+            Source::Imported(_) | Source::HistoryCurrent(_) => true,
+            Source::Transient(_) | Source::HistoryPrevious(_) => false,
+        });
+        for &TextureId(index) in self.outputs.iter() {
+            needed[index] = true;
+        }
+        let mut kept = Vec::from_elem(self.passes.len(), false);
+        for (i, pass) in self.passes.iter().enumerate().rev() {
+            if pass.writes.iter().any(|&TextureId(index)| needed[index]) {
+                kept[i] = true;
+                for &TextureId(index) in pass.reads.iter() {
+                    needed[index] = true;
+                }
+            }
+        }
+
+        // how the kept passes use each texture, and the first and last of them to
+        let mut render_target = Vec::from_elem(texture_count, false);
+        let mut shader_write = Vec::from_elem(texture_count, false);
+        let mut first_use: Vec<Option<uint>> = Vec::from_elem(texture_count, None);
+        let mut last_use = Vec::from_elem(texture_count, 0u);
+        for (i, pass) in self.passes.iter().enumerate().filter(|&(i, _)| kept[i]) {
+            for &TextureId(index) in pass.writes.iter() {
+                match pass.kind {
+                    PassKind::Render => render_target[index] = true,
+                    PassKind::Compute => shader_write[index] = true,
+                    PassKind::Blit => {}
+                }
+            }
+            for &TextureId(index) in pass.reads.iter().chain(pass.writes.iter()) {
+                if first_use[index].is_none() {
+                    first_use[index] = Some(i);
+                }
+                last_use[index] = i;
+            }
+        }
+
+        // which passes go to the async compute queue, and the last pass of the other queue each
+        // pass must wait for: the last writer of what it reads, and the last writer and readers
+        // since of what it writes
+        let on_async = Vec::from_fn(self.passes.len(), |i| {
+            let pass = &self.passes[i];
+            kept[i] && async_commands.is_some() && pass.kind == PassKind::Compute && pass.queue == QueueHint::AsyncCompute
+        });
+        let mut async_used = Vec::from_elem(texture_count, false);
+        let mut wait_for: Vec<Option<uint>> = Vec::from_elem(self.passes.len(), None);
+        let mut signal_after = Vec::from_elem(self.passes.len(), false);
+        let mut last_writer: Vec<Option<uint>> = Vec::from_elem(texture_count, None);
+        let mut readers: Vec<Vec<uint>> = Vec::from_fn(texture_count, |_| Vec::new());
+        let mut dependencies_of: Vec<Vec<uint>> = Vec::from_fn(self.passes.len(), |_| Vec::new());
+        for (i, pass) in self.passes.iter().enumerate().filter(|&(i, _)| kept[i]) {
+            let mut dependencies = Vec::new();
+            for &TextureId(index) in pass.reads.iter() {
+                dependencies.extend(last_writer[index].into_iter());
+            }
+            for &TextureId(index) in pass.writes.iter() {
+                dependencies.extend(last_writer[index].into_iter());
+                dependencies.push_all(readers[index].as_slice());
+            }
+            dependencies.sort();
+            dependencies.dedup();
+            wait_for[i] = dependencies.iter().map(|&j| j).filter(|&j| on_async[j] != on_async[i]).max();
+            dependencies_of[i] = dependencies;
+            if let Some(j) = wait_for[i] {
+                signal_after[j] = true;
+            }
+            for &TextureId(index) in pass.reads.iter() {
+                readers[index].push(i);
+                async_used[index] = async_used[index] || on_async[i];
+            }
+            for &TextureId(index) in pass.writes.iter() {
+                last_writer[index] = Some(i);
+                readers[index].clear();
+                async_used[index] = async_used[index] || on_async[i];
+            }
+        }
+        // the main queue waits for all of the async compute queue's work at the end of the frame
+        let last_async = range(0, self.passes.len()).rev().find(|&i| on_async[i]);
+        if let Some(i) = last_async {
+            signal_after[i] = true;
+        }
+
+        let frame = self.resources.frame;
+        let RenderGraph { resources, textures, histories: declared, passes, .. } = self;
+        let mut passes = passes;
+        let mut async_commands = async_commands;
+        let mut async_started = false;
+        let mut in_use = Vec::from_elem(resources.pool.len(), false);
+        // the pooled textures used this frame, and those used on the async compute queue
+        let mut used = Vec::from_elem(resources.pool.len(), false);
+        let mut async_slot = Vec::from_elem(resources.pool.len(), false);
+        // the pooled texture of every transient texture, in each view, for the report
+        let mut assignments = Vec::new();
+
+        for (view_index, view) in views.iter().enumerate() {
+            let output_size = (view.region.width, view.region.height);
+
+            // recreate the histories of the view which are new or changed, and pick which of
+            // their textures is current this frame
+            let mut history_slots = Vec::with_capacity(declared.len());
+            let mut valid = Vec::from_elem(texture_count, true);
+            for declaration in declared.iter() {
+                let TextureId(current) = declaration.current;
+                let previous = current + 1;
+                // the two textures swap roles, so both must support both uses
+                let key = texture_key(&declaration.descriptor, output_size, render_target[current] || render_target[previous],
+                                      shader_write[current] || shader_write[previous]);
+                let position = resources.histories.iter().position(|history| {
+                    history.name == declaration.name && history.view == view_index
+                });
+                let position = match position {
+                    Some(position) if resources.histories[position].key == key => position,
+                    _ => {
+                        let first = match key.create(device) {
+                            Ok(texture) => texture,
+                            Err(e) => return Err(GraphError::Texture(e)),
+                        };
+                        let second = match key.create(device) {
+                            Ok(texture) => texture,
+                            Err(e) => return Err(GraphError::Texture(e)),
+                        };
+                        let history = History {
+                            name: declaration.name.clone(),
+                            view: view_index,
+                            key: key,
+                            textures: vec![first, second],
+                            latest: 0,
+                            written: None,
+                        };
+                        match position {
+                            Some(position) => {
+                                resources.histories[position] = history;
+                                position
+                            }
+                            None => {
+                                resources.histories.push(history);
+                                resources.histories.len() - 1
+                            }
+                        }
+                    }
+                };
+                let history = &resources.histories[position];
+                valid[previous] = history.written == Some(frame - 1);
+                history_slots.push((position, 1 - history.latest));
+            }
+
+            // give every transient texture a pooled one, free from the pass after its last use
+            // on, and from the next view on for every texture of the async compute queue, as
+            // the queues' passes overlap. Those never take over a texture used by another
+            // queue earlier in the frame either.
+            let mut assigned: Vec<Option<uint>> = Vec::from_elem(texture_count, None);
+            for slot in range(0, in_use.len()) {
+                in_use[slot] = false;
+            }
+            for i in range(0, passes.len()) {
+                for index in range(0, texture_count) {
+                    let descriptor = match textures[index].source {
+                        Source::Transient(ref descriptor) if first_use[index] == Some(i) => descriptor,
+                        _ => continue,
+                    };
+                    let key = texture_key(descriptor, output_size, render_target[index], shader_write[index]);
+                    let free = resources.pool.iter().enumerate().position(|(slot, pooled)| {
+                        !in_use[slot] && !async_slot[slot] && !(async_used[index] && used[slot]) && pooled.key == key
+                    });
+                    let slot = match free {
+                        Some(slot) => slot,
+                        None => {
+                            let texture = match key.create(device) {
+                                Ok(texture) => texture,
+                                Err(e) => return Err(GraphError::Texture(e)),
+                            };
+                            resources.pool.push(PooledTexture { key: key, texture: texture, last_used: frame });
+                            in_use.push(false);
+                            used.push(false);
+                            async_slot.push(false);
+                            resources.pool.len() - 1
+                        }
+                    };
+                    in_use[slot] = true;
+                    used[slot] = true;
+                    async_slot[slot] = async_used[index];
+                    resources.pool[slot].last_used = frame;
+                    assigned[index] = Some(slot);
+                }
+                for index in range(0, texture_count) {
+                    if let Some(slot) = assigned[index] {
+                        if first_use[index].is_some() && last_use[index] == i && !async_used[index] {
+                            in_use[slot] = false;
+                        }
+                    }
+                }
+            }
+            if resources.reporting {
+                assignments.push(assigned.clone());
+            }
+
+            let mut signal_values = Vec::from_elem(passes.len(), 0u64);
+            if last_async.is_some() {
+                if resources.events.is_none() {
+                    resources.events = Some((device.create_event(), device.create_event()));
+                }
+                for i in range(0, passes.len()).filter(|&i| signal_after[i]) {
+                    resources.event_value += 1;
+                    signal_values[i] = resources.event_value;
+                }
+            }
+            let previous_frame_end = resources.frame_end_value;
+
+            {
+                let resources = &*resources;
+                let table = PassResources {
+                    textures: Vec::from_fn(texture_count, |index| match textures[index].source {
+                        Source::Transient(_) => assigned[index].map(|slot| &resources.pool[slot].texture),
+                        Source::Imported(texture) => Some(texture),
+                        Source::HistoryCurrent(history) => {
+                            let (position, current) = history_slots[history];
+                            Some(&resources.histories[position].textures[current])
+                        }
+                        Source::HistoryPrevious(history) => {
+                            let (position, current) = history_slots[history];
+                            Some(&resources.histories[position].textures[1 - current])
+                        }
+                    }),
+                    valid: valid,
+                    view_index: view_index,
+                    view: view.clone(),
+                };
+                for (i, pass) in passes.iter_mut().enumerate().filter(|&(i, _)| kept[i]) {
+                    let commands: &mut D::CommandBuffer = match async_commands {
+                        Some(ref mut async_commands) if on_async[i] => &mut **async_commands,
+                        _ => &mut *commands,
+                    };
+                    let events = resources.events.as_ref();
+                    // the event signaled by the async compute queue, or the main one
+                    let event = |of_async: bool| {
+                        let &(ref main_event, ref async_event) = events.unwrap();
+                        if of_async { async_event } else { main_event }
+                    };
+                    if on_async[i] && !async_started {
+                        // the previous frame's main queue may still use the textures of this one
+                        if let Some(value) = previous_frame_end {
+                            commands.encode_wait_for_event(event(false), value);
+                        }
+                        async_started = true;
+                    }
+                    if let Some(j) = wait_for[i] {
+                        commands.encode_wait_for_event(event(on_async[j]), signal_values[j]);
+                    }
+                    commands.push_debug_group(pass.name.as_slice());
+                    (pass.execute)(commands, &table);
+                    commands.pop_debug_group();
+                    if signal_after[i] {
+                        commands.encode_signal_event(event(on_async[i]), signal_values[i]);
+                    }
+                }
+                match last_async {
+                    // the main queue waits for all of the async compute queue's work at the end of
+                    // the frame, and only then signals the next frame's async compute passes
+                    Some(i) if view_index + 1 == views.len() => {
+                        enter_span!("RenderGraph::end_frame", value = signal_values[i]);
+                        let &(ref main_event, ref async_event) = resources.events.as_ref().unwrap();
+                        commands.encode_wait_for_event(async_event, signal_values[i]);
+                        commands.encode_signal_event(main_event, resources.event_value + 1);
+                    }
+                    _ => {}
+                }
+            }
+
+            // the current texture of every history written is the next frame's previous
+            for (declaration, &(position, current)) in declared.iter().zip(history_slots.iter()) {
+                let TextureId(index) = declaration.current;
+                if first_use[index].is_some() && written[index] {
+                    let history = &mut resources.histories[position];
+                    history.latest = current;
+                    history.written = Some(frame);
+                }
+            }
+        }
+
+        if last_async.is_some() {
+            resources.event_value += 1;
+            resources.frame_end_value = Some(resources.event_value);
+        }
+        if resources.reporting {
+            resources.report = Some(GraphReport {
+                frame: frame,
+                passes: passes.iter().enumerate().map(|(i, pass)| PassReport {
+                    name: pass.name.clone(),
+                    kind: pass.kind.clone(),
+                    culled: !kept[i],
+                    queue: if on_async[i] { QueueHint::AsyncCompute } else { QueueHint::Main },
+                    reads: pass.reads.iter().map(|&TextureId(index)| index).collect(),
+                    writes: pass.writes.iter().map(|&TextureId(index)| index).collect(),
+                    dependencies: dependencies_of[i].clone(),
+                    waits_for: wait_for[i],
+                }).collect(),
+                textures: textures.iter().enumerate().map(|(index, texture)| TextureReport {
+                    name: texture.name.clone(),
+                    kind: match texture.source {
+                        Source::Transient(_) => TextureKind::Transient,
+                        Source::Imported(_) => TextureKind::Imported,
+                        Source::HistoryCurrent(_) => TextureKind::HistoryCurrent,
+                        Source::HistoryPrevious(_) => TextureKind::HistoryPrevious,
+                    },
+                    first_use: first_use[index],
+                    last_use: first_use[index].map(|_| last_use[index]),
+                    pooled: assignments.iter().map(|assigned| assigned[index]).collect(),
+                }).collect(),
+            });
+        }
+        resources.pool.retain(|pooled| pooled.last_used + 1 >= frame);
+        Ok(())
+    }
+}
+
+/// Where a texture of a `GraphReport` comes from.
+#[deriving(Clone, PartialEq, Show)]
+pub enum TextureKind {
+    Transient,
+    Imported,
+    HistoryCurrent,
+    HistoryPrevious,
+}
+
+/// A pass, as a graph executed it.
+#[deriving(Clone, Show)]
+pub struct PassReport {
+    pub name: String,
+    pub kind: PassKind,
+    /// Whether the pass was culled, as nothing kept uses what it writes.
+    pub culled: bool,
+    /// The queue the pass ran on.
+    pub queue: QueueHint,
+    /// The indices in `GraphReport::textures` of the textures the pass reads and writes.
+    pub reads: Vec<uint>,
+    pub writes: Vec<uint>,
+    /// The indices of the kept passes this one must run after, as it reads what they write or
+    /// writes what they use.
+    pub dependencies: Vec<uint>,
+    /// The pass of the other queue this pass waits for with an event, if any.
+    pub waits_for: Option<uint>,
+}
+
+/// A texture, as a graph executed it.
+#[deriving(Clone, Show)]
+pub struct TextureReport {
+    pub name: String,
+    pub kind: TextureKind,
+    /// The indices of the first and last kept passes which use the texture, if any does.
+    pub first_use: Option<uint>,
+    pub last_use: Option<uint>,
+    /// For each view, the pooled texture a transient texture was given. Transient textures
+    /// given the same one share it, one after the other.
+    pub pooled: Vec<Option<uint>>,
+}
+
+/// What an execution of a graph decided. See `GraphResources::set_reporting`.
+#[deriving(Clone, Show)]
+pub struct GraphReport {
+    /// The frame, counting the graphs of the `GraphResources` from `1`.
+    pub frame: u64,
+    pub passes: Vec<PassReport>,
+    pub textures: Vec<TextureReport>,
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(format!("\\u{:04x}", c as u32).as_slice()),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn json_indices(indices: &[uint]) -> String {
+    let indices: Vec<String> = indices.iter().map(|index| index.to_string()).collect();
+    format!("[{}]", indices.connect(","))
+}
+
+fn json_option(value: Option<uint>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "null".to_string(),
+    }
+}
+
+impl GraphReport {
+    /// The report as a Graphviz digraph: passes are boxes in the order they were declared,
+    /// textures ellipses labeled with the passes they live between and their pooled textures.
+    /// Culled passes are dashed, async compute passes blue, and event waits red dashed edges.
+    pub fn to_graphviz(&self) -> String {
+        let mut dot = format!("digraph \"frame {}\" {{\n    rankdir=LR;\n", self.frame);
+        for (i, pass) in self.passes.iter().enumerate() {
+            let style = if pass.culled { ",style=dashed" } else { "" };
+            let color = if pass.queue == QueueHint::AsyncCompute { ",color=blue" } else { "" };
+            dot.push_str(format!("    p{} [shape=box,label=\"{}: {}\\n{}\"{}{}];\n",
+                                 i, i, escape(pass.name.as_slice()), pass.kind, style, color).as_slice());
+        }
+        for (index, texture) in self.textures.iter().enumerate() {
+            let mut label = format!("{}\\n{}", escape(texture.name.as_slice()), texture.kind);
+            if let (Some(first), Some(last)) = (texture.first_use, texture.last_use) {
+                label.push_str(format!("\\npasses {}..{}", first, last).as_slice());
+            }
+            let pooled: Vec<String> = texture.pooled.iter().filter_map(|slot| slot.map(|slot| format!("#{}", slot))).collect();
+            if !pooled.is_empty() {
+                label.push_str(format!("\\npooled {}", pooled.connect(" ")).as_slice());
+            }
+            dot.push_str(format!("    t{} [shape=ellipse,label=\"{}\"];\n", index, label).as_slice());
+        }
+        for (i, pass) in self.passes.iter().enumerate() {
+            for index in pass.reads.iter() {
+                dot.push_str(format!("    t{} -> p{};\n", index, i).as_slice());
+            }
+            for index in pass.writes.iter() {
+                dot.push_str(format!("    p{} -> t{};\n", i, index).as_slice());
+            }
+            if let Some(j) = pass.waits_for {
+                dot.push_str(format!("    p{} -> p{} [style=dashed,color=red,label=\"wait\"];\n", j, i).as_slice());
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The report as JSON: an object with the `frame`, and `passes` and `textures` arrays of
+    /// objects with the fields of `PassReport` and `TextureReport`, indices and all.
+    pub fn to_json(&self) -> String {
+        let passes: Vec<String> = self.passes.iter().map(|pass| {
+            format!("{{\"name\":\"{}\",\"kind\":\"{}\",\"culled\":{},\"queue\":\"{}\",\"reads\":{},\"writes\":{},\
+                     \"dependencies\":{},\"waits_for\":{}}}",
+                    escape(pass.name.as_slice()), pass.kind, pass.culled, pass.queue,
+                    json_indices(pass.reads.as_slice()), json_indices(pass.writes.as_slice()),
+                    json_indices(pass.dependencies.as_slice()), json_option(pass.waits_for))
+        }).collect();
+        let textures: Vec<String> = self.textures.iter().map(|texture| {
+            let pooled: Vec<String> = texture.pooled.iter().map(|&slot| json_option(slot)).collect();
+            format!("{{\"name\":\"{}\",\"kind\":\"{}\",\"first_use\":{},\"last_use\":{},\"pooled\":[{}]}}",
+                    escape(texture.name.as_slice()), texture.kind, json_option(texture.first_use),
+                    json_option(texture.last_use), pooled.connect(","))
+        }).collect();
+        format!("{{\"frame\":{},\"passes\":[{}],\"textures\":[{}]}}", self.frame, passes.connect(","), textures.connect(","))
+    }
+}
@@ -0,0 +1,530 @@
+//! Rendering at a fraction of the output resolution.
+//!
+//! `RenderScale` owns the internal color and depth targets of a renderer, sized for the output
+//! (usually the window) times a render scale. Passes render into the top left `render_size()` of
+//! them, with the `viewport()` it gives, and `upscale` then scales that region over the output:
+//!
+//! ```ignore
+//! scale.set_scale(0.75);
+//! let mut encoder = commands.render_command_encoder(RenderPassDescriptor {
+//!     color_attachments: vec![RenderPassColorAttachment { texture: scale.color(), .. }],
+//!     ..
+//! });
+//! encoder.set_viewport(scale.viewport());
+//! // draw the scene
+//! encoder.end_encoding();
+//! try!(scale.upscale(&mut commands, &mut blitter, &window_texture));
+//! ```
+//!
+//! The targets are allocated once at `max_scale`, so changing the scale from frame to frame (to
+//! keep the GPU time of a frame within budget, for example with `GpuProfiler` timings) never
+//! reallocates anything. Only `resize` does, when the output itself changes size.
+//!
+//! # Upscalers
+//!
+//! What does the scaling is an `Upscaler`: `Blitter`'s bilinear filtering, `SpatialUpscaler`'s
+//! edge adaptive filtering and sharpening, or a temporal upscaler, which accumulates jittered
+//! frames into more detail than any one of them has. Temporal upscalers read more than color, and
+//! say so through `Upscaler::inputs`: depth, motion vectors (see `with_motion_vectors`), and a
+//! camera jitter which `RenderScale` cycles through, a phase per frame, once `begin_frame` is
+//! called every frame:
+//!
+//! ```ignore
+//! scale.begin_frame(&upscaler);
+//! let (x, y) = scale.clip_jitter();
+//! // offset the projection by (x, y) in clip space, as projection[2][0] += x, projection[2][1] += y
+//! // render color, depth and motion vectors into the targets
+//! try!(scale.upscale(&mut commands, &mut upscaler, &window_texture));
+//! ```
+//!
+//! Vendor temporal upscalers (MetalFX's temporal scaler, FSR 2, DLSS, XeSS) are integrated by
+//! backends, as `Upscaler`s of their own device type: the backend's command buffer is its
+//! native one, so its upscaler encodes the vendor's pass into it directly. Renderers written
+//! against `Upscaler` take whichever the backend offers, and fall back to `SpatialUpscaler`.
+
+use {Device, PixelFormat, PrimitiveType, RenderCommandEncoder, RenderPipelineDescriptor, Texture, TextureDescriptor};
+use {CommandBuffer, ClearColor, LoadAction, RenderPassColorAttachment, RenderPassDescriptor, StoreAction};
+use {TextureType, TextureUsage, Viewport};
+use util::as_bytes;
+use util::blit::{BlitError, BlitFilter, Blitter};
+
+/// What an `Upscaler` scales up.
+pub struct UpscaleSource<'a, D: Device> {
+    pub color: &'a D::Texture,
+    pub depth: Option<&'a D::Texture>,
+    /// The motion of every rendered pixel, if the renderer writes it, by the convention of
+    /// `util::motion_vectors` (see `RenderScale::with_motion_vectors`).
+    pub motion_vectors: Option<&'a D::Texture>,
+    /// The part of `color` and `depth` rendered this frame, in pixels from the top left.
+    pub render_size: (uint, uint),
+    /// The full size of `color` and `depth`.
+    pub target_size: (uint, uint),
+    /// The output size, which `destination` is.
+    pub output_size: (uint, uint),
+    /// The offset the camera was jittered by this frame, in pixels of the rendered region (see
+    /// `RenderScale::jitter`), `(0, 0)` for upscalers without jitter.
+    pub jitter: (f32, f32),
+    /// Whether earlier frames must not be accumulated into this one: the first frame, after a
+    /// camera cut, or after the targets were resized.
+    pub reset: bool,
+    /// The number of frames begun before this one.
+    pub frame: u64,
+}
+
+/// What an upscaler reads besides color, for `RenderScale` to provide.
+#[deriving(Clone, Default, Show)]
+pub struct UpscalerInputs {
+    pub depth: bool,
+    pub motion_vectors: bool,
+}
+
+/// Something that scales the rendered region up to the output: `Blitter`'s bilinear filtering,
+/// or a better upscaler, such as one which sharpens, or which is temporal and reads depth. See
+/// the module documentation.
+pub trait Upscaler<D: Device> {
+    type Error;
+
+    /// What the upscaler reads besides color. Nothing, by default.
+    fn inputs(&self) -> UpscalerInputs {
+        Default::default()
+    }
+
+    /// The number of jitter phases the renderer should cycle through when rendering at
+    /// `render_size` for `output_size`. `1`, for no jitter, by default; temporal upscalers
+    /// want more the more they scale up.
+    fn jitter_phase_count(&self, render_size: (uint, uint), output_size: (uint, uint)) -> uint {
+        1
+    }
+
+    /// Encode into `commands` whatever replaces all of `destination` with the rendered region
+    /// of `source`.
+    fn upscale(&mut self, commands: &mut D::CommandBuffer, source: &UpscaleSource<D>,
+               destination: &D::Texture) -> Result<(), Self::Error>;
+}
+
+/// The jitter of phase `index` of `phase_count`, in pixels within `[-0.5, 0.5)`: the Halton
+/// sequence of bases 2 and 3, which covers a pixel evenly for any number of phases. `(0, 0)` for
+/// a single phase.
+pub fn jitter_offset(index: uint, phase_count: uint) -> (f32, f32) {
+    if phase_count <= 1 {
+        return (0.0, 0.0);
+    }
+    let halton = |index: uint, base: uint| {
+        let (mut result, mut fraction, mut i) = (0.0f32, 1.0f32, index);
+        while i > 0 {
+            fraction /= base as f32;
+            result += fraction * (i % base) as f32;
+            i /= base;
+        }
+        result
+    };
+    // the sequence starts at 1, as 0 is (0, 0) in both bases
+    let i = index % phase_count + 1;
+    (halton(i, 2) - 0.5, halton(i, 3) - 0.5)
+}
+
+/// The jitter phase count temporal upscalers commonly use: eight per pixel of the output each
+/// rendered pixel covers, so that every output pixel is hit by a few phases.
+pub fn temporal_jitter_phase_count(render_size: (uint, uint), output_size: (uint, uint)) -> uint {
+    let ratio = output_size.0 as f32 / render_size.0 as f32;
+    std::cmp::max(8, (8.0 * ratio * ratio).ceil() as uint)
+}
+
+impl<D: Device> Upscaler<D> for Blitter<D> {
+    type Error = BlitError;
+
+    fn upscale(&mut self, commands: &mut D::CommandBuffer, source: &UpscaleSource<D>,
+               destination: &D::Texture) -> Result<(), BlitError> {
+        let region = [source.render_size.0 as f32 / source.target_size.0 as f32,
+                      source.render_size.1 as f32 / source.target_size.1 as f32];
+        self.blit_region(commands, source.color, region, destination, BlitFilter::Linear, true)
+    }
+}
+
+#[repr(C)]
+struct SpatialUpscaleUniforms {
+    /// The rendered region, and the size of the texture it is in, in pixels.
+    render_size: [f32, ..2],
+    output_size: [f32, ..2],
+    sharpness: f32,
+    _padding: [f32, ..3],
+}
+
+/// An upscaler which takes nothing but color: edge adaptive filtering of the rendered region,
+/// which keeps edges sharp where bilinear filtering blurs them, then contrast adaptive
+/// sharpening, both in a single render pass over the output. Much like FSR 1, and about as
+/// good: what detail was not rendered stays missing, but edges look as if it were not.
+///
+/// The sharpening expects colors in `[0, 1]`, so upscales after tonemapping.
+pub struct SpatialUpscaler<D: Device> {
+    pipeline: D::RenderPipeline,
+    sharpness: f32,
+}
+
+impl<D: Device> SpatialUpscaler<D> {
+    /// `program` must be a shader program created from `SPATIAL_SOURCE`. The sharpness starts
+    /// out at `0.5`.
+    pub fn new(device: &mut D, program: &D::ShaderProgram) -> Result<SpatialUpscaler<D>, D::RenderPipelineCreationError> {
+        let pipeline = try!(device.create_render_pipeline(program, RenderPipelineDescriptor {
+            label: Some("spatial upscale".to_string()),
+            robustness: None,
+            vertex_function: "spatial_upscale_vertex".to_string(),
+            fragment_function: Some("spatial_upscale_fragment".to_string()),
+            vertex_descriptor: None,
+            blending: Vec::new(),
+            sample_count: 1,
+            attachment_formats: None,
+        }));
+        Ok(SpatialUpscaler { pipeline: pipeline, sharpness: 0.5 })
+    }
+
+    /// Sharpen by `sharpness`, from `0` (not at all) to `1`, clamped.
+    pub fn set_sharpness(&mut self, sharpness: f32) {
+        self.sharpness = if sharpness < 0.0 { 0.0 } else if sharpness > 1.0 { 1.0 } else { sharpness };
+    }
+
+    pub fn sharpness(&self) -> f32 {
+        self.sharpness
+    }
+}
+
+impl<D: Device> Upscaler<D> for SpatialUpscaler<D> {
+    type Error = BlitError;
+
+    fn upscale(&mut self, commands: &mut D::CommandBuffer, source: &UpscaleSource<D>,
+               destination: &D::Texture) -> Result<(), BlitError> {
+        enter_span!("SpatialUpscaler::upscale");
+        for format in [source.color.pixel_format(), destination.pixel_format()].iter() {
+            if format.is_integer() {
+                return Err(BlitError::IntegerFormat);
+            } else if format.has_depth() {
+                return Err(BlitError::DepthFormat);
+            }
+        }
+        let uniforms = SpatialUpscaleUniforms {
+            render_size: [source.render_size.0 as f32, source.render_size.1 as f32],
+            output_size: [source.output_size.0 as f32, source.output_size.1 as f32],
+            sharpness: self.sharpness,
+            _padding: [0.0, ..3],
+        };
+        let mut encoder = commands.render_command_encoder(RenderPassDescriptor {
+            color_attachments: vec![RenderPassColorAttachment {
+                texture: destination, mipmap_level: 0, slice: 0,
+                // every pixel is drawn over
+                load_action: LoadAction::DontCare, store_action: StoreAction::Store,
+                clear_color: ClearColor::Float { red: 0.0, green: 0.0, blue: 0.0, alpha: 0.0 },
+            }],
+            depth_attachment: None,
+            stencil_attachment: None,
+            sample_positions: None,
+            visibility_result_buffer: None,
+        });
+        encoder.set_render_pipeline(&self.pipeline);
+        encoder.set_fragment_texture(source.color, 0);
+        encoder.set_fragment_bytes(as_bytes(&uniforms), 0);
+        encoder.draw(PrimitiveType::Triangle, 0, 3, 1, 0);
+        encoder.end_encoding();
+        Ok(())
+    }
+}
+
+/// The internal targets of a renderer, and the scale it renders at into them.
+pub struct RenderScale<D: Device> {
+    color: D::Texture,
+    depth: Option<D::Texture>,
+    motion_vectors: Option<D::Texture>,
+    color_format: PixelFormat,
+    depth_format: Option<PixelFormat>,
+    motion_vector_format: Option<PixelFormat>,
+    output_size: (uint, uint),
+    target_size: (uint, uint),
+    scale: f32,
+    max_scale: f32,
+    /// The number of frames begun, and the jitter of the current one.
+    frame: u64,
+    jitter: (f32, f32),
+    /// Whether the next upscale must not accumulate earlier frames.
+    reset: bool,
+}
+
+fn scaled(size: (uint, uint), scale: f32) -> (uint, uint) {
+    let (width, height) = size;
+    (std::cmp::max(1, (width as f32 * scale).round() as uint),
+     std::cmp::max(1, (height as f32 * scale).round() as uint))
+}
+
+impl<D: Device> RenderScale<D> {
+    /// Create targets for an output of `output_size` pixels, rendered at up to `max_scale` times
+    /// its size (above `1.0` for supersampling). The scale starts out at `1.0`, or `max_scale`
+    /// if that is less.
+    ///
+    /// The color target is a render target and `shader_read`, in `color_format`, which must be a
+    /// float or normalized format for `Blitter` to upscale it. The depth target, if there is one,
+    /// is too, for upscalers which read it.
+    pub fn new(device: &mut D, output_size: (uint, uint), color_format: PixelFormat,
+               depth_format: Option<PixelFormat>, max_scale: f32) -> Result<RenderScale<D>, D::TextureCreationError> {
+        assert!(max_scale > 0.0, "render scale {} is not positive", max_scale);
+        let (color, depth, target_size) = try!(create_targets(device, output_size, &color_format,
+                                                               &depth_format, max_scale));
+        Ok(RenderScale {
+            color: color,
+            depth: depth,
+            motion_vectors: None,
+            color_format: color_format,
+            depth_format: depth_format,
+            motion_vector_format: None,
+            output_size: output_size,
+            target_size: target_size,
+            scale: if max_scale < 1.0 { max_scale } else { 1.0 },
+            max_scale: max_scale,
+            frame: 0,
+            jitter: (0.0, 0.0),
+            reset: true,
+        })
+    }
+
+    /// Add a motion vector target in `format` (`motion_vectors::MOTION_VECTOR_FORMAT`, for
+    /// motion by the crate's convention), the same size as the others, for upscalers which read
+    /// motion vectors. Replaces any earlier one.
+    pub fn with_motion_vectors(&mut self, device: &mut D, format: PixelFormat) -> Result<(), D::TextureCreationError> {
+        self.motion_vectors = Some(try!(device.create_texture(target_descriptor(self.target_size, format.clone()))));
+        self.motion_vector_format = Some(format);
+        Ok(())
+    }
+
+    /// Recreate the targets for an output of `output_size` pixels, keeping the scale.
+    ///
+    /// Does nothing if that is the size already.
+    pub fn resize(&mut self, device: &mut D, output_size: (uint, uint)) -> Result<(), D::TextureCreationError> {
+        if output_size == self.output_size {
+            return Ok(());
+        }
+        let (color, depth, target_size) = try!(create_targets(device, output_size, &self.color_format,
+                                                               &self.depth_format, self.max_scale));
+        if let Some(format) = self.motion_vector_format.clone() {
+            self.motion_vectors = Some(try!(device.create_texture(target_descriptor(target_size, format))));
+        }
+        self.color = color;
+        self.depth = depth;
+        self.output_size = output_size;
+        self.target_size = target_size;
+        self.reset = true;
+        Ok(())
+    }
+
+    /// Start a frame rendered for `upscaler`: pick the jitter of the frame's phase, of as many as
+    /// the upscaler wants at the current scale.
+    pub fn begin_frame<U: Upscaler<D>>(&mut self, upscaler: &U) {
+        let phases = upscaler.jitter_phase_count(self.render_size(), self.output_size);
+        self.jitter = jitter_offset(self.frame as uint, phases);
+        self.frame += 1;
+    }
+
+    /// The jitter of the current frame, in pixels of the rendered region, which the camera should
+    /// be offset by.
+    pub fn jitter(&self) -> (f32, f32) {
+        self.jitter
+    }
+
+    /// `jitter`, in clip space: the offset to add to the projected position before the divide
+    /// by `w`, or in a projection matrix, to the third column's `x` and `y`.
+    pub fn clip_jitter(&self) -> (f32, f32) {
+        let (width, height) = self.render_size();
+        (2.0 * self.jitter.0 / width as f32, -2.0 * self.jitter.1 / height as f32)
+    }
+
+    /// Have the next upscale start over, without earlier frames: after a camera cut, or when
+    /// switching upscalers.
+    pub fn reset_history(&mut self) {
+        self.reset = true;
+    }
+
+    /// Render at `scale` times the output size from now on, clamped to `max_scale`.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = if scale > self.max_scale { self.max_scale } else { scale };
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn max_scale(&self) -> f32 {
+        self.max_scale
+    }
+
+    /// The size of the output, in pixels.
+    pub fn output_size(&self) -> (uint, uint) {
+        self.output_size
+    }
+
+    /// The size of the region rendered at the current scale, in pixels. Never larger than the
+    /// targets, and never empty.
+    pub fn render_size(&self) -> (uint, uint) {
+        let (width, height) = scaled(self.output_size, self.scale);
+        (std::cmp::min(width, self.target_size.0), std::cmp::min(height, self.target_size.1))
+    }
+
+    /// The viewport of the rendered region, for every pass rendering into the targets.
+    pub fn viewport(&self) -> Viewport {
+        let (width, height) = self.render_size();
+        Viewport { x: 0.0, y: 0.0, width: width as f64, height: height as f64, znear: 0.0, zfar: 1.0 }
+    }
+
+    pub fn color(&self) -> &D::Texture {
+        &self.color
+    }
+
+    pub fn depth(&self) -> Option<&D::Texture> {
+        self.depth.as_ref()
+    }
+
+    pub fn motion_vectors(&self) -> Option<&D::Texture> {
+        self.motion_vectors.as_ref()
+    }
+
+    /// Encode into `commands` the scaling of the rendered region over all of `destination`,
+    /// which must be the output size.
+    ///
+    /// Panics if the upscaler reads depth or motion vectors, and there is no target for them.
+    pub fn upscale<U: Upscaler<D>>(&mut self, commands: &mut D::CommandBuffer, upscaler: &mut U,
+                                   destination: &D::Texture) -> Result<(), U::Error> {
+        enter_span!("RenderScale::upscale", scale = self.scale);
+        let inputs = upscaler.inputs();
+        if inputs.depth && self.depth.is_none() {
+            panic!("the upscaler reads depth, and the render scale has no depth target");
+        }
+        if inputs.motion_vectors && self.motion_vectors.is_none() {
+            panic!("the upscaler reads motion vectors, and the render scale has no motion vector target");
+        }
+        let reset = self.reset;
+        self.reset = false;
+        let source = UpscaleSource {
+            color: &self.color,
+            depth: self.depth.as_ref(),
+            motion_vectors: self.motion_vectors.as_ref(),
+            render_size: self.render_size(),
+            target_size: self.target_size,
+            output_size: self.output_size,
+            jitter: self.jitter,
+            reset: reset,
+            frame: self.frame,
+        };
+        upscaler.upscale(commands, &source, destination)
+    }
+}
+
+fn create_targets<D: Device>(device: &mut D, output_size: (uint, uint), color_format: &PixelFormat,
+                             depth_format: &Option<PixelFormat>, max_scale: f32)
+                             -> Result<(D::Texture, Option<D::Texture>, (uint, uint)), D::TextureCreationError> {
+    let size = scaled(output_size, max_scale);
+    let color = try!(device.create_texture(target_descriptor(size, color_format.clone())));
+    let depth = match *depth_format {
+        Some(ref format) => Some(try!(device.create_texture(target_descriptor(size, format.clone())))),
+        None => None,
+    };
+    Ok((color, depth, size))
+}
+
+fn target_descriptor(size: (uint, uint), pixel_format: PixelFormat) -> TextureDescriptor {
+    TextureDescriptor {
+        texture_type: TextureType::Type2D,
+        pixel_format: pixel_format,
+        width: size.0,
+        height: size.1,
+        depth: 1,
+        mipmap_level_count: 1,
+        array_length: 1,
+        sample_count: 1,
+        usage: TextureUsage { shader_read: true, shader_write: false, render_target: true },
+    }
+}
+
+/// The metal shading language source of `SpatialUpscaler`.
+pub const SPATIAL_SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct SpatialUpscaleUniforms {
+    float2 render_size;
+    float2 output_size;
+    float sharpness;
+    float padding[3];
+};
+
+struct UpscaleVertex {
+    float4 position [[position]];
+    float2 uv;
+};
+
+vertex UpscaleVertex spatial_upscale_vertex(uint vid [[vertex_id]])
+{
+    float2 uv = float2((vid << 1) & 2, vid & 2);
+    UpscaleVertex out;
+    out.position = float4(uv.x * 2.0f - 1.0f, 1.0f - uv.y * 2.0f, 0.0f, 1.0f);
+    out.uv = uv;
+    return out;
+}
+
+static float lanczos2(float x) {
+    x = abs(x);
+    if (x < 1e-4f) {
+        return 1.0f;
+    }
+    if (x >= 2.0f) {
+        return 0.0f;
+    }
+    float px = M_PI_F * x;
+    return 2.0f * sin(px) * sin(px * 0.5f) / (px * px);
+}
+
+// the rendered region, upsampled at p (in pixels of the region) with a 4x4 lanczos filter,
+// clamped to the nearest 2x2 texels so that edges do not ring
+static float4 upsample(texture2d<float> source, float2 p, float2 render_size) {
+    float2 base = floor(p - 0.5f);
+    float2 f = p - 0.5f - base;
+    int2 last = int2(render_size) - 1;
+    float4 sum = 0.0f;
+    float weights = 0.0f;
+    float4 lo = INFINITY;
+    float4 hi = -INFINITY;
+    for (int y = -1; y <= 2; y++) {
+        for (int x = -1; x <= 2; x++) {
+            uint2 texel = uint2(clamp(int2(base) + int2(x, y), int2(0), last));
+            float4 c = source.read(texel);
+            float w = lanczos2(float(x) - f.x) * lanczos2(float(y) - f.y);
+            sum += c * w;
+            weights += w;
+            if (x >= 0 && x <= 1 && y >= 0 && y <= 1) {
+                lo = min(lo, c);
+                hi = max(hi, c);
+            }
+        }
+    }
+    return clamp(sum / weights, lo, hi);
+}
+
+fragment float4 spatial_upscale_fragment(UpscaleVertex in [[stage_in]],
+                                         texture2d<float> source [[texture(0)]],
+                                         constant SpatialUpscaleUniforms &u [[buffer(0)]])
+{
+    float2 p = in.uv * u.render_size;
+    float4 c = upsample(source, p, u.render_size);
+    if (u.sharpness <= 0.0f) {
+        return c;
+    }
+
+    // contrast adaptive sharpening against the neighbors one output pixel away, less where the
+    // neighborhood already has contrast, so that edges do not get halos
+    float2 step = u.render_size / u.output_size;
+    float3 n = upsample(source, p - float2(0.0f, step.y), u.render_size).rgb;
+    float3 s = upsample(source, p + float2(0.0f, step.y), u.render_size).rgb;
+    float3 w = upsample(source, p - float2(step.x, 0.0f), u.render_size).rgb;
+    float3 e = upsample(source, p + float2(step.x, 0.0f), u.render_size).rgb;
+    float3 lo = saturate(min(c.rgb, min(min(n, s), min(w, e))));
+    float3 hi = saturate(max(c.rgb, max(max(n, s), max(w, e))));
+    float3 amount = sqrt(saturate(min(lo, 1.0f - hi) / max(hi, 1e-5f)));
+    float3 lobe = -amount * mix(0.125f, 0.2f, u.sharpness);
+    c.rgb = (c.rgb + (n + s + w + e) * lobe) / (1.0f + 4.0f * lobe);
+    return c;
+}
+"#;
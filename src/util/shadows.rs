@@ -0,0 +1,351 @@
+//! Cascaded shadow maps for a directional light.
+//!
+//! The camera's view distance is split into up to `MAX_CASCADES` ranges, each covered by a shadow
+//! map slice of its own, so nearby shadows get as many texels as distant ones. `cascade_splits`
+//! places the ranges; `CascadedShadows` fits a light projection around each, keeps the slices in
+//! a depth array texture, and adds a render graph pass per cascade for the caller to draw the
+//! shadow casters in:
+//!
+//! ```ignore
+//! shadows.update(&camera, [0.3, -1.0, 0.2]);
+//! let cascades = shadows.add_passes(&mut graph, |encoder, cascade, view_projection| {
+//!     encoder.set_render_pipeline(&depth_only);
+//!     encoder.set_vertex_bytes(as_bytes(view_projection), 1);
+//!     scene.draw_casters(encoder, cascade);
+//! });
+//! graph.add_pass("lighting", PassKind::Render, &[cascades, gbuffer], &[hdr], |commands, textures| {
+//!     ..
+//!     shadows.bind(&mut encoder, textures.texture(cascades), 3, 2);
+//! });
+//! ```
+//!
+//! The lighting shader includes `SHADER_HEADER`, which declares the comparison sampler the maps
+//! are read with, and calls `shadow_factor` for the fraction of light reaching a point, filtered
+//! with a 3x3 PCF kernel (or `shadow_pcf_poisson`, for softer edges).
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use {Device, CommandBuffer, LoadAction, PixelFormat, RenderCommandEncoder, RenderPassDepthAttachment};
+use {RenderPassDescriptor, StoreAction, TextureDescriptor, TextureType, TextureUsage, Viewport};
+use util::as_bytes;
+use util::render_graph::{PassKind, RenderGraph, TextureId};
+
+/// The most cascades `CascadedShadows` supports.
+pub const MAX_CASCADES: uint = 4;
+
+/// The far distances of `count` cascades covering `near` to `far`, blending logarithmic splits
+/// (`lambda` of `1.0`, which keep the texel density even) with uniform ones (`0.0`, which keep
+/// distant cascades from getting too thin). `0.5` to `0.8` usually works well.
+pub fn cascade_splits(near: f32, far: f32, count: uint, lambda: f32) -> Vec<f32> {
+    Vec::from_fn(count, |i| {
+        let fraction = (i + 1) as f32 / count as f32;
+        let logarithmic = near * (far / near).powf(fraction);
+        let uniform = near + (far - near) * fraction;
+        lambda * logarithmic + (1.0 - lambda) * uniform
+    })
+}
+
+/// The camera whose view is shadowed.
+pub struct ShadowCamera {
+    /// The column major view to world transform of the camera, which looks down its -z axis.
+    pub inverse_view: [f32, ..16],
+    /// The vertical field of view, in radians.
+    pub fov_y: f32,
+    /// The width of the view divided by its height.
+    pub aspect: f32,
+    pub near: f32,
+    /// How far shadows reach, which can be closer than the camera's far plane.
+    pub far: f32,
+}
+
+#[repr(C)]
+struct ShadowUniforms {
+    view_projections: [[f32, ..16], ..MAX_CASCADES],
+    splits: [f32, ..MAX_CASCADES],
+    cascade_count: u32,
+    texel_size: f32,
+    padding: [u32, ..2],
+}
+
+fn sub(a: [f32, ..3], b: [f32, ..3]) -> [f32, ..3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32, ..3], b: [f32, ..3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32, ..3], b: [f32, ..3]) -> [f32, ..3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(a: [f32, ..3]) -> [f32, ..3] {
+    let length = dot(a, a).sqrt();
+    [a[0] / length, a[1] / length, a[2] / length]
+}
+
+fn transform_point(m: &[f32, ..16], p: [f32, ..3]) -> [f32, ..3] {
+    [m[0] * p[0] + m[4] * p[1] + m[8] * p[2] + m[12],
+     m[1] * p[0] + m[5] * p[1] + m[9] * p[2] + m[13],
+     m[2] * p[0] + m[6] * p[1] + m[10] * p[2] + m[14]]
+}
+
+fn multiply(a: &[f32, ..16], b: &[f32, ..16]) -> [f32, ..16] {
+    let mut m = [0.0f32, ..16];
+    for column in range(0, 4u) {
+        for row in range(0, 4u) {
+            m[column * 4 + row] = range(0, 4u).fold(0.0, |sum, k| sum + a[k * 4 + row] * b[column * 4 + k]);
+        }
+    }
+    m
+}
+
+/// A right handed view from `eye` towards `target`, looking down -z.
+fn look_at(eye: [f32, ..3], target: [f32, ..3], up: [f32, ..3]) -> [f32, ..16] {
+    let f = normalize(sub(target, eye));
+    let s = normalize(cross(f, up));
+    let u = cross(s, f);
+    [s[0], u[0], -f[0], 0.0,
+     s[1], u[1], -f[1], 0.0,
+     s[2], u[2], -f[2], 0.0,
+     -dot(s, eye), -dot(u, eye), dot(f, eye), 1.0]
+}
+
+/// An orthographic projection of a view looking down -z, to depths of `0` to `1`.
+fn orthographic(half_size: f32, near: f32, far: f32) -> [f32, ..16] {
+    [1.0 / half_size, 0.0, 0.0, 0.0,
+     0.0, 1.0 / half_size, 0.0, 0.0,
+     0.0, 0.0, -1.0 / (far - near), 0.0,
+     0.0, 0.0, -near / (far - near), 1.0]
+}
+
+/// The shadow maps of a directional light. See the module documentation.
+pub struct CascadedShadows<D: Device> {
+    /// One depth slice per cascade.
+    texture: D::Texture,
+    resolution: uint,
+    cascade_count: uint,
+    lambda: f32,
+    view_projections: [[f32, ..16], ..MAX_CASCADES],
+    splits: [f32, ..MAX_CASCADES],
+}
+
+impl<D: Device> CascadedShadows<D> {
+    /// Create `cascade_count` shadow maps of `resolution` by `resolution` texels, split as
+    /// `cascade_splits` does with `lambda`.
+    ///
+    /// `cascade_count` must be from `1` to `MAX_CASCADES`.
+    pub fn new(device: &mut D, resolution: uint, cascade_count: uint, lambda: f32) -> Result<CascadedShadows<D>, D::TextureCreationError> {
+        assert!(cascade_count >= 1 && cascade_count <= MAX_CASCADES);
+        let texture = try!(device.create_texture(TextureDescriptor {
+            texture_type: TextureType::Type2DArray,
+            pixel_format: PixelFormat::Depth32Float,
+            width: resolution,
+            height: resolution,
+            depth: 1,
+            mipmap_level_count: 1,
+            array_length: cascade_count,
+            sample_count: 1,
+            usage: TextureUsage { shader_read: true, shader_write: false, render_target: true },
+        }));
+        Ok(CascadedShadows {
+            texture: texture,
+            resolution: resolution,
+            cascade_count: cascade_count,
+            lambda: lambda,
+            view_projections: [[0.0, ..16], ..MAX_CASCADES],
+            splits: [0.0, ..MAX_CASCADES],
+        })
+    }
+
+    /// The depth array texture, with a slice per cascade.
+    pub fn texture(&self) -> &D::Texture {
+        &self.texture
+    }
+
+    pub fn cascade_count(&self) -> uint {
+        self.cascade_count
+    }
+
+    /// The column major world to shadow map transform of `cascade`, as of the last `update`.
+    pub fn view_projection(&self, cascade: uint) -> &[f32, ..16] {
+        &self.view_projections[cascade]
+    }
+
+    /// The far distances of the cascades, as of the last `update`.
+    pub fn splits(&self) -> &[f32] {
+        self.splits.slice_to(self.cascade_count)
+    }
+
+    /// Fit the cascades to `camera`, for a light shining along `light_direction` (which need
+    /// not be normalized).
+    ///
+    /// Each cascade bounds its slice of the view frustum with a sphere, so its projection does
+    /// not change size as the camera turns, and moves in whole texels, so shadow edges do not
+    /// shimmer as the camera moves.
+    pub fn update(&mut self, camera: &ShadowCamera, light_direction: [f32, ..3]) {
+        let splits = cascade_splits(camera.near, camera.far, self.cascade_count, self.lambda);
+        let direction = normalize(light_direction);
+        let up = if direction[1].abs() > 0.99 { [0.0, 0.0, 1.0] } else { [0.0, 1.0, 0.0] };
+        let tan_y = (camera.fov_y * 0.5).tan();
+        let tan_x = tan_y * camera.aspect;
+
+        let mut near = camera.near;
+        for (cascade, &far) in splits.iter().enumerate() {
+            // the corners of the slice, in world space
+            let mut corners = Vec::with_capacity(8);
+            for &distance in [near, far].iter() {
+                for &(x, y) in [(-1.0f32, -1.0f32), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter() {
+                    corners.push(transform_point(&camera.inverse_view,
+                                                 [x * tan_x * distance, y * tan_y * distance, -distance]));
+                }
+            }
+            let center = corners.iter().fold([0.0f32, ..3], |sum, c| [sum[0] + c[0] / 8.0, sum[1] + c[1] / 8.0, sum[2] + c[2] / 8.0]);
+            let radius = corners.iter().fold(0.0f32, |radius, &c| radius.max(dot(sub(c, center), sub(c, center)).sqrt()));
+            // round the radius up so the projection's size only changes with the split distances
+            let radius = (radius * 16.0).ceil() / 16.0;
+
+            let eye = sub(center, [direction[0] * radius, direction[1] * radius, direction[2] * radius]);
+            let view = look_at(eye, center, up);
+            let mut view_projection = multiply(&orthographic(radius, 0.0, radius * 2.0), &view);
+
+            // snap the projection of the world origin to a texel
+            let half_resolution = self.resolution as f32 * 0.5;
+            let origin = transform_point(&view_projection, [0.0, 0.0, 0.0]);
+            let (x, y) = (origin[0] * half_resolution, origin[1] * half_resolution);
+            view_projection[12] += (x.round() - x) / half_resolution;
+            view_projection[13] += (y.round() - y) / half_resolution;
+
+            self.view_projections[cascade] = view_projection;
+            self.splits[cascade] = far;
+            near = far;
+        }
+    }
+
+    /// Add a pass per cascade to `graph`, each clearing its slice of the shadow maps and calling
+    /// `draw` with the render encoder, the cascade and its view projection to draw the shadow
+    /// casters. Returns the shadow maps, for the passes which sample them to read.
+    pub fn add_passes<'a, F>(&'a self, graph: &mut RenderGraph<'a, D>, draw: F) -> TextureId
+            where F: FnMut(&mut D::RenderCommandEncoder, uint, &[f32, ..16]) + 'a {
+        let maps = graph.import_texture("shadows.cascades", &self.texture);
+        let draw = Rc::new(RefCell::new(draw));
+        for cascade in range(0, self.cascade_count) {
+            let draw = draw.clone();
+            let name = format!("shadows.cascade{}", cascade);
+            graph.add_pass(name.as_slice(), PassKind::Render, &[], &[maps.clone()], move |commands, _| {
+                self.encode_pass(commands, cascade, |encoder, view_projection| {
+                    (&mut *draw.borrow_mut())(encoder, cascade, view_projection)
+                });
+            });
+        }
+        maps
+    }
+
+    /// Encode the pass of `cascade` into `commands`, as `add_passes` does, for callers without
+    /// a render graph: clear its slice of the shadow maps, and call `draw` with the render encoder
+    /// and the cascade's view projection.
+    pub fn encode_pass<F>(&self, commands: &mut D::CommandBuffer, cascade: uint, draw: F)
+            where F: FnOnce(&mut D::RenderCommandEncoder, &[f32, ..16]) {
+        let mut encoder = commands.render_command_encoder(RenderPassDescriptor {
+            color_attachments: Vec::new(),
+            depth_attachment: Some(RenderPassDepthAttachment {
+                texture: &self.texture,
+                mipmap_level: 0,
+                slice: cascade,
+                load_action: LoadAction::Clear,
+                store_action: StoreAction::Store,
+                clear_depth: 1.0,
+            }),
+            stencil_attachment: None,
+            sample_positions: None,
+            visibility_result_buffer: None,
+        });
+        encoder.set_viewport(Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.resolution as f64,
+            height: self.resolution as f64,
+            znear: 0.0,
+            zfar: 1.0,
+        });
+        draw(&mut encoder, &self.view_projections[cascade]);
+        encoder.end_encoding();
+    }
+
+    /// Bind the shadow maps for a fragment shader: `maps` (the texture `add_passes` returned, or
+    /// `texture()`) at fragment texture `texture_index`, and the `ShadowUniforms` of
+    /// `SHADER_HEADER` at fragment buffer `buffer_index`.
+    pub fn bind<E: RenderCommandEncoder<D>>(&self, encoder: &mut E, maps: &D::Texture, texture_index: uint, buffer_index: uint) {
+        let uniforms = ShadowUniforms {
+            view_projections: self.view_projections,
+            splits: self.splits,
+            cascade_count: self.cascade_count as u32,
+            texel_size: 1.0 / self.resolution as f32,
+            padding: [0, ..2],
+        };
+        encoder.set_fragment_texture(maps, texture_index);
+        encoder.set_fragment_bytes(as_bytes(&uniforms), buffer_index);
+    }
+}
+
+/// Metal shading language declarations for sampling the shadow maps, to paste in front of the
+/// shaders which do.
+pub const SHADER_HEADER: &'static str = r#"
+// the comparison sampler shadow maps are read with: the fraction of the (bilinearly filtered)
+// texels whose depth is at least the compared depth
+constexpr sampler shadow_sampler(coord::normalized, filter::linear, address::clamp_to_edge,
+                                 compare_func::less_equal);
+
+struct ShadowUniforms {
+    float4x4 view_projections[4];
+    float4 splits;
+    uint cascade_count;
+    float texel_size;
+};
+
+// 3x3 percentage closer filtering around `coord`, the shadow map position and depth of a point
+static float shadow_pcf3x3(depth2d_array<float> maps, float3 coord, uint cascade, float texel_size) {
+    float sum = 0.0;
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            sum += maps.sample_compare(shadow_sampler, coord.xy + float2(x, y) * texel_size, cascade, coord.z);
+        }
+    }
+    return sum / 9.0;
+}
+
+constant float2 shadow_poisson_disk[12] = {
+    float2(-0.326, -0.406), float2(-0.840, -0.074), float2(-0.696, 0.457), float2(-0.203, 0.621),
+    float2(0.962, -0.195), float2(0.473, -0.480), float2(0.519, 0.767), float2(0.185, -0.893),
+    float2(0.507, 0.064), float2(0.896, 0.412), float2(-0.322, -0.933), float2(-0.792, -0.598),
+};
+
+// percentage closer filtering over a disk of `radius` texels around `coord`
+static float shadow_pcf_poisson(depth2d_array<float> maps, float3 coord, uint cascade, float texel_size, float radius) {
+    float sum = 0.0;
+    for (uint i = 0; i < 12; i++) {
+        sum += maps.sample_compare(shadow_sampler, coord.xy + shadow_poisson_disk[i] * radius * texel_size, cascade, coord.z);
+    }
+    return sum / 12.0;
+}
+
+// the cascade covering a point `view_depth` in front of the camera
+static uint shadow_cascade(constant ShadowUniforms &u, float view_depth) {
+    uint cascade = 0;
+    while (cascade + 1 < u.cascade_count && view_depth > u.splits[cascade]) {
+        cascade++;
+    }
+    return cascade;
+}
+
+// the fraction of light reaching `world_position`, `view_depth` in front of the camera, with its
+// shadow map depth biased towards the light by `bias`
+static float shadow_factor(depth2d_array<float> maps, constant ShadowUniforms &u, float3 world_position,
+                           float view_depth, float bias) {
+    uint cascade = shadow_cascade(u, view_depth);
+    float4 position = u.view_projections[cascade] * float4(world_position, 1.0);
+    float3 coord = float3(position.xy * float2(0.5, -0.5) + 0.5, position.z - bias);
+    return shadow_pcf3x3(maps, coord, cascade, u.texel_size);
+}
+"#;
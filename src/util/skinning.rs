@@ -0,0 +1,300 @@
+//! Skinning and morph targets, on the GPU.
+//!
+//! A `SkinnedMesh` holds a mesh's bind pose, its morph target deltas, and an output buffer the
+//! deformed vertices are written to. Each frame, `Skinner::skin` uploads the mesh's joint palette
+//! and encodes a compute pass which applies the weighted morph targets and then the joints to
+//! every vertex:
+//!
+//! ```ignore
+//! skinner.begin_frame();
+//! for character in characters.iter() {
+//!     try!(skinner.skin(&mut device, &mut compute, &character.mesh, character.palette.as_slice(),
+//!                       character.expression_weights.as_slice()));
+//! }
+//! compute.end_encoding();
+//! // draw each character with `character.mesh.output()` as its vertex buffer
+//! ```
+//!
+//! Deforming once in compute, rather than in every vertex function, means a mesh drawn in several
+//! passes (depth prepass, shadows, the main pass) is only deformed once, and every pass draws it
+//! like a static mesh.
+//!
+//! Normals and tangents are transformed by the same matrices as positions, so joint transforms
+//! must not contain non-uniform scale.
+
+use {Device, BufferSlice, ComputeCommandEncoder, Size};
+use util::{as_bytes, slice_as_bytes};
+use util::transient_buffer::{TransientBuffer, TransientUsage};
+
+/// The number of threads in each skinning threadgroup.
+const THREADS_PER_THREADGROUP: uint = 64;
+
+/// The maximum number of morph targets with a non-zero weight in one `skin`.
+pub const MAX_ACTIVE_MORPH_TARGETS: uint = 32;
+
+/// A vertex of a mesh's bind pose.
+#[repr(C)]
+pub struct SkinVertex {
+    /// The position in `xyz`. `w` is ignored.
+    pub position: [f32, ..4],
+    /// The normal in `xyz`. `w` is ignored.
+    pub normal: [f32, ..4],
+    /// The tangent in `xyz`, and the handedness of the bitangent (`1` or `-1`) in `w`.
+    pub tangent: [f32, ..4],
+    /// The indices of the (up to) four joints influencing the vertex, into the joint palette.
+    pub joints: [u32, ..4],
+    /// The weight of each joint. These should add up to `1`.
+    pub weights: [f32, ..4],
+}
+
+/// How a morph target moves one vertex.
+#[repr(C)]
+pub struct MorphDelta {
+    /// Added to the position, scaled by the target's weight. `w` is ignored.
+    pub position: [f32, ..4],
+    /// Added to the normal, scaled by the target's weight, before renormalizing. `w` is ignored.
+    pub normal: [f32, ..4],
+}
+
+/// A deformed vertex, as laid out in `SkinnedMesh::output`.
+#[repr(C)]
+pub struct SkinnedVertex {
+    /// The position in `xyz`, and `1` in `w`.
+    pub position: [f32, ..4],
+    /// The normal in `xyz`, and `0` in `w`.
+    pub normal: [f32, ..4],
+    /// As for `SkinVertex::tangent`.
+    pub tangent: [f32, ..4],
+}
+
+#[repr(C)]
+struct SkinUniforms {
+    vertex_count: u32,
+    joint_count: u32,
+    target_count: u32,
+    _padding: u32,
+    /// The target index of each active morph target in `x`, and its weight (as bits) in `y`.
+    targets: [[u32, ..2], ..MAX_ACTIVE_MORPH_TARGETS],
+}
+
+/// The buffers of one deformable mesh.
+pub struct SkinnedMesh<D: Device> {
+    bind_pose: D::Buffer,
+    /// Every target's delta for every vertex, target by target.
+    deltas: Option<D::Buffer>,
+    output: D::Buffer,
+    vertex_count: uint,
+    target_count: uint,
+}
+
+impl<D: Device> SkinnedMesh<D> {
+    /// Create the buffers for a mesh with the given bind pose.
+    ///
+    /// `morph_targets` holds a delta for every vertex of each target; it may be empty.
+    pub fn new(device: &mut D, vertices: &[SkinVertex], morph_targets: &[&[MorphDelta]]) -> Result<SkinnedMesh<D>, D::BufferCreationError> {
+        let vertex_count = vertices.len();
+        let bind_pose = try!(device.create_buffer(vertex_count * std::mem::size_of::<SkinVertex>(), None));
+        device.write_buffer(&bind_pose, 0, slice_as_bytes(vertices));
+
+        let deltas = if morph_targets.is_empty() {
+            None
+        } else {
+            let target_size = vertex_count * std::mem::size_of::<MorphDelta>();
+            let deltas = try!(device.create_buffer(morph_targets.len() * target_size, None));
+            for (i, target) in morph_targets.iter().enumerate() {
+                assert_eq!(target.len(), vertex_count);
+                device.write_buffer(&deltas, i * target_size, slice_as_bytes(*target));
+            }
+            Some(deltas)
+        };
+
+        let output = try!(device.create_buffer(vertex_count * std::mem::size_of::<SkinnedVertex>(), None));
+        Ok(SkinnedMesh {
+            bind_pose: bind_pose,
+            deltas: deltas,
+            output: output,
+            vertex_count: vertex_count,
+            target_count: morph_targets.len(),
+        })
+    }
+
+    /// The deformed vertices, as `SkinnedVertex`es, written by `Skinner::skin`. Their contents are
+    /// undefined until the first `skin`.
+    pub fn output(&self) -> &D::Buffer {
+        &self.output
+    }
+
+    pub fn vertex_count(&self) -> uint {
+        self.vertex_count
+    }
+
+    pub fn morph_target_count(&self) -> uint {
+        self.target_count
+    }
+}
+
+/// Why a `skin` could not be encoded.
+#[deriving(Show)]
+pub enum SkinningError {
+    /// This frame's palette buffer has no room left for the joints.
+    PaletteFull,
+    /// More than `MAX_ACTIVE_MORPH_TARGETS` morph weights are non-zero.
+    TooManyMorphTargets,
+}
+
+/// Encodes the deformation of `SkinnedMesh`es.
+pub struct Skinner<D: Device> {
+    pipeline: D::ComputePipeline,
+    palettes: TransientBuffer<D>,
+    /// Bound in place of the palette and deltas when a mesh has none, as something must be.
+    dummy: D::Buffer,
+}
+
+impl<D: Device> Skinner<D> {
+    /// Create a skinner whose joint palettes for one frame take up to `palette_size` bytes (64
+    /// bytes per joint).
+    ///
+    /// `pipeline` must be a compute pipeline created from the `skin` function of `SOURCE`. As for
+    /// `DebugDraw`, `frames_in_flight` must be at least the number of frames the CPU can get
+    /// ahead of the GPU.
+    pub fn new(device: &mut D, pipeline: D::ComputePipeline, palette_size: uint, frames_in_flight: uint) -> Result<Skinner<D>, D::BufferCreationError> {
+        let palettes = try!(TransientBuffer::new(device, palette_size, frames_in_flight));
+        let dummy = try!(device.create_buffer(std::mem::size_of::<MorphDelta>(), None));
+        Ok(Skinner {
+            pipeline: pipeline,
+            palettes: palettes,
+            dummy: dummy,
+        })
+    }
+
+    /// Start a new frame, freeing the joint palettes of the oldest frame.
+    pub fn begin_frame(&mut self) {
+        self.palettes.begin_frame();
+    }
+
+    /// Deform `mesh` into its output buffer.
+    ///
+    /// `joints` is the mesh's joint palette: for each joint, its current transform multiplied with
+    /// its inverse bind transform, column major. If it is empty, the mesh is only morphed.
+    /// `morph_weights` holds the weight of each of the mesh's morph targets; missing weights are
+    /// `0`.
+    pub fn skin<E: ComputeCommandEncoder<D>>(&mut self, device: &mut D, encoder: &mut E, mesh: &SkinnedMesh<D>,
+                                             joints: &[[f32, ..16]], morph_weights: &[f32]) -> Result<(), SkinningError> {
+        enter_span!("Skinner::skin");
+        let mut uniforms = SkinUniforms {
+            vertex_count: mesh.vertex_count as u32,
+            joint_count: joints.len() as u32,
+            target_count: 0,
+            _padding: 0,
+            targets: [[0, 0], ..MAX_ACTIVE_MORPH_TARGETS],
+        };
+        for (i, &weight) in morph_weights.iter().take(mesh.target_count).enumerate() {
+            if weight == 0.0 {
+                continue;
+            }
+            if uniforms.target_count as uint == MAX_ACTIVE_MORPH_TARGETS {
+                return Err(SkinningError::TooManyMorphTargets);
+            }
+            let bits: u32 = unsafe { std::mem::transmute(weight) };
+            uniforms.targets[uniforms.target_count as uint] = [i as u32, bits];
+            uniforms.target_count += 1;
+        }
+
+        let palette = if joints.is_empty() {
+            None
+        } else {
+            match self.palettes.allocate(device, slice_as_bytes(joints), TransientUsage::Storage) {
+                Some(offset) => Some(offset),
+                None => return Err(SkinningError::PaletteFull),
+            }
+        };
+
+        encoder.set_compute_pipeline(&self.pipeline);
+        encoder.set_buffer(BufferSlice::whole(&mesh.bind_pose), 0);
+        encoder.set_buffer(BufferSlice::whole(mesh.deltas.as_ref().unwrap_or(&self.dummy)), 1);
+        match palette {
+            Some(offset) => encoder.set_buffer(BufferSlice::new(self.palettes.buffer(), offset, std::mem::size_of_val(joints)), 2),
+            None => encoder.set_buffer(BufferSlice::whole(&self.dummy), 2),
+        }
+        encoder.set_buffer(BufferSlice::whole(&mesh.output), 3);
+        encoder.set_bytes(as_bytes(&uniforms), 4);
+        let groups = (mesh.vertex_count + THREADS_PER_THREADGROUP - 1) / THREADS_PER_THREADGROUP;
+        encoder.dispatch_threadgroups(Size { width: groups, height: 1, depth: 1 },
+                                      Size { width: THREADS_PER_THREADGROUP, height: 1, depth: 1 });
+        Ok(())
+    }
+}
+
+/// The metal shading language source of the skinning kernel.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+#define MAX_ACTIVE_MORPH_TARGETS 32
+
+struct SkinVertex {
+    float4 position;
+    float4 normal;
+    float4 tangent;
+    uint4 joints;
+    float4 weights;
+};
+
+struct MorphDelta {
+    float4 position;
+    float4 normal;
+};
+
+struct SkinnedVertex {
+    float4 position;
+    float4 normal;
+    float4 tangent;
+};
+
+struct SkinUniforms {
+    uint vertex_count;
+    uint joint_count;
+    uint target_count;
+    uint padding;
+    uint2 targets[MAX_ACTIVE_MORPH_TARGETS];
+};
+
+kernel void skin(const device SkinVertex *vertices [[buffer(0)]],
+                 const device MorphDelta *deltas [[buffer(1)]],
+                 const device float4x4 *palette [[buffer(2)]],
+                 device SkinnedVertex *output [[buffer(3)]],
+                 constant SkinUniforms &u [[buffer(4)]],
+                 uint id [[thread_position_in_grid]])
+{
+    if (id >= u.vertex_count) {
+        return;
+    }
+
+    SkinVertex v = vertices[id];
+    float3 position = v.position.xyz;
+    float3 normal = v.normal.xyz;
+    for (uint i = 0; i < u.target_count; i++) {
+        float weight = as_type<float>(u.targets[i].y);
+        MorphDelta d = deltas[u.targets[i].x * u.vertex_count + id];
+        position += d.position.xyz * weight;
+        normal += d.normal.xyz * weight;
+    }
+
+    float4x4 m = float4x4(1.0f);
+    if (u.joint_count > 0) {
+        m = palette[min(v.joints.x, u.joint_count - 1)] * v.weights.x +
+            palette[min(v.joints.y, u.joint_count - 1)] * v.weights.y +
+            palette[min(v.joints.z, u.joint_count - 1)] * v.weights.z +
+            palette[min(v.joints.w, u.joint_count - 1)] * v.weights.w;
+    }
+
+    SkinnedVertex out;
+    out.position = float4((m * float4(position, 1.0f)).xyz, 1.0f);
+    out.normal = float4(normalize((m * float4(normal, 0.0f)).xyz), 0.0f);
+    // re-orthogonalize against the morphed normal
+    float3 tangent = (m * float4(v.tangent.xyz, 0.0f)).xyz;
+    tangent = normalize(tangent - out.normal.xyz * dot(out.normal.xyz, tangent));
+    out.tangent = float4(tangent, v.tangent.w);
+    output[id] = out;
+}
+"#;
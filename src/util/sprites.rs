@@ -0,0 +1,251 @@
+//! Batched 2D sprite rendering.
+//!
+//! Each frame, sprites are pushed into a `SpriteBatch`, which `SpriteRenderer::draw` sorts and
+//! draws with as few draw calls as possible: one per run of sprites sharing a texture and scissor
+//! rectangle, after ordering by layer. Within a layer, sprites keep the order they were pushed in,
+//! so later sprites are drawn over earlier ones.
+//!
+//! ```ignore
+//! let icons = try!(renderer.texture_bind_group(&mut device, &atlas, &sampler));
+//! let mut batch = SpriteBatch::new();
+//! batch.push(Sprite { texture: &icons, rect: [10.0, 10.0, 32.0, 32.0], uv: heart_uv, color: WHITE, layer: 1, scissor: None });
+//! renderer.draw(&mut device, &mut encoder, batch, (width, height));
+//! ```
+//!
+//! Positions are in pixels, from the top left corner of the attachment.
+
+use {Device, BlendDescriptor, BufferSlice, RenderCommandEncoder, RenderPipelineDescriptor, PrimitiveType, ScissorRect};
+use {BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry};
+use {BindingCount, BindingResource, BindingType, ShaderStages};
+use {VertexAttribute, VertexBufferLayout, VertexDescriptor, VertexFormat, VertexStepFunction};
+use util::{as_bytes, slice_as_bytes};
+use util::debug_draw::Color;
+
+/// A single textured quad.
+pub struct Sprite<'a, D: Device> {
+    /// The texture (and sampler) to draw with, from `SpriteRenderer::texture_bind_group`.
+    pub texture: &'a D::BindGroup,
+    /// The quad's `x`, `y`, width and height, in pixels.
+    pub rect: [f32, ..4],
+    /// The area of the texture to draw, as normalized `u0`, `v0`, `u1`, `v1`. For a sprite in an
+    /// atlas, this is its rectangle in the atlas.
+    pub uv: [f32, ..4],
+    /// Multiplied with the texture's color.
+    pub color: Color,
+    /// Sprites in higher layers are drawn over those in lower layers.
+    pub layer: i32,
+    /// Only draw the part of the sprite within this rectangle, if `Some`.
+    pub scissor: Option<ScissorRect>,
+}
+
+/// The sprites to draw in one frame.
+pub struct SpriteBatch<'a, D: Device> {
+    sprites: Vec<Sprite<'a, D>>,
+}
+
+impl<'a, D: Device> SpriteBatch<'a, D> {
+    pub fn new() -> SpriteBatch<'a, D> {
+        SpriteBatch { sprites: Vec::new() }
+    }
+
+    pub fn push(&mut self, sprite: Sprite<'a, D>) {
+        self.sprites.push(sprite);
+    }
+
+    pub fn len(&self) -> uint {
+        self.sprites.len()
+    }
+}
+
+#[repr(C)]
+struct SpriteInstance {
+    rect: [f32, ..4],
+    uv: [f32, ..4],
+    color: Color,
+    _padding: [u32, ..3],
+}
+
+/// Draws `SpriteBatch`es.
+pub struct SpriteRenderer<D: Device> {
+    pipeline: D::RenderPipeline,
+    layout: D::BindGroupLayout,
+    /// One instance buffer per frame in flight, used in rotation.
+    buffers: Vec<D::Buffer>,
+    next_buffer: uint,
+    capacity: uint,
+}
+
+/// Why a `SpriteRenderer` could not be created.
+pub enum SpriteRendererCreationError<D: Device> {
+    Pipeline(D::RenderPipelineCreationError),
+    BindGroupLayout(D::BindGroupLayoutCreationError),
+    Buffer(D::BufferCreationError),
+}
+
+fn same_texture<D: Device>(a: &D::BindGroup, b: &D::BindGroup) -> bool {
+    a as *const D::BindGroup == b as *const D::BindGroup
+}
+
+impl<D: Device> SpriteRenderer<D> {
+    /// Create a renderer which draws up to `max_sprites` sprites per frame.
+    ///
+    /// `program` must be a shader program created from `SOURCE`. As for `DebugDraw`,
+    /// `frames_in_flight` must be at least the number of frames the CPU can get ahead of the GPU.
+    pub fn new(device: &mut D, program: &D::ShaderProgram, max_sprites: uint, frames_in_flight: uint) -> Result<SpriteRenderer<D>, SpriteRendererCreationError<D>> {
+        let instance_size = std::mem::size_of::<SpriteInstance>();
+        let fragment = ShaderStages { vertex: false, fragment: true, compute: false };
+        let layout = match device.create_bind_group_layout(BindGroupLayoutDescriptor {
+            entries: vec![
+                BindGroupLayoutEntry { binding: 0, visibility: fragment, ty: BindingType::SampledTexture,
+                                       count: BindingCount::Single, partially_bound: false, update_after_bind: false },
+                BindGroupLayoutEntry { binding: 1, visibility: fragment, ty: BindingType::Sampler,
+                                       count: BindingCount::Single, partially_bound: false, update_after_bind: false },
+            ],
+        }) {
+            Ok(layout) => layout,
+            Err(e) => return Err(SpriteRendererCreationError::BindGroupLayout(e)),
+        };
+
+        let desc = RenderPipelineDescriptor {
+            label: Some("sprites".to_string()),
+            robustness: None,
+            vertex_function: "sprite_vertex".to_string(),
+            fragment_function: Some("sprite_fragment".to_string()),
+            vertex_descriptor: Some(VertexDescriptor {
+                attributes: vec![
+                    VertexAttribute { format: VertexFormat::Float4, offset: 0, buffer_index: 0 },
+                    VertexAttribute { format: VertexFormat::Float4, offset: 16, buffer_index: 0 },
+                    VertexAttribute { format: VertexFormat::UChar4Normalized, offset: 32, buffer_index: 0 },
+                ],
+                layouts: vec![
+                    VertexBufferLayout { stride: instance_size, step_function: VertexStepFunction::PerInstance, step_rate: 1 },
+                ],
+            }),
+            blending: vec![Some(BlendDescriptor::alpha())],
+            sample_count: 1,
+            attachment_formats: None,
+        };
+        let pipeline = match device.create_render_pipeline(program, desc) {
+            Ok(pipeline) => pipeline,
+            Err(e) => return Err(SpriteRendererCreationError::Pipeline(e)),
+        };
+
+        let mut buffers = Vec::with_capacity(frames_in_flight);
+        for _ in range(0, frames_in_flight) {
+            match device.create_buffer(max_sprites * instance_size, None) {
+                Ok(buffer) => buffers.push(buffer),
+                Err(e) => return Err(SpriteRendererCreationError::Buffer(e)),
+            }
+        }
+
+        Ok(SpriteRenderer {
+            pipeline: pipeline,
+            layout: layout,
+            buffers: buffers,
+            next_buffer: 0,
+            capacity: max_sprites,
+        })
+    }
+
+    /// Create the bind group sprites use to refer to a texture and the sampler to draw it with.
+    pub fn texture_bind_group(&self, device: &mut D, texture: &D::Texture, sampler: &D::Sampler) -> Result<D::BindGroup, D::BindGroupCreationError> {
+        device.create_bind_group(BindGroupDescriptor {
+            layout: &self.layout,
+            entries: vec![
+                BindGroupEntry { binding: 0, array_element: 0, resource: BindingResource::Texture(texture) },
+                BindGroupEntry { binding: 1, array_element: 0, resource: BindingResource::Sampler(sampler) },
+            ],
+            unsized_length: 0,
+        })
+    }
+
+    /// Draw a batch of sprites onto a `viewport_size` pixel attachment.
+    ///
+    /// Sprites past the renderer's capacity are not drawn. Returns the number of draw calls
+    /// issued.
+    pub fn draw<'a, E: RenderCommandEncoder<D>>(&mut self, device: &mut D, encoder: &mut E,
+                                                mut batch: SpriteBatch<'a, D>, viewport_size: (uint, uint)) -> uint {
+        enter_span!("SpriteRenderer::draw");
+        batch.sprites.truncate(self.capacity);
+        if batch.sprites.is_empty() {
+            return 0;
+        }
+        // stable, so sprites within a layer stay in submission order
+        batch.sprites.sort_by(|a, b| a.layer.cmp(&b.layer));
+
+        let instances: Vec<SpriteInstance> = batch.sprites.iter().map(|s| SpriteInstance {
+            rect: s.rect,
+            uv: s.uv,
+            color: s.color,
+            _padding: [0, 0, 0],
+        }).collect();
+        let buffer = &self.buffers[self.next_buffer];
+        self.next_buffer = (self.next_buffer + 1) % self.buffers.len();
+        device.write_buffer(buffer, 0, slice_as_bytes(instances.as_slice()));
+
+        let (width, height) = viewport_size;
+        let full = ScissorRect { x: 0, y: 0, width: width, height: height };
+        let size = [width as f32, height as f32];
+        encoder.set_render_pipeline(&self.pipeline);
+        encoder.set_vertex_buffer(BufferSlice::whole(buffer), 0);
+        encoder.set_vertex_bytes(as_bytes(&size), 1);
+
+        let mut draws = 0;
+        let mut start = 0;
+        while start < batch.sprites.len() {
+            let first = &batch.sprites[start];
+            let mut end = start + 1;
+            while end < batch.sprites.len() &&
+                  same_texture::<D>(batch.sprites[end].texture, first.texture) &&
+                  batch.sprites[end].scissor == first.scissor {
+                end += 1;
+            }
+
+            encoder.set_bind_group(first.texture, 0);
+            encoder.set_scissor_rect(first.scissor.unwrap_or(full));
+            encoder.draw(PrimitiveType::TriangleStrip, 0, 4, end - start, start);
+            draws += 1;
+            start = end;
+        }
+        draws
+    }
+}
+
+/// The metal shading language source of the sprite shaders.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct SpriteInstance {
+    float4 rect [[attribute(0)]];
+    float4 uv [[attribute(1)]];
+    float4 color [[attribute(2)]];
+};
+
+struct SpriteFragment {
+    float4 position [[position]];
+    float2 uv;
+    float4 color;
+};
+
+vertex SpriteFragment sprite_vertex(SpriteInstance in [[stage_in]],
+                                    constant float2 &viewport_size [[buffer(1)]],
+                                    uint vid [[vertex_id]])
+{
+    float2 corner = float2(vid & 1, vid >> 1);
+    float2 pixel = in.rect.xy + corner * in.rect.zw;
+
+    SpriteFragment out;
+    out.position = float4(pixel / viewport_size * float2(2.0f, -2.0f) + float2(-1.0f, 1.0f), 0.0f, 1.0f);
+    out.uv = mix(in.uv.xy, in.uv.zw, corner);
+    out.color = in.color;
+    return out;
+}
+
+fragment float4 sprite_fragment(SpriteFragment in [[stage_in]],
+                                texture2d<float> texture [[texture(0)]],
+                                sampler s [[sampler(0)]])
+{
+    return texture.sample(s, in.uv) * in.color;
+}
+"#;
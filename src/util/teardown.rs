@@ -0,0 +1,137 @@
+//! Shutting down in the order the `Device` documentation lays out, and deferring the deletion of
+//! objects pending work still uses.
+//!
+//! Dropping a device (or a buffer, or a swapchain) while the GPU is still executing work which
+//! uses it is undefined on every backend, and what usually happens is a GPU fault at exit, an
+//! error dialog from the driver, or a hang. Nothing in the types prevents it: objects do not
+//! borrow their device. `shutdown` takes what is left of the device and drops it in the right
+//! order, waiting for the GPU first:
+//!
+//! ```ignore
+//! // at exit, once the event loop is done
+//! shutdown(device, vec![queue], vec![deletions], vec![box swapchain as Box<Any>], vec![box renderer as Box<Any>]);
+//! ```
+//!
+//! Objects replaced while frames are in flight (a resized render target, a rebuilt pipeline) must
+//! outlive those frames. A `DeletionQueue` keeps them until the frame they were replaced in has
+//! completed:
+//!
+//! ```ignore
+//! deletions.defer(old_target);
+//! // at the end of the frame
+//! commands.add_completed_handler(fence.completed_handler());
+//! deletions.end_frame(fence);
+//! // at the start of a later frame
+//! deletions.collect();
+//! ```
+
+use std::any::Any;
+
+use {Device, CommandQueue};
+use util::fence::Fence;
+
+/// Objects kept alive until the frames which may still use them have completed. See the module
+/// documentation.
+///
+/// A deletion queue must be flushed before it is dropped (`shutdown` does), as dropping pending
+/// objects is what it exists to prevent: debug builds assert that it was.
+pub struct DeletionQueue {
+    /// The objects deferred since the last `end_frame`.
+    current: Vec<Box<Any>>,
+    /// The objects of every ended frame, with the fence of the frame.
+    pending: Vec<(Fence, Vec<Box<Any>>)>,
+}
+
+impl DeletionQueue {
+    pub fn new() -> DeletionQueue {
+        DeletionQueue { current: Vec::new(), pending: Vec::new() }
+    }
+
+    /// Keep `object` alive until the frame being encoded has completed, then drop it.
+    pub fn defer<T: 'static>(&mut self, object: T) {
+        self.current.push(box object as Box<Any>);
+    }
+
+    /// End the frame being encoded: the objects deferred during it are dropped once `fence`, the
+    /// fence of the frame's last command buffer, is signaled.
+    pub fn end_frame(&mut self, fence: Fence) {
+        if self.current.is_empty() {
+            return;
+        }
+        let objects = std::mem::replace(&mut self.current, Vec::new());
+        self.pending.push((fence, objects));
+    }
+
+    /// Drop the objects of every frame which has completed, whether it succeeded or not, and
+    /// return how many there were. Never blocks.
+    pub fn collect(&mut self) -> uint {
+        let mut dropped = 0;
+        let mut index = 0;
+        while index < self.pending.len() {
+            if self.pending[index].0.is_signaled() {
+                dropped += self.pending.remove(index).unwrap().1.len();
+            } else {
+                index += 1;
+            }
+        }
+        dropped
+    }
+
+    /// The number of objects not dropped yet.
+    pub fn len(&self) -> uint {
+        self.current.len() + self.pending.iter().fold(0, |count, &(_, ref objects)| count + objects.len())
+    }
+
+    /// Wait for every frame, then drop every object, including those deferred since the last
+    /// `end_frame`: call this once the GPU is idle, as teardown does.
+    pub fn flush(&mut self) {
+        enter_span!("DeletionQueue::flush", objects = self.len());
+        for (fence, _) in self.pending.drain() {
+            fence.wait();
+        }
+        self.current.clear();
+    }
+}
+
+impl Drop for DeletionQueue {
+    fn drop(&mut self) {
+        debug_assert!(self.len() == 0, "a deletion queue was dropped with {} objects pending; flush it during teardown, \
+                                        after waiting for the GPU", self.len());
+    }
+}
+
+/// Shut `device` down, in the order of the `Device` documentation:
+///
+/// 1. wait for every queue in `queues`, and the device, to be idle;
+/// 2. flush every deletion queue;
+/// 3. drop the `presentation` objects (swapchains, surfaces and whatever wraps them), in order;
+/// 4. drop the other `objects` of the device (the renderer holding its resources and pipelines,
+///    say), in order, then the queues;
+/// 5. drop the device.
+///
+/// Nothing else of the device may be left. Debug builds assert that no command buffer is in
+/// flight once the device is idle, which would mean one was committed to a queue not in
+/// `queues` from another thread.
+pub fn shutdown<D: Device>(mut device: D, queues: Vec<D::CommandQueue>, deletion_queues: Vec<DeletionQueue>,
+                           presentation: Vec<Box<Any>>, objects: Vec<Box<Any>>) {
+    enter_span!("teardown::shutdown");
+    for queue in queues.iter() {
+        queue.wait_idle();
+    }
+    device.wait_idle();
+
+    for mut deletions in deletion_queues.into_iter() {
+        deletions.flush();
+    }
+    for object in presentation.into_iter() {
+        drop(object);
+    }
+    for object in objects.into_iter() {
+        drop(object);
+    }
+    drop(queues);
+
+    let in_flight = device.in_flight_command_buffers();
+    debug_assert!(in_flight == 0, "{} command buffers are still in flight after waiting for the device", in_flight);
+    drop(device);
+}
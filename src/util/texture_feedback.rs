@@ -0,0 +1,231 @@
+//! Feedback of the mip levels shaders sample, for texture streaming and virtual texturing.
+//!
+//! A streaming system should load the mip levels (and, for virtual textures, the tiles) that
+//! are actually sampled, and evict the rest. `TextureFeedback` finds out which those are: every
+//! registered texture is divided into a grid of regions, and fragment shaders record the finest
+//! level they sample in each region into a feedback buffer, which is read back
+//! `frames_in_flight` frames later:
+//!
+//! ```ignore
+//! let terrain = try!(feedback.register(&mut device, 16384, 16384, (64, 64)));
+//!
+//! for request in feedback.begin_frame(&mut device).into_iter() {
+//!     streamer.want(request.texture, request.region, request.level);
+//! }
+//! // in the forward pass
+//! feedback.bind(&mut encoder, 5);
+//! ```
+//!
+//! Fragment shaders include `SHADER_HEADER` and call `texture_feedback_record` next to the
+//! sample. Only one pixel in `16` records, rotating every frame, which keeps the atomics cheap
+//! and still covers the screen every `16` frames. Levels are computed from the derivatives of
+//! the texture coordinates; with the `texture_lod_query` device feature, shaders can pass the
+//! level `calculate_unclamped_lod` returns instead.
+
+use {Device, BufferSlice, RenderCommandEncoder};
+use util::{as_bytes, slice_as_bytes, slice_as_bytes_mut};
+
+/// The value of a region nothing sampled. Never a mip level.
+const NOT_SAMPLED: u32 = 0xffffffff;
+
+/// A texture registered with `TextureFeedback::register`.
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+pub struct FeedbackTextureId(pub u32);
+
+/// The finest mip level sampled in a region of a texture.
+#[deriving(Clone, Show)]
+pub struct FeedbackRequest {
+    pub texture: FeedbackTextureId,
+    /// The region, in regions across and down from the top left.
+    pub region: (uint, uint),
+    /// The finest level sampled, which may be past the last level of the texture (for
+    /// magnified samples, it is `0`).
+    pub level: uint,
+}
+
+/// Why a texture could not be registered.
+#[deriving(Show)]
+pub enum RegisterError {
+    /// As many textures as the feedback was created for are registered.
+    TooManyTextures,
+    /// The regions of the registered textures fill the feedback buffer.
+    TooManyRegions,
+}
+
+/// How the shader finds a texture's regions. Mirrors `FeedbackEntry` in `SHADER_HEADER`.
+#[repr(C)]
+struct Entry {
+    size: [f32, ..2],
+    regions: [u32, ..2],
+    first_region: u32,
+    padding: [u32, ..3],
+}
+
+#[repr(C)]
+struct FrameUniforms {
+    frame: u32,
+}
+
+/// Feedback buffers, and the textures they cover. See the module documentation.
+pub struct TextureFeedback<D: Device> {
+    /// A `u32` per region, per frame in flight.
+    buffers: Vec<D::Buffer>,
+    entries: D::Buffer,
+    /// The `regions` of each texture, and their first region, in registration order.
+    textures: Vec<((uint, uint), uint)>,
+    region_count: uint,
+    max_textures: uint,
+    max_regions: uint,
+    current: uint,
+    frame: u64,
+    /// Whether each frame in flight was bound, and so has feedback to read back.
+    recorded: Vec<bool>,
+}
+
+impl<D: Device> TextureFeedback<D> {
+    /// Create feedback for up to `max_textures` textures, with up to `max_regions` regions
+    /// between them.
+    ///
+    /// As for `DebugDraw`, `frames_in_flight` must be at least the number of frames the CPU can
+    /// get ahead of the GPU: feedback is read back that many frames after it was recorded.
+    pub fn new(device: &mut D, max_textures: uint, max_regions: uint, frames_in_flight: uint) -> Result<TextureFeedback<D>, D::BufferCreationError> {
+        let entries = try!(device.create_buffer(max_textures * std::mem::size_of::<Entry>(), None));
+        let mut buffers = Vec::with_capacity(frames_in_flight);
+        for _ in range(0, frames_in_flight) {
+            buffers.push(try!(device.create_buffer(max_regions * 4, None)));
+        }
+        Ok(TextureFeedback {
+            buffers: buffers,
+            entries: entries,
+            textures: Vec::new(),
+            region_count: 0,
+            max_textures: max_textures,
+            max_regions: max_regions,
+            current: frames_in_flight - 1,
+            frame: 0,
+            recorded: Vec::from_elem(frames_in_flight, false),
+        })
+    }
+
+    /// Register a `width` by `height` texture, divided into `regions` regions across and down:
+    /// its tiles, for a virtual texture, or a single region for a texture which is only streamed
+    /// by mip level. Shaders record samples of it with the returned ID.
+    pub fn register(&mut self, device: &mut D, width: uint, height: uint, regions: (uint, uint)) -> Result<FeedbackTextureId, RegisterError> {
+        let (regions_x, regions_y) = regions;
+        if self.textures.len() == self.max_textures {
+            return Err(RegisterError::TooManyTextures);
+        }
+        if self.region_count + regions_x * regions_y > self.max_regions {
+            return Err(RegisterError::TooManyRegions);
+        }
+        let entry = Entry {
+            size: [width as f32, height as f32],
+            regions: [regions_x as u32, regions_y as u32],
+            first_region: self.region_count as u32,
+            padding: [0, ..3],
+        };
+        // entries are only ever appended, so frames in flight never see one change
+        let index = self.textures.len();
+        device.write_buffer(&self.entries, index * std::mem::size_of::<Entry>(), as_bytes(&entry));
+        self.textures.push((regions, self.region_count));
+        self.region_count += regions_x * regions_y;
+        Ok(FeedbackTextureId(index as u32))
+    }
+
+    /// Start a new frame: read back the feedback of the frame `frames_in_flight` frames ago, as
+    /// a request for every region something was sampled in, and clear its buffer for this frame.
+    // todo: as in `occlusion_queries`, `read_buffer` waits for every committed command buffer.
+    pub fn begin_frame(&mut self, device: &mut D) -> Vec<FeedbackRequest> {
+        enter_span!("TextureFeedback::begin_frame", regions = self.region_count);
+        self.current = (self.current + 1) % self.buffers.len();
+        self.frame += 1;
+        let mut requests = Vec::new();
+        if self.region_count == 0 {
+            return requests;
+        }
+        let mut levels = Vec::from_elem(self.region_count, NOT_SAMPLED);
+        if self.recorded[self.current] {
+            device.read_buffer(&self.buffers[self.current], 0, slice_as_bytes_mut(levels.as_mut_slice()));
+            for (index, &((regions_x, regions_y), first)) in self.textures.iter().enumerate() {
+                for region in range(0, regions_x * regions_y) {
+                    let level = levels[first + region];
+                    if level != NOT_SAMPLED {
+                        requests.push(FeedbackRequest {
+                            texture: FeedbackTextureId(index as u32),
+                            region: (region % regions_x, region / regions_x),
+                            level: level as uint,
+                        });
+                    }
+                }
+            }
+            for level in levels.iter_mut() {
+                *level = NOT_SAMPLED;
+            }
+        }
+        device.write_buffer(&self.buffers[self.current], 0, slice_as_bytes(levels.as_slice()));
+        self.recorded[self.current] = false;
+        requests
+    }
+
+    /// Bind this frame's feedback for a fragment shader: the feedback buffer, the texture
+    /// entries and the frame uniforms at fragment buffers `first_buffer` to `first_buffer + 2`,
+    /// as `SHADER_HEADER` declares them.
+    pub fn bind<E: RenderCommandEncoder<D>>(&mut self, encoder: &mut E, first_buffer: uint) {
+        let uniforms = FrameUniforms { frame: self.frame as u32 };
+        encoder.set_fragment_buffer(BufferSlice::whole(&self.buffers[self.current]), first_buffer);
+        encoder.set_fragment_buffer(BufferSlice::whole(&self.entries), first_buffer + 1);
+        encoder.set_fragment_bytes(as_bytes(&uniforms), first_buffer + 2);
+        self.recorded[self.current] = true;
+    }
+}
+
+/// Metal shading language declarations for recording feedback, to paste in front of the
+/// fragment shaders which do. Declare the fragment arguments as `TextureFeedback::bind` binds
+/// them:
+///
+/// ```ignore
+/// device atomic_uint *feedback [[buffer(5)]],
+/// device const FeedbackEntry *feedback_entries [[buffer(6)]],
+/// constant FeedbackFrame &feedback_frame [[buffer(7)]],
+/// ```
+pub const SHADER_HEADER: &'static str = r#"
+struct FeedbackEntry {
+    float2 size;
+    uint2 regions;
+    uint first_region;
+};
+
+struct FeedbackFrame {
+    uint frame;
+};
+
+// the mip level sampling a texture of `size` texels at `uv` uses, from the derivatives of `uv`
+static float texture_feedback_lod(float2 uv, float2 size) {
+    float2 dx = dfdx(uv) * size;
+    float2 dy = dfdy(uv) * size;
+    return max(0.5f * log2(max(dot(dx, dx), dot(dy, dy))), 0.0f);
+}
+
+// record that texture `texture` is sampled at `uv` with mip level `lod`, from the fragment at
+// `position` (its `[[position]]`)
+static void texture_feedback_record(device atomic_uint *feedback, device const FeedbackEntry *entries,
+                                    constant FeedbackFrame &frame, uint texture, float2 uv, float lod,
+                                    float4 position) {
+    uint2 pixel = uint2(position.xy);
+    if (((pixel.x & 3) | ((pixel.y & 3) << 2)) != (frame.frame & 15)) {
+        return;
+    }
+    FeedbackEntry entry = entries[texture];
+    uint2 region = min(uint2(fract(uv) * float2(entry.regions)), entry.regions - 1);
+    uint level = uint(max(floor(lod), 0.0f));
+    atomic_fetch_min_explicit(&feedback[entry.first_region + region.y * entry.regions.x + region.x], level,
+                              memory_order_relaxed);
+}
+
+// `texture_feedback_record`, with the level computed from the derivatives of `uv`
+static void texture_feedback_record(device atomic_uint *feedback, device const FeedbackEntry *entries,
+                                    constant FeedbackFrame &frame, uint texture, float2 uv, float4 position) {
+    float lod = texture_feedback_lod(uv, entries[texture].size);
+    texture_feedback_record(feedback, entries, frame, texture, uv, lod, position);
+}
+"#;
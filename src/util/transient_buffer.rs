@@ -0,0 +1,98 @@
+//! Per-frame allocation of short lived buffer data.
+//!
+//! Uniform and storage data which changes every frame (per-draw constants, light lists, ...) is
+//! most cheaply written into one large buffer, one sub-allocation after the other, and bound with
+//! offsets into it. `TransientBuffer` does that, aligning every allocation to the device's
+//! binding offset alignments so the offsets it hands out can always be bound:
+//!
+//! ```ignore
+//! transient.begin_frame();
+//! let offset = transient.allocate(&mut device, as_bytes(&constants), TransientUsage::Uniform).unwrap();
+//! let group = try!(device.create_bind_group(BindGroupDescriptor {
+//!     layout: &layout,
+//!     entries: vec![BindGroupEntry { binding: 0, array_element: 0, resource: BindingResource::Buffer(
+//!         BufferSlice::new(transient.buffer(), offset, std::mem::size_of::<Constants>())) }],
+//!     unsized_length: 0,
+//! }));
+//! ```
+
+use {Device, DeviceLimits, COPY_OFFSET_ALIGNMENT};
+use util::round_up;
+
+/// How an allocation will be bound, which decides its alignment.
+pub enum TransientUsage {
+    /// Bound through a `UniformBuffer` binding.
+    Uniform,
+    /// Bound through a `StorageBuffer` binding.
+    Storage,
+    /// The source of a `copy_buffer_to_texture`.
+    CopySource,
+    /// Not bound through a bind group (vertex data, `set_buffer` with no alignment requirement).
+    Other,
+}
+
+/// A linear allocator over one buffer per frame in flight.
+pub struct TransientBuffer<D: Device> {
+    buffers: Vec<D::Buffer>,
+    current: uint,
+    size: uint,
+    used: uint,
+    limits: DeviceLimits,
+}
+
+impl<D: Device> TransientBuffer<D> {
+    /// Create an allocator with `size` bytes per frame.
+    ///
+    /// As for `DebugDraw`, `frames_in_flight` must be at least the number of frames the CPU can
+    /// get ahead of the GPU: a frame's allocations are overwritten `frames_in_flight` frames
+    /// later.
+    pub fn new(device: &mut D, size: uint, frames_in_flight: uint) -> Result<TransientBuffer<D>, D::BufferCreationError> {
+        let mut buffers = Vec::with_capacity(frames_in_flight);
+        for _ in range(0, frames_in_flight) {
+            buffers.push(try!(device.create_buffer(size, None)));
+        }
+        Ok(TransientBuffer {
+            buffers: buffers,
+            current: frames_in_flight - 1,
+            size: size,
+            used: size,
+            limits: device.limits(),
+        })
+    }
+
+    /// Start a new frame, moving on to the next buffer and freeing everything allocated in it.
+    pub fn begin_frame(&mut self) {
+        self.current = (self.current + 1) % self.buffers.len();
+        self.used = 0;
+    }
+
+    /// The buffer allocations of the current frame are in.
+    pub fn buffer(&self) -> &D::Buffer {
+        &self.buffers[self.current]
+    }
+
+    /// The number of bytes allocated in the current frame, including alignment padding.
+    pub fn used(&self) -> uint {
+        self.used
+    }
+
+    /// Copy `data` into the current frame's buffer, returning its offset.
+    ///
+    /// Returns `None` if the frame's buffer is full.
+    pub fn allocate(&mut self, device: &mut D, data: &[u8], usage: TransientUsage) -> Option<uint> {
+        let alignment = match usage {
+            TransientUsage::Uniform => self.limits.min_uniform_buffer_offset_alignment,
+            TransientUsage::Storage => self.limits.min_storage_buffer_offset_alignment,
+            TransientUsage::CopySource => COPY_OFFSET_ALIGNMENT,
+            // still 4, so the data can be read as 32-bit values
+            TransientUsage::Other => 4,
+        };
+        let offset = round_up(self.used, alignment);
+        if offset + data.len() > self.size {
+            return None;
+        }
+        device.write_buffer(&self.buffers[self.current], offset, data);
+        self.used = offset + data.len();
+        Some(offset)
+    }
+}
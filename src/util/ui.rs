@@ -0,0 +1,306 @@
+//! Rendering for immediate mode user interface libraries.
+//!
+//! Libraries like egui and Dear ImGui produce their output as lists of indexed triangles with a
+//! texture and clip rectangle per range of indices. `UiRenderer` draws exactly that, so hooking
+//! such a library up is a matter of pointing `UiDrawList`s at its output:
+//!
+//! ```ignore
+//! let primitives = context.tessellate(output.shapes, pixels_per_point);
+//! let lists = egui_draw_lists(primitives.as_slice(), |id| textures.get(&id));
+//! ui.draw(&mut device, &mut encoder, lists.as_slice(), (width, height), pixels_per_point);
+//! ```
+//!
+//! `UiVertex` has the same layout as both egui's `Vertex` and Dear ImGui's `ImDrawVert`, so vertex
+//! data is used without conversion. With the `egui` feature, `egui_draw_lists` does the rest for
+//! egui; for Dear ImGui, each `ImDrawList` maps to one `UiDrawList` and each of its commands to a
+//! `UiDrawCommand`, with the display position subtracted from clip rectangles.
+//!
+//! Textures, including the library's font atlas, are created and updated by the caller, and bound
+//! with bind groups from `UiRenderer::texture_bind_group`.
+
+use {Device, BlendDescriptor, BufferSlice, RenderCommandEncoder, RenderPipelineDescriptor, PrimitiveType, ScissorRect, IndexFormat};
+use {BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry};
+use {BindingCount, BindingResource, BindingType, ShaderStages};
+use {VertexAttribute, VertexBufferLayout, VertexDescriptor, VertexFormat, VertexStepFunction};
+use util::{as_bytes, slice_as_bytes};
+use util::debug_draw::Color;
+
+/// A user interface vertex, in points.
+#[repr(C)]
+#[deriving(Clone)]
+pub struct UiVertex {
+    pub position: [f32, ..2],
+    pub uv: [f32, ..2],
+    pub color: Color,
+}
+
+/// The index data of a draw list.
+pub enum UiIndices<'a> {
+    UInt16(&'a [u16]),
+    UInt32(&'a [u32]),
+}
+
+/// A range of a draw list's triangles drawn with the same texture and clip rectangle.
+pub struct UiDrawCommand<'a, D: Device> {
+    /// The texture (and sampler) to draw with, from `UiRenderer::texture_bind_group`.
+    pub texture: &'a D::BindGroup,
+    /// Only draw within this rectangle, as `min_x`, `min_y`, `max_x`, `max_y` in points.
+    pub clip_rect: [f32, ..4],
+    pub first_index: uint,
+    pub index_count: uint,
+    /// Added to every index of this command.
+    pub vertex_offset: uint,
+}
+
+/// A list of triangles, drawn in order.
+pub struct UiDrawList<'a, D: Device> {
+    pub vertices: &'a [UiVertex],
+    pub indices: UiIndices<'a>,
+    pub commands: Vec<UiDrawCommand<'a, D>>,
+}
+
+/// Draws `UiDrawList`s.
+pub struct UiRenderer<D: Device> {
+    pipeline: D::RenderPipeline,
+    layout: D::BindGroupLayout,
+    /// One vertex and one index buffer per frame in flight, used in rotation.
+    vertex_buffers: Vec<D::Buffer>,
+    index_buffers: Vec<D::Buffer>,
+    next_buffer: uint,
+    max_vertices: uint,
+    max_indices: uint,
+}
+
+/// Why a `UiRenderer` could not be created.
+pub enum UiRendererCreationError<D: Device> {
+    Pipeline(D::RenderPipelineCreationError),
+    BindGroupLayout(D::BindGroupLayoutCreationError),
+    Buffer(D::BufferCreationError),
+}
+
+impl<D: Device> UiRenderer<D> {
+    /// Create a renderer which draws up to `max_vertices` vertices and `max_indices` indices per
+    /// frame.
+    ///
+    /// `program` must be a shader program created from `SOURCE`. As for `DebugDraw`,
+    /// `frames_in_flight` must be at least the number of frames the CPU can get ahead of the GPU.
+    pub fn new(device: &mut D, program: &D::ShaderProgram, max_vertices: uint, max_indices: uint,
+               frames_in_flight: uint) -> Result<UiRenderer<D>, UiRendererCreationError<D>> {
+        let fragment = ShaderStages { vertex: false, fragment: true, compute: false };
+        let layout = match device.create_bind_group_layout(BindGroupLayoutDescriptor {
+            entries: vec![
+                BindGroupLayoutEntry { binding: 0, visibility: fragment, ty: BindingType::SampledTexture,
+                                       count: BindingCount::Single, partially_bound: false, update_after_bind: false },
+                BindGroupLayoutEntry { binding: 1, visibility: fragment, ty: BindingType::Sampler,
+                                       count: BindingCount::Single, partially_bound: false, update_after_bind: false },
+            ],
+        }) {
+            Ok(layout) => layout,
+            Err(e) => return Err(UiRendererCreationError::BindGroupLayout(e)),
+        };
+
+        let desc = RenderPipelineDescriptor {
+            label: Some("ui".to_string()),
+            robustness: None,
+            vertex_function: "ui_vertex".to_string(),
+            fragment_function: Some("ui_fragment".to_string()),
+            vertex_descriptor: Some(VertexDescriptor {
+                attributes: vec![
+                    VertexAttribute { format: VertexFormat::Float2, offset: 0, buffer_index: 0 },
+                    VertexAttribute { format: VertexFormat::Float2, offset: 8, buffer_index: 0 },
+                    VertexAttribute { format: VertexFormat::UChar4Normalized, offset: 16, buffer_index: 0 },
+                ],
+                layouts: vec![
+                    VertexBufferLayout { stride: std::mem::size_of::<UiVertex>(),
+                                         step_function: VertexStepFunction::PerVertex, step_rate: 1 },
+                ],
+            }),
+            blending: vec![Some(BlendDescriptor::premultiplied_alpha())],
+            sample_count: 1,
+            attachment_formats: None,
+        };
+        let pipeline = match device.create_render_pipeline(program, desc) {
+            Ok(pipeline) => pipeline,
+            Err(e) => return Err(UiRendererCreationError::Pipeline(e)),
+        };
+
+        let mut vertex_buffers = Vec::with_capacity(frames_in_flight);
+        let mut index_buffers = Vec::with_capacity(frames_in_flight);
+        for _ in range(0, frames_in_flight) {
+            match device.create_buffer(max_vertices * std::mem::size_of::<UiVertex>(), None) {
+                Ok(buffer) => vertex_buffers.push(buffer),
+                Err(e) => return Err(UiRendererCreationError::Buffer(e)),
+            }
+            match device.create_buffer(max_indices * 4, None) {
+                Ok(buffer) => index_buffers.push(buffer),
+                Err(e) => return Err(UiRendererCreationError::Buffer(e)),
+            }
+        }
+
+        Ok(UiRenderer {
+            pipeline: pipeline,
+            layout: layout,
+            vertex_buffers: vertex_buffers,
+            index_buffers: index_buffers,
+            next_buffer: 0,
+            max_vertices: max_vertices,
+            max_indices: max_indices,
+        })
+    }
+
+    /// Create the bind group draw commands use to refer to a texture and the sampler to draw it
+    /// with.
+    pub fn texture_bind_group(&self, device: &mut D, texture: &D::Texture, sampler: &D::Sampler) -> Result<D::BindGroup, D::BindGroupCreationError> {
+        device.create_bind_group(BindGroupDescriptor {
+            layout: &self.layout,
+            entries: vec![
+                BindGroupEntry { binding: 0, array_element: 0, resource: BindingResource::Texture(texture) },
+                BindGroupEntry { binding: 1, array_element: 0, resource: BindingResource::Sampler(sampler) },
+            ],
+            unsized_length: 0,
+        })
+    }
+
+    /// Draw the lists, in order, onto a `viewport_size` pixel attachment with `pixels_per_point`
+    /// pixels per point.
+    ///
+    /// Lists past the renderer's capacity are not drawn. Returns the number of draw calls issued.
+    pub fn draw<'a, E: RenderCommandEncoder<D>>(&mut self, device: &mut D, encoder: &mut E, lists: &[UiDrawList<'a, D>],
+                                                viewport_size: (uint, uint), pixels_per_point: f32) -> uint {
+        enter_span!("UiRenderer::draw");
+        // every list goes into the same buffers, with indices widened to 32 bits so lists of
+        // either index type can be drawn without rebinding.
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut drawn = 0;
+        for list in lists.iter() {
+            let index_count = match list.indices {
+                UiIndices::UInt16(i) => i.len(),
+                UiIndices::UInt32(i) => i.len(),
+            };
+            if vertices.len() + list.vertices.len() > self.max_vertices ||
+               indices.len() + index_count > self.max_indices {
+                break;
+            }
+            vertices.push_all(list.vertices);
+            match list.indices {
+                UiIndices::UInt16(i) => indices.extend(i.iter().map(|&i| i as u32)),
+                UiIndices::UInt32(i) => indices.push_all(i),
+            }
+            drawn += 1;
+        }
+        if drawn == 0 {
+            return 0;
+        }
+
+        let vertex_buffer = &self.vertex_buffers[self.next_buffer];
+        let index_buffer = &self.index_buffers[self.next_buffer];
+        self.next_buffer = (self.next_buffer + 1) % self.vertex_buffers.len();
+        device.write_buffer(vertex_buffer, 0, slice_as_bytes(vertices.as_slice()));
+        device.write_buffer(index_buffer, 0, slice_as_bytes(indices.as_slice()));
+
+        let (width, height) = viewport_size;
+        let size = [width as f32 / pixels_per_point, height as f32 / pixels_per_point];
+        encoder.set_render_pipeline(&self.pipeline);
+        encoder.set_vertex_buffer(BufferSlice::whole(vertex_buffer), 0);
+        encoder.set_vertex_bytes(as_bytes(&size), 1);
+        encoder.set_index_buffer(BufferSlice::whole(index_buffer), IndexFormat::UInt32);
+
+        let mut draws = 0;
+        let (mut base_vertex, mut base_index) = (0, 0);
+        for list in lists.slice_to(drawn).iter() {
+            for command in list.commands.iter() {
+                let clip = command.clip_rect;
+                let min_x = (clip[0] * pixels_per_point).max(0.0).round() as uint;
+                let min_y = (clip[1] * pixels_per_point).max(0.0).round() as uint;
+                let max_x = std::cmp::min((clip[2] * pixels_per_point).max(0.0).round() as uint, width);
+                let max_y = std::cmp::min((clip[3] * pixels_per_point).max(0.0).round() as uint, height);
+                if command.index_count == 0 || max_x <= min_x || max_y <= min_y {
+                    continue;
+                }
+
+                encoder.set_bind_group(command.texture, 0);
+                encoder.set_scissor_rect(ScissorRect { x: min_x, y: min_y, width: max_x - min_x, height: max_y - min_y });
+                encoder.draw_indexed(PrimitiveType::Triangle, base_index + command.first_index, command.index_count,
+                                     1, (base_vertex + command.vertex_offset) as int, 0);
+                draws += 1;
+            }
+            base_vertex += list.vertices.len();
+            base_index += match list.indices {
+                UiIndices::UInt16(i) => i.len(),
+                UiIndices::UInt32(i) => i.len(),
+            };
+        }
+        draws
+    }
+}
+
+/// Turn tessellated egui output into draw lists.
+///
+/// `textures` maps egui texture ids to the bind groups to draw them with; meshes whose texture it
+/// does not know are skipped, as are paint callbacks.
+#[cfg(feature = "egui")]
+pub fn egui_draw_lists<'a, D: Device, F>(primitives: &'a [egui::ClippedPrimitive], textures: F) -> Vec<UiDrawList<'a, D>>
+        where F: FnMut(egui::TextureId) -> Option<&'a D::BindGroup> {
+    let mut textures = textures;
+    let mut lists = Vec::new();
+    for primitive in primitives.iter() {
+        let mesh = match primitive.primitive {
+            egui::epaint::Primitive::Mesh(ref mesh) => mesh,
+            egui::epaint::Primitive::Callback(_) => continue,
+        };
+        let texture = match textures(mesh.texture_id) {
+            Some(texture) => texture,
+            None => continue,
+        };
+        let clip = primitive.clip_rect;
+        lists.push(UiDrawList {
+            // same layout, see the module documentation
+            vertices: unsafe { std::mem::transmute::<&'a [egui::epaint::Vertex], &'a [UiVertex]>(mesh.vertices.as_slice()) },
+            indices: UiIndices::UInt32(mesh.indices.as_slice()),
+            commands: vec![UiDrawCommand {
+                texture: texture,
+                clip_rect: [clip.min.x, clip.min.y, clip.max.x, clip.max.y],
+                first_index: 0,
+                index_count: mesh.indices.len(),
+                vertex_offset: 0,
+            }],
+        });
+    }
+    lists
+}
+
+/// The metal shading language source of the user interface shaders.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct UiVertex {
+    float2 position [[attribute(0)]];
+    float2 uv [[attribute(1)]];
+    float4 color [[attribute(2)]];
+};
+
+struct UiFragment {
+    float4 position [[position]];
+    float2 uv;
+    float4 color;
+};
+
+vertex UiFragment ui_vertex(UiVertex in [[stage_in]],
+                            constant float2 &screen_size [[buffer(1)]])
+{
+    UiFragment out;
+    out.position = float4(in.position / screen_size * float2(2.0f, -2.0f) + float2(-1.0f, 1.0f), 0.0f, 1.0f);
+    out.uv = in.uv;
+    out.color = in.color;
+    return out;
+}
+
+fragment float4 ui_fragment(UiFragment in [[stage_in]],
+                            texture2d<float> texture [[texture(0)]],
+                            sampler s [[sampler(0)]])
+{
+    return texture.sample(s, in.uv) * in.color;
+}
+"#;
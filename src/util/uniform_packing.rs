@@ -0,0 +1,105 @@
+//! Packing per-instance data into storage buffers in fewer bytes, with the shader functions
+//! unpacking it.
+//!
+//! Large arrays of instance data (transforms, normals, tints) are read by every vertex or
+//! thread that touches an instance, so their size is bandwidth on every draw. Most of it needs
+//! far less than the floats it is computed in: a unit normal fits in 32 bits as octahedral
+//! coordinates, a color in four 8-bit unorms, and an affine transform in the 48 bytes of its top
+//! three rows. The functions here pack on the CPU, and `SHADER_HEADER` declares the matching
+//! layouts and unpacking functions for the shader side, to prepend to the source or include
+//! (as `HEADER_PATH`) through `shader_preprocessor`:
+//!
+//! ```ignore
+//! #[repr(C)]
+//! struct Instance {
+//!     transform: PackedMatrix3x4,
+//!     normal: u32,
+//!     tint: u32,
+//! }
+//!
+//! let instance = Instance {
+//!     transform: pack_matrix_3x4(&model),
+//!     normal: pack_octahedral(up),
+//!     tint: pack_color(color),
+//! };
+//!
+//! let mut includes = HashMap::new();
+//! includes.insert(HEADER_PATH.to_string(), SHADER_HEADER.to_string());
+//! // and in the shader: float4x4 model = unpack_matrix_3x4(instances[iid].transform);
+//! ```
+//!
+//! Values are rounded to the nearest representable one, as in `util::vertex_packing`, which
+//! does the same for vertex attributes read through vertex formats.
+
+use util::vertex_packing::{pack_snorm16x2, pack_unorm8x4};
+
+/// The path `SHADER_HEADER` is meant to be included as.
+pub const HEADER_PATH: &'static str = "uniform_packing.h";
+
+/// The top three rows of an affine transform, which is all of it: the fourth row is always
+/// `(0, 0, 0, 1)`. Declared as `PackedMatrix3x4` in `SHADER_HEADER`.
+#[repr(C)]
+#[deriving(Clone, Show)]
+pub struct PackedMatrix3x4 {
+    pub rows: [[f32, ..4], ..3],
+}
+
+/// Pack `matrix`, a column major affine transform. Its fourth row is dropped, whatever it is.
+pub fn pack_matrix_3x4(matrix: &[f32, ..16]) -> PackedMatrix3x4 {
+    let row = |r: uint| [matrix[r], matrix[4 + r], matrix[8 + r], matrix[12 + r]];
+    PackedMatrix3x4 { rows: [row(0), row(1), row(2)] }
+}
+
+/// Pack `normal`, a unit vector, as octahedral coordinates: two 16-bit signed normalized
+/// integers, `x` in the low half. Unpacked by `unpack_octahedral`, to within about `0.005`
+/// degrees. Zero vectors unpack as `+z`.
+pub fn pack_octahedral(normal: [f32, ..3]) -> u32 {
+    let sign = |v: f32| if v >= 0.0 { 1.0f32 } else { -1.0 };
+    let l1 = normal[0].abs() + normal[1].abs() + normal[2].abs();
+    let (x, y) = if l1 > 0.0 { (normal[0] / l1, normal[1] / l1) } else { (0.0, 0.0) };
+    // the lower hemisphere is folded over the diagonals onto the corners of the square
+    let (x, y) = if normal[2] < 0.0 {
+        ((1.0 - y.abs()) * sign(x), (1.0 - x.abs()) * sign(y))
+    } else {
+        (x, y)
+    };
+    let packed = pack_snorm16x2([x, y]);
+    packed[0] as u32 | (packed[1] as u32 << 16)
+}
+
+/// Pack `color`, with components in `[0, 1]`, as four 8-bit unsigned normalized integers, red
+/// in the low byte. Unpacked by `unpack_color`.
+pub fn pack_color(color: [f32, ..4]) -> u32 {
+    let packed = pack_unorm8x4(color);
+    packed[0] as u32 | (packed[1] as u32 << 8) | (packed[2] as u32 << 16) | (packed[3] as u32 << 24)
+}
+
+/// Metal shading language declarations of the packed layouts, and the functions unpacking them.
+pub const SHADER_HEADER: &'static str = r#"
+#pragma once
+#include <metal_stdlib>
+
+struct PackedMatrix3x4 {
+    float4 rows[3];
+};
+
+static inline metal::float4x4 unpack_matrix_3x4(PackedMatrix3x4 m) {
+    return metal::float4x4(metal::float4(m.rows[0].x, m.rows[1].x, m.rows[2].x, 0.0f),
+                           metal::float4(m.rows[0].y, m.rows[1].y, m.rows[2].y, 0.0f),
+                           metal::float4(m.rows[0].z, m.rows[1].z, m.rows[2].z, 0.0f),
+                           metal::float4(m.rows[0].w, m.rows[1].w, m.rows[2].w, 1.0f));
+}
+
+static inline metal::float3 unpack_octahedral(uint packed) {
+    metal::float2 p = metal::max(metal::float2(as_type<short2>(packed)) / 32767.0f, -1.0f);
+    metal::float3 n = metal::float3(p, 1.0f - metal::abs(p.x) - metal::abs(p.y));
+    float t = metal::saturate(-n.z);
+    n.x += n.x >= 0.0f ? -t : t;
+    n.y += n.y >= 0.0f ? -t : t;
+    return metal::normalize(n);
+}
+
+static inline metal::float4 unpack_color(uint packed) {
+    return metal::unpack_unorm4x8_to_float(packed);
+}
+"#;
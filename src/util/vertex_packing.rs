@@ -0,0 +1,162 @@
+//! Packing vertex attributes into the small vertex formats.
+//!
+//! Positions rarely need more than half floats once a mesh is centered, and normals, tangents,
+//! texture coordinates and colors fit in 8 to 16 bits per component, so most meshes can halve
+//! their vertex buffers (and the bandwidth drawing them takes) with the formats of the
+//! `packed_vertex_formats` device feature. The functions here produce the bytes each format is
+//! read from; values are rounded to the nearest representable one, and normalized values are
+//! clamped to their range first.
+//!
+//! ```ignore
+//! #[repr(C)]
+//! struct PackedVertex {
+//!     position: [u16, ..4], // VertexFormat::Half4
+//!     normal: u32,          // VertexFormat::Int1010102Normalized
+//!     uv: [u16, ..2],       // VertexFormat::UShort2Normalized
+//! }
+//!
+//! let packed = PackedVertex {
+//!     position: pack_half4([p[0], p[1], p[2], 1.0]),
+//!     normal: pack_snorm1010102([n[0], n[1], n[2], 0.0]),
+//!     uv: pack_unorm16x2(uv),
+//! };
+//! ```
+
+/// `value` as a 16-bit float, rounded to the nearest. Values too large for a half float become
+/// infinities, and NaNs stay NaNs.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits: u32 = unsafe { std::mem::transmute(value) };
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7fffff;
+
+    if exponent == 0xff {
+        // infinity, or NaN with a mantissa bit kept set
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        // a subnormal half, or zero
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x800000;
+        let shift = (14 - exponent) as uint;
+        let half = mantissa >> shift;
+        // round to nearest, ties to even
+        let rest = mantissa & ((1 << shift) - 1);
+        let midpoint = 1 << (shift - 1);
+        let round = rest > midpoint || (rest == midpoint && (half & 1) != 0);
+        return sign | (half + if round { 1 } else { 0 }) as u16;
+    }
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let rest = mantissa & 0x1fff;
+    let round = rest > 0x1000 || (rest == 0x1000 && (half & 1) != 0);
+    // a carry out of the mantissa correctly bumps the exponent, up to infinity
+    sign | (half + if round { 1 } else { 0 }) as u16
+}
+
+/// The value of the 16-bit float `half`.
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+    let bits = if exponent == 0x1f {
+        sign | 0x7f800000 | (mantissa << 13)
+    } else if exponent != 0 {
+        sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)
+    } else if mantissa == 0 {
+        sign
+    } else {
+        // renormalize the subnormal
+        let mut exponent = 127 - 15 + 1;
+        let mut mantissa = mantissa;
+        while mantissa & 0x400 == 0 {
+            mantissa <<= 1;
+            exponent -= 1;
+        }
+        sign | (exponent << 23) | ((mantissa & 0x3ff) << 13)
+    };
+    unsafe { std::mem::transmute(bits) }
+}
+
+/// `VertexFormat::Half2`.
+pub fn pack_half2(values: [f32, ..2]) -> [u16, ..2] {
+    [f32_to_f16(values[0]), f32_to_f16(values[1])]
+}
+
+/// `VertexFormat::Half4`.
+pub fn pack_half4(values: [f32, ..4]) -> [u16, ..4] {
+    [f32_to_f16(values[0]), f32_to_f16(values[1]), f32_to_f16(values[2]), f32_to_f16(values[3])]
+}
+
+/// `value`, clamped to `[0, 1]`, as an unsigned normalized integer of `bits` bits.
+fn unorm(value: f32, bits: uint) -> u32 {
+    let max = ((1u32 << bits) - 1) as f32;
+    let value = if value > 0.0 { value } else { 0.0 };
+    let value = if value < 1.0 { value } else { 1.0 };
+    (value * max + 0.5) as u32
+}
+
+/// `value`, clamped to `[-1, 1]`, as a two's complement signed normalized integer of `bits` bits.
+fn snorm(value: f32, bits: uint) -> u32 {
+    let max = ((1u32 << (bits - 1)) - 1) as f32;
+    let value = if value > -1.0 { value } else { -1.0 };
+    let value = if value < 1.0 { value } else { 1.0 };
+    let scaled = value * max;
+    let rounded = if scaled >= 0.0 { (scaled + 0.5) as i32 } else { (scaled - 0.5) as i32 };
+    (rounded as u32) & ((1u32 << bits) - 1)
+}
+
+/// `VertexFormat::UChar2Normalized`.
+pub fn pack_unorm8x2(values: [f32, ..2]) -> [u8, ..2] {
+    [unorm(values[0], 8) as u8, unorm(values[1], 8) as u8]
+}
+
+/// `VertexFormat::UChar4Normalized`.
+pub fn pack_unorm8x4(values: [f32, ..4]) -> [u8, ..4] {
+    [unorm(values[0], 8) as u8, unorm(values[1], 8) as u8, unorm(values[2], 8) as u8, unorm(values[3], 8) as u8]
+}
+
+/// `VertexFormat::Char2Normalized`.
+pub fn pack_snorm8x2(values: [f32, ..2]) -> [u8, ..2] {
+    [snorm(values[0], 8) as u8, snorm(values[1], 8) as u8]
+}
+
+/// `VertexFormat::Char4Normalized`.
+pub fn pack_snorm8x4(values: [f32, ..4]) -> [u8, ..4] {
+    [snorm(values[0], 8) as u8, snorm(values[1], 8) as u8, snorm(values[2], 8) as u8, snorm(values[3], 8) as u8]
+}
+
+/// `VertexFormat::UShort2Normalized`.
+pub fn pack_unorm16x2(values: [f32, ..2]) -> [u16, ..2] {
+    [unorm(values[0], 16) as u16, unorm(values[1], 16) as u16]
+}
+
+/// `VertexFormat::UShort4Normalized`.
+pub fn pack_unorm16x4(values: [f32, ..4]) -> [u16, ..4] {
+    [unorm(values[0], 16) as u16, unorm(values[1], 16) as u16, unorm(values[2], 16) as u16, unorm(values[3], 16) as u16]
+}
+
+/// `VertexFormat::Short2Normalized`.
+pub fn pack_snorm16x2(values: [f32, ..2]) -> [u16, ..2] {
+    [snorm(values[0], 16) as u16, snorm(values[1], 16) as u16]
+}
+
+/// `VertexFormat::Short4Normalized`.
+pub fn pack_snorm16x4(values: [f32, ..4]) -> [u16, ..4] {
+    [snorm(values[0], 16) as u16, snorm(values[1], 16) as u16, snorm(values[2], 16) as u16, snorm(values[3], 16) as u16]
+}
+
+/// `VertexFormat::UInt1010102Normalized`.
+pub fn pack_unorm1010102(values: [f32, ..4]) -> u32 {
+    unorm(values[0], 10) | (unorm(values[1], 10) << 10) | (unorm(values[2], 10) << 20) | (unorm(values[3], 2) << 30)
+}
+
+/// `VertexFormat::Int1010102Normalized`. The 2-bit component can only hold `-1`, `0` and `1`.
+pub fn pack_snorm1010102(values: [f32, ..4]) -> u32 {
+    snorm(values[0], 10) | (snorm(values[1], 10) << 10) | (snorm(values[2], 10) << 20) | (snorm(values[3], 2) << 30)
+}
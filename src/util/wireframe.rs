@@ -0,0 +1,204 @@
+//! Wireframe overlays, as editors draw over the selected objects.
+//!
+//! `WireframeOverlay` draws the edges of meshes over an image which already has them rendered,
+//! with its own pipeline: meshes are drawn from the vertex and index buffers they are rendered
+//! from, whatever their vertex layout, as long as positions are three floats. Lines are biased
+//! towards the camera and depth tested against the scene, so hidden edges stay hidden without
+//! the visible ones fighting the surfaces they lie on:
+//!
+//! ```ignore
+//! let mut encoder = commands.render_command_encoder(overlay.pass(&color, Some(&depth)));
+//! overlay.draw(&mut encoder, selected.iter().map(|object| WireframeDraw {
+//!     vertices: BufferSlice::whole(&object.mesh.vertices),
+//!     stride: 32,
+//!     position_offset: 0,
+//!     indices: Some((BufferSlice::whole(&object.mesh.indices), IndexFormat::UInt16)),
+//!     first: 0,
+//!     count: object.mesh.index_count,
+//!     base_vertex: 0,
+//!     transform: object.model_view_projection,
+//! }).collect::<Vec<_>>().as_slice());
+//! encoder.end_encoding();
+//! ```
+//!
+//! This needs the `fill_mode_lines` device feature. Materials which should always be drawn as
+//! wireframes can instead be set to `TriangleFillMode::Lines` (see
+//! `MaterialInstance::set_fill_mode`).
+
+use {Device, BlendDescriptor, BufferSlice, ClearColor, CompareFunction, DepthStencilStateDescriptor, IndexFormat};
+use {LoadAction, PrimitiveType, RenderCommandEncoder, RenderPassColorAttachment, RenderPassDepthAttachment};
+use {RenderPassDescriptor, RenderPipelineDescriptor, StoreAction, Texture, TriangleFillMode};
+use util::as_bytes;
+
+/// A mesh to draw the edges of.
+pub struct WireframeDraw<'a, D: Device> {
+    /// The vertex buffer. The `n`th vertex is `stride` bytes after the `n - 1`th, from the
+    /// start of the slice.
+    pub vertices: BufferSlice<'a, D::Buffer>,
+    pub stride: uint,
+    /// The offset of the position (three floats) in each vertex.
+    pub position_offset: uint,
+    /// The index buffer, and the type of its indices, or `None` for a non-indexed mesh.
+    pub indices: Option<(BufferSlice<'a, D::Buffer>, IndexFormat)>,
+    /// The first index (or vertex, without indices), and the number of them, which must make
+    /// up whole triangles.
+    pub first: uint,
+    pub count: uint,
+    /// Added to every index.
+    pub base_vertex: int,
+    /// The model view projection matrix, column major.
+    pub transform: [f32, ..16],
+}
+
+/// Why a `WireframeOverlay` could not be created.
+pub enum WireframeCreationError<D: Device> {
+    /// The device lacks the `fill_mode_lines` feature.
+    Unsupported,
+    Pipeline(D::RenderPipelineCreationError),
+}
+
+/// Mirrors `WireframeUniforms` in `SOURCE`.
+#[repr(C)]
+struct Uniforms {
+    transform: [f32, ..16],
+    color: [f32, ..4],
+    stride: u32,
+    position_offset: u32,
+    padding: [u32, ..2],
+}
+
+/// The pipeline and state of wireframe overlays. See the module documentation.
+pub struct WireframeOverlay<D: Device> {
+    pipeline: D::RenderPipeline,
+    depth_state: D::DepthStencilState,
+    color: [f32, ..4],
+    bias: f32,
+    slope_scale: f32,
+}
+
+impl<D: Device> WireframeOverlay<D> {
+    /// `program` must be a shader program created from `SOURCE`.
+    ///
+    /// Lines are drawn in opaque white, with a depth bias of `-1` and a slope scale of `-1`
+    /// until changed.
+    pub fn new(device: &mut D, program: &D::ShaderProgram) -> Result<WireframeOverlay<D>, WireframeCreationError<D>> {
+        if !device.features().fill_mode_lines {
+            return Err(WireframeCreationError::Unsupported);
+        }
+        let pipeline = match device.create_render_pipeline(program, RenderPipelineDescriptor {
+            label: Some("wireframe".to_string()),
+            robustness: None,
+            vertex_function: "wireframe_vertex".to_string(),
+            fragment_function: Some("wireframe_fragment".to_string()),
+            vertex_descriptor: None,
+            blending: vec![Some(BlendDescriptor::alpha())],
+            sample_count: 1,
+            attachment_formats: None,
+        }) {
+            Ok(pipeline) => pipeline,
+            Err(e) => return Err(WireframeCreationError::Pipeline(e)),
+        };
+        // edges behind the scene are hidden, and the overlay leaves the depth as it was
+        let depth_state = device.create_depth_stencil_state(DepthStencilStateDescriptor {
+            depth_compare_function: CompareFunction::LessEqual,
+            depth_write_enabled: false,
+            front_face_stencil: None,
+            back_face_stencil: None,
+        });
+        Ok(WireframeOverlay {
+            pipeline: pipeline,
+            depth_state: depth_state,
+            color: [1.0, 1.0, 1.0, 1.0],
+            bias: -1.0,
+            slope_scale: -1.0,
+        })
+    }
+
+    /// Draw lines in `color` (straight alpha, blended over the image).
+    pub fn set_color(&mut self, color: [f32, ..4]) {
+        self.color = color;
+    }
+
+    /// Set the depth bias lines are drawn with, as for `RenderCommandEncoder::set_depth_bias`.
+    /// More negative values keep lines visible on surfaces seen at grazing angles, but also let
+    /// them show through nearby geometry in front.
+    pub fn set_depth_bias(&mut self, bias: f32, slope_scale: f32) {
+        self.bias = bias;
+        self.slope_scale = slope_scale;
+    }
+
+    /// A render pass which draws over `color`, keeping what is in it, depth tested against
+    /// `depth` (the depth the scene was rendered with) if given.
+    pub fn pass<'a>(&self, color: &'a D::Texture, depth: Option<&'a D::Texture>) -> RenderPassDescriptor<'a, D> {
+        RenderPassDescriptor {
+            color_attachments: vec![RenderPassColorAttachment {
+                texture: color, mipmap_level: 0, slice: 0,
+                load_action: LoadAction::Load, store_action: StoreAction::Store,
+                clear_color: ClearColor::zero_for(&color.pixel_format()),
+            }],
+            depth_attachment: depth.map(|depth| RenderPassDepthAttachment {
+                texture: depth, mipmap_level: 0, slice: 0,
+                load_action: LoadAction::Load, store_action: StoreAction::Store, clear_depth: 1.0,
+            }),
+            stencil_attachment: None,
+            sample_positions: None,
+            visibility_result_buffer: None,
+        }
+    }
+
+    /// Draw the edges of `draws` in the pass `encoder` records, which should be one `pass`
+    /// describes. The fill mode and depth bias are reset to their defaults afterwards.
+    pub fn draw<E: RenderCommandEncoder<D>>(&self, encoder: &mut E, draws: &[WireframeDraw<D>]) {
+        enter_span!("WireframeOverlay::draw", draws = draws.len());
+        encoder.set_render_pipeline(&self.pipeline);
+        encoder.set_depth_stencil_state(&self.depth_state);
+        encoder.set_triangle_fill_mode(TriangleFillMode::Lines);
+        encoder.set_depth_bias(self.bias, self.slope_scale, 0.0);
+        for draw in draws.iter() {
+            let uniforms = Uniforms {
+                transform: draw.transform,
+                color: self.color,
+                stride: draw.stride as u32,
+                position_offset: draw.position_offset as u32,
+                padding: [0, ..2],
+            };
+            encoder.set_vertex_buffer(draw.vertices.clone(), 0);
+            encoder.set_vertex_bytes(as_bytes(&uniforms), 1);
+            encoder.set_fragment_bytes(as_bytes(&uniforms), 0);
+            match draw.indices {
+                Some((ref indices, ref format)) => {
+                    encoder.set_index_buffer(indices.clone(), format.clone());
+                    encoder.draw_indexed(PrimitiveType::Triangle, draw.first, draw.count, 1, draw.base_vertex, 0);
+                }
+                None => encoder.draw(PrimitiveType::Triangle, draw.first, draw.count, 1, 0),
+            }
+        }
+        encoder.set_depth_bias(0.0, 0.0, 0.0);
+        encoder.set_triangle_fill_mode(TriangleFillMode::Fill);
+    }
+}
+
+/// Metal shading language source for `WireframeOverlay`. Positions are pulled from the vertex
+/// buffer by vertex ID (see `vertex_pulling`), which is why any vertex layout works.
+pub const SOURCE: &'static str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+struct WireframeUniforms {
+    float4x4 transform;
+    float4 color;
+    uint stride;
+    uint position_offset;
+};
+
+vertex float4 wireframe_vertex(uint vid [[vertex_id]],
+                               device const uchar *vertices [[buffer(0)]],
+                               constant WireframeUniforms &u [[buffer(1)]]) {
+    device const float *p = (device const float *)(vertices + vid * u.stride + u.position_offset);
+    return u.transform * float4(p[0], p[1], p[2], 1.0f);
+}
+
+fragment float4 wireframe_fragment(constant WireframeUniforms &u [[buffer(0)]]) {
+    return u.color;
+}
+"#;
@@ -0,0 +1,81 @@
+//! Helpers for programmable vertex pulling.
+//!
+//! With vertex pulling, a render pipeline has no `VertexDescriptor`. Instead, the index and vertex
+//! data are bound as ordinary buffers with `set_vertex_buffer`, and the vertex function uses the
+//! built-in vertex and instance IDs to load whatever it needs. This lets an engine use arbitrary
+//! (compressed, interleaved, per-meshlet) vertex layouts without a pipeline per layout.
+//!
+//! A pipeline for vertex pulling is described like any other, but leaves the vertex descriptor
+//! out:
+//!
+//! ```ignore
+//! let desc = RenderPipelineDescriptor {
+//!     label: Some("pulled meshes".to_string()),
+//!     robustness: None,
+//!     vertex_function: "pulled_vertex".to_string(),
+//!     fragment_function: Some("shade".to_string()),
+//!     vertex_descriptor: None,
+//!     // ... everything else as usual ...
+//! };
+//! let pipeline = try!(device.create_render_pipeline(&program, desc));
+//! ```
+//!
+//! # Conventions
+//!
+//! Every backend presents the same built-in IDs to vertex functions, regardless of what the
+//! underlying API does:
+//!
+//! - The vertex ID is the value fetched from the index buffer (or the vertex number, for
+//!   non-indexed draws) *plus* the draw's base vertex.
+//! - The instance ID is the instance number *plus* the draw's base instance.
+//!
+//! Metal and Vulkan already behave like this; backends where the native IDs exclude the bases
+//! (d3d12's `SV_VertexID`/`SV_InstanceID`) add them in the translated shader.
+//!
+//! For an indexed draw with pulling, the vertex function is still invoked once per index and
+//! receives the already fetched index through the vertex ID. Binding the index buffer as a plain
+//! buffer is only needed by shaders which want to read neighbouring indices (for example, to
+//! reconstruct a whole triangle).
+
+#[cfg(not(feature = "std"))]
+use core::prelude::*;
+
+use {Device, BufferSlice, RenderCommandEncoder};
+
+/// The vertex buffer index that `bind_pulled_buffers` binds the first buffer to.
+///
+/// Indices below this are left free for per-draw uniforms.
+pub const FIRST_PULLED_BUFFER_INDEX: uint = 4;
+
+/// Bind a set of buffers for vertex pulling.
+///
+/// The buffers are bound to consecutive vertex buffer indices starting at
+/// `FIRST_PULLED_BUFFER_INDEX`, in order. Shaders written against these helpers declare their
+/// pulled buffers at the same indices.
+pub fn bind_pulled_buffers<D: Device, E: RenderCommandEncoder<D>>(encoder: &mut E, buffers: &[BufferSlice<D::Buffer>]) {
+    for (i, slice) in buffers.iter().enumerate() {
+        encoder.set_vertex_buffer(slice.clone(), FIRST_PULLED_BUFFER_INDEX + i);
+    }
+}
+
+/// Helpers for metal shading language vertex functions using vertex pulling.
+///
+/// Prepend this to vertex function source to get `pull_*` loaders which take a buffer, a stride
+/// in bytes, an offset in bytes, and the vertex or instance ID.
+pub const MSL_HELPERS: &'static str = r#"
+static float4 pull_float4(const device uchar *buf, uint stride, uint offset, uint id) {
+    return *(const device packed_float4 *)(buf + id * stride + offset);
+}
+
+static float3 pull_float3(const device uchar *buf, uint stride, uint offset, uint id) {
+    return *(const device packed_float3 *)(buf + id * stride + offset);
+}
+
+static float2 pull_float2(const device uchar *buf, uint stride, uint offset, uint id) {
+    return *(const device packed_float2 *)(buf + id * stride + offset);
+}
+
+static float4 pull_unorm4x8(const device uchar *buf, uint stride, uint offset, uint id) {
+    return unpack_unorm4x8_to_float(*(const device uint *)(buf + id * stride + offset));
+}
+"#;
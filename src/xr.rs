@@ -0,0 +1,195 @@
+//! Multiview and foveated rendering, for XR renderers.
+//!
+//! Headsets need two views (or four, for displays with an inset) rendered every frame, at high
+//! resolutions and refresh rates, and renderers make that budget in two ways this module gives
+//! them through the abstraction:
+//!
+//! * multiview: a pass renders every view at once, each into its own array slice of the
+//!   attachments, with every draw broadcast to all of them. The vertex function is run per view
+//!   and picks its view's matrices by the view index. Passes are begun with
+//!   `CommandBuffer::xr_render_command_encoder`, and draw with pipelines created by
+//!   `Device::create_multiview_render_pipeline` for the same number of views;
+//! * foveation: the periphery of each view is rasterized at a lower rate than where the user
+//!   looks, by a `RasterizationRateMap` attached to the pass. The rates are given as
+//!   `RasterizationRateLayer`s, one per view (or one for all of them), which
+//!   `RasterizationRateLayer::foveated` builds from the gaze point eye tracking (or the lens
+//!   center) gives.
+//!
+//! ```ignore
+//! let map = try!(device.create_rasterization_rate_map(RasterizationRateMapDescriptor {
+//!     screen_width: 2064,
+//!     screen_height: 2208,
+//!     layers: vec![RasterizationRateLayer::foveated((16, 16), left_gaze, 0.2, 0.25),
+//!                  RasterizationRateLayer::foveated((16, 16), right_gaze, 0.2, 0.25)],
+//! }));
+//! // attachments of two array slices, of map.physical_size(0)
+//! let encoder = commands.xr_render_command_encoder(desc, XrRenderPass { view_count: 2, rasterization_rate_map: Some(&map) });
+//! ```
+//!
+//! Depending on the backend, the attachments of a foveated pass are either of the screen size
+//! with fewer fragments shaded in the periphery (fragment density maps), or smaller, holding the
+//! periphery compressed (rasterization rate maps). Passes after the foveated ones which read its
+//! attachments by screen position map it with `screen_to_physical`, or on the GPU with the
+//! map's `parameter_data`; the compositor of the XR runtime is handed the attachments and the
+//! map's physical size. `RasterizationRateMap::physical_size` is the size to create them at.
+//!
+//! Everything here needs the `xr` feature of the crate, and the `multiview` and
+//! `rasterization_rate_maps` device features.
+
+#[cfg(not(feature = "std"))]
+use core::prelude::*;
+#[cfg(not(feature = "std"))]
+use collections::Vec;
+
+use Device;
+
+/// The rates one view is rasterized at: the screen is split into `horizontal.len()` columns
+/// and `vertical.len()` rows of zones of equal size, and the zone in column `x` and row `y` is
+/// rasterized at `horizontal[x]` of the full rate horizontally and `vertical[y]` vertically.
+/// Rates are within `(0, 1]`.
+#[deriving(Clone, Show)]
+pub struct RasterizationRateLayer {
+    pub horizontal: Vec<f32>,
+    pub vertical: Vec<f32>,
+}
+
+impl RasterizationRateLayer {
+    /// The full rate everywhere.
+    pub fn full() -> RasterizationRateLayer {
+        RasterizationRateLayer { horizontal: vec![1.0], vertical: vec![1.0] }
+    }
+
+    /// `zones` columns and rows of zones at the full rate within `inner_radius` of `center`,
+    /// along each axis, falling off linearly to `min_rate` at the edges of the screen.
+    /// `center` and `inner_radius` are fractions of the screen size, so `(0.5, 0.5)` is its
+    /// middle.
+    pub fn foveated(zones: (uint, uint), center: (f32, f32), inner_radius: f32, min_rate: f32) -> RasterizationRateLayer {
+        let rates = |count: uint, center: f32| -> Vec<f32> {
+            range(0, count).map(|zone| {
+                let position = (zone as f32 + 0.5) / count as f32;
+                let distance = (position - center).abs();
+                let edge = if position < center { center } else { 1.0 - center };
+                if distance <= inner_radius || edge <= inner_radius {
+                    1.0
+                } else {
+                    let t = (distance - inner_radius) / (edge - inner_radius);
+                    1.0 + (min_rate - 1.0) * t.min(1.0)
+                }
+            }).collect()
+        };
+        RasterizationRateLayer { horizontal: rates(zones.0, center.0), vertical: rates(zones.1, center.1) }
+    }
+}
+
+/// A rasterization rate map to create. See `Device::create_rasterization_rate_map`.
+#[deriving(Clone, Show)]
+pub struct RasterizationRateMapDescriptor {
+    /// The size the views are seen at, at the full rate.
+    pub screen_width: uint,
+    pub screen_height: uint,
+    /// The rates of each view of the passes the map is used in, or a single layer for all of
+    /// them.
+    pub layers: Vec<RasterizationRateLayer>,
+}
+
+/// Why a `RasterizationRateMapDescriptor` is invalid.
+#[deriving(Clone, PartialEq, Show)]
+pub enum RasterizationRateMapError {
+    /// The screen width or height is zero.
+    ZeroSize,
+    /// There are no layers.
+    NoLayers,
+    /// The layer at this index has no columns or no rows of zones.
+    NoZones(uint),
+    /// A rate of the layer at this index is not within `(0, 1]`.
+    RateOutOfRange(uint),
+}
+
+impl RasterizationRateMapDescriptor {
+    /// Check that the map has a size, and layers of zones with rates within `(0, 1]`.
+    ///
+    /// Backends call this from `Device::create_rasterization_rate_map`, which fails on an error.
+    pub fn validate(&self) -> Result<(), RasterizationRateMapError> {
+        if self.screen_width == 0 || self.screen_height == 0 {
+            return Err(RasterizationRateMapError::ZeroSize);
+        }
+        if self.layers.is_empty() {
+            return Err(RasterizationRateMapError::NoLayers);
+        }
+        for (index, layer) in self.layers.iter().enumerate() {
+            if layer.horizontal.is_empty() || layer.vertical.is_empty() {
+                return Err(RasterizationRateMapError::NoZones(index));
+            }
+            if layer.horizontal.iter().chain(layer.vertical.iter()).any(|&rate| !(rate > 0.0 && rate <= 1.0)) {
+                return Err(RasterizationRateMapError::RateOutOfRange(index));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The rates a foveated pass is rasterized at, created by
+/// `Device::create_rasterization_rate_map`.
+pub trait RasterizationRateMap {
+    /// The size the views are seen at.
+    fn screen_size(&self) -> (uint, uint);
+
+    /// The number of layers of the map.
+    fn layer_count(&self) -> uint;
+
+    /// The size the attachments of passes rendered with the map must have, for layer `layer`:
+    /// the screen size on backends which shade fewer fragments in place, and smaller on those
+    /// which compress the periphery.
+    fn physical_size(&self, layer: uint) -> (uint, uint);
+
+    /// Where the point at `screen` (in pixels of the screen size) is in the attachments of
+    /// layer `layer`.
+    fn screen_to_physical(&self, layer: uint, screen: (f32, f32)) -> (f32, f32);
+
+    /// Where the point at `physical` in the attachments of layer `layer` is on the screen.
+    fn physical_to_screen(&self, layer: uint, physical: (f32, f32)) -> (f32, f32);
+
+    /// The data shaders map positions with, to copy into a buffer they bind
+    /// (`rasterization_rate_map_data` in metal-speak). Empty on backends whose attachments are
+    /// of the screen size, where positions need no mapping.
+    fn parameter_data(&self) -> Vec<u8>;
+}
+
+/// How an XR render pass renders its views. See `CommandBuffer::xr_render_command_encoder`.
+pub struct XrRenderPass<'a, D: Device> {
+    /// The views rendered at once. View `v` is rendered into array slice `slice + v` of every
+    /// attachment, which must have that many slices.
+    pub view_count: uint,
+    /// The rates the views are rasterized at, with a layer per view or a single layer for all
+    /// of them, or `None` for the full rate everywhere.
+    pub rasterization_rate_map: Option<&'a D::RasterizationRateMap>,
+}
+
+/// Why an `XrRenderPass` is invalid.
+#[deriving(Clone, PartialEq, Show)]
+pub enum XrRenderPassError {
+    /// A view count of zero.
+    NoViews,
+    /// The rasterization rate map has neither a single layer nor one per view.
+    LayerCountMismatch,
+}
+
+impl<'a, D: Device> XrRenderPass<'a, D> {
+    /// Check that the pass has views, and a layer of its map for each of them.
+    ///
+    /// Backends call this from `CommandBuffer::xr_render_command_encoder`, and panic on an
+    /// error. That the attachments have the slices of every view, of the physical size, is left
+    /// to the backend's validation, as textures do not report their size.
+    pub fn validate(&self) -> Result<(), XrRenderPassError> {
+        if self.view_count == 0 {
+            return Err(XrRenderPassError::NoViews);
+        }
+        if let Some(map) = self.rasterization_rate_map {
+            let layers = map.layer_count();
+            if layers != 1 && layers != self.view_count {
+                return Err(XrRenderPassError::LayerCountMismatch);
+            }
+        }
+        Ok(())
+    }
+}